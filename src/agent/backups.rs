@@ -0,0 +1,226 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use fctrl::schema::{
+    regex::{AUTOSAVE_STARTED_RE, SAVE_FINISHED_RE},
+    AgentStreamingMessage, AgentStreamingMessageInner, BackupFailure, BackupObject, BackupStatus,
+};
+use log::{error, info, warn};
+use tokio::sync::broadcast;
+
+use crate::{
+    consts::*,
+    error::Result,
+    server::proc::ProcessManager,
+    util::{
+        self,
+        s3::{S3Client, S3Object},
+    },
+};
+
+const DEFAULT_PREFIX: &str = "backups/";
+
+/// Rotating backups of savefiles in an S3-compatible bucket, kept apart from the savefiles
+/// themselves so that a bad save or a deleted save can be recovered.
+///
+/// Every completed autosave is backed up under the name of the hosted savefile, and backups can
+/// also be made on demand. Only the newest backups of each savefile are kept.
+pub struct Backups {
+    client: S3Client,
+    prefix: String,
+    retain: usize,
+    last_success: Mutex<Option<BackupObject>>,
+    last_failure: Mutex<Option<BackupFailure>>,
+}
+
+impl Backups {
+    /// Configures backups from the environment, if an S3 bucket is configured and a number of
+    /// backups to retain is set
+    pub fn from_env() -> Option<Backups> {
+        let retain = match std::env::var(ENV_AGENT_BACKUP_RETAIN)
+            .ok()?
+            .parse::<usize>()
+        {
+            Ok(retain) if retain > 0 => retain,
+            _ => {
+                warn!("Invalid {}, backups disabled", ENV_AGENT_BACKUP_RETAIN);
+                return None;
+            }
+        };
+        Some(Backups {
+            client: S3Client::from_env()?,
+            prefix: std::env::var(ENV_AGENT_S3_BACKUPS_PREFIX)
+                .unwrap_or_else(|_| DEFAULT_PREFIX.to_owned()),
+            retain,
+            last_success: Mutex::new(None),
+            last_failure: Mutex::new(None),
+        })
+    }
+
+    /// Backs up each autosave of the hosted savefile as it completes
+    pub fn start(
+        self: &Arc<Self>,
+        global_tx: &broadcast::Sender<AgentStreamingMessage>,
+        proc_manager: Arc<ProcessManager>,
+    ) {
+        let backups = Arc::clone(self);
+        let mut rx = global_tx.subscribe();
+        tokio::spawn(async move {
+            let mut in_progress = None;
+            loop {
+                let line = match rx.recv().await {
+                    Ok(AgentStreamingMessage {
+                        content: AgentStreamingMessageInner::ServerStdout(line),
+                        ..
+                    }) => line,
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("Autosave backups lagging, skipped {} messages", n);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if let Some(captures) = AUTOSAVE_STARTED_RE.captures(&line) {
+                    in_progress = Some(captures[1].to_owned());
+                    continue;
+                }
+                if !SAVE_FINISHED_RE.is_match(&line) {
+                    continue;
+                }
+                // manual saves also finish with this line, but never follow an autosave start
+                let autosave = match in_progress.take() {
+                    Some(autosave) => autosave,
+                    None => continue,
+                };
+                if let Some(save_name) = proc_manager.hosted_savefile().await {
+                    if let Err(e) = backups.backup(&save_name, &autosave).await {
+                        error!("Failed to back up autosave of {}: {:?}", save_name, e);
+                    }
+                }
+            }
+
+            error!("autosave backup task is finishing - this should never happen!");
+        });
+    }
+
+    /// Uploads the local savefile `source` as a new backup of `save_name`, then prunes the
+    /// oldest backups of `save_name` beyond the number to retain
+    pub async fn backup(&self, save_name: &str, source: &str) -> Result<BackupObject> {
+        let result = self.upload(save_name, source).await;
+        match &result {
+            Ok(backup) => {
+                *self.last_success.lock().unwrap() = Some(backup.clone());
+            }
+            Err(e) => {
+                *self.last_failure.lock().unwrap() = Some(BackupFailure {
+                    save_name: save_name.to_owned(),
+                    timestamp: Utc::now(),
+                    error: format!("{:?}", e),
+                });
+            }
+        }
+        result
+    }
+
+    pub async fn status(&self) -> Result<BackupStatus> {
+        let mut backups: Vec<_> = self
+            .client
+            .list(&self.prefix)
+            .await?
+            .into_iter()
+            .filter_map(|o| to_backup_object(&self.prefix, o))
+            .collect();
+        backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        Ok(BackupStatus {
+            enabled: true,
+            last_success: self.last_success.lock().unwrap().clone(),
+            last_failure: self.last_failure.lock().unwrap().clone(),
+            backups,
+        })
+    }
+
+    async fn upload(&self, save_name: &str, source: &str) -> Result<BackupObject> {
+        let timestamp = Utc::now();
+        let key = format!(
+            "{}{}/{}.zip",
+            self.prefix,
+            save_name,
+            timestamp.format("%Y%m%dT%H%M%SZ")
+        );
+        let size_bytes = self
+            .client
+            .put_file(&key, util::saves::get_savefile_path(source))
+            .await?;
+        info!(
+            "Backed up savefile `{}` to `{}`, {} bytes",
+            save_name, key, size_bytes
+        );
+
+        let existing = self
+            .client
+            .list(&format!("{}{}/", self.prefix, save_name))
+            .await?;
+        for key in keys_to_prune(existing, self.retain) {
+            info!("Pruning old backup `{}`", key);
+            self.client.delete(&key).await?;
+        }
+
+        Ok(BackupObject {
+            save_name: save_name.to_owned(),
+            key,
+            timestamp,
+            size_bytes,
+        })
+    }
+}
+
+fn to_backup_object(prefix: &str, object: S3Object) -> Option<BackupObject> {
+    let (save_name, file_name) = object.key.strip_prefix(prefix)?.split_once('/')?;
+    if save_name.is_empty() || !file_name.ends_with(".zip") {
+        return None;
+    }
+    Some(BackupObject {
+        save_name: save_name.to_owned(),
+        key: object.key.clone(),
+        timestamp: object.last_modified,
+        size_bytes: object.size,
+    })
+}
+
+/// Keys of all but the newest `retain` backups. Keys end with the backup time, so they sort in
+/// the order the backups were made.
+fn keys_to_prune(mut existing: Vec<S3Object>, retain: usize) -> Vec<String> {
+    existing.sort_by(|a, b| b.key.cmp(&a.key));
+    existing.into_iter().skip(retain).map(|o| o.key).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(key: &str) -> S3Object {
+        S3Object {
+            key: key.to_owned(),
+            last_modified: Utc::now(),
+            size: 1,
+        }
+    }
+
+    #[test]
+    fn prunes_oldest_backups() {
+        let existing = vec![
+            object("backups/world/20260102T000000Z.zip"),
+            object("backups/world/20260101T000000Z.zip"),
+            object("backups/world/20260103T000000Z.zip"),
+        ];
+        assert_eq!(
+            keys_to_prune(existing, 2),
+            vec!["backups/world/20260101T000000Z.zip"]
+        );
+
+        let backup =
+            to_backup_object("backups/", object("backups/world/20260101T000000Z.zip")).unwrap();
+        assert_eq!(backup.save_name, "world");
+        assert!(to_backup_object("backups/", object("backups/stray.zip")).is_none());
+    }
+}
@@ -2,7 +2,27 @@ use std::path::PathBuf;
 
 use lazy_static::lazy_static;
 
+pub const ENV_AGENT_BACKUP_RETAIN: &str = "AGENT_BACKUP_RETAIN";
+pub const ENV_AGENT_BIND_ADDRESS: &str = "AGENT_BIND_ADDRESS";
+pub const ENV_AGENT_BUS_CAPACITY: &str = "AGENT_BUS_CAPACITY";
+pub const ENV_AGENT_CA_CERT_FILE: &str = "AGENT_CA_CERT_FILE";
+pub const ENV_AGENT_DOWNLOAD_CONCURRENCY: &str = "AGENT_DOWNLOAD_CONCURRENCY";
+pub const ENV_AGENT_DOWNLOAD_PORT: &str = "AGENT_DOWNLOAD_PORT";
+pub const ENV_AGENT_DOWNLOAD_SECRET: &str = "AGENT_DOWNLOAD_SECRET";
+pub const ENV_AGENT_HEALTH_FILE: &str = "AGENT_HEALTH_FILE";
+pub const ENV_AGENT_SEED_ADMIN_LIST: &str = "AGENT_SEED_ADMIN_LIST";
+pub const ENV_AGENT_SEED_BAN_LIST: &str = "AGENT_SEED_BAN_LIST";
+pub const ENV_AGENT_SEED_WHITE_LIST: &str = "AGENT_SEED_WHITE_LIST";
+pub const ENV_AGENT_SAVE_CHUNK_BYTES: &str = "AGENT_SAVE_CHUNK_BYTES";
+pub const ENV_AGENT_S3_ACCESS_KEY_ID: &str = "AGENT_S3_ACCESS_KEY_ID";
+pub const ENV_AGENT_S3_BACKUPS_PREFIX: &str = "AGENT_S3_BACKUPS_PREFIX";
+pub const ENV_AGENT_S3_BUCKET: &str = "AGENT_S3_BUCKET";
+pub const ENV_AGENT_S3_ENDPOINT: &str = "AGENT_S3_ENDPOINT";
+pub const ENV_AGENT_S3_REGION: &str = "AGENT_S3_REGION";
+pub const ENV_AGENT_S3_SAVES_PREFIX: &str = "AGENT_S3_SAVES_PREFIX";
+pub const ENV_AGENT_S3_SECRET_ACCESS_KEY: &str = "AGENT_S3_SECRET_ACCESS_KEY";
 pub const ENV_AGENT_WS_PORT: &str = "AGENT_WS_PORT";
+pub const ENV_FACTORIO_BIND_ADDRESS: &str = "FACTORIO_BIND_ADDRESS";
 pub const ENV_FACTORIO_PORT: &str = "FACTORIO_PORT";
 pub const ENV_FACTORIO_RCON_PORT: &str = "FACTORIO_RCON_PORT";
 
@@ -12,4 +32,8 @@ lazy_static! {
     pub static ref CONFIG_DIR: PathBuf = ROAMING_DATA_DIR.join("configs");
     pub static ref MOD_DIR: PathBuf = ROAMING_DATA_DIR.join("mods");
     pub static ref SAVEFILE_DIR: PathBuf = ROAMING_DATA_DIR.join("saves");
+    pub static ref SAVEFILE_STAGING_DIR: PathBuf = ROAMING_DATA_DIR.join("saves_staging");
+    pub static ref MAP_GEN_SETTINGS_DIR: PathBuf = ROAMING_DATA_DIR.join("map_gen_settings");
+    pub static ref MAP_PREVIEW_DIR: PathBuf = ROAMING_DATA_DIR.join("map_preview");
+    pub static ref MAP_PREVIEW_PATH: PathBuf = MAP_PREVIEW_DIR.join("map-preview.png");
 }
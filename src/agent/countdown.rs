@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use fctrl::game_message::GameMessage;
+use log::{info, warn};
+
+use crate::server::proc::ProcessManager;
+
+/// Times left before the server goes down at which players are warned in-game, in addition to a
+/// warning as soon as the countdown starts
+pub const WARNINGS: [Duration; 3] = [
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(60),
+    Duration::from_secs(10),
+];
+
+/// Warns players in-game of the server going down at the given time, waiting until then. Each
+/// warning is a chat message built from a description of the time left in the server's locale,
+/// such as "5 min".
+///
+/// Returns false as soon as the server is found to have been stopped or restarted by other means,
+/// cutting the countdown short.
+pub async fn count_down(
+    proc_manager: &ProcessManager,
+    at: DateTime<Utc>,
+    warning: impl Fn(&str) -> String,
+) -> bool {
+    let started_at = match proc_manager.running_since().await {
+        Some(started_at) => started_at,
+        None => return false,
+    };
+
+    // to the nearest second, so that the first warning isn't a few milliseconds short
+    let total_ms = (at - Utc::now()).num_milliseconds().max(0) as u64;
+    let total = Duration::from_secs((total_ms + 500) / 1000);
+    let warnings = std::iter::once(total)
+        .chain(WARNINGS.iter().copied().filter(|left| *left < total))
+        .map(Some)
+        .chain(std::iter::once(None));
+    for left in warnings {
+        let wait = (at - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+            .saturating_sub(left.unwrap_or(Duration::ZERO));
+        tokio::time::sleep(wait).await;
+
+        if proc_manager.running_since().await != Some(started_at) {
+            info!("Server was stopped or restarted, cancelling countdown");
+            return false;
+        }
+        if let Some(left) = left {
+            let time_left = GameMessage::TimeLeft {
+                seconds: left.as_secs(),
+            }
+            .render();
+            let message = warning(&time_left);
+            if let Err(e) = proc_manager.send_rcon_command_to_instance(&message).await {
+                warn!("Couldn't warn players of the server going down: {:?}", e);
+            }
+        }
+    }
+    true
+}
+
+/// Saves the game ahead of stopping the server. Stopping saves the game too, but only once the
+/// server is already going down.
+pub async fn save(proc_manager: &ProcessManager) {
+    if let Err(e) = proc_manager
+        .send_rcon_command_to_instance("/server-save")
+        .await
+    {
+        warn!("Couldn't save before the server goes down: {:?}", e);
+    }
+}
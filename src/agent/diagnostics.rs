@@ -0,0 +1,139 @@
+use fctrl::schema::{ConfigWarning, ConfigWarningKind, ServerSettingsConfig};
+
+use crate::{
+    error::Result,
+    server::{
+        mods::ModManager,
+        settings::{LaunchSettings, Secrets, ServerSettings, WhiteList},
+    },
+};
+
+/// Snapshot of the configuration relevant to the consistency checks
+pub struct ConfigSnapshot {
+    pub launch_settings: LaunchSettings,
+    pub whitelist: Vec<String>,
+    /// Not present until a version has been installed
+    pub server_settings: Option<ServerSettingsConfig>,
+    pub has_credentials: bool,
+    pub mod_count: usize,
+}
+
+impl ConfigSnapshot {
+    pub async fn read() -> Result<ConfigSnapshot> {
+        let launch_settings = LaunchSettings::read_or_apply_default().await?;
+        let whitelist = WhiteList::read().await?.map(|wl| wl.list).unwrap_or_default();
+        let server_settings = ServerSettings::read().await?.map(|ss| ss.config);
+        let has_credentials = Secrets::read()
+            .await?
+            .is_some_and(|s| !s.username.is_empty() && !s.token.is_empty());
+        let mod_count = ModManager::read().await?.map_or(0, |m| m.mods.len());
+        Ok(ConfigSnapshot {
+            launch_settings,
+            whitelist,
+            server_settings,
+            has_credentials,
+            mod_count,
+        })
+    }
+
+    /// Cross-checks the configuration, returning a warning for each problem found
+    pub fn check(&self) -> Vec<ConfigWarning> {
+        let mut warnings = vec![];
+
+        if self.launch_settings.use_whitelist && self.whitelist.is_empty() {
+            warnings.push(ConfigWarning {
+                kind: ConfigWarningKind::WhitelistEmpty,
+                message: "The whitelist is enabled but empty, so nobody will be able to join. Add players to the whitelist or disable it.".to_owned(),
+            });
+        }
+
+        if let Some(server_settings) = &self.server_settings {
+            if server_settings.visibility.public && !self.has_credentials {
+                warnings.push(ConfigWarning {
+                    kind: ConfigWarningKind::PublicWithoutCredentials,
+                    message: "The server is set to public visibility but no factorio.com credentials are configured, so it will fail to start. Set the username and token in secrets, or disable public visibility.".to_owned(),
+                });
+            }
+        }
+
+        let rcon_port = self.launch_settings.rcon_bind.port();
+        if rcon_port == self.launch_settings.server_bind.port() {
+            warnings.push(ConfigWarning {
+                kind: ConfigWarningKind::RconPortCollision,
+                message: format!(
+                    "RCON and the game server are both configured to use port {}. Change one of them to a different port.",
+                    rcon_port
+                ),
+            });
+        }
+
+        if self.mod_count > 0 && !self.has_credentials {
+            warnings.push(ConfigWarning {
+                kind: ConfigWarningKind::ModsWithoutCredentials,
+                message: format!(
+                    "{} mod(s) are installed but no factorio.com credentials are set, so mod changes can't be downloaded. Set the username and token in secrets.",
+                    self.mod_count
+                ),
+            });
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use super::*;
+
+    fn snapshot() -> ConfigSnapshot {
+        ConfigSnapshot {
+            launch_settings: LaunchSettings {
+                server_bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 34197),
+                rcon_bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 27015),
+                rcon_password: "password".to_owned(),
+                use_whitelist: false,
+                cpu_affinity: None,
+                restart_policy: Default::default(),
+            },
+            whitelist: vec![],
+            server_settings: None,
+            has_credentials: false,
+            mod_count: 0,
+        }
+    }
+
+    fn kinds(warnings: Vec<ConfigWarning>) -> Vec<ConfigWarningKind> {
+        warnings.into_iter().map(|w| w.kind).collect()
+    }
+
+    #[test]
+    fn default_config_has_no_warnings() {
+        assert!(snapshot().check().is_empty());
+    }
+
+    #[test]
+    fn enabled_empty_whitelist_is_reported() {
+        let mut s = snapshot();
+        s.launch_settings.use_whitelist = true;
+        assert_eq!(kinds(s.check()), vec![ConfigWarningKind::WhitelistEmpty]);
+
+        s.whitelist = vec!["player".to_owned()];
+        assert!(s.check().is_empty());
+    }
+
+    #[test]
+    fn port_collision_and_mods_without_credentials_are_reported() {
+        let mut s = snapshot();
+        s.launch_settings.rcon_bind.set_port(34197);
+        s.mod_count = 3;
+        assert_eq!(
+            kinds(s.check()),
+            vec![
+                ConfigWarningKind::RconPortCollision,
+                ConfigWarningKind::ModsWithoutCredentials
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::{convert::Infallible, io::SeekFrom, net::SocketAddr, sync::Arc};
+
+use chrono::Utc;
+use futures::TryStreamExt;
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, StreamBody};
+use hyper::{
+    body::{Bytes, Frame, Incoming},
+    header,
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use log::{debug, error, info, warn};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+    net::TcpListener,
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{consts::*, util};
+
+type Body = BoxBody<Bytes, std::io::Error>;
+
+/// Minimal HTTP server allowing savefiles to be downloaded directly from the agent, using links
+/// signed by the mgmt-server. This avoids relaying large files over the WebSocket connection.
+pub struct DownloadServer {
+    tcp: TcpListener,
+    secret: Arc<String>,
+}
+
+impl DownloadServer {
+    pub async fn new(port: u16, secret: String) -> std::io::Result<DownloadServer> {
+        let bind_addr = SocketAddr::new(util::net::bind_ip_from_env(ENV_AGENT_BIND_ADDRESS), port);
+        let tcp = TcpListener::bind(bind_addr).await?;
+        Ok(DownloadServer {
+            tcp,
+            secret: Arc::new(secret),
+        })
+    }
+
+    pub fn start(self) {
+        info!(
+            "Serving direct savefile downloads on {}",
+            self.tcp
+                .local_addr()
+                .map_or("<unknown>".to_owned(), |a| a.to_string())
+        );
+        tokio::spawn(async move {
+            loop {
+                match self.tcp.accept().await {
+                    Ok((stream, peer_addr)) => {
+                        let secret = Arc::clone(&self.secret);
+                        tokio::spawn(async move {
+                            let service =
+                                service_fn(move |req| handle_request(req, Arc::clone(&secret)));
+                            if let Err(e) = http1::Builder::new()
+                                .serve_connection(TokioIo::new(stream), service)
+                                .await
+                            {
+                                debug!("Error serving download to {}: {:?}", peer_addr, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("Error accepting download connection: {:?}", e);
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn handle_request(
+    req: Request<Incoming>,
+    secret: Arc<String>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET {
+        return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    // expected form is /saves/<save_name>?expires=<unix timestamp>&signature=<signature>
+    let save_name = match req
+        .uri()
+        .path()
+        .strip_prefix("/saves/")
+        .and_then(|s| urlencoding::decode(s).ok())
+    {
+        Some(s) if !s.is_empty() && !s.contains('/') && !s.contains("..") => s.into_owned(),
+        _ => return Ok(status_response(StatusCode::NOT_FOUND)),
+    };
+    let mut expires = None;
+    let mut signature = None;
+    for (k, v) in url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes()) {
+        match k.as_ref() {
+            "expires" => expires = v.parse::<i64>().ok(),
+            "signature" => signature = Some(v.into_owned()),
+            _ => (),
+        }
+    }
+    let (expires, signature) = match (expires, signature) {
+        (Some(expires), Some(signature)) => (expires, signature),
+        _ => return Ok(status_response(StatusCode::FORBIDDEN)),
+    };
+    if !fctrl::util::signing::verify(&secret, &save_name, expires, &signature) {
+        warn!("Rejecting download of {} with invalid signature", save_name);
+        return Ok(status_response(StatusCode::FORBIDDEN));
+    }
+    if Utc::now().timestamp() > expires {
+        return Ok(status_response(StatusCode::GONE));
+    }
+
+    let path = util::saves::get_savefile_path(&save_name);
+    let mut file = match fs::File::open(&path).await {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(status_response(StatusCode::NOT_FOUND));
+        }
+        Err(e) => {
+            error!("Error opening savefile {} for download: {:?}", save_name, e);
+            return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+    let metadata = match file.metadata().await {
+        Ok(m) => m,
+        Err(e) => {
+            error!("Error reading metadata of savefile {}: {:?}", save_name, e);
+            return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+    let len = metadata.len();
+    let sha256 = match util::saves::savefile_sha256(&save_name).await {
+        Ok(sha256) => sha256,
+        Err(e) => {
+            error!(
+                "Error computing checksum of savefile {}: {:?}",
+                save_name, e
+            );
+            return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(&format!("{}.zip", save_name)),
+        )
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, format!("\"{}\"", sha256))
+        .header("X-Content-Sha256", &sha256);
+
+    // only resume if the save is unchanged since the download started
+    let range_header = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|h| h.to_str().ok())
+        .filter(|_| if_range_matches(&req, &sha256));
+    let range = match range_header.map(|h| fctrl::util::range::parse(h, len)) {
+        Some(Ok(range)) => range,
+        Some(Err(_)) => {
+            return Ok(Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(empty_body())
+                .unwrap_or_else(|_| status_response(StatusCode::RANGE_NOT_SATISFIABLE)));
+        }
+        None => None,
+    };
+
+    let body = match range {
+        Some(range) => {
+            info!(
+                "Serving direct download of savefile {}, resuming from byte {}",
+                save_name, range.start
+            );
+            if let Err(e) = file.seek(SeekFrom::Start(range.start)).await {
+                error!("Error seeking savefile {} for download: {:?}", save_name, e);
+                return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+            }
+            builder = builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, range.content_range(len))
+                .header(header::CONTENT_LENGTH, range.size());
+            StreamBody::new(ReaderStream::new(file.take(range.size())).map_ok(Frame::data)).boxed()
+        }
+        None => {
+            info!("Serving direct download of savefile {}", save_name);
+            builder = builder.header(header::CONTENT_LENGTH, len);
+            StreamBody::new(ReaderStream::new(file).map_ok(Frame::data)).boxed()
+        }
+    };
+    Ok(builder
+        .body(body)
+        .unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR)))
+}
+
+/// Builds an attachment `Content-Disposition` for the filename, with a quoted ASCII fallback and
+/// the exact name RFC 5987-encoded for clients that support it
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback,
+        urlencoding::encode(filename)
+    )
+}
+
+/// Whether a range request should be honoured, which is only if its If-Range header (if any)
+/// matches the current ETag of the save
+fn if_range_matches(req: &Request<Incoming>, sha256: &str) -> bool {
+    match req.headers().get(header::IF_RANGE).map(|h| h.to_str()) {
+        Some(Ok(etag)) => etag.trim_matches('"') == sha256,
+        Some(Err(_)) => false,
+        None => true,
+    }
+}
+
+fn empty_body() -> Body {
+    Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(empty_body());
+    *response.status_mut() = status;
+    response
+}
@@ -9,6 +9,7 @@ pub enum Error {
     ProcessPidError,
     ProcessPipeError,
     ProcessSignalError(nix::Error),
+    InvalidCpuAffinity(usize),
 
     // Mods
     MalformedModList,
@@ -16,13 +17,29 @@ pub enum Error {
         mod_name: String,
         mod_version: String,
     },
+    ModVersionsNotFound(Vec<fctrl::schema::ModVersionNotFound>),
+
+    // Launch profiles
+    InvalidProfileName(String),
+    ServerSettingsNotFound,
 
     // RCON
     RconEmptyCommand,
     RconNotConnected,
+    RconUnexpectedResponse(String),
+
+    // Installation
+    InvalidInstallArchive(String),
 
-    // SaveHeader
+    // Disk space
+    InsufficientDiskSpace(fctrl::schema::InsufficientDiskSpace),
+
+    // Savefiles
     HeaderNotFound,
+    SavefileChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
 
     // Generic
     Aggregate(Vec<Error>),
@@ -32,6 +49,7 @@ pub enum Error {
     FactorioDatFileSerde(factorio_file_parser::Error),
     Io(std::io::Error),
     Json(serde_json::error::Error),
+    ObjectStore(object_store::Error),
     Rcon(rcon::Error),
     Reqwest(reqwest::Error),
     TomlDe(toml::de::Error),
@@ -65,6 +83,12 @@ impl From<factorio_file_parser::Error> for Error {
     }
 }
 
+impl From<object_store::Error> for Error {
+    fn from(e: object_store::Error) -> Self {
+        Error::ObjectStore(e)
+    }
+}
+
 impl From<rcon::Error> for Error {
     fn from(e: rcon::Error) -> Self {
         Error::Rcon(e)
@@ -1,16 +1,30 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
 use bytes::Buf;
+use fctrl::schema::{Dlc, FactorioVersion};
+use lazy_static::lazy_static;
 use log::{error, info, warn};
+use regex::Regex;
 use tar::Archive;
-use tokio::fs;
+use tokio::{fs, process::Command, sync::mpsc};
 use xz2::read::XzDecoder;
 
-use crate::{error::Result, util};
+use crate::{
+    error::{Error, Result},
+    journal::JournalEntry,
+    util::{self, downloader::DownloadProgress},
+};
+
+lazy_static! {
+    /// First line of `factorio --version`, e.g.
+    /// "Version: 2.0.28 (build 79441, linux64, headless, space-age)"
+    static ref VERSION_OUTPUT_RE: Regex = Regex::new(r"Version: (\d+\.\d+\.\d+)").unwrap();
+}
 
 /// Represents an installation of Factorio headless server software
 pub struct Factorio {
@@ -18,6 +32,23 @@ pub struct Factorio {
     pub version: String,
 }
 
+impl Factorio {
+    /// Official DLCs shipped with this installation, found as directories in its data dir
+    /// alongside base
+    pub async fn available_dlcs(&self) -> Result<HashSet<Dlc>> {
+        let mut dlcs = HashSet::new();
+        let mut entries = fs::read_dir(self.path.join("factorio").join("data")).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path().is_dir() {
+                if let Some(dlc) = entry.file_name().to_str().and_then(|n| Dlc::from_str(n).ok()) {
+                    dlcs.insert(dlc);
+                }
+            }
+        }
+        Ok(dlcs)
+    }
+}
+
 pub struct VersionManager {
     install_dir: PathBuf,
     pub versions: HashMap<String, Factorio>,
@@ -35,7 +66,10 @@ impl VersionManager {
         while let Some(entry) = entries.next_entry().await? {
             if entry.path().is_dir() {
                 if let Some(dir_name) = entry.file_name().to_str() {
-                    if let Some(version) = dir_name.strip_prefix("factorio_headless_x64_") {
+                    if let Some(version) = dir_name
+                        .strip_prefix("factorio_headless_x64_")
+                        .or_else(|| dir_name.strip_prefix("factorio-headless_linux_"))
+                    {
                         let factorio_installation = Factorio {
                             path: entry.path(),
                             version: version.to_string(),
@@ -62,14 +96,31 @@ impl VersionManager {
         })
     }
 
-    pub async fn install(&mut self, version: String) -> Result<()> {
+    /// The installation used when no specific version is requested, which is the latest
+    /// installed version
+    pub fn default_version(&self) -> Option<&Factorio> {
+        self.versions
+            .values()
+            .max_by_key(|f| FactorioVersion(f.version.clone()))
+    }
+
+    /// Downloads and installs the version, optionally reporting download progress
+    pub async fn install(
+        &mut self,
+        version: String,
+        progress_tx: Option<mpsc::UnboundedSender<DownloadProgress>>,
+    ) -> Result<()> {
         let uri = format!(
             "https://factorio.com/get-download/{}/headless/linux64",
             version
         );
         info!("Attempting to download version {} from {}", version, uri);
-        let xz_bytes =
-            util::downloader::download(&format!("{}.tar.xz", &VersionManager::get_download_id(&version)), uri).await?;
+        let xz_bytes = util::downloader::download_with_progress(
+            &format!("{}.tar.xz", &VersionManager::get_download_id(&version)),
+            uri,
+            progress_tx,
+        )
+        .await?;
 
         // decompress in memory
         let decompress = XzDecoder::new(xz_bytes.reader());
@@ -77,6 +128,12 @@ impl VersionManager {
         // extract tar archive and write files to install location
         let install_path = self.get_install_path(&version);
         info!("Attempting to install to {}", install_path.display());
+        let _journal = JournalEntry::VersionInstall {
+            version: Some(version.clone()),
+            path: install_path.clone(),
+        }
+        .begin()
+        .await?;
         let mut tar = Archive::new(decompress);
         if let Err(e) = tar.unpack(&install_path) {
             error!("Error unpacking tar: {:?}", e);
@@ -91,6 +148,82 @@ impl VersionManager {
         }
     }
 
+    /// Installs from a headless archive on the agent host, for hosts that can't reach
+    /// factorio.com. The version is read from the unpacked server binary, and installed alongside
+    /// existing versions. Returns the installed version.
+    pub async fn install_from_archive(&mut self, archive_path: impl AsRef<Path>) -> Result<String> {
+        let staging_path = self
+            .install_dir
+            .join(format!(".archive_{}", uuid::Uuid::new_v4()));
+        info!(
+            "Unpacking archive {} to {}",
+            archive_path.as_ref().display(),
+            staging_path.display()
+        );
+        let _journal = JournalEntry::VersionInstall {
+            version: None,
+            path: staging_path.clone(),
+        }
+        .begin()
+        .await?;
+        let result = self
+            .unpack_archive(archive_path.as_ref(), &staging_path)
+            .await;
+        let version = match result {
+            Ok(version) => version,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging_path).await;
+                return Err(e);
+            }
+        };
+
+        let install_path = self.get_install_path(&version);
+        fs::rename(&staging_path, &install_path).await?;
+        info!(
+            "Installed version {} from archive to {}",
+            version,
+            install_path.display()
+        );
+        self.versions.insert(
+            version.clone(),
+            Factorio {
+                path: install_path,
+                version: version.clone(),
+            },
+        );
+        Ok(version)
+    }
+
+    /// Unpacks and validates the archive, returning the version of the server binary inside
+    async fn unpack_archive(&self, archive_path: &Path, staging_path: &Path) -> Result<String> {
+        let xz_bytes = fs::read(archive_path).await?;
+        let mut tar = Archive::new(XzDecoder::new(xz_bytes.reader()));
+        tar.unpack(staging_path)?;
+
+        let binary_path = staging_path
+            .join("factorio")
+            .join("bin")
+            .join("x64")
+            .join("factorio");
+        if !binary_path.is_file() {
+            return Err(Error::InvalidInstallArchive(
+                "Archive does not contain a Factorio headless server".to_owned(),
+            ));
+        }
+        let output = Command::new(&binary_path).arg("--version").output().await?;
+        let version =
+            parse_version_output(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+                Error::InvalidInstallArchive("Could not read version from server binary".to_owned())
+            })?;
+        if self.versions.contains_key(&version) {
+            return Err(Error::InvalidInstallArchive(format!(
+                "Version {} is already installed",
+                version
+            )));
+        }
+        Ok(version)
+    }
+
     pub async fn delete(&mut self, version: &str) -> Result<()> {
         if let Some(installation) = self.versions.get(version) {
             fs::remove_dir_all(&installation.path).await?;
@@ -124,12 +257,69 @@ impl VersionManager {
     }
 }
 
+fn parse_version_output(output: &str) -> Option<String> {
+    VERSION_OUTPUT_RE
+        .captures(output)
+        .map(|c| c.get(1).unwrap().as_str().to_owned())
+}
+
 #[cfg(test)]
 mod tests {
     use uuid::Uuid;
 
     use super::*;
 
+    #[tokio::test]
+    async fn scan_finds_side_by_side_versions() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        fctrl::util::testing::logger_init();
+
+        let tmp_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(tmp_dir.join("factorio_headless_x64_1.1.104")).await?;
+        fs::create_dir_all(tmp_dir.join("factorio-headless_linux_2.0.28")).await?;
+        fs::create_dir_all(tmp_dir.join("factorio-headless_linux_2.0.9")).await?;
+        let vm = VersionManager::new(&tmp_dir).await?;
+
+        assert_eq!(vm.versions.len(), 3);
+        assert_eq!(vm.default_version().map(|f| f.version.as_str()), Some("2.0.28"));
+
+        let _ = fs::remove_dir_all(tmp_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn available_dlcs_found_in_data_dir() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        fctrl::util::testing::logger_init();
+
+        let tmp_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let data_dir = tmp_dir.join("factorio").join("data");
+        for dir in ["base", "core", "quality", "space-age"] {
+            fs::create_dir_all(data_dir.join(dir)).await?;
+        }
+        let installation = Factorio {
+            path: tmp_dir.clone(),
+            version: "2.0.28".to_owned(),
+        };
+        let dlcs = installation.available_dlcs().await?;
+
+        assert_eq!(dlcs, HashSet::from([Dlc::Base, Dlc::Quality, Dlc::SpaceAge]));
+
+        let _ = fs::remove_dir_all(tmp_dir).await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn version_read_from_binary_output() {
+        assert_eq!(
+            parse_version_output(
+                "Version: 2.0.28 (build 79441, linux64, headless, space-age)\nBinary version: 64\n"
+            ),
+            Some("2.0.28".to_owned())
+        );
+        assert_eq!(parse_version_output("not factorio"), None);
+    }
+
     #[tokio::test]
     async fn can_install_version_1_1_104() -> std::result::Result<(), Box<dyn std::error::Error>> {
         fctrl::util::testing::logger_init();
@@ -137,7 +327,7 @@ mod tests {
         let tmp_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
         fs::create_dir(&tmp_dir).await?;
         let mut vm = VersionManager::new(&tmp_dir).await?;
-        vm.install("1.1.104".to_owned()).await?;
+        vm.install("1.1.104".to_owned(), None).await?;
 
         assert!(vm.versions.contains_key("1.1.104"));
 
@@ -153,7 +343,7 @@ mod tests {
         let tmp_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
         fs::create_dir(&tmp_dir).await?;
         let mut vm = VersionManager::new(&tmp_dir).await?;
-        vm.install("2.0.28".to_owned()).await?;
+        vm.install("2.0.28".to_owned(), None).await?;
 
         assert!(vm.versions.contains_key("2.0.28"));
 
@@ -0,0 +1,123 @@
+use std::{
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use fctrl::schema::MissedRunPolicy;
+use log::{error, info, warn};
+use tokio::fs;
+
+use crate::{
+    consts::*,
+    scheduler::{JobDefinition, Scheduler},
+    server::proc::{ProcessManager, ProcessStatus},
+};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Signals agent liveness to process supervisors.
+///
+/// Each heartbeat writes the current time and Factorio server state to a health file, and pings
+/// the systemd watchdog if running under systemd. Heartbeats stop if the process manager is
+/// unresponsive, so a stale health file indicates a wedged agent.
+pub struct HealthReporter {
+    health_file: Option<PathBuf>,
+    notify_socket: Option<String>,
+}
+
+impl HealthReporter {
+    pub fn from_env() -> HealthReporter {
+        HealthReporter {
+            health_file: std::env::var(ENV_AGENT_HEALTH_FILE).ok().map(PathBuf::from),
+            // set by systemd for units with Type=notify
+            notify_socket: std::env::var("NOTIFY_SOCKET").ok(),
+        }
+    }
+
+    pub async fn start(self: Arc<Self>, proc_manager: Arc<ProcessManager>, scheduler: &Scheduler) {
+        if self.health_file.is_none() && self.notify_socket.is_none() {
+            info!("No health file or notify socket configured, health reporting disabled");
+            return;
+        }
+
+        let reporter = self;
+        let definition = JobDefinition {
+            name: "health_heartbeat",
+            interval: HEARTBEAT_INTERVAL,
+            jitter: Duration::ZERO,
+            missed_run: MissedRunPolicy::Skip,
+        };
+        scheduler
+            .register(definition, move || {
+                let reporter = Arc::clone(&reporter);
+                let proc_manager = Arc::clone(&proc_manager);
+                Box::pin(async move {
+                    match tokio::time::timeout(HEARTBEAT_INTERVAL, proc_manager.status()).await {
+                        Ok(status) => {
+                            let status = describe_status(&status);
+                            reporter.heartbeat(&status).await;
+                        }
+                        Err(_) => {
+                            warn!("Timed out querying process status, skipping heartbeat");
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .await;
+    }
+
+    /// Tells systemd that startup has finished, once the agent is accepting connections
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    async fn heartbeat(&self, status: &str) {
+        if let Some(path) = &self.health_file {
+            let contents = format!("{} {}\n", Utc::now().to_rfc3339(), status);
+            if let Err(e) = fs::write(path, contents).await {
+                error!("Error writing health file: {:?}", e);
+            }
+        }
+        self.notify(&format!("WATCHDOG=1\nSTATUS={}", status));
+    }
+
+    /// Sends a state update to the systemd notify socket, if there is one
+    fn notify(&self, state: &str) {
+        if let Some(notify_socket) = &self.notify_socket {
+            // abstract socket names are given with a leading '@'
+            let addr = match notify_socket.strip_prefix('@') {
+                Some(name) => SocketAddr::from_abstract_name(name),
+                None => SocketAddr::from_pathname(notify_socket),
+            };
+            let res = addr.and_then(|addr| {
+                let socket = UnixDatagram::unbound()?;
+                socket.send_to_addr(state.as_bytes(), &addr)
+            });
+            if let Err(e) = res {
+                error!("Error sending notification to {}: {:?}", notify_socket, e);
+            }
+        }
+    }
+}
+
+fn describe_status(status: &ProcessStatus) -> String {
+    match status {
+        ProcessStatus::NotRunning => "Factorio server not running".to_owned(),
+        ProcessStatus::Running {
+            player_count,
+            server_state,
+            ..
+        } => format!(
+            "Factorio server {} with {} player(s) online",
+            server_state.as_ref(),
+            player_count
+        ),
+    }
+}
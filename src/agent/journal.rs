@@ -0,0 +1,212 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use fctrl::schema::{AgentStreamingMessage, AgentStreamingMessageInner};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    consts::*,
+    error::Result,
+    server::{
+        config_file::{ConfigBackend, ConfigFile, ConfigFormat},
+        mods::Mod,
+    },
+};
+
+lazy_static! {
+    static ref JOURNAL_DIR: PathBuf = ROAMING_DATA_DIR.join("journal");
+}
+
+/// A change to disk state made over several steps, recorded in the journal while in progress so
+/// that it can be rolled back if the agent restarts part way through
+#[derive(Debug, Deserialize, Serialize)]
+pub enum JournalEntry {
+    /// Unpacking a Factorio installation into the path
+    VersionInstall {
+        /// None if installing from an archive, where the version isn't known until unpacked
+        version: Option<String>,
+        path: PathBuf,
+    },
+    /// Downloading mods into the mod directory
+    ModApply {
+        mod_dir: PathBuf,
+        mod_names: Vec<String>,
+        /// Mod zips in the mod directory before the operation started, which are left alone
+        #[serde(default)]
+        existing_files: Vec<String>,
+    },
+}
+
+/// Marks an operation as in progress, removing its journal entry when dropped, that is once the
+/// operation has finished, successfully or not
+pub struct JournalGuard {
+    path: PathBuf,
+}
+
+impl JournalEntry {
+    /// Entry for downloading the named mods into the mod directory, noting the mod zips already
+    /// there so that only those the operation creates are rolled back
+    pub async fn mod_apply(mod_dir: PathBuf, mod_names: Vec<String>) -> Result<JournalEntry> {
+        let existing_files = mod_zips(&mod_dir).await?;
+        Ok(JournalEntry::ModApply {
+            mod_dir,
+            mod_names,
+            existing_files,
+        })
+    }
+
+    pub async fn begin(self) -> Result<JournalGuard> {
+        let path = JOURNAL_DIR.join(format!("{}.json", uuid::Uuid::new_v4()));
+        entry_file(&path).write(&self).await?;
+        Ok(JournalGuard { path })
+    }
+
+    /// Undoes the partial changes of the interrupted operation, returning a description of what
+    /// was done
+    async fn roll_back(&self) -> Result<String> {
+        match self {
+            JournalEntry::VersionInstall { version, path } => {
+                if path.is_dir() {
+                    fs::remove_dir_all(path).await?;
+                }
+                Ok(match version {
+                    Some(version) => format!(
+                        "Removed incomplete installation of Factorio version {}, install it again to use it",
+                        version
+                    ),
+                    None => "Removed incomplete installation from archive".to_owned(),
+                })
+            }
+            JournalEntry::ModApply {
+                mod_dir,
+                mod_names,
+                existing_files,
+            } => {
+                // a download cut short leaves a truncated zip behind, which can't be told apart
+                // from a complete one, so remove every mod zip the operation created
+                let mut removed = vec![];
+                for file_name in mod_zips(mod_dir).await? {
+                    let created = !existing_files.contains(&file_name)
+                        && Mod::try_from_filename(&file_name)
+                            .map_or(false, |m| mod_names.contains(&m.name));
+                    if created {
+                        fs::remove_file(mod_dir.join(&file_name)).await?;
+                        removed.push(file_name);
+                    }
+                }
+                Ok(format!(
+                    "Removed possibly incomplete mod downloads [{}], apply the mod list again to install them",
+                    removed.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+impl Drop for JournalGuard {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            error!(
+                "Failed to remove journal entry {}: {:?}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Rolls back operations left unfinished by the previous run of the agent, returning messages
+/// reporting what was recovered. Must be called before anything reads the affected state.
+pub async fn recover() -> Result<Vec<AgentStreamingMessage>> {
+    let mut recovered = vec![];
+    if !JOURNAL_DIR.is_dir() {
+        return Ok(recovered);
+    }
+
+    let mut entries = fs::read_dir(&*JOURNAL_DIR).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let outcome = match entry_file(&path).read().await {
+            Ok(Some(journal_entry)) => {
+                warn!("Found interrupted operation {:?}", journal_entry);
+                journal_entry.roll_back().await.unwrap_or_else(|e| {
+                    format!(
+                        "Failed to roll back interrupted operation {:?}: {:?}",
+                        journal_entry, e
+                    )
+                })
+            }
+            Ok(None) => continue,
+            // most likely written partially as the agent stopped, before the operation started
+            Err(e) => format!("Discarded unreadable journal entry: {:?}", e),
+        };
+        info!("Recovery: {}", outcome);
+        recovered.push(AgentStreamingMessage {
+            timestamp: Utc::now(),
+            content: AgentStreamingMessageInner::OperationRecovered(outcome),
+        });
+        if let Err(e) = fs::remove_file(&path).await {
+            error!("Failed to remove journal entry {}: {:?}", path.display(), e);
+        }
+    }
+    Ok(recovered)
+}
+
+/// File names of the mod zips in the mod directory
+async fn mod_zips(mod_dir: &Path) -> Result<Vec<String>> {
+    let mut mod_zips = vec![];
+    if mod_dir.is_dir() {
+        let mut entries = fs::read_dir(mod_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if Mod::try_from_filename(&file_name).is_some() {
+                mod_zips.push(file_name);
+            }
+        }
+    }
+    Ok(mod_zips)
+}
+
+fn entry_file(path: &Path) -> ConfigFile<JournalEntry> {
+    ConfigFile::new(
+        "journal entry",
+        ConfigFormat::Json,
+        ConfigBackend::Filesystem(path.to_path_buf()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rolls_back_only_mods_being_downloaded(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        fctrl::util::testing::logger_init();
+
+        let mod_dir = std::env::temp_dir().join("rolls_back_only_mods_being_downloaded");
+        let _ = fs::remove_dir_all(&mod_dir).await;
+        fs::create_dir_all(&mod_dir).await?;
+        for file_name in [
+            "rso-mod_6.2.22.zip",
+            "rso-mod-extra_1.0.0.zip",
+            "mod-list.json",
+        ] {
+            fs::write(mod_dir.join(file_name), b"").await?;
+        }
+
+        let entry = JournalEntry::mod_apply(mod_dir.clone(), vec!["rso-mod".to_owned()]).await?;
+        fs::write(mod_dir.join("rso-mod_6.2.23.zip"), b"").await?;
+        entry.roll_back().await?;
+        assert!(!mod_dir.join("rso-mod_6.2.23.zip").exists());
+        assert!(mod_dir.join("rso-mod_6.2.22.zip").exists());
+        assert!(mod_dir.join("rso-mod-extra_1.0.0.zip").exists());
+        assert!(mod_dir.join("mod-list.json").exists());
+
+        let _ = fs::remove_dir_all(&mod_dir).await;
+        Ok(())
+    }
+}
@@ -1,68 +1,175 @@
 #![feature(trait_alias)]
 
 use std::{
-    collections::HashSet, convert::{TryFrom, TryInto}, net::{IpAddr, Ipv4Addr, SocketAddr}, str::FromStr, sync::Arc, time::Duration
+    collections::{HashMap, HashSet}, net::SocketAddr, str::FromStr, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration
 };
 
 use crate::{
+    backups::Backups,
     consts::*,
+    diagnostics::ConfigSnapshot,
+    download_server::DownloadServer,
     factorio::{Factorio, VersionManager},
+    health::HealthReporter,
+    outgoing::{OutgoingQueue, Priority},
+    remote_saves::RemoteSaves,
+    scheduler::Scheduler,
     server::{
-        builder::{ServerBuilder, StartableInstanceBuilder},
+        builder::{
+            ServerBuilder, ServerHostBuilder, StartableInstanceBuilder,
+            StartableShortLivedInstanceBuilder,
+        },
         proc::ProcessManager,
+        profiles::Profile,
         settings::{AdminList, LaunchSettings, ServerSettings},
         StoppedInstance,
     },
 };
 use chrono::Utc;
-use factorio_file_parser::ModSettings;
-use fctrl::schema::*;
-use futures::Sink;
-use futures_util::{
-    stream::{SplitSink, SplitStream},
-    SinkExt, StreamExt,
+use fctrl::{
+    game_message::{GameMessage, MessageCatalog},
+    mod_settings::ModSettings,
+    schema::*,
+    util::{ws_binary, ws_zlib_framing},
 };
+use futures_util::{stream::SplitStream, StreamExt};
 use log::{debug, error, info, warn};
 use server::{
-    mods::{Mod, ModManager},
-    settings::{BanList, Secrets, WhiteList},
+    mods::{Mod, ModInstallProgress, ModManager},
+    settings::{BanList, PlayerListDelta, Secrets, WhiteList},
 };
 use tokio::{
     fs,
     net::{TcpListener, TcpStream},
     sync::{
         broadcast::{self, error::RecvError},
-        watch, Mutex, RwLock,
+        watch, Mutex, RwLock, Semaphore,
     },
     task::JoinHandle,
 };
-use tokio_tungstenite::{accept_async, tungstenite, WebSocketStream};
-use tungstenite::Message;
+use tokio_tungstenite::{accept_hdr_async, tungstenite, WebSocketStream};
+use tungstenite::{
+    handshake::server::{Request, Response},
+    http::HeaderValue,
+    Message,
+};
 
+mod backups;
 mod consts;
+mod countdown;
+mod diagnostics;
+mod download_server;
 mod error;
 mod factorio;
+mod health;
+mod journal;
+mod outgoing;
+mod remote_saves;
+mod scheduler;
 mod server;
+mod uptime_restart;
 mod util;
 
 const MAX_WS_PAYLOAD_BYTES: usize = 8000000;
+const DEFAULT_SAVE_CHUNK_BYTES: usize = 1000000;
+/// How long to wait for the peer to acknowledge save chunks before abandoning the transfer
+const SAVE_GET_ACK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Width and height of generated map previews, in pixels
+const MAP_PREVIEW_SIZE: u32 = 1024;
+/// Held while a map preview is generated, as every preview is written to the same path
+static MAP_PREVIEW_GENERATING: Mutex<()> = Mutex::const_new(());
+/// Prints the map seed. Unlike reading the surface's map-gen-settings with Lua, this doesn't
+/// disable achievements.
+const SEED_RCON_COMMAND: &str = "/seed";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
+    info!("Init in-game message catalog");
+    MessageCatalog::from_env()?.install();
+
+    info!("Init outbound HTTP client");
+    util::http::init()?;
+
+    info!("Recovering interrupted operations");
+    // failing to recover shouldn't stop the agent from starting, as the rest still works
+    let recovered = match journal::recover().await {
+        Ok(recovered) => recovered,
+        Err(e) => {
+            error!("Failed to recover interrupted operations: {:?}", e);
+            vec![]
+        }
+    };
+
     info!("Init Factorio installation manager");
     let version_manager = Arc::new(RwLock::new(
         VersionManager::new(&*FACTORIO_INSTALL_DIR).await?,
     ));
 
+    let remote_saves = RemoteSaves::from_env().map(Arc::new);
+    if remote_saves.is_some() {
+        info!("Remote save storage enabled");
+    }
+
     info!("Init Factorio server process management");
-    let proc_manager = Arc::new(ProcessManager::new());
+    let proc_manager = Arc::new(ProcessManager::new(remote_saves.clone()));
+
+    info!("Checking configuration consistency");
+    match ConfigSnapshot::read().await {
+        Ok(snapshot) => {
+            for warning in snapshot.check() {
+                warn!("Configuration warning: {}", warning.message);
+            }
+        }
+        Err(e) => warn!("Unable to check configuration consistency: {:?}", e),
+    }
+
+    info!("Init job scheduler");
+    let scheduler = Scheduler::start();
+
+    info!("Init health reporting");
+    let health_reporter = Arc::new(HealthReporter::from_env());
+    Arc::clone(&health_reporter)
+        .start(Arc::clone(&proc_manager), &scheduler)
+        .await;
+
+    info!("Init uptime restarts");
+    uptime_restart::register(Arc::clone(&proc_manager), &scheduler).await;
+
+    if let (Ok(port), Ok(secret)) = (
+        std::env::var(ENV_AGENT_DOWNLOAD_PORT),
+        std::env::var(ENV_AGENT_DOWNLOAD_SECRET),
+    ) {
+        info!("Init direct download server");
+        DownloadServer::new(port.parse()?, secret).await?.start();
+    }
 
-    let (global_bus_tx, ..) = broadcast::channel::<AgentStreamingMessage>(300);
+    let global_bus_capacity = match std::env::var(ENV_AGENT_BUS_CAPACITY) {
+        Ok(s) => s.parse()?,
+        Err(_) => 300,
+    };
+    let (global_bus_tx, ..) = broadcast::channel::<AgentStreamingMessage>(global_bus_capacity);
+    let global_bus_tx = Arc::new(global_bus_tx);
+    let global_bus_dropped = Arc::new(AtomicU64::new(0));
+
+    info!("Init server process supervisor");
+    proc_manager.start_supervisor(Arc::clone(&global_bus_tx));
+
+    let save_chunk_bytes = match std::env::var(ENV_AGENT_SAVE_CHUNK_BYTES) {
+        Ok(s) => s.parse::<usize>()?.clamp(1, MAX_WS_PAYLOAD_BYTES),
+        Err(_) => DEFAULT_SAVE_CHUNK_BYTES,
+    };
+
+    let backups = Backups::from_env().map(Arc::new);
+    if let Some(backups) = &backups {
+        info!("Savefile backups enabled");
+        backups.start(&global_bus_tx, Arc::clone(&proc_manager));
+    }
 
     info!("Init WebSocketListener");
-    let ws_listener = WebSocketListener::new().await?;
+    let ws_listener = WebSocketListener::new(recovered).await?;
+    health_reporter.ready();
 
     info!("Init SIGINT handler");
     let (sigint_tx, sigint_rx) = watch::channel(false);
@@ -81,9 +188,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ws_listener
         .run(
             sigint_rx,
-            Arc::new(global_bus_tx),
+            global_bus_tx,
+            global_bus_dropped,
+            save_chunk_bytes,
             Arc::clone(&proc_manager),
             version_manager,
+            remote_saves,
+            backups,
+            scheduler,
         )
         .await;
 
@@ -95,23 +207,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 struct WebSocketListener {
     tcp: TcpListener,
+    /// Whether to accept message compression when offered by a peer
+    compression: bool,
+    /// Reports of operations recovered on startup, sent once the first peer connects
+    recovered: Vec<AgentStreamingMessage>,
 }
 
 impl WebSocketListener {
-    async fn new() -> Result<WebSocketListener, std::io::Error> {
+    async fn new(
+        recovered: Vec<AgentStreamingMessage>,
+    ) -> Result<WebSocketListener, std::io::Error> {
         // Safe to unwrap as this is checked by docker-compose
         let port = std::env::var(ENV_AGENT_WS_PORT).unwrap().parse().unwrap();
-        let bind_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
+        let bind_addr = SocketAddr::new(util::net::bind_ip_from_env(ENV_AGENT_BIND_ADDRESS), port);
         let tcp = TcpListener::bind(bind_addr).await?;
-        Ok(WebSocketListener { tcp })
+        Ok(WebSocketListener {
+            tcp,
+            compression: ws_zlib_framing::enabled_from_env(),
+            recovered,
+        })
     }
 
     async fn run(
-        self,
+        mut self,
         mut shutdown_rx: watch::Receiver<bool>,
         global_bus_tx: Arc<broadcast::Sender<AgentStreamingMessage>>,
+        global_bus_dropped: Arc<AtomicU64>,
+        save_chunk_bytes: usize,
         proc_manager: Arc<ProcessManager>,
         version_manager: Arc<RwLock<VersionManager>>,
+        remote_saves: Option<Arc<RemoteSaves>>,
+        backups: Option<Arc<Backups>>,
+        scheduler: Arc<Scheduler>,
     ) {
         loop {
             tokio::select! {
@@ -119,14 +246,24 @@ impl WebSocketListener {
                     if let Ok((stream, _)) = res {
                         match AgentController::handle_connection(
                             stream,
+                            self.compression,
                             shutdown_rx.clone(),
                             Arc::clone(&global_bus_tx),
+                            Arc::clone(&global_bus_dropped),
+                            save_chunk_bytes,
                             Arc::clone(&proc_manager),
                             Arc::clone(&version_manager),
+                            remote_saves.clone(),
+                            backups.clone(),
+                            Arc::clone(&scheduler),
                         )
                         .await
                         {
                             Ok(controller) => {
+                                // the controller is subscribed to the global bus by now
+                                for message in self.recovered.drain(..) {
+                                    let _ = global_bus_tx.send(message);
+                                }
                                 tokio::spawn(async move {
                                     if let Err(e) = controller.message_loop().await {
                                         match e {
@@ -155,13 +292,38 @@ impl WebSocketListener {
     }
 }
 
+/// Settings and mods a server is started with
+struct ServerStartSettings {
+    mods: ModManager,
+    launch_settings: LaunchSettings,
+    server_settings: ServerSettings,
+    admin_list: AdminList,
+    ban_list: BanList,
+    white_list: WhiteList,
+}
+
 struct AgentController {
     peer_addr: SocketAddr,
     proc_manager: Arc<ProcessManager>,
     version_manager: Arc<RwLock<VersionManager>>,
+    /// Additional savefile storage, if configured
+    remote_saves: Option<Arc<RemoteSaves>>,
+    /// Off-box savefile backups, if configured
+    backups: Option<Arc<Backups>>,
+    scheduler: Arc<Scheduler>,
     global_tx: Arc<broadcast::Sender<AgentStreamingMessage>>,
+    global_bus_dropped: Arc<AtomicU64>,
+    save_chunk_bytes: usize,
+    /// Flow control for in-progress save transfers, keyed by operation id.
+    /// Each chunk sent consumes a permit, and each ack from the peer adds the ack interval back.
+    save_get_credits: Arc<Mutex<HashMap<String, (Arc<Semaphore>, usize)>>>,
     ws_rx: Option<SplitStream<WebSocketStream<TcpStream>>>,
-    ws_tx: Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
+    outgoing: OutgoingQueue,
+    /// Whether the peer accepts file transfers in binary encoding
+    binary_encoding: bool,
+    /// Stdout categories the peer is interested in, or None for all
+    stdout_filter: watch::Sender<Option<HashSet<StdoutCategory>>>,
+    _outgoing_task: JoinHandle<()>,
     _send_global_outgoing_msgs_task: JoinHandle<()>,
     _sigint_task: JoinHandle<()>,
 }
@@ -169,24 +331,68 @@ struct AgentController {
 impl AgentController {
     async fn handle_connection(
         tcp: TcpStream,
+        compression: bool,
         mut shutdown_rx: watch::Receiver<bool>,
         global_bus_tx: Arc<broadcast::Sender<AgentStreamingMessage>>,
+        global_bus_dropped: Arc<AtomicU64>,
+        save_chunk_bytes: usize,
         proc_manager: Arc<ProcessManager>,
         version_manager: Arc<RwLock<VersionManager>>,
+        remote_saves: Option<Arc<RemoteSaves>>,
+        backups: Option<Arc<Backups>>,
+        scheduler: Arc<Scheduler>,
     ) -> tungstenite::Result<AgentController> {
         let peer_addr = tcp.peer_addr()?;
-        let ws = accept_async(tcp).await?;
+        let mut compress = false;
+        let mut binary_encoding = false;
+        let ws = accept_hdr_async(tcp, |request: &Request, mut response: Response| {
+            if compression && ws_zlib_framing::negotiated(request.headers()) {
+                response.headers_mut().insert(
+                    ws_zlib_framing::HEADER,
+                    HeaderValue::from_static(ws_zlib_framing::ZLIB),
+                );
+                compress = true;
+            }
+            // only offered by peers configured to use it, so always accept
+            if ws_binary::negotiated(request.headers()) {
+                response.headers_mut().insert(
+                    ws_binary::HEADER,
+                    HeaderValue::from_static(ws_binary::MSGPACK),
+                );
+                binary_encoding = true;
+            }
+            Ok(response)
+        })
+        .await?;
         let (ws_tx, ws_rx) = ws.split();
-        let ws_tx = Arc::new(Mutex::new(ws_tx));
-        info!("WebSocket peer connected: {}", peer_addr);
+        info!(
+            "WebSocket peer connected: {}, compression {}, binary encoding {}",
+            peer_addr,
+            if compress { "enabled" } else { "disabled" },
+            if binary_encoding { "enabled" } else { "disabled" }
+        );
+
+        // Set up background task to write outgoing messages in priority order, so that large
+        // transfers and stdout floods don't hold up responses to other requests
+        let (outgoing, outgoing_lanes) = OutgoingQueue::new();
+        let _outgoing_task = tokio::spawn(outgoing_lanes.with_compression(compress).run(ws_tx));
 
         // Set up background task to deliver outgoing messages from the global broadcast message bus
-        let ws_tx_clone = Arc::clone(&ws_tx);
+        let outgoing_clone = outgoing.clone();
+        let (stdout_filter, stdout_filter_rx) = watch::channel(None);
         let mut global_bus_rx = global_bus_tx.subscribe();
+        let global_bus_dropped_clone = Arc::clone(&global_bus_dropped);
         let _send_global_outgoing_msgs_task = tokio::spawn(async move {
             loop {
                 match global_bus_rx.recv().await {
                     Ok(outgoing) => {
+                        if let AgentStreamingMessageInner::ServerStdout(line) = &outgoing.content {
+                            if let Some(categories) = stdout_filter_rx.borrow().as_ref() {
+                                if !categories.contains(&StdoutCategory::classify(line)) {
+                                    continue;
+                                }
+                            }
+                        }
                         let json = serde_json::to_string(&outgoing);
                         match json {
                             Err(e) => {
@@ -194,16 +400,15 @@ impl AgentController {
                             }
                             Ok(json) => {
                                 debug!("Sending streaming message: {}", json);
-                                AgentController::_send_message(
-                                    Arc::clone(&ws_tx_clone),
-                                    Message::Text(json.into()),
-                                )
-                                .await;
+                                outgoing_clone
+                                    .send(Priority::Streaming, Message::Text(json.into()))
+                                    .await;
                             }
                         }
                     }
                     Err(RecvError::Lagged(num_skipped)) => {
-                        warn!("global bus rx lagging, skipped {} messages!", num_skipped)
+                        warn!("global bus rx lagging, skipped {} messages!", num_skipped);
+                        global_bus_dropped_clone.fetch_add(num_skipped, Ordering::Relaxed);
                     }
                     Err(RecvError::Closed) => {
                         error!("All global bus senders closed - this should never happen");
@@ -216,20 +421,31 @@ impl AgentController {
         });
 
         // Background task to close connection on SIGINT
-        let ws_tx_close = Arc::clone(&ws_tx);
+        let outgoing_close = outgoing.clone();
         let _sigint_task = tokio::spawn(async move {
             let _ = shutdown_rx.changed().await;
             info!("Closing WebSocket connection for peer {}", peer_addr);
-            let _ = ws_tx_close.lock().await.close().await;
+            outgoing_close
+                .send(Priority::Control, Message::Close(None))
+                .await;
         });
 
         Ok(AgentController {
             peer_addr,
             proc_manager,
             version_manager,
+            remote_saves,
+            backups,
+            scheduler,
             global_tx: global_bus_tx,
+            global_bus_dropped,
+            save_chunk_bytes,
+            save_get_credits: Arc::new(Mutex::new(HashMap::new())),
             ws_rx: Some(ws_rx),
-            ws_tx,
+            outgoing,
+            binary_encoding,
+            stdout_filter,
+            _outgoing_task,
             _send_global_outgoing_msgs_task,
             _sigint_task,
         })
@@ -263,203 +479,327 @@ impl AgentController {
 
         info!("Cleaning up for peer {}", controller.peer_addr);
         controller._send_global_outgoing_msgs_task.abort();
+        controller._outgoing_task.abort();
         controller._sigint_task.abort();
         Ok(())
     }
 
     async fn handle_message(&self, msg: Message) {
-        match msg {
-            Message::Text(json) => {
-                if let Ok(msg) = serde_json::from_str::<AgentRequestWithId>(&json) {
+        let msg = match ws_zlib_framing::decompress(msg).await {
+            Ok(msg) => msg,
+            Err(e) => {
+                warn!("Failed to decompress message from {}: {:?}", self.peer_addr, e);
+                return;
+            }
+        };
+        let request = match &msg {
+            Message::Text(json) => match serde_json::from_str::<AgentRequestWithId>(json) {
+                Ok(request) => {
                     debug!("Got incoming message from {}: {}", self.peer_addr, json);
-                    let operation_id = msg.operation_id;
-                    match msg.message {
-                        // *******************
-                        // Internal versioning
-                        // *******************
-                        AgentRequest::BuildVersion => {
-                            self.build_version(operation_id).await;
-                        }
+                    request
+                }
+                Err(_) => return,
+            },
+            Message::Binary(_) => match ws_binary::decode::<AgentRequestWithId>(&msg) {
+                Some(Ok(request)) => {
+                    debug!(
+                        "Got binary-encoded incoming message from {}: {:?}",
+                        self.peer_addr, request.operation_id
+                    );
+                    request
+                }
+                Some(Err(e)) => {
+                    warn!("Failed to decode message from {}: {:?}", self.peer_addr, e);
+                    return;
+                }
+                None => return,
+            },
+            // pings are handled by the tungstenite library, and close messages by the message loop
+            _ => return,
+        };
+        self.handle_request(request).await;
+    }
 
-                        // *****************
-                        // System monitoring
-                        // *****************
-                        AgentRequest::SystemResources => {
-                            self.system_resources(operation_id).await;
-                        }
+    async fn handle_request(&self, request: AgentRequestWithId) {
+        let operation_id = request.operation_id;
+        match request.message {
+            // *******************
+            // Internal versioning
+            // *******************
+            AgentRequest::BuildVersion => {
+                self.build_version(operation_id).await;
+            }
 
-                        // ***********************
-                        // Installation management
-                        // ***********************
-                        AgentRequest::VersionInstall {
-                            version,
-                            force_install,
-                        } => {
-                            self.version_install(version, force_install, operation_id)
-                                .await
-                        }
+            // *****************
+            // System monitoring
+            // *****************
+            AgentRequest::SystemResources => {
+                self.system_resources(operation_id).await;
+            }
 
-                        AgentRequest::VersionGet => {
-                            self.version_get(operation_id).await;
-                        }
+            AgentRequest::JobList => {
+                self.job_list(operation_id).await;
+            }
 
-                        // **************
-                        // Server control
-                        // **************
-                        AgentRequest::ServerStart(savefile) => {
-                            self.server_start(savefile, operation_id).await
-                        }
+            // ***********************
+            // Installation management
+            // ***********************
+            AgentRequest::VersionInstall {
+                version,
+                force_install,
+                keep_existing,
+            } => {
+                self.version_install(version, force_install, keep_existing, operation_id)
+                    .await
+            }
 
-                        AgentRequest::ServerStop => self.server_stop(operation_id).await,
+            AgentRequest::VersionInstallFromArchive(path) => {
+                self.version_install_from_archive(path, operation_id).await
+            }
 
-                        AgentRequest::ServerStatus => self.server_status(operation_id).await,
+            AgentRequest::VersionGet => {
+                self.version_get(operation_id).await;
+            }
 
-                        // *******************
-                        // Savefile management
-                        // *******************
-                        AgentRequest::SaveCreate(save_name, map_gen_settings, map_gen_seed) => {
-                            self.save_create(
-                                save_name,
-                                map_gen_settings,
-                                map_gen_seed,
-                                operation_id,
-                            )
-                            .await
-                        }
+            AgentRequest::VersionList => {
+                self.version_list(operation_id).await;
+            }
 
-                        AgentRequest::SaveDelete(save_name) => {
-                            self.save_delete(save_name, operation_id).await
-                        }
+            // **************
+            // Server control
+            // **************
+            AgentRequest::ServerStart(savefile, version, profile) => {
+                self.server_start(savefile, version, profile, operation_id).await
+            }
 
-                        AgentRequest::SaveGet(save_name) => {
-                            self.save_get(save_name, operation_id).await
-                        }
+            AgentRequest::ServerStartPlan(savefile, version, profile) => {
+                self.server_start_plan(savefile, version, profile, operation_id).await
+            }
 
-                        AgentRequest::SaveList => {
-                            self.save_list(operation_id).await;
-                        }
+            AgentRequest::ServerStop => self.server_stop(operation_id).await,
 
-                        AgentRequest::SaveSet(save_name, bytes) => {
-                            self.save_set(save_name, bytes, operation_id).await;
-                        }
+            AgentRequest::ServerStopScheduled {
+                delay_secs,
+                message,
+            } => {
+                self.server_stop_scheduled(delay_secs, message, operation_id)
+                    .await
+            }
 
-                        // **************
-                        // Mod management
-                        // **************
-                        AgentRequest::ModDlcsGet => {
-                            self.mod_dlcs_get(operation_id).await;
-                        }
+            AgentRequest::ServerStatus => self.server_status(operation_id).await,
 
-                        AgentRequest::ModDlcsSet(dlcs) => {
-                            self.mod_dlcs_set(dlcs.into_iter().collect(), operation_id).await;
-                        }
+            // *******************
+            // Savefile management
+            // *******************
+            AgentRequest::SaveCreate(save_name, map_gen_settings, map_gen_seed) => {
+                self.save_create(
+                    save_name,
+                    map_gen_settings,
+                    map_gen_seed,
+                    operation_id,
+                )
+                .await
+            }
 
-                        AgentRequest::ModListGet => {
-                            self.mod_list_get(operation_id).await;
-                        }
+            AgentRequest::SaveDelete(save_name) => {
+                self.save_delete(save_name, operation_id).await
+            }
 
-                        AgentRequest::ModListExtractFromSave(save_name) => {
-                            self.mod_list_extract_from_save(save_name, operation_id)
-                                .await;
-                        }
+            AgentRequest::SaveRename(save_name, new_name) => {
+                self.save_rename(save_name, new_name, operation_id).await
+            }
 
-                        AgentRequest::ModListSet(mod_list) => {
-                            self.mod_list_set(mod_list, operation_id).await;
-                        }
+            AgentRequest::SaveCopy(save_name, new_name) => {
+                self.save_copy(save_name, new_name, operation_id).await
+            }
 
-                        AgentRequest::ModSettingsGet => {
-                            self.mod_settings_get(operation_id).await;
-                        }
+            AgentRequest::BackupCreate(save_name) => {
+                self.backup_create(save_name, operation_id).await
+            }
 
-                        AgentRequest::ModSettingsSet(bytes) => {
-                            self.mod_settings_set(bytes, operation_id).await;
-                        }
+            AgentRequest::BackupStatusGet => self.backup_status_get(operation_id).await,
 
-                        // *************
-                        // Configuration
-                        // *************
-                        AgentRequest::ConfigAdminListGet => {
-                            self.config_admin_list_get(operation_id).await;
-                        }
+            AgentRequest::SaveGet(save_name, ack_interval) => {
+                self.save_get(save_name, ack_interval, operation_id).await
+            }
 
-                        AgentRequest::ConfigAdminListSet { admins } => {
-                            self.config_admin_list_set(admins, operation_id).await;
-                        }
+            AgentRequest::SaveGetAck(transfer_id) => {
+                self.save_get_ack(transfer_id).await
+            }
 
-                        AgentRequest::ConfigBanListGet => {
-                            self.config_ban_list_get(operation_id).await;
-                        }
+            AgentRequest::SaveList => {
+                self.save_list(operation_id).await;
+            }
 
-                        AgentRequest::ConfigBanListSet { users } => {
-                            self.config_ban_list_set(users, operation_id).await;
-                        }
+            AgentRequest::SaveChecksumGet(save_name) => {
+                self.save_checksum_get(save_name, operation_id).await;
+            }
 
-                        AgentRequest::ConfigRconGet => {
-                            self.config_rcon_get(operation_id).await;
-                        }
+            AgentRequest::SaveMetadataGet(save_name) => {
+                self.save_metadata_get(save_name, operation_id).await;
+            }
 
-                        AgentRequest::ConfigRconSet { password } => {
-                            self.config_rcon_set(password, operation_id).await;
-                        }
+            AgentRequest::SaveSet(save_name, bytes) => {
+                self.save_set(save_name, bytes, operation_id).await;
+            }
 
-                        AgentRequest::ConfigSecretsGet => {
-                            self.config_secrets_get(operation_id).await;
-                        }
+            AgentRequest::MapPreviewGenerate => {
+                self.map_preview_generate(operation_id).await;
+            }
 
-                        AgentRequest::ConfigSecretsSet { username, token } => {
-                            self.config_secrets_set(username, token, operation_id).await;
-                        }
+            AgentRequest::MapPreviewGet => {
+                self.map_preview_get(operation_id).await;
+            }
 
-                        AgentRequest::ConfigServerSettingsGet => {
-                            self.config_server_settings_get(operation_id).await;
-                        }
+            // **************
+            // Mod management
+            // **************
+            AgentRequest::ModDlcsGet => {
+                self.mod_dlcs_get(operation_id).await;
+            }
 
-                        AgentRequest::ConfigServerSettingsSet { config } => {
-                            self.config_server_settings_set(config, operation_id).await;
-                        }
+            AgentRequest::ModDlcsAvailableGet => {
+                self.mod_dlcs_available_get(operation_id).await;
+            }
 
-                        AgentRequest::ConfigWhiteListGet => {
-                            self.config_white_list_get(operation_id).await;
-                        }
+            AgentRequest::ModDlcsSet(dlcs) => {
+                self.mod_dlcs_set(dlcs.into_iter().collect(), operation_id).await;
+            }
 
-                        AgentRequest::ConfigWhiteListSet { enabled, users } => {
-                            self.config_white_list_set(enabled, users, operation_id)
-                                .await;
-                        }
+            AgentRequest::ModListGet => {
+                self.mod_list_get(operation_id).await;
+            }
 
-                        // *******
-                        // In-game
-                        // *******
-                        AgentRequest::RconCommand(cmd) => {
-                            self.rcon_command(cmd, operation_id).await
-                        }
-                    }
-                }
+            AgentRequest::ModListExtractFromSave(save_name) => {
+                self.mod_list_extract_from_save(save_name, operation_id)
+                    .await;
             }
-            Message::Binary(_) => {
-                // binary messages not supported
+
+            AgentRequest::ModListSet(mod_list) => {
+                self.mod_list_set(mod_list, operation_id).await;
             }
-            Message::Ping(_) => {
-                // tungstenite library handles pings already
+
+            AgentRequest::ModListUpdateAll => {
+                self.mod_list_update_all(operation_id).await;
             }
-            Message::Close(_) => {
-                // this should have been handled already
+
+            AgentRequest::ModEnable(name) => {
+                self.mod_set_enabled(name, true, operation_id).await;
             }
-            _ => {
-                // other message
+
+            AgentRequest::ModDisable(name) => {
+                self.mod_set_enabled(name, false, operation_id).await;
             }
-        }
-    }
 
-    async fn _send_message<S: Sink<Message> + Unpin>(ws_tx: Arc<Mutex<S>>, message: Message)
-    where
-        <S as futures::Sink<tokio_tungstenite::tungstenite::Message>>::Error: std::fmt::Debug,
-    {
-        let mut tx = ws_tx.lock().await;
-        if let Err(e) = tx.send(message).await {
-            error!("Error sending message: {:?}", e);
-        } else {
-            let _ = tx.flush().await;
+            AgentRequest::ModSettingsGet => {
+                self.mod_settings_get(operation_id).await;
+            }
+
+            AgentRequest::ModSettingsSet(bytes) => {
+                self.mod_settings_set(bytes, operation_id).await;
+            }
+
+            // *************
+            // Configuration
+            // *************
+            AgentRequest::ConfigAdminListGet => {
+                self.config_admin_list_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigAdminListSet { admins } => {
+                self.config_admin_list_set(admins, operation_id).await;
+            }
+
+            AgentRequest::ConfigBanListGet => {
+                self.config_ban_list_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigBanListSet { users } => {
+                self.config_ban_list_set(users, operation_id).await;
+            }
+
+            AgentRequest::ConfigDiagnosticsGet => {
+                self.config_diagnostics_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigPerformanceGet => {
+                self.config_performance_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigPerformanceSet(config) => {
+                self.config_performance_set(config, operation_id).await;
+            }
+
+            AgentRequest::ConfigProfileList => {
+                self.config_profile_list(operation_id).await;
+            }
+
+            AgentRequest::ConfigProfileSave(name) => {
+                self.config_profile_save(name, operation_id).await;
+            }
+
+            AgentRequest::ConfigProfileDelete(name) => {
+                self.config_profile_delete(name, operation_id).await;
+            }
+
+            AgentRequest::ConfigRconGet => {
+                self.config_rcon_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigRconSet { password } => {
+                self.config_rcon_set(password, operation_id).await;
+            }
+
+            AgentRequest::ConfigRestartPolicyGet => {
+                self.config_restart_policy_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigRestartPolicySet(restart_policy) => {
+                self.config_restart_policy_set(restart_policy, operation_id).await;
+            }
+
+            AgentRequest::ConfigSecretsGet => {
+                self.config_secrets_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigSecretsSet { username, token } => {
+                self.config_secrets_set(username, token, operation_id).await;
+            }
+
+            AgentRequest::ConfigServerSettingsGet => {
+                self.config_server_settings_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigServerSettingsSet { config } => {
+                self.config_server_settings_set(config, operation_id).await;
+            }
+
+            AgentRequest::ConfigWhiteListGet => {
+                self.config_white_list_get(operation_id).await;
+            }
+
+            AgentRequest::ConfigWhiteListSet { enabled, users } => {
+                self.config_white_list_set(enabled, users, operation_id)
+                    .await;
+            }
+
+            // *******
+            // In-game
+            // *******
+            AgentRequest::RconCommand(cmd) => {
+                self.rcon_command(cmd, operation_id).await
+            }
+
+            AgentRequest::RconWhisper { players, message } => {
+                self.rcon_whisper(players, message, operation_id).await
+            }
+
+            // *********
+            // Streaming
+            // *********
+            AgentRequest::StreamStdoutSubscribe(categories) => {
+                self.stream_stdout_subscribe(categories, operation_id).await;
+            }
         }
     }
 
@@ -470,16 +810,7 @@ impl AgentController {
             timestamp: Utc::now(),
             content: AgentOutMessage::Ok,
         };
-        let json = serde_json::to_string(&with_id);
-        match json {
-            Err(e) => {
-                error!("Error serialising message: {:?}", e);
-            }
-            Ok(json) => {
-                debug!("Sending ack: {}", json);
-                AgentController::_send_message(Arc::clone(&self.ws_tx), Message::Text(json.into())).await;
-            }
-        }
+        self.send_response(Priority::Control, with_id, "ack").await;
     }
 
     async fn reply(&self, message: AgentOutMessage, operation_id: &OperationId) {
@@ -489,16 +820,8 @@ impl AgentController {
             timestamp: Utc::now(),
             content: message,
         };
-        let json = serde_json::to_string(&with_id);
-        match json {
-            Err(e) => {
-                error!("Error serialising message: {:?}", e);
-            }
-            Ok(json) => {
-                debug!("Sending reply: {}", json);
-                AgentController::_send_message(Arc::clone(&self.ws_tx), Message::Text(json.into())).await;
-            }
-        }
+        let priority = self.outgoing.response_priority(&with_id);
+        self.send_response(priority, with_id, "reply").await;
     }
 
     async fn reply_success(&self, message: AgentOutMessage, operation_id: OperationId) {
@@ -508,14 +831,27 @@ impl AgentController {
             timestamp: Utc::now(),
             content: message,
         };
-        let json = serde_json::to_string(&with_id);
-        match json {
-            Err(e) => {
-                error!("Error serialising message: {:?}", e);
-            }
-            Ok(json) => {
-                debug!("Sending reply_success: {}", json);
-                AgentController::_send_message(Arc::clone(&self.ws_tx), Message::Text(json.into())).await;
+        let priority = self.outgoing.response_priority(&with_id);
+        self.send_response(priority, with_id, "reply_success").await;
+    }
+
+    /// Fails the operation if there isn't enough free space for it to write to the path.
+    /// Returns whether the operation can go ahead.
+    async fn preflight_disk_space(
+        &self,
+        path: &std::path::Path,
+        required_bytes: u64,
+        operation_id: &OperationId,
+    ) -> bool {
+        match util::disk::check_available_space(path, required_bytes) {
+            Ok(()) => true,
+            Err(insufficient) => {
+                self.reply_failed(
+                    AgentOutMessage::InsufficientDiskSpace(insufficient),
+                    operation_id.clone(),
+                )
+                .await;
+                false
             }
         }
     }
@@ -527,15 +863,33 @@ impl AgentController {
             timestamp: Utc::now(),
             content: message,
         };
-        let json = serde_json::to_string(&with_id);
-        match json {
-            Err(e) => {
-                error!("Error serialising message: {:?}", e);
-            }
-            Ok(json) => {
-                debug!("Sending reply_failed: {}", json);
-                AgentController::_send_message(Arc::clone(&self.ws_tx), Message::Text(json.into())).await;
+        let priority = self.outgoing.response_priority(&with_id);
+        self.send_response(priority, with_id, "reply_failed").await;
+    }
+
+    /// Sends a response as JSON, or binary-encoded if it is a file transfer and the peer accepts
+    /// binary encoding
+    async fn send_response(&self, priority: Priority, with_id: AgentResponseWithId, kind: &str) {
+        let file_transfer = Priority::of(&with_id.content) == Priority::Bulk;
+        let message = if self.binary_encoding && file_transfer {
+            debug!("Sending binary-encoded {}: {:?}", kind, with_id.operation_id);
+            ws_binary::encode(&with_id)
+                .map_err(|e| error!("Error serialising message: {:?}", e))
+                .ok()
+        } else {
+            match serde_json::to_string(&with_id) {
+                Err(e) => {
+                    error!("Error serialising message: {:?}", e);
+                    None
+                }
+                Ok(json) => {
+                    debug!("Sending {}: {}", kind, json);
+                    Some(Message::Text(json.into()))
+                }
             }
+        };
+        if let Some(message) = message {
+            self.outgoing.send(priority, message).await;
         }
     }
 
@@ -550,7 +904,8 @@ impl AgentController {
 
     async fn system_resources(&self, operation_id: OperationId) {
         match self.proc_manager.system_resources().await {
-            Ok(system_resources) => {
+            Ok(mut system_resources) => {
+                system_resources.dropped_messages = self.global_bus_dropped.load(Ordering::Relaxed);
                 self.reply_success(AgentOutMessage::SystemResources(system_resources), operation_id).await;
             },
             Err(e) => {
@@ -562,19 +917,45 @@ impl AgentController {
         }
     }
 
+    async fn job_list(&self, operation_id: OperationId) {
+        self.reply_success(AgentOutMessage::JobList(self.scheduler.list()), operation_id)
+            .await;
+    }
+
     async fn version_install(
         &self,
         version_to_install: FactorioVersion,
         force_install: bool,
+        keep_existing: bool,
         operation_id: OperationId,
     ) {
         if let Ok(mut vm) =
             tokio::time::timeout(Duration::from_millis(250), self.version_manager.write()).await
         {
             let version_to_install = version_to_install.0;
+            if !self
+                .preflight_disk_space(
+                    &FACTORIO_INSTALL_DIR,
+                    util::disk::VERSION_INSTALL_REQUIRED_BYTES,
+                    &operation_id,
+                )
+                .await
+            {
+                return;
+            }
             self.long_running_ack(&operation_id).await;
-            // Assume there is at most one version installed
-            match vm.versions.keys().next() {
+            if keep_existing {
+                self.version_install_side_by_side(
+                    &mut vm,
+                    version_to_install,
+                    force_install,
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+
+            match vm.default_version().map(|f| f.version.clone()) {
                 None => {
                     info!("Installing version {}", version_to_install);
                     self.reply(
@@ -585,7 +966,10 @@ impl AgentController {
                         &operation_id,
                     )
                     .await;
-                    if let Err(e) = vm.install(version_to_install.clone()).await {
+                    if let Err(e) = self
+                        .install_reporting_progress(&mut vm, version_to_install.clone(), &operation_id)
+                        .await
+                    {
                         self.reply_failed(
                             AgentOutMessage::Message(format!("Failed to install: {:?}", e)),
                             operation_id,
@@ -597,17 +981,39 @@ impl AgentController {
                     }
                 }
                 Some(version_from) => {
-                    let version_from = version_from.to_string();
-                    let is_reinstall = version_from == version_to_install;
+                    let already_installed = vm.versions.contains_key(&version_to_install);
+                    let other_versions: Vec<String> = vm
+                        .versions
+                        .keys()
+                        .filter(|v| **v != version_to_install)
+                        .cloned()
+                        .collect();
 
                     // Only reinstall if forced, otherwise noop
-                    if is_reinstall && !force_install {
+                    if already_installed && other_versions.is_empty() && !force_install {
                         self.reply_success(AgentOutMessage::Ok, operation_id).await;
                         return;
                     }
 
+                    if FactorioVersion(version_to_install.clone())
+                        < FactorioVersion(version_from.clone())
+                    {
+                        warn!(
+                            "Requested install of version {} is a downgrade from version {}",
+                            version_to_install, version_from
+                        );
+                        self.reply(
+                            AgentOutMessage::Message(format!(
+                                "Warning: version {} is older than the installed version {}. Saves last played on version {} may fail to load.",
+                                version_to_install, version_from, version_from
+                            )),
+                            &operation_id,
+                        )
+                        .await;
+                    }
+
                     let opt_stopped_instance;
-                    if is_reinstall {
+                    if already_installed && force_install {
                         // Stop server first before re-installing
                         info!("Stopping server for reinstall");
                         opt_stopped_instance = self.proc_manager.stop_instance().await;
@@ -628,7 +1034,10 @@ impl AgentController {
                             &operation_id,
                         )
                         .await;
-                        if let Err(e) = vm.install(version_to_install.clone()).await {
+                        if let Err(e) = self
+                            .install_reporting_progress(&mut vm, version_to_install.clone(), &operation_id)
+                            .await
+                        {
                             self.reply_failed(
                                 AgentOutMessage::Error(format!("Failed to install: {:?}", e)),
                                 operation_id,
@@ -647,33 +1056,48 @@ impl AgentController {
                             .await;
                         }
                     } else {
-                        // Install requested version
-                        info!("Installing version {} for upgrade", version_to_install);
-                        self.reply(
-                            AgentOutMessage::Message(format!(
-                                "Starting to install version {}",
-                                version_to_install
-                            )),
-                            &operation_id,
-                        )
-                        .await;
-                        if let Err(e) = vm.install(version_to_install.clone()).await {
-                            self.reply_failed(
-                                AgentOutMessage::Error(format!("Failed to install: {:?}", e)),
-                                operation_id,
+                        if already_installed {
+                            // Installed previously alongside other versions, nothing to download
+                            self.reply(
+                                AgentOutMessage::Message(format!(
+                                    "Version {} is already installed",
+                                    version_to_install
+                                )),
+                                &operation_id,
                             )
                             .await;
-                            return;
                         } else {
-                            info!("Installed version {} for upgrade", version_to_install);
+                            // Install requested version
+                            info!("Installing version {} for upgrade", version_to_install);
                             self.reply(
                                 AgentOutMessage::Message(format!(
-                                    "Installed version {} for upgrade",
+                                    "Starting to install version {}",
                                     version_to_install
                                 )),
                                 &operation_id,
                             )
                             .await;
+                            if let Err(e) = self
+                                .install_reporting_progress(&mut vm, version_to_install.clone(), &operation_id)
+                                .await
+                            {
+                                self.reply_failed(
+                                    AgentOutMessage::Error(format!("Failed to install: {:?}", e)),
+                                    operation_id,
+                                )
+                                .await;
+                                return;
+                            } else {
+                                info!("Installed version {} for upgrade", version_to_install);
+                                self.reply(
+                                    AgentOutMessage::Message(format!(
+                                        "Installed version {} for upgrade",
+                                        version_to_install
+                                    )),
+                                    &operation_id,
+                                )
+                                .await;
+                            }
                         }
 
                         // Stop server if running
@@ -690,8 +1114,8 @@ impl AgentController {
 
                     // TODO stage save migrations?
 
-                    // If not a reinstall, remove previous version
-                    if !is_reinstall {
+                    // Remove all other versions, including any installed side-by-side
+                    for version_from in other_versions {
                         info!("Removing previous version {} after upgrade", version_from);
                         if let Err(e) = vm.delete(&version_from).await {
                             self.reply_failed(AgentOutMessage::Error(format!("Failed to remove previous version {} after upgrading to version {}: {:?}", version_from, version_to_install, e)), operation_id).await;
@@ -720,6 +1144,7 @@ impl AgentController {
                         self.internal_server_start_with_version(
                             version,
                             previous_instance.savefile.clone(),
+                            previous_instance.profile.clone(),
                             operation_id,
                             Some(previous_instance),
                         )
@@ -735,11 +1160,159 @@ impl AgentController {
         }
     }
 
-    async fn version_get(&self, operation_id: OperationId) {
-        if let Ok(vm) =
-            tokio::time::timeout(Duration::from_millis(250), self.version_manager.read()).await
-        {
-            match vm.versions.values().next() {
+    /// Installs the version, replying with download progress every 10% so the operation doesn't
+    /// look hung during large downloads
+    async fn install_reporting_progress(
+        &self,
+        vm: &mut VersionManager,
+        version: String,
+        operation_id: &OperationId,
+    ) -> crate::error::Result<()> {
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+        let report_progress = async {
+            let mut last_reported = 0;
+            while let Some(progress) = progress_rx.recv().await {
+                let total_bytes = match progress.total_bytes {
+                    Some(total_bytes) if total_bytes > 0 => total_bytes,
+                    _ => continue,
+                };
+                let percent = progress.downloaded_bytes * 100 / total_bytes;
+                if percent >= last_reported + 10 {
+                    last_reported = percent - percent % 10;
+                    self.reply(
+                        AgentOutMessage::Message(format!(
+                            "Downloading version {}: {}% ({} / {} MiB)",
+                            version,
+                            last_reported,
+                            progress.downloaded_bytes / (1024 * 1024),
+                            total_bytes / (1024 * 1024)
+                        )),
+                        operation_id,
+                    )
+                    .await;
+                }
+            }
+        };
+        // the progress channel closes once the install finishes with the sender
+        let (result, _) = tokio::join!(
+            vm.install(version.clone(), Some(progress_tx)),
+            report_progress
+        );
+        result
+    }
+
+    /// Installs a version without touching other installed versions or the running server
+    async fn version_install_side_by_side(
+        &self,
+        vm: &mut VersionManager,
+        version_to_install: String,
+        force_install: bool,
+        operation_id: OperationId,
+    ) {
+        if vm.versions.contains_key(&version_to_install) {
+            if force_install {
+                // the running server may be using this installation
+                self.reply_failed(
+                    AgentOutMessage::Error(format!(
+                        "Version {} is already installed, reinstall without keeping existing versions instead",
+                        version_to_install
+                    )),
+                    operation_id,
+                )
+                .await;
+            } else {
+                self.reply_success(AgentOutMessage::Ok, operation_id).await;
+            }
+            return;
+        }
+
+        info!("Installing version {} alongside existing versions", version_to_install);
+        self.reply(
+            AgentOutMessage::Message(format!(
+                "Starting to install version {} alongside existing versions",
+                version_to_install
+            )),
+            &operation_id,
+        )
+        .await;
+        if let Err(e) = self
+            .install_reporting_progress(vm, version_to_install.clone(), &operation_id)
+            .await
+        {
+            self.reply_failed(
+                AgentOutMessage::Error(format!("Failed to install: {:?}", e)),
+                operation_id,
+            )
+            .await;
+        } else {
+            info!("Installed version {} alongside existing versions", version_to_install);
+            self.reply_success(AgentOutMessage::Ok, operation_id).await;
+        }
+    }
+
+    async fn version_install_from_archive(&self, path: String, operation_id: OperationId) {
+        let archive_path = std::path::PathBuf::from(path);
+        if !archive_path.is_file() {
+            self.reply_failed(
+                AgentOutMessage::Error(format!(
+                    "Archive {} does not exist on the agent host",
+                    archive_path.display()
+                )),
+                operation_id,
+            )
+            .await;
+            return;
+        }
+
+        if let Ok(mut vm) =
+            tokio::time::timeout(Duration::from_millis(250), self.version_manager.write()).await
+        {
+            if !self
+                .preflight_disk_space(
+                    &FACTORIO_INSTALL_DIR,
+                    util::disk::VERSION_INSTALL_REQUIRED_BYTES,
+                    &operation_id,
+                )
+                .await
+            {
+                return;
+            }
+            self.long_running_ack(&operation_id).await;
+            self.reply(
+                AgentOutMessage::Message(format!(
+                    "Starting to install from archive {}",
+                    archive_path.display()
+                )),
+                &operation_id,
+            )
+            .await;
+            match vm.install_from_archive(&archive_path).await {
+                Ok(version) => {
+                    self.reply_success(
+                        AgentOutMessage::FactorioVersion(FactorioVersion(version)),
+                        operation_id,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    self.reply_failed(
+                        AgentOutMessage::Error(format!("Failed to install from archive: {:?}", e)),
+                        operation_id,
+                    )
+                    .await;
+                }
+            }
+        } else {
+            self.reply_failed(AgentOutMessage::ConflictingOperation, operation_id)
+                .await;
+        }
+    }
+
+    async fn version_get(&self, operation_id: OperationId) {
+        if let Ok(vm) =
+            tokio::time::timeout(Duration::from_millis(250), self.version_manager.read()).await
+        {
+            match vm.default_version() {
                 None => {
                     self.reply_success(AgentOutMessage::NotInstalled, operation_id)
                         .await;
@@ -758,24 +1331,65 @@ impl AgentController {
         }
     }
 
-    async fn server_start(&self, savefile: ServerStartSaveFile, operation_id: OperationId) {
-        // assume there is at most one version installed
+    async fn version_list(&self, operation_id: OperationId) {
+        if let Ok(vm) =
+            tokio::time::timeout(Duration::from_millis(250), self.version_manager.read()).await
+        {
+            let mut versions: Vec<FactorioVersion> = vm
+                .versions
+                .keys()
+                .map(|v| FactorioVersion(v.clone()))
+                .collect();
+            versions.sort();
+            self.reply_success(AgentOutMessage::FactorioVersionList(versions), operation_id)
+                .await;
+        } else {
+            self.reply_failed(AgentOutMessage::ConflictingOperation, operation_id)
+                .await;
+        }
+    }
+
+    async fn server_start(
+        &self,
+        savefile: ServerStartSaveFile,
+        requested_version: Option<FactorioVersion>,
+        profile: Option<String>,
+        operation_id: OperationId,
+    ) {
         if let Ok(vm) =
             tokio::time::timeout(Duration::from_millis(250), self.version_manager.read()).await
         {
             let version;
-            match vm.versions.values().next() {
-                None => {
-                    self.reply_failed(AgentOutMessage::NotInstalled, operation_id)
+            match requested_version {
+                None => match vm.default_version() {
+                    None => {
+                        self.reply_failed(AgentOutMessage::NotInstalled, operation_id)
+                            .await;
+                        return;
+                    }
+                    Some(v) => {
+                        version = v;
+                    }
+                },
+                Some(requested_version) => match vm.versions.get(&requested_version.0) {
+                    None => {
+                        self.reply_failed(
+                            AgentOutMessage::Error(format!(
+                                "Version {} is not installed",
+                                requested_version.0
+                            )),
+                            operation_id,
+                        )
                         .await;
-                    return;
-                }
-                Some(v) => {
-                    version = v;
-                }
+                        return;
+                    }
+                    Some(v) => {
+                        version = v;
+                    }
+                },
             }
 
-            self.internal_server_start_with_version(version, savefile, operation_id, None)
+            self.internal_server_start_with_version(version, savefile, profile, operation_id, None)
                 .await;
         } else {
             self.reply_failed(AgentOutMessage::ConflictingOperation, operation_id)
@@ -787,12 +1401,40 @@ impl AgentController {
         &self,
         version: &Factorio,
         savefile: ServerStartSaveFile,
+        profile: Option<String>,
         operation_id: OperationId,
         opt_restart_instance: Option<StoppedInstance>,
     ) {
         // Verify savefile exists
         if let ServerStartSaveFile::Specific(name) = &savefile {
+            if let Err(e) = util::saves::apply_staged_savefile(name).await {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!(
+                        "Failed to swap in staged replacement for savefile {}: {:?}",
+                        name, e
+                    )),
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+
             let save_path = util::saves::get_savefile_path(name);
+            if !save_path.is_file() {
+                if let Some(remote_saves) = &self.remote_saves {
+                    if let Err(e) = remote_saves.pull(name).await {
+                        self.reply_failed(
+                            AgentOutMessage::Error(format!(
+                                "Failed to pull savefile {} from remote storage: {:?}",
+                                name, e
+                            )),
+                            operation_id,
+                        )
+                        .await;
+                        return;
+                    }
+                }
+            }
             if !save_path.is_file() {
                 self.reply_failed(
                     AgentOutMessage::Error(format!("Savefile with name {} does not exist", name)),
@@ -815,7 +1457,7 @@ impl AgentController {
         }
 
         // Mods
-        let mods;
+        let mut mods;
         match ModManager::read_or_apply_default().await {
             Ok(m) => mods = m,
             Err(_e) => {
@@ -830,7 +1472,7 @@ impl AgentController {
 
         // Launch settings is required to start
         // Pre-populate with default if not exist
-        let launch_settings;
+        let mut launch_settings;
         match LaunchSettings::read_or_apply_default().await {
             Ok(ls) => launch_settings = ls,
             Err(_e) => {
@@ -862,57 +1504,70 @@ impl AgentController {
             }
         }
 
-        // If game is public visibility, we need factorio.com credentials
-        if server_settings.config.visibility.public {
-            match Secrets::read().await {
-                Ok(Some(secrets)) => {
-                    if secrets.username.is_empty() || secrets.token.is_empty() {
-                        self.reply_failed(
-                            AgentOutMessage::Error(
-                                "Missing credentials required for server visible to public".to_owned(),
-                            ),
-                            operation_id,
-                        )
-                        .await;
-                        return;
-                    }
-
-                    // Write them into the config file, since there's no other way to pass them in
-                    server_settings.config.username = Some(secrets.username);
-                    server_settings.config.token = Some(secrets.token);
-                    if let Err(_) = ServerSettings::write(&server_settings).await {
-                        self.reply_failed(
-                            AgentOutMessage::Error(
-                                "Failed to write to server settings file".to_owned()
-                            ),
-                            operation_id,
-                        ).await;
-                        return;
-                    }
-                },
+        // A launch profile replaces the server settings, mods and whitelist flag
+        if let Some(name) = &profile {
+            match Profile::read(name).await {
+                Ok(Some(p)) => server_settings = p.apply(&mut mods, &mut launch_settings),
                 Ok(None) => {
-                    self.reply_failed(
-                        AgentOutMessage::Error(
-                            "Missing credentials required for server visible to public".to_owned(),
-                        ),
-                        operation_id,
-                    )
-                    .await;
+                    self.reply_failed(AgentOutMessage::ProfileNotFound, operation_id)
+                        .await;
                     return;
-                },
-                Err(_) => {
+                }
+                Err(e) => {
                     self.reply_failed(
-                        AgentOutMessage::Error(
-                            "Failed to read secrets".to_owned(),
-                        ),
+                        AgentOutMessage::Error(format!(
+                            "Failed to read launch profile {}: {:?}",
+                            name, e
+                        )),
                         operation_id,
                     )
                     .await;
                     return;
-                },
+                }
             }
         }
 
+        // factorio.com credentials, required if game is public visibility
+        let secrets = match Secrets::read().await {
+            Ok(secrets) => secrets.filter(|s| !s.username.is_empty() && !s.token.is_empty()),
+            Err(e) if !server_settings.config.visibility.public => {
+                warn!("Failed to read secrets, starting without credentials: {:?}", e);
+                None
+            }
+            Err(_) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(
+                        "Failed to read secrets".to_owned(),
+                    ),
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+        };
+        if server_settings.config.visibility.public && secrets.is_none() {
+            self.reply_failed(
+                AgentOutMessage::Error(
+                    "Missing credentials required for server visible to public".to_owned(),
+                ),
+                operation_id,
+            )
+            .await;
+            return;
+        }
+
+        // Pass them in through a generated copy of the server settings, since there's no other
+        // way to pass them in, keeping them out of the config file itself
+        server_settings = server_settings.with_secrets(secrets);
+        if server_settings.write().await.is_err() {
+            self.reply_failed(
+                AgentOutMessage::Error("Failed to write to server settings file".to_owned()),
+                operation_id,
+            )
+            .await;
+            return;
+        }
+
         // Admin list
         let admin_list;
         match AdminList::read_or_apply_default().await {
@@ -959,8 +1614,41 @@ impl AgentController {
             }
         }
 
+        let settings = ServerStartSettings {
+            mods,
+            launch_settings,
+            server_settings,
+            admin_list,
+            ban_list,
+            white_list,
+        };
+        let mut builder = self.server_host_builder(version, savefile, profile, settings);
+
+        if let Some(previous_instance) = opt_restart_instance {
+            builder.replay_optional_args(previous_instance);
+        }
+
+        if let Err(e) = self.proc_manager.start_instance(builder).await {
+            self.reply_failed(
+                AgentOutMessage::Error(format!("Failed to start: {:?}", e)),
+                operation_id,
+            )
+            .await;
+        } else {
+            self.reply_success(AgentOutMessage::Ok, operation_id).await;
+        }
+    }
+
+    /// Builds the server to start, shared with the dry run so that it plans the exact same command
+    fn server_host_builder(
+        &self,
+        version: &Factorio,
+        savefile: ServerStartSaveFile,
+        profile: Option<String>,
+        settings: ServerStartSettings,
+    ) -> ServerHostBuilder {
         let stream_out = Arc::clone(&self.global_tx);
-        let mut builder = ServerBuilder::using_installation(version)
+        ServerBuilder::using_installation(version)
             .with_stdout_handler(move |s| {
                 let msg = AgentStreamingMessage {
                     timestamp: Utc::now(),
@@ -972,26 +1660,148 @@ impl AgentController {
             })
             .hosting_savefile(
                 savefile,
-                mods,
-                admin_list,
-                ban_list,
-                white_list,
-                launch_settings,
-                server_settings,
-            );
+                settings.mods,
+                settings.admin_list,
+                settings.ban_list,
+                settings.white_list,
+                settings.launch_settings,
+                settings.server_settings,
+            )
+            .with_profile(profile)
+    }
 
-        if let Some(previous_instance) = opt_restart_instance {
-            builder.replay_optional_args(previous_instance);
+    async fn server_start_plan(
+        &self,
+        savefile: ServerStartSaveFile,
+        requested_version: Option<FactorioVersion>,
+        profile: Option<String>,
+        operation_id: OperationId,
+    ) {
+        let vm = match tokio::time::timeout(Duration::from_millis(250), self.version_manager.read()).await {
+            Ok(vm) => vm,
+            Err(_) => {
+                self.reply_failed(AgentOutMessage::ConflictingOperation, operation_id)
+                    .await;
+                return;
+            }
+        };
+        let version = match &requested_version {
+            None => vm.default_version(),
+            Some(requested_version) => vm.versions.get(&requested_version.0),
+        };
+        let plan = match version {
+            Some(version) => self.plan_server_start(version, savefile, profile).await,
+            None => ServerStartPlan {
+                command_line: vec![],
+                settings_files: vec![],
+                issues: vec![match requested_version {
+                    Some(v) => format!("Version {} is not installed", v.0),
+                    None => "Factorio is not installed".to_owned(),
+                }],
+            },
+        };
+        self.reply_success(AgentOutMessage::ServerStartPlan(plan), operation_id)
+            .await;
+    }
+
+    /// Dry run of `internal_server_start_with_version`, collecting every issue found rather than
+    /// failing on the first. Nothing is written, so any settings files missing are planned with
+    /// the defaults that would be written on start.
+    async fn plan_server_start(
+        &self,
+        version: &Factorio,
+        savefile: ServerStartSaveFile,
+        profile: Option<String>,
+    ) -> ServerStartPlan {
+        let mut issues = vec![];
+        if !matches!(self.proc_manager.status().await, server::proc::ProcessStatus::NotRunning) {
+            issues.push("Server is already running".to_owned());
+        }
+        match &savefile {
+            ServerStartSaveFile::Specific(name) => {
+                if util::saves::get_staged_savefile_path(name).is_file() {
+                    issues.push(format!(
+                        "Savefile {} has a staged replacement, which would be swapped in",
+                        name
+                    ));
+                } else if !util::saves::get_savefile_path(name).is_file() {
+                    match &self.remote_saves {
+                        Some(_) => issues.push(format!(
+                            "Savefile {} is not on the agent, and would be pulled from remote storage",
+                            name
+                        )),
+                        None => issues.push(format!("Savefile with name {} does not exist", name)),
+                    }
+                }
+            }
+            ServerStartSaveFile::Latest => {
+                issues.push("Latest save functionality not implemented".to_owned());
+            }
         }
 
-        if let Err(e) = self.proc_manager.start_instance(builder).await {
-            self.reply_failed(
-                AgentOutMessage::Error(format!("Failed to start: {:?}", e)),
-                operation_id,
-            )
-            .await;
-        } else {
-            self.reply_success(AgentOutMessage::Ok, operation_id).await;
+        let settings = async {
+            Ok::<_, crate::error::Error>(ServerStartSettings {
+                mods: ModManager::read_or_default().await?,
+                launch_settings: LaunchSettings::read_or_default().await?,
+                server_settings: ServerSettings::read_or_default(version).await?,
+                admin_list: AdminList::read_or_default().await?,
+                ban_list: BanList::read_or_default().await?,
+                white_list: WhiteList::read_or_default().await?,
+            })
+        };
+        let mut settings = match settings.await {
+            Ok(settings) => settings,
+            Err(e) => {
+                issues.push(format!("Failed to read settings files: {:?}", e));
+                return ServerStartPlan {
+                    command_line: vec![],
+                    settings_files: vec![],
+                    issues,
+                };
+            }
+        };
+
+        if let Some(name) = &profile {
+            match Profile::read(name).await {
+                Ok(Some(p)) => {
+                    settings.server_settings =
+                        p.apply(&mut settings.mods, &mut settings.launch_settings)
+                }
+                Ok(None) => issues.push(format!("Launch profile {} does not exist", name)),
+                Err(e) => issues.push(format!("Failed to read launch profile {}: {:?}", name, e)),
+            }
+        }
+
+        let secrets = match Secrets::read().await {
+            Ok(secrets) => secrets.filter(|s| !s.username.is_empty() && !s.token.is_empty()),
+            Err(e) => {
+                issues.push(format!("Failed to read secrets: {:?}", e));
+                None
+            }
+        };
+        if settings.server_settings.config.visibility.public && secrets.is_none() {
+            issues.push("Missing credentials required for server visible to public".to_owned());
+        }
+        settings.server_settings = settings.server_settings.with_secrets(secrets);
+        if let Err(e) = settings.launch_settings.cpu_set() {
+            issues.push(format!("Invalid CPU affinity in launch settings would be ignored: {:?}", e));
+        }
+
+        let settings_files = [
+            &settings.server_settings.path,
+            &settings.admin_list.path,
+            &settings.ban_list.path,
+            &settings.white_list.path,
+            &settings.mods.path,
+        ]
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect();
+        let builder = self.server_host_builder(version, savefile, profile, settings);
+        ServerStartPlan {
+            command_line: builder.command_line(),
+            settings_files,
+            issues,
         }
     }
 
@@ -1000,18 +1810,69 @@ impl AgentController {
         self.reply_success(AgentOutMessage::Ok, operation_id).await;
     }
 
+    async fn server_stop_scheduled(
+        &self,
+        delay_secs: u32,
+        message: Option<String>,
+        operation_id: OperationId,
+    ) {
+        if self.proc_manager.uptime_status().await.is_none() {
+            self.reply_failed(
+                AgentOutMessage::Error("Server is not running".to_owned()),
+                operation_id,
+            )
+            .await;
+            return;
+        }
+
+        self.long_running_ack(&operation_id).await;
+        let stop_at = Utc::now() + chrono::Duration::seconds(delay_secs as i64);
+        info!("Stopping server at {}", stop_at);
+        self.reply(
+            AgentOutMessage::Message(format!("Stopping server at {}", stop_at)),
+            &operation_id,
+        )
+        .await;
+
+        let warning = |time_left: &str| match &message {
+            Some(message) => GameMessage::ServerStopSoonWithMessage { time_left, message }.render(),
+            None => GameMessage::ServerStopSoon { time_left }.render(),
+        };
+        if !countdown::count_down(&self.proc_manager, stop_at, warning).await {
+            self.reply_failed(
+                AgentOutMessage::Error(
+                    "Server was stopped or restarted before the scheduled stop".to_owned(),
+                ),
+                operation_id,
+            )
+            .await;
+            return;
+        }
+
+        countdown::save(&self.proc_manager).await;
+        self.server_stop(operation_id).await;
+    }
+
     async fn server_status(&self, operation_id: OperationId) {
         let status = match self.proc_manager.status().await {
             server::proc::ProcessStatus::NotRunning => ServerStatus::NotRunning,
             server::proc::ProcessStatus::Running {
                 server_state,
                 player_count,
+                ups,
+                map_clock,
+                next_restart,
             } => match server_state {
                 InternalServerState::Ready
                 | InternalServerState::PreparedToHostGame
                 | InternalServerState::CreatingGame => ServerStatus::PreGame,
                 InternalServerState::InGame | InternalServerState::InGameSavingMap => {
-                    ServerStatus::InGame { player_count }
+                    ServerStatus::InGame {
+                        player_count,
+                        ups,
+                        map_clock,
+                        next_restart,
+                    }
                 }
                 InternalServerState::DisconnectingScheduled
                 | InternalServerState::Disconnecting
@@ -1052,13 +1913,23 @@ impl AgentController {
             },
         }
 
-        // assume there is at most one version installed
+        if !self
+            .preflight_disk_space(
+                &SAVEFILE_DIR,
+                util::disk::SAVE_CREATE_REQUIRED_BYTES,
+                &operation_id,
+            )
+            .await
+        {
+            return;
+        }
+
         if let Ok(version_mg) =
             tokio::time::timeout(Duration::from_millis(250), self.version_manager.read()).await
         {
             self.long_running_ack(&operation_id).await;
             let version;
-            match version_mg.versions.values().next() {
+            match version_mg.default_version() {
                 None => {
                     self.reply_failed(AgentOutMessage::NotInstalled, operation_id)
                         .await;
@@ -1097,7 +1968,7 @@ impl AgentController {
                         error!("Failed to send streaming message: {:?}", e);
                     }
                 })
-                .creating_savefile(&save_name, map_gen_settings, map_settings)
+                .creating_savefile(&save_name, map_gen_settings.clone(), map_settings)
                 .await;
             match builder {
                 Err(e) => {
@@ -1126,6 +1997,17 @@ impl AgentController {
                     Ok(si) => {
                         if si.exit_status.success() {
                             info!("Successfully created savefile with name: {}", save_name);
+                            if let Some(map_gen_settings) = &map_gen_settings {
+                                if let Err(e) =
+                                    util::saves::set_map_gen_settings(&save_name, map_gen_settings)
+                                        .await
+                                {
+                                    warn!(
+                                        "Failed to store map-gen-settings of savefile {}: {:?}",
+                                        save_name, e
+                                    );
+                                }
+                            }
                             self.reply_success(AgentOutMessage::Ok, operation_id).await;
                         } else {
                             self.reply_failed(
@@ -1147,6 +2029,12 @@ impl AgentController {
     }
 
     async fn save_delete(&self, save_name: String, operation_id: OperationId) {
+        if self.proc_manager.hosted_savefile().await.as_ref() == Some(&save_name) {
+            self.reply_failed(AgentOutMessage::SaveInUse, operation_id)
+                .await;
+            return;
+        }
+
         match util::saves::exists_savefile(&save_name).await {
             Ok(true) => {
                 if let Err(e) = util::saves::delete_savefile(&save_name).await {
@@ -1159,19 +2047,125 @@ impl AgentController {
                     self.reply_success(AgentOutMessage::Ok, operation_id).await;
                 }
             }
-            Ok(false) => {
+            Ok(false) => {
+                self.reply_failed(AgentOutMessage::SaveNotFound, operation_id)
+                    .await
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to list saves: {:?}", e)),
+                    operation_id,
+                )
+                .await
+            }
+        }
+    }
+
+    async fn save_rename(&self, save_name: String, new_name: String, operation_id: OperationId) {
+        if self.proc_manager.hosted_savefile().await.as_ref() == Some(&save_name) {
+            self.reply_failed(AgentOutMessage::SaveInUse, operation_id)
+                .await;
+            return;
+        }
+        if let Err(m) = self.check_save_copy_target(&save_name, &new_name).await {
+            self.reply_failed(m, operation_id).await;
+            return;
+        }
+
+        if let Err(e) = util::saves::rename_savefile(&save_name, &new_name).await {
+            self.reply_failed(
+                AgentOutMessage::Error(format!("Failed to rename save: {:?}", e)),
+                operation_id,
+            )
+            .await;
+        } else {
+            self.reply_success(AgentOutMessage::Ok, operation_id).await;
+        }
+    }
+
+    async fn save_copy(&self, save_name: String, new_name: String, operation_id: OperationId) {
+        if let Err(m) = self.check_save_copy_target(&save_name, &new_name).await {
+            self.reply_failed(m, operation_id).await;
+            return;
+        }
+
+        if let Err(e) = util::saves::copy_savefile(&save_name, &new_name).await {
+            self.reply_failed(
+                AgentOutMessage::Error(format!("Failed to copy save: {:?}", e)),
+                operation_id,
+            )
+            .await;
+        } else {
+            self.reply_success(AgentOutMessage::Ok, operation_id).await;
+        }
+    }
+
+    async fn backup_create(&self, save_name: String, operation_id: OperationId) {
+        let backups = match &self.backups {
+            Some(backups) => Arc::clone(backups),
+            None => {
+                self.reply_failed(
+                    AgentOutMessage::Error("Backup storage is not configured".to_owned()),
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+        };
+        match util::saves::exists_savefile(&save_name).await {
+            Ok(true) => (),
+            Ok(false) => {
+                self.reply_failed(AgentOutMessage::SaveNotFound, operation_id)
+                    .await;
+                return;
+            }
+            Err(e) => {
                 self.reply_failed(
-                    AgentOutMessage::Error(format!(
-                        "Savefile with name {} does not exist",
-                        save_name
-                    )),
+                    AgentOutMessage::Error(format!("Failed to list saves: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+        }
+
+        self.long_running_ack(&operation_id).await;
+        match backups.backup(&save_name, &save_name).await {
+            Ok(backup) => {
+                self.reply_success(
+                    AgentOutMessage::Message(format!("Backed up save to {}", backup.key)),
                     operation_id,
                 )
                 .await
             }
             Err(e) => {
                 self.reply_failed(
-                    AgentOutMessage::Error(format!("Failed to list saves: {:?}", e)),
+                    AgentOutMessage::Error(format!("Failed to back up save: {:?}", e)),
+                    operation_id,
+                )
+                .await
+            }
+        }
+    }
+
+    async fn backup_status_get(&self, operation_id: OperationId) {
+        let status = match &self.backups {
+            Some(backups) => backups.status().await,
+            None => Ok(BackupStatus {
+                enabled: false,
+                last_success: None,
+                last_failure: None,
+                backups: vec![],
+            }),
+        };
+        match status {
+            Ok(status) => {
+                self.reply_success(AgentOutMessage::BackupStatus(status), operation_id)
+                    .await
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to list backups: {:?}", e)),
                     operation_id,
                 )
                 .await
@@ -1179,26 +2173,97 @@ impl AgentController {
         }
     }
 
-    async fn save_get(&self, save_name: String, operation_id: OperationId) {
+    /// Checks that the savefile exists, and that the new name is valid and not already in use
+    async fn check_save_copy_target(
+        &self,
+        save_name: &str,
+        new_name: &str,
+    ) -> std::result::Result<(), AgentOutMessage> {
+        if !util::saves::is_valid_savefile_name(new_name) {
+            return Err(AgentOutMessage::Error(format!(
+                "Invalid savefile name {}",
+                new_name
+            )));
+        }
+        let saves = util::saves::list_savefiles()
+            .await
+            .map_err(|e| AgentOutMessage::Error(format!("Failed to list saves: {:?}", e)))?;
+        if !saves.iter().any(|s| s.name == save_name) {
+            return Err(AgentOutMessage::SaveNotFound);
+        }
+        if saves.iter().any(|s| s.name == new_name) {
+            return Err(AgentOutMessage::SaveAlreadyExists);
+        }
+        Ok(())
+    }
+
+    async fn save_get(
+        &self,
+        save_name: String,
+        ack_interval: Option<usize>,
+        operation_id: OperationId,
+    ) {
         match util::saves::get_savefile(&save_name).await {
             Ok(Some(savebytes)) => {
                 self.long_running_ack(&operation_id).await;
-                let chunks = savebytes.bytes.chunks(MAX_WS_PAYLOAD_BYTES);
+                // allow up to two batches in flight, so the next batch is already on its way
+                // while the peer is processing the previous one
+                let credits = match ack_interval {
+                    Some(n) if n > 0 => {
+                        let semaphore = Arc::new(Semaphore::new(n * 2));
+                        self.save_get_credits
+                            .lock()
+                            .await
+                            .insert(operation_id.0.clone(), (Arc::clone(&semaphore), n));
+                        Some(semaphore)
+                    }
+                    _ => None,
+                };
+
+                let chunks = savebytes.bytes.chunks(self.save_chunk_bytes);
                 let mut i = 0;
+                let mut acked = true;
                 for chunk in chunks {
+                    if let Some(credits) = &credits {
+                        match tokio::time::timeout(SAVE_GET_ACK_TIMEOUT, credits.acquire()).await {
+                            Ok(Ok(permit)) => permit.forget(),
+                            _ => {
+                                acked = false;
+                                break;
+                            }
+                        }
+                    }
                     let chunk_len = chunk.len();
                     let msg = AgentOutMessage::SaveFile(SaveBytes {
                         multipart_start: Some(i),
                         bytes: chunk.to_vec(),
+                        sha256: None,
                     });
                     self.reply(msg, &operation_id).await;
                     i += chunk_len;
                 }
-                self.reply_success(
-                    AgentOutMessage::SaveFile(SaveBytes::sentinel(i)),
-                    operation_id,
-                )
-                .await;
+                self.save_get_credits.lock().await.remove(&operation_id.0);
+
+                if acked {
+                    self.reply_success(
+                        AgentOutMessage::SaveFile(SaveBytes::sentinel(i)),
+                        operation_id,
+                    )
+                    .await;
+                } else {
+                    warn!(
+                        "Save transfer {} not acknowledged within {:?}, abandoning",
+                        operation_id.0, SAVE_GET_ACK_TIMEOUT
+                    );
+                    self.reply_failed(
+                        AgentOutMessage::Error(format!(
+                            "Save transfer not acknowledged within {:?}",
+                            SAVE_GET_ACK_TIMEOUT
+                        )),
+                        operation_id,
+                    )
+                    .await;
+                }
             }
             Ok(None) => {
                 self.reply_failed(AgentOutMessage::SaveNotFound, operation_id)
@@ -1214,8 +2279,20 @@ impl AgentController {
         }
     }
 
+    async fn save_get_ack(&self, transfer_id: OperationId) {
+        match self.save_get_credits.lock().await.get(&transfer_id.0) {
+            Some((credits, ack_interval)) => credits.add_permits(*ack_interval),
+            // transfer may have already completed
+            None => debug!("Got ack for unknown save transfer {}", transfer_id.0),
+        }
+    }
+
     async fn save_list(&self, operation_id: OperationId) {
-        match util::saves::list_savefiles().await {
+        let saves = match (util::saves::list_savefiles().await, &self.remote_saves) {
+            (Ok(local), Some(remote_saves)) => remote_saves.list_merged(local).await,
+            (result, _) => result,
+        };
+        match saves {
             Ok(saves) => {
                 self.reply_success(AgentOutMessage::SaveList(saves), operation_id)
                     .await;
@@ -1230,8 +2307,205 @@ impl AgentController {
         }
     }
 
+    async fn map_preview_generate(&self, operation_id: OperationId) {
+        let _generating = match MAP_PREVIEW_GENERATING.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => {
+                self.reply_failed(AgentOutMessage::ConflictingOperation, operation_id)
+                    .await;
+                return;
+            }
+        };
+        let save_name = match self.proc_manager.hosted_savefile().await {
+            Some(save_name) => save_name,
+            None => {
+                self.reply_failed(
+                    AgentOutMessage::Error("No savefile is being hosted to preview".to_owned()),
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+        };
+        let map_gen_settings = match self.hosted_map_gen_settings(&save_name).await {
+            Ok(map_gen_settings) => map_gen_settings,
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!(
+                        "Couldn't read map-gen-settings of savefile {}: {:?}",
+                        save_name, e
+                    )),
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+        };
+
+        let version_mg =
+            match tokio::time::timeout(Duration::from_millis(250), self.version_manager.read())
+                .await
+            {
+                Ok(version_mg) => version_mg,
+                Err(_) => {
+                    self.reply_failed(AgentOutMessage::ConflictingOperation, operation_id)
+                        .await;
+                    return;
+                }
+            };
+        let version = match version_mg.default_version() {
+            Some(v) => v,
+            None => {
+                self.reply_failed(AgentOutMessage::NotInstalled, operation_id)
+                    .await;
+                return;
+            }
+        };
+
+        self.long_running_ack(&operation_id).await;
+        let builder = match ServerBuilder::using_installation(version)
+            .generating_map_preview(map_gen_settings, MAP_PREVIEW_SIZE)
+            .await
+        {
+            Ok(builder) => builder,
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to prepare map preview: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+        };
+        drop(version_mg);
+
+        // doesn't go through the process manager, as this runs alongside the hosted server
+        match builder.build().start_and_wait().await {
+            Ok(si) if si.exit_status.success() => {
+                info!("Generated map preview at {}", MAP_PREVIEW_PATH.display());
+                self.reply_success(AgentOutMessage::Ok, operation_id).await;
+            }
+            Ok(si) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!(
+                        "Map preview generation failed: process exited with non-success code {}",
+                        si.exit_status
+                    )),
+                    operation_id,
+                )
+                .await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Map preview generation failed: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    /// The map-gen-settings of the hosted savefile, as stored when it was created. Savefiles created
+    /// elsewhere have none stored and are assumed to use the defaults. If no seed was given, the
+    /// map was generated from a random one, so it is read from the running server.
+    async fn hosted_map_gen_settings(
+        &self,
+        save_name: &str,
+    ) -> crate::error::Result<MapGenSettingsJson> {
+        let mut map_gen_settings = match util::saves::read_map_gen_settings(save_name).await? {
+            Some(MapGenSettingsJson(json)) => serde_json::from_str(&json)?,
+            None => serde_json::Map::new(),
+        };
+        if map_gen_settings
+            .get("seed")
+            .map_or(true, serde_json::Value::is_null)
+        {
+            let response = self
+                .proc_manager
+                .send_rcon_command_to_instance(SEED_RCON_COMMAND)
+                .await?;
+            let seed: u32 = response
+                .trim()
+                .parse()
+                .map_err(|_| crate::error::Error::RconUnexpectedResponse(response.clone()))?;
+            map_gen_settings.insert("seed".to_owned(), seed.into());
+        }
+        let json = serde_json::to_string(&map_gen_settings)?;
+        Ok(MapGenSettingsJson(json))
+    }
+
+    async fn map_preview_get(&self, operation_id: OperationId) {
+        let preview = match fs::metadata(&*MAP_PREVIEW_PATH).await {
+            Ok(metadata) => match (fs::read(&*MAP_PREVIEW_PATH).await, metadata.modified()) {
+                (Ok(bytes), Ok(modified)) => Some(MapPreviewBytes {
+                    bytes,
+                    generated_at: modified.into(),
+                }),
+                (Err(e), _) | (_, Err(e)) => {
+                    self.reply_failed(
+                        AgentOutMessage::Error(format!("Failed to read map preview: {:?}", e)),
+                        operation_id,
+                    )
+                    .await;
+                    return;
+                }
+            },
+            Err(_) => None,
+        };
+        self.reply_success(AgentOutMessage::MapPreview(preview), operation_id)
+            .await;
+    }
+
+    async fn save_checksum_get(&self, save_name: String, operation_id: OperationId) {
+        match util::saves::savefile_sha256(&save_name).await {
+            Ok(sha256) => {
+                self.reply_success(AgentOutMessage::SaveChecksum(sha256), operation_id)
+                    .await;
+            }
+            Err(crate::error::Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.reply_failed(AgentOutMessage::SaveNotFound, operation_id)
+                    .await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to compute savefile checksum: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn save_metadata_get(&self, save_name: String, operation_id: OperationId) {
+        match util::saves::read_metadata(&save_name).await {
+            Ok(Some(metadata)) => {
+                self.reply_success(AgentOutMessage::SaveMetadata(metadata), operation_id)
+                    .await;
+            }
+            Ok(None) => {
+                self.reply_failed(AgentOutMessage::SaveNotFound, operation_id)
+                    .await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to read savefile metadata: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
     async fn save_set(&self, save_name: String, savebytes: SaveBytes, operation_id: OperationId) {
-        if let Err(e) = util::saves::set_savefile(&save_name, savebytes).await {
+        // Overwriting the savefile under a running server will corrupt it,
+        // so stage the upload to be swapped in on the next start instead
+        let result = if self.proc_manager.hosted_savefile().await.as_ref() == Some(&save_name) {
+            info!("Savefile `{}` is in use by the running server, staging upload", save_name);
+            util::saves::set_staged_savefile(&save_name, savebytes).await
+        } else {
+            util::saves::set_savefile(&save_name, savebytes).await
+        };
+        if let Err(e) = result {
             self.reply_failed(
                 AgentOutMessage::Error(format!(
                     "Failed to set savefile with name `{}`: {:?}",
@@ -1261,6 +2535,41 @@ impl AgentController {
         }
     }
 
+    async fn mod_dlcs_available_get(&self, operation_id: OperationId) {
+        if let Ok(vm) =
+            tokio::time::timeout(Duration::from_millis(250), self.version_manager.read()).await
+        {
+            match vm.default_version() {
+                None => {
+                    self.reply_success(AgentOutMessage::NotInstalled, operation_id)
+                        .await;
+                }
+                Some(v) => match v.available_dlcs().await {
+                    Ok(available) => {
+                        self.reply_success(
+                            AgentOutMessage::DlcList(available.into_iter().collect()),
+                            operation_id,
+                        )
+                        .await;
+                    }
+                    Err(e) => {
+                        self.reply_failed(
+                            AgentOutMessage::Error(format!(
+                                "Failed to read DLC from installation: {:?}",
+                                e
+                            )),
+                            operation_id,
+                        )
+                        .await;
+                    }
+                },
+            }
+        } else {
+            self.reply_failed(AgentOutMessage::ConflictingOperation, operation_id)
+                .await;
+        }
+    }
+
     async fn mod_dlcs_set(&self, dlcs: HashSet<Dlc>, operation_id: OperationId) {
         // validate that base is included
         if !dlcs.contains(&Dlc::Base) {
@@ -1271,16 +2580,35 @@ impl AgentController {
         if let Ok(vm) =
             tokio::time::timeout(Duration::from_millis(250), self.version_manager.read()).await
         {
-            match vm.versions.values().next() {
+            match vm.default_version() {
                 None => {
                     self.reply_failed(AgentOutMessage::NotInstalled, operation_id)
                         .await;
                 }
                 Some(v) => {
-                    // validate if non-base DLC, then version > 1
-                    if dlcs.len() > 1 && v.version.starts_with("1") {
+                    // validate that every DLC ships with the installed game version
+                    let available = match v.available_dlcs().await {
+                        Ok(available) => available,
+                        Err(e) => {
+                            self.reply_failed(
+                                AgentOutMessage::Error(format!(
+                                    "Failed to read DLC from installation: {:?}",
+                                    e
+                                )),
+                                operation_id,
+                            )
+                            .await;
+                            return;
+                        }
+                    };
+                    let unavailable: Vec<_> = dlcs
+                        .iter()
+                        .filter(|d| !available.contains(d))
+                        .map(|d| d.to_string())
+                        .collect();
+                    if !unavailable.is_empty() {
                         self.reply_failed(
-                            AgentOutMessage::Error(format!("Failed to set DLC: list includes non-base DLC which installed game version {} does not support", v.version))
+                            AgentOutMessage::Error(format!("Failed to set DLC: installed game version {} does not include {}", v.version, unavailable.join(", ")))
                             , operation_id
                         )
                         .await;
@@ -1326,7 +2654,8 @@ impl AgentController {
                     .iter()
                     .map(|m| ModObject {
                         name: m.name.clone(),
-                        version: m.version.clone(),
+                        version: m.version.to_string(),
+                        enabled: m.enabled,
                     })
                     .collect();
                 self.reply_success(AgentOutMessage::ModsList(list), operation_id)
@@ -1356,6 +2685,7 @@ impl AgentController {
                         .map(|shm| ModObject {
                             name: shm.name,
                             version: shm.version.to_string(),
+                            enabled: true,
                         })
                         .collect();
                     self.reply_success(AgentOutMessage::ModsList(ret), operation_id)
@@ -1383,26 +2713,90 @@ impl AgentController {
         }
     }
 
-    async fn mod_list_set(&self, mod_list: Vec<ModObject>, operation_id: OperationId) {
+    async fn mod_list_set(&self, mod_list: Vec<ModObject>, operation_id: OperationId) {
+        match ModManager::read_or_apply_default().await {
+            Ok(mut m) => match Secrets::read().await {
+                Ok(Some(s)) => {
+                    m.mods = mod_list
+                        .into_iter()
+                        .map(|m| Mod {
+                            name: m.name,
+                            version: ModVersion::from(m.version),
+                            enabled: m.enabled,
+                        })
+                        .collect();
+                    self.long_running_ack(&operation_id).await;
+                    self.apply_mods(m, s, operation_id).await;
+                }
+                Ok(None) => {
+                    self.reply_failed(AgentOutMessage::MissingSecrets, operation_id)
+                        .await;
+                }
+                Err(e) => {
+                    self.reply_failed(
+                        AgentOutMessage::Error(format!("Failed to read secrets: {:?}", e)),
+                        operation_id,
+                    )
+                    .await;
+                }
+            },
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to initialise mod manager: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn mod_list_update_all(&self, operation_id: OperationId) {
+        let factorio_version =
+            match tokio::time::timeout(Duration::from_millis(250), self.version_manager.read())
+                .await
+            {
+                Ok(vm) => match vm.default_version() {
+                    Some(v) => FactorioVersion(v.version.clone()),
+                    None => {
+                        self.reply_failed(AgentOutMessage::NotInstalled, operation_id)
+                            .await;
+                        return;
+                    }
+                },
+                Err(_) => {
+                    self.reply_failed(AgentOutMessage::ConflictingOperation, operation_id)
+                        .await;
+                    return;
+                }
+            };
+
         match ModManager::read_or_apply_default().await {
             Ok(mut m) => match Secrets::read().await {
                 Ok(Some(s)) => {
-                    m.mods = mod_list
-                        .into_iter()
-                        .map(|m| Mod {
-                            name: m.name,
-                            version: m.version,
-                        })
-                        .collect();
                     self.long_running_ack(&operation_id).await;
-                    match m.apply(&s).await {
-                        Ok(_) => {
-                            self.reply_success(AgentOutMessage::Ok, operation_id).await;
+                    match m.update_all(&factorio_version).await {
+                        Ok(updates) => {
+                            if updates.is_empty() {
+                                self.reply(
+                                    AgentOutMessage::Message("All mods are up to date".to_owned()),
+                                    &operation_id,
+                                )
+                                .await;
+                            }
+                            for (m, previous_version) in updates {
+                                let message = format!(
+                                    "Updating mod {} from {} to {}",
+                                    m.name, previous_version, m.version
+                                );
+                                self.reply(AgentOutMessage::Message(message), &operation_id)
+                                    .await;
+                            }
+                            self.apply_mods(m, s, operation_id).await;
                         }
                         Err(e) => {
                             self.reply_failed(
                                 AgentOutMessage::Error(format!(
-                                    "Failed to apply mod changes: {:?}",
+                                    "Failed to find mod updates: {:?}",
                                     e
                                 )),
                                 operation_id,
@@ -1433,30 +2827,127 @@ impl AgentController {
         }
     }
 
+    /// Applies the mod manager's mod list as the remainder of a long-running operation,
+    /// reporting the progress of each mod download
+    async fn apply_mods(&self, mut m: ModManager, secrets: Secrets, operation_id: OperationId) {
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+        let report_progress = async {
+            while let Some(progress) = progress_rx.recv().await {
+                let message = match progress {
+                    ModInstallProgress::Started(m) => {
+                        format!("Downloading mod {} {}", m.name, m.version)
+                    }
+                    ModInstallProgress::Completed(m) => {
+                        format!("Installed mod {} {}", m.name, m.version)
+                    }
+                    ModInstallProgress::Failed(m, reason) => {
+                        format!("Failed to install mod {} {}: {}", m.name, m.version, reason)
+                    }
+                };
+                self.reply(AgentOutMessage::Message(message), &operation_id)
+                    .await;
+            }
+        };
+        // the progress channel closes once every download task has finished
+        let (result, _) = tokio::join!(m.apply(&secrets, Some(progress_tx)), report_progress);
+        match result {
+            Ok(_) => {
+                self.reply_success(AgentOutMessage::Ok, operation_id).await;
+            }
+            Err(crate::error::Error::ModVersionsNotFound(not_found)) => {
+                self.reply_failed(
+                    AgentOutMessage::ModVersionsNotFound(not_found),
+                    operation_id,
+                )
+                .await;
+            }
+            Err(crate::error::Error::Aggregate(errors)) => {
+                // report running out of space over the other downloads it caused to fail
+                let insufficient = errors.iter().find_map(|e| match e {
+                    crate::error::Error::InsufficientDiskSpace(insufficient) => {
+                        Some(insufficient.clone())
+                    }
+                    _ => None,
+                });
+                let message = match insufficient {
+                    Some(insufficient) => AgentOutMessage::InsufficientDiskSpace(insufficient),
+                    None => AgentOutMessage::Error(format!(
+                        "Failed to apply mod changes: {:?}",
+                        crate::error::Error::Aggregate(errors)
+                    )),
+                };
+                self.reply_failed(message, operation_id).await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to apply mod changes: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn mod_set_enabled(&self, name: String, enabled: bool, operation_id: OperationId) {
+        let mut m = match ModManager::read_or_apply_default().await {
+            Ok(m) => m,
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to get mods: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+                return;
+            }
+        };
+
+        // every installed version of the mod has an entry in mod-list.json, and they all need the
+        // same flag for the mod to be reliably enabled or disabled
+        let mut found = false;
+        for installed in m.mods.iter_mut().filter(|installed| installed.name == name) {
+            installed.enabled = enabled;
+            found = true;
+        }
+        if !found {
+            self.reply_failed(AgentOutMessage::ModNotFound, operation_id)
+                .await;
+            return;
+        }
+
+        // only the mod list changes, so there's nothing to download or delete
+        if let Err(e) = m.apply_metadata_only().await {
+            self.reply_failed(
+                AgentOutMessage::Error(format!("Failed to write mod list: {:?}", e)),
+                operation_id,
+            )
+            .await;
+        } else {
+            info!(
+                "{} mod {}",
+                if enabled { "Enabled" } else { "Disabled" },
+                name
+            );
+            self.reply_success(AgentOutMessage::Ok, operation_id).await;
+        }
+    }
+
     async fn mod_settings_get(&self, operation_id: OperationId) {
         match ModManager::read_or_apply_default().await {
             Ok(m) => {
                 if let Some(s) = m.settings {
-                    match s.try_into() {
-                        Ok(bytes) => {
-                            self.reply_success(
-                                AgentOutMessage::ModSettings(Some(ModSettingsBytes { bytes })),
-                                operation_id,
-                            )
-                            .await;
-                        }
-                        Err(e) => {
-                            error!("Failed to serialise ModSettings: {:?}", e);
-                            self.reply_failed(
-                                AgentOutMessage::Error(format!(
-                                    "Failed to parse ModSettings: {:?}",
-                                    e
-                                )),
-                                operation_id,
-                            )
-                            .await;
-                        }
-                    }
+                    self.reply_success(
+                        AgentOutMessage::ModSettings(Some(ModSettingsBytes {
+                            bytes: s.to_bytes(),
+                        })),
+                        operation_id,
+                    )
+                    .await;
+                } else if let Some(unreadable) = m.settings_unreadable {
+                    self.reply_failed(
+                        AgentOutMessage::ModSettingsUnreadable(unreadable),
+                        operation_id,
+                    )
+                    .await;
                 } else {
                     self.reply_success(AgentOutMessage::ModSettings(None), operation_id)
                         .await;
@@ -1476,9 +2967,10 @@ impl AgentController {
         match ModManager::read_or_apply_default().await {
             Ok(mut m) => {
                 // Validate by attempting to parse
-                match ModSettings::try_from(ms_bytes.bytes.as_ref()) {
+                match ModSettings::parse(&ms_bytes.bytes) {
                     Ok(ms) => {
                         m.settings = Some(ms);
+                        m.settings_unreadable = None;
                         if let Err(e) = m.apply_metadata_only().await {
                             self.reply_failed(
                                 AgentOutMessage::Error(format!(
@@ -1492,12 +2984,9 @@ impl AgentController {
                             self.reply_success(AgentOutMessage::Ok, operation_id).await;
                         }
                     }
-                    Err(e) => {
+                    Err(unreadable) => {
                         self.reply_failed(
-                            AgentOutMessage::Error(format!(
-                                "Unable to parse mod settings: {:?}",
-                                e
-                            )),
+                            AgentOutMessage::ModSettingsUnreadable(unreadable),
                             operation_id,
                         )
                         .await;
@@ -1534,8 +3023,11 @@ impl AgentController {
     }
 
     async fn config_admin_list_set(&self, list: Vec<String>, operation_id: OperationId) {
+        let old_list = AdminList::read().await.ok().flatten().map_or(vec![], |al| al.list);
+        let delta = PlayerListDelta::between(&old_list, &list);
         match AdminList::set(list).await {
             Ok(_) => {
+                self.sync_player_list_via_rcon(delta, "/promote", "/demote").await;
                 self.reply_success(AgentOutMessage::Ok, operation_id).await;
             }
             Err(e) => {
@@ -1568,8 +3060,11 @@ impl AgentController {
     }
 
     async fn config_ban_list_set(&self, list: Vec<String>, operation_id: OperationId) {
+        let old_list = BanList::read().await.ok().flatten().map_or(vec![], |bl| bl.list);
+        let delta = PlayerListDelta::between(&old_list, &list);
         match BanList::set(list).await {
             Ok(_) => {
+                self.sync_player_list_via_rcon(delta, "/ban", "/unban").await;
                 self.reply_success(AgentOutMessage::Ok, operation_id).await;
             }
             Err(e) => {
@@ -1582,6 +3077,132 @@ impl AgentController {
         }
     }
 
+    async fn config_diagnostics_get(&self, operation_id: OperationId) {
+        match ConfigSnapshot::read().await {
+            Ok(snapshot) => {
+                self.reply_success(AgentOutMessage::ConfigDiagnostics(snapshot.check()), operation_id)
+                    .await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to read configuration: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn config_performance_get(&self, operation_id: OperationId) {
+        match LaunchSettings::read_or_apply_default().await {
+            Ok(ls) => {
+                self.reply_success(
+                    AgentOutMessage::ConfigPerformance(PerformanceConfig {
+                        cpu_affinity: ls.cpu_affinity,
+                    }),
+                    operation_id,
+                )
+                .await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!(
+                        "Failed to read or initialise launch settings file: {:?}",
+                        e
+                    )),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn config_performance_set(&self, config: PerformanceConfig, operation_id: OperationId) {
+        match LaunchSettings::read_or_apply_default().await {
+            Ok(mut ls) => {
+                // an empty mask would leave the server with nowhere to run, treat it as unset
+                ls.cpu_affinity = config.cpu_affinity.filter(|cpus| !cpus.is_empty());
+                if let Err(e) = ls.cpu_set() {
+                    self.reply_failed(
+                        AgentOutMessage::Error(format!("Invalid CPU affinity: {:?}", e)),
+                        operation_id,
+                    )
+                    .await;
+                } else if let Err(e) = ls.write().await {
+                    self.reply_failed(
+                        AgentOutMessage::Error(format!("Failed to set launch settings: {:?}", e)),
+                        operation_id,
+                    )
+                    .await;
+                } else {
+                    self.reply_success(AgentOutMessage::Ok, operation_id).await;
+                }
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!(
+                        "Failed to read or initialise launch settings file: {:?}",
+                        e
+                    )),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn config_profile_list(&self, operation_id: OperationId) {
+        match Profile::list().await {
+            Ok(profiles) => {
+                self.reply_success(AgentOutMessage::ConfigProfileList(profiles), operation_id)
+                    .await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to list launch profiles: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn config_profile_save(&self, name: String, operation_id: OperationId) {
+        match Profile::save_current(&name).await {
+            Ok(()) => self.reply_success(AgentOutMessage::Ok, operation_id).await,
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!(
+                        "Failed to save launch profile {}: {:?}",
+                        name, e
+                    )),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn config_profile_delete(&self, name: String, operation_id: OperationId) {
+        match Profile::delete(&name).await {
+            Ok(true) => self.reply_success(AgentOutMessage::Ok, operation_id).await,
+            Ok(false) => {
+                self.reply_failed(AgentOutMessage::ProfileNotFound, operation_id)
+                    .await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!(
+                        "Failed to delete launch profile {}: {:?}",
+                        name, e
+                    )),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
     async fn config_rcon_get(&self, operation_id: OperationId) {
         match LaunchSettings::read_or_apply_default().await {
             Ok(ls) => {
@@ -1634,6 +3255,46 @@ impl AgentController {
         }
     }
 
+    async fn config_restart_policy_get(&self, operation_id: OperationId) {
+        match LaunchSettings::read_or_apply_default().await {
+            Ok(ls) => {
+                self.reply_success(AgentOutMessage::ConfigRestartPolicy(ls.restart_policy), operation_id)
+                    .await;
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to read launch settings: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn config_restart_policy_set(&self, restart_policy: RestartPolicy, operation_id: OperationId) {
+        match LaunchSettings::read_or_apply_default().await {
+            Ok(mut ls) => {
+                ls.restart_policy = restart_policy;
+                if let Err(e) = ls.write().await {
+                    self.reply_failed(
+                        AgentOutMessage::Error(format!("Failed to set launch settings: {:?}", e)),
+                        operation_id,
+                    )
+                    .await;
+                } else {
+                    self.reply_success(AgentOutMessage::Ok, operation_id).await;
+                }
+            }
+            Err(e) => {
+                self.reply_failed(
+                    AgentOutMessage::Error(format!("Failed to read launch settings: {:?}", e)),
+                    operation_id,
+                )
+                .await;
+            }
+        }
+    }
+
     async fn config_secrets_get(&self, operation_id: OperationId) {
         match Secrets::read().await {
             Ok(Some(s)) => {
@@ -1692,7 +3353,7 @@ impl AgentController {
         // if there's no existing file, we need to ensure there's an installed Factorio version
         // to generate a default from
         let vm = self.version_manager.read().await;
-        if let Some((_, version)) = vm.versions.iter().next() {
+        if let Some(version) = vm.default_version() {
             match ServerSettings::read_or_apply_default(version).await {
                 Ok(mut ss) => {
                     // strip any credentials from the return
@@ -1725,8 +3386,17 @@ impl AgentController {
         config: ServerSettingsConfig,
         operation_id: OperationId,
     ) {
+        let max_players = config.max_players;
         match ServerSettings::set(config).await {
             Ok(_) => {
+                // the running server only reads its settings on start, but the player limit can
+                // be changed in place
+                let cmd = format!("/config set max-players {}", max_players);
+                match self.proc_manager.send_rcon_command_to_instance(&cmd).await {
+                    Ok(_) => info!("Applied `{}` to running server", cmd),
+                    Err(crate::error::Error::ProcessNotRunning) => (),
+                    Err(e) => warn!("Couldn't apply `{}` to running server: {:?}", cmd, e),
+                }
                 self.reply_success(AgentOutMessage::Ok, operation_id).await;
             }
             Err(e) => {
@@ -1782,6 +3452,8 @@ impl AgentController {
         list: Vec<String>,
         operation_id: OperationId,
     ) {
+        let old_list = WhiteList::read().await.ok().flatten().map_or(vec![], |wl| wl.list);
+        let delta = PlayerListDelta::between(&old_list, &list);
         match LaunchSettings::read_or_apply_default().await {
             Ok(mut ls) => {
                 ls.use_whitelist = enabled;
@@ -1794,6 +3466,12 @@ impl AgentController {
                 } else {
                     match WhiteList::set(list).await {
                         Ok(_) => {
+                            self.sync_player_list_via_rcon(
+                                delta,
+                                "/whitelist add",
+                                "/whitelist remove",
+                            )
+                            .await;
                             self.reply_success(AgentOutMessage::Ok, operation_id).await;
                         }
                         Err(e) => {
@@ -1822,6 +3500,28 @@ impl AgentController {
         }
     }
 
+    /// Applies a player list change to the running server, if any, as it only reads the list
+    /// files on start. Failures are logged, as the change still applies from the next start.
+    async fn sync_player_list_via_rcon(
+        &self,
+        delta: PlayerListDelta,
+        add_command: &str,
+        remove_command: &str,
+    ) {
+        let commands = delta
+            .added
+            .iter()
+            .map(|p| format!("{} {}", add_command, p))
+            .chain(delta.removed.iter().map(|p| format!("{} {}", remove_command, p)));
+        for cmd in commands {
+            match self.proc_manager.send_rcon_command_to_instance(&cmd).await {
+                Ok(_) => info!("Applied `{}` to running server", cmd),
+                Err(crate::error::Error::ProcessNotRunning) => return,
+                Err(e) => warn!("Couldn't apply `{}` to running server: {:?}", cmd, e),
+            }
+        }
+    }
+
     async fn rcon_command(&self, cmd: String, operation_id: OperationId) {
         match self.proc_manager.send_rcon_command_to_instance(&cmd).await {
             Ok(s) => {
@@ -1838,4 +3538,42 @@ impl AgentController {
             }
         }
     }
+
+    async fn rcon_whisper(&self, players: Vec<String>, message: String, operation_id: OperationId) {
+        let mut failed = vec![];
+        for player in players {
+            let cmd = format!("/whisper {} {}", player, message);
+            if let Err(e) = self.proc_manager.send_rcon_command_to_instance(&cmd).await {
+                error!("Couldn't send whisper to {} via RCON: {:?}", player, e);
+                failed.push(player);
+            }
+        }
+
+        if failed.is_empty() {
+            self.reply_success(AgentOutMessage::Ok, operation_id).await;
+        } else {
+            self.reply_failed(
+                AgentOutMessage::Error(format!(
+                    "Couldn't send whisper to players: {}",
+                    failed.join(", ")
+                )),
+                operation_id,
+            )
+            .await;
+        }
+    }
+
+    async fn stream_stdout_subscribe(
+        &self,
+        categories: Option<Vec<StdoutCategory>>,
+        operation_id: OperationId,
+    ) {
+        match &categories {
+            Some(c) => info!("Peer {} subscribed to stdout categories {:?}", self.peer_addr, c),
+            None => info!("Peer {} subscribed to all stdout", self.peer_addr),
+        }
+        self.stdout_filter
+            .send_replace(categories.map(|c| c.into_iter().collect()));
+        self.reply_success(AgentOutMessage::Ok, operation_id).await;
+    }
 }
@@ -0,0 +1,263 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use futures::{Sink, SinkExt};
+use log::{error, warn};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+use fctrl::{
+    schema::{AgentOutMessage, AgentResponseWithId, OperationStatus},
+    util::ws_zlib_framing,
+};
+
+const LANE_CAPACITY: usize = 64;
+
+/// Maximum number of messages sent from each lane per scheduling round, highest priority first.
+/// Lower priority lanes still get a share of every round, so they are never starved outright.
+const LANE_WEIGHTS: [usize; 4] = [8, 4, 2, 1];
+
+/// Outgoing message priority, highest first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Operation acks and responses
+    Control = 0,
+    /// Server status and system monitoring responses
+    Status = 1,
+    /// Streaming messages from the global bus, e.g. server stdout
+    Streaming = 2,
+    /// Large transfers, e.g. save chunks
+    Bulk = 3,
+}
+
+impl Priority {
+    /// Priority for a response message, decided by message type.
+    ///
+    /// This alone doesn't keep the messages of an operation in order, see
+    /// [`OutgoingQueue::response_priority`].
+    pub fn of(message: &AgentOutMessage) -> Priority {
+        match message {
+            AgentOutMessage::SaveFile(_)
+            | AgentOutMessage::ModSettings(_)
+            | AgentOutMessage::MapPreview(_) => Priority::Bulk,
+            AgentOutMessage::ServerStatus(_) | AgentOutMessage::SystemResources(_) => {
+                Priority::Status
+            }
+            _ => Priority::Control,
+        }
+    }
+}
+
+/// Handle for queueing messages to be sent to the peer
+#[derive(Clone)]
+pub struct OutgoingQueue {
+    lanes: [mpsc::Sender<Message>; 4],
+    /// Operations which have sent messages in the bulk lane and not yet finished
+    bulk_operations: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Receiving end of the outgoing queue, which writes queued messages to the peer
+pub struct OutgoingLanes {
+    lanes: [mpsc::Receiver<Message>; 4],
+    /// Whether the peer accepted message compression
+    compress: bool,
+}
+
+impl OutgoingQueue {
+    pub fn new() -> (OutgoingQueue, OutgoingLanes) {
+        let (control_tx, control_rx) = mpsc::channel(LANE_CAPACITY);
+        let (status_tx, status_rx) = mpsc::channel(LANE_CAPACITY);
+        let (streaming_tx, streaming_rx) = mpsc::channel(LANE_CAPACITY);
+        let (bulk_tx, bulk_rx) = mpsc::channel(LANE_CAPACITY);
+        (
+            OutgoingQueue {
+                lanes: [control_tx, status_tx, streaming_tx, bulk_tx],
+                bulk_operations: Arc::new(Mutex::new(HashSet::new())),
+            },
+            OutgoingLanes {
+                lanes: [control_rx, status_rx, streaming_rx, bulk_rx],
+                compress: false,
+            },
+        )
+    }
+
+    /// Priority for a response message.
+    ///
+    /// All messages of a single operation must keep their relative order, so once an operation
+    /// has sent a message in the bulk lane, every later message of it goes in that lane too. In
+    /// particular an error partway through a save transfer must not overtake the preceding chunks.
+    pub fn response_priority(&self, with_id: &AgentResponseWithId) -> Priority {
+        let priority = Priority::of(&with_id.content);
+        let id = &with_id.operation_id.0;
+        let mut bulk_operations = self.bulk_operations.lock().unwrap();
+        let in_bulk = priority == Priority::Bulk || bulk_operations.contains(id);
+        match with_id.status {
+            OperationStatus::Completed | OperationStatus::Failed => {
+                bulk_operations.remove(id);
+            }
+            OperationStatus::Ack | OperationStatus::Ongoing if in_bulk => {
+                bulk_operations.insert(id.clone());
+            }
+            OperationStatus::Ack | OperationStatus::Ongoing => (),
+        }
+        if in_bulk {
+            Priority::Bulk
+        } else {
+            priority
+        }
+    }
+
+    /// Queues a message, waiting if the lane is full
+    pub async fn send(&self, priority: Priority, message: Message) {
+        if self.lanes[priority as usize].send(message).await.is_err() {
+            warn!("Outgoing queue closed, dropping {:?} message", priority);
+        }
+    }
+}
+
+impl OutgoingLanes {
+    pub fn with_compression(mut self, compress: bool) -> OutgoingLanes {
+        self.compress = compress;
+        self
+    }
+
+    /// Writes queued messages to the sink until all queue handles are dropped
+    pub async fn run<S: Sink<Message> + Unpin>(mut self, mut sink: S)
+    where
+        <S as Sink<Message>>::Error: std::fmt::Debug,
+    {
+        let compress = self.compress;
+        loop {
+            let mut sent_any = false;
+            for (lane, weight) in self.lanes.iter_mut().zip(LANE_WEIGHTS) {
+                for _ in 0..weight {
+                    match lane.try_recv() {
+                        Ok(message) => {
+                            send(&mut sink, message, compress).await;
+                            sent_any = true;
+                        }
+                        Err(_) => break,
+                    }
+                }
+            }
+
+            if !sent_any {
+                // all lanes empty, wait for the next message on any of them
+                let [control, status, streaming, bulk] = &mut self.lanes;
+                let message = tokio::select! {
+                    biased;
+                    Some(m) = control.recv() => m,
+                    Some(m) = status.recv() => m,
+                    Some(m) = streaming.recv() => m,
+                    Some(m) = bulk.recv() => m,
+                    else => break,
+                };
+                send(&mut sink, message, compress).await;
+            }
+        }
+    }
+}
+
+async fn send<S: Sink<Message> + Unpin>(sink: &mut S, message: Message, compress: bool)
+where
+    <S as Sink<Message>>::Error: std::fmt::Debug,
+{
+    let message = match compress {
+        true => match ws_zlib_framing::compress(message).await {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Error compressing message: {:?}", e);
+                return;
+            }
+        },
+        false => message,
+    };
+    if let Err(e) = sink.send(message).await {
+        error!("Error sending message: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn control_messages_overtake_queued_bulk_messages() {
+        fctrl::util::testing::logger_init();
+
+        let (queue, lanes) = OutgoingQueue::new();
+        for i in 0..10 {
+            queue.send(Priority::Bulk, Message::Text(format!("bulk {}", i).into())).await;
+        }
+        queue.send(Priority::Control, Message::Text("control".into())).await;
+        drop(queue);
+
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        lanes.run(tx).await;
+        let sent: Vec<_> = rx
+            .map(|m| m.into_text().unwrap().to_string())
+            .collect()
+            .await;
+
+        assert_eq!(sent.len(), 11);
+        assert_eq!(sent[0], "control");
+        assert_eq!(sent[1], "bulk 0");
+        assert_eq!(sent[10], "bulk 9");
+    }
+
+    #[test]
+    fn operations_with_bulk_output_stay_in_bulk_lane() {
+        let (queue, _lanes) = OutgoingQueue::new();
+        let response = |id: &str, status, content| AgentResponseWithId {
+            operation_id: fctrl::schema::OperationId(id.to_owned()),
+            status,
+            timestamp: chrono::Utc::now(),
+            content,
+        };
+        let bulk = || AgentOutMessage::ModSettings(None);
+        let error = || AgentOutMessage::Error("failed".to_owned());
+
+        assert_eq!(
+            queue.response_priority(&response("a", OperationStatus::Ongoing, bulk())),
+            Priority::Bulk
+        );
+        assert_eq!(
+            queue.response_priority(&response("b", OperationStatus::Failed, error())),
+            Priority::Control
+        );
+        assert_eq!(
+            queue.response_priority(&response("a", OperationStatus::Failed, error())),
+            Priority::Bulk
+        );
+        assert_eq!(
+            queue.response_priority(&response("a", OperationStatus::Ongoing, error())),
+            Priority::Control
+        );
+    }
+
+    #[tokio::test]
+    async fn low_priority_lanes_are_not_starved() {
+        fctrl::util::testing::logger_init();
+
+        let (queue, lanes) = OutgoingQueue::new();
+        for i in 0..20 {
+            queue.send(Priority::Control, Message::Text(format!("control {}", i).into())).await;
+        }
+        queue.send(Priority::Bulk, Message::Text("bulk".into())).await;
+        drop(queue);
+
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        lanes.run(tx).await;
+        let sent: Vec<_> = rx
+            .map(|m| m.into_text().unwrap().to_string())
+            .collect()
+            .await;
+
+        let bulk_position = sent.iter().position(|m| m == "bulk").unwrap();
+        assert_eq!(bulk_position, LANE_WEIGHTS[0]);
+    }
+}
@@ -0,0 +1,101 @@
+use fctrl::schema::Save;
+use log::info;
+use tokio_util::io::StreamReader;
+
+use crate::{
+    consts::*,
+    error::Result,
+    util::{self, s3::S3Client},
+};
+
+const DEFAULT_PREFIX: &str = "saves/";
+
+/// Savefiles held in an S3-compatible bucket as well as the local save dir.
+///
+/// Remote savefiles are pulled into the local save dir when they are hosted, and the hosted
+/// savefile is pushed back when the server stops. With this, the agent can run on a host that
+/// doesn't keep any state between games.
+pub struct RemoteSaves {
+    client: S3Client,
+    prefix: String,
+}
+
+impl RemoteSaves {
+    /// Configures remote saves from the environment, if an S3 bucket is configured
+    pub fn from_env() -> Option<RemoteSaves> {
+        Some(RemoteSaves {
+            client: S3Client::from_env()?,
+            prefix: std::env::var(ENV_AGENT_S3_SAVES_PREFIX)
+                .unwrap_or_else(|_| DEFAULT_PREFIX.to_owned()),
+        })
+    }
+
+    pub async fn list(&self) -> Result<Vec<Save>> {
+        Ok(self
+            .client
+            .list(&self.prefix)
+            .await?
+            .into_iter()
+            .filter_map(|o| {
+                let name = o.key.strip_prefix(&self.prefix)?.strip_suffix(".zip")?;
+                // ignore anything in nested "directories"
+                if name.is_empty() || name.contains('/') {
+                    return None;
+                }
+                Some(Save {
+                    name: name.to_owned(),
+                    last_modified: o.last_modified,
+                    staged: false,
+                    remote: true,
+                })
+            })
+            .collect())
+    }
+
+    /// Local savefiles, followed by any remote savefiles not held locally
+    pub async fn list_merged(&self, local: Vec<Save>) -> Result<Vec<Save>> {
+        let mut saves = local;
+        for remote in self.list().await? {
+            if !saves.iter().any(|s| s.name == remote.name) {
+                saves.push(remote);
+            }
+        }
+        Ok(saves)
+    }
+
+    /// Downloads the savefile into the local save dir, returning false if it doesn't exist
+    /// remotely
+    pub async fn pull(&self, save_name: &str) -> Result<bool> {
+        let contents = match self.client.get(&self.key(save_name)).await? {
+            Some(contents) => contents,
+            None => return Ok(false),
+        };
+        let len =
+            util::saves::set_savefile_from_reader(save_name, StreamReader::new(contents)).await?;
+        info!(
+            "Pulled savefile `{}` from remote storage, {} bytes",
+            save_name, len
+        );
+        Ok(true)
+    }
+
+    /// Uploads the local savefile to remote storage, replacing any previous copy
+    pub async fn push(&self, save_name: &str) -> Result<()> {
+        let len = self
+            .client
+            .put_file(
+                &self.key(save_name),
+                util::saves::get_savefile_path(save_name),
+            )
+            .await?;
+        info!(
+            "Pushed savefile `{}` to remote storage, {} bytes",
+            save_name, len
+        );
+        Ok(())
+    }
+
+    fn key(&self, save_name: &str) -> String {
+        format!("{}{}.zip", self.prefix, save_name)
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use fctrl::schema::{JobStatus, MissedRunPolicy};
+use futures::future::BoxFuture;
+use lazy_static::lazy_static;
+use log::{error, info};
+use rand::Rng;
+
+use crate::{
+    consts::*,
+    error::Result,
+    server::config_file::{ConfigBackend, ConfigFile, ConfigFormat},
+};
+
+/// How often the scheduler checks for due jobs
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// Time of the last run of each job with a `RunOnce` missed run policy, so that runs missed
+    /// while the agent was down can be caught up after a restart
+    static ref JOB_RUNS_FILE: ConfigFile<HashMap<String, DateTime<Utc>>> = ConfigFile::new(
+        "job runs",
+        ConfigFormat::Json,
+        ConfigBackend::Filesystem(CONFIG_DIR.join("job-runs.json")),
+    );
+}
+
+/// When and how a recurring job runs
+pub struct JobDefinition {
+    pub name: &'static str,
+    pub interval: Duration,
+    /// Each run is delayed by up to this long, chosen at random, to spread out load
+    pub jitter: Duration,
+    pub missed_run: MissedRunPolicy,
+}
+
+type JobFn = Arc<dyn Fn() -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+struct Job {
+    definition: JobDefinition,
+    run: JobFn,
+    running: bool,
+    last_run: Option<DateTime<Utc>>,
+    next_run: DateTime<Utc>,
+    last_error: Option<String>,
+}
+
+/// Runs the agent's recurring jobs from a single timer loop, so that features needing periodic
+/// work register a job instead of spawning their own loop.
+///
+/// A job never overlaps with itself. If a run takes longer than the interval, the next run
+/// starts once it finishes.
+pub struct Scheduler {
+    jobs: Mutex<Vec<Job>>,
+    /// Held while recording a run in the job runs file, so that jobs finishing together don't
+    /// overwrite each other's runs
+    job_runs_lock: tokio::sync::Mutex<()>,
+}
+
+impl Scheduler {
+    pub fn start() -> Arc<Scheduler> {
+        let scheduler = Arc::new(Scheduler {
+            jobs: Mutex::new(vec![]),
+            job_runs_lock: tokio::sync::Mutex::new(()),
+        });
+
+        let scheduler_clone = Arc::clone(&scheduler);
+        tokio::spawn(async move {
+            loop {
+                scheduler_clone.run_due();
+                tokio::time::sleep(TICK_INTERVAL).await;
+            }
+        });
+
+        scheduler
+    }
+
+    pub async fn register(
+        &self,
+        definition: JobDefinition,
+        run: impl Fn() -> BoxFuture<'static, Result<()>> + Send + Sync + 'static,
+    ) {
+        let last_run = match definition.missed_run {
+            MissedRunPolicy::RunOnce => JOB_RUNS_FILE
+                .read()
+                .await
+                .ok()
+                .flatten()
+                .and_then(|runs| runs.get(definition.name).copied()),
+            MissedRunPolicy::Skip => None,
+        };
+        let next_run = first_run(&definition, last_run, Utc::now());
+        info!(
+            "Registered job {}, next run at {}",
+            definition.name, next_run
+        );
+        self.jobs.lock().unwrap().push(Job {
+            definition,
+            run: Arc::new(run),
+            running: false,
+            last_run,
+            next_run,
+            last_error: None,
+        });
+    }
+
+    pub fn list(&self) -> Vec<JobStatus> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|job| JobStatus {
+                name: job.definition.name.to_owned(),
+                interval_secs: job.definition.interval.as_secs(),
+                jitter_secs: job.definition.jitter.as_secs(),
+                missed_run: job.definition.missed_run,
+                last_run: job.last_run,
+                next_run: job.next_run,
+                last_error: job.last_error.clone(),
+            })
+            .collect()
+    }
+
+    fn run_due(self: &Arc<Self>) {
+        let now = Utc::now();
+        let mut jobs = self.jobs.lock().unwrap();
+        for (i, job) in jobs.iter_mut().enumerate() {
+            if job.running || job.next_run > now {
+                continue;
+            }
+            job.running = true;
+            let run = Arc::clone(&job.run);
+            let scheduler = Arc::clone(self);
+            tokio::spawn(async move {
+                let result = run().await;
+                scheduler.finish(i, result).await;
+            });
+        }
+    }
+
+    async fn finish(&self, i: usize, result: Result<()>) {
+        let now = Utc::now();
+        let persist = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = &mut jobs[i];
+            if let Err(e) = &result {
+                error!("Job {} failed: {:?}", job.definition.name, e);
+            }
+            job.running = false;
+            job.last_run = Some(now);
+            job.last_error = result.err().map(|e| format!("{:?}", e));
+            job.next_run = now + with_jitter(job.definition.interval, job.definition.jitter);
+            match job.definition.missed_run {
+                MissedRunPolicy::RunOnce => Some(job.definition.name),
+                MissedRunPolicy::Skip => None,
+            }
+        };
+
+        if let Some(name) = persist {
+            let _guard = self.job_runs_lock.lock().await;
+            let mut runs = JOB_RUNS_FILE
+                .read()
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+            runs.insert(name.to_owned(), now);
+            if let Err(e) = JOB_RUNS_FILE.write(&runs).await {
+                error!("Failed to record run of job {}: {:?}", name, e);
+            }
+        }
+    }
+}
+
+/// Time of the first run of a job after the scheduler starts, given when it last ran
+fn first_run(
+    definition: &JobDefinition,
+    last_run: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let interval = chrono::Duration::from_std(definition.interval).unwrap_or_default();
+    match last_run {
+        Some(last_run) if last_run + interval > now => last_run + interval,
+        // a run was missed while the agent was down
+        Some(_) => match definition.missed_run {
+            MissedRunPolicy::RunOnce => now,
+            MissedRunPolicy::Skip => now + interval,
+        },
+        None => now,
+    }
+}
+
+fn with_jitter(interval: Duration, jitter: Duration) -> chrono::Duration {
+    let jitter = match jitter.as_millis() {
+        0 => Duration::ZERO,
+        max => Duration::from_millis(rand::thread_rng().gen_range(0..=max as u64)),
+    };
+    chrono::Duration::from_std(interval + jitter).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_up_missed_runs_only_if_configured() {
+        let now = Utc::now();
+        let mut definition = JobDefinition {
+            name: "test",
+            interval: Duration::from_secs(3600),
+            jitter: Duration::ZERO,
+            missed_run: MissedRunPolicy::RunOnce,
+        };
+        let hour = chrono::Duration::hours(1);
+        assert_eq!(first_run(&definition, None, now), now);
+        assert_eq!(
+            first_run(&definition, Some(now - chrono::Duration::minutes(10)), now),
+            now + chrono::Duration::minutes(50)
+        );
+        assert_eq!(first_run(&definition, Some(now - hour * 5), now), now);
+
+        definition.missed_run = MissedRunPolicy::Skip;
+        assert_eq!(
+            first_run(&definition, Some(now - hour * 5), now),
+            now + hour
+        );
+    }
+}
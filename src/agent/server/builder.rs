@@ -1,9 +1,11 @@
-use std::{ffi::OsString, process::Stdio};
+use std::{ffi::OsString, process::Stdio, sync::Arc};
 
+use log::warn;
+use nix::{sched::sched_setaffinity, unistd::Pid};
 use tokio::{fs, io::AsyncWriteExt, process::Command};
 use uuid::Uuid;
 
-use crate::{factorio::Factorio, util, error::Result};
+use crate::{consts::*, factorio::Factorio, util, error::Result};
 use fctrl::schema::{MapSettingsJson, MapGenSettingsJson, ServerStartSaveFile};
 
 use super::{
@@ -84,6 +86,45 @@ impl ServerBuilder {
         })
     }
 
+    /// Renders a map preview image of the terrain generated by the given map-gen-settings.
+    ///
+    /// The instance uses its own write-data directory, so unlike other short-lived instances it
+    /// can run alongside a hosted server.
+    pub async fn generating_map_preview(
+        mut self,
+        map_gen_settings: MapGenSettingsJson,
+        size: u32,
+    ) -> Result<MapPreviewBuilder> {
+        let write_dir = std::env::current_dir()?.join(&*MAP_PREVIEW_DIR);
+        fs::create_dir_all(&write_dir).await?;
+        let config_path = write_dir.join("config.ini");
+        fs::write(
+            &config_path,
+            format!(
+                "[path]\nread-data=__PATH__executable__/../../data\nwrite-data={}\n",
+                write_dir.display()
+            ),
+        )
+        .await?;
+        let map_gen_settings_path = write_dir.join("map-gen-settings.json");
+        fs::write(&map_gen_settings_path, map_gen_settings.0.as_bytes()).await?;
+
+        self.with_cli_args(&[
+            &OsString::from("--config"),
+            config_path.as_os_str(),
+            &OsString::from("--generate-map-preview"),
+            MAP_PREVIEW_PATH.as_os_str(),
+            &OsString::from("--map-gen-settings"),
+            map_gen_settings_path.as_os_str(),
+            &OsString::from("--map-preview-size"),
+            &OsString::from(size.to_string()),
+        ]);
+        Ok(MapPreviewBuilder {
+            cmd_builder: self.cmd_builder,
+            stdout_handler: self.stdout_handler,
+        })
+    }
+
     pub fn hosting_savefile(
         mut self,
         savefile: ServerStartSaveFile,
@@ -138,12 +179,27 @@ impl ServerBuilder {
 
         self.with_cli_args(&[&OsString::from("--mod-directory"), mods.path.as_os_str()]);
 
+        match launch_settings.cpu_set() {
+            Ok(Some(cpu_set)) => {
+                // Pin in the child before exec, so every thread Factorio spawns inherits the mask.
+                // Safety: sched_setaffinity is a plain syscall and is safe to call after fork.
+                unsafe {
+                    self.cmd_builder.pre_exec(move || {
+                        sched_setaffinity(Pid::from_raw(0), &cpu_set).map_err(std::io::Error::from)
+                    });
+                }
+            }
+            Ok(None) => (),
+            Err(e) => warn!("Ignoring invalid CPU affinity in launch settings: {:?}", e),
+        }
+
         ServerHostBuilder {
             cmd_builder: self.cmd_builder,
             stdout_handler: self.stdout_handler,
             admin_list,
             launch_settings,
             savefile,
+            profile: None,
             server_settings,
             _optional_args: vec![],
         }
@@ -169,10 +225,38 @@ pub struct ServerHostBuilder {
     admin_list: AdminList,
     launch_settings: LaunchSettings,
     savefile: ServerStartSaveFile,
+    profile: Option<String>,
     server_settings: ServerSettings,
     _optional_args: Vec<String>,
 }
 
+impl ServerHostBuilder {
+    /// Records the launch profile the settings were taken from, so that the same profile is used
+    /// if the server is restarted after an upgrade
+    pub fn with_profile(mut self, profile: Option<String>) -> ServerHostBuilder {
+        self.profile = profile;
+        self
+    }
+
+    /// The executable and arguments the server would be started with, with the RCON password
+    /// redacted
+    pub fn command_line(&self) -> Vec<String> {
+        let cmd = self.cmd_builder.as_std();
+        let mut command_line = vec![cmd.get_program().to_string_lossy().into_owned()];
+        let mut redact_next = false;
+        for arg in cmd.get_args() {
+            let arg = arg.to_string_lossy().into_owned();
+            let is_password_flag = arg == "--rcon-password";
+            command_line.push(match redact_next {
+                true => "<redacted>".to_owned(),
+                false => arg,
+            });
+            redact_next = is_password_flag;
+        }
+        command_line
+    }
+}
+
 impl StartableInstanceBuilder for ServerHostBuilder {
     fn replay_optional_args(&mut self, previous_instance: StoppedInstance) -> &Self {
         self._optional_args.extend(previous_instance._optional_args);
@@ -191,10 +275,11 @@ impl StartableInstanceBuilder for ServerHostBuilder {
 
         StartableInstance {
             cmd: self.cmd_builder,
-            stdout_handler: self.stdout_handler,
+            stdout_handler: Arc::from(self.stdout_handler),
             admin_list: self.admin_list,
             launch_settings: self.launch_settings,
             savefile: self.savefile,
+            profile: self.profile,
             server_settings: self.server_settings,
             _optional_args: self._optional_args,
         }
@@ -224,3 +309,26 @@ impl StartableShortLivedInstanceBuilder for SaveCreatorBuilder {
         }
     }
 }
+
+pub struct MapPreviewBuilder {
+    cmd_builder: Command,
+    stdout_handler: Box<dyn HandlerFn>,
+}
+
+impl StartableShortLivedInstanceBuilder for MapPreviewBuilder {
+    fn build(mut self) -> StartableShortLivedInstance {
+        // configure io to be piped
+        self.cmd_builder
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        // set this for a better night's sleep
+        self.cmd_builder.kill_on_drop(true);
+
+        StartableShortLivedInstance {
+            cmd: self.cmd_builder,
+            stdout_handler: self.stdout_handler,
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use log::{error, info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs;
+
+use crate::error::Result;
+
+/// Serialisation format of a config file
+#[derive(Clone, Copy, Debug)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    fn deserialise<T: DeserializeOwned>(self, s: &str) -> Result<T> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::from_str(s)?),
+            ConfigFormat::Toml => Ok(toml::from_str(s)?),
+        }
+    }
+
+    fn serialise<T: Serialize>(self, value: &T) -> Result<String> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::to_string_pretty(value)?),
+            ConfigFormat::Toml => Ok(toml::to_string(value)?),
+        }
+    }
+}
+
+/// Where the contents of a config file are kept
+pub enum ConfigBackend {
+    /// A file on disk
+    Filesystem(PathBuf),
+    /// A file on disk, with contents taken from the environment variable until the file is first
+    /// written, so that a fresh agent can be provisioned without touching its data dir
+    EnvSeeded { path: PathBuf, var: &'static str },
+    /// Held in memory only
+    #[cfg(test)]
+    InMemory(std::sync::Mutex<Option<String>>),
+}
+
+impl ConfigBackend {
+    async fn read(&self) -> std::io::Result<Option<String>> {
+        match self {
+            ConfigBackend::Filesystem(path) => read_if_exists(path).await,
+            ConfigBackend::EnvSeeded { path, var } => match read_if_exists(path).await? {
+                Some(s) => Ok(Some(s)),
+                None => Ok(std::env::var(var).ok()),
+            },
+            #[cfg(test)]
+            ConfigBackend::InMemory(contents) => Ok(contents.lock().unwrap().clone()),
+        }
+    }
+
+    async fn write(&self, s: String) -> std::io::Result<()> {
+        match self {
+            ConfigBackend::Filesystem(path) | ConfigBackend::EnvSeeded { path, .. } => {
+                let parent = path.parent().ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid config path")
+                })?;
+                fs::create_dir_all(parent).await?;
+                fs::write(path, s).await
+            }
+            #[cfg(test)]
+            ConfigBackend::InMemory(contents) => {
+                *contents.lock().unwrap() = Some(s);
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn read_if_exists(path: &Path) -> std::io::Result<Option<String>> {
+    if !path.is_file() {
+        Ok(None)
+    } else {
+        fs::read_to_string(path).await.map(Some)
+    }
+}
+
+/// A settings file holding a value of type `T`, handling the reading, parsing, defaulting and
+/// writing shared by all the settings the agent keeps
+pub struct ConfigFile<T> {
+    /// Human-readable name for logs, e.g. "admin list"
+    name: &'static str,
+    format: ConfigFormat,
+    backend: ConfigBackend,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned + Serialize> ConfigFile<T> {
+    pub fn new(name: &'static str, format: ConfigFormat, backend: ConfigBackend) -> ConfigFile<T> {
+        ConfigFile {
+            name,
+            format,
+            backend,
+            _value: PhantomData,
+        }
+    }
+
+    /// Reads the value, or None if nothing has been written yet
+    pub async fn read(&self) -> Result<Option<T>> {
+        let s = match self.backend.read().await {
+            Ok(Some(s)) => s,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                error!("Error reading {}: {:?}", self.name, e);
+                return Err(e.into());
+            }
+        };
+        match self.format.deserialise(&s) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                error!("Error parsing {}: {:?}", self.name, e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Reads the value, or returns the default without writing it if nothing has been written yet
+    pub async fn read_or_default(&self, default: impl FnOnce() -> T) -> Result<T> {
+        Ok(self.read().await?.unwrap_or_else(default))
+    }
+
+    /// Reads the value, or writes and returns the default if nothing has been written yet.
+    /// Failing to write the default is not an error.
+    pub async fn read_or_apply_default(&self, default: impl FnOnce() -> T) -> Result<T> {
+        match self.read().await? {
+            Some(value) => Ok(value),
+            None => {
+                info!("Generating {} using defaults", self.name);
+                let value = default();
+                if let Err(e) = self.write(&value).await {
+                    // this is okay
+                    warn!("Failed to write default {} to file: {:?}", self.name, e);
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    pub async fn write(&self, value: &T) -> Result<()> {
+        let s = self.format.serialise(value)?;
+        if let Err(e) = self.backend.write(s).await {
+            error!("Error writing {}: {:?}", self.name, e);
+            Err(e.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn in_memory<T: DeserializeOwned + Serialize>(format: ConfigFormat) -> ConfigFile<T> {
+        ConfigFile::new(
+            "test config",
+            format,
+            ConfigBackend::InMemory(std::sync::Mutex::new(None)),
+        )
+    }
+
+    #[tokio::test]
+    async fn applies_default_then_reads_back_written_value() {
+        fctrl::util::testing::logger_init();
+
+        for format in [ConfigFormat::Json, ConfigFormat::Toml] {
+            let file = in_memory::<HashMap<String, u32>>(format);
+            assert!(file.read().await.unwrap().is_none());
+
+            let default = file.read_or_apply_default(HashMap::new).await.unwrap();
+            assert!(default.is_empty());
+            assert_eq!(file.read().await.unwrap(), Some(HashMap::new()));
+
+            let value = HashMap::from([("a".to_owned(), 1)]);
+            file.write(&value).await.unwrap();
+            assert_eq!(
+                file.read_or_apply_default(HashMap::new).await.unwrap(),
+                value
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn env_seeded_reads_env_until_written() {
+        let var = "FCTRL_TEST_CONFIG_FILE_SEED";
+        std::env::set_var(var, r#"["alice"]"#);
+        let dir = std::env::temp_dir().join(format!("fctrl-config-file-{}", std::process::id()));
+        let file: ConfigFile<Vec<String>> = ConfigFile::new(
+            "test list",
+            ConfigFormat::Json,
+            ConfigBackend::EnvSeeded {
+                path: dir.join("list.json"),
+                var,
+            },
+        );
+        assert_eq!(file.read().await.unwrap(), Some(vec!["alice".to_owned()]));
+
+        file.write(&vec!["bob".to_owned()]).await.unwrap();
+        assert_eq!(file.read().await.unwrap(), Some(vec!["bob".to_owned()]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
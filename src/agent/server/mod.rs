@@ -1,9 +1,6 @@
 use std::sync::Arc;
-use std::time::Duration;
-use std::{
-    net::{Ipv4Addr, SocketAddrV4},
-    process::ExitStatus,
-};
+use std::time::{Duration, Instant};
+use std::process::ExitStatus;
 use std::{
     str::FromStr,
     sync::atomic::{AtomicU32, Ordering},
@@ -24,27 +21,36 @@ use fctrl::schema::*;
 
 use settings::*;
 
-use self::rcon::Rcon;
+use self::{rcon::Rcon, ups::UpsTracker};
 
 pub mod builder;
+pub mod config_file;
 pub mod mods;
 pub mod proc;
+pub mod profiles;
 pub mod rcon;
 pub mod settings;
+pub mod ups;
 
 pub trait HandlerFn = Fn(String) + Send + Sync + 'static;
 
 pub struct StartableInstance {
     cmd: Command,
-    stdout_handler: Box<dyn HandlerFn>,
+    stdout_handler: Arc<dyn HandlerFn>,
     admin_list: AdminList,
     launch_settings: LaunchSettings,
     savefile: ServerStartSaveFile,
+    /// Launch profile the server was started with, if any
+    profile: Option<String>,
     server_settings: ServerSettings,
     _optional_args: Vec<String>,
 }
 
 impl StartableInstance {
+    pub fn get_savefile(&self) -> &ServerStartSaveFile {
+        &self.savefile
+    }
+
     pub async fn start(mut self) -> Result<StartedInstance> {
         let mut instance = self.cmd.spawn()?;
         info!(
@@ -55,7 +61,7 @@ impl StartableInstance {
         );
 
         // set up to pass various things to the stdout and stderr handlers
-        let stdout_handler = self.stdout_handler;
+        let stdout_handler = Arc::clone(&self.stdout_handler);
 
         let rcon = Arc::new(RwLock::new(None));
         let rcon_clone = Arc::clone(&rcon);
@@ -123,16 +129,46 @@ impl StartableInstance {
             }
         });
 
+        let ups = Arc::new(std::sync::Mutex::new(UpsTracker::default()));
+        let ups_clone = Arc::clone(&ups);
+        let rcon_clone = Arc::clone(&rcon);
+        let ups_monitor_task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(ups::MEASUREMENT_INTERVAL).await;
+                if let Some(rcon) = rcon_clone.read().await.as_ref() {
+                    match rcon.send(ups::MEASUREMENT_COMMAND).await {
+                        Ok(resp) => match ups::parse_time_output(&resp) {
+                            Some(game_secs) => {
+                                ups_clone.lock().unwrap().record(Instant::now(), game_secs);
+                            }
+                            None => {
+                                warn!("Unexpected response when measuring game time via RCON: {}", resp);
+                            }
+                        },
+                        Err(e) => {
+                            warn!("Error measuring game time via RCON: {}", e);
+                        }
+                    }
+                }
+            }
+        });
+
         Ok(StartedInstance {
             process: instance,
+            started_at: Instant::now(),
+            cmd: self.cmd,
+            stdout_handler: self.stdout_handler,
             rcon,
             internal_server_state,
             player_count,
+            ups,
             admin_list: self.admin_list,
             launch_settings: self.launch_settings,
             savefile: self.savefile,
+            profile: self.profile,
             server_settings: self.server_settings,
             player_count_refresh_task,
+            ups_monitor_task,
             _optional_args: self._optional_args,
         })
     }
@@ -140,14 +176,21 @@ impl StartableInstance {
 
 pub struct StartedInstance {
     process: Child,
+    started_at: Instant,
+    /// Kept to be able to launch the same server again after a crash
+    cmd: Command,
+    stdout_handler: Arc<dyn HandlerFn>,
     rcon: Arc<RwLock<Option<Rcon>>>,
     internal_server_state: Arc<RwLock<InternalServerState>>,
     player_count: Arc<AtomicU32>,
+    ups: Arc<std::sync::Mutex<UpsTracker>>,
     admin_list: AdminList,
     launch_settings: LaunchSettings,
     savefile: ServerStartSaveFile,
+    profile: Option<String>,
     server_settings: ServerSettings,
     player_count_refresh_task: JoinHandle<()>,
+    ups_monitor_task: JoinHandle<()>,
     _optional_args: Vec<String>,
 }
 
@@ -162,6 +205,7 @@ impl StartedInstance {
     /// - wait() on the process failed
     pub async fn stop(mut self) -> Result<StoppedInstance> {
         self.player_count_refresh_task.abort();
+        self.ups_monitor_task.abort();
 
         if let Some(exit_status) = self.process.try_wait()? {
             // process already exited
@@ -174,6 +218,7 @@ impl StartedInstance {
                 admin_list: self.admin_list,
                 launch_settings: self.launch_settings,
                 savefile: self.savefile,
+                profile: self.profile,
                 server_settings: self.server_settings,
                 _optional_args: self._optional_args,
             });
@@ -197,6 +242,7 @@ impl StartedInstance {
 
     pub async fn wait(mut self) -> Result<StoppedInstance> {
         self.player_count_refresh_task.abort();
+        self.ups_monitor_task.abort();
 
         let exit_status = self.process.wait().await?;
         info!("Child process exited with status {}", exit_status);
@@ -206,11 +252,46 @@ impl StartedInstance {
             admin_list: self.admin_list,
             launch_settings: self.launch_settings,
             savefile: self.savefile,
+            profile: self.profile,
             server_settings: self.server_settings,
             _optional_args: self._optional_args,
         })
     }
 
+    /// Reaps an instance whose process has already exited, returning an instance which launches
+    /// the same server again.
+    pub async fn into_exited(mut self) -> Result<ExitedInstance> {
+        self.player_count_refresh_task.abort();
+        self.ups_monitor_task.abort();
+
+        let exit_status = self.process.wait().await?;
+        Ok(ExitedInstance {
+            exit_status,
+            uptime: self.started_at.elapsed(),
+            restartable: StartableInstance {
+                cmd: self.cmd,
+                stdout_handler: self.stdout_handler,
+                admin_list: self.admin_list,
+                launch_settings: self.launch_settings,
+                savefile: self.savefile,
+                profile: self.profile,
+                server_settings: self.server_settings,
+                _optional_args: self._optional_args,
+            },
+        })
+    }
+
+    /// Stops the instance like [`StartedInstance::stop`], returning an instance which launches the
+    /// same server again
+    pub async fn stop_to_restart(self) -> Result<StartableInstance> {
+        if let Some(pid) = self.process.id() {
+            // server will gracefully save and shut down
+            signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM)
+                .map_err(Error::ProcessSignalError)?;
+        }
+        Ok(self.into_exited().await?.restartable)
+    }
+
     /// Manually poll whether the child process has exited
     pub async fn poll_process_exited(&mut self) -> Result<bool> {
         Ok(self.process.try_wait()?.is_some())
@@ -220,10 +301,41 @@ impl StartedInstance {
         self.internal_server_state.read().await.clone()
     }
 
+    pub fn get_pid(&self) -> Option<u32> {
+        self.process.id()
+    }
+
+    pub fn get_uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn get_started_at(&self) -> Instant {
+        self.started_at
+    }
+
+    pub fn get_restart_policy(&self) -> &RestartPolicy {
+        &self.launch_settings.restart_policy
+    }
+
+    pub fn get_savefile(&self) -> &ServerStartSaveFile {
+        &self.savefile
+    }
+
     pub fn get_player_count(&self) -> u32 {
         self.player_count.load(Ordering::Relaxed)
     }
 
+    /// Rolling server updates per second, if it has been measured
+    pub fn get_ups(&self) -> Option<f32> {
+        self.ups.lock().unwrap().ups()
+    }
+
+    /// In-game clock as of the latest game time measurement
+    pub fn get_map_clock(&self) -> Option<MapClock> {
+        let game_secs = self.ups.lock().unwrap().game_secs()?;
+        Some(MapClock::from_tick(game_secs * ups::TARGET_UPS as u64))
+    }
+
     pub async fn get_rcon(&self) -> tokio::sync::RwLockReadGuard<'_, Option<Rcon>> {
         self.rcon.read().await
     }
@@ -235,10 +347,24 @@ pub struct StoppedInstance {
     pub admin_list: AdminList,
     pub launch_settings: LaunchSettings,
     pub savefile: ServerStartSaveFile,
+    pub profile: Option<String>,
     pub server_settings: ServerSettings,
     pub _optional_args: Vec<String>,
 }
 
+/// An instance whose process exited without being stopped
+pub struct ExitedInstance {
+    pub exit_status: ExitStatus,
+    pub uptime: Duration,
+    pub restartable: StartableInstance,
+}
+
+impl ExitedInstance {
+    pub fn restart_policy(&self) -> &RestartPolicy {
+        &self.restartable.launch_settings.restart_policy
+    }
+}
+
 pub struct StartableShortLivedInstance {
     cmd: Command,
     stdout_handler: Box<dyn HandlerFn>,
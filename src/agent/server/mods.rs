@@ -1,20 +1,21 @@
 use std::{
-    borrow::Borrow, collections::HashSet, convert::{TryFrom, TryInto}, path::{Path, PathBuf}, str::FromStr
+    borrow::Borrow, collections::HashSet, hash::{Hash, Hasher}, path::{Path, PathBuf}, str::FromStr
 };
 
-use factorio_file_parser::ModSettings;
 use futures::future;
 use lazy_static::lazy_static;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
-use tokio::fs;
+use tokio::{fs, sync::mpsc};
 
 use crate::{
     consts::*,
     error::{Error, Result},
-    util::downloader,
+    journal::JournalEntry,
+    util::{self, downloader, http},
 };
 
+use fctrl::mod_settings::ModSettings;
 use fctrl::schema::{regex::*, *};
 
 use super::settings::Secrets;
@@ -24,10 +25,20 @@ lazy_static! {
     static ref MOD_SETTINGS_PATH: PathBuf = MOD_DIR.join("mod-settings.dat");
 }
 
+/// Progress of an individual mod download while applying mod changes
+#[derive(Debug)]
+pub enum ModInstallProgress {
+    Started(Mod),
+    Completed(Mod),
+    Failed(Mod, String),
+}
+
 pub struct ModManager {
     pub dlcs: HashSet<Dlc>,
     pub mods: Vec<Mod>,
     pub settings: Option<ModSettings>,
+    /// Why the mod settings on disk couldn't be read, in which case they are left untouched
+    pub settings_unreadable: Option<ModSettingsUnreadable>,
     pub path: PathBuf,
 }
 
@@ -47,10 +58,9 @@ impl ModManager {
                 },
             };
 
-            // For actual mods, don't bother with mod list, directly parse the mod zips
-            // No support for "installed but disabled" mods
-            // TODO we're reading the mod-list.json now anyway, use that instead of parsing
-            // TODO also mod-list.json supports versioning now
+            // For actual mods, directly parse the mod zips, taking only whether each is enabled
+            // from the mod list. Mods missing from the mod list are enabled by the game.
+            // TODO mod-list.json supports versioning now
             let mut mod_zip_names = vec![];
             let mut entries = fs::read_dir(&*MOD_DIR).await?;
             while let Some(entry) = entries.next_entry().await? {
@@ -73,17 +83,27 @@ impl ModManager {
             let mods = mod_zip_names
                 .into_iter()
                 .filter_map(|n| Mod::try_from_filename(&n))
+                .map(|mut m| {
+                    m.enabled = mod_list
+                        .mods
+                        .iter()
+                        .find(|elem| elem.name == m.name)
+                        .map_or(true, |elem| elem.enabled);
+                    m
+                })
                 .collect();
 
-            // mod settings is optional
+            // mod settings is optional, and unreadable settings shouldn't stop the mods from being
+            // managed, so are only reported when the settings themselves are asked for
             let mut settings = None;
+            let mut settings_unreadable = None;
             if MOD_SETTINGS_PATH.is_file() {
                 let bytes = fs::read(&*MOD_SETTINGS_PATH).await?;
-                match ModSettings::try_from(bytes.as_ref()) {
+                match ModSettings::parse(&bytes) {
                     Ok(s) => settings = Some(s),
                     Err(e) => {
-                        error!("Error parsing mod settings: {:?}", e);
-                        return Err(e.into());
+                        error!("Error parsing mod settings: {}", e);
+                        settings_unreadable = Some(e);
                     }
                 }
             }
@@ -92,6 +112,7 @@ impl ModManager {
                 dlcs,
                 mods,
                 settings,
+                settings_unreadable,
                 path: MOD_DIR.clone(),
             }))
         }
@@ -103,21 +124,45 @@ impl ModManager {
             None => {
                 info!("Generating mod dir and contents using defaults");
 
-                let ret = ModManager {
-                    dlcs: HashSet::from([Dlc::Base]),
-                    mods: vec![],
-                    settings: None,
-                    path: MOD_DIR.clone(),
-                };
+                let ret = ModManager::empty();
                 ret.apply_metadata_only().await?;
                 Ok(ret)
             }
         }
     }
 
-    pub async fn apply(&self, secrets: &Secrets) -> Result<()> {
-        // Read current mods, figure out the delta
+    /// Reads the mods, or the empty mod dir that would be generated, without writing it
+    pub async fn read_or_default() -> Result<ModManager> {
+        Ok(ModManager::read().await?.unwrap_or_else(ModManager::empty))
+    }
+
+    /// Only the base game, with no mods or mod settings
+    fn empty() -> ModManager {
+        ModManager {
+            dlcs: HashSet::from([Dlc::Base]),
+            mods: vec![],
+            settings: None,
+            settings_unreadable: None,
+            path: MOD_DIR.clone(),
+        }
+    }
+
+    /// Installs and deletes mods to match `self.mods`, optionally reporting the progress of each
+    /// mod download
+    pub async fn apply(
+        &mut self,
+        secrets: &Secrets,
+        progress_tx: Option<mpsc::UnboundedSender<ModInstallProgress>>,
+    ) -> Result<()> {
         let currently_installed = ModManager::read().await?.map_or(vec![], |m| m.mods);
+
+        // Requested versions are authoritative, so check them all before changing anything
+        let not_found = self.resolve_versions(&currently_installed).await?;
+        if !not_found.is_empty() {
+            return Err(Error::ModVersionsNotFound(not_found));
+        }
+
+        // Figure out the delta
         let ModDelta { install, delete } =
             ModManager::calculate_mod_delta(&currently_installed, &self.mods);
 
@@ -138,13 +183,35 @@ impl ModManager {
                 .join(", ")
         );
 
+        // Mods are downloaded straight into the mod directory, so record which until they are done
+        let _journal = JournalEntry::mod_apply(
+            self.path.clone(),
+            install.iter().map(|m| m.name.clone()).collect(),
+        )
+        .await?
+        .begin()
+        .await?;
+
         // Start tasks to install
         let mut tasks = vec![];
         for install in install.into_iter() {
             let install_path = self.path.clone();
             let secrets_clone = secrets.clone();
+            let progress_tx = progress_tx.clone();
             tasks.push(tokio::spawn(async move {
-                ModManager::download_mod(&install, &install_path, &secrets_clone).await
+                // receiver may have lost interest, which doesn't affect the install
+                if let Some(tx) = &progress_tx {
+                    let _ = tx.send(ModInstallProgress::Started(install.clone()));
+                }
+                let result =
+                    ModManager::download_mod(&install, &install_path, &secrets_clone).await;
+                if let Some(tx) = &progress_tx {
+                    let _ = tx.send(match &result {
+                        Ok(()) => ModInstallProgress::Completed(install),
+                        Err(e) => ModInstallProgress::Failed(install, format!("{:?}", e)),
+                    });
+                }
+                result
             }));
         }
 
@@ -191,48 +258,177 @@ impl ModManager {
         let mod_list_json = serde_json::to_string(&ModList::from(self))?;
         fs::write(&*MOD_LIST_PATH, mod_list_json).await?;
 
-        if let Some(settings) = self.settings.clone() {
-            let bytes: Vec<u8> = settings.try_into()?;
-            fs::write(&*MOD_SETTINGS_PATH, bytes).await?;
+        if let Some(settings) = &self.settings {
+            fs::write(&*MOD_SETTINGS_PATH, settings.to_bytes()).await?;
         }
 
         Ok(())
     }
 
+    /// Sets each mod to its newest release that the given version of Factorio can load, returning
+    /// the mods that were changed along with their previous version. Mods not on the mod portal
+    /// or without a loadable release are left as they are.
+    pub async fn update_all(
+        &mut self,
+        factorio_version: &FactorioVersion,
+    ) -> Result<Vec<(Mod, ModVersion)>> {
+        let mut updates = vec![];
+        for m in self.mods.iter_mut() {
+            let info = match ModManager::short_query_mod(m).await {
+                Ok(info) => info,
+                Err(Error::Reqwest(e)) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => {
+                    warn!("Mod {} does not exist on the mod portal, not updating", m.name);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let newest = info
+                .releases
+                .iter()
+                .filter(|r| factorio_version.loads_mods_for(&r.info_json.factorio_version))
+                .map(|r| ModVersion::from(r.version.as_str()))
+                .max();
+            match newest {
+                Some(newest) if newest > m.version => {
+                    info!("Updating mod {} from {} to {}", m.name, m.version, newest);
+                    let previous = std::mem::replace(&mut m.version, newest);
+                    updates.push((m.clone(), previous));
+                }
+                Some(_) => (),
+                None => warn!(
+                    "Mod {} has no release for Factorio {}, not updating",
+                    m.name, factorio_version.0
+                ),
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Pins requests for the latest release to a concrete version, so they can be compared
+    /// against what is currently installed, and checks that every other requested version is
+    /// published. Returns the requests that don't match a release on the mod portal.
+    async fn resolve_versions(
+        &mut self,
+        currently_installed: &[Mod],
+    ) -> Result<Vec<ModVersionNotFound>> {
+        let mut not_found = vec![];
+        for m in self.mods.iter_mut() {
+            if currently_installed.contains(m) {
+                continue;
+            }
+
+            let info = match ModManager::short_query_mod(m).await {
+                Ok(info) => info,
+                Err(Error::Reqwest(e)) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => {
+                    error!("Mod {} does not exist on the mod portal", m.name);
+                    not_found.push(ModVersionNotFound {
+                        name: m.name.clone(),
+                        requested_version: m.version.to_string(),
+                        available_versions: vec![],
+                    });
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            if let ModVersion::Latest = m.version {
+                if let Some(r) = ModManager::latest_release(&info) {
+                    info!("Resolved latest version of mod {} as {}", m.name, r.version);
+                    m.version = ModVersion::from(r.version.as_str());
+                    continue;
+                }
+            }
+            let published = info
+                .releases
+                .iter()
+                .map(|r| r.version.as_str())
+                .collect::<Vec<_>>();
+            if let Some(missing) = ModManager::check_requested_version(m, &published) {
+                error!(
+                    "Mod {} has no release {} on the mod portal, found [{}]",
+                    m.name,
+                    m.version,
+                    missing.available_versions.join(", ")
+                );
+                not_found.push(missing);
+            }
+        }
+        Ok(not_found)
+    }
+
+    /// Checks that the requested version of the mod is one of the published versions, returning
+    /// those that are published if not
+    fn check_requested_version(m: &Mod, published: &[&str]) -> Option<ModVersionNotFound> {
+        if published.iter().any(|v| ModVersion::from(*v) == m.version) {
+            return None;
+        }
+
+        let mut available_versions = published
+            .iter()
+            .map(|v| ModVersion::from(*v))
+            .collect::<Vec<_>>();
+        available_versions.sort_by(|a, b| b.cmp(a));
+        Some(ModVersionNotFound {
+            name: m.name.clone(),
+            requested_version: m.version.to_string(),
+            available_versions: available_versions.iter().map(|v| v.to_string()).collect(),
+        })
+    }
+
     async fn short_query_mod(mod_to_query: &Mod) -> Result<factorio_mod_portal_api::ModInfoShort> {
         let short_query_url = format!("https://mods.factorio.com/api/mods/{}", mod_to_query.name);
 
         debug!("Querying mod {} at {}", mod_to_query.name, short_query_url);
-        let short_query_response = reqwest::get(short_query_url).await?.error_for_status()?;
+        let short_query_response = http::client()
+            .get(short_query_url)
+            .send()
+            .await?
+            .error_for_status()?;
         Ok(short_query_response
             .json::<factorio_mod_portal_api::ModInfoShort>()
             .await?)
     }
 
+    fn latest_release(
+        info: &factorio_mod_portal_api::ModInfoShort,
+    ) -> Option<&factorio_mod_portal_api::Release> {
+        info.releases
+            .iter()
+            .max_by_key(|r| ModVersion::from(r.version.as_str()))
+    }
+
     async fn download_mod<P: AsRef<Path>>(
         mod_to_download: &Mod,
         destination_dir: P,
         secrets: &Secrets,
     ) -> Result<()> {
         let info = ModManager::short_query_mod(&mod_to_download).await?;
-        if let Some(r) = info
-            .releases
-            .iter()
-            .find(|r| r.version == mod_to_download.version)
-        {
+        let release = match mod_to_download.version {
+            ModVersion::Latest => ModManager::latest_release(&info),
+            ref version => info
+                .releases
+                .iter()
+                .find(|r| ModVersion::from(r.version.as_str()) == *version),
+        };
+        if let Some(r) = release {
             // Construct actual download url
             let download_url = format!(
                 "https://mods.factorio.com/{}?username={}&token={}",
                 r.download_url, secrets.username, secrets.token,
             );
-            let filename = format!("{}_{}.zip", mod_to_download.name, mod_to_download.version);
+            // Use the version string as published, which may differ from the requested string
+            // while still being numerically equal
+            let filename = format!("{}_{}.zip", mod_to_download.name, r.version);
             let out_file = destination_dir.as_ref().join(&filename);
             let bytes = downloader::download(&filename, download_url).await?;
+            // downloads are held in memory, so nothing is written unless it all fits
+            util::disk::check_available_space(destination_dir.as_ref(), bytes.len() as u64)
+                .map_err(Error::InsufficientDiskSpace)?;
             fs::write(&out_file, bytes).await?;
             info!(
                 "Installed mod {} version {} to {}",
                 mod_to_download.name,
-                mod_to_download.version,
+                r.version,
                 out_file.display()
             );
             Ok(())
@@ -243,7 +439,7 @@ impl ModManager {
             );
             Err(Error::ModNotFound {
                 mod_name: mod_to_download.name.clone(),
-                mod_version: mod_to_download.version.clone(),
+                mod_version: mod_to_download.version.to_string(),
             })
         }
     }
@@ -290,21 +486,45 @@ impl ModManager {
     }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+/// A mod in the mod directory. Disabled mods are kept on disk but not loaded by the game.
+///
+/// Mods compare equal if they are the same release, whether or not they are enabled, so that
+/// enabling or disabling a mod doesn't download or delete it.
+#[derive(Clone, Debug)]
 pub struct Mod {
     pub name: String,
-    pub version: String,
+    pub version: ModVersion,
+    pub enabled: bool,
+}
+
+impl PartialEq for Mod {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.version == other.version
+    }
+}
+
+impl Eq for Mod {}
+
+impl Hash for Mod {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.version.hash(state);
+    }
 }
 
 impl Mod {
-    fn try_from_filename(s: &str) -> Option<Mod> {
+    pub fn try_from_filename(s: &str) -> Option<Mod> {
         // Per https://wiki.factorio.com/Tutorial:Mod_structure, mod zip files must be named with the pattern:
         // {mod-name}_{version-number}.zip
         // No support for unzipped mods (yet?)
         if let Some(captures) = MOD_FILENAME_RE.captures(s) {
             let name = captures.get(1).unwrap().as_str().to_string();
-            let version = captures.get(2).unwrap().as_str().to_string();
-            Some(Mod { name, version })
+            let version = ModVersion::from(captures.get(2).unwrap().as_str());
+            Some(Mod {
+                name,
+                version,
+                enabled: true,
+            })
         } else {
             debug!(
                 "Filename {} could not be parsed into a mod name and version",
@@ -347,8 +567,8 @@ impl<T: Borrow<ModManager>> From<T> for ModList {
         }).collect();
         elems.extend(m.borrow().mods.iter().map(|m| ModListElem {
             name: m.name.clone(),
-            enabled: true,
-            version: Some(m.version.clone()),
+            enabled: m.enabled,
+            version: Some(m.version.to_string()),
         }));
         ModList { mods: elems }
     }
@@ -412,49 +632,56 @@ mod tests {
             "A Sea Block Config_0.5.1.zip",
             Mod {
                 name: "A Sea Block Config".to_owned(),
-                version: "0.5.1".to_owned(),
+                version: "0.5.1".into(),
+                enabled: true,
             },
         );
         valid_names.insert(
             "AfraidOfTheDark_1.1.1.zip",
             Mod {
                 name: "AfraidOfTheDark".to_owned(),
-                version: "1.1.1".to_owned(),
+                version: "1.1.1".into(),
+                enabled: true,
             },
         );
         valid_names.insert(
             "Companion_Drones_1.0.19.zip",
             Mod {
                 name: "Companion_Drones".to_owned(),
-                version: "1.0.19".to_owned(),
+                version: "1.0.19".into(),
+                enabled: true,
             },
         );
         valid_names.insert(
             "KS_Power_quickfix_0.4.05.zip",
             Mod {
                 name: "KS_Power_quickfix".to_owned(),
-                version: "0.4.05".to_owned(),
+                version: "0.4.05".into(),
+                enabled: true,
             },
         );
         valid_names.insert(
             "Squeak Through_1.8.1.zip",
             Mod {
                 name: "Squeak Through".to_owned(),
-                version: "1.8.1".to_owned(),
+                version: "1.8.1".into(),
+                enabled: true,
             },
         );
         valid_names.insert(
             "Todo-List_19.1.0.zip",
             Mod {
                 name: "Todo-List".to_owned(),
-                version: "19.1.0".to_owned(),
+                version: "19.1.0".into(),
+                enabled: true,
             },
         );
         valid_names.insert(
             "train-pubsub_1.1.4.zip",
             Mod {
                 name: "train-pubsub".to_owned(),
-                version: "1.1.4".to_owned(),
+                version: "1.1.4".into(),
+                enabled: true,
             },
         );
 
@@ -493,7 +720,8 @@ mod tests {
 
         let mod_to_query = Mod {
             name: "rso-mod".to_owned(),
-            version: "6.2.5".to_owned(),
+            version: "6.2.5".into(),
+            enabled: true,
         };
 
         assert!(ModManager::short_query_mod(&mod_to_query).await.is_ok());
@@ -501,6 +729,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn rejects_requested_versions_that_are_not_published() {
+        let published = ["6.2.4", "6.2.23", "6.2.5"];
+        let mut m = Mod {
+            name: "rso-mod".to_owned(),
+            version: "6.2.05".into(),
+            enabled: true,
+        };
+        assert_eq!(ModManager::check_requested_version(&m, &published), None);
+
+        m.version = "6.2.6".into();
+        assert_eq!(
+            ModManager::check_requested_version(&m, &published),
+            Some(ModVersionNotFound {
+                name: "rso-mod".to_owned(),
+                requested_version: "6.2.6".to_owned(),
+                available_versions: vec![
+                    "6.2.23".to_owned(),
+                    "6.2.5".to_owned(),
+                    "6.2.4".to_owned()
+                ],
+            })
+        );
+    }
+
     #[test]
     fn can_calculate_mod_delta_with_empty_current_list() {
         util::testing::logger_init();
@@ -508,7 +761,8 @@ mod tests {
         let current = vec![];
         let desired = vec![Mod {
             name: "rso-mod".to_owned(),
-            version: "6.2.5".to_owned(),
+            version: "6.2.5".into(),
+            enabled: true,
         }];
 
         let delta = ModManager::calculate_mod_delta(&current, &desired);
@@ -523,25 +777,30 @@ mod tests {
         let current = vec![
             Mod {
                 name: "test1".to_owned(),
-                version: "2.3.4".to_owned(),
+                version: "2.3.4".into(),
+                enabled: true,
             },
             Mod {
                 name: "test2".to_owned(),
-                version: "1.2.5".to_owned(),
+                version: "1.2.5".into(),
+                enabled: true,
             },
             Mod {
                 name: "rso-mod".to_owned(),
-                version: "6.2.4".to_owned(),
+                version: "6.2.4".into(),
+                enabled: true,
             },
         ];
         let desired = vec![
             Mod {
                 name: "test1".to_owned(),
-                version: "2.3.4".to_owned(),
+                version: "2.3.4".into(),
+                enabled: true,
             },
             Mod {
                 name: "rso-mod".to_owned(),
-                version: "6.2.5".to_owned(),
+                version: "6.2.5".into(),
+                enabled: true,
             },
         ];
 
@@ -549,17 +808,72 @@ mod tests {
         assert_eq!(delta.delete.len(), 2);
         assert!(delta.delete.contains(&Mod {
             name: "test2".to_owned(),
-            version: "1.2.5".to_owned(),
+            version: "1.2.5".into(),
+            enabled: true,
         }));
         assert!(delta.delete.contains(&Mod {
             name: "rso-mod".to_owned(),
-            version: "6.2.4".to_owned(),
+            version: "6.2.4".into(),
+            enabled: true,
         }));
         assert_eq!(delta.install.len(), 1);
         assert_eq!(delta.install.len(), 1);
         assert!(delta.install.contains(&Mod {
             name: "rso-mod".to_owned(),
-            version: "6.2.5".to_owned(),
+            version: "6.2.5".into(),
+            enabled: true,
         }));
     }
+
+    #[test]
+    fn mod_delta_treats_numerically_equal_versions_as_installed() {
+        util::testing::logger_init();
+
+        let current = vec![Mod {
+            name: "KS_Power_quickfix".to_owned(),
+            version: "0.4.05".into(),
+            enabled: true,
+        }];
+        let desired = vec![Mod {
+            name: "KS_Power_quickfix".to_owned(),
+            version: "0.4.5".into(),
+            enabled: true,
+        }];
+
+        let delta = ModManager::calculate_mod_delta(&current, &desired);
+        assert!(delta.install.is_empty());
+        assert!(delta.delete.is_empty());
+    }
+
+    #[test]
+    fn disabling_mod_keeps_it_installed() {
+        util::testing::logger_init();
+
+        let current = vec![Mod {
+            name: "rso-mod".to_owned(),
+            version: "6.2.23".into(),
+            enabled: true,
+        }];
+        let desired = vec![Mod {
+            name: "rso-mod".to_owned(),
+            version: "6.2.23".into(),
+            enabled: false,
+        }];
+
+        let delta = ModManager::calculate_mod_delta(&current, &desired);
+        assert!(delta.install.is_empty());
+        assert!(delta.delete.is_empty());
+
+        let m = ModManager {
+            dlcs: HashSet::from([Dlc::Base]),
+            mods: desired,
+            settings: None,
+            settings_unreadable: None,
+            path: MOD_DIR.clone(),
+        };
+        let mod_list = ModList::from(&m);
+        let elem = mod_list.mods.iter().find(|e| e.name == "rso-mod").unwrap();
+        assert!(!elem.enabled);
+        assert_eq!(elem.version.as_deref(), Some("6.2.23"));
+    }
 }
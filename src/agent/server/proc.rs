@@ -3,31 +3,47 @@ use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use log::debug;
-use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, Pid, ProcessRefreshKind, RefreshKind, System};
+use chrono::{DateTime, Utc};
 use tokio::{
     io::{AsyncBufRead, AsyncBufReadExt},
-    sync::{Mutex, RwLock},
+    sync::{broadcast, mpsc, Mutex, RwLock},
 };
 
 use crate::{
+    consts::*,
     error::{Error, Result},
+    remote_saves::RemoteSaves,
     server::{
         builder::{StartableInstanceBuilder, StartableShortLivedInstanceBuilder},
         *,
     },
+    uptime_restart, util,
 };
 use fctrl::schema::regex::*;
 
+/// How often the supervisor checks whether the server process is still running
+const SUPERVISOR_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// An instance that stays up for this long is considered healthy, resetting the restart attempts
+const RESTART_ATTEMPTS_RESET_AFTER: Duration = Duration::from_secs(10 * 60);
+
 pub struct ProcessManager {
     sysinfo: Arc<RwLock<System>>,
     running_instance: Arc<Mutex<Option<StartedInstance>>>,
+    exited_tx: mpsc::UnboundedSender<ExitedInstance>,
+    exited_rx: std::sync::Mutex<Option<mpsc::UnboundedReceiver<ExitedInstance>>>,
+    /// Time of an uptime restart being counted down to, if any
+    uptime_restart_at: std::sync::Mutex<Option<DateTime<Utc>>>,
+    /// Remote storage to push the hosted savefile to whenever the server stops, if enabled
+    remote_saves: Option<Arc<RemoteSaves>>,
 }
 
 impl ProcessManager {
-    pub fn new() -> Self {
+    pub fn new(remote_saves: Option<Arc<RemoteSaves>>) -> Self {
         let sysinfo_refresh_specifics = RefreshKind::nothing()
             .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
-            .with_memory(MemoryRefreshKind::nothing().with_ram());
+            .with_memory(MemoryRefreshKind::nothing().with_ram())
+            .with_processes(ProcessRefreshKind::nothing().with_cpu().with_memory());
         let sysinfo = Arc::new(RwLock::new(System::new_with_specifics(sysinfo_refresh_specifics)));
         let sysinfo_arc = Arc::clone(&sysinfo);
         tokio::spawn(async move {
@@ -41,12 +57,82 @@ impl ProcessManager {
                 }
             }
         });
+        let (exited_tx, exited_rx) = mpsc::unbounded_channel();
         ProcessManager {
             sysinfo,
             running_instance: Arc::new(Mutex::new(None)),
+            exited_tx,
+            exited_rx: std::sync::Mutex::new(Some(exited_rx)),
+            uptime_restart_at: std::sync::Mutex::new(None),
+            remote_saves,
         }
     }
 
+    /// Starts a background task which detects the server process exiting without being stopped,
+    /// reports it on the global bus, and restarts the server according to its restart policy.
+    pub fn start_supervisor(self: &Arc<Self>, global_tx: Arc<broadcast::Sender<AgentStreamingMessage>>) {
+        let mut exited_rx = match self.exited_rx.lock().unwrap().take() {
+            Some(rx) => rx,
+            None => {
+                error!("Process supervisor already started");
+                return;
+            }
+        };
+        let proc_manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut poll = tokio::time::interval(SUPERVISOR_POLL_INTERVAL);
+            let mut attempt = 0;
+            loop {
+                tokio::select! {
+                    _ = poll.tick() => {
+                        // reaps the instance if it has exited, which is then received below
+                        proc_manager.instance_is_running_or_cleanup().await;
+                    }
+                    Some(exited) = exited_rx.recv() => {
+                        proc_manager.push_remote_save(exited.restartable.get_savefile()).await;
+                        if exited.exit_status.success() {
+                            info!("Server process exited cleanly without being stopped, not restarting");
+                            attempt = 0;
+                            continue;
+                        }
+
+                        if exited.uptime >= RESTART_ATTEMPTS_RESET_AFTER {
+                            attempt = 0;
+                        }
+                        attempt += 1;
+                        let backoff = exited.restart_policy().backoff(attempt);
+                        error!(
+                            "Server process crashed with {} after running for {}s",
+                            exited.exit_status,
+                            exited.uptime.as_secs()
+                        );
+                        let msg = AgentStreamingMessage {
+                            timestamp: Utc::now(),
+                            content: AgentStreamingMessageInner::ServerCrashed {
+                                exit_status: exited.exit_status.to_string(),
+                                restart_attempt: backoff.map(|_| attempt),
+                            },
+                        };
+                        if let Err(e) = global_tx.send(msg) {
+                            error!("Failed to send streaming message: {:?}", e);
+                        }
+
+                        match backoff {
+                            None => warn!("Not restarting server, restart policy allows {} attempt(s)", exited.restart_policy().max_retries),
+                            Some(backoff) => {
+                                info!("Restarting server in {}s, attempt {}", backoff.as_secs(), attempt);
+                                tokio::time::sleep(backoff).await;
+                                if let Err(e) = proc_manager.restart_instance(exited).await {
+                                    error!("Failed to restart server: {:?}", e);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
     pub async fn system_resources(&self) -> Result<SystemResources> {
         if let Ok(sysinfo) = tokio::time::timeout(Duration::from_millis(250), self.sysinfo.read()).await {
             Ok(SystemResources {
@@ -54,12 +140,28 @@ impl ProcessManager {
                 cpus: sysinfo.cpus().into_iter().map(|cpu| cpu.cpu_usage()).collect(),
                 mem_total_bytes: sysinfo.total_memory(),
                 mem_used_bytes: sysinfo.used_memory(),
+                dropped_messages: 0,
+                disks: util::disk::usage([&*FACTORIO_INSTALL_DIR, &*ROAMING_DATA_DIR]),
+                server_process: self.server_process_resources(&sysinfo),
             })
         } else {
             Err(Error::Timeout)
         }
     }
 
+    /// Resource usage of the running server process. Skipped if the instance is busy, e.g. being
+    /// stopped, so as not to hold up the response.
+    fn server_process_resources(&self, sysinfo: &System) -> Option<ProcessResources> {
+        let mg = self.running_instance.try_lock().ok()?;
+        let instance = mg.as_ref()?;
+        let process = sysinfo.process(Pid::from_u32(instance.get_pid()?))?;
+        Some(ProcessResources {
+            cpu_usage: process.cpu_usage(),
+            mem_rss_bytes: process.memory(),
+            uptime_secs: instance.get_uptime().as_secs(),
+        })
+    }
+
     pub async fn status(&self) -> ProcessStatus {
         if !self.instance_is_running_or_cleanup().await {
             ProcessStatus::NotRunning
@@ -84,6 +186,68 @@ impl ProcessManager {
         Ok(())
     }
 
+    async fn restart_instance(&self, exited: ExitedInstance) -> Result<()> {
+        let mut mg = self.running_instance.lock().await;
+
+        if mg.is_some() {
+            // started by other means while waiting to restart
+            return Err(Error::ProcessAlreadyRunning);
+        }
+
+        let running = exited.restartable.start().await?;
+        mg.replace(running);
+
+        Ok(())
+    }
+
+    /// Stops the running instance and launches the same server again
+    pub async fn restart_running_instance(&self) -> Result<()> {
+        let mut mg = self.running_instance.lock().await;
+
+        let running = mg.take().ok_or(Error::ProcessNotRunning)?;
+        let restartable = running.stop_to_restart().await?;
+        let savefile = restartable.get_savefile().clone();
+        let started = restartable.start().await.map(|running| {
+            mg.replace(running);
+        });
+        drop(mg);
+
+        // the savefile is only written by the server when it stops, so it's safe to read now
+        self.push_remote_save(&savefile).await;
+        started
+    }
+
+    /// Uptime, player count and restart policy of the running instance, if any
+    pub async fn uptime_status(&self) -> Option<(Duration, u32, RestartPolicy)> {
+        if !self.instance_is_running_or_cleanup().await {
+            return None;
+        }
+
+        let mg = self.running_instance.lock().await;
+        mg.as_ref().map(|instance| {
+            (
+                instance.get_uptime(),
+                instance.get_player_count(),
+                instance.get_restart_policy().clone(),
+            )
+        })
+    }
+
+    /// When the running server was started, which tells it apart from a server that has since
+    /// been stopped or restarted
+    pub async fn running_since(&self) -> Option<Instant> {
+        if !self.instance_is_running_or_cleanup().await {
+            return None;
+        }
+
+        let mg = self.running_instance.lock().await;
+        mg.as_ref().map(|instance| instance.get_started_at())
+    }
+
+    pub fn set_uptime_restart_at(&self, restart_at: Option<DateTime<Utc>>) {
+        *self.uptime_restart_at.lock().unwrap() = restart_at;
+    }
+
     pub async fn stop_instance(&self) -> Option<StoppedInstance> {
         let mut mg = self.running_instance.lock().await;
 
@@ -91,7 +255,11 @@ impl ProcessManager {
             None => None,
             Some(running) => {
                 match running.stop().await {
-                    Ok(s) => Some(s),
+                    Ok(s) => {
+                        drop(mg);
+                        self.push_remote_save(&s.savefile).await;
+                        Some(s)
+                    }
                     Err(e) => {
                         // Could not stop the instance for whatever reason (should never happen).
                         // Tricky to deal with. For now we just drop the instance and hope the
@@ -141,6 +309,34 @@ impl ProcessManager {
         Ok(stopped)
     }
 
+    /// Uploads the savefile of an instance that has stopped to remote storage, if enabled. This
+    /// is done on every way the server can stop, so that the remote copy is never behind.
+    async fn push_remote_save(&self, savefile: &ServerStartSaveFile) {
+        if let (Some(remote_saves), ServerStartSaveFile::Specific(save_name)) =
+            (&self.remote_saves, savefile)
+        {
+            if let Err(e) = remote_saves.push(save_name).await {
+                error!(
+                    "Failed to push savefile {} to remote storage: {:?}",
+                    save_name, e
+                );
+            }
+        }
+    }
+
+    /// Name of the savefile hosted by the running instance, if any
+    pub async fn hosted_savefile(&self) -> Option<String> {
+        if !self.instance_is_running_or_cleanup().await {
+            return None;
+        }
+
+        let mg = self.running_instance.lock().await;
+        mg.as_ref().and_then(|instance| match instance.get_savefile() {
+            ServerStartSaveFile::Specific(name) => Some(name.clone()),
+            ServerStartSaveFile::Latest => None,
+        })
+    }
+
     pub async fn send_rcon_command_to_instance(&self, cmd: &str) -> Result<String> {
         let mg = self.running_instance.lock().await;
         if let Some(instance) = mg.as_ref() {
@@ -159,6 +355,16 @@ impl ProcessManager {
         if let Some(started) = mg.as_ref() {
             ProcessStatus::Running {
                 player_count: started.get_player_count(),
+                ups: started.get_ups(),
+                map_clock: started.get_map_clock(),
+                next_restart: self.uptime_restart_at.lock().unwrap().or_else(|| {
+                    uptime_restart::planned_at(
+                        started.get_restart_policy(),
+                        started.get_uptime(),
+                        started.get_player_count(),
+                        Utc::now(),
+                    )
+                }),
                 server_state: started.get_internal_server_state().await,
             }
         } else {
@@ -183,7 +389,14 @@ impl ProcessManager {
                     // polled result shows process exited, update our status
                     // Manually wait (should be no-op), and drop StoppedInstance
                     warn!("Detected premature process exited");
-                    let _ = mg.take().unwrap().wait().await; // safe since we hold the mutex guard
+                    // safe since we hold the mutex guard
+                    match mg.take().unwrap().into_exited().await {
+                        Ok(exited) => {
+                            // hand over to the supervisor, if there is one
+                            let _ = self.exited_tx.send(exited);
+                        }
+                        Err(e) => error!("Error reaping exited process: {:?}", e),
+                    }
                     false
                 }
             }
@@ -198,13 +411,17 @@ pub enum ProcessStatus {
     NotRunning,
     Running {
         player_count: u32,
+        ups: Option<f32>,
+        map_clock: Option<MapClock>,
+        /// Latest time the server will be restarted for uptime, if it will be
+        next_restart: Option<DateTime<Utc>>,
         server_state: InternalServerState,
     },
 }
 
 pub async fn parse_process_stdout(
     lines_reader: impl AsyncBufRead + Unpin,
-    stdout_handler: Box<dyn HandlerFn>,
+    stdout_handler: Arc<dyn HandlerFn>,
     rcon: Arc<RwLock<Option<Rcon>>>,
     rcon_password: String,
     rcon_bind: SocketAddr,
@@ -260,7 +477,7 @@ pub async fn parse_process_stdout(
                                         warn!("RCON bound port was configured to be {}, but Factorio is using port {} instead!", rcon_bind.port(), port);
                                     }
                                     match Rcon::connect(
-                                        SocketAddrV4::new(Ipv4Addr::LOCALHOST, port),
+                                        util::net::local_connect_addr(SocketAddr::new(rcon_bind.ip(), port)),
                                         &rcon_password,
                                     )
                                     .await
@@ -282,7 +499,7 @@ pub async fn parse_process_stdout(
                     }
 
                     // Pass off to stdout handler
-                    (stdout_handler)(line);
+                    (*stdout_handler)(line);
                 } else {
                     // None means end of stream
                     break;
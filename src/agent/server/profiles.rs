@@ -0,0 +1,237 @@
+use std::path::{Path, PathBuf};
+
+use fctrl::schema::{LaunchProfile, ServerSettingsConfig};
+use lazy_static::lazy_static;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    consts::*,
+    error::{Error, Result},
+    server::{
+        config_file::{ConfigBackend, ConfigFile, ConfigFormat},
+        mods::ModManager,
+        settings::{LaunchSettings, ServerSettings},
+    },
+};
+
+const MANIFEST_FILE_NAME: &str = "profile.toml";
+const SERVER_SETTINGS_FILE_NAME: &str = "server-settings.json";
+const MODS_DIR_NAME: &str = "mods";
+
+lazy_static! {
+    static ref PROFILES_DIR: PathBuf = CONFIG_DIR.join("profiles");
+}
+
+/// Settings of a profile that aren't kept in a file of their own
+#[derive(Deserialize, Serialize)]
+struct ProfileManifest {
+    use_whitelist: bool,
+}
+
+/// A launch profile read from disk, ready to be started with in place of the current settings.
+///
+/// Each profile is a directory under the config dir holding a snapshot of the server settings
+/// and mod directory as they were when the profile was saved.
+pub struct Profile {
+    pub name: String,
+    pub use_whitelist: bool,
+    pub server_settings: ServerSettings,
+    pub mods_path: PathBuf,
+}
+
+impl Profile {
+    pub async fn read(name: &str) -> Result<Option<Profile>> {
+        let dir = profile_dir(name)?;
+        let manifest = match manifest_file(&dir).read().await? {
+            Some(manifest) => manifest,
+            None => return Ok(None),
+        };
+        let config = server_settings_file(&dir)
+            .read()
+            .await?
+            .ok_or(Error::ServerSettingsNotFound)?;
+        Ok(Some(Profile {
+            name: name.to_owned(),
+            use_whitelist: manifest.use_whitelist,
+            server_settings: ServerSettings {
+                config,
+                path: dir.join(SERVER_SETTINGS_FILE_NAME),
+            },
+            mods_path: dir.join(MODS_DIR_NAME),
+        }))
+    }
+
+    pub async fn list() -> Result<Vec<LaunchProfile>> {
+        if !PROFILES_DIR.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut ret = vec![];
+        let mut entries = fs::read_dir(&*PROFILES_DIR).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !is_valid_profile_name(&name) {
+                // a profile being saved
+                continue;
+            }
+            if let Some(profile) = Profile::read(&name).await? {
+                ret.push(profile.summary().await?);
+            }
+        }
+        ret.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(ret)
+    }
+
+    /// Snapshots the current server settings, mods and whitelist flag as the named profile,
+    /// replacing any existing profile with that name
+    pub async fn save_current(name: &str) -> Result<()> {
+        let dir = profile_dir(name)?;
+        let mut config = ServerSettings::read()
+            .await?
+            .ok_or(Error::ServerSettingsNotFound)?
+            .config;
+        // these are written in from the secrets file on each start
+        config.username = None;
+        config.token = None;
+        let manifest = ProfileManifest {
+            use_whitelist: LaunchSettings::read_or_apply_default().await?.use_whitelist,
+        };
+        let mods = ModManager::read_or_apply_default().await?;
+
+        // build the profile alongside so a failed save doesn't leave a broken profile behind
+        let partial_dir = PROFILES_DIR.join(format!(".{}.partial", name));
+        let _ = fs::remove_dir_all(&partial_dir).await;
+        if let Err(e) = write_profile(&partial_dir, &config, &manifest, &mods.path).await {
+            let _ = fs::remove_dir_all(&partial_dir).await;
+            return Err(e);
+        }
+        if dir.is_dir() {
+            fs::remove_dir_all(&dir).await?;
+        }
+        fs::rename(&partial_dir, &dir).await?;
+        info!("Saved current settings as launch profile `{}`", name);
+        Ok(())
+    }
+
+    /// Deletes the named profile, returning whether it existed
+    pub async fn delete(name: &str) -> Result<bool> {
+        let dir = profile_dir(name)?;
+        if !dir.is_dir() {
+            return Ok(false);
+        }
+        fs::remove_dir_all(&dir).await?;
+        info!("Deleted launch profile `{}`", name);
+        Ok(true)
+    }
+
+    /// Replaces the parts of the current settings that the profile holds, returning the server
+    /// settings to start with. Only the mod directory is replaced, as that is all the server is
+    /// given.
+    pub fn apply(
+        self,
+        mods: &mut ModManager,
+        launch_settings: &mut LaunchSettings,
+    ) -> ServerSettings {
+        mods.path = self.mods_path;
+        launch_settings.use_whitelist = self.use_whitelist;
+        self.server_settings
+    }
+
+    async fn summary(&self) -> Result<LaunchProfile> {
+        let mut mod_count = 0;
+        if self.mods_path.is_dir() {
+            let mut entries = fs::read_dir(&self.mods_path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.path().extension().map_or(false, |ext| ext == "zip") {
+                    mod_count += 1;
+                }
+            }
+        }
+        Ok(LaunchProfile {
+            name: self.name.clone(),
+            use_whitelist: self.use_whitelist,
+            public: self.server_settings.config.visibility.public,
+            mod_count,
+        })
+    }
+}
+
+async fn write_profile(
+    dir: &Path,
+    config: &ServerSettingsConfig,
+    manifest: &ProfileManifest,
+    mod_dir: &Path,
+) -> Result<()> {
+    let mods_path = dir.join(MODS_DIR_NAME);
+    fs::create_dir_all(&mods_path).await?;
+    snapshot_mods(mod_dir, &mods_path).await?;
+    server_settings_file(dir).write(config).await?;
+    manifest_file(dir).write(manifest).await
+}
+
+/// Hard links the mod zips, which are never modified in place, falling back to copying if the
+/// profile is on another filesystem. Everything else, such as the mod settings, is copied.
+async fn snapshot_mods(from: &Path, to: &Path) -> Result<()> {
+    let mut entries = fs::read_dir(from).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        let dest = to.join(entry.file_name());
+        let is_zip = path.extension().map_or(false, |ext| ext == "zip");
+        if !is_zip || fs::hard_link(&path, &dest).await.is_err() {
+            fs::copy(&path, &dest).await?;
+        }
+    }
+    Ok(())
+}
+
+fn manifest_file(dir: &Path) -> ConfigFile<ProfileManifest> {
+    ConfigFile::new(
+        "launch profile",
+        ConfigFormat::Toml,
+        ConfigBackend::Filesystem(dir.join(MANIFEST_FILE_NAME)),
+    )
+}
+
+fn server_settings_file(dir: &Path) -> ConfigFile<ServerSettingsConfig> {
+    ConfigFile::new(
+        "launch profile server settings",
+        ConfigFormat::Json,
+        ConfigBackend::Filesystem(dir.join(SERVER_SETTINGS_FILE_NAME)),
+    )
+}
+
+fn profile_dir(name: &str) -> Result<PathBuf> {
+    if is_valid_profile_name(name) {
+        Ok(PROFILES_DIR.join(name))
+    } else {
+        Err(Error::InvalidProfileName(name.to_owned()))
+    }
+}
+
+/// Whether the name can be used for a profile, which is kept in a directory of that name
+fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_plain_profile_names() {
+        assert!(is_valid_profile_name("vanilla-public"));
+        assert!(is_valid_profile_name("modded_friends2"));
+        assert!(!is_valid_profile_name(""));
+        assert!(!is_valid_profile_name("../mods"));
+        assert!(!is_valid_profile_name(".vanilla.partial"));
+        assert!(profile_dir("a/b").is_err());
+    }
+}
@@ -1,16 +1,20 @@
-use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr},
-    path::PathBuf,
-};
+use std::{net::SocketAddr, path::PathBuf};
 
-use fctrl::schema::ServerSettingsConfig;
+use fctrl::schema::{RestartPolicy, ServerSettingsConfig};
 use lazy_static::lazy_static;
-use log::{error, info, warn};
+use log::{error, info};
+use nix::sched::CpuSet;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 
-use crate::{consts::*, error::Result, factorio::Factorio};
+use crate::{
+    consts::*,
+    error::{Error, Result},
+    factorio::Factorio,
+    server::config_file::{ConfigBackend, ConfigFile, ConfigFormat},
+    util,
+};
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct LaunchSettings {
@@ -18,74 +22,65 @@ pub struct LaunchSettings {
     pub rcon_bind: SocketAddr,
     pub rcon_password: String,
     pub use_whitelist: bool,
+    /// CPUs to pin the server process to
+    #[serde(default)]
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// What to do when the server process exits unexpectedly
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
 }
 
 impl LaunchSettings {
     pub async fn read() -> Result<Option<LaunchSettings>> {
-        let path = &*LAUNCH_SETTINGS_PATH;
-        if !path.is_file() {
-            Ok(None)
-        } else {
-            match fs::read_to_string(path).await {
-                Ok(s) => match toml::from_str::<LaunchSettings>(&s) {
-                    Ok(launch_settings) => {
-                        // ignore saved values for the binds, use defaults read from env vars
-                        Ok(Some(LaunchSettings {
-                            rcon_password: launch_settings.rcon_password,
-                            ..Default::default()
-                        }))
-                    }
-                    Err(e) => {
-                        error!("Error parsing launch settings: {:?}", e);
-                        Err(e.into())
-                    }
-                },
-                Err(e) => {
-                    error!("Error reading launch settings: {:?}", e);
-                    Err(e.into())
-                }
-            }
-        }
+        Ok(LAUNCH_SETTINGS_FILE
+            .read()
+            .await?
+            .map(LaunchSettings::with_default_binds))
     }
 
     pub async fn read_or_apply_default() -> Result<LaunchSettings> {
-        match LaunchSettings::read().await? {
-            Some(ls) => Ok(ls),
-            None => {
-                info!("Generating launch settings using defaults");
-                let ls: LaunchSettings = Default::default();
-                if let Err(e) = ls.write().await {
-                    // this is okay
-                    warn!("Failed to write default launch settings to file: {:?}", e);
+        let ls = LAUNCH_SETTINGS_FILE
+            .read_or_apply_default(LaunchSettings::default)
+            .await?;
+        Ok(ls.with_default_binds())
+    }
+
+    /// Reads the launch settings, or the defaults that would be applied, without writing them
+    pub async fn read_or_default() -> Result<LaunchSettings> {
+        let ls = LAUNCH_SETTINGS_FILE
+            .read_or_default(LaunchSettings::default)
+            .await?;
+        Ok(ls.with_default_binds())
+    }
+
+    /// Ignores saved values for the binds, using defaults read from env vars
+    fn with_default_binds(self) -> LaunchSettings {
+        LaunchSettings {
+            rcon_password: self.rcon_password,
+            cpu_affinity: self.cpu_affinity,
+            restart_policy: self.restart_policy,
+            ..Default::default()
+        }
+    }
+
+    /// Builds the CPU mask to pin the server process to, if CPU affinity is configured
+    pub fn cpu_set(&self) -> Result<Option<CpuSet>> {
+        match &self.cpu_affinity {
+            None => Ok(None),
+            Some(cpus) => {
+                let mut cpu_set = CpuSet::new();
+                for cpu in cpus {
+                    cpu_set
+                        .set(*cpu)
+                        .map_err(|_| Error::InvalidCpuAffinity(*cpu))?;
                 }
-                Ok(ls)
+                Ok(Some(cpu_set))
             }
         }
     }
 
     pub async fn write(&self) -> Result<()> {
-        let path = &*LAUNCH_SETTINGS_PATH;
-        if let Err(e) = fs::create_dir_all(path.parent().ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "invalid launch settings path",
-            )
-        })?)
-        .await
-        {
-            error!(
-                "Error creating directory structure for launch settings: {:?}",
-                e
-            );
-            return Err(e.into());
-        }
-
-        if let Err(e) = fs::write(path, toml::to_string(self)?).await {
-            error!("Error writing launch settings: {:?}", e);
-            Err(e.into())
-        } else {
-            Ok(())
-        }
+        LAUNCH_SETTINGS_FILE.write(self).await
     }
 }
 
@@ -103,11 +98,14 @@ impl Default for LaunchSettings {
             .take(12)
             .map(char::from)
             .collect();
+        let bind_ip = util::net::bind_ip_from_env(ENV_FACTORIO_BIND_ADDRESS);
         LaunchSettings {
-            server_bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), server_port),
-            rcon_bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), rcon_port),
+            server_bind: SocketAddr::new(bind_ip, server_port),
+            rcon_bind: SocketAddr::new(bind_ip, rcon_port),
             rcon_password,
             use_whitelist: false,
+            cpu_affinity: None,
+            restart_policy: Default::default(),
         }
     }
 }
@@ -120,46 +118,11 @@ pub struct Secrets {
 
 impl Secrets {
     pub async fn read() -> Result<Option<Secrets>> {
-        let path = &*SECRETS_PATH;
-        if !path.is_file() {
-            Ok(None)
-        } else {
-            match fs::read_to_string(path).await {
-                Ok(s) => match toml::from_str(&s) {
-                    Ok(secrets) => Ok(Some(secrets)),
-                    Err(e) => {
-                        error!("Error parsing secrets file: {:?}", e);
-                        Err(e.into())
-                    }
-                },
-                Err(e) => {
-                    error!("Error reading secrets file: {:?}", e);
-                    Err(e.into())
-                }
-            }
-        }
+        SECRETS_FILE.read().await
     }
 
     pub async fn write(&self) -> Result<()> {
-        let path = &*SECRETS_PATH;
-        if let Err(e) = fs::create_dir_all(path.parent().ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid secrets path")
-        })?)
-        .await
-        {
-            error!(
-                "Error creating directory structure for secrets file: {:?}",
-                e
-            );
-            return Err(e.into());
-        }
-
-        if let Err(e) = fs::write(path, toml::to_string(self)?).await {
-            error!("Error writing secrets file: {:?}", e);
-            Err(e.into())
-        } else {
-            Ok(())
-        }
+        SECRETS_FILE.write(self).await
     }
 }
 
@@ -170,74 +133,45 @@ pub struct AdminList {
 
 impl AdminList {
     pub async fn read() -> Result<Option<AdminList>> {
-        let path = &*ADMIN_LIST_PATH;
-        if !path.is_file() {
-            Ok(None)
-        } else {
-            match fs::read_to_string(path).await {
-                Ok(s) => match serde_json::from_str(&s) {
-                    Ok(list) => Ok(Some(AdminList {
-                        list,
-                        path: path.clone(),
-                    })),
-                    Err(e) => {
-                        error!("Error parsing admin list: {:?}", e);
-                        Err(e.into())
-                    }
-                },
-                Err(e) => {
-                    error!("Error reading admin list: {:?}", e);
-                    Err(e.into())
-                }
-            }
-        }
+        Ok(ADMIN_LIST_FILE.read().await?.map(AdminList::new))
     }
 
     pub async fn read_or_apply_default() -> Result<AdminList> {
-        match AdminList::read().await? {
-            Some(adminlist) => Ok(adminlist),
-            None => {
-                info!("Generating admin list using defaults");
-                let adminlist = AdminList {
-                    list: vec![],
-                    path: ADMIN_LIST_PATH.clone(),
-                };
-                if let Err(e) = adminlist.write().await {
-                    // this is okay
-                    warn!("Failed to write default admin list to file: {:?}", e);
-                }
-                Ok(adminlist)
-            }
-        }
+        Ok(AdminList::new(
+            ADMIN_LIST_FILE.read_or_apply_default(Vec::new).await?,
+        ))
+    }
+
+    pub async fn read_or_default() -> Result<AdminList> {
+        Ok(AdminList::new(
+            ADMIN_LIST_FILE.read_or_default(Vec::new).await?,
+        ))
     }
 
     pub async fn set(list: Vec<String>) -> Result<()> {
-        let al = AdminList {
+        ADMIN_LIST_FILE.write(&list).await
+    }
+
+    fn new(list: Vec<String>) -> AdminList {
+        AdminList {
             list,
             path: ADMIN_LIST_PATH.clone(),
-        };
-        al.write().await
+        }
     }
+}
 
-    pub async fn write(&self) -> Result<()> {
-        if let Err(e) = fs::create_dir_all(self.path.parent().ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid admin list path")
-        })?)
-        .await
-        {
-            error!("Error creating directory structure for admin list: {:?}", e);
-            return Err(e.into());
-        }
+/// Players added to and removed from a player list such as the ban list
+#[derive(Debug, Default, PartialEq)]
+pub struct PlayerListDelta {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
 
-        if let Err(e) = fs::write(&self.path, serde_json::to_string_pretty(&self.list)?).await {
-            error!(
-                "Error writing admin list to {}: {:?}",
-                self.path.display(),
-                e
-            );
-            Err(e.into())
-        } else {
-            Ok(())
+impl PlayerListDelta {
+    pub fn between(old: &[String], new: &[String]) -> PlayerListDelta {
+        PlayerListDelta {
+            added: new.iter().filter(|p| !old.contains(p)).cloned().collect(),
+            removed: old.iter().filter(|p| !new.contains(p)).cloned().collect(),
         }
     }
 }
@@ -249,70 +183,27 @@ pub struct BanList {
 
 impl BanList {
     pub async fn read() -> Result<Option<BanList>> {
-        let path = &*BAN_LIST_PATH;
-        if !path.is_file() {
-            Ok(None)
-        } else {
-            match fs::read_to_string(path).await {
-                Ok(s) => match serde_json::from_str(&s) {
-                    Ok(list) => Ok(Some(BanList {
-                        list,
-                        path: path.clone(),
-                    })),
-                    Err(e) => {
-                        error!("Error parsing ban list: {:?}", e);
-                        Err(e.into())
-                    }
-                },
-                Err(e) => {
-                    error!("Error reading ban list: {:?}", e);
-                    Err(e.into())
-                }
-            }
-        }
+        Ok(BAN_LIST_FILE.read().await?.map(BanList::new))
     }
 
     pub async fn read_or_apply_default() -> Result<BanList> {
-        match BanList::read().await? {
-            Some(banlist) => Ok(banlist),
-            None => {
-                info!("Generating ban list using defaults");
-                let banlist = BanList {
-                    list: vec![],
-                    path: BAN_LIST_PATH.clone(),
-                };
-                if let Err(e) = banlist.write().await {
-                    // this is okay
-                    warn!("Failed to write default ban list to file: {:?}", e);
-                }
-                Ok(banlist)
-            }
-        }
+        Ok(BanList::new(
+            BAN_LIST_FILE.read_or_apply_default(Vec::new).await?,
+        ))
     }
 
-    pub async fn set(list: Vec<String>) -> Result<()> {
-        let bl = BanList {
-            list,
-            path: BAN_LIST_PATH.clone(),
-        };
-        bl.write().await
+    pub async fn read_or_default() -> Result<BanList> {
+        Ok(BanList::new(BAN_LIST_FILE.read_or_default(Vec::new).await?))
     }
 
-    pub async fn write(&self) -> Result<()> {
-        if let Err(e) = fs::create_dir_all(self.path.parent().ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid ban list path")
-        })?)
-        .await
-        {
-            error!("Error creating directory structure for ban list: {:?}", e);
-            return Err(e.into());
-        }
+    pub async fn set(list: Vec<String>) -> Result<()> {
+        BAN_LIST_FILE.write(&list).await
+    }
 
-        if let Err(e) = fs::write(&self.path, serde_json::to_string_pretty(&self.list)?).await {
-            error!("Error writing ban list to {}: {:?}", self.path.display(), e);
-            Err(e.into())
-        } else {
-            Ok(())
+    fn new(list: Vec<String>) -> BanList {
+        BanList {
+            list,
+            path: BAN_LIST_PATH.clone(),
         }
     }
 }
@@ -324,77 +215,33 @@ pub struct WhiteList {
 
 impl WhiteList {
     pub async fn read() -> Result<Option<WhiteList>> {
-        let path = &*WHITE_LIST_PATH;
-        if !path.is_file() {
-            Ok(None)
-        } else {
-            match fs::read_to_string(path).await {
-                Ok(s) => match serde_json::from_str(&s) {
-                    Ok(list) => Ok(Some(WhiteList {
-                        list,
-                        path: path.clone(),
-                    })),
-                    Err(e) => {
-                        error!("Error parsing white list: {:?}", e);
-                        Err(e.into())
-                    }
-                },
-                Err(e) => {
-                    error!("Error reading white list: {:?}", e);
-                    Err(e.into())
-                }
-            }
-        }
+        Ok(WHITE_LIST_FILE.read().await?.map(WhiteList::new))
     }
 
     pub async fn read_or_apply_default() -> Result<WhiteList> {
-        match WhiteList::read().await? {
-            Some(whitelist) => Ok(whitelist),
-            None => {
-                info!("Generating white list using defaults");
-                let whitelist = WhiteList {
-                    list: vec![],
-                    path: WHITE_LIST_PATH.clone(),
-                };
-                if let Err(e) = whitelist.write().await {
-                    // this is okay
-                    warn!("Failed to write default white list to file: {:?}", e);
-                }
-                Ok(whitelist)
-            }
-        }
+        Ok(WhiteList::new(
+            WHITE_LIST_FILE.read_or_apply_default(Vec::new).await?,
+        ))
     }
 
-    pub async fn set(list: Vec<String>) -> Result<()> {
-        let wl = WhiteList {
-            list,
-            path: WHITE_LIST_PATH.clone(),
-        };
-        wl.write().await
+    pub async fn read_or_default() -> Result<WhiteList> {
+        Ok(WhiteList::new(
+            WHITE_LIST_FILE.read_or_default(Vec::new).await?,
+        ))
     }
 
-    pub async fn write(&self) -> Result<()> {
-        if let Err(e) = fs::create_dir_all(self.path.parent().ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid white list path")
-        })?)
-        .await
-        {
-            error!("Error creating directory structure for white list: {:?}", e);
-            return Err(e.into());
-        }
+    pub async fn set(list: Vec<String>) -> Result<()> {
+        WHITE_LIST_FILE.write(&list).await
+    }
 
-        if let Err(e) = fs::write(&self.path, serde_json::to_string_pretty(&self.list)?).await {
-            error!(
-                "Error writing white list to {}: {:?}",
-                self.path.display(),
-                e
-            );
-            Err(e.into())
-        } else {
-            Ok(())
+    fn new(list: Vec<String>) -> WhiteList {
+        WhiteList {
+            list,
+            path: WHITE_LIST_PATH.clone(),
         }
     }
 }
+
 pub struct ServerSettings {
     pub config: ServerSettingsConfig,
     pub path: PathBuf,
@@ -402,29 +249,7 @@ pub struct ServerSettings {
 
 impl ServerSettings {
     pub async fn read() -> Result<Option<ServerSettings>> {
-        let path = &*SERVER_SETTINGS_PATH;
-        if !path.is_file() {
-            Ok(None)
-        } else {
-            match fs::read_to_string(path).await {
-                Ok(s) => {
-                    match serde_json::from_str(&s) {
-                        Ok(config) => Ok(Some(ServerSettings {
-                            config,
-                            path: path.clone(),
-                        })),
-                        Err(e) => {
-                            error!("Error deserialising server settings: {:?}", e);
-                            Err(e.into())
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("Error reading server settings: {:?}", e);
-                    Err(e.into())
-                }
-            }
-        }
+        Ok(SERVER_SETTINGS_FILE.read().await?.map(ServerSettings::new))
     }
 
     pub async fn read_or_apply_default(installation: &Factorio) -> Result<ServerSettings> {
@@ -432,14 +257,7 @@ impl ServerSettings {
             Some(ls) => Ok(ls),
             None => {
                 info!("Generating server settings using defaults");
-                let mut config = ServerSettings::read_default_server_settings(installation).await?;
-                // clear the default empty secrets
-                config.username = None;
-                config.token = None;
-                let s = ServerSettings {
-                    config,
-                    path: SERVER_SETTINGS_PATH.clone(),
-                };
+                let s = ServerSettings::default_for(installation).await?;
                 if let Err(e) = s.write().await {
                     error!("Failed to write default server settings to file: {:?}", e);
                     Err(e)
@@ -450,44 +268,60 @@ impl ServerSettings {
         }
     }
 
+    /// Reads the server settings, or the defaults that would be applied, without writing them
+    pub async fn read_or_default(installation: &Factorio) -> Result<ServerSettings> {
+        match ServerSettings::read().await? {
+            Some(ss) => Ok(ss),
+            None => ServerSettings::default_for(installation).await,
+        }
+    }
+
     pub async fn set(config: ServerSettingsConfig) -> Result<()> {
-        let ss = ServerSettings {
-            config,
-            path: SERVER_SETTINGS_PATH.clone(),
-        };
-        ss.write().await
+        SERVER_SETTINGS_FILE.write(&config).await
     }
 
+    /// Writes to `self.path`, which is the server settings of a launch profile if started with one
     pub async fn write(&self) -> Result<()> {
-        if let Err(e) = fs::create_dir_all(self.path.parent().ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "invalid server settings path",
-            )
-        })?)
+        ConfigFile::new(
+            "server settings",
+            ConfigFormat::Json,
+            ConfigBackend::Filesystem(self.path.clone()),
+        )
+        .write(&self.config)
         .await
-        {
-            error!(
-                "Error creating directory structure for server settings: {:?}",
-                e
-            );
-            return Err(e.into());
-        }
+    }
+
+    /// Fills in the factorio.com credentials from the secrets, pointing at a generated copy for
+    /// the server to read, which must be written before the server starts. The credentials are
+    /// never written back to the config file, so they stay out of profiles and anything else
+    /// copied from it.
+    pub fn with_secrets(mut self, secrets: Option<Secrets>) -> ServerSettings {
+        let (username, token) = match secrets {
+            Some(s) => (Some(s.username), Some(s.token)),
+            None => (None, None),
+        };
+        self.config.username = username;
+        self.config.token = token;
+        self.path = GENERATED_SERVER_SETTINGS_PATH.clone();
+        self
+    }
 
-        let pretty_out = serde_json::to_string_pretty(&self.config)?;
-
-        if let Err(e) = fs::write(&self.path, pretty_out).await {
-            error!(
-                "Error writing server settings to {}: {:?}",
-                self.path.display(),
-                e
-            );
-            Err(e.into())
-        } else {
-            Ok(())
+    fn new(config: ServerSettingsConfig) -> ServerSettings {
+        ServerSettings {
+            config,
+            path: SERVER_SETTINGS_PATH.clone(),
         }
     }
 
+    /// Server settings from the example shipped with the installation
+    async fn default_for(installation: &Factorio) -> Result<ServerSettings> {
+        let mut config = ServerSettings::read_default_server_settings(installation).await?;
+        // clear the default empty secrets
+        config.username = None;
+        config.token = None;
+        Ok(ServerSettings::new(config))
+    }
+
     async fn read_default_server_settings(installation: &Factorio) -> Result<ServerSettingsConfig> {
         let path = installation
             .path
@@ -509,12 +343,55 @@ lazy_static! {
     static ref ADMIN_LIST_PATH: PathBuf = CONFIG_DIR.join("server-adminlist.json");
     static ref BAN_LIST_PATH: PathBuf = CONFIG_DIR.join("server-banlist.json");
     static ref SERVER_SETTINGS_PATH: PathBuf = CONFIG_DIR.join("server-settings.json");
+    static ref GENERATED_SERVER_SETTINGS_PATH: PathBuf =
+        ROAMING_DATA_DIR.join("server-settings.generated.json");
     static ref SECRETS_PATH: PathBuf = CONFIG_DIR.join("secrets.toml");
     static ref WHITE_LIST_PATH: PathBuf = CONFIG_DIR.join("server-whitelist.json");
+    static ref LAUNCH_SETTINGS_FILE: ConfigFile<LaunchSettings> = ConfigFile::new(
+        "launch settings",
+        ConfigFormat::Toml,
+        ConfigBackend::Filesystem(LAUNCH_SETTINGS_PATH.clone()),
+    );
+    static ref ADMIN_LIST_FILE: ConfigFile<Vec<String>> = ConfigFile::new(
+        "admin list",
+        ConfigFormat::Json,
+        ConfigBackend::EnvSeeded {
+            path: ADMIN_LIST_PATH.clone(),
+            var: ENV_AGENT_SEED_ADMIN_LIST,
+        },
+    );
+    static ref BAN_LIST_FILE: ConfigFile<Vec<String>> = ConfigFile::new(
+        "ban list",
+        ConfigFormat::Json,
+        ConfigBackend::EnvSeeded {
+            path: BAN_LIST_PATH.clone(),
+            var: ENV_AGENT_SEED_BAN_LIST,
+        },
+    );
+    static ref SERVER_SETTINGS_FILE: ConfigFile<ServerSettingsConfig> = ConfigFile::new(
+        "server settings",
+        ConfigFormat::Json,
+        ConfigBackend::Filesystem(SERVER_SETTINGS_PATH.clone()),
+    );
+    static ref SECRETS_FILE: ConfigFile<Secrets> = ConfigFile::new(
+        "secrets file",
+        ConfigFormat::Toml,
+        ConfigBackend::Filesystem(SECRETS_PATH.clone()),
+    );
+    static ref WHITE_LIST_FILE: ConfigFile<Vec<String>> = ConfigFile::new(
+        "white list",
+        ConfigFormat::Json,
+        ConfigBackend::EnvSeeded {
+            path: WHITE_LIST_PATH.clone(),
+            var: ENV_AGENT_SEED_WHITE_LIST,
+        },
+    );
 }
 
 #[cfg(test)]
 mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
     use super::*;
 
     #[test]
@@ -527,6 +404,8 @@ mod tests {
             rcon_bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 54321),
             rcon_password: "password123".to_owned(),
             use_whitelist: false,
+            cpu_affinity: None,
+            restart_policy: RestartPolicy::default(),
         };
         let string_from_ls = toml::to_string(&ls)?;
 
@@ -535,6 +414,10 @@ server_bind = "0.0.0.0:12345"
 rcon_bind = "127.0.0.1:54321"
 rcon_password = "password123"
 use_whitelist = false
+
+[restart_policy]
+max_retries = 0
+backoff_secs = 10
 "#
         .to_owned();
         let ls_from_string = toml::from_str(&string)?;
@@ -544,4 +427,18 @@ use_whitelist = false
 
         Ok(())
     }
+
+    #[test]
+    fn player_list_delta_between_lists() {
+        let old = vec!["alice".to_owned(), "bob".to_owned()];
+        let new = vec!["bob".to_owned(), "carol".to_owned()];
+        assert_eq!(
+            PlayerListDelta::between(&old, &new),
+            PlayerListDelta {
+                added: vec!["carol".to_owned()],
+                removed: vec!["alice".to_owned()],
+            }
+        );
+        assert_eq!(PlayerListDelta::between(&old, &old), PlayerListDelta::default());
+    }
 }
@@ -0,0 +1,118 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref TIME_COMPONENT_RE: Regex = Regex::new(r"(\d+) (day|hour|minute|second)s?").unwrap();
+}
+
+/// The rate the game runs at when it keeps up
+pub const TARGET_UPS: f32 = 60.0;
+
+/// RCON command to measure game time with. Unlike a Lua command, this leaves achievements enabled.
+pub const MEASUREMENT_COMMAND: &str = "/time";
+
+/// How often game time is measured
+pub const MEASUREMENT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Game time is only reported to the second, so measurements are taken over a long enough window
+/// to keep the UPS accurate to about one update per second
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Rolling measurement of server updates per second, from the game time elapsed against wall
+/// clock time
+#[derive(Default)]
+pub struct UpsTracker {
+    /// Wall clock time of each measurement and the game time in seconds at that point
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl UpsTracker {
+    pub fn record(&mut self, at: Instant, game_secs: u64) {
+        // game time going backwards means a different save was loaded
+        if matches!(self.samples.back(), Some((_, last)) if *last > game_secs) {
+            self.samples.clear();
+        }
+        self.samples.push_back((at, game_secs));
+        while let Some((oldest, _)) = self.samples.front() {
+            if at.duration_since(*oldest) > WINDOW {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Game time in seconds at the latest measurement
+    pub fn game_secs(&self) -> Option<u64> {
+        self.samples.back().map(|(_, game_secs)| *game_secs)
+    }
+
+    /// UPS over the measurement window. None until there are enough measurements, or while the
+    /// game is paused, e.g. when no players are online.
+    pub fn ups(&self) -> Option<f32> {
+        let (first_at, first_secs) = self.samples.front()?;
+        let (last_at, last_secs) = self.samples.back()?;
+        let wall_secs = last_at.duration_since(*first_at).as_secs_f32();
+        if wall_secs < MEASUREMENT_INTERVAL.as_secs_f32() || last_secs == first_secs {
+            return None;
+        }
+        Some((last_secs - first_secs) as f32 / wall_secs * TARGET_UPS)
+    }
+}
+
+/// Parses the output of the `/time` command, e.g. "2 hours, 14 minutes and 3 seconds", into a
+/// number of seconds
+pub fn parse_time_output(output: &str) -> Option<u64> {
+    let mut total = None;
+    for captures in TIME_COMPONENT_RE.captures_iter(output) {
+        let value: u64 = captures[1].parse().ok()?;
+        let unit_secs = match &captures[2] {
+            "day" => 24 * 60 * 60,
+            "hour" => 60 * 60,
+            "minute" => 60,
+            _ => 1,
+        };
+        total = Some(total.unwrap_or(0) + value * unit_secs);
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_time_output() {
+        assert_eq!(
+            parse_time_output("1 day, 2 hours, 14 minutes and 3 seconds"),
+            Some(94443)
+        );
+        assert_eq!(parse_time_output("1 minute and 1 second"), Some(61));
+        assert_eq!(parse_time_output("Unknown command"), None);
+    }
+
+    #[test]
+    fn ups_from_game_time_over_window() {
+        let start = Instant::now();
+        let mut tracker = UpsTracker::default();
+        tracker.record(start, 100);
+        assert_eq!(tracker.ups(), None);
+
+        // game running at half speed
+        for i in 1..=6 {
+            tracker.record(start + MEASUREMENT_INTERVAL * i, 100 + 5 * i as u64);
+        }
+        assert_eq!(tracker.ups(), Some(30.0));
+
+        // paused
+        let paused_at = start + MEASUREMENT_INTERVAL * 20;
+        tracker.record(paused_at, 200);
+        tracker.record(paused_at + MEASUREMENT_INTERVAL, 200);
+        assert_eq!(tracker.ups(), None);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use fctrl::{
+    game_message::GameMessage,
+    schema::{MissedRunPolicy, RestartPolicy},
+};
+use log::info;
+
+use crate::{
+    countdown,
+    error::Result,
+    scheduler::{JobDefinition, Scheduler},
+    server::proc::ProcessManager,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time from deciding on a restart to restarting, over which players are warned in-game
+const COUNTDOWN: Duration = countdown::WARNINGS[0];
+
+/// Registers a job which restarts the server once it has been running for as long as its restart
+/// policy allows, as long-running servers slowly accumulate memory.
+///
+/// Past the uptime limit, the restart waits for the server to empty, up to the policy's maximum
+/// delay. Players are warned in-game in the minutes leading up to the restart, and the game is
+/// saved just before it.
+pub async fn register(proc_manager: Arc<ProcessManager>, scheduler: &Scheduler) {
+    let definition = JobDefinition {
+        name: "uptime_restart",
+        interval: CHECK_INTERVAL,
+        jitter: Duration::ZERO,
+        missed_run: MissedRunPolicy::Skip,
+    };
+    scheduler
+        .register(definition, move || {
+            let proc_manager = Arc::clone(&proc_manager);
+            Box::pin(async move {
+                match proc_manager.uptime_status().await {
+                    Some((uptime, player_count, policy))
+                        if is_due(&policy, uptime, player_count) =>
+                    {
+                        restart(&proc_manager, uptime).await
+                    }
+                    _ => Ok(()),
+                }
+            })
+        })
+        .await;
+}
+
+/// Latest time the policy would restart a server with the given uptime, if it restarts for
+/// uptime at all. The restart may come sooner if the server empties.
+pub fn planned_at(
+    policy: &RestartPolicy,
+    uptime: Duration,
+    player_count: u32,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let limit = uptime_limit(policy)?;
+    let countdown_from = if player_count == 0 {
+        limit
+    } else {
+        limit + max_delay(policy)
+    };
+    let until_restart = countdown_from.saturating_sub(uptime) + COUNTDOWN;
+    Some(now + chrono::Duration::from_std(until_restart).ok()?)
+}
+
+/// Whether to start counting down to a restart
+fn is_due(policy: &RestartPolicy, uptime: Duration, player_count: u32) -> bool {
+    match uptime_limit(policy) {
+        Some(limit) => {
+            uptime >= limit && (player_count == 0 || uptime >= limit + max_delay(policy))
+        }
+        None => false,
+    }
+}
+
+fn uptime_limit(policy: &RestartPolicy) -> Option<Duration> {
+    match policy.uptime_restart_hours {
+        0 => None,
+        hours => Some(Duration::from_secs(hours as u64 * 60 * 60)),
+    }
+}
+
+fn max_delay(policy: &RestartPolicy) -> Duration {
+    Duration::from_secs(policy.uptime_restart_max_delay_mins as u64 * 60)
+}
+
+async fn restart(proc_manager: &ProcessManager, uptime: Duration) -> Result<()> {
+    let restart_at = Utc::now() + chrono::Duration::from_std(COUNTDOWN).unwrap_or_default();
+    info!(
+        "Server has been running for {}h, restarting at {}",
+        uptime.as_secs() / 3600,
+        restart_at
+    );
+    proc_manager.set_uptime_restart_at(Some(restart_at));
+    let result = count_down_and_restart(proc_manager, restart_at).await;
+    proc_manager.set_uptime_restart_at(None);
+    result
+}
+
+async fn count_down_and_restart(
+    proc_manager: &ProcessManager,
+    restart_at: DateTime<Utc>,
+) -> Result<()> {
+    let warning = |time_left: &str| GameMessage::UptimeRestartSoon { time_left }.render();
+    if !countdown::count_down(proc_manager, restart_at, warning).await {
+        info!("Cancelled uptime restart");
+        return Ok(());
+    }
+
+    countdown::save(proc_manager).await;
+    proc_manager.restart_running_instance().await?;
+    info!("Restarted server after uptime limit");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_server_to_empty_up_to_max_delay() {
+        let policy = RestartPolicy {
+            uptime_restart_hours: 24,
+            uptime_restart_max_delay_mins: 60,
+            ..Default::default()
+        };
+        let hours = |h: u64| Duration::from_secs(h * 60 * 60);
+        assert!(!is_due(&policy, hours(23), 0));
+        assert!(is_due(&policy, hours(24), 0));
+        assert!(!is_due(&policy, hours(24), 3));
+        assert!(is_due(&policy, hours(25), 3));
+        assert!(!is_due(&RestartPolicy::default(), hours(1000), 0));
+
+        let now = Utc::now();
+        assert_eq!(
+            planned_at(&policy, hours(20), 3, now),
+            Some(now + chrono::Duration::minutes(5 * 60 + 5))
+        );
+        assert_eq!(
+            planned_at(&policy, hours(30), 0, now),
+            Some(now + chrono::Duration::minutes(5))
+        );
+    }
+}
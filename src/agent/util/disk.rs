@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use fctrl::schema::{DiskUsage, InsufficientDiskSpace};
+use log::{debug, warn};
+use sysinfo::{Disk, Disks};
+
+/// Free space needed to download and extract a Factorio headless installation
+pub const VERSION_INSTALL_REQUIRED_BYTES: u64 = 1024 * 1024 * 1024;
+/// Free space needed to generate a new savefile
+pub const SAVE_CREATE_REQUIRED_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Free space on the filesystem holding the path, which doesn't need to exist yet. Returns None
+/// if the filesystem can't be determined.
+pub fn available_space(path: impl AsRef<Path>) -> Option<u64> {
+    let disks = Disks::new_with_refreshed_list();
+    find_disk(&disks, path).map(|d| d.available_space())
+}
+
+/// Usage of the filesystems holding each of the paths, each filesystem reported once
+pub fn usage<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Vec<DiskUsage> {
+    let disks = Disks::new_with_refreshed_list();
+    let mut usage: Vec<DiskUsage> = vec![];
+    for disk in paths.into_iter().filter_map(|p| find_disk(&disks, p)) {
+        let path = disk.mount_point().display().to_string();
+        if !usage.iter().any(|u| u.path == path) {
+            usage.push(DiskUsage {
+                path,
+                total_bytes: disk.total_space(),
+                available_bytes: disk.available_space(),
+            });
+        }
+    }
+    usage
+}
+
+/// The disk mounted closest to the path, which doesn't need to exist yet
+fn find_disk(disks: &Disks, path: impl AsRef<Path>) -> Option<&Disk> {
+    let path = std::env::current_dir().ok()?.join(path);
+    let path = path
+        .ancestors()
+        .find_map(|p| std::fs::canonicalize(p).ok())?;
+    disks
+        .iter()
+        .filter(|d| path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+}
+
+/// Checks there is enough free space for an operation writing to the path, before it starts.
+/// The check is skipped if the free space can't be determined.
+pub fn check_available_space(
+    path: impl AsRef<Path>,
+    required_bytes: u64,
+) -> std::result::Result<(), InsufficientDiskSpace> {
+    let path = path.as_ref();
+    match available_space(path) {
+        Some(available_bytes) if available_bytes < required_bytes => {
+            warn!(
+                "Insufficient disk space for {}: {} bytes available, {} bytes required",
+                path.display(),
+                available_bytes,
+                required_bytes
+            );
+            Err(InsufficientDiskSpace {
+                path: path.display().to_string(),
+                required_bytes,
+                available_bytes,
+            })
+        }
+        Some(_) => Ok(()),
+        None => {
+            debug!(
+                "Couldn't determine free space for {}, skipping check",
+                path.display()
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_check_space_for_path_not_yet_created() {
+        let path = std::env::temp_dir().join("fctrl_disk_check").join("nested");
+        assert!(available_space(&path).is_some());
+        assert!(check_available_space(&path, 0).is_ok());
+        assert!(check_available_space(&path, u64::MAX).is_err());
+    }
+}
@@ -1,29 +1,144 @@
-use bytes::Bytes;
-use log::{debug, error};
+use bytes::{Bytes, BytesMut};
+use lazy_static::lazy_static;
+use log::{debug, error, warn};
+use reqwest::{header, StatusCode, Url};
 use std::time::Duration;
 use std::{path::PathBuf, time::SystemTime};
-use tokio::fs;
+use tokio::{
+    fs,
+    sync::{mpsc, Semaphore},
+};
 
-use crate::error::Result;
+use crate::{consts::ENV_AGENT_DOWNLOAD_CONCURRENCY, error::Result};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// Limits the number of downloads in flight across the agent, e.g. when applying a large mod
+    /// list
+    static ref DOWNLOAD_PERMITS: Semaphore = {
+        let concurrency = match std::env::var(ENV_AGENT_DOWNLOAD_CONCURRENCY) {
+            Ok(s) => s.parse().unwrap_or_else(|_| {
+                warn!(
+                    "Invalid {}, defaulting to {}",
+                    ENV_AGENT_DOWNLOAD_CONCURRENCY, DEFAULT_CONCURRENCY
+                );
+                DEFAULT_CONCURRENCY
+            }),
+            Err(_) => DEFAULT_CONCURRENCY,
+        };
+        Semaphore::new(concurrency.max(1))
+    };
+}
+
+/// Bytes received so far by an in-progress download
+#[derive(Clone, Copy, Debug)]
+pub struct DownloadProgress {
+    pub downloaded_bytes: u64,
+    /// Size of the download, if the server reported it
+    pub total_bytes: Option<u64>,
+}
 
 pub async fn download<T: reqwest::IntoUrl>(id: &str, uri: T) -> Result<Bytes> {
+    download_with_progress(id, uri, None).await
+}
+
+/// Like `download`, additionally sending progress after every chunk received. Nothing is sent on
+/// a cache hit.
+///
+/// Transient failures are retried with exponential backoff. If the server supports range
+/// requests, a retry resumes from the last byte received instead of starting over.
+pub async fn download_with_progress<T: reqwest::IntoUrl>(
+    id: &str,
+    uri: T,
+    progress_tx: Option<mpsc::UnboundedSender<DownloadProgress>>,
+) -> Result<Bytes> {
     if let Some(cached_bytes) = read_from_cache(id).await? {
         debug!("Cache hit on {}", id);
         return Ok(cached_bytes);
     }
 
-    match reqwest::get(uri).await {
-        Ok(response) => match response.error_for_status() {
-            Ok(response) => {
-                let bytes = response.bytes().await?;
-                debug!("Download succesful, downloaded {} bytes", bytes.len());
-                write_to_cache(id, &bytes).await?;
-                Ok(bytes)
+    let uri = uri.into_url()?;
+    let _permit = DOWNLOAD_PERMITS
+        .acquire()
+        .await
+        .expect("download semaphore should never be closed");
+    let client = super::http::client();
+    let mut buf = BytesMut::new();
+    let mut attempt = 1;
+    loop {
+        match download_attempt(client, uri.clone(), &mut buf, &progress_tx).await {
+            Ok(()) => break,
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                let backoff = backoff(attempt);
+                // strip the url, as mod portal urls contain credentials
+                warn!(
+                    "Download of {} failed on attempt {}, retrying in {}s: {}",
+                    id,
+                    attempt,
+                    backoff.as_secs(),
+                    e.without_url()
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
             }
-            Err(e) => Err(e.into()),
-        },
-        Err(e) => Err(e.into()),
+            Err(e) => return Err(e.without_url().into()),
+        }
+    }
+
+    let bytes = buf.freeze();
+    debug!("Download succesful, downloaded {} bytes", bytes.len());
+    write_to_cache(id, &bytes).await?;
+    Ok(bytes)
+}
+
+/// Downloads into `buf`, resuming after any bytes already in it
+async fn download_attempt(
+    client: &reqwest::Client,
+    uri: Url,
+    buf: &mut BytesMut,
+    progress_tx: &Option<mpsc::UnboundedSender<DownloadProgress>>,
+) -> reqwest::Result<()> {
+    let mut request = client.get(uri);
+    if !buf.is_empty() {
+        request = request.header(header::RANGE, format!("bytes={}-", buf.len()));
+    }
+    let mut response = request.send().await?.error_for_status()?;
+    if response.status() == StatusCode::PARTIAL_CONTENT {
+        debug!("Resuming download from byte {}", buf.len());
+    } else {
+        // range not supported, start over
+        buf.clear();
+    }
+
+    let total_bytes = response.content_length().map(|len| len + buf.len() as u64);
+    if let Some(total_bytes) = total_bytes {
+        buf.reserve((total_bytes as usize).saturating_sub(buf.len()));
     }
+    while let Some(chunk) = response.chunk().await? {
+        buf.extend_from_slice(&chunk);
+        if let Some(tx) = progress_tx {
+            // receiver may have lost interest, which doesn't affect the download
+            let _ = tx.send(DownloadProgress {
+                downloaded_bytes: buf.len() as u64,
+                total_bytes,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn is_transient(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        None => e.is_timeout() || e.is_connect() || e.is_body(),
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF * 2u32.pow(attempt - 1)
 }
 
 pub async fn purge(id: &str) -> Result<()> {
@@ -120,4 +235,11 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn backoff_doubles_each_attempt() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(8));
+    }
 }
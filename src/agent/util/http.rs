@@ -0,0 +1,67 @@
+use std::sync::OnceLock;
+
+use log::{error, info};
+
+use crate::{consts::*, error::Result};
+
+/// Environment variables for outbound proxies, as honoured by reqwest
+const PROXY_ENV_VARS: [&str; 6] = [
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Builds the shared client from the environment. This is done at startup so that an invalid CA
+/// certificate is reported straight away, instead of on the first download.
+pub fn init() -> Result<()> {
+    let client = build_client()?;
+    let _ = CLIENT.set(client);
+    Ok(())
+}
+
+/// Shared client for all outbound HTTP from the agent, which goes through the proxy given by the
+/// usual HTTPS_PROXY / HTTP_PROXY / NO_PROXY variables, and trusts any additional CA certificates
+/// configured
+pub fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(|| {
+        build_client().unwrap_or_else(|e| {
+            error!(
+                "Failed to build HTTP client, falling back to defaults: {:?}",
+                e
+            );
+            reqwest::Client::new()
+        })
+    })
+}
+
+fn build_client() -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+
+    // proxy urls may contain credentials, so only log which variable is used
+    if let Some(var) = PROXY_ENV_VARS
+        .iter()
+        .find(|var| std::env::var_os(var).is_some())
+    {
+        info!("Outbound HTTP will use the proxy set in {}", var);
+    }
+
+    if let Ok(path) = std::env::var(ENV_AGENT_CA_CERT_FILE) {
+        let pem = std::fs::read(&path)?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)?;
+        info!(
+            "Trusting {} additional CA certificates from {}",
+            certs.len(),
+            path
+        );
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(builder.build()?)
+}
@@ -1,2 +1,6 @@
+pub mod disk;
 pub mod downloader;
+pub mod http;
+pub mod net;
+pub mod s3;
 pub mod saves;
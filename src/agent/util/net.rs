@@ -0,0 +1,47 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use log::warn;
+
+/// Address to bind listeners to, read from the environment variable. Defaults to all IPv4
+/// interfaces; "::" binds to all interfaces for both IPv4 and IPv6 on a dual-stack host.
+pub fn bind_ip_from_env(env_var: &str) -> IpAddr {
+    match std::env::var(env_var) {
+        Ok(s) => s.parse().unwrap_or_else(|e| {
+            warn!("Invalid {} '{}', binding to 0.0.0.0: {:?}", env_var, s, e);
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        }),
+        Err(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    }
+}
+
+/// Address to connect to a local listener bound to the given address, using the loopback address
+/// of the same family if it is bound to all interfaces
+pub fn local_connect_addr(bind: SocketAddr) -> SocketAddr {
+    let ip = match bind.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    SocketAddr::new(ip, bind.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connects_to_loopback_of_same_family() {
+        assert_eq!(
+            local_connect_addr("0.0.0.0:27015".parse().unwrap()),
+            "127.0.0.1:27015".parse().unwrap()
+        );
+        assert_eq!(
+            local_connect_addr("[::]:27015".parse().unwrap()),
+            "[::1]:27015".parse().unwrap()
+        );
+        assert_eq!(
+            local_connect_addr("10.0.0.5:27015".parse().unwrap()),
+            "10.0.0.5:27015".parse().unwrap()
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::{path::Path as FsPath, sync::Arc};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStreamExt};
+use log::warn;
+use object_store::{aws::AmazonS3Builder, buffered::BufWriter, path::Path, ObjectStore};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{consts::*, error::Result};
+
+/// Connection details for an S3-compatible bucket
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    /// Base URL of the service, e.g. https://s3.us-east-1.amazonaws.com or a MinIO instance
+    pub endpoint: url::Url,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+impl S3Config {
+    /// Reads the bucket configuration from the environment, if the endpoint, bucket and
+    /// credentials are all set
+    pub fn from_env() -> Option<S3Config> {
+        let endpoint = match std::env::var(ENV_AGENT_S3_ENDPOINT).ok()?.parse() {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                warn!("Invalid {}, ignoring: {:?}", ENV_AGENT_S3_ENDPOINT, e);
+                return None;
+            }
+        };
+        Some(S3Config {
+            endpoint,
+            bucket: std::env::var(ENV_AGENT_S3_BUCKET).ok()?,
+            region: std::env::var(ENV_AGENT_S3_REGION).unwrap_or_else(|_| "us-east-1".to_owned()),
+            access_key_id: std::env::var(ENV_AGENT_S3_ACCESS_KEY_ID).ok()?,
+            secret_access_key: std::env::var(ENV_AGENT_S3_SECRET_ACCESS_KEY).ok()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct S3Object {
+    pub key: String,
+    pub last_modified: DateTime<Utc>,
+    pub size: u64,
+}
+
+/// Client for S3-compatible object storage, using path-style requests
+pub struct S3Client {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl S3Client {
+    pub fn new(config: S3Config) -> Result<S3Client> {
+        let store = AmazonS3Builder::new()
+            .with_endpoint(config.endpoint.as_str().trim_end_matches('/'))
+            .with_allow_http(config.endpoint.scheme() == "http")
+            .with_bucket_name(config.bucket)
+            .with_region(config.region)
+            .with_access_key_id(config.access_key_id)
+            .with_secret_access_key(config.secret_access_key)
+            .build()?;
+        Ok(S3Client {
+            store: Arc::new(store),
+        })
+    }
+
+    /// Connects to the bucket configured in the environment, if there is one
+    pub fn from_env() -> Option<S3Client> {
+        match S3Client::new(S3Config::from_env()?) {
+            Ok(client) => Some(client),
+            Err(e) => {
+                warn!("Invalid S3 configuration, ignoring: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// All objects with keys starting with the prefix
+    pub async fn list(&self, prefix: &str) -> Result<Vec<S3Object>> {
+        // objects are listed by whole path segments, so list everything under the last "/" of
+        // the prefix and match the rest of it here
+        let dir = match prefix.rsplit_once('/') {
+            Some((dir, _)) => Some(path(dir)?),
+            None => None,
+        };
+        let objects: Vec<_> = self.store.list(dir.as_ref()).try_collect().await?;
+        Ok(objects
+            .into_iter()
+            .map(|meta| S3Object {
+                key: meta.location.to_string(),
+                last_modified: meta.last_modified,
+                size: meta.size as u64,
+            })
+            .filter(|o| o.key.starts_with(prefix))
+            .collect())
+    }
+
+    /// Contents of the object as they are downloaded, or None if there is no such object
+    pub async fn get(
+        &self,
+        key: &str,
+    ) -> Result<Option<impl Stream<Item = std::io::Result<Bytes>> + Unpin>> {
+        match self.store.get(&path(key)?).await {
+            Ok(result) => Ok(Some(result.into_stream().map_err(std::io::Error::from))),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Uploads the file as it is read, in parts if it is large, replacing any previous object with
+    /// the key. Returns the number of bytes uploaded.
+    pub async fn put_file(&self, key: &str, file_path: impl AsRef<FsPath>) -> Result<u64> {
+        let mut file = fs::File::open(file_path).await?;
+        let mut writer = BufWriter::new(Arc::clone(&self.store), path(key)?);
+        let uploaded = async {
+            let len = tokio::io::copy(&mut file, &mut writer).await?;
+            writer.shutdown().await?;
+            Ok::<_, std::io::Error>(len)
+        }
+        .await;
+        match uploaded {
+            Ok(len) => Ok(len),
+            Err(e) => {
+                // don't leave the parts of a failed multipart upload taking up space
+                if let Err(abort_err) = writer.abort().await {
+                    warn!("Failed to abort upload of `{}`: {:?}", key, abort_err);
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        self.store.delete(&path(key)?).await?;
+        Ok(())
+    }
+}
+
+/// Object path for the key, kept exactly as given rather than re-encoded
+fn path(key: &str) -> Result<Path> {
+    Ok(Path::parse(key).map_err(object_store::Error::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trips_objects() {
+        let client = S3Client {
+            store: Arc::new(InMemory::new()),
+        };
+        let file_path = std::env::temp_dir().join("s3_round_trips_objects.zip");
+        fs::write(&file_path, b"savefile").await.unwrap();
+        assert_eq!(
+            client
+                .put_file("saves/world.zip", &file_path)
+                .await
+                .unwrap(),
+            8
+        );
+        client
+            .put_file("saves-old/world.zip", &file_path)
+            .await
+            .unwrap();
+        fs::remove_file(&file_path).await.unwrap();
+
+        let keys = |objects: Vec<S3Object>| -> Vec<String> {
+            objects.into_iter().map(|o| o.key).collect()
+        };
+        assert_eq!(
+            keys(client.list("saves/").await.unwrap()),
+            vec!["saves/world.zip"]
+        );
+        assert_eq!(keys(client.list("saves").await.unwrap()).len(), 2);
+        assert!(client.list("saves/x").await.unwrap().is_empty());
+
+        let contents: Vec<Bytes> = client
+            .get("saves/world.zip")
+            .await
+            .unwrap()
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(contents.concat(), b"savefile");
+        assert!(client.get("saves/missing.zip").await.unwrap().is_none());
+
+        client.delete("saves/world.zip").await.unwrap();
+        assert!(client.list("saves/").await.unwrap().is_empty());
+    }
+}
@@ -1,22 +1,46 @@
-use std::{convert::TryFrom, io::SeekFrom, path::{Path, PathBuf}};
+use std::{collections::HashMap, convert::TryFrom, io::SeekFrom, path::{Path, PathBuf}, sync::Mutex, time::SystemTime};
 
 use async_zip::tokio::read::fs::ZipFileReader;
 use factorio_file_parser::SaveHeader;
-use fctrl::schema::{Save, SaveBytes};
+use fctrl::schema::{MapGenSettingsJson, ModObject, Save, SaveBytes, SaveMetadata};
 use futures::AsyncReadExt;
+use lazy_static::lazy_static;
 use log::{error, info, warn};
-use tokio::{fs::{self, OpenOptions}, io::{AsyncSeekExt, AsyncWriteExt}};
+use sha2::{Digest, Sha256};
+use tokio::{fs::{self, OpenOptions}, io::{AsyncRead, AsyncReadExt as TokioAsyncReadExt, AsyncSeekExt, AsyncWriteExt}};
 
 use crate::{consts::*, error::{Error, Result}};
 
+lazy_static! {
+    /// SHA-256 of each savefile as last hashed, keyed by name, with the modification time and
+    /// size it was computed for. Saves are only rehashed when they change, which matters when a
+    /// multi-GB download is resumed many times.
+    static ref SAVEFILE_CHECKSUMS: Mutex<HashMap<String, (SystemTime, u64, String)>> =
+        Mutex::new(HashMap::new());
+}
+
 pub fn get_savefile_path(save_name: impl AsRef<str>) -> PathBuf {
     SAVEFILE_DIR.join(format!("{}.zip", save_name.as_ref()))
 }
 
+/// Path of a replacement savefile that was uploaded while the original was in use by the server
+pub fn get_staged_savefile_path(save_name: impl AsRef<str>) -> PathBuf {
+    SAVEFILE_STAGING_DIR.join(format!("{}.zip", save_name.as_ref()))
+}
+
+/// Path of the map-gen-settings a savefile was created with, kept so that its map can be previewed
+/// without reading them back out of the running game
+pub fn get_map_gen_settings_path(save_name: impl AsRef<str>) -> PathBuf {
+    MAP_GEN_SETTINGS_DIR.join(format!("{}.json", save_name.as_ref()))
+}
+
 pub async fn delete_savefile(save_name: impl AsRef<str>) -> Result<()> {
     let path = get_savefile_path(save_name.as_ref());
     match fs::remove_file(path).await {
         Ok(()) => {
+            // don't resurrect the save on next start
+            let _ = fs::remove_file(get_staged_savefile_path(save_name.as_ref())).await;
+            let _ = fs::remove_file(get_map_gen_settings_path(save_name.as_ref())).await;
             info!("Successfully deleted savefile `{}`", save_name.as_ref());
             Ok(())
         },
@@ -27,6 +51,67 @@ pub async fn delete_savefile(save_name: impl AsRef<str>) -> Result<()> {
     }
 }
 
+/// Renames the savefile, along with any replacement staged for it
+pub async fn rename_savefile(save_name: impl AsRef<str>, new_name: impl AsRef<str>) -> Result<()> {
+    fs::rename(get_savefile_path(save_name.as_ref()), get_savefile_path(new_name.as_ref())).await?;
+    match fs::rename(get_staged_savefile_path(save_name.as_ref()), get_staged_savefile_path(new_name.as_ref())).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            warn!("Failed to rename staged replacement for savefile `{}`: {:?}", save_name.as_ref(), e);
+        },
+        _ => (),
+    }
+    match fs::rename(get_map_gen_settings_path(save_name.as_ref()), get_map_gen_settings_path(new_name.as_ref())).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            warn!("Failed to rename map-gen-settings of savefile `{}`: {:?}", save_name.as_ref(), e);
+        },
+        _ => (),
+    }
+    info!("Successfully renamed savefile `{}` to `{}`", save_name.as_ref(), new_name.as_ref());
+    Ok(())
+}
+
+/// Copies the savefile to a new name, via a partial file so the copy never appears half-written
+pub async fn copy_savefile(save_name: impl AsRef<str>, new_name: impl AsRef<str>) -> Result<()> {
+    let path = get_savefile_path(new_name.as_ref());
+    let partial_path = get_partial_path(&path);
+    if let Err(e) = fs::copy(get_savefile_path(save_name.as_ref()), &partial_path).await {
+        let _ = fs::remove_file(&partial_path).await;
+        return Err(e.into());
+    }
+    fs::rename(&partial_path, &path).await?;
+    match fs::copy(get_map_gen_settings_path(save_name.as_ref()), get_map_gen_settings_path(new_name.as_ref())).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            warn!("Failed to copy map-gen-settings of savefile `{}`: {:?}", save_name.as_ref(), e);
+        },
+        _ => (),
+    }
+    info!("Successfully copied savefile `{}` to `{}`", save_name.as_ref(), new_name.as_ref());
+    Ok(())
+}
+
+pub async fn set_map_gen_settings(save_name: impl AsRef<str>, map_gen_settings: &MapGenSettingsJson) -> Result<()> {
+    fs::create_dir_all(&*MAP_GEN_SETTINGS_DIR).await?;
+    fs::write(get_map_gen_settings_path(save_name), map_gen_settings.0.as_bytes()).await?;
+    Ok(())
+}
+
+/// The map-gen-settings the savefile was created with, if it was created with any
+pub async fn read_map_gen_settings(save_name: impl AsRef<str>) -> Result<Option<MapGenSettingsJson>> {
+    match fs::read_to_string(get_map_gen_settings_path(save_name)).await {
+        Ok(json) => Ok(Some(MapGenSettingsJson(json))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether the name can be used for a savefile, without escaping the savefile directory
+pub fn is_valid_savefile_name(save_name: impl AsRef<str>) -> bool {
+    let save_name = save_name.as_ref();
+    !save_name.trim().is_empty()
+        && !save_name.starts_with('.')
+        && !save_name.contains(|c| c == '/' || c == '\\')
+}
+
 pub async fn exists_savefile(save_name: impl AsRef<str>) -> Result<bool> {
     Ok(list_savefiles().await?.into_iter().find(|s| s.name == save_name.as_ref()).is_some())
 }
@@ -54,7 +139,12 @@ pub async fn list_savefiles() -> Result<Vec<Save>> {
     let mut ret = vec![];
     let mut entries = fs::read_dir(&*SAVEFILE_DIR).await?;
     while let Ok(Some(e)) = entries.next_entry().await {
-        if let Ok(save) = parse_from_path(e.path()) {
+        if is_partial_path(e.path()) {
+            // upload in progress
+            continue;
+        }
+        if let Ok(mut save) = parse_from_path(e.path()) {
+            save.staged = get_staged_savefile_path(&save.name).is_file();
             ret.push(save);
         } else {
             warn!("Invalid file {} found in save dir", e.path().display());
@@ -70,15 +160,73 @@ pub async fn set_savefile(save_name: impl AsRef<str>, savebytes: SaveBytes) -> R
         fs::create_dir_all(SAVEFILE_DIR.as_path()).await?;
     }
 
+    let path = get_savefile_path(save_name.as_ref());
+    write_savefile(save_name, path, savebytes).await
+}
+
+/// Writes to the staging area instead, for a savefile that is currently in use by the server.
+/// The staged savefile is swapped in by `apply_staged_savefile` on the next server start.
+pub async fn set_staged_savefile(save_name: impl AsRef<str>, savebytes: SaveBytes) -> Result<()> {
+    // Create staging dir if not exist
+    if !SAVEFILE_STAGING_DIR.is_dir() {
+        fs::create_dir_all(SAVEFILE_STAGING_DIR.as_path()).await?;
+    }
+
+    let path = get_staged_savefile_path(save_name.as_ref());
+    write_savefile(save_name, path, savebytes).await
+}
+
+/// Writes the savefile as it is read rather than from memory, for savefiles coming from elsewhere
+/// in full. Returns the number of bytes written.
+pub async fn set_savefile_from_reader(
+    save_name: impl AsRef<str>,
+    mut reader: impl AsyncRead + Unpin,
+) -> Result<u64> {
+    // Create save dir if not exist
+    if !SAVEFILE_DIR.is_dir() {
+        fs::create_dir_all(SAVEFILE_DIR.as_path()).await?;
+    }
+
+    let path = get_savefile_path(save_name.as_ref());
+    let partial_path = get_partial_path(&path);
+    let mut file = fs::File::create(&partial_path).await?;
+    let len = tokio::io::copy(&mut reader, &mut file).await?;
+    file.flush().await?;
+    drop(file);
+    finalise_savefile(&partial_path, &path, None).await?;
+    info!("Successfully set savefile `{}`, wrote {} bytes", save_name.as_ref(), len);
+    Ok(len)
+}
+
+/// Replaces the savefile with its staged replacement, if there is one
+pub async fn apply_staged_savefile(save_name: impl AsRef<str>) -> Result<()> {
+    let staged_path = get_staged_savefile_path(save_name.as_ref());
+    if staged_path.is_file() {
+        fs::rename(&staged_path, get_savefile_path(save_name.as_ref())).await?;
+        info!("Swapped in staged replacement for savefile `{}`", save_name.as_ref());
+    }
+    Ok(())
+}
+
+/// Uploads are written to a partial file alongside the target, and only moved into place once
+/// the complete file has been verified. This way a failed or interrupted upload never destroys an
+/// existing savefile of the same name.
+async fn write_savefile(
+    save_name: impl AsRef<str>,
+    path: PathBuf,
+    savebytes: SaveBytes,
+) -> Result<()> {
+    let partial_path = get_partial_path(&path);
     let bytes_length = savebytes.bytes.len();
     if let Some(start_byte) = savebytes.multipart_start {
         // partial file write
-        let filename = get_savefile_path(save_name.as_ref());
         // create if not exist
-        let mut file = OpenOptions::new().write(true).create(true).open(filename).await?;
+        let mut file = OpenOptions::new().write(true).create(true).open(&partial_path).await?;
         if savebytes.is_sentinel() {
             // finalise and trim down to size
             file.set_len(start_byte as u64).await?;
+            drop(file);
+            finalise_savefile(&partial_path, &path, savebytes.sha256.as_deref()).await?;
             info!("Successfully finalised savefile `{}`, final length {} bytes", save_name.as_ref(), start_byte);
         } else {
             // seek to correct write location before writing
@@ -90,39 +238,142 @@ pub async fn set_savefile(save_name: impl AsRef<str>, savebytes: SaveBytes) -> R
         Ok(())
     } else {
         // write the whole file
-        match fs::write(get_savefile_path(save_name.as_ref()), savebytes.bytes).await {
+        fs::write(&partial_path, savebytes.bytes).await?;
+        match finalise_savefile(&partial_path, &path, savebytes.sha256.as_deref()).await {
             Ok(()) => {
                 info!("Successfully set savefile `{}`, wrote {} bytes", save_name.as_ref(), bytes_length);
                 Ok(())
             },
             Err(e) => {
                 error!("Failed to set savefile `{}`: {:?}", save_name.as_ref(), e);
-                Err(e.into())
+                Err(e)
             }
         }
     }
 }
 
+/// Verifies the checksum (if provided) and zip structure of a fully received partial file,
+/// then atomically renames it over the target path
+async fn finalise_savefile(
+    partial_path: &Path,
+    path: &Path,
+    expected_sha256: Option<&str>,
+) -> Result<()> {
+    if let Some(expected) = expected_sha256 {
+        let actual = sha256_file(partial_path).await?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            let _ = fs::remove_file(partial_path).await;
+            return Err(Error::SavefileChecksumMismatch {
+                expected: expected.to_owned(),
+                actual,
+            });
+        }
+    }
+
+    if let Err(e) = ZipFileReader::new(partial_path).await {
+        let _ = fs::remove_file(partial_path).await;
+        return Err(e.into());
+    }
+
+    fs::rename(partial_path, path).await?;
+    Ok(())
+}
+
+pub async fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = TokioAsyncReadExt::read(&mut file, &mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// SHA-256 of the savefile with the given name, only rehashing it if it changed since last time
+pub async fn savefile_sha256(save_name: &str) -> Result<String> {
+    let path = get_savefile_path(save_name);
+    let metadata = fs::metadata(&path).await?;
+    let modified = metadata.modified()?;
+    if let Some((cached_modified, cached_len, sha256)) =
+        SAVEFILE_CHECKSUMS.lock().unwrap().get(save_name)
+    {
+        if *cached_modified == modified && *cached_len == metadata.len() {
+            return Ok(sha256.clone());
+        }
+    }
+
+    let sha256 = sha256_file(&path).await?;
+    SAVEFILE_CHECKSUMS.lock().unwrap().insert(
+        save_name.to_owned(),
+        (modified, metadata.len(), sha256.clone()),
+    );
+    Ok(sha256)
+}
+
+fn get_partial_path(path: &Path) -> PathBuf {
+    path.with_extension("zip.partial")
+}
+
+fn is_partial_path(path: impl AsRef<Path>) -> bool {
+    path.as_ref().extension().map_or(false, |ext| ext == "partial")
+}
+
 pub async fn read_header(save_name: impl AsRef<str>) -> Result<SaveHeader> {
     // 1. open zip
     let reader = ZipFileReader::new(get_savefile_path(save_name.as_ref())).await?;
+
+    // 2. locate the header. Since 1.0 it is kept in level-init.dat, older saves only have it at
+    //    the start of level.dat
+    let mut level_dat_index = None;
+    let mut level_init_dat_index = None;
     for index in 0..reader.file().entries().len() {
         let entry = reader.file().entries().get(index).unwrap();
-        // 2. locate level-init.dat and read into memory
         if let Ok(filename_str) = entry.filename().as_str() {
             if filename_str.ends_with("level-init.dat") {
-                let mut entry_reader = reader.reader_without_entry(index).await?;
-                let mut buf = vec![];
-                entry_reader.read_to_end(&mut buf).await?;
-                // 3. Parse as SaveHeader
-                let save_header = SaveHeader::try_from(buf.as_ref())?;
-                return Ok(save_header);
+                level_init_dat_index = Some(index);
+            } else if filename_str.ends_with("level.dat") {
+                level_dat_index = Some(index);
             }
         } else {
             warn!("unable to convert zip entry filename '{:?}' to UTF-8, skipping", entry.filename());
         }
     }
-    Err(Error::HeaderNotFound)
+
+    // 3. read into memory and parse as SaveHeader
+    let index = level_init_dat_index.or(level_dat_index).ok_or(Error::HeaderNotFound)?;
+    let mut entry_reader = reader.reader_without_entry(index).await?;
+    let mut buf = vec![];
+    entry_reader.read_to_end(&mut buf).await?;
+    let save_header = SaveHeader::try_from(buf.as_ref())?;
+    Ok(save_header)
+}
+
+pub async fn read_metadata(save_name: impl AsRef<str>) -> Result<Option<SaveMetadata>> {
+    let save = match list_savefiles().await?.into_iter().find(|s| s.name == save_name.as_ref()) {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let size_bytes = fs::metadata(get_savefile_path(&save.name)).await?.len();
+    let header = read_header(&save.name).await?;
+    let mods = header
+        .mods
+        .into_iter()
+        .map(|shm| ModObject {
+            name: shm.name,
+            version: shm.version.to_string(),
+            enabled: true,
+        })
+        .collect();
+    Ok(Some(SaveMetadata {
+        name: save.name,
+        last_modified: save.last_modified,
+        size_bytes,
+        mods,
+    }))
 }
 
 fn parse_from_path<P: AsRef<Path>>(path: P) -> Result<Save> {
@@ -140,6 +391,8 @@ fn parse_from_path<P: AsRef<Path>>(path: P) -> Result<Save> {
             return Ok(Save {
                 name,
                 last_modified,
+                staged: false,
+                remote: false,
             });
         }
     }
@@ -0,0 +1,315 @@
+use std::{collections::HashMap, io, sync::OnceLock};
+
+use log::info;
+
+/// Text fctrl sends to players in-game, rendered in the locale chosen for the server
+#[derive(Clone, Copy, Debug)]
+pub enum GameMessage<'a> {
+    Announcement { message: &'a str },
+    AutosaveSoon { seconds: u64 },
+    ChatFilterWarning,
+    ModerationWarning { text: &'a str },
+    Alert { message: &'a str },
+    ReservedSlotKick,
+    ReservedSlotKickNotice { player: &'a str },
+    JoinFloodBan,
+    SoftCapExceeded {
+        player: &'a str,
+        online: usize,
+        cap: usize,
+    },
+    Welcome { player: &'a str },
+    DiscordInvite { url: &'a str },
+    /// Time left until the server goes down, in whole minutes where possible
+    TimeLeft { seconds: u64 },
+    UptimeRestartSoon { time_left: &'a str },
+    ServerStopSoon { time_left: &'a str },
+    ServerStopSoonWithMessage { time_left: &'a str, message: &'a str },
+}
+
+impl GameMessage<'_> {
+    fn key(&self) -> &'static str {
+        match self {
+            GameMessage::Announcement { .. } => "announcement",
+            GameMessage::AutosaveSoon { .. } => "autosave_soon",
+            GameMessage::ChatFilterWarning => "chat_filter_warning",
+            GameMessage::ModerationWarning { .. } => "moderation_warning",
+            GameMessage::Alert { .. } => "alert",
+            GameMessage::ReservedSlotKick => "reserved_slot_kick",
+            GameMessage::ReservedSlotKickNotice { .. } => "reserved_slot_kick_notice",
+            GameMessage::JoinFloodBan => "join_flood_ban",
+            GameMessage::SoftCapExceeded { .. } => "soft_cap_exceeded",
+            GameMessage::Welcome { .. } => "welcome",
+            GameMessage::DiscordInvite { .. } => "discord_invite",
+            GameMessage::TimeLeft { seconds } if seconds % 60 == 0 => "minutes_left",
+            GameMessage::TimeLeft { .. } => "seconds_left",
+            GameMessage::UptimeRestartSoon { .. } => "uptime_restart_soon",
+            GameMessage::ServerStopSoon { .. } => "server_stop_soon",
+            GameMessage::ServerStopSoonWithMessage { .. } => "server_stop_soon_with_message",
+        }
+    }
+
+    fn args(&self) -> Vec<(&'static str, String)> {
+        match self {
+            GameMessage::Announcement { message } | GameMessage::Alert { message } => {
+                vec![("message", message.to_string())]
+            }
+            GameMessage::AutosaveSoon { seconds } => vec![("seconds", seconds.to_string())],
+            GameMessage::ModerationWarning { text } => vec![("text", text.to_string())],
+            GameMessage::SoftCapExceeded {
+                player,
+                online,
+                cap,
+            } => vec![
+                ("player", player.to_string()),
+                ("online", online.to_string()),
+                ("cap", cap.to_string()),
+            ],
+            GameMessage::Welcome { player } | GameMessage::ReservedSlotKickNotice { player } => {
+                vec![("player", player.to_string())]
+            }
+            GameMessage::DiscordInvite { url } => vec![("url", url.to_string())],
+            GameMessage::TimeLeft { seconds } => vec![
+                ("minutes", (seconds / 60).to_string()),
+                ("seconds", seconds.to_string()),
+            ],
+            GameMessage::UptimeRestartSoon { time_left }
+            | GameMessage::ServerStopSoon { time_left } => {
+                vec![("time_left", time_left.to_string())]
+            }
+            GameMessage::ServerStopSoonWithMessage { time_left, message } => vec![
+                ("time_left", time_left.to_string()),
+                ("message", message.to_string()),
+            ],
+            GameMessage::ChatFilterWarning
+            | GameMessage::ReservedSlotKick
+            | GameMessage::JoinFloodBan => vec![],
+        }
+    }
+
+    /// The message in the server's locale
+    pub fn render(&self) -> String {
+        CATALOG
+            .get_or_init(|| MessageCatalog::new(DEFAULT_LOCALE, HashMap::new()).unwrap())
+            .render(self)
+    }
+}
+
+const DEFAULT_LOCALE: &str = "en";
+
+/// Built-in templates per locale, where `{name}` is replaced with the message arguments
+const BUILTIN_CATALOG: &[(&str, &[(&str, &str)])] = &[
+    (
+        "en",
+        &[
+            ("announcement", "[Announcement] {message}"),
+            ("autosave_soon", "Autosave in {seconds} seconds"),
+            ("chat_filter_warning", "Your message was flagged by the chat filter, please keep chat civil"),
+            ("moderation_warning", "[Warning] {text}"),
+            ("alert", "[ALERT] {message}"),
+            ("reserved_slot_kick", "Sorry, the server is full and your slot was needed for a reserved player. Please try again later."),
+            ("reserved_slot_kick_notice", "{player} was kicked to keep a reserved slot free"),
+            ("join_flood_ban", "Too many connection attempts, please wait before reconnecting"),
+            ("soft_cap_exceeded", "{player} joined beyond the soft cap of {cap} players, {online} are now online"),
+            ("welcome", "Welcome to the server, {player}!"),
+            ("discord_invite", "Join us on Discord: {url}"),
+            ("minutes_left", "{minutes} min"),
+            ("seconds_left", "{seconds} s"),
+            ("uptime_restart_soon", "[Server restarting in {time_left} for maintenance, the game will be saved first]"),
+            ("server_stop_soon", "[Server stopping in {time_left}, the game will be saved first]"),
+            ("server_stop_soon_with_message", "[Server stopping in {time_left}: {message}]"),
+        ],
+    ),
+    (
+        "de",
+        &[
+            ("announcement", "[Ankündigung] {message}"),
+            ("autosave_soon", "Automatische Speicherung in {seconds} Sekunden"),
+            ("chat_filter_warning", "Deine Nachricht wurde vom Chatfilter markiert, bitte bleib freundlich"),
+            ("moderation_warning", "[Verwarnung] {text}"),
+            ("alert", "[ALARM] {message}"),
+            ("reserved_slot_kick", "Der Server ist leider voll und dein Platz wurde für einen reservierten Spieler benötigt. Bitte versuche es später erneut."),
+            ("reserved_slot_kick_notice", "{player} wurde gekickt, um einen reservierten Platz freizuhalten"),
+            ("join_flood_ban", "Zu viele Verbindungsversuche, bitte warte vor dem erneuten Verbinden"),
+            ("soft_cap_exceeded", "{player} ist über die weiche Grenze von {cap} Spielern hinaus beigetreten, jetzt sind {online} online"),
+            ("welcome", "Willkommen auf dem Server, {player}!"),
+            ("discord_invite", "Besuche uns auf Discord: {url}"),
+            ("minutes_left", "{minutes} Min."),
+            ("seconds_left", "{seconds} Sek."),
+            ("uptime_restart_soon", "[Neustart des Servers zur Wartung in {time_left}, das Spiel wird vorher gespeichert]"),
+            ("server_stop_soon", "[Server wird in {time_left} gestoppt, das Spiel wird vorher gespeichert]"),
+            ("server_stop_soon_with_message", "[Server wird in {time_left} gestoppt: {message}]"),
+        ],
+    ),
+    (
+        "es",
+        &[
+            ("announcement", "[Anuncio] {message}"),
+            ("autosave_soon", "Autoguardado en {seconds} segundos"),
+            ("chat_filter_warning", "Tu mensaje fue marcado por el filtro del chat, por favor mantén un tono respetuoso"),
+            ("moderation_warning", "[Advertencia] {text}"),
+            ("alert", "[ALERTA] {message}"),
+            ("reserved_slot_kick", "Lo sentimos, el servidor está lleno y tu plaza era necesaria para un jugador reservado. Inténtalo de nuevo más tarde."),
+            ("reserved_slot_kick_notice", "{player} fue expulsado para mantener libre una plaza reservada"),
+            ("join_flood_ban", "Demasiados intentos de conexión, espera antes de volver a conectarte"),
+            ("soft_cap_exceeded", "{player} se ha unido superando el límite flexible de {cap} jugadores, ahora hay {online} conectados"),
+            ("welcome", "¡Bienvenido al servidor, {player}!"),
+            ("discord_invite", "Únete a nosotros en Discord: {url}"),
+            ("minutes_left", "{minutes} min"),
+            ("seconds_left", "{seconds} s"),
+            ("uptime_restart_soon", "[El servidor se reiniciará por mantenimiento en {time_left}, la partida se guardará antes]"),
+            ("server_stop_soon", "[El servidor se detendrá en {time_left}, la partida se guardará antes]"),
+            ("server_stop_soon_with_message", "[El servidor se detendrá en {time_left}: {message}]"),
+        ],
+    ),
+    (
+        "fr",
+        &[
+            ("announcement", "[Annonce] {message}"),
+            ("autosave_soon", "Sauvegarde automatique dans {seconds} secondes"),
+            ("chat_filter_warning", "Votre message a été signalé par le filtre de discussion, merci de rester courtois"),
+            ("moderation_warning", "[Avertissement] {text}"),
+            ("alert", "[ALERTE] {message}"),
+            ("reserved_slot_kick", "Désolé, le serveur est plein et votre place était nécessaire pour un joueur réservé. Veuillez réessayer plus tard."),
+            ("reserved_slot_kick_notice", "{player} a été expulsé pour garder une place réservée libre"),
+            ("join_flood_ban", "Trop de tentatives de connexion, veuillez patienter avant de vous reconnecter"),
+            ("soft_cap_exceeded", "{player} a rejoint au-delà de la limite souple de {cap} joueurs, {online} sont maintenant en ligne"),
+            ("welcome", "Bienvenue sur le serveur, {player} !"),
+            ("discord_invite", "Rejoignez-nous sur Discord : {url}"),
+            ("minutes_left", "{minutes} min"),
+            ("seconds_left", "{seconds} s"),
+            ("uptime_restart_soon", "[Redémarrage du serveur pour maintenance dans {time_left}, la partie sera sauvegardée avant]"),
+            ("server_stop_soon", "[Arrêt du serveur dans {time_left}, la partie sera sauvegardée avant]"),
+            ("server_stop_soon_with_message", "[Arrêt du serveur dans {time_left} : {message}]"),
+        ],
+    ),
+];
+
+static CATALOG: OnceLock<MessageCatalog> = OnceLock::new();
+
+/// Templates for fctrl-generated in-game messages in one locale, with optional overrides for
+/// individual messages
+#[derive(Debug)]
+pub struct MessageCatalog {
+    templates: &'static [(&'static str, &'static str)],
+    overrides: HashMap<String, String>,
+}
+
+impl MessageCatalog {
+    pub fn new(locale: &str, overrides: HashMap<String, String>) -> io::Result<MessageCatalog> {
+        let templates = BUILTIN_CATALOG
+            .iter()
+            .find(|(l, _)| l.eq_ignore_ascii_case(locale))
+            .map(|(_, templates)| *templates)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Unsupported in-game message locale '{}', expected one of: {}",
+                        locale,
+                        BUILTIN_CATALOG
+                            .iter()
+                            .map(|(l, _)| *l)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                )
+            })?;
+        Ok(MessageCatalog {
+            templates,
+            overrides,
+        })
+    }
+
+    /// Reads the locale from GAME_MESSAGE_LOCALE, and overrides from the JSON object of message
+    /// keys to templates in GAME_MESSAGE_CATALOG_FILE
+    pub fn from_env() -> io::Result<MessageCatalog> {
+        let locale =
+            std::env::var("GAME_MESSAGE_LOCALE").unwrap_or_else(|_| DEFAULT_LOCALE.to_owned());
+        let overrides = match std::env::var("GAME_MESSAGE_CATALOG_FILE") {
+            Ok(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+            Err(_) => HashMap::new(),
+        };
+        info!(
+            "In-game messages will be sent in locale '{}' with {} override(s)",
+            locale,
+            overrides.len()
+        );
+        MessageCatalog::new(&locale, overrides)
+    }
+
+    /// Sets the catalog used to render all in-game messages. Only the first call has an effect.
+    pub fn install(self) {
+        let _ = CATALOG.set(self);
+    }
+
+    fn render(&self, message: &GameMessage) -> String {
+        let key = message.key();
+        let template = match self.overrides.get(key) {
+            Some(template) => template.as_str(),
+            None => self
+                .templates
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map_or(key, |(_, template)| *template),
+        };
+        message
+            .args()
+            .into_iter()
+            .fold(template.to_owned(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), &value)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_messages_in_locale_with_overrides() {
+        let catalog = MessageCatalog::new("DE", HashMap::new()).unwrap();
+        assert_eq!(
+            catalog.render(&GameMessage::AutosaveSoon { seconds: 10 }),
+            "Automatische Speicherung in 10 Sekunden"
+        );
+
+        let mut overrides = HashMap::new();
+        overrides.insert("announcement".to_owned(), ">> {message} <<".to_owned());
+        let catalog = MessageCatalog::new("en", overrides).unwrap();
+        assert_eq!(
+            catalog.render(&GameMessage::Announcement { message: "hi" }),
+            ">> hi <<"
+        );
+
+        assert!(MessageCatalog::new("xx", HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn renders_time_left_in_minutes_where_whole() {
+        let catalog = MessageCatalog::new("en", HashMap::new()).unwrap();
+        assert_eq!(
+            catalog.render(&GameMessage::TimeLeft { seconds: 300 }),
+            "5 min"
+        );
+        assert_eq!(
+            catalog.render(&GameMessage::TimeLeft { seconds: 90 }),
+            "90 s"
+        );
+    }
+
+    #[test]
+    fn every_locale_has_every_message() {
+        let (_, en) = BUILTIN_CATALOG[0];
+        for (locale, templates) in BUILTIN_CATALOG {
+            for (key, _) in en {
+                assert!(
+                    templates.iter().any(|(k, _)| k == key),
+                    "{} missing {}",
+                    locale,
+                    key
+                );
+            }
+        }
+    }
+}
@@ -1,2 +1,4 @@
+pub mod game_message;
+pub mod mod_settings;
 pub mod schema;
 pub mod util;
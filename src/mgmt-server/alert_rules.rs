@@ -0,0 +1,307 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use fctrl::schema::{AgentOutMessage, AgentResponseWithId, OperationStatus, ServerStatus};
+use futures::{pin_mut, StreamExt};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clients::AgentApiClient,
+    db::{Cf, Db, Record},
+    discord::DiscordClient,
+    error::{Error, Result},
+    events::{broker::EventBroker, TopicName, JOIN_TOPIC_NAME, OPERATION_TOPIC_NAME},
+    ha::Leadership,
+    scheduler::{JobDefinition, Scheduler},
+};
+
+lazy_static! {
+    static ref ALERT_RULES_CF: Cf = Cf("alert_rules".to_owned());
+    static ref EVOLUTION_RE: Regex = Regex::new(r"Evolution factor: (\d+(?:\.\d+)?)").unwrap();
+}
+
+/// How often the evolution factor is checked while the server is in game
+const EVOLUTION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A condition to alert the Discord alert channel on, persisted in the db
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AlertRule {
+    pub id: String,
+    pub condition: AlertCondition,
+    /// Discord user ID to mention in the alert
+    pub mention: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// The server process exited without being stopped. Crashes are always alerted, these rules
+    /// add mentions to the alert.
+    ServerCrash,
+    /// The named player joined the server
+    PlayerJoin { player: String },
+    /// The evolution factor rose to the threshold, between 0 and 1
+    Evolution { threshold: f64 },
+    /// A long-running operation such as an install or mod update failed
+    OperationFailed,
+}
+
+/// Admin-defined alerts on server events, sent to the Discord alert channel.
+///
+/// Only the leader sends alerts. Any instance can manage rules as they are kept in the db.
+pub struct AlertRules {
+    db: Arc<Db>,
+}
+
+impl AlertRules {
+    pub fn new(db: Arc<Db>) -> AlertRules {
+        AlertRules { db }
+    }
+
+    pub async fn start(
+        self: &Arc<Self>,
+        agent_client: Arc<AgentApiClient>,
+        discord: Arc<Option<DiscordClient>>,
+        event_broker: Arc<EventBroker>,
+        leadership: Leadership,
+        scheduler: &Scheduler,
+    ) {
+        let join_sub = event_broker
+            .subscribe(TopicName::new(JOIN_TOPIC_NAME), |_| true)
+            .await;
+        let operation_sub = event_broker
+            .subscribe(TopicName::new(OPERATION_TOPIC_NAME), |_| true)
+            .await;
+
+        let alert_rules = Arc::clone(self);
+        let discord_clone = Arc::clone(&discord);
+        tokio::spawn(async move {
+            pin_mut!(join_sub);
+            pin_mut!(operation_sub);
+            loop {
+                let alerts: Vec<(AlertRule, String)> = tokio::select! {
+                    Some(event) = join_sub.next() => {
+                        let player = event.tags.get(&TopicName::new(JOIN_TOPIC_NAME)).unwrap();
+                        alert_rules.matching(|c| matches!(c, AlertCondition::PlayerJoin { player: p } if p == player))
+                            .into_iter()
+                            .map(|r| (r, format!("{} joined the server", player)))
+                            .collect()
+                    }
+                    Some(event) = operation_sub.next() => {
+                        match serde_json::from_str::<AgentResponseWithId>(&event.content) {
+                            Ok(AgentResponseWithId {
+                                operation_id,
+                                status: OperationStatus::Failed,
+                                content,
+                                ..
+                            }) => {
+                                let reason = match content {
+                                    AgentOutMessage::Error(e) => e,
+                                    m => format!("{:?}", m),
+                                };
+                                alert_rules.matching(|c| *c == AlertCondition::OperationFailed)
+                                    .into_iter()
+                                    .map(|r| (r, format!("Operation {} failed: {}", operation_id.0, reason)))
+                                    .collect()
+                            }
+                            _ => vec![],
+                        }
+                    }
+                    else => break,
+                };
+                if leadership.is_leader() {
+                    send_alerts(&discord_clone, alerts);
+                }
+            }
+
+            error!("alert rules subscriber task is finishing - this should never happen!");
+        });
+
+        let alert_rules = Arc::clone(self);
+        let last_evolution = Arc::new(Mutex::new(None));
+        let definition = JobDefinition {
+            name: "evolution_alerts",
+            interval: EVOLUTION_CHECK_INTERVAL,
+            jitter: Duration::ZERO,
+        };
+        scheduler.register(definition, move || {
+            let alert_rules = Arc::clone(&alert_rules);
+            let agent_client = Arc::clone(&agent_client);
+            let discord = Arc::clone(&discord);
+            let last_evolution = Arc::clone(&last_evolution);
+            Box::pin(async move {
+                alert_rules
+                    .check_evolution(&agent_client, &discord, &last_evolution)
+                    .await
+            })
+        });
+    }
+
+    /// Alerts on evolution thresholds crossed since the last check, given the evolution factor
+    /// read on the last check
+    async fn check_evolution(
+        &self,
+        agent_client: &AgentApiClient,
+        discord: &Option<DiscordClient>,
+        last_evolution: &Mutex<Option<f64>>,
+    ) -> Result<()> {
+        let rules = self.matching(|c| matches!(c, AlertCondition::Evolution { .. }));
+        if rules.is_empty() {
+            return Ok(());
+        }
+        if !matches!(
+            agent_client.server_status().await,
+            Ok(ServerStatus::InGame { .. })
+        ) {
+            *last_evolution.lock().unwrap() = None;
+            return Ok(());
+        }
+        let output = agent_client.rcon_command("/evolution".to_owned()).await?;
+        let evolution = match parse_evolution(&output) {
+            Some(evolution) => evolution,
+            None => {
+                warn!("Unexpected /evolution output: {}", output);
+                return Ok(());
+            }
+        };
+        // the first reading only sets the baseline, so restarts don't re-alert
+        let previous = last_evolution.lock().unwrap().replace(evolution);
+        if let Some(previous) = previous {
+            let alerts = rules
+                .into_iter()
+                .filter(|r| match r.condition {
+                    AlertCondition::Evolution { threshold } => {
+                        crossed(previous, evolution, threshold)
+                    }
+                    _ => false,
+                })
+                .map(|r| {
+                    let message = format!("Evolution factor reached {:.4}", evolution);
+                    (r, message)
+                })
+                .collect();
+            send_alerts(discord, alerts);
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<AlertRule>> {
+        self.db
+            .read_prefix(&ALERT_RULES_CF, "")?
+            .into_iter()
+            .map(|r| Ok(serde_json::from_str(&r.value)?))
+            .collect()
+    }
+
+    pub fn create(&self, condition: AlertCondition, mention: Option<String>) -> Result<AlertRule> {
+        if let AlertCondition::Evolution { threshold } = condition {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(Error::BadRequest(
+                    "Evolution threshold must be between 0 and 1".to_owned(),
+                ));
+            }
+        }
+        if matches!(&mention, Some(m) if m.parse::<u64>().is_err()) {
+            return Err(Error::BadRequest(
+                "mention must be a Discord user ID".to_owned(),
+            ));
+        }
+        let rule = AlertRule {
+            id: uuid::Uuid::new_v4().to_string(),
+            condition,
+            mention,
+        };
+        self.db.write(
+            &ALERT_RULES_CF,
+            &Record {
+                key: rule.id.clone(),
+                value: serde_json::to_string(&rule)?,
+            },
+        )?;
+        info!("Created alert rule {} ({:?})", rule.id, rule.condition);
+        Ok(rule)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<()> {
+        if self.db.read(&ALERT_RULES_CF, id.to_owned())?.is_none() {
+            return Err(Error::AlertRuleNotFound);
+        }
+        self.db.delete(&ALERT_RULES_CF, id)
+    }
+
+    /// Users to mention when alerting a server crash
+    pub fn crash_mentions(&self) -> Vec<String> {
+        self.matching(|c| *c == AlertCondition::ServerCrash)
+            .into_iter()
+            .filter_map(|r| r.mention)
+            .collect()
+    }
+
+    fn matching(&self, predicate: impl Fn(&AlertCondition) -> bool) -> Vec<AlertRule> {
+        match self.list() {
+            Ok(rules) => rules
+                .into_iter()
+                .filter(|r| predicate(&r.condition))
+                .collect(),
+            Err(e) => {
+                error!("Couldn't read alert rules: {:?}", e);
+                vec![]
+            }
+        }
+    }
+}
+
+fn send_alerts(discord: &Option<DiscordClient>, alerts: Vec<(AlertRule, String)>) {
+    for (rule, message) in alerts {
+        info!("Alert rule {} triggered: {}", rule.id, message);
+        if let Some(discord) = discord {
+            if let Err(e) = discord.oneshot_alert(rule.mention, message) {
+                error!("Couldn't send alert for rule {}: {:?}", rule.id, e);
+            }
+        }
+    }
+}
+
+/// Evolution factor from the output of the `/evolution` command. Where several surfaces are
+/// listed, this is the first, which is the starting surface.
+fn parse_evolution(output: &str) -> Option<f64> {
+    EVOLUTION_RE.captures(output)?[1].parse().ok()
+}
+
+fn crossed(previous: f64, current: f64, threshold: f64) -> bool {
+    previous < threshold && current >= threshold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_evolution_output() {
+        assert_eq!(
+            parse_evolution(
+                "Evolution factor: 0.4512. (Time 12%) (Pollution 80%) (Spawner kills 8%)"
+            ),
+            Some(0.4512)
+        );
+        assert_eq!(
+            parse_evolution(
+                "Nauvis - Evolution factor: 0.0031. (Time 100%)\nGleba - Evolution factor: 0.0000."
+            ),
+            Some(0.0031)
+        );
+        assert_eq!(parse_evolution("Unknown command"), None);
+    }
+
+    #[test]
+    fn alerts_only_when_threshold_crossed() {
+        assert!(crossed(0.49, 0.5, 0.5));
+        assert!(!crossed(0.5, 0.51, 0.5));
+        assert!(!crossed(0.3, 0.4, 0.5));
+    }
+}
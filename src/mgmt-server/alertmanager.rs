@@ -0,0 +1,187 @@
+use std::{collections::HashMap, sync::Arc};
+
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::{
+    clients::AgentApiClient,
+    discord::DiscordClient,
+    error::{Error, Result},
+    game_message::{AchievementsPolicy, GameMessage, MessageSource},
+};
+
+/// Alert label choosing where the alert is sent: `discord`, `ingame` or `all`
+const DESTINATION_LABEL: &str = "fctrl_destination";
+/// Alert label holding a Discord user id to mention in the alert
+const MENTION_LABEL: &str = "fctrl_mention";
+
+/// Body of an Alertmanager webhook notification, version 4
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertmanagerWebhook {
+    pub version: String,
+    pub status: String,
+    pub alerts: Vec<AlertmanagerAlert>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertmanagerAlert {
+    /// Either `firing` or `resolved`
+    pub status: String,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Destinations {
+    discord: bool,
+    in_game: bool,
+}
+
+/// Relays alerts from an existing Prometheus and Alertmanager setup to Discord and in-game.
+///
+/// Alertmanager authenticates with a shared bearer token. The receiver is disabled if no token
+/// is configured.
+pub struct AlertmanagerReceiver {
+    token: Option<String>,
+    agent_client: Arc<AgentApiClient>,
+    discord: Arc<Option<DiscordClient>>,
+    achievements_policy: AchievementsPolicy,
+}
+
+impl AlertmanagerReceiver {
+    pub fn new(
+        token: Option<String>,
+        agent_client: Arc<AgentApiClient>,
+        discord: Arc<Option<DiscordClient>>,
+        achievements_policy: AchievementsPolicy,
+    ) -> AlertmanagerReceiver {
+        AlertmanagerReceiver {
+            token,
+            agent_client,
+            discord,
+            achievements_policy,
+        }
+    }
+
+    pub async fn receive(&self, token: Option<&str>, webhook: AlertmanagerWebhook) -> Result<()> {
+        match (&self.token, token) {
+            (Some(expected), Some(token)) if expected == token => (),
+            _ => return Err(Error::WebhookUnauthorized),
+        }
+        if webhook.version != "4" {
+            return Err(Error::BadRequest(format!(
+                "Unsupported Alertmanager webhook version '{}', expected 4",
+                webhook.version
+            )));
+        }
+
+        info!(
+            "Received {} {} alerts from Alertmanager",
+            webhook.alerts.len(),
+            webhook.status
+        );
+        for alert in webhook.alerts {
+            let message = alert_message(&alert);
+            let destinations = destinations(&alert);
+            if destinations.discord {
+                if let Some(discord) = self.discord.as_ref() {
+                    let mention = alert.labels.get(MENTION_LABEL).cloned();
+                    if let Err(e) = discord.oneshot_alert(mention, message.clone()) {
+                        error!("Couldn't relay Alertmanager alert to Discord: {:?}", e);
+                    }
+                }
+            }
+            if destinations.in_game {
+                let in_game_msg = GameMessage::Alert { message: &message }.render();
+                let command = self
+                    .achievements_policy
+                    .broadcast_command(MessageSource::Announcement, &in_game_msg);
+                if let Err(e) = self.agent_client.rcon_command(command).await {
+                    error!("Couldn't relay Alertmanager alert in-game: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Summary line for the alert, preferring its `summary` annotation
+fn alert_message(alert: &AlertmanagerAlert) -> String {
+    let text = alert
+        .annotations
+        .get("summary")
+        .or_else(|| alert.annotations.get("description"))
+        .or_else(|| alert.labels.get("alertname"))
+        .map(|s| s.as_str())
+        .unwrap_or("Unnamed alert");
+    format!("[{}] {}", alert.status.to_uppercase(), text)
+}
+
+fn destinations(alert: &AlertmanagerAlert) -> Destinations {
+    match alert.labels.get(DESTINATION_LABEL).map(|s| s.as_str()) {
+        Some("ingame") => Destinations {
+            discord: false,
+            in_game: true,
+        },
+        Some("all") => Destinations {
+            discord: true,
+            in_game: true,
+        },
+        // anything else goes to Discord only, so unlabelled alerts never interrupt players
+        _ => Destinations {
+            discord: true,
+            in_game: false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_alerts_by_label() {
+        let webhook: AlertmanagerWebhook = serde_json::from_value(serde_json::json!({
+            "version": "4",
+            "groupKey": "{}:{alertname=\"HighMemory\"}",
+            "status": "firing",
+            "receiver": "fctrl",
+            "alerts": [
+                {
+                    "status": "firing",
+                    "labels": { "alertname": "HighMemory", "fctrl_destination": "all" },
+                    "annotations": { "summary": "Memory usage above 90%" },
+                    "startsAt": "2026-01-01T00:00:00Z",
+                },
+                {
+                    "status": "resolved",
+                    "labels": { "alertname": "DiskFull" },
+                },
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            alert_message(&webhook.alerts[0]),
+            "[FIRING] Memory usage above 90%"
+        );
+        assert_eq!(
+            destinations(&webhook.alerts[0]),
+            Destinations {
+                discord: true,
+                in_game: true
+            }
+        );
+        assert_eq!(alert_message(&webhook.alerts[1]), "[RESOLVED] DiskFull");
+        assert_eq!(
+            destinations(&webhook.alerts[1]),
+            Destinations {
+                discord: true,
+                in_game: false
+            }
+        );
+    }
+}
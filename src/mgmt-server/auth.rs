@@ -68,6 +68,7 @@ impl AuthnManager {
         if let AuthnProvider::Discord {
             client_id,
             client_secret,
+            ..
         } = &self.provider
         {
             let client = reqwest::Client::new();
@@ -123,6 +124,7 @@ impl AuthnManager {
         if let AuthnProvider::Discord {
             client_id,
             client_secret,
+            ..
         } = &self.provider
         {
             // Get the stored refresh token
@@ -164,8 +166,11 @@ impl AuthnManager {
         }
     }
 
+    /// Identity of the holder of the access token, including their roles in the Discord guild if
+    /// one is configured. Roles are resolved once per access token, so role changes take effect
+    /// when the token is next refreshed.
     pub async fn get_id_details(&self, access_token: impl AsRef<str>) -> Result<UserIdentity> {
-        if let AuthnProvider::Discord { .. } = &self.provider {
+        if let AuthnProvider::Discord { guild_id, .. } = &self.provider {
             // Check if in cache first
             {
                 let mg = self.token_to_id_map.lock().await;
@@ -184,7 +189,18 @@ impl AuthnManager {
                 Ok(resp) => {
                     let text = resp.error_for_status()?.text().await?;
                     let discord_user = serde_json::from_str::<DiscordUser>(&text)?;
-                    let user_id: UserIdentity = discord_user.into();
+                    let mut user_id: UserIdentity = discord_user.into();
+                    if let Some(guild_id) = guild_id {
+                        user_id.roles = get_guild_roles(&client, guild_id, access_token.as_ref())
+                            .await
+                            .unwrap_or_else(|e| {
+                                warn!(
+                                    "Couldn't read guild roles for user {}, assuming none: {:?}",
+                                    user_id.sub, e
+                                );
+                                vec![]
+                            });
+                    }
                     // Cache result
                     let mut mg = self.token_to_id_map.lock().await;
                     mg.insert(access_token.as_ref().to_string(), user_id.clone());
@@ -207,20 +223,63 @@ impl Drop for AuthnManager {
     }
 }
 
+/// Role ids of the user's membership in the guild, requires the `guilds.members.read` scope
+async fn get_guild_roles(
+    client: &reqwest::Client,
+    guild_id: &str,
+    access_token: &str,
+) -> Result<Vec<String>> {
+    let text = client
+        .get(format!("{}/{}/member", DISCORD_GUILDS_URL, guild_id))
+        .bearer_auth(access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let member = serde_json::from_str::<DiscordGuildMember>(&text)?;
+    Ok(member.roles)
+}
+
 const DISCORD_TOKEN_URL: &'static str = "https://discord.com/api/oauth2/token";
 const DISCORD_IDENTITY_URL: &'static str = "https://discord.com/api/users/@me";
+const DISCORD_GUILDS_URL: &'static str = "https://discord.com/api/users/@me/guilds";
+
+/// Access levels for mgmt-server endpoints, each including everything allowed by the ones before
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuthzRole {
+    /// Read-only access to server status, logs and players
+    Viewer,
+    /// Day-to-day running of the server, e.g. starting and stopping it or moderating players
+    Operator,
+    /// Everything, including configuration and secrets
+    Admin,
+}
 
 pub struct AuthzManager {
     admin: UserIdentity,
+    /// Mapping from Discord role id to the access it grants
+    role_mapping: HashMap<String, AuthzRole>,
 }
 
 impl AuthzManager {
-    pub fn new(admin: UserIdentity) -> AuthzManager {
-        AuthzManager { admin }
+    pub fn new(admin: UserIdentity, role_mapping: HashMap<String, AuthzRole>) -> AuthzManager {
+        AuthzManager {
+            admin,
+            role_mapping,
+        }
     }
 
-    pub fn authorize(&self, id: &UserIdentity) -> bool {
-        *id == self.admin
+    /// Highest access granted to the user, either as the admin user or by any of their roles
+    pub fn authorize(&self, id: &UserIdentity) -> Option<AuthzRole> {
+        if id.sub == self.admin.sub {
+            return Some(AuthzRole::Admin);
+        }
+        id.roles
+            .iter()
+            .filter_map(|r| self.role_mapping.get(r))
+            .max()
+            .copied()
     }
 }
 
@@ -228,25 +287,40 @@ impl AuthzManager {
 #[derive(Clone, PartialEq)]
 pub struct UserIdentity {
     pub sub: String,
+    /// Discord role ids held by the user in the configured guild
+    pub roles: Vec<String>,
 }
 
 impl UserIdentity {
     pub fn anonymous() -> UserIdentity {
         UserIdentity {
             sub: "anonymous".to_owned(),
+            roles: vec![],
         }
     }
 }
 
 impl From<DiscordUser> for UserIdentity {
     fn from(du: DiscordUser) -> Self {
-        UserIdentity { sub: du.id }
+        UserIdentity {
+            sub: du.id,
+            roles: vec![],
+        }
     }
 }
 
+/// User with admin access
 #[allow(dead_code)]
 pub struct AuthorizedUser(pub UserIdentity);
 
+/// User with at least operator access
+#[allow(dead_code)]
+pub struct OperatorUser(pub UserIdentity);
+
+/// User with at least viewer access
+#[allow(dead_code)]
+pub struct ViewerUser(pub UserIdentity);
+
 #[allow(dead_code)]
 #[derive(serde::Deserialize)]
 struct DiscordUser {
@@ -255,11 +329,18 @@ struct DiscordUser {
     discriminator: String,
 }
 
+#[derive(serde::Deserialize)]
+struct DiscordGuildMember {
+    roles: Vec<String>,
+}
+
 pub enum AuthnProvider {
     None,
     Discord {
         client_id: String,
         client_secret: String,
+        /// Guild to resolve user roles from, if any
+        guild_id: Option<String>,
     },
 }
 
@@ -272,3 +353,35 @@ struct OAuthTokenFullResponse {
     refresh_token: String,
     scope: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(sub: &str, roles: &[&str]) -> UserIdentity {
+        UserIdentity {
+            sub: sub.to_owned(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn authorizes_highest_mapped_role() {
+        let mut role_mapping = HashMap::new();
+        role_mapping.insert("100".to_owned(), AuthzRole::Viewer);
+        role_mapping.insert("200".to_owned(), AuthzRole::Operator);
+        let authz = AuthzManager::new(user("1", &[]), role_mapping);
+
+        assert_eq!(authz.authorize(&user("1", &[])), Some(AuthzRole::Admin));
+        assert_eq!(
+            authz.authorize(&user("2", &["100", "200", "300"])),
+            Some(AuthzRole::Operator)
+        );
+        assert_eq!(
+            authz.authorize(&user("2", &["100"])),
+            Some(AuthzRole::Viewer)
+        );
+        assert_eq!(authz.authorize(&user("2", &["300"])), None);
+        assert!(AuthzRole::Admin > AuthzRole::Operator);
+    }
+}
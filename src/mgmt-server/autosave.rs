@@ -0,0 +1,280 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use fctrl::schema::{
+    regex::{AUTOSAVE_STARTED_RE, SAVE_FINISHED_RE},
+    AgentStreamingMessage, AgentStreamingMessageInner, InternalServerState, ServerSettingsConfig,
+};
+use futures::{pin_mut, StreamExt};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::{
+    clients::AgentApiClient,
+    events::{
+        broker::EventBroker, Event, TopicName, AUTOSAVE_TOPIC_NAME, SERVERSTATE_TOPIC_NAME,
+        STDOUT_TOPIC_NAME,
+    },
+    game_message::{AchievementsPolicy, GameMessage, MessageSource},
+    ha::Leadership,
+};
+
+/// Body of the request sent to the autosave webhook
+#[derive(Serialize)]
+struct AutosaveWebhookBody<'a> {
+    savefile: &'a str,
+    timestamp: DateTime<Utc>,
+}
+
+/// Watches the server logs for completed autosaves, publishing each on the autosave topic tagged
+/// with the autosave name.
+///
+/// If a webhook is configured, it is also sent a POST request after every autosave, so that
+/// external backup tooling only ever copies a completely written file.
+pub struct AutosaveNotifier {
+    webhook_url: Option<url::Url>,
+    /// Autosave currently being written, if any
+    in_progress: Option<String>,
+}
+
+impl AutosaveNotifier {
+    pub fn new(webhook_url: Option<url::Url>) -> AutosaveNotifier {
+        AutosaveNotifier {
+            webhook_url,
+            in_progress: None,
+        }
+    }
+
+    /// Tracks a server log line, returning the name of the autosave it completes, if any
+    fn on_stdout(&mut self, line: &str) -> Option<String> {
+        if let Some(captures) = AUTOSAVE_STARTED_RE.captures(line) {
+            self.in_progress = Some(captures.get(1).unwrap().as_str().to_owned());
+            None
+        } else if SAVE_FINISHED_RE.is_match(line) {
+            // manual saves also finish with this line, but never follow an autosave start
+            self.in_progress.take()
+        } else {
+            None
+        }
+    }
+
+    pub async fn start(mut self, event_broker: Arc<EventBroker>, leadership: Leadership) {
+        if let Some(url) = &self.webhook_url {
+            info!("Autosave completions will be posted to {}", url);
+        }
+        let stdout_sub = event_broker
+            .subscribe(TopicName::new(STDOUT_TOPIC_NAME), |_| true)
+            .await;
+        let http = reqwest::Client::new();
+        tokio::spawn(async move {
+            pin_mut!(stdout_sub);
+            while let Some(event) = stdout_sub.next().await {
+                let line = match serde_json::from_str::<AgentStreamingMessage>(&event.content) {
+                    Ok(AgentStreamingMessage {
+                        content: AgentStreamingMessageInner::ServerStdout(line),
+                        ..
+                    }) => line,
+                    _ => continue,
+                };
+                let savefile = match self.on_stdout(&line) {
+                    Some(savefile) => savefile,
+                    None => continue,
+                };
+
+                info!("Autosave {} completed", savefile);
+                let mut tags = HashMap::new();
+                tags.insert(TopicName::new(AUTOSAVE_TOPIC_NAME), savefile.clone());
+                event_broker
+                    .publish(Event {
+                        tags,
+                        timestamp: event.timestamp,
+                        content: savefile.clone(),
+                    })
+                    .await;
+
+                if let Some(url) = &self.webhook_url {
+                    if !leadership.is_leader() {
+                        continue;
+                    }
+                    let body = AutosaveWebhookBody {
+                        savefile: &savefile,
+                        timestamp: event.timestamp,
+                    };
+                    let result = http
+                        .post(url.clone())
+                        .json(&body)
+                        .send()
+                        .await
+                        .and_then(|r| r.error_for_status());
+                    if let Err(e) = result {
+                        error!("Couldn't notify autosave webhook: {:?}", e);
+                    }
+                }
+            }
+
+            error!("autosave subscriber task is finishing - this should never happen!");
+        });
+    }
+}
+
+/// Warns players in-game shortly before each autosave, since the game freezes while saving unless
+/// non-blocking saving is enabled.
+///
+/// The next autosave is expected one autosave interval after the game starts or the last autosave
+/// completes. The interval counts game time, so announcements are approximate if the game pauses.
+pub struct AutosaveAnnouncer {
+    lead_time: Duration,
+    achievements_policy: AchievementsPolicy,
+}
+
+impl AutosaveAnnouncer {
+    pub fn new(lead_time: Duration, achievements_policy: AchievementsPolicy) -> AutosaveAnnouncer {
+        AutosaveAnnouncer {
+            lead_time,
+            achievements_policy,
+        }
+    }
+
+    pub async fn start(
+        self,
+        agent_client: Arc<AgentApiClient>,
+        event_broker: Arc<EventBroker>,
+        leadership: Leadership,
+    ) {
+        info!(
+            "Autosaves will be announced in-game {} seconds in advance",
+            self.lead_time.as_secs()
+        );
+        let autosave_sub = event_broker
+            .subscribe(TopicName::new(AUTOSAVE_TOPIC_NAME), |_| true)
+            .await;
+        let serverstate_sub = event_broker
+            .subscribe(TopicName::new(SERVERSTATE_TOPIC_NAME), |_| true)
+            .await;
+        tokio::spawn(async move {
+            pin_mut!(autosave_sub);
+            pin_mut!(serverstate_sub);
+            let mut next_announcement: Option<Instant> = None;
+            loop {
+                let announcement_due = async {
+                    match next_announcement {
+                        Some(at) => tokio::time::sleep_until(at).await,
+                        None => futures::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    Some(_) = autosave_sub.next() => {
+                        next_announcement = self.schedule(&agent_client).await;
+                    }
+                    Some(event) = serverstate_sub.next() => {
+                        let states = event.tags.get(&TopicName::new(SERVERSTATE_TOPIC_NAME)).unwrap();
+                        let (from, to) = match states.split_once(' ') {
+                            Some(states) => states,
+                            None => continue,
+                        };
+                        if to == InternalServerState::InGame.as_ref() {
+                            // saving the map returns to in-game, but is followed by an autosave event
+                            if from != InternalServerState::InGameSavingMap.as_ref() {
+                                next_announcement = self.schedule(&agent_client).await;
+                            }
+                        } else if to != InternalServerState::InGameSavingMap.as_ref() {
+                            next_announcement = None;
+                        }
+                    }
+                    _ = announcement_due => {
+                        next_announcement = None;
+                        if !leadership.is_leader() {
+                            continue;
+                        }
+                        let message = GameMessage::AutosaveSoon {
+                            seconds: self.lead_time.as_secs(),
+                        }
+                        .render();
+                        let command = self
+                            .achievements_policy
+                            .broadcast_command(MessageSource::Announcement, &message);
+                        if let Err(e) = agent_client.rcon_command(command).await {
+                            error!("Couldn't announce upcoming autosave via RCON: {:?}", e);
+                        }
+                    }
+                    else => break,
+                }
+            }
+
+            error!("autosave announcer task is finishing - this should never happen!");
+        });
+    }
+
+    /// When to announce the next autosave, from the current server settings
+    async fn schedule(&self, agent_client: &AgentApiClient) -> Option<Instant> {
+        match agent_client.config_server_settings_get().await {
+            Ok(settings) => {
+                let delay = announcement_delay(&settings, self.lead_time)?;
+                debug!("Next autosave announcement in {:?}", delay);
+                Some(Instant::now() + delay)
+            }
+            Err(e) => {
+                warn!(
+                    "Couldn't read autosave interval from server settings: {:?}",
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Time until players should be warned of the next autosave, or None if autosaves don't freeze
+/// the game
+fn announcement_delay(settings: &ServerSettingsConfig, lead_time: Duration) -> Option<Duration> {
+    if settings.autosave_interval == 0 || settings.non_blocking_saving {
+        return None;
+    }
+    let interval = Duration::from_secs(settings.autosave_interval as u64 * 60);
+    interval.checked_sub(lead_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn autosave_completes_on_next_save_finished() {
+        let mut notifier = AutosaveNotifier::new(None);
+        assert_eq!(
+            notifier.on_stdout(
+                " 300.016 Info AppManager.cpp:301: Saving to _autosave2 (non-blocking)."
+            ),
+            None
+        );
+        assert_eq!(
+            notifier.on_stdout(" 300.533 Info AppManagerStates.cpp:1900: Saving finished"),
+            Some("_autosave2".to_owned())
+        );
+        // a manual save afterwards is not an autosave
+        assert_eq!(
+            notifier.on_stdout(" 412.120 Info AppManagerStates.cpp:1900: Saving finished"),
+            None
+        );
+    }
+
+    fn settings(autosave_interval: u32, non_blocking_saving: bool) -> ServerSettingsConfig {
+        let mut settings = test_util::server_settings();
+        settings.autosave_interval = autosave_interval;
+        settings.non_blocking_saving = non_blocking_saving;
+        settings
+    }
+
+    #[test]
+    fn announces_ahead_of_blocking_autosaves_only() {
+        let lead_time = Duration::from_secs(10);
+        assert_eq!(
+            announcement_delay(&settings(5, false), lead_time),
+            Some(Duration::from_secs(290))
+        );
+        assert_eq!(announcement_delay(&settings(5, true), lead_time), None);
+        assert_eq!(announcement_delay(&settings(0, false), lead_time), None);
+    }
+}
@@ -1,6 +1,6 @@
-use rocket::{catch, fs::NamedFile, http::Status, Request};
+use rocket::{catch, http::Status, Request};
 
-use crate::get_dist_path;
+use crate::{get_dist_path, guards::AcceptEncodingHeader, routes::frontend::StaticFile};
 
 #[catch(404)]
 pub fn not_found(_req: &Request) -> String {
@@ -8,10 +8,12 @@ pub fn not_found(_req: &Request) -> String {
 }
 
 #[catch(404)]
-pub async fn fallback_to_index_html() -> Option<(Status, NamedFile)> {
+pub async fn fallback_to_index_html(req: &Request<'_>) -> Option<(Status, StaticFile)> {
     // Required to serve Angular application that uses routing
-    NamedFile::open(get_dist_path().join("index.html"))
-        .await
-        .ok()
-        .map(|nf| (Status::Ok, nf))
+    StaticFile::open(
+        &get_dist_path().join("index.html"),
+        &AcceptEncodingHeader::of(req),
+    )
+    .await
+    .map(|file| (Status::Ok, file))
 }
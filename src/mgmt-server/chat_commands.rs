@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::{pin_mut, StreamExt};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clients::AgentApiClient,
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+    events::{broker::EventBroker, TopicName, CHAT_TOPIC_NAME},
+    game_message::{AchievementsPolicy, MessageSource},
+    ha::Leadership,
+};
+
+lazy_static! {
+    static ref CHAT_COMMANDS_CF: Cf = Cf("chat_commands".to_owned());
+}
+
+/// Chat messages starting with this are treated as commands, e.g. `!players`
+const TRIGGER_PREFIX: char = '!';
+/// Minimum time between two runs of the same command, so players can't flood the server with it
+const COOLDOWN: Duration = Duration::from_secs(5);
+/// Output beyond this many lines is dropped to keep chat readable
+const MAX_RESPONSE_LINES: usize = 10;
+/// Placeholder in the response template replaced with the RCON output
+const OUTPUT_PLACEHOLDER: &str = "{output}";
+
+/// A chat command players can use in-game, persisted in the db
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChatCommand {
+    /// Name players type after the `!`, e.g. "seed"
+    pub name: String,
+    /// Command run via RCON when triggered, e.g. "/seed". Anything players type after the command
+    /// name is ignored, so they can't inject their own commands.
+    pub rcon_command: String,
+    /// Reply sent in-game, where `{output}` is replaced with the RCON output
+    pub response: String,
+}
+
+/// Runs admin-defined commands when players type them in chat, replying in-game with the result.
+///
+/// Only the leader responds to commands. Any instance can manage commands as they are kept in the
+/// db.
+pub struct ChatCommands {
+    agent_client: Arc<AgentApiClient>,
+    db: Arc<Db>,
+    achievements_policy: AchievementsPolicy,
+}
+
+impl ChatCommands {
+    pub async fn start(
+        agent_client: Arc<AgentApiClient>,
+        db: Arc<Db>,
+        achievements_policy: AchievementsPolicy,
+        event_broker: Arc<EventBroker>,
+        leadership: Leadership,
+    ) -> Arc<ChatCommands> {
+        let chat_commands = Arc::new(ChatCommands {
+            agent_client,
+            db,
+            achievements_policy,
+        });
+
+        let chat_sub = event_broker
+            .subscribe(TopicName::new(CHAT_TOPIC_NAME), |_| true)
+            .await;
+        let chat_commands_clone = Arc::clone(&chat_commands);
+        tokio::spawn(async move {
+            pin_mut!(chat_sub);
+            let mut last_runs: HashMap<String, Instant> = HashMap::new();
+            while let Some(event) = chat_sub.next().await {
+                if !leadership.is_leader() {
+                    continue;
+                }
+                let line = event.tags.get(&TopicName::new(CHAT_TOPIC_NAME)).unwrap();
+                let name = match line.split_once(": ").and_then(|(_, m)| parse_trigger(m)) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let command = match chat_commands_clone.get(&name) {
+                    Ok(Some(command)) => command,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        error!("Couldn't read chat command {}: {:?}", name, e);
+                        continue;
+                    }
+                };
+                if matches!(last_runs.get(&name), Some(at) if at.elapsed() < COOLDOWN) {
+                    continue;
+                }
+                last_runs.insert(name, Instant::now());
+                if let Err(e) = chat_commands_clone.run(&command).await {
+                    error!("Error running chat command {}: {:?}", command.name, e);
+                }
+            }
+
+            error!("chat command subscriber task is finishing - this should never happen!");
+        });
+
+        chat_commands
+    }
+
+    pub fn list(&self) -> Result<Vec<ChatCommand>> {
+        self.db
+            .read_prefix(&CHAT_COMMANDS_CF, "")?
+            .into_iter()
+            .map(|r| Ok(serde_json::from_str(&r.value)?))
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<ChatCommand>> {
+        match self.db.read(&CHAT_COMMANDS_CF, name.to_owned())? {
+            Some(record) => Ok(Some(serde_json::from_str(&record.value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Creates or replaces the command with the same name
+    pub fn set(&self, command: &ChatCommand) -> Result<()> {
+        if command.name.is_empty()
+            || !command
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(Error::BadRequest(
+                "Chat command name may only contain lowercase letters, numbers, '-' and '_'"
+                    .to_owned(),
+            ));
+        }
+        if !command.rcon_command.starts_with('/') {
+            return Err(Error::BadRequest(
+                "RCON command must start with '/', e.g. /seed".to_owned(),
+            ));
+        }
+        self.db.write(
+            &CHAT_COMMANDS_CF,
+            &Record {
+                key: command.name.clone(),
+                value: serde_json::to_string(command)?,
+            },
+        )?;
+        info!(
+            "Set chat command !{} to run {}",
+            command.name, command.rcon_command
+        );
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        if self.get(name)?.is_none() {
+            return Err(Error::ChatCommandNotFound);
+        }
+        self.db.delete(&CHAT_COMMANDS_CF, name)
+    }
+
+    async fn run(&self, command: &ChatCommand) -> Result<()> {
+        let output = self
+            .agent_client
+            .rcon_command(command.rcon_command.clone())
+            .await?;
+        for line in format_response(&command.response, &output) {
+            let reply = self
+                .achievements_policy
+                .broadcast_command(MessageSource::ChatCommand, &line);
+            self.agent_client.rcon_command(reply).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the command triggered by a chat message, if any
+fn parse_trigger(message: &str) -> Option<String> {
+    let name = message
+        .trim()
+        .strip_prefix(TRIGGER_PREFIX)?
+        .split_whitespace()
+        .next()?;
+    Some(name.to_lowercase())
+}
+
+/// Lines to reply with in-game, from the response template and the RCON output
+fn format_response(template: &str, output: &str) -> Vec<String> {
+    let template = if template.is_empty() {
+        OUTPUT_PLACEHOLDER
+    } else {
+        template
+    };
+    template
+        .replace(OUTPUT_PLACEHOLDER, output.trim())
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .take(MAX_RESPONSE_LINES)
+        .map(|l| l.to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_trigger_from_chat_message() {
+        assert_eq!(parse_trigger("!Seed"), Some("seed".to_owned()));
+        assert_eq!(
+            parse_trigger(" !players /c game.print('x')"),
+            Some("players".to_owned())
+        );
+        assert_eq!(parse_trigger("hello !seed"), None);
+        assert_eq!(parse_trigger("!"), None);
+    }
+
+    #[test]
+    fn formats_multiline_response() {
+        assert_eq!(
+            format_response(
+                "Online: {output}",
+                "Online players (2):\n  a (online)\n  b (online)\n"
+            ),
+            vec!["Online: Online players (2):", "a (online)", "b (online)"]
+        );
+        assert_eq!(format_response("", " 12345 "), vec!["12345"]);
+    }
+}
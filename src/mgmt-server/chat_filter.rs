@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use futures::{pin_mut, StreamExt};
+use lazy_static::lazy_static;
+use log::{error, info};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clients::AgentApiClient,
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+    events::{broker::EventBroker, TopicName, CHAT_TOPIC_NAME},
+    game_message::GameMessage,
+    ha::Leadership,
+};
+
+lazy_static! {
+    static ref CHAT_FILTER_RULES_CF: Cf = Cf("chat_filter_rules".to_owned());
+}
+
+/// A pattern to look for in chat, and what to do when a message matches it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChatFilterRule {
+    pub id: String,
+    /// Case-insensitive regular expression, e.g. `https?://` to match links
+    pub pattern: String,
+    /// Whisper a warning to the player who sent the message
+    pub warn: bool,
+    /// Leave the message out of the Discord chat link
+    pub hide_from_discord: bool,
+    /// Mute the player once they have sent this many filtered messages
+    pub mute_after: Option<u32>,
+}
+
+/// Checks chat against admin-defined rules, warning or muting players whose messages match.
+///
+/// Rules are kept in the db and compiled in memory. Strikes towards muting are counted per
+/// player until mgmt-server restarts.
+pub struct ChatFilter {
+    db: Arc<Db>,
+    rules: RwLock<Vec<(ChatFilterRule, Regex)>>,
+    strikes: RwLock<HashMap<String, u32>>,
+}
+
+/// What to do about a chat message
+#[derive(Debug, Default, PartialEq)]
+pub struct ChatFilterVerdict {
+    pub warn: bool,
+    pub hide_from_discord: bool,
+    pub mute: bool,
+}
+
+impl ChatFilter {
+    pub fn new(db: Arc<Db>) -> Result<ChatFilter> {
+        let chat_filter = ChatFilter {
+            db,
+            rules: RwLock::new(vec![]),
+            strikes: RwLock::new(HashMap::new()),
+        };
+        chat_filter.reload()?;
+        Ok(chat_filter)
+    }
+
+    pub async fn start(
+        self: &Arc<Self>,
+        agent_client: Arc<AgentApiClient>,
+        event_broker: Arc<EventBroker>,
+        leadership: Leadership,
+    ) {
+        let chat_sub = event_broker
+            .subscribe(TopicName::new(CHAT_TOPIC_NAME), |_| true)
+            .await;
+        let chat_filter = Arc::clone(self);
+        tokio::spawn(async move {
+            pin_mut!(chat_sub);
+            while let Some(event) = chat_sub.next().await {
+                let line = event.tags.get(&TopicName::new(CHAT_TOPIC_NAME)).unwrap();
+                let (player, message) = match line.split_once(": ") {
+                    Some(split) => split,
+                    None => continue,
+                };
+                let verdict = chat_filter.record(player, message);
+                if !leadership.is_leader() {
+                    continue;
+                }
+                if verdict.mute {
+                    info!("Muting {} after repeated filtered chat messages", player);
+                    let command = format!("/mute {}", player);
+                    if let Err(e) = agent_client.rcon_command(command).await {
+                        error!("Couldn't mute player {} via RCON: {:?}", player, e);
+                    }
+                } else if verdict.warn {
+                    if let Err(e) = agent_client
+                        .rcon_whisper(
+                            vec![player.to_owned()],
+                            GameMessage::ChatFilterWarning.render(),
+                        )
+                        .await
+                    {
+                        error!("Couldn't warn player {} via RCON: {:?}", player, e);
+                    }
+                }
+            }
+
+            error!("chat filter subscriber task is finishing - this should never happen!");
+        });
+    }
+
+    pub fn list(&self) -> Vec<ChatFilterRule> {
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(rule, _)| rule.clone())
+            .collect()
+    }
+
+    pub fn create(
+        &self,
+        pattern: String,
+        warn: bool,
+        hide_from_discord: bool,
+        mute_after: Option<u32>,
+    ) -> Result<ChatFilterRule> {
+        compile(&pattern)?;
+        let rule = ChatFilterRule {
+            id: uuid::Uuid::new_v4().to_string(),
+            pattern,
+            warn,
+            hide_from_discord,
+            mute_after,
+        };
+        self.db.write(
+            &CHAT_FILTER_RULES_CF,
+            &Record {
+                key: rule.id.clone(),
+                value: serde_json::to_string(&rule)?,
+            },
+        )?;
+        info!("Created chat filter rule {} ({})", rule.id, rule.pattern);
+        self.reload()?;
+        Ok(rule)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<()> {
+        if self
+            .db
+            .read(&CHAT_FILTER_RULES_CF, id.to_owned())?
+            .is_none()
+        {
+            return Err(Error::ChatFilterRuleNotFound);
+        }
+        self.db.delete(&CHAT_FILTER_RULES_CF, id)?;
+        self.reload()
+    }
+
+    /// Whether the message matches a rule that keeps it out of the Discord chat link
+    pub fn hides_from_discord(&self, message: &str) -> bool {
+        evaluate(&self.rules.read().unwrap(), message, 0)
+            .map_or(false, |verdict| verdict.hide_from_discord)
+    }
+
+    /// Checks a message sent by the player, counting a strike against them if it matches any
+    /// rule
+    fn record(&self, player: &str, message: &str) -> ChatFilterVerdict {
+        let rules = self.rules.read().unwrap();
+        if !rules.iter().any(|(_, regex)| regex.is_match(message)) {
+            return ChatFilterVerdict::default();
+        }
+        let mut strikes = self.strikes.write().unwrap();
+        let count = strikes.entry(player.to_owned()).or_insert(0);
+        *count += 1;
+        evaluate(&rules, message, *count).unwrap_or_default()
+    }
+
+    fn reload(&self) -> Result<()> {
+        let rules = self
+            .db
+            .read_prefix(&CHAT_FILTER_RULES_CF, "")?
+            .into_iter()
+            .map(|r| {
+                let rule: ChatFilterRule = serde_json::from_str(&r.value)?;
+                let regex = compile(&rule.pattern)?;
+                Ok((rule, regex))
+            })
+            .collect::<Result<_>>()?;
+        *self.rules.write().unwrap() = rules;
+        Ok(())
+    }
+}
+
+/// What to do about a message matching any of the rules, given the number of filtered messages
+/// the player has sent including this one. None if no rule matches.
+fn evaluate(
+    rules: &[(ChatFilterRule, Regex)],
+    message: &str,
+    strikes: u32,
+) -> Option<ChatFilterVerdict> {
+    let mut verdict = None;
+    for (rule, regex) in rules {
+        if regex.is_match(message) {
+            let v = verdict.get_or_insert_with(ChatFilterVerdict::default);
+            v.warn |= rule.warn;
+            v.hide_from_discord |= rule.hide_from_discord;
+            v.mute |= matches!(rule.mute_after, Some(n) if strikes >= n);
+        }
+    }
+    verdict
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| Error::BadRequest(format!("Invalid chat filter pattern: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(
+        pattern: &str,
+        hide_from_discord: bool,
+        mute_after: Option<u32>,
+    ) -> (ChatFilterRule, Regex) {
+        (
+            ChatFilterRule {
+                id: pattern.to_owned(),
+                pattern: pattern.to_owned(),
+                warn: true,
+                hide_from_discord,
+                mute_after,
+            },
+            compile(pattern).unwrap(),
+        )
+    }
+
+    #[test]
+    fn combines_actions_of_matching_rules() {
+        let rules = vec![
+            rule(r"https?://", true, None),
+            rule(r"\bheck\b", false, Some(3)),
+        ];
+        assert_eq!(evaluate(&rules, "gg", 0), None);
+        assert_eq!(
+            evaluate(&rules, "see HTTPS://example.com", 1),
+            Some(ChatFilterVerdict {
+                warn: true,
+                hide_from_discord: true,
+                mute: false,
+            })
+        );
+        assert_eq!(
+            evaluate(&rules, "what the heck", 3),
+            Some(ChatFilterVerdict {
+                warn: true,
+                hide_from_discord: false,
+                mute: true,
+            })
+        );
+    }
+}
@@ -5,16 +5,18 @@ use std::{
     }, time::Duration
 };
 
-use chrono::Utc;
-use fctrl::schema::{
-    regex::*,
-    *,
+use chrono::{DateTime, Utc};
+use fctrl::{
+    schema::{regex::*, *},
+    util::{ws_binary, ws_zlib_framing},
 };
 use futures::{future, pin_mut, Future, SinkExt, Stream, StreamExt};
 use log::{error, info, trace, warn};
 use stream_cancel::Valved;
 use tokio::sync::{mpsc, Mutex};
-use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::{
+    client::IntoClientRequest, http::HeaderValue, Message,
+};
 use uuid::Uuid;
 
 use crate::{
@@ -25,31 +27,75 @@ use crate::{
     },
 };
 
+/// Health of the WebSocket connection to the agent, as measured by the keep-alive pings
+#[derive(Clone, Debug, Default)]
+pub struct AgentConnectionHealth {
+    /// Time of the last keep-alive pong from the agent
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    /// Round trip time of the last keep-alive ping
+    pub latency: Option<Duration>,
+    /// Failed attempts to connect since the connection was last established
+    pub reconnect_attempts: u32,
+}
+
 pub struct AgentApiClient {
     event_broker: Arc<EventBroker>,
     ws_addr: url::Url,
     ws_connected: Arc<AtomicBool>,
+    connection_health: Arc<std::sync::Mutex<AgentConnectionHealth>>,
+    /// Long-running operations that have been acked but not yet completed or failed
+    in_flight_operations: Arc<Mutex<HashSet<String>>>,
+    operation_timeout: Duration,
 }
 
 impl AgentApiClient {
-    pub async fn new(ws_addr: url::Url, event_broker: Arc<EventBroker>) -> AgentApiClient {
+    pub async fn new(
+        ws_addr: url::Url,
+        event_broker: Arc<EventBroker>,
+        operation_timeout: Duration,
+    ) -> AgentApiClient {
         let ws_connected = Arc::new(AtomicBool::new(false));
+        let connection_health = Arc::new(std::sync::Mutex::new(AgentConnectionHealth::default()));
+        let in_flight_operations = Arc::new(Mutex::new(HashSet::new()));
 
         let event_broker_clone = Arc::clone(&event_broker);
         let ws_addr_clone = ws_addr.clone();
         let ws_connected_clone = Arc::clone(&ws_connected);
+        let connection_health_clone = Arc::clone(&connection_health);
+        let in_flight_operations_clone = Arc::clone(&in_flight_operations);
         tokio::spawn(async move {
             loop {
                 info!("Attempting to establish WebSocket connection with agent");
-                match connect(ws_addr_clone.clone(), Arc::clone(&event_broker_clone)).await {
+                match connect(
+                    ws_addr_clone.clone(),
+                    Arc::clone(&event_broker_clone),
+                    Arc::clone(&connection_health_clone),
+                )
+                .await
+                {
                     Ok(dc_fut) => {
                         ws_connected_clone.store(true, Ordering::Relaxed);
+                        connection_health_clone.lock().unwrap().reconnect_attempts = 0;
                         dc_fut.await;
                         warn!("Agent WebSocket disconnected, will attempt to reconnect");
                         ws_connected_clone.store(false, Ordering::Relaxed);
+
+                        // responses for in-flight operations will never arrive, fail them now
+                        // rather than leaving their streams hanging
+                        let orphaned = std::mem::take(&mut *in_flight_operations_clone.lock().await);
+                        for operation_id in orphaned {
+                            warn!("Failing operation {} orphaned by agent disconnect", operation_id);
+                            fail_operation(
+                                &event_broker_clone,
+                                operation_id,
+                                "Agent disconnected before the operation completed".to_owned(),
+                            )
+                            .await;
+                        }
                     }
                     Err(e) => {
                         error!("Failed to connect to agent websocket: {:?}", e);
+                        connection_health_clone.lock().unwrap().reconnect_attempts += 1;
                     }
                 }
 
@@ -62,9 +108,20 @@ impl AgentApiClient {
             event_broker,
             ws_addr,
             ws_connected,
+            connection_health,
+            in_flight_operations,
+            operation_timeout,
         }
     }
 
+    pub fn is_connected(&self) -> bool {
+        self.ws_connected.load(Ordering::Relaxed)
+    }
+
+    pub fn connection_health(&self) -> AgentConnectionHealth {
+        self.connection_health.lock().unwrap().clone()
+    }
+
     pub async fn build_version(&self) -> Result<BuildVersion> {
         let request = AgentRequest::BuildVersion;
         let (_id, sub) = self.send_request_and_subscribe(request).await?;
@@ -87,18 +144,43 @@ impl AgentApiClient {
         .await
     }
 
+    pub async fn job_list(&self) -> Result<Vec<JobStatus>> {
+        let request = AgentRequest::JobList;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::JobList(jobs) => Ok(jobs),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
     pub async fn version_install(
         &self,
         version: FactorioVersion,
         force_install: bool,
+        keep_existing: bool,
     ) -> Result<(OperationId, impl Stream<Item = Event>)> {
         let request = AgentRequest::VersionInstall {
             version,
             force_install,
+            keep_existing,
         };
         let (id, sub) = self.send_request_and_subscribe(request).await?;
 
-        ack_or_timeout(sub, Duration::from_millis(500), id).await
+        self.long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await
+    }
+
+    pub async fn version_install_from_archive(
+        &self,
+        path: String,
+    ) -> Result<(OperationId, impl Stream<Item = Event>)> {
+        let request = AgentRequest::VersionInstallFromArchive(path);
+        let (id, sub) = self.send_request_and_subscribe(request).await?;
+
+        self.long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await
     }
 
     pub async fn version_get(&self) -> Result<Option<FactorioVersion>> {
@@ -113,8 +195,24 @@ impl AgentApiClient {
         .await
     }
 
-    pub async fn server_start(&self, savefile: ServerStartSaveFile) -> Result<()> {
-        let request = AgentRequest::ServerStart(savefile);
+    pub async fn version_list(&self) -> Result<Vec<FactorioVersion>> {
+        let request = AgentRequest::VersionList;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::FactorioVersionList(v) => Ok(v),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn server_start(
+        &self,
+        savefile: ServerStartSaveFile,
+        version: Option<FactorioVersion>,
+        profile: Option<String>,
+    ) -> Result<()> {
+        let request = AgentRequest::ServerStart(savefile, version, profile);
         let (_id, sub) = self.send_request_and_subscribe(request).await?;
 
         response_or_timeout(sub, Duration::from_millis(2000), |r| match r.content {
@@ -124,6 +222,22 @@ impl AgentApiClient {
         .await
     }
 
+    pub async fn server_start_plan(
+        &self,
+        savefile: ServerStartSaveFile,
+        version: Option<FactorioVersion>,
+        profile: Option<String>,
+    ) -> Result<ServerStartPlan> {
+        let request = AgentRequest::ServerStartPlan(savefile, version, profile);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(2000), |r| match r.content {
+            AgentOutMessage::ServerStartPlan(plan) => Ok(plan),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
     pub async fn server_stop(&self) -> Result<()> {
         let request = AgentRequest::ServerStop;
         let (_id, sub) = self.send_request_and_subscribe(request).await?;
@@ -135,6 +249,21 @@ impl AgentApiClient {
         .await
     }
 
+    pub async fn server_stop_scheduled(
+        &self,
+        delay_secs: u32,
+        message: Option<String>,
+    ) -> Result<(OperationId, impl Stream<Item = Event> + Unpin)> {
+        let request = AgentRequest::ServerStopScheduled {
+            delay_secs,
+            message,
+        };
+        let (id, sub) = self.send_request_and_subscribe(request).await?;
+
+        self.long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await
+    }
+
     pub async fn server_status(&self) -> Result<ServerStatus> {
         let request = AgentRequest::ServerStatus;
         let (_id, sub) = self.send_request_and_subscribe(request).await?;
@@ -159,7 +288,8 @@ impl AgentApiClient {
         let request = AgentRequest::SaveCreate(savefile_name, map_gen_settings, map_settings);
         let (id, sub) = self.send_request_and_subscribe(request).await?;
 
-        ack_or_timeout(sub, Duration::from_millis(500), id).await
+        self.long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await
     }
 
     pub async fn save_delete(&self, savefile_name: String) -> Result<()> {
@@ -176,15 +306,98 @@ impl AgentApiClient {
         }).await
     }
 
+    pub async fn save_rename(&self, savefile_name: String, new_name: String) -> Result<()> {
+        if savefile_name.trim().is_empty() || new_name.trim().is_empty() {
+            return Err(Error::BadRequest("Empty savefile name".to_owned()));
+        }
+
+        let request = AgentRequest::SaveRename(savefile_name, new_name);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(10000), |r| match r.content {
+            AgentOutMessage::Ok => Ok(()),
+            m => Err(default_message_handler(m)),
+        }).await
+    }
+
+    pub async fn save_copy(&self, savefile_name: String, new_name: String) -> Result<()> {
+        if savefile_name.trim().is_empty() || new_name.trim().is_empty() {
+            return Err(Error::BadRequest("Empty savefile name".to_owned()));
+        }
+
+        let request = AgentRequest::SaveCopy(savefile_name, new_name);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        // copying a large save can take a while
+        response_or_timeout(sub, Duration::from_millis(60000), |r| match r.content {
+            AgentOutMessage::Ok => Ok(()),
+            m => Err(default_message_handler(m)),
+        }).await
+    }
+
+    pub async fn backup_create(
+        &self,
+        savefile_name: String,
+    ) -> Result<(OperationId, impl Stream<Item = Event> + Unpin)> {
+        if savefile_name.trim().is_empty() {
+            return Err(Error::BadRequest("Empty savefile name".to_owned()));
+        }
+
+        let request = AgentRequest::BackupCreate(savefile_name);
+        let (id, sub) = self.send_request_and_subscribe(request).await?;
+
+        self.long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await
+    }
+
+    pub async fn backup_status_get(&self) -> Result<BackupStatus> {
+        let request = AgentRequest::BackupStatusGet;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        // listing the bucket can take a while
+        response_or_timeout(sub, Duration::from_millis(10000), |r| match r.content {
+            AgentOutMessage::BackupStatus(s) => Ok(s),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
     pub async fn save_get(&self, savefile_name: String) -> Result<(OperationId, impl Stream<Item = Event> + Unpin)> {
         if savefile_name.trim().is_empty() {
             return Err(Error::BadRequest("Empty savefile name".to_owned()));
         }
 
-        let request = AgentRequest::SaveGet(savefile_name);
+        let request = AgentRequest::SaveGet(savefile_name, Some(SAVE_GET_ACK_INTERVAL));
         let (id, sub) = self.send_request_and_subscribe(request).await?;
+        let (id, sub) = self
+            .long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await?;
+
+        // ack chunks as they are consumed, so that the agent only sends as fast as the
+        // downstream consumer can keep up
+        let event_broker = Arc::clone(&self.event_broker);
+        let ws_addr = self.ws_addr.clone();
+        let transfer_id = id.clone();
+        let mut received = 0;
+        let sub = sub.then(move |event| {
+            received += 1;
+            let ack = if received % SAVE_GET_ACK_INTERVAL == 0 {
+                Some((Arc::clone(&event_broker), ws_addr.clone(), transfer_id.clone()))
+            } else {
+                None
+            };
+            async move {
+                if let Some((event_broker, ws_addr, transfer_id)) = ack {
+                    match request_event(&ws_addr, AgentRequest::SaveGetAck(transfer_id)) {
+                        Ok((_, ack_event)) => event_broker.publish(ack_event).await,
+                        Err(e) => error!("Error building save transfer ack: {:?}", e),
+                    }
+                }
+                event
+            }
+        });
 
-        ack_or_timeout(sub, Duration::from_millis(500), id).await
+        Ok((id, Box::pin(sub)))
     }
 
     pub async fn save_put(&self, savefile_name: String, savebytes: SaveBytes) -> Result<()> {
@@ -192,10 +405,18 @@ impl AgentApiClient {
             return Err(Error::BadRequest("Empty savefile name".to_owned()));
         }
 
+        // finalising checksums the whole savefile, which takes longer the larger it is
+        let timeout = match (savebytes.is_sentinel(), savebytes.multipart_start) {
+            (true, Some(total_length)) => {
+                Duration::from_millis(10000)
+                    + Duration::from_secs((total_length / SAVE_FINALISE_BYTES_PER_SEC) as u64)
+            }
+            _ => Duration::from_millis(10000),
+        };
         let request = AgentRequest::SaveSet(savefile_name, savebytes);
         let (_id, sub) = self.send_request_and_subscribe(request).await?;
 
-        response_or_timeout(sub, Duration::from_millis(10000), |r| match r.content {
+        response_or_timeout(sub, timeout, |r| match r.content {
             AgentOutMessage::Ok => Ok(()),
             m => Err(default_message_handler(m)),
         }).await
@@ -212,6 +433,65 @@ impl AgentApiClient {
         .await
     }
 
+    /// SHA-256 of a savefile of the given size, which the agent may need to hash in full
+    pub async fn save_checksum_get(
+        &self,
+        savefile_name: String,
+        size_bytes: u64,
+    ) -> Result<String> {
+        if savefile_name.trim().is_empty() {
+            return Err(Error::BadRequest("Empty savefile name".to_owned()));
+        }
+
+        let timeout = Duration::from_millis(10000)
+            + Duration::from_secs(size_bytes / SAVE_FINALISE_BYTES_PER_SEC as u64);
+        let request = AgentRequest::SaveChecksumGet(savefile_name);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, timeout, |r| match r.content {
+            AgentOutMessage::SaveChecksum(sha256) => Ok(sha256),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn save_metadata_get(&self, savefile_name: String) -> Result<SaveMetadata> {
+        if savefile_name.trim().is_empty() {
+            return Err(Error::BadRequest("Empty savefile name".to_owned()));
+        }
+
+        let request = AgentRequest::SaveMetadataGet(savefile_name);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::SaveMetadata(metadata) => Ok(metadata),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn map_preview_generate(
+        &self,
+    ) -> Result<(OperationId, impl Stream<Item = Event> + Unpin)> {
+        let request = AgentRequest::MapPreviewGenerate;
+        let (id, sub) = self.send_request_and_subscribe(request).await?;
+
+        self.long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await
+    }
+
+    pub async fn map_preview_get(&self) -> Result<MapPreviewBytes> {
+        let request = AgentRequest::MapPreviewGet;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(2000), |r| match r.content {
+            AgentOutMessage::MapPreview(Some(preview)) => Ok(preview),
+            AgentOutMessage::MapPreview(None) => Err(Error::MapPreviewNotFound),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
     pub async fn mod_dlcs_get(&self) -> Result<HashSet<Dlc>> {
         let request = AgentRequest::ModDlcsGet;
         let (_id, sub) = self.send_request_and_subscribe(request).await?;
@@ -223,6 +503,19 @@ impl AgentApiClient {
         .await
     }
 
+    /// DLCs shipped with the installed version of Factorio, or none if it isn't installed
+    pub async fn mod_dlcs_available_get(&self) -> Result<HashSet<Dlc>> {
+        let request = AgentRequest::ModDlcsAvailableGet;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::DlcList(mods) => Ok(mods.into_iter().collect()),
+            AgentOutMessage::NotInstalled => Ok(HashSet::new()),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
     pub async fn mod_dlcs_set(&self, dlcs: HashSet<Dlc>) -> Result<()> {
         let request = AgentRequest::ModDlcsSet(dlcs.into_iter().collect());
         let (_id, sub) = self.send_request_and_subscribe(request).await?;
@@ -245,6 +538,21 @@ impl AgentApiClient {
         .await
     }
 
+    pub async fn mod_set_enabled(&self, name: String, enabled: bool) -> Result<()> {
+        let request = if enabled {
+            AgentRequest::ModEnable(name)
+        } else {
+            AgentRequest::ModDisable(name)
+        };
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::Ok => Ok(()),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
     pub async fn mod_list_extract_from_save(&self, savefile_name: String) -> Result<Vec<ModObject>> {
         if savefile_name.trim().is_empty() {
             return Err(Error::BadRequest("Empty savefile name".to_owned()));
@@ -267,7 +575,18 @@ impl AgentApiClient {
         let request = AgentRequest::ModListSet(mods);
         let (id, sub) = self.send_request_and_subscribe(request).await?;
 
-        ack_or_timeout(sub, Duration::from_millis(500), id).await
+        self.long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await
+    }
+
+    pub async fn mod_list_update_all(
+        &self,
+    ) -> Result<(OperationId, impl Stream<Item = Event> + Unpin)> {
+        let request = AgentRequest::ModListUpdateAll;
+        let (id, sub) = self.send_request_and_subscribe(request).await?;
+
+        self.long_running_ack_or_timeout(sub, Duration::from_millis(500), id)
+            .await
     }
 
     pub async fn mod_settings_get(&self) -> Result<ModSettingsBytes> {
@@ -337,6 +656,95 @@ impl AgentApiClient {
         .await
     }
 
+    pub async fn config_diagnostics_get(&self) -> Result<Vec<ConfigWarning>> {
+        let request = AgentRequest::ConfigDiagnosticsGet;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::ConfigDiagnostics(warnings) => Ok(warnings),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn config_performance_get(&self) -> Result<PerformanceConfig> {
+        let request = AgentRequest::ConfigPerformanceGet;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::ConfigPerformance(config) => Ok(config),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn config_performance_set(&self, config: PerformanceConfig) -> Result<()> {
+        let request = AgentRequest::ConfigPerformanceSet(config);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::Ok => Ok(()),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn config_profile_list(&self) -> Result<Vec<LaunchProfile>> {
+        let request = AgentRequest::ConfigProfileList;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::ConfigProfileList(profiles) => Ok(profiles),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn config_profile_save(&self, name: String) -> Result<()> {
+        let request = AgentRequest::ConfigProfileSave(name);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        // mods are snapshotted into the profile
+        response_or_timeout(sub, Duration::from_secs(10), |r| match r.content {
+            AgentOutMessage::Ok => Ok(()),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn config_profile_delete(&self, name: String) -> Result<()> {
+        let request = AgentRequest::ConfigProfileDelete(name);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(2000), |r| match r.content {
+            AgentOutMessage::Ok => Ok(()),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn config_restart_policy_get(&self) -> Result<RestartPolicy> {
+        let request = AgentRequest::ConfigRestartPolicyGet;
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::ConfigRestartPolicy(restart_policy) => Ok(restart_policy),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    pub async fn config_restart_policy_set(&self, restart_policy: RestartPolicy) -> Result<()> {
+        let request = AgentRequest::ConfigRestartPolicySet(restart_policy);
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_millis(500), |r| match r.content {
+            AgentOutMessage::Ok => Ok(()),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
     pub async fn config_rcon_get(&self) -> Result<RconConfig> {
         let request = AgentRequest::ConfigRconGet;
         let (_id, sub) = self.send_request_and_subscribe(request).await?;
@@ -446,6 +854,90 @@ impl AgentApiClient {
         .await
     }
 
+    pub async fn rcon_whisper(&self, players: Vec<String>, message: String) -> Result<()> {
+        let request = AgentRequest::RconWhisper { players, message };
+        let (_id, sub) = self.send_request_and_subscribe(request).await?;
+
+        response_or_timeout(sub, Duration::from_secs(5), |r| match r.content {
+            AgentOutMessage::Ok => Ok(()),
+            m => Err(default_message_handler(m)),
+        })
+        .await
+    }
+
+    /// Whispers a message to the admins among the given online players, if there are any
+    pub async fn rcon_whisper_admins(&self, online: &[String], message: String) -> Result<()> {
+        let online_admins: Vec<String> = self
+            .config_adminlist_get()
+            .await?
+            .into_iter()
+            .filter(|a| online.contains(a))
+            .collect();
+        if online_admins.is_empty() {
+            return Ok(());
+        }
+        self.rcon_whisper(online_admins, message).await
+    }
+
+    /// Waits for the ack of a long-running operation, then tracks the operation against the
+    /// configured deadline. If no Completed or Failed response arrives in time, the operation is
+    /// marked Failed, which also closes its response stream.
+    async fn long_running_ack_or_timeout(
+        &self,
+        sub: impl Stream<Item = Event> + Unpin,
+        no_ack_timeout: Duration,
+        operation_id: OperationId,
+    ) -> Result<(OperationId, impl Stream<Item = Event> + Unpin)> {
+        // subscribe before the ack arrives so that an early completion is not missed
+        let id_clone = operation_id.clone();
+        let deadline_sub = self
+            .event_broker
+            .subscribe(TopicName::new(OPERATION_TOPIC_NAME), move |v| {
+                v == id_clone.0
+            })
+            .await;
+
+        let (operation_id, stream) = ack_or_timeout(sub, no_ack_timeout, operation_id).await?;
+
+        self.in_flight_operations
+            .lock()
+            .await
+            .insert(operation_id.0.clone());
+        let event_broker = Arc::clone(&self.event_broker);
+        let in_flight_operations = Arc::clone(&self.in_flight_operations);
+        let operation_timeout = self.operation_timeout;
+        let id = operation_id.0.clone();
+        tokio::spawn(async move {
+            let finished = tokio::time::timeout(operation_timeout, async move {
+                pin_mut!(deadline_sub);
+                while let Some(e) = deadline_sub.next().await {
+                    if let Ok(r) = serde_json::from_str::<AgentResponseWithId>(&e.content) {
+                        if let OperationStatus::Completed | OperationStatus::Failed = r.status {
+                            return;
+                        }
+                    }
+                }
+            })
+            .await;
+
+            // if the operation was already failed due to agent disconnect, there is nothing to do
+            if in_flight_operations.lock().await.remove(&id) && finished.is_err() {
+                warn!(
+                    "Operation {} did not complete within {:?}, marking as failed",
+                    id, operation_timeout
+                );
+                fail_operation(
+                    &event_broker,
+                    id,
+                    format!("Operation timed out after {:?}", operation_timeout),
+                )
+                .await;
+            }
+        });
+
+        Ok((operation_id, stream))
+    }
+
     async fn send_request_and_subscribe(
         &self,
         request: AgentRequest,
@@ -454,23 +946,7 @@ impl AgentApiClient {
             return Err(Error::AgentDisconnected);
         }
 
-        let id = OperationId(Uuid::new_v4().to_string());
-        let request_with_id = AgentRequestWithId {
-            operation_id: id.clone(),
-            message: request,
-        };
-        let mut tags = HashMap::new();
-        tags.insert(
-            TopicName::new(OUTGOING_TOPIC_NAME),
-            self.ws_addr.to_string(),
-        );
-        let timestamp = Utc::now();
-        let content = serde_json::to_string(&request_with_id)?;
-        let event = Event {
-            tags,
-            timestamp,
-            content,
-        };
+        let (id, event) = request_event(&self.ws_addr, request)?;
 
         let id_clone = id.clone();
         let subscriber = self
@@ -486,24 +962,54 @@ impl AgentApiClient {
     }
 }
 
+/// Wraps a request with a new operation id into an event for the outgoing topic
+fn request_event(ws_addr: &url::Url, request: AgentRequest) -> Result<(OperationId, Event)> {
+    let id = OperationId(Uuid::new_v4().to_string());
+    let request_with_id = AgentRequestWithId {
+        operation_id: id.clone(),
+        message: request,
+    };
+    let mut tags = HashMap::new();
+    tags.insert(TopicName::new(OUTGOING_TOPIC_NAME), ws_addr.to_string());
+    let timestamp = Utc::now();
+    let content = serde_json::to_string(&request_with_id)?;
+    let event = Event {
+        tags,
+        timestamp,
+        content,
+    };
+    Ok((id, event))
+}
+
 /// "Default" handler for incoming messages from agent, to handle errors
 fn default_message_handler(agent_message: AgentOutMessage) -> Error {
     match agent_message {
         AgentOutMessage::AgentBuildVersion(_)
+        | AgentOutMessage::BackupStatus(_)
         | AgentOutMessage::ConfigAdminList(_)
         | AgentOutMessage::ConfigBanList(_)
+        | AgentOutMessage::ConfigDiagnostics(_)
+        | AgentOutMessage::ConfigPerformance(_)
+        | AgentOutMessage::ConfigProfileList(_)
         | AgentOutMessage::ConfigRcon { .. }
+        | AgentOutMessage::ConfigRestartPolicy(_)
         | AgentOutMessage::ConfigSecrets(_)
         | AgentOutMessage::ConfigServerSettings(_)
         | AgentOutMessage::ConfigWhiteList(_)
         | AgentOutMessage::DlcList(_)
         | AgentOutMessage::FactorioVersion(_)
+        | AgentOutMessage::FactorioVersionList(_)
+        | AgentOutMessage::JobList(_)
+        | AgentOutMessage::MapPreview(_)
         | AgentOutMessage::Message(_)
         | AgentOutMessage::ModsList(_)
         | AgentOutMessage::ModSettings(_)
         | AgentOutMessage::RconResponse(_)
+        | AgentOutMessage::SaveChecksum(_)
         | AgentOutMessage::SaveFile(_)
         | AgentOutMessage::SaveList(_)
+        | AgentOutMessage::SaveMetadata(_)
+        | AgentOutMessage::ServerStartPlan(_)
         | AgentOutMessage::ServerStatus(_)
         | AgentOutMessage::SystemResources(_)
         | AgentOutMessage::Ok => Error::AgentCommunicationError,
@@ -515,17 +1021,70 @@ fn default_message_handler(agent_message: AgentOutMessage) -> Error {
         AgentOutMessage::NotInstalled => {
             Error::AgentInternalError("Factorio not installed".to_owned())
         }
+        AgentOutMessage::InsufficientDiskSpace(d) => Error::InsufficientDiskSpace {
+            required_bytes: d.required_bytes,
+            available_bytes: d.available_bytes,
+        },
+        AgentOutMessage::ModVersionsNotFound(not_found) => Error::BadRequest(format!(
+            "Mod versions not found: {}",
+            not_found
+                .iter()
+                .map(|m| format!(
+                    "{} {} (available: [{}])",
+                    m.name,
+                    m.requested_version,
+                    m.available_versions.join(", ")
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        AgentOutMessage::ModNotFound => Error::ModNotFound,
+        AgentOutMessage::ModSettingsUnreadable(unreadable) => {
+            Error::ModSettingsUnreadable(unreadable.to_string())
+        }
+        AgentOutMessage::ProfileNotFound => Error::LaunchProfileNotFound,
+        AgentOutMessage::SaveAlreadyExists => Error::SaveAlreadyExists,
+        AgentOutMessage::SaveInUse => Error::SaveInUse,
         AgentOutMessage::SaveNotFound => Error::SaveNotFound,
     }
 }
 
-const OUTGOING_TOPIC_NAME: &str = "_AGENT_OUTGOING";
+/// Requests to the agent, tagged with the address of the agent they are sent to
+pub const OUTGOING_TOPIC_NAME: &str = "_AGENT_OUTGOING";
+/// Number of savefile chunks to receive before acking to the agent.
+/// Keep this well below the event topic capacity, as up to twice this many chunks may be in flight.
+const SAVE_GET_ACK_INTERVAL: usize = 4;
+/// Conservative rate at which the agent checksums a savefile, such as when finalising an upload
+const SAVE_FINALISE_BYTES_PER_SEC: usize = 50 * 1024 * 1024;
 
 /// Create a WebSocket connection and set it up to pipe incoming / outgoing to the event broker, using pub/sub.
 /// This way we can easily re-create the connection at any time.
-pub async fn connect(ws_addr: url::Url, event_broker: Arc<EventBroker>) -> Result<impl Future> {
-    let (ws_stream, ..) = tokio_tungstenite::connect_async(&ws_addr).await?;
-    info!("Agent WebSocket connected");
+pub async fn connect(
+    ws_addr: url::Url,
+    event_broker: Arc<EventBroker>,
+    connection_health: Arc<std::sync::Mutex<AgentConnectionHealth>>,
+) -> Result<impl Future> {
+    let mut request = ws_addr.as_str().into_client_request()?;
+    if ws_zlib_framing::enabled_from_env() {
+        request.headers_mut().insert(
+            ws_zlib_framing::HEADER,
+            HeaderValue::from_static(ws_zlib_framing::ZLIB),
+        );
+    }
+    if ws_binary::enabled_from_env() {
+        request.headers_mut().insert(
+            ws_binary::HEADER,
+            HeaderValue::from_static(ws_binary::MSGPACK),
+        );
+    }
+    let (ws_stream, response) = tokio_tungstenite::connect_async(request).await?;
+    let compress = ws_zlib_framing::negotiated(response.headers());
+    let binary_encoding = ws_binary::negotiated(response.headers());
+    info!(
+        "Agent WebSocket connected, compression {}, binary encoding {}",
+        if compress { "enabled" } else { "disabled" },
+        if binary_encoding { "enabled" } else { "disabled" }
+    );
     let (ws_write, mut ws_read) = ws_stream.split();
 
     let outgoing_stream = event_broker
@@ -539,10 +1098,14 @@ pub async fn connect(ws_addr: url::Url, event_broker: Arc<EventBroker>) -> Resul
 
     let consecutive_missed_pings = Arc::new(AtomicU8::new(0));
     let consecutive_missed_pings_1 = Arc::clone(&consecutive_missed_pings);
+    // time the last keep-alive ping was sent, to measure the round trip
+    let ping_sent = Arc::new(std::sync::Mutex::new(None));
+    let ping_sent_1 = Arc::clone(&ping_sent);
     let keep_alive_task = tokio::spawn(async move {
         while consecutive_missed_pings_1.load(Ordering::Acquire) < 3 {
             tokio::time::sleep(Duration::from_secs(15)).await;
             let ping = Message::Ping(b"ping".to_vec().into());
+            *ping_sent_1.lock().unwrap() = Some(std::time::Instant::now());
             if let Err(e) = ws_write_1.lock().await.send(ping).await {
                 error!("Failed to send ping: {:?}", e);
             } else {
@@ -558,7 +1121,19 @@ pub async fn connect(ws_addr: url::Url, event_broker: Arc<EventBroker>) -> Resul
     let forward_outgoing_task = tokio::spawn(async move {
         pin_mut!(outgoing_stream);
         while let Some(outgoing_event) = outgoing_stream.next().await {
-            let msg = Message::Text(outgoing_event.content.into());
+            let mut msg = match binary_encoding {
+                true => encode_outgoing_message(outgoing_event.content),
+                false => Message::Text(outgoing_event.content.into()),
+            };
+            if compress {
+                msg = match ws_zlib_framing::compress(msg).await {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        error!("Error compressing request to agent: {:?}", e);
+                        continue;
+                    }
+                };
+            }
             if let Err(e) = ws_write_2.lock().await.send(msg).await {
                 error!("Websocket error sending request to agent: {:?}", e);
                 break;
@@ -570,8 +1145,15 @@ pub async fn connect(ws_addr: url::Url, event_broker: Arc<EventBroker>) -> Resul
 
     let publish_incoming_task = tokio::spawn(async move {
         while let Some(incoming) = ws_read.next().await {
+            let incoming = match incoming {
+                Ok(msg) => Ok(ws_zlib_framing::decompress(msg).await),
+                Err(e) => Err(e),
+            };
             match incoming {
-                Ok(msg) => {
+                Ok(Err(e)) => {
+                    warn!("Failed to decompress message from agent: {:?}", e);
+                }
+                Ok(Ok(msg)) => {
                     match msg {
                         Message::Text(s) => {
                             if let Some(event) = tag_incoming_message(s.to_string()) {
@@ -579,7 +1161,11 @@ pub async fn connect(ws_addr: url::Url, event_broker: Arc<EventBroker>) -> Resul
                             }
                         }
                         Message::Binary(_) => {
-                            // binary message not supported or used
+                            // compressed text messages are decompressed above, leaving
+                            // binary-encoded file transfers
+                            if let Some(event) = decode_incoming_message(&msg) {
+                                event_broker.publish(event).await;
+                            }
                         }
                         Message::Ping(_) => {
                             // tungstenite library handles pings already
@@ -588,6 +1174,11 @@ pub async fn connect(ws_addr: url::Url, event_broker: Arc<EventBroker>) -> Resul
                             // Reset the keepalive
                             trace!("Received pong response, resetting keepalive");
                             consecutive_missed_pings.fetch_min(0, Ordering::Release);
+                            let mut health = connection_health.lock().unwrap();
+                            health.last_heartbeat = Some(Utc::now());
+                            if let Some(sent) = ping_sent.lock().unwrap().take() {
+                                health.latency = Some(sent.elapsed());
+                            }
                         }
                         Message::Close(_) => {
                             warn!("Agent requested to close the websocket connection");
@@ -619,6 +1210,76 @@ pub async fn connect(ws_addr: url::Url, event_broker: Arc<EventBroker>) -> Resul
     Ok(fut_disconnect)
 }
 
+/// Publishes a Failed response for an operation on behalf of the agent, closing any open response
+/// streams for that operation
+async fn fail_operation(event_broker: &EventBroker, operation_id: String, reason: String) {
+    publish_operation_event(
+        event_broker,
+        OperationId(operation_id),
+        OperationStatus::Failed,
+        AgentOutMessage::Error(reason),
+    )
+    .await;
+}
+
+/// Publishes a response for an operation as if it came from the agent, for operations run by the
+/// mgmt-server itself
+pub async fn publish_operation_event(
+    event_broker: &EventBroker,
+    operation_id: OperationId,
+    status: OperationStatus,
+    content: AgentOutMessage,
+) {
+    let response_with_id = AgentResponseWithId {
+        operation_id,
+        status,
+        timestamp: Utc::now(),
+        content,
+    };
+    match serde_json::to_string(&response_with_id) {
+        Ok(s) => {
+            if let Some(event) = tag_incoming_message(s) {
+                event_broker.publish(event).await;
+            }
+        }
+        Err(e) => error!("Failed to serialise operation event: {:?}", e),
+    }
+}
+
+/// Binary-encodes the request if it is a file transfer, otherwise sends it as is
+fn encode_outgoing_message(json: String) -> Message {
+    match serde_json::from_str::<AgentRequestWithId>(&json) {
+        Ok(request) if ws_binary::is_file_transfer(&request.message) => {
+            match ws_binary::encode(&request) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    warn!("Failed to binary-encode request, sending as JSON: {:?}", e);
+                    Message::Text(json.into())
+                }
+            }
+        }
+        _ => Message::Text(json.into()),
+    }
+}
+
+/// Events on the broker carry JSON, so binary-encoded responses are converted back to JSON
+fn decode_incoming_message(msg: &Message) -> Option<Event> {
+    let response = match ws_binary::decode::<AgentResponseWithId>(msg)? {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Failed to decode binary message from agent: {:?}", e);
+            return None;
+        }
+    };
+    match serde_json::to_string(&response) {
+        Ok(json) => tag_incoming_message(json),
+        Err(e) => {
+            error!("Failed to convert binary message from agent to JSON: {:?}", e);
+            None
+        }
+    }
+}
+
 fn tag_incoming_message(s: String) -> Option<Event> {
     if let Ok(response_with_id) = serde_json::from_str::<AgentResponseWithId>(&s) {
         let mut tags = HashMap::new();
@@ -638,6 +1299,12 @@ fn tag_incoming_message(s: String) -> Option<Event> {
             AgentStreamingMessageInner::ServerStdout(stdout_message) => {
                 tag_server_stdout_message(&stdout_message, &mut tags);
             }
+            AgentStreamingMessageInner::ServerCrashed { exit_status, .. } => {
+                tags.insert(TopicName::new(SERVERCRASH_TOPIC_NAME), exit_status);
+            }
+            AgentStreamingMessageInner::OperationRecovered(outcome) => {
+                tags.insert(TopicName::new(RECOVERY_TOPIC_NAME), outcome);
+            }
         }
         let event = Event {
             tags,
@@ -726,7 +1393,7 @@ fn fuse_agent_response_stream(s: impl Stream<Item = Event>) -> impl Stream<Item
     })
 }
 
-fn tag_server_stdout_message(message: &str, tags: &mut HashMap<TopicName, String>) {
+pub fn tag_server_stdout_message(message: &str, tags: &mut HashMap<TopicName, String>) {
     if let Some(chat_captures) = CHAT_DISCORD_ECHO_RE.captures(message) {
         // echo from achievement-preserve setting discord chat link
         // tag separately and not as regular chat
@@ -802,5 +1469,20 @@ fn tag_server_stdout_message(message: &str, tags: &mut HashMap<TopicName, String
             TopicName::new(STDOUT_TOPIC_NAME),
                 StdoutTopicCategory::SystemLog.to_string(),
         );
+        if let Some(peer_captures) = PEER_INFO_RE.captures(message) {
+            let peer_id = peer_captures.get(1).unwrap().as_str();
+            let user = peer_captures.get(2).unwrap().as_str();
+            tags.insert(
+                TopicName::new(PEER_TOPIC_NAME),
+                format!("{} {}", peer_id, user),
+            );
+        } else if PEER_CONNECTION_ISSUE_RE.is_match(message) {
+            if let Some(peer_captures) = PEER_ID_RE.captures(message) {
+                tags.insert(
+                    TopicName::new(CONNECTION_ISSUE_TOPIC_NAME),
+                    peer_captures.get(1).unwrap().as_str().to_owned(),
+                );
+            }
+        }
     }
 }
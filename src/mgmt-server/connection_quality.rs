@@ -0,0 +1,205 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Duration, Utc};
+use fctrl::schema::InternalServerState;
+use futures::{pin_mut, StreamExt};
+use log::{error, warn};
+use strum_macros::AsRefStr;
+use tokio::sync::Mutex;
+
+use crate::events::{
+    broker::EventBroker, TopicName, CONNECTION_ISSUE_TOPIC_NAME, JOIN_TOPIC_NAME,
+    LEAVE_TOPIC_NAME, PEER_TOPIC_NAME, SERVERSTATE_TOPIC_NAME,
+};
+
+/// Connection issues older than this no longer count towards a player's connection quality
+const ISSUE_WINDOW_MINUTES: i64 = 5;
+/// Number of recent connection issues at or above which a connection is considered poor
+const POOR_ISSUE_COUNT: usize = 3;
+
+#[derive(AsRefStr, Clone, Copy, Debug, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum ConnectionQuality {
+    Good,
+    Fair,
+    Poor,
+}
+
+impl ConnectionQuality {
+    fn from_recent_issues(issues: &[DateTime<Utc>], now: DateTime<Utc>) -> ConnectionQuality {
+        let window_start = now - Duration::minutes(ISSUE_WINDOW_MINUTES);
+        let recent = issues.iter().filter(|t| **t >= window_start).count();
+        if recent == 0 {
+            ConnectionQuality::Good
+        } else if recent < POOR_ISSUE_COUNT {
+            ConnectionQuality::Fair
+        } else {
+            ConnectionQuality::Poor
+        }
+    }
+}
+
+pub struct PlayerSession {
+    pub name: String,
+    pub joined_at: DateTime<Utc>,
+    pub connection_quality: ConnectionQuality,
+    /// Total connection issues logged by the server during this session
+    pub connection_issues: usize,
+}
+
+#[derive(Default)]
+struct SessionState {
+    /// Network peer id to player name, as logged by the server when a peer connects
+    peers: HashMap<u32, String>,
+    /// Online players, with the time they joined and the times of any connection issues
+    online: HashMap<String, (DateTime<Utc>, Vec<DateTime<Utc>>)>,
+}
+
+impl SessionState {
+    fn on_connection_issue(&mut self, peer_id: u32, timestamp: DateTime<Utc>) {
+        match self.peers.get(&peer_id) {
+            Some(name) => {
+                if let Some((_, issues)) = self.online.get_mut(name) {
+                    issues.push(timestamp);
+                }
+            }
+            None => warn!("Connection issue logged for unknown peer {}", peer_id),
+        }
+    }
+
+    fn on_leave(&mut self, name: &str) {
+        self.online.remove(name);
+        self.peers.retain(|_, n| n != name);
+    }
+}
+
+/// Tracks online players and the quality of their connection to the server, based on the
+/// connection problems the server logs for each network peer.
+#[derive(Clone)]
+pub struct PlayerSessionTracker {
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl PlayerSessionTracker {
+    pub async fn start(event_broker: Arc<EventBroker>) -> PlayerSessionTracker {
+        let tracker = PlayerSessionTracker {
+            state: Arc::new(Mutex::new(SessionState::default())),
+        };
+
+        let peer_sub = event_broker
+            .subscribe(TopicName::new(PEER_TOPIC_NAME), |_| true)
+            .await;
+        let issue_sub = event_broker
+            .subscribe(TopicName::new(CONNECTION_ISSUE_TOPIC_NAME), |_| true)
+            .await;
+        let join_sub = event_broker
+            .subscribe(TopicName::new(JOIN_TOPIC_NAME), |_| true)
+            .await;
+        let leave_sub = event_broker
+            .subscribe(TopicName::new(LEAVE_TOPIC_NAME), |_| true)
+            .await;
+        let closed_sub = event_broker
+            .subscribe(TopicName::new(SERVERSTATE_TOPIC_NAME), |states_str| {
+                states_str.ends_with(InternalServerState::Closed.as_ref())
+            })
+            .await;
+        let state = Arc::clone(&tracker.state);
+        tokio::spawn(async move {
+            pin_mut!(peer_sub);
+            pin_mut!(issue_sub);
+            pin_mut!(join_sub);
+            pin_mut!(leave_sub);
+            pin_mut!(closed_sub);
+            loop {
+                tokio::select! {
+                    Some(event) = peer_sub.next() => {
+                        let value = event.tags.get(&TopicName::new(PEER_TOPIC_NAME)).unwrap();
+                        if let Some((peer_id, name)) = value.split_once(' ') {
+                            if let Ok(peer_id) = peer_id.parse() {
+                                state.lock().await.peers.insert(peer_id, name.to_owned());
+                            }
+                        }
+                    }
+                    Some(event) = issue_sub.next() => {
+                        let value = event.tags.get(&TopicName::new(CONNECTION_ISSUE_TOPIC_NAME)).unwrap();
+                        if let Ok(peer_id) = value.parse() {
+                            state.lock().await.on_connection_issue(peer_id, event.timestamp);
+                        }
+                    }
+                    Some(event) = join_sub.next() => {
+                        let name = event.tags.get(&TopicName::new(JOIN_TOPIC_NAME)).unwrap().clone();
+                        state.lock().await.online.insert(name, (event.timestamp, vec![]));
+                    }
+                    Some(event) = leave_sub.next() => {
+                        let name = event.tags.get(&TopicName::new(LEAVE_TOPIC_NAME)).unwrap();
+                        state.lock().await.on_leave(name);
+                    }
+                    Some(_) = closed_sub.next() => {
+                        *state.lock().await = SessionState::default();
+                    }
+                    else => break,
+                }
+            }
+
+            error!("player session subscriber task is finishing - this should never happen!");
+        });
+
+        tracker
+    }
+
+    /// Online players, ordered by name
+    pub async fn sessions(&self) -> Vec<PlayerSession> {
+        let now = Utc::now();
+        let state = self.state.lock().await;
+        let mut sessions: Vec<_> = state
+            .online
+            .iter()
+            .map(|(name, (joined_at, issues))| PlayerSession {
+                name: name.clone(),
+                joined_at: *joined_at,
+                connection_quality: ConnectionQuality::from_recent_issues(issues, now),
+                connection_issues: issues.len(),
+            })
+            .collect();
+        sessions.sort_by(|a, b| a.name.cmp(&b.name));
+        sessions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality_degrades_with_recent_issues_only() {
+        let now = Utc::now();
+        let old = now - Duration::minutes(ISSUE_WINDOW_MINUTES + 1);
+        assert_eq!(
+            ConnectionQuality::from_recent_issues(&[old, old, old], now),
+            ConnectionQuality::Good
+        );
+        assert_eq!(
+            ConnectionQuality::from_recent_issues(&[old, now], now),
+            ConnectionQuality::Fair
+        );
+        assert_eq!(
+            ConnectionQuality::from_recent_issues(&[now, now, now], now),
+            ConnectionQuality::Poor
+        );
+    }
+
+    #[test]
+    fn issues_are_attributed_through_peer_id() {
+        let now = Utc::now();
+        let mut state = SessionState::default();
+        state.peers.insert(2, "someone".to_owned());
+        state.online.insert("someone".to_owned(), (now, vec![]));
+        state.on_connection_issue(2, now);
+        state.on_connection_issue(3, now);
+        assert_eq!(state.online["someone"].1.len(), 1);
+
+        state.on_leave("someone");
+        assert!(state.online.is_empty());
+        assert!(state.peers.is_empty());
+    }
+}
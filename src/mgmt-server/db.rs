@@ -36,6 +36,16 @@ impl Db {
         Ok(Db { primary })
     }
 
+    /// Opens a fresh db held entirely in memory, which is gone once dropped
+    pub fn open_in_memory() -> Result<Db> {
+        let mut open_options = rocksdb::Options::default();
+        open_options.set_env(&rocksdb::Env::mem_env()?);
+        open_options.create_if_missing(true);
+        let primary = RocksDbMultiThreaded::open(&open_options, consts::DB_NAME)?;
+
+        Ok(Db { primary })
+    }
+
     pub fn create_cf(&self, name: &Cf) -> Result<()> {
         let opts = rocksdb::Options::default();
         Ok(self.primary.create_cf(&name.0, &opts)?)
@@ -91,6 +101,65 @@ impl Db {
         self.read_range_internal(cfh, read_opts, mode, count)
     }
 
+    /// Reads up to `count` records satisfying the predicate, iterating from the given key or from
+    /// the start or end, and stopping after the `until` key. At most `scan_limit` records are
+    /// examined, so that a search for something rare returns with a continuation point rather
+    /// than scanning the whole column family at once.
+    pub fn search(
+        &self,
+        cf: &Cf,
+        from: Option<String>,
+        until: Option<String>,
+        direction: RangeDirection,
+        count: u32,
+        scan_limit: u32,
+        predicate: impl Fn(&Record) -> bool,
+    ) -> Result<ReadRange> {
+        let cfh = self.get_or_create_cf_handle(cf)?;
+        let mode = match (&from, &direction) {
+            (Some(key), RangeDirection::Forward) => {
+                rocksdb::IteratorMode::From(key.as_bytes(), rocksdb::Direction::Forward)
+            }
+            (Some(key), RangeDirection::Backward) => {
+                rocksdb::IteratorMode::From(key.as_bytes(), rocksdb::Direction::Reverse)
+            }
+            (None, RangeDirection::Forward) => rocksdb::IteratorMode::Start,
+            (None, RangeDirection::Backward) => rocksdb::IteratorMode::End,
+        };
+
+        let mut continue_from = None;
+        let mut records = vec![];
+        let mut scanned = 0;
+        for item in self.primary.iterator_cf(&cfh, mode) {
+            let (k, v) = item?;
+            let past_until = until.as_ref().map_or(false, |until| match direction {
+                RangeDirection::Forward => &*k > until.as_bytes(),
+                RangeDirection::Backward => &*k < until.as_bytes(),
+            });
+            if past_until {
+                break;
+            }
+            let key = String::from_utf8_lossy(&k).to_string();
+            if records.len() as u32 == count || scanned == scan_limit {
+                continue_from = Some(key);
+                break;
+            }
+            scanned += 1;
+            let record = Record {
+                key,
+                value: String::from_utf8_lossy(&v).to_string(),
+            };
+            if predicate(&record) {
+                records.push(record);
+            }
+        }
+
+        Ok(ReadRange {
+            records,
+            continue_from,
+        })
+    }
+
     pub fn write(&self, cf: &Cf, record: &Record) -> Result<()> {
         let cfh = self.get_or_create_cf_handle(cf)?;
         Ok(self
@@ -98,6 +167,48 @@ impl Db {
             .put_cf(&cfh, record.key.as_bytes(), record.value.as_bytes())?)
     }
 
+    /// Reads all records with keys starting with the given prefix, in key order
+    pub fn read_prefix(&self, cf: &Cf, prefix: &str) -> Result<Vec<Record>> {
+        let cfh = self.get_or_create_cf_handle(cf)?;
+        let mode = rocksdb::IteratorMode::From(prefix.as_bytes(), rocksdb::Direction::Forward);
+        let mut records = vec![];
+        for item in self.primary.iterator_cf(&cfh, mode) {
+            let (k, v) = item?;
+            if !k.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            records.push(Record {
+                key: String::from_utf8_lossy(&k).to_string(),
+                value: String::from_utf8_lossy(&v).to_string(),
+            });
+        }
+        Ok(records)
+    }
+
+    pub fn delete(&self, cf: &Cf, key: &str) -> Result<()> {
+        let cfh = self.get_or_create_cf_handle(cf)?;
+        Ok(self.primary.delete_cf(&cfh, key.as_bytes())?)
+    }
+
+    /// Deletes all records in the column family that do not satisfy the predicate,
+    /// returning the number of records deleted
+    pub fn retain(&self, cf: &Cf, predicate: impl Fn(&Record) -> bool) -> Result<usize> {
+        let cfh = self.get_or_create_cf_handle(cf)?;
+        let mut deleted = 0;
+        for item in self.primary.iterator_cf(&cfh, rocksdb::IteratorMode::Start) {
+            let (k, v) = item?;
+            let record = Record {
+                key: String::from_utf8_lossy(&k).to_string(),
+                value: String::from_utf8_lossy(&v).to_string(),
+            };
+            if !predicate(&record) {
+                self.primary.delete_cf(&cfh, &k)?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
     async fn exists(db_path: impl AsRef<Path>) -> bool {
         fs::metadata(db_path).await.map_or(false, |m| m.is_dir())
     }
@@ -511,4 +622,128 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn can_search_within_range() -> GenericResult {
+        fctrl::util::testing::logger_init();
+
+        let db_dir = std::env::temp_dir().join("can_search_within_range");
+        if fs::metadata(&db_dir).await.is_ok() {
+            let _ = fs::remove_dir_all(&db_dir).await;
+        };
+
+        let cf = Cf("can_search_within_range".to_owned());
+        let db = Db::open_or_new(&db_dir).await?;
+
+        for i in 0..10 {
+            let record = Record {
+                key: i.to_string(),
+                value: if i % 2 == 0 { "even" } else { "odd" }.to_owned(),
+            };
+            db.write(&cf, &record)?;
+        }
+
+        let is_even = |r: &Record| r.value == "even";
+        let ret = db.search(
+            &cf,
+            Some("1".to_owned()),
+            Some("7".to_owned()),
+            RangeDirection::Forward,
+            2,
+            100,
+            is_even,
+        )?;
+        let keys: Vec<_> = ret.records.into_iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec!["2".to_owned(), "4".to_owned()]);
+        assert_eq!(ret.continue_from, Some("5".to_owned()));
+
+        // the rest of the range holds only one more match
+        let ret = db.search(
+            &cf,
+            ret.continue_from,
+            Some("7".to_owned()),
+            RangeDirection::Forward,
+            2,
+            100,
+            is_even,
+        )?;
+        let keys: Vec<_> = ret.records.into_iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec!["6".to_owned()]);
+        assert_eq!(ret.continue_from, None);
+
+        // scan limit reached before any match
+        let ret = db.search(&cf, None, None, RangeDirection::Backward, 2, 1, is_even)?;
+        assert!(ret.records.is_empty());
+        assert_eq!(ret.continue_from, Some("8".to_owned()));
+
+        // Clean up
+        let _ = fs::remove_dir_all(&db_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn can_read_prefix() -> GenericResult {
+        fctrl::util::testing::logger_init();
+
+        let db_dir = std::env::temp_dir().join("can_read_prefix");
+        if fs::metadata(&db_dir).await.is_ok() {
+            let _ = fs::remove_dir_all(&db_dir).await;
+        };
+
+        let cf = Cf("can_read_prefix".to_owned());
+        let db = Db::open_or_new(&db_dir).await?;
+
+        for key in ["a/1", "a/2", "ab/1", "b/1"] {
+            db.write(
+                &cf,
+                &Record {
+                    key: key.to_owned(),
+                    value: key.to_owned(),
+                },
+            )?;
+        }
+
+        let ret = db.read_prefix(&cf, "a/")?;
+        let keys: Vec<_> = ret.into_iter().map(|r| r.key).collect();
+        assert_eq!(keys, vec!["a/1".to_owned(), "a/2".to_owned()]);
+
+        // Clean up
+        let _ = fs::remove_dir_all(&db_dir).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn can_retain_matching_records() -> GenericResult {
+        fctrl::util::testing::logger_init();
+
+        let db_dir = std::env::temp_dir().join("can_retain_matching_records");
+        if fs::metadata(&db_dir).await.is_ok() {
+            let _ = fs::remove_dir_all(&db_dir).await;
+        };
+
+        let cf = Cf("can_retain_matching_records".to_owned());
+        let db = Db::open_or_new(&db_dir).await?;
+
+        for key in ["keep1", "drop1", "keep2", "drop2"] {
+            db.write(
+                &cf,
+                &Record {
+                    key: key.to_owned(),
+                    value: key.to_owned(),
+                },
+            )?;
+        }
+
+        let deleted = db.retain(&cf, |r| r.value.starts_with("keep"))?;
+        assert_eq!(deleted, 2);
+        assert!(db.read(&cf, "keep1".to_owned())?.is_some());
+        assert!(db.read(&cf, "drop1".to_owned())?.is_none());
+
+        // Clean up
+        let _ = fs::remove_dir_all(&db_dir).await;
+
+        Ok(())
+    }
 }
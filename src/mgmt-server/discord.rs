@@ -1,32 +1,114 @@
+use std::future::Future;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::{collections::HashMap, time::Duration};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
 
+use chrono::Utc;
 use fctrl::schema::{InternalServerState, ServerStatus};
-use futures::{pin_mut, StreamExt};
-use log::{error, info, warn};
-use serenity::all::{Builder, CreateCommand, CreateWebhook, ExecuteWebhook};
+use log::{debug, error, info, warn};
+use serenity::all::{
+    Builder, CreateCommand, CreateCommandOption, CreateThread, CreateWebhook, EditThread,
+    ExecuteWebhook,
+};
 use serenity::gateway::ActivityData;
 use serenity::{
     client::{Cache, Context, EventHandler},
-    http::Http,
+    http::{Http, HttpError},
     model::prelude::*,
     utils::MessageBuilder,
 };
-use tokio::{sync::mpsc, task::JoinHandle};
+use tokio::{
+    sync::{mpsc, OnceCell},
+    task::JoinHandle,
+    time::Instant,
+};
 
 use crate::SERVERSTATE_TOPIC_NAME;
 use crate::{
+    chat_filter::ChatFilter,
     clients::AgentApiClient,
+    db::Db,
     error::{Error, Result},
-    events::{broker::EventBroker, TopicName, CHAT_TOPIC_NAME, JOIN_TOPIC_NAME, LEAVE_TOPIC_NAME},
+    events::{Event, TopicName, CHAT_TOPIC_NAME, JOIN_TOPIC_NAME, LEAVE_TOPIC_NAME},
+    game_message::{AchievementsPolicy, MessageSource},
+    ha::Leadership,
+    plugins::{Plugin, Subscription},
 };
 
+/// Presence mentions the UPS below this. UPS is measured to about one update per second, so this
+/// leaves some margin below the usual 60.
+const SLOW_UPS_THRESHOLD: f32 = 59.0;
+
+/// Bounds of the exponential backoff between attempts to reach Discord while it is unavailable
+const RETRY_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Undelivered messages held per channel while Discord is unavailable. The oldest are dropped
+/// beyond this.
+const MAX_BUFFERED_MESSAGES: usize = 500;
+
+/// Whether Discord was reachable on the last attempt, and how many messages are waiting to be
+/// sent once it is
+#[derive(Clone)]
+pub struct DiscordConnectivity {
+    connected: Arc<AtomicBool>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl DiscordConnectivity {
+    fn new() -> DiscordConnectivity {
+        DiscordConnectivity {
+            connected: Arc::new(AtomicBool::new(true)),
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub fn pending_messages(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    fn set_connected(&self, connected: bool) {
+        if self.connected.swap(connected, Ordering::Relaxed) != connected {
+            if connected {
+                info!("Discord connectivity restored");
+            } else {
+                warn!("Lost connectivity to Discord");
+            }
+        }
+    }
+}
+
+/// Discord users allowed to run privileged slash commands, e.g. /rcon
+#[derive(Clone, Debug, Default)]
+pub struct DiscordAdmins {
+    pub user_id: Option<u64>,
+    pub role_id: Option<u64>,
+}
+
+impl DiscordAdmins {
+    fn is_admin(&self, user_id: u64, role_ids: &[u64]) -> bool {
+        self.user_id == Some(user_id) || self.role_id.map_or(false, |r| role_ids.contains(&r))
+    }
+}
+
 pub struct DiscordClient {
     alert_tx: Option<mpsc::UnboundedSender<String>>,
     alert_channel_http: Option<Http>,
     alert_channel_id: Option<u64>,
+    password_tx: Option<mpsc::UnboundedSender<String>>,
     cache: Arc<Cache>,
+    connectivity: DiscordConnectivity,
+    chat_link: Option<Arc<DiscordChatLink>>,
     _jh: JoinHandle<()>,
 }
 
@@ -36,113 +118,179 @@ impl DiscordClient {
         guild_id: Option<u64>,
         alert_channel_id: Option<u64>,
         chat_link_channel_id: Option<u64>,
-        chat_link_preserve_achievements: bool,
+        password_channel_id: Option<u64>,
+        chat_link_session_threads: bool,
+        admins: DiscordAdmins,
+        achievements_policy: AchievementsPolicy,
         agent_client: Arc<AgentApiClient>,
-        event_broker: Arc<EventBroker>,
+        chat_filter: Arc<ChatFilter>,
+        db: Arc<Db>,
+        leadership: Leadership,
     ) -> Result<DiscordClient> {
         let cache = Arc::new(Cache::new());
-        let gateway_intents = GatewayIntents::default() | GatewayIntents::MESSAGE_CONTENT;
-        let mut client_builder = serenity::Client::builder(&bot_token, gateway_intents);
+        let connectivity = DiscordConnectivity::new();
+        let mut handler = None;
         if let Some(chat_link_channel_id) = chat_link_channel_id {
             if let Some(guild_id) = guild_id {
-                let handler = Handler {
+                handler = Some(Handler {
                     guild_id: GuildId::new(guild_id),
                     agent_client: Arc::clone(&agent_client),
+                    db: Arc::clone(&db),
                     listen_channel_id: chat_link_channel_id,
-                    chat_link_preserve_achievements,
-                };
-                client_builder = client_builder.event_handler(handler);
+                    admins,
+                    achievements_policy,
+                    leadership: leadership.clone(),
+                    connectivity: connectivity.clone(),
+                    presence_jh: Arc::new(std::sync::Mutex::new(None)),
+                });
             } else {
                 info!("Discord guild id not provided, chat link and command functionality disabled");
             }
         } else {
             info!("Discord chat link channel id not provided, chat link and command functionality will be disabled");
         }
-        let mut client = client_builder.await?;
 
+        // keep the gateway connection up, rebuilding the client with backoff whenever it stops
+        let bot_token_clone = bot_token.clone();
+        let connectivity_clone = connectivity.clone();
         let jh = tokio::spawn(async move {
-            if let Err(e) = client.start().await {
-                error!("Error with Discord client: {:?}", e);
+            let gateway_intents = GatewayIntents::default() | GatewayIntents::MESSAGE_CONTENT;
+            let mut backoff = RETRY_BACKOFF_MIN;
+            loop {
+                let mut client_builder = serenity::Client::builder(&bot_token_clone, gateway_intents);
+                if let Some(handler) = &handler {
+                    client_builder = client_builder.event_handler(handler.clone());
+                }
+                let started = Instant::now();
+                match client_builder.await {
+                    Ok(mut client) => {
+                        if let Err(e) = client.start().await {
+                            error!("Error with Discord client: {:?}", e);
+                        }
+                    }
+                    Err(e) => error!("Couldn't create Discord client: {:?}", e),
+                }
+                connectivity_clone.set_connected(false);
+                if started.elapsed() > RETRY_BACKOFF_MAX {
+                    backoff = RETRY_BACKOFF_MIN;
+                }
+                info!("Reconnecting to Discord in {:?}", backoff);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(RETRY_BACKOFF_MAX);
             }
         });
 
+        let mut chat_link = None;
         if let Some(chat_link_channel_id) = chat_link_channel_id {
             let bot_token_clone = bot_token.clone();
             // regular string type mpsc for non webhook messages
-            let (chat_link_tx, mut rx) = mpsc::unbounded_channel();
+            let (chat_link_tx, rx) = mpsc::unbounded_channel();
             // for webhook messages we need extra data for "nickname impersonation"
-            let (webhook_msg_tx, mut webhook_rx) = mpsc::unbounded_channel();
+            let (webhook_msg_tx, webhook_rx) = mpsc::unbounded_channel();
 
             let http = Arc::new(Http::new(&bot_token_clone));
             let channel = ChannelId::new(chat_link_channel_id);
 
-            // we use a webhook to allow custom display nickname of sent messages
-            // create if not exist
-            let existing_webhooks = channel.webhooks(&http).await?;
-            let webhook = if let Some(w) = existing_webhooks.into_iter().find(|w| w.name.as_ref().is_some_and(|n| n == "fctrl_chat_link_g2d")) {
-                w
-            } else {
-                let create_webhook = CreateWebhook::new("fctrl_chat_link_g2d");
-                create_webhook.execute(&http, channel).await?
-            };
-
             // regular non webhook message handler
             let http_clone = Arc::clone(&http);
-            tokio::spawn(async move {
-                while let Some(line) = rx.recv().await {
-                    if let Err(e) = channel.say(&http_clone, line).await {
-                        error!("Couldn't send message to Discord: {:?}", e);
-                    }
-                }
+            spawn_delivery(rx, connectivity.clone(), move |line: String| {
+                let http = Arc::clone(&http_clone);
+                async move { channel.say(&http, line).await.map(|_| ()) }
             });
 
             // webhook message handler
+            // we use a webhook to allow custom display nickname of sent messages, looked up on
+            // first use so that Discord being down at startup only delays the chat link
             let http_clone = Arc::clone(&http);
-            tokio::spawn(async move {
-                while let Some((name, line)) = webhook_rx.recv().await {
+            let webhook = Arc::new(OnceCell::new());
+            spawn_delivery(webhook_rx, connectivity.clone(), move |(name, line): (String, String)| {
+                let http = Arc::clone(&http_clone);
+                let webhook = Arc::clone(&webhook);
+                async move {
+                    let webhook = webhook
+                        .get_or_try_init(|| get_or_create_chat_link_webhook(&http, channel))
+                        .await?;
                     let content = ExecuteWebhook::new()
                         .username(name)
                         .content(line);
-                    if let Err(e) = webhook.execute(&http_clone, false, content).await {
-                        error!("Couldn't send message to Discord: {:?}", e);
-                    }
+                    webhook.execute(&http, false, content).await.map(|_| ())
                 }
             });
 
-            DiscordClient::create_chat_link_g2d_subscriber(chat_link_tx.clone(), webhook_msg_tx, event_broker)
-                .await;
+            // optionally move session activity into a thread per session
+            let session_tx = if chat_link_session_threads {
+                let (session_tx, session_rx) = mpsc::unbounded_channel();
+                DiscordClient::create_session_thread_handler(
+                    Arc::clone(&http),
+                    channel,
+                    session_rx,
+                );
+                Some(session_tx)
+            } else {
+                None
+            };
+
+            chat_link = Some(Arc::new(DiscordChatLink {
+                send_msg_tx: chat_link_tx,
+                webhook_msg_tx,
+                session_tx,
+                chat_filter,
+            }));
         }
 
         let alert_tx;
         let alert_channel_http;
         if let Some(alert_channel_id) = alert_channel_id {
             let bot_token_clone = bot_token.clone();
-            let (alert_tx_inner, mut rx) = mpsc::unbounded_channel();
+            let (alert_tx_inner, rx) = mpsc::unbounded_channel();
             alert_tx = Some(alert_tx_inner);
             alert_channel_http = Some(Http::new(&bot_token_clone));
-            tokio::spawn(async move {
-                let http = Http::new(&bot_token_clone);
-                let channel = ChannelId::new(alert_channel_id);
-                while let Some(message) = rx.recv().await {
-                    if let Err(e) = channel.say(&http, message).await {
-                        error!("Couldn't send message to Discord: {:?}", e);
-                    }
-                }
+            let http = Arc::new(Http::new(&bot_token_clone));
+            let channel = ChannelId::new(alert_channel_id);
+            spawn_delivery(rx, connectivity.clone(), move |message: String| {
+                let http = Arc::clone(&http);
+                async move { channel.say(&http, message).await.map(|_| ()) }
             });
         } else {
             alert_tx = None;
             alert_channel_http = None;
         }
 
+        let password_tx = if let Some(password_channel_id) = password_channel_id {
+            let bot_token_clone = bot_token.clone();
+            let (password_tx, rx) = mpsc::unbounded_channel();
+            let http = Arc::new(Http::new(&bot_token_clone));
+            let channel = ChannelId::new(password_channel_id);
+            spawn_delivery(rx, connectivity.clone(), move |message: String| {
+                let http = Arc::clone(&http);
+                async move { channel.say(&http, message).await.map(|_| ()) }
+            });
+            Some(password_tx)
+        } else {
+            None
+        };
+
         Ok(DiscordClient {
             alert_tx,
             alert_channel_http,
             alert_channel_id,
+            password_tx,
             cache,
+            connectivity,
+            chat_link,
             _jh: jh,
         })
     }
 
+    pub fn connectivity(&self) -> &DiscordConnectivity {
+        &self.connectivity
+    }
+
+    /// The chat link, if a chat link channel is configured, to be registered as a plugin
+    pub fn chat_link(&self) -> Option<Arc<DiscordChatLink>> {
+        self.chat_link.clone()
+    }
+
     /// Returns a mapping from snowflake id to username#discriminator
     pub async fn get_user_list(&self) -> Result<HashMap<String, String>> {
         if let Some(http) = &self.alert_channel_http {
@@ -218,81 +366,171 @@ impl DiscordClient {
         }
     }
 
-    async fn create_chat_link_g2d_subscriber(
-        send_msg_tx: mpsc::UnboundedSender<String>,
-        webhook_msg_tx: mpsc::UnboundedSender<(String, String)>,
-        event_broker: Arc<EventBroker>,
+    /// Posts a newly rotated game password to the restricted password channel
+    pub fn post_game_password(&self, password: &str, applies_on_restart: bool) -> Result<()> {
+        if let Some(tx) = &self.password_tx {
+            let mut mb = MessageBuilder::new();
+            mb.push("**Game password rotated**, the new password is: ")
+                .push_mono_safe(password);
+            if applies_on_restart {
+                mb.push("\nThe server is currently running, this will take effect on the next restart.");
+            }
+            if let Err(e) = tx.send(mb.build()) {
+                error!("Error sending password line through mpsc channel: {:?}", e);
+                Err(Error::InternalMessaging("Failed to send password".to_owned()))
+            } else {
+                Ok(())
+            }
+        } else {
+            Err(Error::Misconfiguration(
+                "Discord password channel id not provided".to_owned(),
+            ))
+        }
+    }
+
+    /// Creates a thread in the chat link channel for each server session, and posts session
+    /// activity there instead of the channel itself. The thread is archived once the server stops.
+    fn create_session_thread_handler(
+        http: Arc<Http>,
+        channel: ChannelId,
+        mut rx: mpsc::UnboundedReceiver<SessionThreadMessage>,
     ) {
-        let chat_tx = webhook_msg_tx;
-        let join_tx = send_msg_tx.clone();
-        let leave_tx = send_msg_tx.clone();
-        let statechange_tx = send_msg_tx;
-
-        let chat_sub = event_broker
-            .subscribe(TopicName::new(CHAT_TOPIC_NAME), |_| true)
-            .await;
         tokio::spawn(async move {
-            pin_mut!(chat_sub);
-            while let Some(event) = chat_sub.next().await {
-                let line = event.tags.get(&TopicName::new(CHAT_TOPIC_NAME)).unwrap();
-                // assume names cannot have colon
-                match line.split_once(": ") {
-                    Some((nick, message)) => {
-                        if let Err(e) = chat_tx.send((nick.to_string(), message.to_string())) {
-                            error!("Error sending line through mpsc channel: {:?}", e);
-                            break;
+            let mut thread: Option<GuildChannel> = None;
+            let mut started_at = Utc::now();
+            let mut players = HashSet::new();
+            while let Some(message) = rx.recv().await {
+                match message {
+                    SessionThreadMessage::Started => {
+                        if let Some(previous) = thread.take() {
+                            archive_thread(&http, previous).await;
+                        }
+                        started_at = Utc::now();
+                        players.clear();
+                        let create_thread = CreateThread::new(format!(
+                            "Session {}",
+                            started_at.format("%Y-%m-%d %H:%M UTC")
+                        ))
+                        .kind(ChannelType::PublicThread)
+                        .auto_archive_duration(AutoArchiveDuration::OneWeek);
+                        match channel.create_thread(&http, create_thread).await {
+                            Ok(t) => thread = Some(t),
+                            Err(e) => error!("Couldn't create Discord session thread: {:?}", e),
+                        }
+                    }
+                    SessionThreadMessage::Line { player, line } => {
+                        if let Some(player) = player {
+                            players.insert(player);
+                        }
+                        // fall back to the channel if the thread couldn't be created
+                        let res = match &thread {
+                            Some(t) => t.say(&http, line).await,
+                            None => channel.say(&http, line).await,
+                        };
+                        if let Err(e) = res {
+                            error!("Couldn't send message to Discord: {:?}", e);
+                        }
+                    }
+                    SessionThreadMessage::Stopped => {
+                        if let Some(t) = thread.take() {
+                            let minutes = (Utc::now() - started_at).num_minutes();
+                            let summary = format!(
+                                "**Server stopped** after {} minute(s), {} player(s) joined this session",
+                                minutes,
+                                players.len()
+                            );
+                            if let Err(e) = t.say(&http, summary).await {
+                                error!("Couldn't send message to Discord: {:?}", e);
+                            }
+                            archive_thread(&http, t).await;
                         }
-                    },
-                    None => {
-                        warn!("Failed to parse nick and message out of incoming chat link g2d line: {}", line);
                     }
                 }
             }
-
-            error!("Discord chat link g2d chat subscriber is finishing, this should never happen!");
         });
+    }
+}
 
-        let join_sub = event_broker
-            .subscribe(TopicName::new(JOIN_TOPIC_NAME), |_| true)
-            .await;
-        tokio::spawn(async move {
-            pin_mut!(join_sub);
-            while let Some(event) = join_sub.next().await {
-                let user = event
-                    .tags
-                    .get(&TopicName::new(JOIN_TOPIC_NAME))
-                    .unwrap();
-                let message = format!("**{} has joined the server**", user);
-                if let Err(e) = join_tx.send(message) {
+/// Forwards game activity to the chat link channel: chat, joins and leaves, and the server
+/// starting and stopping
+pub struct DiscordChatLink {
+    send_msg_tx: mpsc::UnboundedSender<String>,
+    webhook_msg_tx: mpsc::UnboundedSender<(String, String)>,
+    session_tx: Option<mpsc::UnboundedSender<SessionThreadMessage>>,
+    chat_filter: Arc<ChatFilter>,
+}
+
+impl DiscordChatLink {
+    fn on_chat(&self, line: &str) {
+        // assume names cannot have colon
+        match line.split_once(": ") {
+            Some((_nick, message)) if self.chat_filter.hides_from_discord(message) => {
+                debug!("Chat filter hid message from Discord: {}", line);
+            }
+            Some((nick, message)) => {
+                if let Err(e) = self.webhook_msg_tx.send((nick.to_string(), message.to_string())) {
                     error!("Error sending line through mpsc channel: {:?}", e);
-                    break;
                 }
             }
+            None => {
+                warn!("Failed to parse nick and message out of incoming chat link g2d line: {}", line);
+            }
+        }
+    }
 
-            error!("Discord chat link g2d join subscriber is finishing, this should never happen!");
-        });
+    /// Posts a line of session activity, to the session thread if there is one
+    fn on_session_line(&self, player: Option<String>, line: String) {
+        let res = match &self.session_tx {
+            Some(tx) => tx
+                .send(SessionThreadMessage::Line { player, line })
+                .map_err(|_| ()),
+            None => self.send_msg_tx.send(line).map_err(|_| ()),
+        };
+        if res.is_err() {
+            error!("Error sending line through mpsc channel");
+        }
+    }
 
-        let leave_sub = event_broker
-            .subscribe(TopicName::new(LEAVE_TOPIC_NAME), |_| true)
-            .await;
-        tokio::spawn(async move {
-            pin_mut!(leave_sub);
-            while let Some(event) = leave_sub.next().await {
-                let user = event.tags.get(&TopicName::new(LEAVE_TOPIC_NAME)).unwrap();
-                let message = format!("**{} has left the server**", user);
-                if let Err(e) = leave_tx.send(message) {
+    fn on_serverstate(&self, serverstate_val: &str) {
+        if let Some((_from, to)) = parse_serverstate_topic_value(serverstate_val) {
+            if let Some(tx) = &self.session_tx {
+                let session_message = match to {
+                    InternalServerState::InGame => Some(SessionThreadMessage::Started),
+                    InternalServerState::Closed => Some(SessionThreadMessage::Stopped),
+                    _ => None,
+                };
+                if let Some(session_message) = session_message {
+                    if tx.send(session_message).is_err() {
+                        error!("Error sending session message through mpsc channel");
+                    }
+                }
+            }
+            let message = match to {
+                InternalServerState::InGame => Some(format!("**Server started**")),
+                InternalServerState::Closed => Some(format!("**Server stopped**")),
+                _ => None,
+            };
+            if let Some(message) = message {
+                if let Err(e) = self.send_msg_tx.send(message) {
                     error!("Error sending line through mpsc channel: {:?}", e);
-                    break;
                 }
             }
+        }
+    }
+}
 
-            error!(
-                "Discord chat link g2d leave subscriber is finishing, this should never happen!"
-            );
-        });
+#[rocket::async_trait]
+impl Plugin for DiscordChatLink {
+    fn name(&self) -> &'static str {
+        "discord-chat-link"
+    }
 
-        let statechange_sub = event_broker
-            .subscribe(TopicName::new(SERVERSTATE_TOPIC_NAME), |states_str| {
+    fn subscriptions(&self) -> Vec<Subscription> {
+        vec![
+            Subscription::all(CHAT_TOPIC_NAME),
+            Subscription::all(JOIN_TOPIC_NAME),
+            Subscription::all(LEAVE_TOPIC_NAME),
+            Subscription::filtered(SERVERSTATE_TOPIC_NAME, |states_str| {
                 if let Some((from, to)) = parse_serverstate_topic_value(states_str) {
                     // we only care about "InGame" and "Closed"
                     // special handling for "InGame" -> "InGameSavingMap" -> "InGame" sequence
@@ -304,32 +542,128 @@ impl DiscordClient {
                 } else {
                     false
                 }
-            })
-            .await;
-        tokio::spawn(async move {
-            pin_mut!(statechange_sub);
-            while let Some(event) = statechange_sub.next().await {
-                let serverstate_val = event.tags.get(&TopicName::new(SERVERSTATE_TOPIC_NAME)).unwrap();
-                if let Some((_from, to)) = parse_serverstate_topic_value(serverstate_val) {
-                    let message = match to {
-                        InternalServerState::InGame => Some(format!("**Server started**")),
-                        InternalServerState::Closed => Some(format!("**Server stopped**")),
-                        _ => None,
-                    };
-                    if let Some(message) = message {
-                        if let Err(e) = statechange_tx.send(message) {
-                            error!("Error sending line through mpsc channel: {:?}", e);
-                        }
-                    }
+            }),
+        ]
+    }
+
+    async fn on_event(&self, topic: &TopicName, event: Event) {
+        let value = match event.tags.get(topic) {
+            Some(value) => value,
+            None => return,
+        };
+        match topic.name.as_str() {
+            CHAT_TOPIC_NAME => self.on_chat(value),
+            JOIN_TOPIC_NAME => self.on_session_line(
+                Some(value.clone()),
+                format!("**{} has joined the server**", value),
+            ),
+            LEAVE_TOPIC_NAME => {
+                self.on_session_line(None, format!("**{} has left the server**", value))
+            }
+            SERVERSTATE_TOPIC_NAME => self.on_serverstate(value),
+            _ => (),
+        }
+    }
+}
+
+enum SessionThreadMessage {
+    Started,
+    /// Activity line, optionally attributed to a player for the session summary
+    Line {
+        player: Option<String>,
+        line: String,
+    },
+    Stopped,
+}
+
+/// Delivers messages to Discord in order, retrying with backoff while Discord is unavailable.
+/// Messages arriving in the meantime are buffered and replayed once Discord recovers.
+fn spawn_delivery<T, F, Fut>(
+    mut rx: mpsc::UnboundedReceiver<T>,
+    connectivity: DiscordConnectivity,
+    deliver: F,
+) where
+    T: Clone + Send + 'static,
+    F: Fn(T) -> Fut + Send + 'static,
+    Fut: Future<Output = serenity::Result<()>> + Send,
+{
+    tokio::spawn(async move {
+        let mut buffer = VecDeque::new();
+        let mut backoff = RETRY_BACKOFF_MIN;
+        loop {
+            if buffer.is_empty() {
+                match rx.recv().await {
+                    Some(message) => buffer.push_back(message),
+                    None => break,
                 }
+                connectivity.pending.fetch_add(1, Ordering::Relaxed);
+            }
+            while let Ok(message) = rx.try_recv() {
+                buffer.push_back(message);
+                connectivity.pending.fetch_add(1, Ordering::Relaxed);
+            }
+            while buffer.len() > MAX_BUFFERED_MESSAGES {
+                buffer.pop_front();
+                connectivity.pending.fetch_sub(1, Ordering::Relaxed);
+                warn!("Too many undelivered Discord messages, dropping the oldest");
             }
 
-            error!(
-                "Discord chat link g2d statechange subscriber is finishing, this should never happen!"
-            );
-        });
+            let message = buffer.front().unwrap().clone();
+            match deliver(message).await {
+                Ok(()) => {
+                    connectivity.set_connected(true);
+                    backoff = RETRY_BACKOFF_MIN;
+                }
+                Err(e) if is_transient(&e) => {
+                    connectivity.set_connected(false);
+                    warn!(
+                        "Couldn't reach Discord, retrying {} buffered messages in {:?}: {:?}",
+                        buffer.len(),
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(RETRY_BACKOFF_MAX);
+                    continue;
+                }
+                Err(e) => error!("Couldn't send message to Discord: {:?}", e),
+            }
+            buffer.pop_front();
+            connectivity.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Whether the request might succeed if retried, i.e. Discord is unreachable or erroring rather
+/// than rejecting the message
+fn is_transient(e: &serenity::Error) -> bool {
+    match e {
+        serenity::Error::Http(HttpError::Request(_)) => true,
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+            response.status_code.is_server_error()
+        }
+        _ => false,
     }
+}
 
+/// The webhook used to post in-game chat under player names, created if it doesn't exist yet
+async fn get_or_create_chat_link_webhook(http: &Http, channel: ChannelId) -> serenity::Result<Webhook> {
+    let existing_webhooks = channel.webhooks(http).await?;
+    if let Some(w) = existing_webhooks.into_iter().find(|w| w.name.as_ref().is_some_and(|n| n == "fctrl_chat_link_g2d")) {
+        Ok(w)
+    } else {
+        let create_webhook = CreateWebhook::new("fctrl_chat_link_g2d");
+        create_webhook.execute(http, channel).await
+    }
+}
+
+async fn archive_thread(http: &Http, mut thread: GuildChannel) {
+    if let Err(e) = thread
+        .edit_thread(http, EditThread::new().archived(true))
+        .await
+    {
+        error!("Couldn't archive Discord session thread: {:?}", e);
+    }
 }
 
 fn parse_serverstate_topic_value(states_str: impl AsRef<str>) -> Option<(InternalServerState, InternalServerState)> {
@@ -351,26 +685,30 @@ fn parse_serverstate_topic_value(states_str: impl AsRef<str>) -> Option<(Interna
     }
 }
 
+#[derive(Clone)]
 struct Handler {
     guild_id: GuildId,
     agent_client: Arc<AgentApiClient>,
+    db: Arc<Db>,
     listen_channel_id: u64,
-    chat_link_preserve_achievements: bool,
+    admins: DiscordAdmins,
+    achievements_policy: AchievementsPolicy,
+    leadership: Leadership,
+    connectivity: DiscordConnectivity,
+    /// Presence update task for the current gateway session
+    presence_jh: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
 }
 
 #[serenity::async_trait]
 impl EventHandler for Handler {
     async fn message(&self, _ctx: Context, msg: Message) {
-        if msg.channel_id == self.listen_channel_id && !msg.author.bot {
+        if msg.channel_id == self.listen_channel_id && !msg.author.bot && self.leadership.is_leader() {
             // TODO indicate if it's a reply
             // TODO handle empty messages with embeds, attachments, etc
-            let message_text = format!("{}: {}", msg.author.name, msg.content);
-            let message_text = message_text.replace('\\', "\\\\");
-            let message_text = message_text.replace('\'', "\\'");
-            let command = match self.chat_link_preserve_achievements {
-                true => format!("[Discord] {}", message_text),
-                false => format!("/silent-command game.print('[Discord] {}')", message_text),
-            };
+            let message_text = format!("[Discord] {}: {}", msg.author.name, msg.content);
+            let command = self
+                .achievements_policy
+                .broadcast_command(MessageSource::ChatLink, &message_text);
             if let Err(e) = self.agent_client.rcon_command(command).await {
                 error!(
                     "Couldn't send message via agent_client rcon_command: {:?}",
@@ -381,10 +719,37 @@ impl EventHandler for Handler {
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if !self.leadership.is_leader() {
+            // the leader replica will respond to this
+            return;
+        }
+
         if let Interaction::Command(command) = interaction {
             let response = match command.data.name.as_str() {
                 "server-save" => Some(commands::server_save(self.agent_client.as_ref()).await),
                 "system-resources" => Some(commands::system_resources(self.agent_client.as_ref()).await),
+                "chat-history" => Some(commands::chat_history(self.db.as_ref(), &command.data.options())),
+                "announce" => Some(
+                    commands::announce(
+                        self.agent_client.as_ref(),
+                        &self.achievements_policy,
+                        &command.data.options(),
+                    )
+                    .await,
+                ),
+                "rcon" => {
+                    let role_ids: Vec<u64> = command
+                        .member
+                        .as_ref()
+                        .map(|m| m.roles.iter().map(|r| r.get()).collect())
+                        .unwrap_or_default();
+                    if self.admins.is_admin(command.user.id.get(), &role_ids) {
+                        Some(commands::rcon(self.agent_client.as_ref(), &command.data.options()).await)
+                    } else {
+                        warn!("Rejected /rcon from non-admin Discord user {}", command.user.name);
+                        Some(commands::ephemeral("Only admins can run RCON commands"))
+                    }
+                }
                 _ => {
                     warn!("unimplemented interaction command");
                     None
@@ -399,24 +764,63 @@ impl EventHandler for Handler {
     }
 
     async fn ready(&self, ctx: Context, _ready: Ready) {
+        self.connectivity.set_connected(true);
         if let Err(e) = self.guild_id.set_commands(&ctx.http, vec![
             CreateCommand::new("server-save").description("Trigger a server-side save"),
-            CreateCommand::new("system-resources").description("Get system resource usage statistics")
+            CreateCommand::new("system-resources").description("Get system resource usage statistics"),
+            CreateCommand::new("chat-history")
+                .description("Show recent in-game chat")
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "count", "Number of lines to show")
+                        .min_int_value(1)
+                        .max_int_value(commands::CHAT_HISTORY_MAX_COUNT as u64),
+                ),
+            CreateCommand::new("announce")
+                .description("Make an announcement to all players in-game")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "message", "Announcement text")
+                        .required(true),
+                ),
+            CreateCommand::new("rcon")
+                .description("Run a command on the server via RCON (admins only)")
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "command", "Command to run, e.g. /evolution")
+                        .required(true),
+                ),
         ]).await {
             error!("Error creating slash commands: {:?}", e);
         }
 
         // update presence info with server status every 15 seconds
         let agent_client = Arc::clone(&self.agent_client);
-        tokio::spawn(async move {
+        let leadership = self.leadership.clone();
+        let presence_jh = tokio::spawn(async move {
             loop {
+                if !leadership.is_leader() {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
                 match agent_client.server_status().await {
                     Ok(ss) => {
                         let formatted = match ss {
                             ServerStatus::NotRunning
                             | ServerStatus::PreGame
                             | ServerStatus::PostGame => "Server offline".to_owned(),
-                            ServerStatus::InGame { player_count } => {
+                            ServerStatus::InGame {
+                                player_count,
+                                ups: Some(ups),
+                                ..
+                            } if ups < SLOW_UPS_THRESHOLD => format!(
+                                "{} players online, slowed to {:.0} UPS",
+                                player_count, ups
+                            ),
+                            ServerStatus::InGame {
+                                player_count,
+                                map_clock: Some(map_clock),
+                                ..
+                            } => format!("{} players online, around {}", player_count, map_clock),
+                            ServerStatus::InGame { player_count, .. } => {
                                 format!("{} players online", player_count)
                             }
                         };
@@ -431,16 +835,171 @@ impl EventHandler for Handler {
                 tokio::time::sleep(Duration::from_secs(5)).await;
             }
         });
+        // replace the task from any previous session, which holds a stale context
+        if let Some(previous) = self.presence_jh.lock().unwrap().replace(presence_jh) {
+            previous.abort();
+        }
 
         info!("Discord event handler ready");
     }
 }
 
 mod commands {
+    use fctrl::schema::{regex::CHAT_RE, AgentStreamingMessage, AgentStreamingMessageInner};
     use log::{error, info};
-    use serenity::all::{CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage};
+    use serenity::all::{
+        CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, ResolvedOption,
+        ResolvedValue,
+    };
+
+    use crate::{
+        clients::AgentApiClient,
+        db::{Cf, Db},
+        events::StdoutTopicCategory,
+        game_message::{AchievementsPolicy, GameMessage, MessageSource},
+    };
 
-    use crate::clients::AgentApiClient;
+    pub const CHAT_HISTORY_MAX_COUNT: u32 = 50;
+    const CHAT_HISTORY_DEFAULT_COUNT: u32 = 20;
+    // Discord limit for embed descriptions
+    const EMBED_DESCRIPTION_MAX_LEN: usize = 4096;
+    // Discord limit for message content
+    const MESSAGE_MAX_LEN: usize = 2000;
+    const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+    pub fn chat_history(db: &Db, options: &[ResolvedOption<'_>]) -> CreateInteractionResponse {
+        let count = options
+            .iter()
+            .find_map(|o| match (o.name, &o.value) {
+                ("count", ResolvedValue::Integer(i)) => Some(*i),
+                _ => None,
+            })
+            .map_or(CHAT_HISTORY_DEFAULT_COUNT, |i| {
+                i.clamp(1, CHAT_HISTORY_MAX_COUNT as i64) as u32
+            });
+
+        let cf = Cf(StdoutTopicCategory::Chat.to_string());
+        match db.read_range_tail(&cf, count) {
+            Ok(range) => {
+                // records are newest first, keep as many of the newest lines as will fit
+                let mut lines = vec![];
+                let mut len = 0;
+                for record in range.records {
+                    let line = match format_chat_record(&record.value) {
+                        Some(line) => line,
+                        None => continue,
+                    };
+                    len += line.len() + 1;
+                    if len > EMBED_DESCRIPTION_MAX_LEN {
+                        break;
+                    }
+                    lines.push(line);
+                }
+                lines.reverse();
+
+                let description = if lines.is_empty() {
+                    "No chat messages".to_owned()
+                } else {
+                    lines.join("\n")
+                };
+                let embed = CreateEmbed::new()
+                    .title(format!("Last {} chat messages", lines.len()))
+                    .description(description);
+                CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().embed(embed))
+            }
+            Err(e) => {
+                error!("Couldn't read chat history from db: {:?}", e);
+                let data = CreateInteractionResponseMessage::new().content("Failed to read chat history");
+                CreateInteractionResponse::Message(data)
+            }
+        }
+    }
+
+    /// Formats a stored chat log record as `[timestamp] user: message`
+    fn format_chat_record(value: &str) -> Option<String> {
+        let message = serde_json::from_str::<AgentStreamingMessage>(value).ok()?;
+        let AgentStreamingMessageInner::ServerStdout(line) = message.content else {
+            return None;
+        };
+        let captures = CHAT_RE.captures(&line)?;
+        Some(format!(
+            "`[{}]` **{}**: {}",
+            captures.get(1)?.as_str(),
+            captures.get(2)?.as_str(),
+            captures.get(3)?.as_str()
+        ))
+    }
+
+    pub async fn announce(
+        agent_client: &AgentApiClient,
+        achievements_policy: &AchievementsPolicy,
+        options: &[ResolvedOption<'_>],
+    ) -> CreateInteractionResponse {
+        let message = options.iter().find_map(|o| match (o.name, &o.value) {
+            ("message", ResolvedValue::String(s)) => Some(*s),
+            _ => None,
+        });
+        let content = match message {
+            Some(message) => {
+                let command = achievements_policy.broadcast_command(
+                    MessageSource::Announcement,
+                    &GameMessage::Announcement { message }.render(),
+                );
+                match agent_client.rcon_command(command).await {
+                    Ok(_) => "Ok".to_owned(),
+                    Err(e) => {
+                        error!("Couldn't send announcement via RCON: {:?}", e);
+                        "Failed to send announcement".to_owned()
+                    }
+                }
+            }
+            None => "Missing announcement message".to_owned(),
+        };
+        CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(content))
+    }
+
+    /// Runs the command via RCON, replying with the output only to the admin who ran it
+    pub async fn rcon(
+        agent_client: &AgentApiClient,
+        options: &[ResolvedOption<'_>],
+    ) -> CreateInteractionResponse {
+        let command = options.iter().find_map(|o| match (o.name, &o.value) {
+            ("command", ResolvedValue::String(s)) => Some(*s),
+            _ => None,
+        });
+        let content = match command {
+            Some(command) => {
+                info!("Running RCON command from Discord: {}", command);
+                match agent_client.rcon_command(command.to_owned()).await {
+                    Ok(output) if output.trim().is_empty() => "Ok".to_owned(),
+                    Ok(output) => {
+                        // leave room for the code block markers
+                        let output: String = output
+                            .replace("```", "'''")
+                            .chars()
+                            .take(MESSAGE_MAX_LEN - 8)
+                            .collect();
+                        format!("```\n{}\n```", output)
+                    }
+                    Err(e) => {
+                        error!("Couldn't run RCON command from Discord: {:?}", e);
+                        format!("Failed to run RCON command: {}", e)
+                    }
+                }
+            }
+            None => "Missing command".to_owned(),
+        };
+        ephemeral(content)
+    }
+
+    /// A reply only visible to the user who ran the command
+    pub fn ephemeral(content: impl Into<String>) -> CreateInteractionResponse {
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .ephemeral(true)
+                .content(content),
+        )
+    }
 
     pub async fn server_save(agent_client: &AgentApiClient) -> CreateInteractionResponse {
         if let Err(e) = agent_client.rcon_command("/server-save".to_owned()).await {
@@ -461,7 +1020,19 @@ mod commands {
                     .title("System resource statistics")
                     .field("CPU total", format!("{:.2}%", system_resources.cpu_total), false)
                     .fields(system_resources.cpus.iter().enumerate().map(|(i, cpu)| (format!("cpu{}", i), format!("{:.2}%", cpu), true)))
-                    .field("Memory used", format!("{:.2}%", (system_resources.mem_used_bytes as f64 / system_resources.mem_total_bytes as f64) * 100 as f64), false);
+                    .field("Memory used", format!("{:.2}%", (system_resources.mem_used_bytes as f64 / system_resources.mem_total_bytes as f64) * 100 as f64), false)
+                    .fields(system_resources.disks.iter().map(|d| (
+                        format!("Disk {}", d.path),
+                        format!("{:.2} GiB free of {:.2} GiB", d.available_bytes as f64 / GIB, d.total_bytes as f64 / GIB),
+                        false,
+                    )));
+                let embed = match system_resources.server_process {
+                    Some(p) => embed
+                        .field("Server CPU", format!("{:.2}%", p.cpu_usage), true)
+                        .field("Server memory", format!("{:.2} GiB", p.mem_rss_bytes as f64 / GIB), true)
+                        .field("Server uptime", format!("{}h {}m", p.uptime_secs / 3600, (p.uptime_secs % 3600) / 60), true),
+                    None => embed.field("Server", "Not running", false),
+                };
                 CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().embed(embed))
             },
             Err(e) => {
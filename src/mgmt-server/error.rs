@@ -19,7 +19,9 @@ pub enum Error {
     AgentTimeout,
     AuthInvalid,
     AuthRefreshUnavailable,
+    WebhookUnauthorized,
     BadRequest(String),
+    PayloadTooLarge(String),
     Db(String),
     InternalMessaging(String),
     Misconfiguration(String),
@@ -28,11 +30,31 @@ pub enum Error {
     Rpc(String),
 
     // Specific errors
-    FactorioDatFileParseError(factorio_file_parser::Error),
     DiscordAlertingDisabled,
+    FeatureFlagNotFound,
+    InsufficientDiskSpace {
+        required_bytes: u64,
+        available_bytes: u64,
+    },
     InvalidLink,
+    LaunchProfileNotFound,
+    RangeNotSatisfiable,
+    MapPreviewNotFound,
+    ModNotFound,
+    OperationWebhookNotFound,
+    PlayerNoteNotFound,
+    PreferenceNotFound,
+    SettingsProfileNotFound,
     ModSettingsNotInitialised,
+    ModSettingsUnreadable(String),
+    SaveAlreadyExists,
+    SaveDuplicate(String),
+    SaveInUse,
     SaveNotFound,
+    ScheduleNotFound,
+    ChatCommandNotFound,
+    ChatFilterRuleNotFound,
+    AlertRuleNotFound,
     SecretsNotInitialised,
 
     // Generic wrappers around external error types
@@ -52,12 +74,6 @@ impl std::fmt::Display for Error {
     }
 }
 
-impl From<factorio_file_parser::Error> for Error {
-    fn from(e: factorio_file_parser::Error) -> Self {
-        Error::FactorioDatFileParseError(e)
-    }
-}
-
 impl From<rocksdb::Error> for Error {
     fn from(e: rocksdb::Error) -> Self {
         Error::DbExternal(e)
@@ -122,7 +138,6 @@ impl<'r> Responder<'r, 'static> for Error {
             | Error::Io(_)
             | Error::Json(_)
             | Error::Reqwest(_)
-            | Error::FactorioDatFileParseError(_)
             | Error::Misconfiguration(_)
             | Error::NotImplemented
             | Error::Rpc(_) => Status::InternalServerError,
@@ -130,8 +145,28 @@ impl<'r> Responder<'r, 'static> for Error {
             | Error::AuthInvalid
             | Error::AuthRefreshUnavailable
             | Error::MetricInvalidKey(_) => Status::BadRequest,
+            Error::PayloadTooLarge(_) => Status::PayloadTooLarge,
+            Error::WebhookUnauthorized => Status::Unauthorized,
             Error::SaveNotFound
-            | Error::InvalidLink => Status::NotFound,
+            | Error::ScheduleNotFound
+            | Error::ChatCommandNotFound
+            | Error::ChatFilterRuleNotFound
+            | Error::AlertRuleNotFound
+            | Error::FeatureFlagNotFound
+            | Error::InvalidLink
+            | Error::LaunchProfileNotFound
+            | Error::MapPreviewNotFound
+            | Error::ModNotFound
+            | Error::OperationWebhookNotFound
+            | Error::PlayerNoteNotFound
+            | Error::PreferenceNotFound
+            | Error::SettingsProfileNotFound => Status::NotFound,
+            Error::SaveAlreadyExists | Error::SaveDuplicate(_) | Error::SaveInUse => {
+                Status::Conflict
+            }
+            Error::RangeNotSatisfiable => Status::RangeNotSatisfiable,
+            Error::InsufficientDiskSpace { .. } => Status::InsufficientStorage,
+            Error::ModSettingsUnreadable(_) => Status::UnprocessableEntity,
             Error::ModSettingsNotInitialised | Error::SecretsNotInitialised => Status::NoContent,
         };
 
@@ -1,4 +1,10 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use futures::{future, Stream, StreamExt};
 use log::warn;
@@ -9,17 +15,26 @@ use super::{Event, TopicName};
 
 pub struct EventBroker {
     topics: RwLock<HashMap<TopicName, broadcast::Sender<Event>>>,
+    topic_capacity: usize,
+    /// Total number of events skipped by lagging subscribers
+    dropped: Arc<AtomicU64>,
 }
 
 impl EventBroker {
-    const TOPIC_CAPACITY: usize = 100;
+    pub const DEFAULT_TOPIC_CAPACITY: usize = 100;
 
-    pub fn new() -> EventBroker {
+    pub fn new(topic_capacity: usize) -> EventBroker {
         EventBroker {
             topics: RwLock::new(HashMap::new()),
+            topic_capacity,
+            dropped: Arc::new(AtomicU64::new(0)),
         }
     }
 
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
     pub async fn publish(&self, event: Event) {
         for topic_name in event.tags.keys() {
             let r_guard = self.topics.read().await;
@@ -54,11 +69,13 @@ impl EventBroker {
             rx = self.create_topic_with_receiver(topic_name.clone()).await;
         }
 
+        let dropped = Arc::clone(&self.dropped);
         Box::pin(
             BroadcastStream::new(rx)
                 .filter_map(move |r| {
                     let filter = filter.clone();
                     let topic_name = topic_name.clone();
+                    let dropped = Arc::clone(&dropped);
                     async move {
                         match r {
                             Ok(event) => {
@@ -70,6 +87,7 @@ impl EventBroker {
                             }
                             Err(BroadcastStreamRecvError::Lagged(skipped)) => {
                                 warn!("Subscriber lagged, skipped {} messages", skipped);
+                                dropped.fetch_add(skipped, Ordering::Relaxed);
                                 None
                             }
                         }
@@ -87,7 +105,7 @@ impl EventBroker {
         let mut w_guard = self.topics.write().await;
         match w_guard.entry(topic_name) {
             Entry::Vacant(e) => {
-                let (tx, rx) = broadcast::channel(EventBroker::TOPIC_CAPACITY);
+                let (tx, rx) = broadcast::channel(self.topic_capacity);
                 e.insert(tx);
                 rx
             }
@@ -99,7 +117,7 @@ impl EventBroker {
         let mut w_guard = self.topics.write().await;
         let sender = match w_guard.entry(topic_name) {
             Entry::Vacant(e) => {
-                let (tx, ..) = broadcast::channel(EventBroker::TOPIC_CAPACITY);
+                let (tx, ..) = broadcast::channel(self.topic_capacity);
                 e.insert(tx)
             }
             Entry::Occupied(o) => o.into_mut(),
@@ -122,7 +140,7 @@ mod tests {
     async fn subscriber_can_receive_published_event() {
         fctrl::util::testing::logger_init();
 
-        let broker = EventBroker::new();
+        let broker = EventBroker::new(EventBroker::DEFAULT_TOPIC_CAPACITY);
 
         let topic = TopicName::new("test_tag");
         let test_event_tags = [(topic.clone(), "yes".to_owned())]
@@ -148,7 +166,7 @@ mod tests {
     async fn subscriber_filters_unwanted_published_event() {
         fctrl::util::testing::logger_init();
 
-        let broker = EventBroker::new();
+        let broker = EventBroker::new(EventBroker::DEFAULT_TOPIC_CAPACITY);
 
         let topic = TopicName::new("test_tag");
         let test_event_tags = [(topic.clone(), "yes".to_owned())]
@@ -173,7 +191,7 @@ mod tests {
     async fn publishing_to_non_subscribed_topic_drops_events() {
         fctrl::util::testing::logger_init();
 
-        let broker = EventBroker::new();
+        let broker = EventBroker::new(EventBroker::DEFAULT_TOPIC_CAPACITY);
 
         let topic = TopicName::new("test_tag");
         let test_event_tags = [(topic.clone(), "yes".to_owned())]
@@ -192,4 +210,28 @@ mod tests {
         pin_mut!(s);
         assert_eq!(s.next().now_or_never(), None);
     }
+
+    #[tokio::test]
+    async fn lagging_subscriber_counts_dropped_events() {
+        fctrl::util::testing::logger_init();
+
+        let broker = EventBroker::new(2);
+
+        let topic = TopicName::new("test_tag");
+        let s = broker.subscribe(topic.clone(), |_| true).await;
+        pin_mut!(s);
+
+        for i in 0..5 {
+            let test_event = Event {
+                tags: [(topic.clone(), "yes".to_owned())].iter().cloned().collect(),
+                timestamp: Utc::now(),
+                content: i.to_string(),
+            };
+            broker.publish(test_event).await;
+        }
+
+        let e = s.next().await.unwrap();
+        assert_eq!(e.content, "3");
+        assert_eq!(broker.dropped_count(), 3);
+    }
 }
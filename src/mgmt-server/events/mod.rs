@@ -34,7 +34,12 @@ pub const CHAT_TOPIC_NAME: &'static str =           "chat";
 pub const JOIN_TOPIC_NAME: &'static str =           "join";
 pub const LEAVE_TOPIC_NAME: &'static str =          "leave";
 pub const RPC_TOPIC_NAME: &'static str =            "rpc";
+pub const PEER_TOPIC_NAME: &'static str =           "peer";
+pub const CONNECTION_ISSUE_TOPIC_NAME: &'static str = "connectionissue";
 pub const SERVERSTATE_TOPIC_NAME: &'static str =    "serverstate";
+pub const SERVERCRASH_TOPIC_NAME: &'static str =    "servercrash";
+pub const AUTOSAVE_TOPIC_NAME: &'static str =       "autosave";
+pub const RECOVERY_TOPIC_NAME: &'static str =       "recovery";
 
 #[derive(EnumString, AsRefStr, Display)]
 pub enum StdoutTopicCategory {
@@ -0,0 +1,78 @@
+use std::{str::FromStr, sync::Arc};
+
+use lazy_static::lazy_static;
+use log::info;
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter, EnumString};
+
+use crate::{
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+};
+
+lazy_static! {
+    static ref FEATURE_FLAGS_CF: Cf = Cf("feature_flags".to_owned());
+}
+
+/// Experimental subsystems that can be switched on or off at runtime.
+///
+/// New flags should default to disabled, so the feature ships dark until enabled per deployment.
+#[derive(AsRefStr, Clone, Copy, Debug, EnumIter, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum FeatureFlag {
+    /// Running admin-defined cron schedules
+    Scheduler,
+}
+
+impl FeatureFlag {
+    fn default_enabled(&self) -> bool {
+        match self {
+            // schedules predate the flag, so existing deployments keep running them
+            FeatureFlag::Scheduler => true,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FeatureFlag::Scheduler => "Run scheduled restarts, mod updates and broadcasts",
+        }
+    }
+}
+
+/// Admin-editable feature flags, kept in the db so every instance sees the same values
+pub struct FeatureFlags {
+    db: Arc<Db>,
+}
+
+impl FeatureFlags {
+    pub fn new(db: Arc<Db>) -> FeatureFlags {
+        FeatureFlags { db }
+    }
+
+    /// Whether the feature is enabled, falling back to its default if never set or on db error
+    pub fn is_enabled(&self, flag: FeatureFlag) -> bool {
+        match self.db.read(&FEATURE_FLAGS_CF, flag.as_ref().to_owned()) {
+            Ok(Some(record)) => record.value.parse().unwrap_or(flag.default_enabled()),
+            _ => flag.default_enabled(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<(FeatureFlag, bool)> {
+        FeatureFlag::iter()
+            .map(|flag| (flag, self.is_enabled(flag)))
+            .collect()
+    }
+
+    pub fn set(&self, name: &str, enabled: bool) -> Result<()> {
+        let flag = FeatureFlag::from_str(name).map_err(|_| Error::FeatureFlagNotFound)?;
+        self.db.write(
+            &FEATURE_FLAGS_CF,
+            &Record {
+                key: flag.as_ref().to_owned(),
+                value: enabled.to_string(),
+            },
+        )?;
+        info!("Feature flag {} set to {}", flag.as_ref(), enabled);
+        Ok(())
+    }
+}
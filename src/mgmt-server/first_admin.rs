@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use futures::{pin_mut, StreamExt};
+use log::{error, info};
+
+use crate::{
+    clients::AgentApiClient,
+    error::Result,
+    events::{broker::EventBroker, TopicName, JOIN_TOPIC_NAME},
+    ha::Leadership,
+};
+
+/// Promotes the first player to join a fresh server to admin.
+///
+/// A server is considered fresh while its adminlist is empty, so this stops having any effect
+/// once an admin has been configured, whether by this or any other means.
+pub struct FirstJoinAdmin {
+    agent_client: Arc<AgentApiClient>,
+    event_broker: Arc<EventBroker>,
+    leadership: Leadership,
+}
+
+impl FirstJoinAdmin {
+    pub fn new(
+        agent_client: Arc<AgentApiClient>,
+        event_broker: Arc<EventBroker>,
+        leadership: Leadership,
+    ) -> FirstJoinAdmin {
+        FirstJoinAdmin {
+            agent_client,
+            event_broker,
+            leadership,
+        }
+    }
+
+    pub async fn start(self) {
+        let join_sub = self
+            .event_broker
+            .subscribe(TopicName::new(JOIN_TOPIC_NAME), |_| true)
+            .await;
+        tokio::spawn(async move {
+            pin_mut!(join_sub);
+            while let Some(event) = join_sub.next().await {
+                if !self.leadership.is_leader() {
+                    continue;
+                }
+                let player = event.tags.get(&TopicName::new(JOIN_TOPIC_NAME)).unwrap();
+                match self.promote_if_first(player).await {
+                    Ok(true) => {
+                        info!("Promoted first joining player {} to admin", player);
+                        break;
+                    }
+                    Ok(false) => break,
+                    Err(e) => error!("Couldn't check adminlist for first joining player: {:?}", e),
+                }
+            }
+
+            info!("First join admin subscriber task finished, an admin has been configured");
+        });
+    }
+
+    /// Adds the player to the adminlist and promotes them live if there are no admins yet.
+    /// Returns whether the player was promoted.
+    async fn promote_if_first(&self, player: &str) -> Result<bool> {
+        let admins = self.agent_client.config_adminlist_get().await?;
+        if !admins.is_empty() {
+            return Ok(false);
+        }
+
+        // the agent also promotes the player in the running server
+        self.agent_client
+            .config_adminlist_set(vec![player.to_owned()])
+            .await?;
+        Ok(true)
+    }
+}
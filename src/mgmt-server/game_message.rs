@@ -0,0 +1,123 @@
+pub use fctrl::game_message::{GameMessage, MessageCatalog};
+
+/// Where an in-game message sent by fctrl originates from
+#[derive(Clone, Copy, Debug)]
+pub enum MessageSource {
+    /// Chat relayed from the Discord chat link channel
+    ChatLink,
+    /// Announcements made by admins
+    Announcement,
+    /// Responses to RPC calls from the fctrl-observers mod, e.g. alerts
+    Rpc,
+    /// Replies to chat commands typed by players
+    ChatCommand,
+}
+
+/// Whether in-game messages from each source are sent in a way that keeps achievements enabled.
+///
+/// Plain server chat and whispers don't affect achievements, but are limited to the default
+/// formatting. Printing via a Lua command allows colours and names without the `<server>` prefix,
+/// but permanently disables achievements for the save.
+#[derive(Clone, Debug)]
+pub struct AchievementsPolicy {
+    pub chat_link: bool,
+    pub announcements: bool,
+    pub rpc: bool,
+    pub chat_commands: bool,
+}
+
+impl AchievementsPolicy {
+    pub fn preserve(&self, source: MessageSource) -> bool {
+        match source {
+            MessageSource::ChatLink => self.chat_link,
+            MessageSource::Announcement => self.announcements,
+            MessageSource::Rpc => self.rpc,
+            MessageSource::ChatCommand => self.chat_commands,
+        }
+    }
+
+    /// RCON command to show a message to all players
+    pub fn broadcast_command(&self, source: MessageSource, message: &str) -> String {
+        if self.preserve(source) {
+            // sent as regular chat from <server>, take care not to be interpreted as a command
+            message.trim_start_matches('/').to_owned()
+        } else {
+            format!("/silent-command game.print('{}')", escape_lua(message))
+        }
+    }
+
+    /// RCON command to show a message to specific players, or None if whispers should be used
+    pub fn print_to_players_command(
+        &self,
+        source: MessageSource,
+        players: &[String],
+        message: &str,
+    ) -> Option<String> {
+        if self.preserve(source) {
+            None
+        } else {
+            let players = players
+                .iter()
+                .map(|p| format!("'{}'", escape_lua(p)))
+                .collect::<Vec<_>>()
+                .join(",");
+            Some(format!(
+                "/silent-command for _, name in pairs({{{}}}) do local p = game.get_player(name) if p then p.print('{}', {{r=1,g=0.4,b=0.4}}) end end",
+                players,
+                escape_lua(message)
+            ))
+        }
+    }
+}
+
+/// Escapes a string to be placed within a single-quoted Lua string literal
+fn escape_lua(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(preserve: bool) -> AchievementsPolicy {
+        AchievementsPolicy {
+            chat_link: preserve,
+            announcements: preserve,
+            rpc: preserve,
+            chat_commands: preserve,
+        }
+    }
+
+    #[test]
+    fn preserving_broadcast_is_plain_chat() {
+        assert_eq!(
+            policy(true).broadcast_command(MessageSource::ChatLink, "/c game.print('hi')"),
+            "c game.print('hi')"
+        );
+    }
+
+    #[test]
+    fn non_preserving_broadcast_escapes_message() {
+        assert_eq!(
+            policy(false).broadcast_command(MessageSource::Announcement, "it's a \\ test"),
+            "/silent-command game.print('it\\'s a \\\\ test')"
+        );
+    }
+
+    #[test]
+    fn per_source_policy() {
+        let p = AchievementsPolicy {
+            chat_link: true,
+            announcements: false,
+            rpc: true,
+            chat_commands: true,
+        };
+        assert!(p.preserve(MessageSource::ChatLink));
+        assert!(!p.preserve(MessageSource::Announcement));
+        assert!(p
+            .print_to_players_command(MessageSource::Rpc, &["a".to_owned()], "x")
+            .is_none());
+    }
+}
@@ -5,11 +5,13 @@ use rocket::{
     request::{FromRequest, Outcome},
 };
 
-use crate::auth::{AuthnManager, AuthnProvider, AuthorizedUser, AuthzManager, UserIdentity};
+use crate::auth::{
+    AuthnManager, AuthnProvider, AuthorizedUser, AuthzManager, AuthzRole, OperatorUser,
+    UserIdentity, ViewerUser,
+};
 
 pub struct HostHeader<'r> {
     pub hostname: &'r str,
-    #[allow(dead_code)]
     pub host: &'r str,
 }
 
@@ -21,18 +23,24 @@ impl<'r> FromRequest<'r> for HostHeader<'r> {
         request: &'r rocket::Request<'_>,
     ) -> rocket::request::Outcome<Self, Self::Error> {
         match request.headers().get_one("Host") {
-            Some(h) => {
-                // remove the port if any
-                match h.split(':').next() {
-                    Some(hostname) => Outcome::Success(HostHeader { hostname, host: h }),
-                    None => Outcome::Forward(Status::InternalServerError),
-                }
-            }
+            Some(h) => match strip_port(h) {
+                Some(hostname) => Outcome::Success(HostHeader { hostname, host: h }),
+                None => Outcome::Forward(Status::InternalServerError),
+            },
             None => Outcome::Forward(Status::InternalServerError),
         }
     }
 }
 
+/// Removes the port if any from a Host header value, keeping the brackets of an IPv6 address
+fn strip_port(host: &str) -> Option<&str> {
+    if host.starts_with('[') {
+        host.find(']').map(|end| &host[..=end])
+    } else {
+        host.split(':').next()
+    }
+}
+
 pub struct ContentLengthHeader {
     pub length: usize,
 }
@@ -65,9 +73,7 @@ impl<'r> FromRequest<'r> for ContentLengthHeader {
 
 pub struct ContentRangeHeader {
     pub start: usize,
-    #[allow(dead_code)]
     pub end: usize,
-    #[allow(dead_code)]
     pub length: usize,
 }
 
@@ -110,6 +116,143 @@ impl<'r> FromRequest<'r> for ContentRangeHeader {
     }
 }
 
+/// Hex-encoded SHA-256 of a complete upload, sent alongside its final chunk
+pub struct ContentSha256Header {
+    pub sha256: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ContentSha256Header {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("X-Content-Sha256") {
+            Some(h) => Outcome::Success(ContentSha256Header {
+                sha256: h.trim().to_owned(),
+            }),
+            None => Outcome::Forward(Status::BadRequest),
+        }
+    }
+}
+
+/// Raw value of the Range header, parsed against the length of the resource being downloaded
+pub struct RangeHeader {
+    pub value: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RangeHeader {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("Range") {
+            Some(h) => Outcome::Success(RangeHeader {
+                value: h.to_owned(),
+            }),
+            None => Outcome::Forward(Status::BadRequest),
+        }
+    }
+}
+
+/// Raw value of the If-Range header, which a range request is only honoured if it matches
+pub struct IfRangeHeader {
+    pub value: String,
+}
+
+impl IfRangeHeader {
+    /// Whether the validator matches the given SHA-256, which is used as the ETag
+    pub fn matches(&self, sha256: &str) -> bool {
+        self.value.trim_matches('"') == sha256
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfRangeHeader {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("If-Range") {
+            Some(h) => Outcome::Success(IfRangeHeader {
+                value: h.to_owned(),
+            }),
+            None => Outcome::Forward(Status::BadRequest),
+        }
+    }
+}
+
+/// Content codings the client accepts, from the Accept-Encoding header. Empty if absent.
+pub struct AcceptEncodingHeader {
+    pub encodings: Vec<String>,
+}
+
+impl AcceptEncodingHeader {
+    pub fn of(request: &rocket::Request<'_>) -> AcceptEncodingHeader {
+        AcceptEncodingHeader {
+            encodings: request
+                .headers()
+                .get("Accept-Encoding")
+                .flat_map(parse_accept_encoding)
+                .collect(),
+        }
+    }
+
+    pub fn accepts(&self, encoding: &str) -> bool {
+        self.encodings
+            .iter()
+            .any(|e| e == "*" || e.eq_ignore_ascii_case(encoding))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptEncodingHeader {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(AcceptEncodingHeader::of(request))
+    }
+}
+
+/// Codings listed in an Accept-Encoding value, leaving out any refused with a zero quality
+fn parse_accept_encoding(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter_map(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().filter(|name| !name.is_empty())?;
+            let refused = params.any(|p| {
+                p.strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .map_or(false, |q| q == 0.0)
+            });
+            (!refused).then(|| name.to_owned())
+        })
+        .collect()
+}
+
+/// Bearer token from the Authorization header, for callers authenticating with a shared secret
+/// rather than as a user
+pub struct BearerTokenHeader {
+    pub token: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BearerTokenHeader {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        match request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "))
+        {
+            Some(token) => Outcome::Success(BearerTokenHeader {
+                token: token.to_owned(),
+            }),
+            None => Outcome::Forward(Status::Unauthorized),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum AuthError {
     Missing,
@@ -151,6 +294,28 @@ impl<'r> FromRequest<'r> for UserIdentity {
     }
 }
 
+/// Identifies the user and checks they have at least the required role
+async fn authorize_request(
+    request: &rocket::Request<'_>,
+    required: AuthzRole,
+) -> Outcome<UserIdentity, AuthError> {
+    match request.guard::<UserIdentity>().await {
+        Outcome::Success(id) => {
+            if let Some(authz_mgr) = request.rocket().state::<AuthzManager>() {
+                match authz_mgr.authorize(&id) {
+                    Some(role) if role >= required => Outcome::Success(id),
+                    _ => Outcome::Error((Status::Forbidden, AuthError::Unauthorized)),
+                }
+            } else {
+                error!("Failed to retrieve AuthzManager, this should never happen!");
+                Outcome::Error((Status::InternalServerError, AuthError::InternalError))
+            }
+        }
+        Outcome::Error(f) => Outcome::Error(f),
+        Outcome::Forward(f) => Outcome::Forward(f),
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for AuthorizedUser {
     type Error = AuthError;
@@ -158,21 +323,57 @@ impl<'r> FromRequest<'r> for AuthorizedUser {
     async fn from_request(
         request: &'r rocket::Request<'_>,
     ) -> rocket::request::Outcome<Self, Self::Error> {
-        match request.guard::<UserIdentity>().await {
-            Outcome::Success(id) => {
-                if let Some(authz_mgr) = request.rocket().state::<AuthzManager>() {
-                    if authz_mgr.authorize(&id) {
-                        Outcome::Success(AuthorizedUser(id))
-                    } else {
-                        Outcome::Error((Status::Forbidden, AuthError::Unauthorized))
-                    }
-                } else {
-                    error!("Failed to retrieve AuthzManager, this should never happen!");
-                    Outcome::Error((Status::InternalServerError, AuthError::InternalError))
-                }
-            }
-            Outcome::Error(f) => Outcome::Error(f),
-            Outcome::Forward(f) => Outcome::Forward(f),
-        }
+        authorize_request(request, AuthzRole::Admin)
+            .await
+            .map(AuthorizedUser)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for OperatorUser {
+    type Error = AuthError;
+
+    async fn from_request(
+        request: &'r rocket::Request<'_>,
+    ) -> rocket::request::Outcome<Self, Self::Error> {
+        authorize_request(request, AuthzRole::Operator)
+            .await
+            .map(OperatorUser)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ViewerUser {
+    type Error = AuthError;
+
+    async fn from_request(
+        request: &'r rocket::Request<'_>,
+    ) -> rocket::request::Outcome<Self, Self::Error> {
+        authorize_request(request, AuthzRole::Viewer)
+            .await
+            .map(ViewerUser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_port_from_host() {
+        assert_eq!(strip_port("example.com:8080"), Some("example.com"));
+        assert_eq!(strip_port("example.com"), Some("example.com"));
+        assert_eq!(strip_port("[2001:db8::1]:8080"), Some("[2001:db8::1]"));
+        assert_eq!(strip_port("[::1]"), Some("[::1]"));
+        assert_eq!(strip_port("[::1"), None);
+    }
+
+    #[test]
+    fn parses_accept_encoding() {
+        assert_eq!(
+            parse_accept_encoding("gzip, deflate, br;q=0.9, zstd;q=0"),
+            vec!["gzip", "deflate", "br"]
+        );
+        assert!(parse_accept_encoding("").is_empty());
     }
 }
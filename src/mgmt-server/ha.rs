@@ -0,0 +1,233 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::error::{Error, Result};
+
+/// Cheaply cloneable handle to query whether this replica currently holds leadership.
+///
+/// Only the leader should perform duties with external side effects: log ingestion, RPC handling,
+/// and the Discord integration. A standby replica stays connected to the agent and keeps serving
+/// the API, but otherwise stays passive until the leader's lease lapses.
+#[derive(Clone)]
+pub struct Leadership {
+    rx: watch::Receiver<bool>,
+}
+
+impl Leadership {
+    /// Leadership for a replica running without HA, which is always the leader
+    pub fn always_leader() -> Leadership {
+        // receiver retains the last value even once the sender is dropped
+        let (_tx, rx) = watch::channel(true);
+        Leadership { rx }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        *self.rx.borrow()
+    }
+}
+
+/// Leader election based on a lease file on storage shared between all replicas.
+///
+/// The holder renews the lease at a third of the lease duration. Any other replica will take over
+/// the lease once it has been allowed to expire. Replicas hold an exclusive lock on a lock file
+/// next to the lease while reading and writing it, so that only one of them can take it over.
+#[derive(Clone)]
+pub struct LeaderElection {
+    replica_id: String,
+    lease_path: PathBuf,
+    lease_duration: Duration,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Lease {
+    holder: String,
+    expires: DateTime<Utc>,
+}
+
+impl LeaderElection {
+    pub fn new(lease_path: PathBuf, lease_duration: Duration) -> LeaderElection {
+        LeaderElection {
+            replica_id: uuid::Uuid::new_v4().to_string(),
+            lease_path,
+            lease_duration,
+        }
+    }
+
+    /// Spawns the background election task, returning a handle to query the leadership state
+    pub async fn start(self) -> Leadership {
+        let initial = match self.try_acquire_or_renew().await {
+            Ok(b) => b,
+            Err(e) => {
+                error!("Error during initial leader election: {:?}", e);
+                false
+            }
+        };
+        if initial {
+            info!("Acquired leadership as replica {}", self.replica_id);
+        } else {
+            info!("Starting in standby mode as replica {}", self.replica_id);
+        }
+
+        let (tx, rx) = watch::channel(initial);
+        tokio::spawn(async move {
+            let interval = self.lease_duration / 3;
+            loop {
+                tokio::time::sleep(interval).await;
+                let is_leader = match self.try_acquire_or_renew().await {
+                    Ok(b) => b,
+                    Err(e) => {
+                        // can't confirm our lease, so step down to avoid two leaders
+                        error!("Error during leader election, assuming standby: {:?}", e);
+                        false
+                    }
+                };
+                let was_leader = *tx.borrow();
+                if is_leader != was_leader {
+                    if is_leader {
+                        info!("Acquired leadership as replica {}", self.replica_id);
+                    } else {
+                        warn!("Lost leadership, replica {} is now in standby", self.replica_id);
+                    }
+                    if tx.send(is_leader).is_err() {
+                        break;
+                    }
+                }
+            }
+
+            error!("leader election task is finishing - this should never happen!");
+        });
+
+        Leadership { rx }
+    }
+
+    /// Attempt to take or renew the lease, returning whether this replica holds it afterwards
+    async fn try_acquire_or_renew(&self) -> Result<bool> {
+        let election = self.clone();
+        tokio::task::spawn_blocking(move || election.try_acquire_or_renew_locked())
+            .await
+            .map_err(|e| {
+                Error::InternalMessaging(format!("Leader election task failed: {:?}", e))
+            })?
+    }
+
+    fn try_acquire_or_renew_locked(&self) -> Result<bool> {
+        // released when the file is closed, including if this replica dies while holding it
+        let lock_file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(self.lease_path.with_extension("lock"))?;
+        lock_file.lock()?;
+
+        let now = Utc::now();
+        if let Some(existing) = self.read_lease()? {
+            if existing.holder != self.replica_id && existing.expires > now {
+                return Ok(false);
+            }
+        }
+
+        let lease = Lease {
+            holder: self.replica_id.clone(),
+            expires: now + chrono::Duration::milliseconds(self.lease_duration.as_millis() as i64),
+        };
+        // write then rename so other replicas never observe a partially written lease
+        let tmp_path = self
+            .lease_path
+            .with_extension(format!("{}.tmp", self.replica_id));
+        fs::write(&tmp_path, serde_json::to_vec(&lease)?)?;
+        fs::rename(&tmp_path, &self.lease_path)?;
+        Ok(true)
+    }
+
+    fn read_lease(&self) -> Result<Option<Lease>> {
+        match fs::read(&self.lease_path) {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(lease) => Ok(Some(lease)),
+                Err(e) => {
+                    warn!("Ignoring malformed lease file: {:?}", e);
+                    Ok(None)
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::fs;
+
+    use super::*;
+
+    type GenericResult = std::result::Result<(), Box<dyn std::error::Error>>;
+
+    #[tokio::test]
+    async fn only_one_replica_holds_lease() -> GenericResult {
+        fctrl::util::testing::logger_init();
+
+        let lease_path = std::env::temp_dir().join("only_one_replica_holds_lease.lease");
+        let _ = fs::remove_file(&lease_path).await;
+
+        let primary = LeaderElection::new(lease_path.clone(), Duration::from_secs(60));
+        let standby = LeaderElection::new(lease_path.clone(), Duration::from_secs(60));
+
+        assert!(primary.try_acquire_or_renew().await?);
+        assert!(!standby.try_acquire_or_renew().await?);
+        // renewal by the holder should succeed
+        assert!(primary.try_acquire_or_renew().await?);
+
+        let _ = fs::remove_file(&lease_path).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn racing_replicas_elect_one_leader() -> GenericResult {
+        fctrl::util::testing::logger_init();
+
+        let lease_path = std::env::temp_dir().join("racing_replicas_elect_one_leader.lease");
+        let _ = fs::remove_file(&lease_path).await;
+
+        let replicas: Vec<_> = (0..8)
+            .map(|_| LeaderElection::new(lease_path.clone(), Duration::from_secs(60)))
+            .collect();
+        let results =
+            futures::future::join_all(replicas.iter().map(|r| r.try_acquire_or_renew())).await;
+        let leaders = results
+            .into_iter()
+            .filter(|r| matches!(r, Ok(true)))
+            .count();
+        assert_eq!(leaders, 1);
+
+        let _ = fs::remove_file(&lease_path).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn standby_takes_over_expired_lease() -> GenericResult {
+        fctrl::util::testing::logger_init();
+
+        let lease_path = std::env::temp_dir().join("standby_takes_over_expired_lease.lease");
+        let _ = fs::remove_file(&lease_path).await;
+
+        let primary = LeaderElection::new(lease_path.clone(), Duration::from_millis(100));
+        let standby = LeaderElection::new(lease_path.clone(), Duration::from_millis(100));
+
+        assert!(primary.try_acquire_or_renew().await?);
+        assert!(!standby.try_acquire_or_renew().await?);
+
+        // primary dies and does not renew
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(standby.try_acquire_or_renew().await?);
+        assert!(!primary.try_acquire_or_renew().await?);
+
+        let _ = fs::remove_file(&lease_path).await;
+
+        Ok(())
+    }
+}
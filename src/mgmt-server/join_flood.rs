@@ -0,0 +1,172 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::{pin_mut, StreamExt};
+use log::{error, info, warn};
+
+use crate::{
+    clients::AgentApiClient,
+    discord::DiscordClient,
+    events::{broker::EventBroker, TopicName, PEER_TOPIC_NAME},
+    game_message::GameMessage,
+    ha::Leadership,
+};
+
+/// Protection against players rapidly reconnecting, e.g. griefer join/leave loops or connection
+/// spam.
+///
+/// Every connection attempt is counted, whether or not it results in a join. A player making more
+/// than `max_connections` attempts within `window` is reported to the Discord alert channel, and
+/// temporarily banned if a ban duration is configured. Temporary bans are lifted by this process,
+/// so a ban in progress when mgmt-server stops will need to be lifted manually.
+pub struct JoinFloodProtection {
+    max_connections: usize,
+    window: Duration,
+    ban_duration: Option<Duration>,
+    /// Recent connection attempts per player, oldest first
+    connections: HashMap<String, VecDeque<Instant>>,
+}
+
+impl JoinFloodProtection {
+    pub fn new(
+        max_connections: usize,
+        window: Duration,
+        ban_duration: Option<Duration>,
+    ) -> JoinFloodProtection {
+        JoinFloodProtection {
+            max_connections,
+            window,
+            ban_duration,
+            connections: HashMap::new(),
+        }
+    }
+
+    /// Records a connection attempt, returning whether the player is now flooding
+    fn on_connection(&mut self, player: &str, now: Instant) -> bool {
+        let attempts = self.connections.entry(player.to_owned()).or_default();
+        attempts.push_back(now);
+        while let Some(oldest) = attempts.front() {
+            if now.duration_since(*oldest) > self.window {
+                attempts.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if attempts.len() > self.max_connections {
+            // start counting afresh, so a single flood is only acted on once
+            self.connections.remove(player);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub async fn start(
+        mut self,
+        agent_client: Arc<AgentApiClient>,
+        event_broker: Arc<EventBroker>,
+        discord: Arc<Option<DiscordClient>>,
+        leadership: Leadership,
+    ) {
+        info!(
+            "Flagging players with more than {} connections in {}s",
+            self.max_connections,
+            self.window.as_secs()
+        );
+        let peer_sub = event_broker
+            .subscribe(TopicName::new(PEER_TOPIC_NAME), |_| true)
+            .await;
+        tokio::spawn(async move {
+            pin_mut!(peer_sub);
+            while let Some(event) = peer_sub.next().await {
+                // tag is "<peer_id> <username>"
+                let tag = event.tags.get(&TopicName::new(PEER_TOPIC_NAME)).unwrap();
+                let player = match tag.split_once(' ') {
+                    Some((_, player)) => player.to_owned(),
+                    None => continue,
+                };
+                if !self.on_connection(&player, Instant::now()) || !leadership.is_leader() {
+                    continue;
+                }
+
+                let alert_msg = match self.ban_duration {
+                    Some(ban_duration) => {
+                        Self::temp_ban(Arc::clone(&agent_client), player.clone(), ban_duration)
+                            .await;
+                        format!(
+                            "Player {} connected more than {} times in {}s and has been banned for {}m",
+                            player,
+                            self.max_connections,
+                            self.window.as_secs(),
+                            ban_duration.as_secs() / 60
+                        )
+                    }
+                    None => format!(
+                        "Player {} connected more than {} times in {}s",
+                        player,
+                        self.max_connections,
+                        self.window.as_secs()
+                    ),
+                };
+                warn!("{}", alert_msg);
+                if let Some(discord) = discord.as_ref() {
+                    if let Err(e) = discord.oneshot_alert(None, alert_msg) {
+                        error!("Couldn't send join flood alert: {:?}", e);
+                    }
+                }
+            }
+
+            error!("join flood subscriber task is finishing - this should never happen!");
+        });
+    }
+
+    async fn temp_ban(agent_client: Arc<AgentApiClient>, player: String, ban_duration: Duration) {
+        let command = format!("/ban {} {}", player, GameMessage::JoinFloodBan.render());
+        if let Err(e) = agent_client.rcon_command(command).await {
+            error!("Couldn't ban player {} via RCON: {:?}", player, e);
+            return;
+        }
+        tokio::spawn(async move {
+            tokio::time::sleep(ban_duration).await;
+            match agent_client
+                .rcon_command(format!("/unban {}", player))
+                .await
+            {
+                Ok(_) => info!("Lifted temporary ban of {}", player),
+                Err(e) => error!("Couldn't lift temporary ban of {}: {:?}", player, e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_player_exceeding_connections_in_window_once() {
+        let mut jfp = JoinFloodProtection::new(3, Duration::from_secs(60), None);
+        let start = Instant::now();
+        for i in 0..3 {
+            assert!(!jfp.on_connection("griefer", start + Duration::from_secs(i)));
+        }
+        assert!(jfp.on_connection("griefer", start + Duration::from_secs(3)));
+        assert!(!jfp.on_connection("griefer", start + Duration::from_secs(4)));
+        assert!(!jfp.on_connection("someone", start + Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn connections_outside_window_are_forgotten() {
+        let mut jfp = JoinFloodProtection::new(2, Duration::from_secs(60), None);
+        let start = Instant::now();
+        assert!(!jfp.on_connection("player", start));
+        assert!(!jfp.on_connection("player", start + Duration::from_secs(30)));
+        assert!(!jfp.on_connection("player", start + Duration::from_secs(90)));
+        assert!(!jfp.on_connection("player", start + Duration::from_secs(91)));
+        assert!(jfp.on_connection("player", start + Duration::from_secs(92)));
+    }
+}
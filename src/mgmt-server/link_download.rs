@@ -1,20 +1,30 @@
 use std::{collections::HashMap, sync::Arc};
 use chrono::{DateTime, Duration, Utc};
-use log::info;
+use log::{error, info};
 use tokio::{select, sync::RwLock};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 const CLEANUP_INTERVAL: Duration = Duration::minutes(15);
 const LINK_EXPIRY: Duration = Duration::minutes(60);
+const DIRECT_LINK_EXPIRY: Duration = Duration::minutes(5);
 
 type LinkMap = Arc<RwLock<HashMap<String, (LinkDownloadTarget, DateTime<Utc>)>>>;
 
 pub struct LinkDownloadManager {
     links: LinkMap,
+    direct_download: Option<AgentDirectDownload>,
     _cleanup_task_ct: CancellationToken,
 }
 
+/// Settings for downloading savefiles directly from the agent's HTTP server
+pub struct AgentDirectDownload {
+    /// Base URL of the agent's download server, as reachable by the browser
+    pub base_url: url::Url,
+    /// Secret shared with the agent, used to sign download URLs
+    pub secret: String,
+}
+
 #[derive(Clone, Debug)]
 pub enum LinkDownloadTarget {
     Savefile { id: String },
@@ -22,7 +32,7 @@ pub enum LinkDownloadTarget {
 }
 
 impl LinkDownloadManager {
-    pub async fn new() -> LinkDownloadManager {
+    pub async fn new(direct_download: Option<AgentDirectDownload>) -> LinkDownloadManager {
         let links = LinkMap::default();
         let links_clone = Arc::clone(&links);
         let cancellation_token = CancellationToken::new();
@@ -32,10 +42,32 @@ impl LinkDownloadManager {
         });
         LinkDownloadManager {
             links,
+            direct_download,
             _cleanup_task_ct,
         }
     }
 
+    /// Builds a short-lived signed URL to download the savefile directly from the agent,
+    /// if direct downloads are enabled
+    pub fn direct_savefile_url(&self, id: &str) -> Option<String> {
+        self.direct_download.as_ref().and_then(|dd| {
+            let expires = (Utc::now() + DIRECT_LINK_EXPIRY).timestamp();
+            let signature = fctrl::util::signing::sign(&dd.secret, id, expires);
+            match dd.base_url.join(&format!("saves/{}", urlencoding::encode(id))) {
+                Ok(mut url) => {
+                    url.query_pairs_mut()
+                        .append_pair("expires", &expires.to_string())
+                        .append_pair("signature", &signature);
+                    Some(url.to_string())
+                }
+                Err(e) => {
+                    error!("Error building direct download url for savefile {}: {:?}", id, e);
+                    None
+                }
+            }
+        })
+    }
+
     pub async fn create_link(&self, target: LinkDownloadTarget) -> String {
         let mut w_guard = self.links.write().await;
         let link = Uuid::new_v4().as_simple().to_string();
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use fctrl::schema::{AgentStreamingMessage, AgentStreamingMessageInner};
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+
+use crate::{
+    clients::tag_server_stdout_message,
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+    events::{TopicName, STDOUT_TOPIC_NAME},
+};
+
+lazy_static! {
+    // first line of a Factorio log, with the time the process started
+    static ref LOG_START_RE: Regex =
+        Regex::new(r"^\s*\d+\.\d+ (\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}); Factorio").unwrap();
+    // seconds since the process started, at the start of most log lines
+    static ref ELAPSED_RE: Regex = Regex::new(r"^\s*(\d+\.\d+) ").unwrap();
+    // wall clock time at the start of chat, join and leave lines
+    static ref DATETIME_RE: Regex =
+        Regex::new(r"^(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}) ").unwrap();
+}
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Result of ingesting a historical log file
+#[derive(Debug, Default, PartialEq)]
+pub struct BackfillSummary {
+    /// Lines read from the file
+    pub lines: usize,
+    /// Lines written to the db, per log category
+    pub ingested: HashMap<String, usize>,
+}
+
+/// Ingests a historical Factorio log, e.g. the factorio-current.log of a server being moved
+/// into fctrl, so that its chat and join history is queryable alongside new logs.
+///
+/// Lines are tagged the same way as live server output. Times in the log are taken to be UTC,
+/// and lines without a time of their own are given the time of the line before. The log must
+/// start with the Factorio version header, which gives the time the other lines are relative to.
+pub fn backfill(db: &Db, log: &str) -> Result<BackfillSummary> {
+    let lines = timestamp_lines(log)?;
+    let mut summary = BackfillSummary {
+        lines: lines.len(),
+        ..Default::default()
+    };
+    for (timestamp, line) in lines {
+        let mut tags = HashMap::new();
+        tag_server_stdout_message(line, &mut tags);
+        let category = match tags.get(&TopicName::new(STDOUT_TOPIC_NAME)) {
+            Some(category) if crate::should_write_stdout_category_to_db(category) => category,
+            _ => continue,
+        };
+        // stored in the same format as live output from the agent
+        let message = AgentStreamingMessage {
+            timestamp,
+            content: AgentStreamingMessageInner::ServerStdout(line.to_owned()),
+        };
+        db.write(
+            &Cf(category.clone()),
+            &Record {
+                key: timestamp.to_rfc3339(),
+                value: serde_json::to_string(&message)?,
+            },
+        )?;
+        *summary.ingested.entry(category.clone()).or_default() += 1;
+    }
+    info!("Backfilled historical log: {:?}", summary);
+    Ok(summary)
+}
+
+/// Pairs each line with the time it was logged. Lines logged in the same second are spaced a
+/// nanosecond apart, keeping them in order without overwriting each other in the db.
+fn timestamp_lines(log: &str) -> Result<Vec<(DateTime<Utc>, &str)>> {
+    let mut lines = log.lines().filter(|l| !l.trim().is_empty());
+    let header = lines.next().unwrap_or_default();
+    let start = LOG_START_RE
+        .captures(header)
+        .and_then(|c| parse_datetime(&c[1]))
+        .ok_or_else(|| {
+            Error::BadRequest(
+                "Log must start with the Factorio version line, e.g. '0.000 2024-01-01 00:00:00; Factorio 1.1.110'"
+                    .to_owned(),
+            )
+        })?;
+
+    let mut timestamped = vec![(start, header)];
+    let mut previous = start;
+    for line in lines {
+        let logged_at = if let Some(c) = DATETIME_RE.captures(line) {
+            parse_datetime(&c[1])
+        } else if let Some(c) = ELAPSED_RE.captures(line) {
+            c[1].parse::<f64>()
+                .ok()
+                .map(|secs| start + Duration::milliseconds((secs * 1000.0) as i64))
+        } else {
+            None
+        };
+        let timestamp = match logged_at {
+            Some(t) if t > previous => t,
+            _ => previous + Duration::nanoseconds(1),
+        };
+        timestamped.push((timestamp, line));
+        previous = timestamp;
+    }
+    Ok(timestamped)
+}
+
+fn parse_datetime(s: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(s, DATETIME_FORMAT)
+        .ok()
+        .map(|dt| dt.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_lines_relative_to_log_start() {
+        let log =
+            "   0.000 2024-01-02 03:04:05; Factorio 1.1.110 (build 62451, linux64, headless)\n\
+                   \x20  1.500 Info main.cpp:1234: Operating system: Linux\n\
+                   2024-01-02 03:10:00 [JOIN] someone joined the game\n\
+                   2024-01-02 03:10:00 [CHAT] someone: hello\n\
+                   continuation line\n";
+        let lines = timestamp_lines(log).unwrap();
+        let start = parse_datetime("2024-01-02 03:04:05").unwrap();
+        let join = parse_datetime("2024-01-02 03:10:00").unwrap();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0].0, start);
+        assert_eq!(lines[1].0, start + Duration::milliseconds(1500));
+        assert_eq!(lines[2].0, join);
+        assert_eq!(lines[3].0, join + Duration::nanoseconds(1));
+        assert_eq!(lines[4].0, join + Duration::nanoseconds(2));
+
+        assert!(timestamp_lines("2024-01-02 03:10:00 [CHAT] someone: hello").is_err());
+    }
+}
@@ -1,31 +1,63 @@
 #![feature(decl_macro)]
 #![feature(type_alias_impl_trait)]
 
-use std::{io::Cursor, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, io::Cursor, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
-use auth::{AuthnManager, AuthnProvider, AuthzManager};
+use auth::{AuthnManager, AuthnProvider, AuthzManager, AuthzRole};
 use events::*;
+use fctrl::schema::{AgentStreamingMessage, AgentStreamingMessageInner};
 use futures::{pin_mut, StreamExt};
-use log::{debug, error, info};
-use rocket::{async_trait, catchers, fairing::Fairing, fs::FileServer, routes};
+use log::{debug, error, info, warn};
+use rocket::{async_trait, catchers, fairing::Fairing, routes};
 
 use crate::{
-    auth::UserIdentity, clients::AgentApiClient, db::{Cf, Db, Record}, discord::DiscordClient, events::broker::EventBroker, link_download::LinkDownloadManager, rpc::RpcHandler, ws::WebSocketServer
+    alert_rules::AlertRules, alertmanager::AlertmanagerReceiver, auth::UserIdentity, autosave::{AutosaveAnnouncer, AutosaveNotifier}, chat_commands::ChatCommands, chat_filter::ChatFilter, clients::AgentApiClient, connection_quality::PlayerSessionTracker, db::{Cf, Db, Record}, discord::{DiscordAdmins, DiscordClient}, events::broker::EventBroker, feature_flags::FeatureFlags, first_admin::FirstJoinAdmin, game_message::{AchievementsPolicy, MessageCatalog}, ha::{LeaderElection, Leadership}, join_flood::JoinFloodProtection, link_download::{AgentDirectDownload, LinkDownloadManager}, migration::Migration, mqtt::MqttPublisher, operation_webhooks::OperationWebhooks, password_rotation::PasswordRotation, player_notes::PlayerNotes, plugins::Plugins, preferences::Preferences, reserved_slots::ReservedSlots, rpc::RpcHandler, save_hashes::SaveHashes, scheduler::Scheduler, settings_profiles::SettingsProfiles, soft_cap::SoftPlayerCap, welcome::WelcomeMessage, ws::WebSocketServer
 };
 
+mod alert_rules;
+mod alertmanager;
 mod auth;
+mod autosave;
+mod chat_commands;
+mod chat_filter;
 mod catchers;
 mod clients;
+mod connection_quality;
 mod consts;
 mod db;
 mod discord;
 mod error;
 mod events;
+mod feature_flags;
+mod first_admin;
+mod game_message;
 mod guards;
+mod ha;
+mod join_flood;
 mod link_download;
+mod log_backfill;
 mod metrics;
+mod migration;
+mod mod_import;
+mod mqtt;
+mod operation_webhooks;
+mod operations;
+mod password_rotation;
+mod player_notes;
+mod plugins;
+mod preferences;
+mod reserved_slots;
 mod routes;
 mod rpc;
+mod save_diff;
+mod save_hashes;
+mod save_upload;
+mod scheduler;
+mod settings_profiles;
+mod soft_cap;
+#[cfg(test)]
+mod test_util;
+mod welcome;
 mod ws;
 
 #[rocket::main]
@@ -33,14 +65,92 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
     info!("Creating event broker");
-    let event_broker = Arc::new(EventBroker::new());
+    let event_topic_capacity = match std::env::var("EVENT_TOPIC_CAPACITY") {
+        Ok(s) => s.parse()?,
+        Err(_) => EventBroker::DEFAULT_TOPIC_CAPACITY,
+    };
+    let event_broker = Arc::new(EventBroker::new(event_topic_capacity));
 
     info!("Opening db");
-    let db = Arc::new(Db::open_or_new(&*consts::DB_DIR).await?);
+    let db = match std::env::var("DB_IN_MEMORY").as_deref() {
+        Ok("true") => {
+            info!("Keeping db in memory, nothing will be persisted");
+            Arc::new(Db::open_in_memory()?)
+        }
+        _ => Arc::new(Db::open_or_new(&*consts::DB_DIR).await?),
+    };
+
+    info!("Checking high-availability mode...");
+    let leadership = match std::env::var("HA_LEASE_FILE") {
+        Ok(lease_file) => {
+            let lease_duration_secs = match std::env::var("HA_LEASE_DURATION_SECS") {
+                Ok(s) => s.parse()?,
+                Err(_) => 15,
+            };
+            info!("HA mode enabled, contending for lease at {}", lease_file);
+            LeaderElection::new(
+                PathBuf::from(lease_file),
+                Duration::from_secs(lease_duration_secs),
+            )
+            .start()
+            .await
+        }
+        Err(_) => {
+            info!("HA mode disabled");
+            Leadership::always_leader()
+        }
+    };
 
     let agent_addr = url::Url::parse(&std::env::var("AGENT_ADDR")?)?;
     info!("Creating agent client with address {}", agent_addr);
-    let agent_client = Arc::new(AgentApiClient::new(agent_addr, Arc::clone(&event_broker)).await);
+    let operation_timeout_secs = match std::env::var("OPERATION_TIMEOUT_SECS") {
+        Ok(s) => s.parse()?,
+        Err(_) => 3600,
+    };
+    let agent_client = Arc::new(
+        AgentApiClient::new(
+            agent_addr,
+            Arc::clone(&event_broker),
+            Duration::from_secs(operation_timeout_secs),
+        )
+        .await,
+    );
+
+    let achievements_policy = AchievementsPolicy {
+        chat_link: match std::env::var("DISCORD_CHAT_LINK_PRESERVE_ACHIEVEMENTS") {
+            Ok(s) => s.parse()?,
+            Err(_) => true,
+        },
+        announcements: match std::env::var("ANNOUNCEMENTS_PRESERVE_ACHIEVEMENTS") {
+            Ok(s) => s.parse()?,
+            Err(_) => true,
+        },
+        rpc: match std::env::var("RPC_PRESERVE_ACHIEVEMENTS") {
+            Ok(s) => s.parse()?,
+            Err(_) => true,
+        },
+        chat_commands: match std::env::var("CHAT_COMMANDS_PRESERVE_ACHIEVEMENTS") {
+            Ok(s) => s.parse()?,
+            Err(_) => true,
+        },
+    };
+
+    info!("Loading in-game message catalog");
+    MessageCatalog::from_env()?.install();
+
+    let feature_flags = Arc::new(FeatureFlags::new(Arc::clone(&db)));
+
+    info!("Creating scheduler");
+    let scheduler = Scheduler::start(
+        Arc::clone(&agent_client),
+        Arc::clone(&db),
+        achievements_policy.clone(),
+        Arc::clone(&feature_flags),
+        leadership.clone(),
+    );
+
+    info!("Loading chat filter rules");
+    let chat_filter = Arc::new(ChatFilter::new(Arc::clone(&db))?);
 
     info!("Checking Discord integration...");
     let discord_client = Arc::new(match &std::env::var("DISCORD_INTEGRATION").as_deref() {
@@ -59,9 +169,23 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
                 Ok(s) => Some(s.parse()?),
                 Err(_) => None,
             };
-            let chat_link_preserve_achievements = match std::env::var("DISCORD_CHAT_LINK_PRESERVE_ACHIEVEMENTS") {
+            let password_channel_id = match std::env::var("DISCORD_PASSWORD_CHANNEL_ID") {
+                Ok(s) => Some(s.parse()?),
+                Err(_) => None,
+            };
+            let chat_link_session_threads = match std::env::var("DISCORD_CHAT_LINK_SESSION_THREADS") {
                 Ok(s) => s.parse()?,
-                Err(_) => true,
+                Err(_) => false,
+            };
+            let admins = DiscordAdmins {
+                user_id: match std::env::var("AUTH_DISCORD_ADMIN_USER_ID") {
+                    Ok(s) => Some(s.parse()?),
+                    Err(_) => None,
+                },
+                role_id: match std::env::var("DISCORD_ADMIN_ROLE_ID") {
+                    Ok(s) => Some(s.parse()?),
+                    Err(_) => None,
+                },
             };
             Some(
                 DiscordClient::new(
@@ -69,9 +193,14 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
                     guild_id,
                     alert_channel_id,
                     chat_link_channel_id,
-                    chat_link_preserve_achievements,
+                    password_channel_id,
+                    chat_link_session_threads,
+                    admins,
+                    achievements_policy.clone(),
                     Arc::clone(&agent_client),
-                    Arc::clone(&event_broker),
+                    Arc::clone(&chat_filter),
+                    Arc::clone(&db),
+                    leadership.clone(),
                 )
                 .await?,
             )
@@ -95,6 +224,7 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
             AuthnProvider::Discord {
                 client_id: std::env::var("DISCORD_OAUTH2_CLIENT_ID")?,
                 client_secret: std::env::var("DISCORD_OAUTH2_CLIENT_SECRET")?,
+                guild_id: std::env::var("DISCORD_GUILD_ID").ok(),
             }
         }
         &"none" => AuthnProvider::None,
@@ -108,13 +238,46 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     };
     let authn = AuthnManager::new(auth_provider)?;
     let admin_user = match std::env::var("AUTH_DISCORD_ADMIN_USER_ID") {
-        Ok(id) => UserIdentity { sub: id },
+        Ok(id) => UserIdentity {
+            sub: id,
+            roles: vec![],
+        },
         Err(_) => UserIdentity::anonymous(),
     };
-    let authz = AuthzManager::new(admin_user);
+    let mut role_mapping = HashMap::new();
+    for (var, role) in [
+        ("AUTH_DISCORD_VIEWER_ROLE_ID", AuthzRole::Viewer),
+        ("AUTH_DISCORD_OPERATOR_ROLE_ID", AuthzRole::Operator),
+        ("DISCORD_ADMIN_ROLE_ID", AuthzRole::Admin),
+    ] {
+        if let Ok(role_id) = std::env::var(var) {
+            info!("Discord role {} grants {:?} access", role_id, role);
+            role_mapping.insert(role_id, role);
+        }
+    }
+    let authz = AuthzManager::new(admin_user, role_mapping);
 
     info!("Creating log ingestion subscriber");
-    create_log_ingestion_subscriber(Arc::clone(&event_broker), Arc::clone(&db)).await?;
+    create_log_ingestion_subscriber(
+        Arc::clone(&event_broker),
+        Arc::clone(&db),
+        leadership.clone(),
+    )
+    .await?;
+
+    info!("Creating operation history subscriber");
+    let operation_history_ttl_hours = match std::env::var("OPERATION_HISTORY_TTL_HOURS") {
+        Ok(s) => s.parse()?,
+        Err(_) => 168,
+    };
+    operations::create_operation_history_subscriber(
+        Arc::clone(&event_broker),
+        Arc::clone(&db),
+        leadership.clone(),
+        &scheduler,
+        Duration::from_secs(operation_history_ttl_hours * 60 * 60),
+    )
+    .await?;
 
     info!("Creating rpc subscriber");
     create_rpc_subscriber(
@@ -122,11 +285,263 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         Arc::clone(&event_broker),
         Arc::clone(&db),
         Arc::clone(&discord_client),
+        achievements_policy.clone(),
+        leadership.clone(),
     )
     .await?;
 
+    info!("Creating server crash subscriber");
+    let alert_rules = Arc::new(AlertRules::new(Arc::clone(&db)));
+    create_server_crash_subscriber(
+        Arc::clone(&event_broker),
+        Arc::clone(&discord_client),
+        Arc::clone(&alert_rules),
+        leadership.clone(),
+    )
+    .await;
+
+    info!("Creating agent recovery subscriber");
+    create_recovery_subscriber(
+        Arc::clone(&event_broker),
+        Arc::clone(&discord_client),
+        leadership.clone(),
+    )
+    .await;
+
+    info!("Creating alert rules subscriber");
+    alert_rules
+        .start(
+            Arc::clone(&agent_client),
+            Arc::clone(&discord_client),
+            Arc::clone(&event_broker),
+            leadership.clone(),
+            &scheduler,
+        )
+        .await;
+
+    info!("Creating operation webhooks subscriber");
+    let operation_webhooks = Arc::new(OperationWebhooks::new(Arc::clone(&db)));
+    operation_webhooks.start(Arc::clone(&event_broker)).await;
+
+    info!("Checking Alertmanager webhook receiver...");
+    let alertmanager_token = std::env::var("ALERTMANAGER_WEBHOOK_TOKEN").ok();
+    if alertmanager_token.is_none() {
+        info!("Alertmanager webhook receiver disabled, no token set");
+    }
+    let alertmanager_receiver = Arc::new(AlertmanagerReceiver::new(
+        alertmanager_token,
+        Arc::clone(&agent_client),
+        Arc::clone(&discord_client),
+        achievements_policy.clone(),
+    ));
+
+    info!("Creating autosave subscriber");
+    let autosave_webhook_url = match std::env::var("AUTOSAVE_WEBHOOK_URL") {
+        Ok(s) => Some(url::Url::parse(&s)?),
+        Err(_) => None,
+    };
+    AutosaveNotifier::new(autosave_webhook_url)
+        .start(Arc::clone(&event_broker), leadership.clone())
+        .await;
+
+    info!("Checking autosave announcements...");
+    match std::env::var("AUTOSAVE_ANNOUNCEMENT_SECS") {
+        Ok(s) => {
+            AutosaveAnnouncer::new(Duration::from_secs(s.parse()?), achievements_policy.clone())
+                .start(
+                    Arc::clone(&agent_client),
+                    Arc::clone(&event_broker),
+                    leadership.clone(),
+                )
+                .await;
+        }
+        Err(_) => info!("Autosave announcements disabled"),
+    }
+
+    info!("Creating player session tracker");
+    let player_sessions = PlayerSessionTracker::start(Arc::clone(&event_broker)).await;
+
+    info!("Checking reserved slots policy...");
+    match std::env::var("RESERVED_SLOTS_CAPACITY") {
+        Ok(s) => {
+            let capacity = s.parse()?;
+            let vips = match std::env::var("RESERVED_SLOTS_VIPS") {
+                Ok(s) => s
+                    .split(',')
+                    .map(|v| v.trim().to_owned())
+                    .filter(|v| !v.is_empty())
+                    .collect(),
+                Err(_) => Default::default(),
+            };
+            ReservedSlots::new(capacity, vips)
+                .start(
+                    Arc::clone(&agent_client),
+                    Arc::clone(&event_broker),
+                    leadership.clone(),
+                )
+                .await;
+        }
+        Err(_) => info!("Reserved slots policy disabled"),
+    }
+
+    info!("Checking soft player cap...");
+    match std::env::var("SOFT_PLAYER_CAP") {
+        Ok(s) => {
+            SoftPlayerCap::new(s.parse()?)
+                .start(
+                    Arc::clone(&agent_client),
+                    Arc::clone(&event_broker),
+                    player_sessions.clone(),
+                    Arc::clone(&discord_client),
+                    leadership.clone(),
+                )
+                .await;
+        }
+        Err(_) => info!("Soft player cap disabled"),
+    }
+
+    info!("Checking join flood protection...");
+    match std::env::var("JOIN_FLOOD_MAX_CONNECTIONS") {
+        Ok(s) => {
+            let max_connections = s.parse()?;
+            let window_secs = match std::env::var("JOIN_FLOOD_WINDOW_SECS") {
+                Ok(s) => s.parse()?,
+                Err(_) => 60,
+            };
+            let ban_duration = match std::env::var("JOIN_FLOOD_BAN_MINUTES") {
+                Ok(s) => Some(Duration::from_secs(s.parse::<u64>()? * 60)),
+                Err(_) => None,
+            };
+            JoinFloodProtection::new(
+                max_connections,
+                Duration::from_secs(window_secs),
+                ban_duration,
+            )
+            .start(
+                Arc::clone(&agent_client),
+                Arc::clone(&event_broker),
+                Arc::clone(&discord_client),
+                leadership.clone(),
+            )
+            .await;
+        }
+        Err(_) => info!("Join flood protection disabled"),
+    }
+
+    info!("Checking first join admin policy...");
+    let first_join_admin = match std::env::var("FIRST_JOIN_ADMIN") {
+        Ok(s) => s.parse()?,
+        Err(_) => false,
+    };
+    if first_join_admin {
+        info!("First player to join a server without admins will be promoted to admin");
+        FirstJoinAdmin::new(
+            Arc::clone(&agent_client),
+            Arc::clone(&event_broker),
+            leadership.clone(),
+        )
+        .start()
+        .await;
+    } else {
+        info!("First join admin policy disabled");
+    }
+
+    info!("Checking welcome message...");
+    match WelcomeMessage::from_env()? {
+        Some(welcome) => {
+            info!("New players will be welcomed in-game on their first join");
+            welcome
+                .start(
+                    Arc::clone(&agent_client),
+                    Arc::clone(&db),
+                    Arc::clone(&event_broker),
+                    leadership.clone(),
+                )
+                .await;
+        }
+        None => info!("Welcome message disabled"),
+    }
+
+    info!("Checking game password rotation...");
+    match std::env::var("PASSWORD_ROTATION_INTERVAL_HOURS") {
+        Ok(s) => {
+            let interval_hours: u64 = s.parse()?;
+            info!("Rotating game password every {} hours", interval_hours);
+            PasswordRotation::new(
+                Duration::from_secs(interval_hours * 60 * 60),
+                Arc::clone(&agent_client),
+                Arc::clone(&db),
+                Arc::clone(&discord_client),
+            )
+            .start(&scheduler);
+        }
+        Err(_) => info!("Game password rotation disabled"),
+    }
+
+    info!("Creating chat filter subscriber");
+    chat_filter
+        .start(
+            Arc::clone(&agent_client),
+            Arc::clone(&event_broker),
+            leadership.clone(),
+        )
+        .await;
+
+    info!("Starting plugins");
+    let mut plugins = Plugins::default();
+    if let Some(chat_link) = discord_client
+        .as_ref()
+        .as_ref()
+        .and_then(|d| d.chat_link())
+    {
+        plugins.register(chat_link);
+    }
+    if let Some(mqtt) = MqttPublisher::from_env()? {
+        plugins.register(mqtt);
+    }
+    plugins
+        .start(Arc::clone(&event_broker), leadership.clone())
+        .await;
+
+    info!("Creating chat command subscriber");
+    let chat_commands = ChatCommands::start(
+        Arc::clone(&agent_client),
+        Arc::clone(&db),
+        achievements_policy.clone(),
+        Arc::clone(&event_broker),
+        leadership,
+    )
+    .await;
+
+    let player_notes = Arc::new(PlayerNotes::new(Arc::clone(&db)));
+    let save_hashes = Arc::new(SaveHashes::new(Arc::clone(&db)));
+    let preferences = Arc::new(Preferences::new(Arc::clone(&db)));
+    let settings_profiles = Arc::new(SettingsProfiles::new(
+        Arc::clone(&agent_client),
+        Arc::clone(&db),
+    ));
+
     info!("Creating link download manager");
-    let link_download_manager = Arc::new(LinkDownloadManager::new().await);
+    let agent_direct_download = match (
+        std::env::var("AGENT_DOWNLOAD_URL"),
+        std::env::var("AGENT_DOWNLOAD_SECRET"),
+    ) {
+        (Ok(base_url), Ok(secret)) => {
+            info!("Savefiles will be downloaded directly from agent at {}", base_url);
+            Some(AgentDirectDownload {
+                base_url: url::Url::parse(&base_url)?,
+                secret,
+            })
+        }
+        _ => None,
+    };
+    let link_download_manager = Arc::new(LinkDownloadManager::new(agent_direct_download).await);
+
+    let migration = Arc::new(Migration::new(
+        Arc::clone(&agent_client),
+        Arc::clone(&event_broker),
+        Arc::clone(&link_download_manager),
+    ));
 
     let ws_port = std::env::var("MGMT_SERVER_WS_PORT")?.parse()?;
     let ws_addr = std::env::var("MGMT_SERVER_WS_ADDRESS")?.parse()?;
@@ -135,10 +550,11 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     if reverse_proxy_enabled {
         info!("Env var suggests reverse proxy is enabled, will enable WSS");
     }
+    let ws_advertised_host = std::env::var("MGMT_SERVER_WS_ADVERTISED_HOST").ok();
     info!("Opening websocket server at {}", ws_bind);
-    let ws = WebSocketServer::new(ws_bind, reverse_proxy_enabled).await?;
+    let ws = WebSocketServer::new(ws_bind, reverse_proxy_enabled, ws_advertised_host).await?;
 
-    rocket::build()
+    let rocket = rocket::build()
         .attach(Cors::new())
         .manage(authn)
         .manage(authz)
@@ -146,7 +562,21 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         .manage(db)
         .manage(agent_client)
         .manage(link_download_manager)
+        .manage(migration)
+        .manage(scheduler)
+        .manage(chat_commands)
+        .manage(chat_filter)
+        .manage(alert_rules)
+        .manage(alertmanager_receiver)
+        .manage(operation_webhooks)
+        .manage(feature_flags)
+        .manage(player_notes)
+        .manage(save_hashes)
+        .manage(preferences)
+        .manage(settings_profiles)
+        .manage(player_sessions)
         .manage(ws)
+        .manage(discord_client)
         .mount("/", routes![routes::options::options,])
         .mount(
             "/api/v0",
@@ -158,45 +588,112 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
                 routes::server::status,
                 routes::server::create_savefile,
                 routes::server::start_server,
+                routes::server::plan_start_server,
                 routes::server::stop_server,
+                routes::server::stop_server_scheduled,
+                routes::server::save_server,
                 routes::server::upgrade_install,
+                routes::server::install_from_archive,
                 routes::server::get_install,
                 routes::server::get_savefile,
                 routes::server::extract_mod_list_from_savefile,
+                routes::server::diff_savefiles,
+                routes::server::get_map_preview,
+                routes::server::generate_map_preview,
                 routes::server::delete_savefile,
+                routes::server::rename_savefile,
+                routes::server::copy_savefile,
+                routes::server::backup_savefile,
+                routes::server::get_backups,
                 routes::server::put_savefile,
+                routes::server::upload_savefile,
                 routes::server::get_savefiles,
                 routes::server::get_adminlist,
                 routes::server::put_adminlist,
                 routes::server::get_banlist,
                 routes::server::put_banlist,
+                routes::server::get_config_diagnostics,
+                routes::server::get_restart_policy,
+                routes::server::put_restart_policy,
                 routes::server::get_whitelist,
                 routes::server::put_whitelist,
+                routes::server::get_launch_profiles,
+                routes::server::put_launch_profile,
+                routes::server::delete_launch_profile,
+                routes::server::get_performance_config,
+                routes::server::put_performance_config,
                 routes::server::get_rcon_config,
                 routes::server::put_rcon_config,
                 routes::server::get_secrets,
                 routes::server::put_secrets,
                 routes::server::get_server_settings,
                 routes::server::put_server_settings,
+                routes::settings_profiles::get_settings_profiles,
+                routes::settings_profiles::put_settings_profile,
+                routes::settings_profiles::activate_settings_profile,
                 routes::server::get_dlcs,
                 routes::server::set_dlcs,
                 routes::server::get_mods_list,
                 routes::server::apply_mods_list,
+                routes::server::update_all_mods,
+                routes::server::import_mods_list,
+                routes::server::enable_mod,
+                routes::server::disable_mod,
                 routes::server::get_mod_settings,
                 routes::server::put_mod_settings,
                 routes::server::get_mod_settings_dat,
                 routes::server::put_mod_settings_dat,
                 routes::server::send_rcon_command,
+                routes::server::proxy_rcon,
+                routes::server::console,
+                routes::players::get_players,
+                routes::players::get_player,
+                routes::players::message_player,
+                routes::players::get_player_notes,
+                routes::players::create_player_note,
+                routes::players::update_player_note,
+                routes::players::delete_player_note,
+                routes::preferences::get_preferences,
+                routes::preferences::get_preference,
+                routes::preferences::put_preference,
+                routes::preferences::delete_preference,
+                routes::migration::get_migration_manifest,
+                routes::migration::import_migration_manifest,
+                routes::schedules::get_schedules,
+                routes::schedules::create_schedule,
+                routes::schedules::delete_schedule,
+                routes::schedules::get_jobs,
+                routes::chat_commands::get_chat_commands,
+                routes::chat_commands::put_chat_command,
+                routes::chat_commands::delete_chat_command,
+                routes::chat_filter::get_chat_filter_rules,
+                routes::chat_filter::create_chat_filter_rule,
+                routes::chat_filter::delete_chat_filter_rule,
+                routes::alert_rules::get_alert_rules,
+                routes::alert_rules::create_alert_rule,
+                routes::alert_rules::delete_alert_rule,
+                routes::alerts::incoming,
+                routes::operation_webhooks::get_operation_webhooks,
+                routes::operation_webhooks::create_operation_webhook,
+                routes::operation_webhooks::delete_operation_webhook,
+                routes::feature_flags::get_feature_flags,
+                routes::feature_flags::put_feature_flag,
                 routes::system::monitor,
+                routes::system::health,
+                routes::system::agent_status,
                 routes::logs::get,
+                routes::logs::search,
+                routes::logs::backfill,
                 routes::logs::stream,
                 routes::metrics::get,
+                routes::operations::events,
             ],
         )
         .mount(
             "/proxy",
             routes![
                 routes::proxy::mod_portal_batch_get,
+                routes::proxy::mod_portal_search,
                 routes::proxy::mod_portal_short_get,
                 routes::proxy::mod_portal_full_get,
             ],
@@ -207,11 +704,10 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
                 routes::download::download,
             ]
         )
-        .mount("/", FileServer::from(get_dist_path()))
+        .mount("/", routes![routes::frontend::get])
         .register("/api/v0", catchers![catchers::not_found,])
-        .register("/", catchers![catchers::fallback_to_index_html,])
-        .launch()
-        .await?;
+        .register("/", catchers![catchers::fallback_to_index_html,]);
+    plugins.mount(rocket).launch().await?;
 
     info!("Shutting down");
 
@@ -229,6 +725,7 @@ fn get_dist_path() -> PathBuf {
 async fn create_log_ingestion_subscriber(
     event_broker: Arc<EventBroker>,
     db: Arc<Db>,
+    leadership: Leadership,
 ) -> crate::error::Result<()> {
     let stdout_sub = event_broker
         .subscribe(TopicName::new(STDOUT_TOPIC_NAME), |_| true)
@@ -236,6 +733,11 @@ async fn create_log_ingestion_subscriber(
     tokio::spawn(async move {
         pin_mut!(stdout_sub);
         while let Some(event) = stdout_sub.next().await {
+            // Standby replicas leave ingestion to the leader
+            if !leadership.is_leader() {
+                continue;
+            }
+
             // Map to the right CF
             if let Some(tag_value) = event.tags.get(&TopicName::new(STDOUT_TOPIC_NAME)) {
                 let category = tag_value.as_str();
@@ -271,14 +773,19 @@ async fn create_rpc_subscriber(
     event_broker: Arc<EventBroker>,
     db: Arc<Db>,
     discord: Arc<Option<DiscordClient>>,
+    achievements_policy: AchievementsPolicy,
+    leadership: Leadership,
 ) -> crate::error::Result<()> {
     let rpc_sub = event_broker
         .subscribe(TopicName::new(RPC_TOPIC_NAME), |_| true)
         .await;
     tokio::spawn(async move {
         pin_mut!(rpc_sub);
-        let rpc_handler = Arc::new(RpcHandler::new(agent_client, db, discord));
+        let rpc_handler = Arc::new(RpcHandler::new(agent_client, db, discord, achievements_policy));
         while let Some(mut event) = rpc_sub.next().await {
+            if !leadership.is_leader() {
+                continue;
+            }
             if let Some(command) = event.tags.remove(&TopicName::new(RPC_TOPIC_NAME)) {
                 let rpc_handler = Arc::clone(&rpc_handler);
                 tokio::spawn(async move {
@@ -296,6 +803,88 @@ async fn create_rpc_subscriber(
     Ok(())
 }
 
+async fn create_server_crash_subscriber(
+    event_broker: Arc<EventBroker>,
+    discord: Arc<Option<DiscordClient>>,
+    alert_rules: Arc<AlertRules>,
+    leadership: Leadership,
+) {
+    let crash_sub = event_broker
+        .subscribe(TopicName::new(SERVERCRASH_TOPIC_NAME), |_| true)
+        .await;
+    tokio::spawn(async move {
+        pin_mut!(crash_sub);
+        while let Some(event) = crash_sub.next().await {
+            if !leadership.is_leader() {
+                continue;
+            }
+            let restart_attempt = match serde_json::from_str::<AgentStreamingMessage>(&event.content) {
+                Ok(AgentStreamingMessage {
+                    content: AgentStreamingMessageInner::ServerCrashed { restart_attempt, .. },
+                    ..
+                }) => restart_attempt,
+                _ => None,
+            };
+            let exit_status = event.tags.get(&TopicName::new(SERVERCRASH_TOPIC_NAME)).unwrap();
+            let alert_msg = match restart_attempt {
+                Some(attempt) => format!(
+                    "Factorio server crashed ({}), restarting (attempt {})",
+                    exit_status, attempt
+                ),
+                None => format!(
+                    "Factorio server crashed ({}) and will not be restarted automatically",
+                    exit_status
+                ),
+            };
+            error!("{}", alert_msg);
+            if let Some(discord) = discord.as_ref() {
+                let mut mentions: Vec<_> = alert_rules.crash_mentions().into_iter().map(Some).collect();
+                if mentions.is_empty() {
+                    mentions.push(None);
+                }
+                for mention in mentions {
+                    if let Err(e) = discord.oneshot_alert(mention, alert_msg.clone()) {
+                        error!("Couldn't send server crash alert: {:?}", e);
+                    }
+                }
+            }
+        }
+
+        error!("server crash subscriber task is finishing - this should never happen!");
+    });
+}
+
+async fn create_recovery_subscriber(
+    event_broker: Arc<EventBroker>,
+    discord: Arc<Option<DiscordClient>>,
+    leadership: Leadership,
+) {
+    let recovery_sub = event_broker
+        .subscribe(TopicName::new(RECOVERY_TOPIC_NAME), |_| true)
+        .await;
+    tokio::spawn(async move {
+        pin_mut!(recovery_sub);
+        while let Some(event) = recovery_sub.next().await {
+            if !leadership.is_leader() {
+                continue;
+            }
+            let outcome = event.tags.get(&TopicName::new(RECOVERY_TOPIC_NAME)).unwrap();
+            let alert_msg = format!(
+                "Agent restarted during an operation and recovered from it: {}",
+                outcome
+            );
+            warn!("{}", alert_msg);
+            if let Some(discord) = discord.as_ref() {
+                if let Err(e) = discord.oneshot_alert(None, alert_msg) {
+                    error!("Couldn't send agent recovery alert: {:?}", e);
+                }
+            }
+        }
+
+        error!("agent recovery subscriber task is finishing - this should never happen!");
+    });
+}
+
 struct Cors {}
 
 impl Cors {
@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use fctrl::schema::{
+    AgentOutMessage, AgentResponseWithId, Dlc, FactorioVersion, ModObject, ModSettingsBytes,
+    OperationId, OperationStatus, ServerSettingsConfig,
+};
+use futures::{pin_mut, Stream, StreamExt};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncRead;
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use crate::{
+    clients::{publish_operation_event, AgentApiClient},
+    error::{Error, Result},
+    events::{broker::EventBroker, Event, TopicName, OPERATION_TOPIC_NAME},
+    link_download::{LinkDownloadManager, LinkDownloadTarget},
+    save_upload,
+};
+
+/// Everything needed to recreate a server on another fctrl deployment.
+///
+/// Artifacts too large to inline are referenced by download links on the source deployment,
+/// which expire an hour after the manifest is produced.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MigrationManifest {
+    pub factorio_version: Option<FactorioVersion>,
+    pub dlcs: Vec<Dlc>,
+    pub mods: Vec<ModObject>,
+    /// Server settings without the factorio.com credentials, which stay with each deployment
+    pub server_settings: ServerSettingsConfig,
+    pub mod_settings_url: Option<String>,
+    pub savefile: Option<MigrationSavefile>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MigrationSavefile {
+    pub name: String,
+    pub url: String,
+}
+
+/// Moves a server between fctrl deployments, e.g. onto new hardware.
+///
+/// The source deployment exports a manifest, which is then imported by the target deployment.
+/// The server on the source should be stopped before exporting so that the latest save is final.
+pub struct Migration {
+    agent_client: Arc<AgentApiClient>,
+    event_broker: Arc<EventBroker>,
+    link_download_manager: Arc<LinkDownloadManager>,
+}
+
+impl Migration {
+    pub fn new(
+        agent_client: Arc<AgentApiClient>,
+        event_broker: Arc<EventBroker>,
+        link_download_manager: Arc<LinkDownloadManager>,
+    ) -> Migration {
+        Migration {
+            agent_client,
+            event_broker,
+            link_download_manager,
+        }
+    }
+
+    /// Produces a manifest of this deployment, with download links relative to `base_url`
+    pub async fn export(&self, base_url: &url::Url) -> Result<MigrationManifest> {
+        let mut server_settings = self.agent_client.config_server_settings_get().await?;
+        server_settings.username = None;
+        server_settings.token = None;
+
+        let mod_settings_url = match self.agent_client.mod_settings_get().await {
+            Ok(_) => Some(
+                self.link_url(base_url, LinkDownloadTarget::ModSettingsDat)
+                    .await?,
+            ),
+            Err(Error::ModSettingsNotInitialised) => None,
+            Err(e) => return Err(e),
+        };
+
+        let latest_save = self
+            .agent_client
+            .save_list()
+            .await?
+            .into_iter()
+            .max_by_key(|s| s.last_modified);
+        let savefile = match latest_save {
+            Some(save) => Some(MigrationSavefile {
+                url: self
+                    .link_url(
+                        base_url,
+                        LinkDownloadTarget::Savefile {
+                            id: save.name.clone(),
+                        },
+                    )
+                    .await?,
+                name: save.name,
+            }),
+            None => None,
+        };
+
+        Ok(MigrationManifest {
+            factorio_version: self.agent_client.version_get().await?,
+            dlcs: self
+                .agent_client
+                .mod_dlcs_get()
+                .await?
+                .into_iter()
+                .collect(),
+            mods: self.agent_client.mod_list_get().await?,
+            server_settings,
+            mod_settings_url,
+            savefile,
+        })
+    }
+
+    /// Starts recreating the server described by the manifest on this deployment, returning a
+    /// stream of progress responses for the import operation
+    pub async fn import(
+        self: Arc<Self>,
+        manifest: MigrationManifest,
+    ) -> Result<(OperationId, impl Stream<Item = Event> + Unpin)> {
+        let operation_id = OperationId(Uuid::new_v4().to_string());
+        let id_clone = operation_id.clone();
+        let sub = self
+            .event_broker
+            .subscribe(TopicName::new(OPERATION_TOPIC_NAME), move |v| {
+                v == id_clone.0
+            })
+            .await;
+
+        let id_clone = operation_id.clone();
+        tokio::spawn(async move {
+            let (status, content) = match self.run_import(&id_clone, manifest).await {
+                Ok(()) => {
+                    info!("Migration import {} completed", id_clone.0);
+                    (OperationStatus::Completed, AgentOutMessage::Ok)
+                }
+                Err(e) => {
+                    error!("Migration import {} failed: {:?}", id_clone.0, e);
+                    (
+                        OperationStatus::Failed,
+                        AgentOutMessage::Error(format!("Migration failed: {:?}", e)),
+                    )
+                }
+            };
+            publish_operation_event(&self.event_broker, id_clone, status, content).await;
+        });
+
+        Ok((operation_id, sub))
+    }
+
+    async fn run_import(
+        &self,
+        operation_id: &OperationId,
+        manifest: MigrationManifest,
+    ) -> Result<()> {
+        if let Some(version) = manifest.factorio_version {
+            self.progress(operation_id, format!("Installing Factorio {}", version.0))
+                .await;
+            let (_id, sub) = self
+                .agent_client
+                .version_install(version, false, false)
+                .await?;
+            self.wait_for_completion(operation_id, sub).await?;
+        }
+
+        self.progress(operation_id, "Setting DLC".to_owned()).await;
+        self.agent_client
+            .mod_dlcs_set(manifest.dlcs.into_iter().collect())
+            .await?;
+
+        self.progress(
+            operation_id,
+            format!("Installing {} mods", manifest.mods.len()),
+        )
+        .await;
+        let (_id, sub) = self.agent_client.mod_list_set(manifest.mods).await?;
+        self.wait_for_completion(operation_id, sub).await?;
+
+        if let Some(url) = manifest.mod_settings_url {
+            self.progress(operation_id, "Copying mod settings".to_owned())
+                .await;
+            let bytes = download(&url).await?;
+            self.agent_client
+                .mod_settings_set(ModSettingsBytes { bytes })
+                .await?;
+        }
+
+        self.progress(operation_id, "Copying server settings".to_owned())
+            .await;
+        self.agent_client
+            .config_server_settings_set(manifest.server_settings)
+            .await?;
+
+        if let Some(savefile) = manifest.savefile {
+            self.progress(operation_id, format!("Copying savefile {}", savefile.name))
+                .await;
+            let body = download_stream(&savefile.url).await?;
+            save_upload::upload(&self.agent_client, savefile.name, body, None, None).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn link_url(&self, base_url: &url::Url, target: LinkDownloadTarget) -> Result<String> {
+        let link_id = self.link_download_manager.create_link(target).await;
+        base_url
+            .join(&format!("download/{}", link_id))
+            .map(|url| url.to_string())
+            .map_err(|e| Error::BadRequest(format!("Invalid base url: {:?}", e)))
+    }
+
+    async fn progress(&self, operation_id: &OperationId, message: String) {
+        info!("Migration import {}: {}", operation_id.0, message);
+        publish_operation_event(
+            &self.event_broker,
+            operation_id.clone(),
+            OperationStatus::Ongoing,
+            AgentOutMessage::Message(message),
+        )
+        .await;
+    }
+
+    /// Waits for a long-running agent operation to finish, relaying its progress messages as
+    /// progress of the import operation
+    async fn wait_for_completion(
+        &self,
+        operation_id: &OperationId,
+        sub: impl Stream<Item = Event>,
+    ) -> Result<()> {
+        pin_mut!(sub);
+        while let Some(event) = sub.next().await {
+            let response = match serde_json::from_str::<AgentResponseWithId>(&event.content) {
+                Ok(response) => response,
+                Err(_) => continue,
+            };
+            match (response.status, response.content) {
+                (OperationStatus::Completed, _) => return Ok(()),
+                (OperationStatus::Failed, content) => {
+                    return Err(Error::AgentInternalError(format!("{:?}", content)))
+                }
+                (_, AgentOutMessage::Message(message)) => {
+                    self.progress(operation_id, message).await
+                }
+                _ => (),
+            }
+        }
+        Err(Error::AgentCommunicationError)
+    }
+}
+
+async fn download(url: &str) -> Result<Vec<u8>> {
+    let response = reqwest::get(url).await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Downloads as the body is read, for savefiles too large to hold in memory
+async fn download_stream(url: &str) -> Result<impl AsyncRead + Unpin> {
+    let response = reqwest::get(url).await?.error_for_status()?;
+    let chunks = futures::stream::unfold(Some(response), |response| async move {
+        let mut response = response?;
+        match response.chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(response))),
+            Ok(None) => None,
+            Err(e) => Some((Err(std::io::Error::new(std::io::ErrorKind::Other, e)), None)),
+        }
+    });
+    Ok(StreamReader::new(Box::pin(chunks)))
+}
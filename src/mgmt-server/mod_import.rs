@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+use async_zip::{error::ZipError, tokio::read::fs::ZipFileReader};
+use fctrl::schema::{regex::MOD_FILENAME_RE, Dlc, ModObject, ModVersion};
+use futures::AsyncReadExt;
+use log::warn;
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+const MOD_LIST_FILE_NAME: &str = "mod-list.json";
+
+/// The mod-list.json file in the mod directory of a Factorio installation
+#[derive(Deserialize)]
+struct ModList {
+    mods: Vec<ModListElem>,
+}
+
+#[derive(Deserialize)]
+struct ModListElem {
+    name: String,
+    enabled: bool,
+    version: Option<String>,
+}
+
+/// Reads the mod list of an existing server from its mod-list.json. Mods without a version are
+/// set to the latest release, and DLCs are left out as they aren't downloaded.
+pub fn from_mod_list_json(json: &str) -> Result<Vec<ModObject>> {
+    let mod_list: ModList = serde_json::from_str(json)
+        .map_err(|e| Error::BadRequest(format!("Invalid mod-list.json: {}", e)))?;
+    Ok(mod_list
+        .mods
+        .into_iter()
+        .filter(|elem| Dlc::from_str(&elem.name).is_err())
+        .map(|elem| ModObject {
+            name: elem.name,
+            version: elem
+                .version
+                .unwrap_or_else(|| ModVersion::Latest.to_string()),
+            enabled: elem.enabled,
+        })
+        .collect())
+}
+
+/// Reads the mod list of a mod pack, that is a zip of mod zips such as an existing server's mod
+/// directory. Mods disabled in a mod-list.json alongside them are imported as disabled.
+pub async fn from_mod_pack_zip(path: &Path) -> Result<Vec<ModObject>> {
+    let reader = ZipFileReader::new(path).await.map_err(invalid_mod_pack)?;
+
+    let mut mods: BTreeMap<String, ModObject> = BTreeMap::new();
+    let mut mod_list_index = None;
+    for (index, entry) in reader.file().entries().iter().enumerate() {
+        let filename = match entry.filename().as_str() {
+            Ok(filename) => filename,
+            Err(_) => {
+                warn!(
+                    "unable to convert zip entry filename '{:?}' to UTF-8, skipping",
+                    entry.filename()
+                );
+                continue;
+            }
+        };
+        // mods may be at the top level or under a directory such as mods/
+        let filename = filename.rsplit('/').next().unwrap_or(filename);
+        if filename == MOD_LIST_FILE_NAME {
+            mod_list_index = Some(index);
+        } else if let Some(captures) = MOD_FILENAME_RE.captures(filename) {
+            let m = ModObject {
+                name: captures[1].to_owned(),
+                version: captures[2].to_owned(),
+                enabled: true,
+            };
+            // the game loads the newest of several versions of a mod
+            let is_newer = mods.get(&m.name).map_or(true, |existing| {
+                ModVersion::from(m.version.as_str()) > ModVersion::from(existing.version.as_str())
+            });
+            if is_newer {
+                mods.insert(m.name.clone(), m);
+            }
+        }
+    }
+
+    let mod_list = match mod_list_index {
+        Some(index) => {
+            let mut entry_reader = reader
+                .reader_without_entry(index)
+                .await
+                .map_err(invalid_mod_pack)?;
+            let mut json = String::new();
+            entry_reader.read_to_string(&mut json).await?;
+            Some(from_mod_list_json(&json)?)
+        }
+        None => None,
+    };
+
+    match mod_list {
+        // a mod pack of only a mod list is imported like the mod list on its own
+        Some(mod_list) if mods.is_empty() => Ok(mod_list),
+        Some(mod_list) => {
+            for elem in mod_list {
+                if let Some(m) = mods.get_mut(&elem.name) {
+                    m.enabled = elem.enabled;
+                }
+            }
+            Ok(mods.into_values().collect())
+        }
+        None => Ok(mods.into_values().collect()),
+    }
+}
+
+fn invalid_mod_pack(e: ZipError) -> Error {
+    Error::BadRequest(format!("Failed to read mod pack zip: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_mod_list_without_dlcs() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mods = from_mod_list_json(
+            r#"{
+                "mods": [
+                    { "name": "base", "enabled": true },
+                    { "name": "space-age", "enabled": true },
+                    { "name": "rso-mod", "enabled": true, "version": "6.2.23" },
+                    { "name": "Squeak Through", "enabled": false }
+                ]
+            }"#,
+        )?;
+        assert_eq!(mods.len(), 2);
+        assert_eq!(mods[0].name, "rso-mod");
+        assert_eq!(mods[0].version, "6.2.23");
+        assert!(mods[0].enabled);
+        assert_eq!(mods[1].name, "Squeak Through");
+        assert_eq!(mods[1].version, "latest");
+        assert!(!mods[1].enabled);
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, EventLoop, MqttOptions, Packet, QoS};
+use serde_json::json;
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter, EnumString};
+use tokio::time::Instant;
+
+use crate::{
+    error::{Error, Result},
+    events::{
+        Event, TopicName, CHAT_TOPIC_NAME, JOIN_TOPIC_NAME, LEAVE_TOPIC_NAME, RPC_TOPIC_NAME,
+        SERVERSTATE_TOPIC_NAME,
+    },
+    plugins::{Plugin, Subscription},
+};
+
+const DEFAULT_CLIENT_ID: &str = "fctrl";
+const DEFAULT_TOPIC_TEMPLATE: &str = "fctrl/{category}";
+const DEFAULT_PORT: u16 = 1883;
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+
+/// Bounds of the exponential backoff between attempts to reach the broker while it is unavailable
+const RETRY_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Messages held while the broker is unavailable. Newer messages are dropped beyond this.
+const MAX_BUFFERED_MESSAGES: usize = 500;
+
+/// Kinds of events that can be published, substituted for `{category}` in the topic template
+#[derive(AsRefStr, Clone, Copy, Debug, EnumIter, EnumString, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+enum Category {
+    Join,
+    Leave,
+    Chat,
+    ServerState,
+    /// Metrics samples streamed from the game by the fctrl mod
+    Metrics,
+}
+
+impl Category {
+    fn subscription(self) -> Subscription {
+        match self {
+            Category::Join => Subscription::all(JOIN_TOPIC_NAME),
+            Category::Leave => Subscription::all(LEAVE_TOPIC_NAME),
+            Category::Chat => Subscription::all(CHAT_TOPIC_NAME),
+            Category::ServerState => Subscription::all(SERVERSTATE_TOPIC_NAME),
+            Category::Metrics => {
+                Subscription::filtered(RPC_TOPIC_NAME, |command| command.starts_with("stream "))
+            }
+        }
+    }
+
+    fn from_topic(topic: &TopicName) -> Option<Category> {
+        match topic.name.as_str() {
+            JOIN_TOPIC_NAME => Some(Category::Join),
+            LEAVE_TOPIC_NAME => Some(Category::Leave),
+            CHAT_TOPIC_NAME => Some(Category::Chat),
+            SERVERSTATE_TOPIC_NAME => Some(Category::ServerState),
+            RPC_TOPIC_NAME => Some(Category::Metrics),
+            _ => None,
+        }
+    }
+
+    /// JSON payload for the event, given the value of its topic tag
+    fn payload(self, event: &Event, value: &str) -> Option<serde_json::Value> {
+        let timestamp = event.timestamp.to_rfc3339();
+        match self {
+            Category::Join | Category::Leave => Some(json!({
+                "timestamp": timestamp,
+                "player": value,
+            })),
+            Category::Chat => {
+                // assume names cannot have colon
+                let (player, message) = value.split_once(": ")?;
+                Some(json!({
+                    "timestamp": timestamp,
+                    "player": player,
+                    "message": message,
+                }))
+            }
+            Category::ServerState => {
+                let (from, to) = value.split_once(' ')?;
+                Some(json!({
+                    "timestamp": timestamp,
+                    "from": from,
+                    "to": to,
+                }))
+            }
+            Category::Metrics => {
+                let batch: serde_json::Value =
+                    serde_json::from_str(value.strip_prefix("stream ")?).ok()?;
+                Some(json!({
+                    "timestamp": timestamp,
+                    "tick": batch.get("timestamp")?,
+                    "data": batch.get("data")?,
+                }))
+            }
+        }
+    }
+}
+
+/// Publishes selected events to an MQTT broker as JSON, e.g. for home automation or custom
+/// dashboards.
+///
+/// Each kind of event is published to the topic given by the template, with `{category}` replaced
+/// by one of join, leave, chat, serverstate or metrics.
+pub struct MqttPublisher {
+    categories: Vec<Category>,
+    topic_template: String,
+    qos: QoS,
+    client: AsyncClient,
+}
+
+impl MqttPublisher {
+    /// Configures the publisher from MQTT_* env vars, connecting to the broker at
+    /// MQTT_BROKER_ADDRESS. Returns None if no broker is configured.
+    pub fn from_env() -> Result<Option<Arc<MqttPublisher>>> {
+        let address = match std::env::var("MQTT_BROKER_ADDRESS") {
+            Ok(address) => address,
+            Err(_) => return Ok(None),
+        };
+        let qos = match std::env::var("MQTT_QOS").as_deref() {
+            Ok("0") | Err(_) => QoS::AtMostOnce,
+            // resent on reconnecting until acknowledged by the broker
+            Ok("1") => QoS::AtLeastOnce,
+            Ok(other) => {
+                return Err(Error::Misconfiguration(format!(
+                    "Unsupported MQTT_QOS '{}', must be 0 or 1",
+                    other
+                )))
+            }
+        };
+        let categories = match std::env::var("MQTT_CATEGORIES") {
+            Ok(s) => s
+                .split(',')
+                .map(|c| {
+                    Category::from_str(c.trim()).map_err(|_| {
+                        Error::Misconfiguration(format!("Unknown MQTT event category '{}'", c))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Err(_) => Category::iter().collect(),
+        };
+        let (host, port) = parse_address(&address)?;
+        let mut options = MqttOptions::new(
+            std::env::var("MQTT_CLIENT_ID").unwrap_or_else(|_| DEFAULT_CLIENT_ID.to_owned()),
+            host,
+            port,
+        );
+        options.set_keep_alive(KEEP_ALIVE);
+        if let Ok(username) = std::env::var("MQTT_USERNAME") {
+            options.set_credentials(username, std::env::var("MQTT_PASSWORD").unwrap_or_default());
+        }
+        info!(
+            "Publishing events {:?} to MQTT broker at {} with {:?}",
+            categories, address, qos
+        );
+
+        let (client, eventloop) = AsyncClient::new(options, MAX_BUFFERED_MESSAGES);
+        tokio::spawn(run(address, eventloop));
+        Ok(Some(Arc::new(MqttPublisher {
+            categories,
+            topic_template: std::env::var("MQTT_TOPIC_TEMPLATE")
+                .unwrap_or_else(|_| DEFAULT_TOPIC_TEMPLATE.to_owned()),
+            qos,
+            client,
+        })))
+    }
+
+    fn topic(&self, category: Category) -> String {
+        self.topic_template.replace("{category}", category.as_ref())
+    }
+}
+
+#[rocket::async_trait]
+impl Plugin for MqttPublisher {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn subscriptions(&self) -> Vec<Subscription> {
+        self.categories.iter().map(|c| c.subscription()).collect()
+    }
+
+    async fn on_event(&self, topic: &TopicName, event: Event) {
+        let category = match Category::from_topic(topic) {
+            Some(category) => category,
+            None => return,
+        };
+        let payload = match event
+            .tags
+            .get(topic)
+            .and_then(|value| category.payload(&event, value))
+        {
+            Some(payload) => payload,
+            None => {
+                warn!(
+                    "Couldn't build MQTT payload for {:?} event: {:?}",
+                    category, event
+                );
+                return;
+            }
+        };
+        let publish =
+            self.client
+                .try_publish(self.topic(category), self.qos, false, payload.to_string());
+        if publish.is_err() {
+            debug!(
+                "MQTT broker unavailable and buffer full, dropping {:?} event",
+                category
+            );
+        }
+    }
+}
+
+/// Splits a broker address into host and port, defaulting to the standard MQTT port
+fn parse_address(address: &str) -> Result<(String, u16)> {
+    match address.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port.parse().map_err(|_| {
+                Error::Misconfiguration(format!("Invalid MQTT broker address '{}'", address))
+            })?;
+            Ok((host.to_owned(), port))
+        }
+        None => Ok((address.to_owned(), DEFAULT_PORT)),
+    }
+}
+
+/// Drives the connection to the broker, which publishes messages as they are sent by the client.
+/// Reconnects with backoff whenever the connection fails, resending any unacknowledged messages.
+async fn run(address: String, mut eventloop: EventLoop) {
+    let mut backoff = RETRY_BACKOFF_MIN;
+    let mut connected_at = None;
+    loop {
+        match eventloop.poll().await {
+            Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Connected to MQTT broker at {}", address);
+                connected_at = Some(Instant::now());
+            }
+            Ok(_) => (),
+            Err(e) => {
+                warn!("MQTT connection to {} failed: {:?}", address, e);
+                if connected_at
+                    .take()
+                    .map_or(false, |t| t.elapsed() > RETRY_BACKOFF_MAX)
+                {
+                    backoff = RETRY_BACKOFF_MIN;
+                }
+                info!("Reconnecting to MQTT broker in {:?}", backoff);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(RETRY_BACKOFF_MAX);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_broker_address() {
+        assert_eq!(
+            parse_address("mosquitto:8883").unwrap(),
+            ("mosquitto".to_owned(), 8883)
+        );
+        assert_eq!(
+            parse_address("localhost").unwrap(),
+            ("localhost".to_owned(), DEFAULT_PORT)
+        );
+        assert!(parse_address("localhost:mqtt").is_err());
+    }
+}
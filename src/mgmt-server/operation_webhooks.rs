@@ -0,0 +1,243 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use fctrl::schema::{
+    AgentOutMessage, AgentRequest, AgentRequestWithId, AgentResponseWithId, OperationStatus,
+};
+use futures::{pin_mut, StreamExt};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clients::OUTGOING_TOPIC_NAME,
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+    events::{broker::EventBroker, TopicName, OPERATION_TOPIC_NAME},
+};
+
+lazy_static! {
+    static ref OPERATION_WEBHOOKS_CF: Cf = Cf("operation_webhooks".to_owned());
+}
+
+/// Long-running operations that webhooks can be registered for
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum WebhookRequestType {
+    VersionInstall,
+    ModListSet,
+    ModListUpdateAll,
+    SaveCreate,
+}
+
+impl WebhookRequestType {
+    pub fn parse(s: &str) -> Option<WebhookRequestType> {
+        match s {
+            "VersionInstall" => Some(WebhookRequestType::VersionInstall),
+            "ModListSet" => Some(WebhookRequestType::ModListSet),
+            "ModListUpdateAll" => Some(WebhookRequestType::ModListUpdateAll),
+            "SaveCreate" => Some(WebhookRequestType::SaveCreate),
+            _ => None,
+        }
+    }
+
+    fn of(request: &AgentRequest) -> Option<WebhookRequestType> {
+        match request {
+            AgentRequest::VersionInstall { .. } => Some(WebhookRequestType::VersionInstall),
+            AgentRequest::ModListSet(_) => Some(WebhookRequestType::ModListSet),
+            AgentRequest::ModListUpdateAll => Some(WebhookRequestType::ModListUpdateAll),
+            AgentRequest::SaveCreate(..) => Some(WebhookRequestType::SaveCreate),
+            _ => None,
+        }
+    }
+}
+
+/// A URL to notify when operations of the given types finish, persisted in the db
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OperationWebhook {
+    pub id: String,
+    pub url: String,
+    pub request_types: Vec<WebhookRequestType>,
+}
+
+/// Body of the request sent to an operation webhook
+#[derive(Debug, Serialize)]
+struct OperationWebhookBody<'a> {
+    operation_id: &'a str,
+    request_type: WebhookRequestType,
+    /// Either `completed` or `failed`
+    status: &'static str,
+    /// Reason for the failure, if failed
+    error: Option<String>,
+    timestamp: DateTime<Utc>,
+}
+
+/// Sends a POST request to registered webhooks whenever a matching operation completes or fails,
+/// so that external automation can chain actions off fctrl operations.
+///
+/// Operations are matched to their request type as they are sent to the agent, so each replica
+/// notifies for the operations it sent.
+pub struct OperationWebhooks {
+    db: Arc<Db>,
+}
+
+impl OperationWebhooks {
+    pub fn new(db: Arc<Db>) -> OperationWebhooks {
+        OperationWebhooks { db }
+    }
+
+    pub async fn start(self: &Arc<Self>, event_broker: Arc<EventBroker>) {
+        let outgoing_sub = event_broker
+            .subscribe(TopicName::new(OUTGOING_TOPIC_NAME), |_| true)
+            .await;
+        let operation_sub = event_broker
+            .subscribe(TopicName::new(OPERATION_TOPIC_NAME), |_| true)
+            .await;
+
+        let webhooks = Arc::clone(self);
+        let http = reqwest::Client::new();
+        tokio::spawn(async move {
+            pin_mut!(outgoing_sub);
+            pin_mut!(operation_sub);
+            // operations in progress that a webhook may be interested in, by operation id
+            let mut in_progress = HashMap::new();
+            loop {
+                tokio::select! {
+                    Some(event) = outgoing_sub.next() => {
+                        if let Ok(request) = serde_json::from_str::<AgentRequestWithId>(&event.content) {
+                            if let Some(request_type) = WebhookRequestType::of(&request.message) {
+                                in_progress.insert(request.operation_id.0, request_type);
+                            }
+                        }
+                    }
+                    Some(event) = operation_sub.next() => {
+                        let response = match serde_json::from_str::<AgentResponseWithId>(&event.content) {
+                            Ok(response) => response,
+                            Err(_) => continue,
+                        };
+                        let (status, error) = match (response.status, response.content) {
+                            (OperationStatus::Completed, _) => ("completed", None),
+                            (OperationStatus::Failed, AgentOutMessage::Error(e)) => ("failed", Some(e)),
+                            (OperationStatus::Failed, m) => ("failed", Some(format!("{:?}", m))),
+                            _ => continue,
+                        };
+                        let request_type = match in_progress.remove(&response.operation_id.0) {
+                            Some(request_type) => request_type,
+                            None => continue,
+                        };
+                        let body = OperationWebhookBody {
+                            operation_id: &response.operation_id.0,
+                            request_type,
+                            status,
+                            error,
+                            timestamp: response.timestamp,
+                        };
+                        for webhook in webhooks.matching(request_type) {
+                            info!(
+                                "Notifying webhook {} of {:?} operation {} {}",
+                                webhook.id, request_type, body.operation_id, status
+                            );
+                            let result = http
+                                .post(webhook.url)
+                                .json(&body)
+                                .send()
+                                .await
+                                .and_then(|r| r.error_for_status());
+                            if let Err(e) = result {
+                                error!("Couldn't notify operation webhook {}: {:?}", webhook.id, e);
+                            }
+                        }
+                    }
+                    else => break,
+                }
+            }
+
+            error!("operation webhooks subscriber task is finishing - this should never happen!");
+        });
+    }
+
+    pub fn list(&self) -> Result<Vec<OperationWebhook>> {
+        self.db
+            .read_prefix(&OPERATION_WEBHOOKS_CF, "")?
+            .into_iter()
+            .map(|r| Ok(serde_json::from_str(&r.value)?))
+            .collect()
+    }
+
+    pub fn create(
+        &self,
+        url: String,
+        request_types: Vec<WebhookRequestType>,
+    ) -> Result<OperationWebhook> {
+        let parsed = url::Url::parse(&url)
+            .map_err(|e| Error::BadRequest(format!("Invalid webhook URL: {}", e)))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(Error::BadRequest(
+                "Webhook URL must be http or https".to_owned(),
+            ));
+        }
+        if request_types.is_empty() {
+            return Err(Error::BadRequest(
+                "At least one request type is required".to_owned(),
+            ));
+        }
+        let webhook = OperationWebhook {
+            id: uuid::Uuid::new_v4().to_string(),
+            url,
+            request_types,
+        };
+        self.db.write(
+            &OPERATION_WEBHOOKS_CF,
+            &Record {
+                key: webhook.id.clone(),
+                value: serde_json::to_string(&webhook)?,
+            },
+        )?;
+        info!(
+            "Created operation webhook {} for {:?}",
+            webhook.id, webhook.request_types
+        );
+        Ok(webhook)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<()> {
+        if self
+            .db
+            .read(&OPERATION_WEBHOOKS_CF, id.to_owned())?
+            .is_none()
+        {
+            return Err(Error::OperationWebhookNotFound);
+        }
+        self.db.delete(&OPERATION_WEBHOOKS_CF, id)
+    }
+
+    fn matching(&self, request_type: WebhookRequestType) -> Vec<OperationWebhook> {
+        match self.list() {
+            Ok(webhooks) => webhooks
+                .into_iter()
+                .filter(|w| w.request_types.contains(&request_type))
+                .collect(),
+            Err(e) => {
+                error!("Couldn't read operation webhooks: {:?}", e);
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_long_running_requests_only() {
+        assert_eq!(
+            WebhookRequestType::of(&AgentRequest::SaveCreate("test".to_owned(), None, None)),
+            Some(WebhookRequestType::SaveCreate)
+        );
+        assert_eq!(
+            WebhookRequestType::of(&AgentRequest::ModListSet(vec![])),
+            Some(WebhookRequestType::ModListSet)
+        );
+        assert_eq!(WebhookRequestType::of(&AgentRequest::ServerStop), None);
+    }
+}
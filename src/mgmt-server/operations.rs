@@ -0,0 +1,103 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use fctrl::schema::{AgentOutMessage, AgentResponseWithId};
+use futures::{pin_mut, StreamExt};
+use lazy_static::lazy_static;
+use log::{error, info};
+
+use crate::{
+    db::{Cf, Db, Record},
+    error::Result,
+    events::{broker::EventBroker, TopicName, OPERATION_TOPIC_NAME},
+    ha::Leadership,
+    scheduler::{JobDefinition, Scheduler},
+};
+
+lazy_static! {
+    pub static ref OPERATION_HISTORY_CF: Cf = Cf("operation_history".to_owned());
+}
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Key prefix under which all events for an operation are stored
+pub fn operation_key_prefix(operation_id: &str) -> String {
+    format!("{}/", operation_id)
+}
+
+/// Persists every agent response by operation id, so that the outcome of an operation can be
+/// reviewed after its response stream has closed. Records older than `ttl` are periodically purged.
+pub async fn create_operation_history_subscriber(
+    event_broker: Arc<EventBroker>,
+    db: Arc<Db>,
+    leadership: Leadership,
+    scheduler: &Scheduler,
+    ttl: Duration,
+) -> Result<()> {
+    let operation_sub = event_broker
+        .subscribe(TopicName::new(OPERATION_TOPIC_NAME), |_| true)
+        .await;
+    let db_clone = Arc::clone(&db);
+    tokio::spawn(async move {
+        pin_mut!(operation_sub);
+        while let Some(event) = operation_sub.next().await {
+            if !leadership.is_leader() {
+                continue;
+            }
+
+            match serde_json::from_str::<AgentResponseWithId>(&event.content) {
+                Ok(response) => {
+                    match response.content {
+                        // savefile transfers and other file contents are bulk data, not useful
+                        // to review later
+                        AgentOutMessage::SaveFile(_)
+                        | AgentOutMessage::MapPreview(_)
+                        | AgentOutMessage::ModSettings(_)
+                        // avoid persisting credentials
+                        | AgentOutMessage::ConfigRcon(_)
+                        | AgentOutMessage::ConfigSecrets(_)
+                        | AgentOutMessage::ConfigServerSettings(_) => continue,
+                        _ => (),
+                    }
+                    let record = Record {
+                        key: format!(
+                            "{}{}",
+                            operation_key_prefix(&response.operation_id.0),
+                            response.timestamp.to_rfc3339()
+                        ),
+                        value: event.content,
+                    };
+                    if let Err(e) = db_clone.write(&OPERATION_HISTORY_CF, &record) {
+                        error!("Error writing to db: {:?}", e);
+                    }
+                }
+                Err(e) => error!("Failed to deserialise operation event: {:?}", e),
+            }
+        }
+
+        error!("operation history subscriber task is finishing - this should never happen!");
+    });
+
+    let ttl = chrono::Duration::seconds(ttl.as_secs() as i64);
+    let definition = JobDefinition {
+        name: "operation_history_cleanup",
+        interval: CLEANUP_INTERVAL,
+        jitter: Duration::ZERO,
+    };
+    scheduler.register(definition, move || {
+        let db = Arc::clone(&db);
+        Box::pin(async move {
+            let cutoff = Utc::now() - ttl;
+            let deleted = db.retain(&OPERATION_HISTORY_CF, |r| {
+                serde_json::from_str::<AgentResponseWithId>(&r.value)
+                    .map_or(false, |response| response.timestamp > cutoff)
+            })?;
+            if deleted > 0 {
+                info!("Purged {} expired operation history records", deleted);
+            }
+            Ok(())
+        })
+    });
+
+    Ok(())
+}
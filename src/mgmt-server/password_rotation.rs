@@ -0,0 +1,126 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use fctrl::schema::ServerStatus;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{
+    clients::AgentApiClient,
+    db::{Cf, Db, Record},
+    discord::DiscordClient,
+    error::Result,
+    scheduler::{JobDefinition, Scheduler},
+};
+
+const PASSWORD_LENGTH: usize = 12;
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref PASSWORD_ROTATION_CF: Cf = Cf("password_rotation".to_owned());
+}
+const LAST_ROTATED_KEY: &str = "last_rotated";
+
+/// Periodically replaces `game_password` in the server settings with a freshly generated one.
+///
+/// The new password is also applied to a running server with `/config set password`, so that it
+/// takes effect straight away rather than on the next server start.
+pub struct PasswordRotation {
+    interval: chrono::Duration,
+    agent_client: Arc<AgentApiClient>,
+    db: Arc<Db>,
+    discord: Arc<Option<DiscordClient>>,
+}
+
+impl PasswordRotation {
+    pub fn new(
+        interval: Duration,
+        agent_client: Arc<AgentApiClient>,
+        db: Arc<Db>,
+        discord: Arc<Option<DiscordClient>>,
+    ) -> PasswordRotation {
+        PasswordRotation {
+            interval: chrono::Duration::seconds(interval.as_secs() as i64),
+            agent_client,
+            db,
+            discord,
+        }
+    }
+
+    pub fn start(self, scheduler: &Scheduler) {
+        let rotation = Arc::new(self);
+        let definition = JobDefinition {
+            name: "password_rotation",
+            interval: CHECK_INTERVAL,
+            jitter: Duration::ZERO,
+        };
+        scheduler.register(definition, move || {
+            let rotation = Arc::clone(&rotation);
+            Box::pin(async move {
+                if rotation.is_due()? {
+                    rotation.rotate().await?;
+                }
+                Ok(())
+            })
+        });
+    }
+
+    fn is_due(&self) -> Result<bool> {
+        let last_rotated = self
+            .db
+            .read(&PASSWORD_ROTATION_CF, LAST_ROTATED_KEY.to_owned())?
+            .and_then(|r| DateTime::parse_from_rfc3339(&r.value).ok())
+            .map(|dt| dt.with_timezone(&Utc));
+        Ok(match last_rotated {
+            Some(last_rotated) => Utc::now() >= last_rotated + self.interval,
+            None => true,
+        })
+    }
+
+    async fn rotate(&self) -> Result<()> {
+        let password: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(PASSWORD_LENGTH)
+            .map(char::from)
+            .collect();
+
+        let mut config = self.agent_client.config_server_settings_get().await?;
+        config.game_password = password.clone();
+        self.agent_client.config_server_settings_set(config).await?;
+        self.db.write(
+            &PASSWORD_ROTATION_CF,
+            &Record {
+                key: LAST_ROTATED_KEY.to_owned(),
+                value: Utc::now().to_rfc3339(),
+            },
+        )?;
+        info!("Rotated game password");
+
+        let applies_on_restart = match self.agent_client.server_status().await {
+            Ok(ServerStatus::NotRunning) => false,
+            _ => {
+                let cmd = format!("/config set password {}", password);
+                match self.agent_client.rcon_command(cmd).await {
+                    Ok(_) => {
+                        info!("Applied new game password to running server");
+                        false
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Couldn't apply new game password to running server: {:?}",
+                            e
+                        );
+                        true
+                    }
+                }
+            }
+        };
+        match &*self.discord {
+            Some(discord) => discord.post_game_password(&password, applies_on_restart)?,
+            None => warn!("Discord integration disabled, new game password will not be posted"),
+        }
+
+        Ok(())
+    }
+}
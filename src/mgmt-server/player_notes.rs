@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, EnumString};
+
+use crate::{
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+};
+
+lazy_static! {
+    static ref PLAYER_NOTES_CF: Cf = Cf("player_notes".to_owned());
+}
+
+#[derive(AsRefStr, Clone, Copy, Debug, Deserialize, EnumString, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PlayerNoteKind {
+    /// Informal note for other moderators
+    Note,
+    /// Formal warning issued to the player
+    Warning,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlayerNote {
+    pub id: String,
+    pub player: String,
+    pub kind: PlayerNoteKind,
+    pub text: String,
+    /// Identity of the moderator who wrote the note
+    pub author: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Moderation notes and warnings per player, kept in the db
+pub struct PlayerNotes {
+    db: Arc<Db>,
+}
+
+impl PlayerNotes {
+    pub fn new(db: Arc<Db>) -> PlayerNotes {
+        PlayerNotes { db }
+    }
+
+    /// All notes for the player, oldest first
+    pub fn list(&self, player: &str) -> Result<Vec<PlayerNote>> {
+        let mut notes = self
+            .db
+            .read_prefix(&PLAYER_NOTES_CF, &key_prefix(player))?
+            .into_iter()
+            .map(|r| Ok(serde_json::from_str::<PlayerNote>(&r.value)?))
+            .collect::<Result<Vec<_>>>()?;
+        notes.sort_by_key(|n| n.created_at);
+        Ok(notes)
+    }
+
+    pub fn create(
+        &self,
+        player: String,
+        kind: PlayerNoteKind,
+        text: String,
+        author: String,
+    ) -> Result<PlayerNote> {
+        let note = PlayerNote {
+            id: uuid::Uuid::new_v4().to_string(),
+            player,
+            kind,
+            text,
+            author,
+            created_at: Utc::now(),
+            updated_at: None,
+        };
+        self.write(&note)?;
+        Ok(note)
+    }
+
+    pub fn update(&self, player: &str, id: &str, text: String) -> Result<PlayerNote> {
+        let mut note = self.read(player, id)?;
+        note.text = text;
+        note.updated_at = Some(Utc::now());
+        self.write(&note)?;
+        Ok(note)
+    }
+
+    pub fn delete(&self, player: &str, id: &str) -> Result<()> {
+        self.read(player, id)?;
+        self.db.delete(&PLAYER_NOTES_CF, &key(player, id))
+    }
+
+    fn read(&self, player: &str, id: &str) -> Result<PlayerNote> {
+        match self.db.read(&PLAYER_NOTES_CF, key(player, id))? {
+            Some(record) => Ok(serde_json::from_str(&record.value)?),
+            None => Err(Error::PlayerNoteNotFound),
+        }
+    }
+
+    fn write(&self, note: &PlayerNote) -> Result<()> {
+        self.db.write(
+            &PLAYER_NOTES_CF,
+            &Record {
+                key: key(&note.player, &note.id),
+                value: serde_json::to_string(note)?,
+            },
+        )
+    }
+}
+
+fn key_prefix(player: &str) -> String {
+    format!("{}/", player)
+}
+
+fn key(player: &str, id: &str) -> String {
+    format!("{}{}", key_prefix(player), id)
+}
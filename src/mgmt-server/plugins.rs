@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use futures::{pin_mut, StreamExt};
+use log::{error, info};
+use rocket::{Build, Rocket};
+
+use crate::{
+    events::{broker::EventBroker, Event, TopicName},
+    ha::Leadership,
+};
+
+/// Base path that each plugin's routes are mounted under, followed by the plugin name
+const PLUGINS_BASE_PATH: &str = "/api/v0/plugins";
+
+/// Events of a topic that a plugin is notified of
+pub struct Subscription {
+    pub topic: TopicName,
+    /// Given the value of the topic tag, whether the plugin wants the event
+    pub filter: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+}
+
+impl Subscription {
+    pub fn all(topic_name: &str) -> Subscription {
+        Subscription::filtered(topic_name, |_| true)
+    }
+
+    pub fn filtered(
+        topic_name: &str,
+        filter: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Subscription {
+        Subscription {
+            topic: TopicName::new(topic_name),
+            filter: Arc::new(filter),
+        }
+    }
+}
+
+/// An integration compiled into the mgmt-server, e.g. the Discord chat link, that reacts to
+/// events and optionally serves its own REST routes.
+///
+/// Plugins are registered with [`Plugins`] at startup, so that adding an integration doesn't
+/// need its subscriber tasks or routes wired up individually.
+#[rocket::async_trait]
+pub trait Plugin: Send + Sync + 'static {
+    /// Unique name, also used in the path the plugin's routes are mounted under
+    fn name(&self) -> &'static str;
+
+    /// Topics to be notified of through [`Plugin::on_event`]
+    fn subscriptions(&self) -> Vec<Subscription> {
+        vec![]
+    }
+
+    /// Handles an event of one of the subscribed topics. Only called on the leader replica.
+    async fn on_event(&self, _topic: &TopicName, _event: Event) {}
+
+    /// Adds the plugin's routes and any state they need, mounted under `base`
+    fn mount(self: Arc<Self>, rocket: Rocket<Build>, _base: &str) -> Rocket<Build> {
+        rocket
+    }
+}
+
+/// The plugins registered at startup
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Arc<dyn Plugin>>,
+}
+
+impl Plugins {
+    pub fn register(&mut self, plugin: Arc<dyn Plugin>) {
+        info!("Registering plugin {}", plugin.name());
+        self.plugins.push(plugin);
+    }
+
+    /// Spawns a subscriber for each subscription of each plugin, passing events on while this
+    /// replica is the leader
+    pub async fn start(&self, event_broker: Arc<EventBroker>, leadership: Leadership) {
+        for plugin in &self.plugins {
+            for subscription in plugin.subscriptions() {
+                let filter = subscription.filter;
+                let sub = event_broker
+                    .subscribe(subscription.topic.clone(), move |v| filter(v))
+                    .await;
+                let plugin = Arc::clone(plugin);
+                let topic = subscription.topic;
+                let leadership = leadership.clone();
+                tokio::spawn(async move {
+                    pin_mut!(sub);
+                    while let Some(event) = sub.next().await {
+                        if !leadership.is_leader() {
+                            continue;
+                        }
+                        plugin.on_event(&topic, event).await;
+                    }
+
+                    error!(
+                        "Plugin {} subscriber for {} is finishing, this should never happen!",
+                        plugin.name(),
+                        topic.name
+                    );
+                });
+            }
+        }
+    }
+
+    pub fn mount(&self, mut rocket: Rocket<Build>) -> Rocket<Build> {
+        for plugin in &self.plugins {
+            let base = format!("{}/{}", PLUGINS_BASE_PATH, plugin.name());
+            rocket = Arc::clone(plugin).mount(rocket, &base);
+        }
+        rocket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Utc;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    struct Recorder {
+        tx: mpsc::UnboundedSender<(String, String)>,
+    }
+
+    #[rocket::async_trait]
+    impl Plugin for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn subscriptions(&self) -> Vec<Subscription> {
+            vec![
+                Subscription::all("a"),
+                Subscription::filtered("b", |v| v == "wanted"),
+            ]
+        }
+
+        async fn on_event(&self, topic: &TopicName, event: Event) {
+            let _ = self.tx.send((topic.name.clone(), event.content));
+        }
+    }
+
+    fn event(topic_name: &str, value: &str, content: &str) -> Event {
+        Event {
+            tags: HashMap::from([(TopicName::new(topic_name), value.to_owned())]),
+            timestamp: Utc::now(),
+            content: content.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_subscribed_events_to_plugin() {
+        fctrl::util::testing::logger_init();
+
+        let broker = Arc::new(EventBroker::new(EventBroker::DEFAULT_TOPIC_CAPACITY));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut plugins = Plugins::default();
+        plugins.register(Arc::new(Recorder { tx }));
+        plugins
+            .start(Arc::clone(&broker), Leadership::always_leader())
+            .await;
+
+        broker.publish(event("b", "unwanted", "1")).await;
+        broker.publish(event("c", "", "2")).await;
+        broker.publish(event("a", "", "3")).await;
+        assert_eq!(rx.recv().await, Some(("a".to_owned(), "3".to_owned())));
+
+        broker.publish(event("b", "wanted", "4")).await;
+        assert_eq!(rx.recv().await, Some(("b".to_owned(), "4".to_owned())));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{collections::HashMap, sync::Arc};
+
+use lazy_static::lazy_static;
+
+use crate::{
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+};
+
+lazy_static! {
+    static ref PREFERENCES_CF: Cf = Cf("user_preferences".to_owned());
+}
+
+const MAX_KEY_LENGTH: usize = 64;
+/// Preferences are meant for small bits of UI state, not as general storage
+const MAX_VALUE_BYTES: usize = 16 * 1024;
+
+/// Web frontend preferences per user, e.g. layout, console filters and theme, kept in the db so
+/// that they follow the user between browsers
+pub struct Preferences {
+    db: Arc<Db>,
+}
+
+impl Preferences {
+    pub fn new(db: Arc<Db>) -> Preferences {
+        Preferences { db }
+    }
+
+    /// All preferences of the user
+    pub fn list(&self, user: &str) -> Result<HashMap<String, serde_json::Value>> {
+        let prefix = key_prefix(user);
+        self.db
+            .read_prefix(&PREFERENCES_CF, &prefix)?
+            .into_iter()
+            .map(|r| {
+                let key = r.key.strip_prefix(&prefix).unwrap_or(&r.key).to_owned();
+                Ok((key, serde_json::from_str(&r.value)?))
+            })
+            .collect()
+    }
+
+    pub fn get(&self, user: &str, key: &str) -> Result<serde_json::Value> {
+        match self.db.read(&PREFERENCES_CF, db_key(user, key))? {
+            Some(record) => Ok(serde_json::from_str(&record.value)?),
+            None => Err(Error::PreferenceNotFound),
+        }
+    }
+
+    pub fn set(&self, user: &str, key: &str, value: &serde_json::Value) -> Result<()> {
+        validate_key(key)?;
+        let value = serde_json::to_string(value)?;
+        if value.len() > MAX_VALUE_BYTES {
+            return Err(Error::BadRequest(format!(
+                "Preference value must be at most {} bytes",
+                MAX_VALUE_BYTES
+            )));
+        }
+        self.db.write(
+            &PREFERENCES_CF,
+            &Record {
+                key: db_key(user, key),
+                value,
+            },
+        )
+    }
+
+    pub fn delete(&self, user: &str, key: &str) -> Result<()> {
+        self.get(user, key)?;
+        self.db.delete(&PREFERENCES_CF, &db_key(user, key))
+    }
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(Error::BadRequest(format!(
+            "Preference key must be between 1 and {} characters",
+            MAX_KEY_LENGTH
+        )));
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(Error::BadRequest(
+            "Preference key may only contain letters, numbers, '-', '_' and '.'".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn key_prefix(user: &str) -> String {
+    format!("{}/", user)
+}
+
+fn db_key(user: &str, key: &str) -> String {
+    format!("{}{}", key_prefix(user), key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_preference_keys() {
+        assert!(validate_key("console.filters").is_ok());
+        assert!(validate_key("theme").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key("a/b").is_err());
+        assert!(validate_key(&"a".repeat(MAX_KEY_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::{collections::HashSet, sync::Arc};
+
+use fctrl::schema::InternalServerState;
+use futures::{pin_mut, StreamExt};
+use log::{error, info};
+
+use crate::{
+    clients::AgentApiClient,
+    events::{
+        broker::EventBroker, TopicName, JOIN_TOPIC_NAME, LEAVE_TOPIC_NAME, SERVERSTATE_TOPIC_NAME,
+    },
+    game_message::GameMessage,
+    ha::Leadership,
+};
+
+/// Reserved player slots, which Factorio does not support natively.
+///
+/// Once more than `capacity` players are online, the newest non-VIP player is kicked to make room,
+/// and any admins online are told in-game. The server's own `max_players` should be set above
+/// `capacity` (or to 0) to leave room for VIPs to join in the first place.
+pub struct ReservedSlots {
+    capacity: usize,
+    vips: HashSet<String>,
+    /// Online players, in order of joining
+    online: Vec<String>,
+}
+
+impl ReservedSlots {
+    pub fn new(capacity: usize, vips: HashSet<String>) -> ReservedSlots {
+        ReservedSlots {
+            capacity,
+            vips,
+            online: vec![],
+        }
+    }
+
+    /// Records a player join, returning the player to kick if this puts the server over capacity
+    fn on_join(&mut self, player: String) -> Option<String> {
+        if !self.online.contains(&player) {
+            self.online.push(player);
+        }
+        if self.online.len() <= self.capacity {
+            return None;
+        }
+
+        // newest non-VIP, which will be the joining player themselves if they are not a VIP
+        let to_kick = self
+            .online
+            .iter()
+            .rev()
+            .find(|p| !self.vips.contains(*p))
+            .cloned();
+        if let Some(to_kick) = &to_kick {
+            self.online.retain(|p| p != to_kick);
+        }
+        to_kick
+    }
+
+    fn on_leave(&mut self, player: &str) {
+        self.online.retain(|p| p != player);
+    }
+
+    fn on_server_closed(&mut self) {
+        self.online.clear();
+    }
+
+    pub async fn start(
+        mut self,
+        agent_client: Arc<AgentApiClient>,
+        event_broker: Arc<EventBroker>,
+        leadership: Leadership,
+    ) {
+        info!(
+            "Enforcing {} player capacity with {} reserved VIP(s)",
+            self.capacity,
+            self.vips.len()
+        );
+        let join_sub = event_broker
+            .subscribe(TopicName::new(JOIN_TOPIC_NAME), |_| true)
+            .await;
+        let leave_sub = event_broker
+            .subscribe(TopicName::new(LEAVE_TOPIC_NAME), |_| true)
+            .await;
+        let closed_sub = event_broker
+            .subscribe(TopicName::new(SERVERSTATE_TOPIC_NAME), |states_str| {
+                states_str.ends_with(InternalServerState::Closed.as_ref())
+            })
+            .await;
+        tokio::spawn(async move {
+            pin_mut!(join_sub);
+            pin_mut!(leave_sub);
+            pin_mut!(closed_sub);
+            loop {
+                tokio::select! {
+                    Some(event) = join_sub.next() => {
+                        let player = event.tags.get(&TopicName::new(JOIN_TOPIC_NAME)).unwrap().clone();
+                        if let Some(to_kick) = self.on_join(player) {
+                            if !leadership.is_leader() {
+                                continue;
+                            }
+                            info!("Server over capacity, kicking {} to free a reserved slot", to_kick);
+                            let command = format!("/kick {} {}", to_kick, GameMessage::ReservedSlotKick.render());
+                            if let Err(e) = agent_client.rcon_command(command).await {
+                                error!("Couldn't kick player {} via RCON: {:?}", to_kick, e);
+                                continue;
+                            }
+                            let notice = GameMessage::ReservedSlotKickNotice { player: &to_kick }.render();
+                            if let Err(e) = agent_client.rcon_whisper_admins(&self.online, notice).await {
+                                error!("Couldn't notify admins in-game of reserved slot kick: {:?}", e);
+                            }
+                        }
+                    }
+                    Some(event) = leave_sub.next() => {
+                        let player = event.tags.get(&TopicName::new(LEAVE_TOPIC_NAME)).unwrap();
+                        self.on_leave(player);
+                    }
+                    Some(_) = closed_sub.next() => {
+                        self.on_server_closed();
+                    }
+                    else => break,
+                }
+            }
+
+            error!("reserved slots subscriber task is finishing - this should never happen!");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vips(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn vip_join_when_full_kicks_newest_non_vip() {
+        let mut rs = ReservedSlots::new(2, vips(&["vip"]));
+        assert_eq!(rs.on_join("a".to_owned()), None);
+        assert_eq!(rs.on_join("b".to_owned()), None);
+        assert_eq!(rs.on_join("vip".to_owned()), Some("b".to_owned()));
+        assert_eq!(rs.online, vec!["a".to_owned(), "vip".to_owned()]);
+    }
+
+    #[test]
+    fn non_vip_join_when_full_is_kicked() {
+        let mut rs = ReservedSlots::new(2, vips(&["vip"]));
+        assert_eq!(rs.on_join("a".to_owned()), None);
+        assert_eq!(rs.on_join("vip".to_owned()), None);
+        assert_eq!(rs.on_join("c".to_owned()), Some("c".to_owned()));
+    }
+
+    #[test]
+    fn leave_frees_slot() {
+        let mut rs = ReservedSlots::new(1, vips(&[]));
+        assert_eq!(rs.on_join("a".to_owned()), None);
+        rs.on_leave("a");
+        assert_eq!(rs.on_join("b".to_owned()), None);
+    }
+
+    #[test]
+    fn all_vips_over_capacity_kicks_nobody() {
+        let mut rs = ReservedSlots::new(1, vips(&["vip1", "vip2"]));
+        assert_eq!(rs.on_join("vip1".to_owned()), None);
+        assert_eq!(rs.on_join("vip2".to_owned()), None);
+    }
+}
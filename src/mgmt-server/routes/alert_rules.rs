@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use fctrl::schema::mgmt_server_rest::{AlertRuleCreateRequest, AlertRuleObject};
+use rocket::{delete, get, post, serde::json::Json, State};
+
+use crate::{
+    alert_rules::{AlertCondition, AlertRule, AlertRules},
+    auth::AuthorizedUser,
+    error::{Error, Result},
+};
+
+#[get("/alertrules")]
+pub async fn get_alert_rules(
+    _a: AuthorizedUser,
+    alert_rules: &State<Arc<AlertRules>>,
+) -> Result<Json<Vec<AlertRuleObject>>> {
+    let rules = alert_rules
+        .list()?
+        .into_iter()
+        .map(to_alert_rule_object)
+        .collect();
+    Ok(Json(rules))
+}
+
+#[post("/alertrules", data = "<body>")]
+pub async fn create_alert_rule(
+    _a: AuthorizedUser,
+    alert_rules: &State<Arc<AlertRules>>,
+    body: Json<AlertRuleCreateRequest>,
+) -> Result<Json<AlertRuleObject>> {
+    let body = body.into_inner();
+    let condition = match body.condition.as_str() {
+        "server_crash" => AlertCondition::ServerCrash,
+        "player_join" => AlertCondition::PlayerJoin {
+            player: body.player.ok_or_else(|| {
+                Error::BadRequest("player is required for player_join".to_owned())
+            })?,
+        },
+        "evolution" => AlertCondition::Evolution {
+            threshold: body.threshold.ok_or_else(|| {
+                Error::BadRequest("threshold is required for evolution".to_owned())
+            })?,
+        },
+        "operation_failed" => AlertCondition::OperationFailed,
+        other => {
+            return Err(Error::BadRequest(format!(
+                "Unknown alert condition '{}'",
+                other
+            )))
+        }
+    };
+    let rule = alert_rules.create(condition, body.mention)?;
+    Ok(Json(to_alert_rule_object(rule)))
+}
+
+#[delete("/alertrules/<id>")]
+pub async fn delete_alert_rule(
+    _a: AuthorizedUser,
+    alert_rules: &State<Arc<AlertRules>>,
+    id: String,
+) -> Result<()> {
+    alert_rules.delete(&id)
+}
+
+fn to_alert_rule_object(rule: AlertRule) -> AlertRuleObject {
+    let (condition, player, threshold) = match rule.condition {
+        AlertCondition::ServerCrash => ("server_crash", None, None),
+        AlertCondition::PlayerJoin { player } => ("player_join", Some(player), None),
+        AlertCondition::Evolution { threshold } => ("evolution", None, Some(threshold)),
+        AlertCondition::OperationFailed => ("operation_failed", None, None),
+    };
+    AlertRuleObject {
+        id: rule.id,
+        condition: condition.to_owned(),
+        player,
+        threshold,
+        mention: rule.mention,
+    }
+}
@@ -0,0 +1,21 @@
+use std::sync::Arc;
+
+use rocket::{http::Status, post, serde::json::Json, State};
+
+use crate::{
+    alertmanager::{AlertmanagerReceiver, AlertmanagerWebhook},
+    error::Result,
+    guards::BearerTokenHeader,
+};
+
+#[post("/alerts/incoming", data = "<body>")]
+pub async fn incoming(
+    token: Option<BearerTokenHeader>,
+    receiver: &State<Arc<AlertmanagerReceiver>>,
+    body: Json<AlertmanagerWebhook>,
+) -> Result<Status> {
+    receiver
+        .receive(token.as_ref().map(|t| t.token.as_str()), body.into_inner())
+        .await?;
+    Ok(Status::Ok)
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use fctrl::schema::mgmt_server_rest::{ChatCommandObject, ChatCommandPutRequest};
+use rocket::{delete, get, put, serde::json::Json, State};
+
+use crate::{
+    auth::AuthorizedUser,
+    chat_commands::{ChatCommand, ChatCommands},
+    error::Result,
+};
+
+#[get("/chatcommands")]
+pub async fn get_chat_commands(
+    _a: AuthorizedUser,
+    chat_commands: &State<Arc<ChatCommands>>,
+) -> Result<Json<Vec<ChatCommandObject>>> {
+    let commands = chat_commands
+        .list()?
+        .into_iter()
+        .map(|c| ChatCommandObject {
+            name: c.name,
+            rcon_command: c.rcon_command,
+            response: c.response,
+        })
+        .collect();
+    Ok(Json(commands))
+}
+
+#[put("/chatcommands/<name>", data = "<body>")]
+pub async fn put_chat_command(
+    _a: AuthorizedUser,
+    chat_commands: &State<Arc<ChatCommands>>,
+    name: String,
+    body: Json<ChatCommandPutRequest>,
+) -> Result<()> {
+    let body = body.into_inner();
+    chat_commands.set(&ChatCommand {
+        name,
+        rcon_command: body.rcon_command,
+        response: body.response.unwrap_or_default(),
+    })
+}
+
+#[delete("/chatcommands/<name>")]
+pub async fn delete_chat_command(
+    _a: AuthorizedUser,
+    chat_commands: &State<Arc<ChatCommands>>,
+    name: String,
+) -> Result<()> {
+    chat_commands.delete(&name)
+}
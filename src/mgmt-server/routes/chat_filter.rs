@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use fctrl::schema::mgmt_server_rest::{ChatFilterRuleCreateRequest, ChatFilterRuleObject};
+use rocket::{delete, get, post, serde::json::Json, State};
+
+use crate::{
+    auth::AuthorizedUser,
+    chat_filter::{ChatFilter, ChatFilterRule},
+    error::{Error, Result},
+};
+
+#[get("/chatfilter/rules")]
+pub async fn get_chat_filter_rules(
+    _a: AuthorizedUser,
+    chat_filter: &State<Arc<ChatFilter>>,
+) -> Result<Json<Vec<ChatFilterRuleObject>>> {
+    let rules = chat_filter
+        .list()
+        .into_iter()
+        .map(to_chat_filter_rule_object)
+        .collect();
+    Ok(Json(rules))
+}
+
+#[post("/chatfilter/rules", data = "<body>")]
+pub async fn create_chat_filter_rule(
+    _a: AuthorizedUser,
+    chat_filter: &State<Arc<ChatFilter>>,
+    body: Json<ChatFilterRuleCreateRequest>,
+) -> Result<Json<ChatFilterRuleObject>> {
+    let body = body.into_inner();
+    let mute_after = body
+        .mute_after
+        .map(|n| {
+            u32::try_from(n)
+                .map_err(|_| Error::BadRequest("mute_after must not be negative".to_owned()))
+        })
+        .transpose()?;
+    let rule = chat_filter.create(
+        body.pattern,
+        body.warn.unwrap_or(false),
+        body.hide_from_discord.unwrap_or(false),
+        mute_after,
+    )?;
+    Ok(Json(to_chat_filter_rule_object(rule)))
+}
+
+#[delete("/chatfilter/rules/<id>")]
+pub async fn delete_chat_filter_rule(
+    _a: AuthorizedUser,
+    chat_filter: &State<Arc<ChatFilter>>,
+    id: String,
+) -> Result<()> {
+    chat_filter.delete(&id)
+}
+
+fn to_chat_filter_rule_object(rule: ChatFilterRule) -> ChatFilterRuleObject {
+    ChatFilterRuleObject {
+        id: rule.id,
+        pattern: rule.pattern,
+        warn: rule.warn,
+        hide_from_discord: rule.hide_from_discord,
+        mute_after: rule.mute_after.map(|n| n as i32),
+    }
+}
@@ -1,11 +1,11 @@
 use std::sync::Arc;
 
-use crate::{clients::AgentApiClient, error::{Error, Result}, link_download::{LinkDownloadManager, LinkDownloadTarget}};
+use crate::{clients::AgentApiClient, error::{Error, Result}, guards::{IfRangeHeader, RangeHeader}, link_download::{LinkDownloadManager, LinkDownloadTarget}};
 
-use fctrl::schema::{AgentOutMessage, AgentResponseWithId};
-use futures::{stream, Stream};
-use log::{error, info};
-use rocket::{get, response::stream::ByteStream, State};
+use fctrl::{schema::{AgentOutMessage, AgentResponseWithId}, util::range::ByteRange};
+use futures::{future, stream, Stream};
+use log::{error, info, warn};
+use rocket::{get, response::{stream::ByteStream, Redirect}, Either, State};
 use tokio_stream::StreamExt;
 
 use super::DownloadResponder;
@@ -15,16 +15,57 @@ pub async fn download(
     agent_client: &State<Arc<AgentApiClient>>,
     link_download_manager: &State<Arc<LinkDownloadManager>>,
     link_id: String,
-) -> Result<DownloadResponder<ByteStream![Vec<u8>]>> {
+    range_header: Option<RangeHeader>,
+    if_range_header: Option<IfRangeHeader>,
+) -> Result<Either<DownloadResponder<ByteStream![Vec<u8>]>, Redirect>> {
     match link_download_manager.get_link(link_id).await {
         Some(target) => {
-            let source_stream;
+            let source_stream: Box<dyn Stream<Item = Vec<u8>> + Unpin + Send>;
             let download_filename;
+            let mut length_and_range = None;
+            let mut checksum = None;
             match target {
                 LinkDownloadTarget::Savefile { id } => {
+                    // skip relaying through the mgmt-server if the agent can serve it directly
+                    if let Some(url) = link_download_manager.direct_savefile_url(&id) {
+                        return Ok(Either::Right(Redirect::temporary(url)));
+                    }
                     download_filename = format!("{}.zip", &id);
-                    source_stream = download_save(agent_client, id).await?;
-
+                    // the size is needed to support resuming, but the save can still be downloaded without it
+                    let length = match agent_client.save_metadata_get(id.clone()).await {
+                        Ok(metadata) => Some(metadata.size_bytes),
+                        Err(e) => {
+                            warn!("Couldn't get size of savefile {} for download, resuming will be unavailable: {:?}", id, e);
+                            None
+                        }
+                    };
+                    // the checksum doubles as the ETag, so that a resume can tell if the save changed
+                    checksum = match length {
+                        Some(length) => match agent_client.save_checksum_get(id.clone(), length).await {
+                            Ok(sha256) => Some(sha256),
+                            Err(e) => {
+                                warn!("Couldn't get checksum of savefile {} for download: {:?}", id, e);
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                    // a range can only be resumed from an unchanged save, otherwise the whole save is sent
+                    let if_range_matches = match (&if_range_header, &checksum) {
+                        (None, _) => true,
+                        (Some(h), Some(sha256)) => h.matches(sha256),
+                        (Some(_), None) => false,
+                    };
+                    let range = match (length, range_header) {
+                        (Some(length), Some(h)) if if_range_matches => fctrl::util::range::parse(&h.value, length).map_err(|_| Error::RangeNotSatisfiable)?,
+                        _ => None,
+                    };
+                    let stream = download_save(agent_client, id).await?;
+                    source_stream = match range {
+                        Some(range) => Box::new(Box::pin(slice_stream(stream, range))),
+                        None => stream,
+                    };
+                    length_and_range = length.map(|length| (length, range));
                 }
                 LinkDownloadTarget::ModSettingsDat => {
                     download_filename = "mod-settings.dat".to_owned();
@@ -32,7 +73,14 @@ pub async fn download(
                 }
             }
 
-            Ok(DownloadResponder::new(ByteStream::from(source_stream), download_filename))
+            let mut responder = DownloadResponder::new(ByteStream::from(source_stream), download_filename);
+            if let Some((length, range)) = length_and_range {
+                responder = responder.with_range(length, range);
+            }
+            if let Some(sha256) = checksum {
+                responder = responder.with_checksum(sha256);
+            }
+            Ok(Either::Left(responder))
         }
         None => Err(Error::InvalidLink)
     }
@@ -78,3 +126,47 @@ async fn download_mod_settings_dat(
     let bytes = agent_client.mod_settings_get().await?;
     Ok(Box::new(Box::pin(stream::once(async { bytes.bytes }))))
 }
+
+/// Cuts a stream of chunks down to the bytes within the range. The agent always sends the whole
+/// save, so the bytes before the range are still transferred from the agent, but not to the client.
+fn slice_stream(
+    s: impl Stream<Item = Vec<u8>> + Unpin + Send,
+    range: ByteRange,
+) -> impl Stream<Item = Vec<u8>> + Send {
+    futures::StreamExt::scan(s, 0, move |offset, chunk: Vec<u8>| {
+        let chunk_start = *offset;
+        *offset += chunk.len() as u64;
+        // stop once past the range, rather than waiting for the rest of the save
+        let sliced = match chunk_start > range.end {
+            true => None,
+            false => Some(slice_chunk(chunk, chunk_start, range)),
+        };
+        future::ready(sliced)
+    })
+    .filter_map(|chunk| chunk)
+}
+
+/// Part of the chunk, starting at the given offset within the download, that lies within the range
+fn slice_chunk(chunk: Vec<u8>, chunk_start: u64, range: ByteRange) -> Option<Vec<u8>> {
+    let chunk_end = chunk_start + chunk.len() as u64;
+    if chunk_end <= range.start || chunk_start > range.end {
+        return None;
+    }
+    let from = range.start.saturating_sub(chunk_start) as usize;
+    let to = (range.end + 1 - chunk_start).min(chunk.len() as u64) as usize;
+    Some(chunk[from..to].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slices_chunks_to_range() {
+        let range = ByteRange { start: 5, end: 12 };
+        assert_eq!(slice_chunk(vec![0; 5], 0, range), None);
+        assert_eq!(slice_chunk((0..10).collect(), 0, range), Some(vec![5, 6, 7, 8, 9]));
+        assert_eq!(slice_chunk((10..20).collect(), 10, range), Some(vec![10, 11, 12]));
+        assert_eq!(slice_chunk(vec![0; 5], 20, range), None);
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use fctrl::schema::mgmt_server_rest::{FeatureFlagObject, FeatureFlagPutRequest};
+use rocket::{get, put, serde::json::Json, State};
+
+use crate::{auth::AuthorizedUser, error::Result, feature_flags::FeatureFlags};
+
+#[get("/featureflags")]
+pub async fn get_feature_flags(
+    _a: AuthorizedUser,
+    feature_flags: &State<Arc<FeatureFlags>>,
+) -> Json<Vec<FeatureFlagObject>> {
+    let flags = feature_flags
+        .list()
+        .into_iter()
+        .map(|(flag, enabled)| FeatureFlagObject {
+            name: flag.as_ref().to_owned(),
+            enabled,
+            description: flag.description().to_owned(),
+        })
+        .collect();
+    Json(flags)
+}
+
+#[put("/featureflags/<name>", data = "<body>")]
+pub async fn put_feature_flag(
+    _a: AuthorizedUser,
+    feature_flags: &State<Arc<FeatureFlags>>,
+    name: String,
+    body: Json<FeatureFlagPutRequest>,
+) -> Result<()> {
+    feature_flags.set(&name, body.into_inner().enabled)
+}
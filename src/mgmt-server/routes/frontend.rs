@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use rocket::{
+    fs::NamedFile,
+    get,
+    http::ContentType,
+    response::{self, Responder},
+    Request,
+};
+
+use crate::{get_dist_path, guards::AcceptEncodingHeader};
+
+/// Files with a content hash in their name never change, so can be cached indefinitely
+const CACHE_CONTROL_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// Everything else is revalidated on each load, most importantly index.html, which references
+/// the hashed files of the current build
+const CACHE_CONTROL_NO_CACHE: &str = "no-cache";
+
+/// Content codings that files may be pre-compressed with alongside the original, in order of
+/// preference, with the extension of the compressed file
+const PRECOMPRESSED_ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// Minimum length of the content hash added to file names by the Angular build
+const MIN_HASH_LEN: usize = 16;
+
+/// Serves the web frontend bundle. Ranked after the API routes, like a `FileServer` would be.
+#[get("/<path..>", rank = 10)]
+pub async fn get(path: PathBuf, accept_encoding: AcceptEncodingHeader) -> Option<StaticFile> {
+    let mut file_path = get_dist_path().join(path);
+    if file_path.is_dir() {
+        file_path.push("index.html");
+    }
+    StaticFile::open(&file_path, &accept_encoding).await
+}
+
+/// A file of the frontend bundle, with caching headers suited to it, served pre-compressed if
+/// a compressed copy exists that the client accepts
+pub struct StaticFile {
+    file: NamedFile,
+    content_type: Option<ContentType>,
+    content_encoding: Option<&'static str>,
+    cache_control: &'static str,
+}
+
+impl StaticFile {
+    pub async fn open(path: &Path, accept_encoding: &AcceptEncodingHeader) -> Option<StaticFile> {
+        let content_type = path
+            .extension()
+            .and_then(|ext| ContentType::from_extension(&ext.to_string_lossy()));
+        let cache_control = if is_hashed(path) {
+            CACHE_CONTROL_IMMUTABLE
+        } else {
+            CACHE_CONTROL_NO_CACHE
+        };
+
+        for (encoding, extension) in PRECOMPRESSED_ENCODINGS {
+            if !accept_encoding.accepts(encoding) {
+                continue;
+            }
+            let mut compressed_path = path.as_os_str().to_owned();
+            compressed_path.push(".");
+            compressed_path.push(extension);
+            if let Ok(file) = NamedFile::open(compressed_path).await {
+                return Some(StaticFile {
+                    file,
+                    content_type,
+                    content_encoding: Some(encoding),
+                    cache_control,
+                });
+            }
+        }
+
+        let file = NamedFile::open(path).await.ok()?;
+        Some(StaticFile {
+            file,
+            content_type,
+            content_encoding: None,
+            cache_control,
+        })
+    }
+}
+
+impl<'r> Responder<'r, 'static> for StaticFile {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.file.respond_to(request)?;
+        // the content type guessed from a compressed file's name is that of the compression
+        match self.content_type {
+            Some(content_type) => {
+                response.set_header(content_type);
+            }
+            None => {
+                response.remove_header("Content-Type");
+            }
+        }
+        if let Some(encoding) = self.content_encoding {
+            response.set_raw_header("Content-Encoding", encoding);
+        }
+        response.set_raw_header("Vary", "Accept-Encoding");
+        response.set_raw_header("Cache-Control", self.cache_control);
+        Ok(response)
+    }
+}
+
+/// Whether the file name contains a content hash, e.g. main.0f3c2a7b9d8e1f45.js
+fn is_hashed(path: &Path) -> bool {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => return false,
+    };
+    let parts = file_name.split('.').collect::<Vec<_>>();
+    parts.len() >= 3
+        && parts[1..parts.len() - 1]
+            .iter()
+            .any(|p| p.len() >= MIN_HASH_LEN && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_hashed_file_names() {
+        assert!(is_hashed(Path::new("main.0f3c2a7b9d8e1f45.js")));
+        assert!(is_hashed(Path::new("dist/web/592.c9d1b4a0e8f7a6b3.js")));
+        assert!(is_hashed(Path::new("styles.e5a1b3c2d4f60789.css")));
+        assert!(!is_hashed(Path::new("index.html")));
+        assert!(!is_hashed(Path::new("favicon.ico")));
+        assert!(!is_hashed(Path::new("assets/factorio-icon.png")));
+        assert!(!is_hashed(Path::new("0f3c2a7b9d8e1f45.js")));
+    }
+}
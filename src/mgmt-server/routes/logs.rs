@@ -1,25 +1,37 @@
 use std::{sync::Arc, time::Duration};
 
+use chrono::{DateTime, Utc};
 use fctrl::schema::{
-    mgmt_server_rest::{LogStreamPreviousMarker, LogsPaginationObject},
-    OperationId,
+    mgmt_server_rest::{LogBackfillResult, LogStreamPreviousMarker, LogsPaginationObject},
+    AgentStreamingMessage, AgentStreamingMessageInner, OperationId,
 };
-use rocket::{get, serde::json::Json, State};
+use regex::Regex;
+use rocket::{data::ToByteUnit, get, post, serde::json::Json, Data, State};
 use uuid::Uuid;
 
 use crate::{
+    auth::{AuthorizedUser, ViewerUser},
     db::{Cf, Db, RangeDirection},
     error::{Error, Result},
     events::{broker::EventBroker, TopicName, STDOUT_TOPIC_NAME},
     guards::HostHeader,
+    log_backfill,
     ws::WebSocketServer,
 };
 
 use super::WsStreamingResponderWithPreviousMarker;
 
+/// Largest historical log accepted for backfill
+const MAX_BACKFILL_SIZE_MIB: u64 = 256;
+
+/// Most logs examined by a single search request, beyond which the search returns early with a
+/// position to continue from
+const SEARCH_SCAN_LIMIT: u32 = 100_000;
+
 #[get("/logs/<category>?<count>&<direction>&<from>")]
 pub async fn get<'a>(
     // host: HostHeader<'a>,
+    _a: ViewerUser,
     db: &State<Arc<Db>>,
     category: String,
     count: u32,
@@ -54,6 +66,118 @@ pub async fn get<'a>(
     Ok(Json(LogsPaginationObject { next, logs }))
 }
 
+#[get("/logs/<category>/search?<query>&<regex>&<since>&<until>&<count>&<direction>&<from>")]
+pub async fn search(
+    _a: ViewerUser,
+    db: &State<Arc<Db>>,
+    category: String,
+    query: String,
+    regex: Option<bool>,
+    since: Option<String>,
+    until: Option<String>,
+    count: u32,
+    direction: String,
+    from: Option<String>,
+) -> Result<Json<LogsPaginationObject>> {
+    let cf = Cf(category);
+
+    let range_direction = match direction.to_lowercase().as_ref() {
+        "forward" => Ok(RangeDirection::Forward),
+        "backward" => Ok(RangeDirection::Backward),
+        s => Err(Error::BadRequest(format!(
+            "Invalid direction '{}', expected Forward or Backward",
+            s
+        ))),
+    }?;
+    let matcher = if regex.unwrap_or(false) {
+        let re = Regex::new(&query)
+            .map_err(|e| Error::BadRequest(format!("Invalid regex '{}': {}", query, e)))?;
+        LogMatcher::Regex(re)
+    } else {
+        LogMatcher::Substring(query)
+    };
+
+    // logs are keyed by timestamp, so the time window is a key range
+    let since = since.map(|s| to_log_key(&s)).transpose()?;
+    let until = until.map(|s| to_log_key(&s)).transpose()?;
+    let (start, end) = match range_direction {
+        RangeDirection::Forward => (since, until),
+        RangeDirection::Backward => (until, since),
+    };
+    // matching parses and scans every record up to the scan limit, which would stall the runtime
+    let db = Arc::clone(db);
+    let ret = tokio::task::spawn_blocking(move || {
+        db.search(
+            &cf,
+            from.or(start),
+            end,
+            range_direction,
+            count,
+            SEARCH_SCAN_LIMIT,
+            |r| matcher.is_match(&r.value),
+        )
+    })
+    .await
+    .map_err(|e| Error::Db(format!("Log search task failed: {:?}", e)))??;
+
+    let next = ret.continue_from;
+    let logs = ret.records.into_iter().map(|r| r.value).collect();
+
+    Ok(Json(LogsPaginationObject { next, logs }))
+}
+
+enum LogMatcher {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl LogMatcher {
+    /// Matches against the logged line, rather than the stored message it is wrapped in
+    fn is_match(&self, value: &str) -> bool {
+        let line = match serde_json::from_str::<AgentStreamingMessage>(value) {
+            Ok(AgentStreamingMessage {
+                content: AgentStreamingMessageInner::ServerStdout(line),
+                ..
+            }) => line,
+            _ => value.to_owned(),
+        };
+        match self {
+            LogMatcher::Substring(s) => line.contains(s.as_str()),
+            LogMatcher::Regex(re) => re.is_match(&line),
+        }
+    }
+}
+
+fn to_log_key(timestamp: &str) -> Result<String> {
+    let timestamp = DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|e| Error::BadRequest(format!("Invalid timestamp '{}': {}", timestamp, e)))?;
+    Ok(timestamp.with_timezone(&Utc).to_rfc3339())
+}
+
+#[post("/logs/backfill", data = "<body>")]
+pub async fn backfill(
+    _a: AuthorizedUser,
+    db: &State<Arc<Db>>,
+    body: Data<'_>,
+) -> Result<Json<LogBackfillResult>> {
+    let log = body.open(MAX_BACKFILL_SIZE_MIB.mebibytes()).into_string().await?;
+    if !log.is_complete() {
+        return Err(Error::BadRequest(format!(
+            "Log file exceeds the {} MiB limit",
+            MAX_BACKFILL_SIZE_MIB
+        )));
+    }
+    let summary = log_backfill::backfill(db, &log.into_inner())?;
+    Ok(Json(LogBackfillResult {
+        lines: summary.lines as i64,
+        ingested: summary
+            .ingested
+            .into_iter()
+            .map(|(category, count)| (category, count as i64))
+            .collect(),
+    }))
+}
+
 #[get("/logs/<category>/stream")]
 pub async fn stream<'a>(
     host: HostHeader<'a>,
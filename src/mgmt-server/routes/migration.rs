@@ -0,0 +1,49 @@
+use std::{sync::Arc, time::Duration};
+
+use rocket::{get, post, serde::json::Json, State};
+
+use crate::{
+    auth::AuthorizedUser,
+    error::{Error, Result},
+    guards::HostHeader,
+    migration::{Migration, MigrationManifest},
+    ws::WebSocketServer,
+};
+
+use super::WsStreamingResponder;
+
+#[get("/migration/manifest")]
+pub async fn get_migration_manifest(
+    host: HostHeader<'_>,
+    _a: AuthorizedUser,
+    migration: &State<Arc<Migration>>,
+    ws: &State<Arc<WebSocketServer>>,
+) -> Result<Json<MigrationManifest>> {
+    // the target deployment downloads from the same address the manifest was requested on
+    let scheme = if ws.use_wss { "https" } else { "http" };
+    let base_url = url::Url::parse(&format!("{}://{}/", scheme, host.host))
+        .map_err(|e| Error::BadRequest(format!("Invalid Host header: {:?}", e)))?;
+    let manifest = migration.export(&base_url).await?;
+    Ok(Json(manifest))
+}
+
+#[post("/migration/import", data = "<body>")]
+pub async fn import_migration_manifest(
+    host: HostHeader<'_>,
+    _a: AuthorizedUser,
+    migration: &State<Arc<Migration>>,
+    ws: &State<Arc<WebSocketServer>>,
+    body: Json<MigrationManifest>,
+) -> Result<WsStreamingResponder> {
+    let (id, sub) = Arc::clone(migration).import(body.into_inner()).await?;
+
+    let resp = WsStreamingResponder::new(Arc::clone(&ws), host, id);
+
+    let ws = Arc::clone(&ws);
+    let path = resp.path.clone();
+    tokio::spawn(async move {
+        ws.stream_at(path, sub, Duration::from_secs(300)).await;
+    });
+
+    Ok(resp)
+}
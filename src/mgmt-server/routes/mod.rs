@@ -1,6 +1,9 @@
 use std::{io::Cursor, sync::Arc};
 
-use fctrl::schema::{mgmt_server_rest::LogStreamPreviousMarker, OperationId};
+use fctrl::{
+    schema::{mgmt_server_rest::LogStreamPreviousMarker, OperationId},
+    util::range::ByteRange,
+};
 use log::error;
 use rocket::{
     http::{ContentType, Header, Status},
@@ -9,14 +12,27 @@ use rocket::{
 
 use crate::{guards::HostHeader, ws::WebSocketServer};
 
+pub mod alert_rules;
+pub mod alerts;
 pub mod auth;
 pub mod buildinfo;
+pub mod chat_commands;
+pub mod chat_filter;
 pub mod download;
+pub mod feature_flags;
+pub mod frontend;
 pub mod logs;
 pub mod metrics;
+pub mod migration;
+pub mod operation_webhooks;
+pub mod operations;
 pub mod options;
+pub mod players;
+pub mod preferences;
 pub mod proxy;
+pub mod schedules;
 pub mod server;
+pub mod settings_profiles;
 pub mod system;
 
 pub struct LinkDownloadResponder {
@@ -43,10 +59,15 @@ impl<'r> Responder<'r, 'static> for LinkDownloadResponder {
     }
 }
 
-#[derive(Responder)]
 pub struct DownloadResponder<T> {
     inner: T,
     content_disposition: ContentDisposition,
+    /// Total size of the download, if known up front
+    length: Option<u64>,
+    /// Part of the download being sent, if resuming
+    range: Option<ByteRange>,
+    /// SHA-256 of the whole download, if known up front
+    sha256: Option<String>,
 }
 
 impl<T> DownloadResponder<T> {
@@ -54,7 +75,51 @@ impl<T> DownloadResponder<T> {
         DownloadResponder {
             inner: content,
             content_disposition: ContentDisposition(download_filename),
+            length: None,
+            range: None,
+            sha256: None,
+        }
+    }
+
+    /// Advertises range support for a download of known length, with the content being only the
+    /// given range of it if one was requested
+    pub fn with_range(mut self, length: u64, range: Option<ByteRange>) -> DownloadResponder<T> {
+        self.length = Some(length);
+        self.range = range;
+        self
+    }
+
+    /// Sends the checksum of the whole download, which also serves as its ETag
+    pub fn with_checksum(mut self, sha256: String) -> DownloadResponder<T> {
+        self.sha256 = Some(sha256);
+        self
+    }
+}
+
+impl<'r, 'o: 'r, T: Responder<'r, 'o>> Responder<'r, 'o> for DownloadResponder<T> {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        let mut builder = Response::build_from(self.inner.respond_to(request)?);
+        builder.header(self.content_disposition);
+        if let Some(length) = self.length {
+            builder.header(Header::new("Accept-Ranges", "bytes"));
+            match self.range {
+                Some(range) => {
+                    builder
+                        .status(Status::PartialContent)
+                        .header(Header::new("Content-Range", range.content_range(length)))
+                        .header(Header::new("Content-Length", range.size().to_string()));
+                }
+                None => {
+                    builder.header(Header::new("Content-Length", length.to_string()));
+                }
+            }
+        }
+        if let Some(sha256) = self.sha256 {
+            builder
+                .header(Header::new("ETag", format!("\"{}\"", sha256)))
+                .header(Header::new("X-Content-Sha256", sha256));
         }
+        builder.ok()
     }
 }
 
@@ -85,9 +150,10 @@ impl WsStreamingResponder {
         // If reverse proxy through Traefik is enabled, we advertise the same port as regular HTTPS traffic (443),
         // and let routing rules forward to the right port inside the container network.
         // Otherwise, advertise the separate port as normal
+        let hostname = ws.advertised_host.as_deref().unwrap_or(host.hostname);
         let full_uri = match ws.use_wss {
-            true => format!("wss://{}{}", host.hostname, path),
-            false => format!("ws://{}:{}{}", host.hostname, ws.port, path),
+            true => format!("wss://{}{}", hostname, path),
+            false => format!("ws://{}:{}{}", hostname, ws.port, path),
         };
         WsStreamingResponder { path, full_uri }
     }
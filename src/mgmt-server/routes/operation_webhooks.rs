@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use fctrl::schema::mgmt_server_rest::{OperationWebhookCreateRequest, OperationWebhookObject};
+use rocket::{delete, get, post, serde::json::Json, State};
+
+use crate::{
+    auth::AuthorizedUser,
+    error::{Error, Result},
+    operation_webhooks::{OperationWebhook, OperationWebhooks, WebhookRequestType},
+};
+
+#[get("/webhooks/operations")]
+pub async fn get_operation_webhooks(
+    _a: AuthorizedUser,
+    operation_webhooks: &State<Arc<OperationWebhooks>>,
+) -> Result<Json<Vec<OperationWebhookObject>>> {
+    let webhooks = operation_webhooks
+        .list()?
+        .into_iter()
+        .map(to_operation_webhook_object)
+        .collect();
+    Ok(Json(webhooks))
+}
+
+#[post("/webhooks/operations", data = "<body>")]
+pub async fn create_operation_webhook(
+    _a: AuthorizedUser,
+    operation_webhooks: &State<Arc<OperationWebhooks>>,
+    body: Json<OperationWebhookCreateRequest>,
+) -> Result<Json<OperationWebhookObject>> {
+    let body = body.into_inner();
+    let request_types = body
+        .request_types
+        .iter()
+        .map(|s| {
+            WebhookRequestType::parse(s)
+                .ok_or_else(|| Error::BadRequest(format!("Unsupported request type '{}'", s)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let webhook = operation_webhooks.create(body.url, request_types)?;
+    Ok(Json(to_operation_webhook_object(webhook)))
+}
+
+#[delete("/webhooks/operations/<id>")]
+pub async fn delete_operation_webhook(
+    _a: AuthorizedUser,
+    operation_webhooks: &State<Arc<OperationWebhooks>>,
+    id: String,
+) -> Result<()> {
+    operation_webhooks.delete(&id)
+}
+
+fn to_operation_webhook_object(webhook: OperationWebhook) -> OperationWebhookObject {
+    OperationWebhookObject {
+        id: webhook.id,
+        url: webhook.url,
+        request_types: webhook
+            .request_types
+            .into_iter()
+            .map(|t| format!("{:?}", t))
+            .collect(),
+    }
+}
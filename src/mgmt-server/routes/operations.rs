@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use fctrl::schema::{mgmt_server_rest::OperationEvent, AgentResponseWithId};
+use rocket::{get, serde::json::Json, State};
+
+use crate::{
+    auth::ViewerUser,
+    db::Db,
+    error::Result,
+    operations::{operation_key_prefix, OPERATION_HISTORY_CF},
+};
+
+#[get("/operations/<id>/events")]
+pub async fn events(
+    _a: ViewerUser,
+    db: &State<Arc<Db>>,
+    id: String,
+) -> Result<Json<Vec<OperationEvent>>> {
+    let records = db.read_prefix(&OPERATION_HISTORY_CF, &operation_key_prefix(&id))?;
+    let mut events = vec![];
+    for record in records {
+        let response = serde_json::from_str::<AgentResponseWithId>(&record.value)?;
+        events.push(OperationEvent {
+            timestamp: response.timestamp.to_rfc3339(),
+            status: format!("{:?}", response.status),
+            message: serde_json::to_string(&response.content)?,
+        });
+    }
+    Ok(Json(events))
+}
@@ -0,0 +1,147 @@
+use std::{str::FromStr, sync::Arc};
+
+use fctrl::schema::mgmt_server_rest::{
+    PlayerDetailObject, PlayerMessageRequest, PlayerNoteCreateRequest, PlayerNoteObject,
+    PlayerNoteUpdateRequest, PlayerSessionObject,
+};
+use rocket::{delete, get, post, put, serde::json::Json, State};
+
+use crate::{
+    auth::{OperatorUser, ViewerUser},
+    clients::AgentApiClient,
+    connection_quality::{PlayerSession, PlayerSessionTracker},
+    error::{Error, Result},
+    game_message::GameMessage,
+    player_notes::{PlayerNote, PlayerNoteKind, PlayerNotes},
+};
+
+#[get("/players")]
+pub async fn get_players(
+    _a: ViewerUser,
+    player_sessions: &State<PlayerSessionTracker>,
+) -> Json<Vec<PlayerSessionObject>> {
+    let sessions = player_sessions
+        .sessions()
+        .await
+        .into_iter()
+        .map(to_player_session_object)
+        .collect();
+    Json(sessions)
+}
+
+#[get("/players/<player_name>")]
+pub async fn get_player(
+    _a: ViewerUser,
+    player_sessions: &State<PlayerSessionTracker>,
+    player_notes: &State<Arc<PlayerNotes>>,
+    player_name: String,
+) -> Result<Json<PlayerDetailObject>> {
+    let session = player_sessions
+        .sessions()
+        .await
+        .into_iter()
+        .find(|s| s.name == player_name)
+        .map(|s| Box::new(to_player_session_object(s)));
+    let notes = player_notes
+        .list(&player_name)?
+        .into_iter()
+        .map(to_player_note_object)
+        .collect();
+    Ok(Json(PlayerDetailObject {
+        name: player_name,
+        session,
+        notes,
+    }))
+}
+
+#[get("/players/<player_name>/notes")]
+pub async fn get_player_notes(
+    _a: OperatorUser,
+    player_notes: &State<Arc<PlayerNotes>>,
+    player_name: String,
+) -> Result<Json<Vec<PlayerNoteObject>>> {
+    let notes = player_notes
+        .list(&player_name)?
+        .into_iter()
+        .map(to_player_note_object)
+        .collect();
+    Ok(Json(notes))
+}
+
+#[post("/players/<player_name>/notes", data = "<body>")]
+pub async fn create_player_note(
+    a: OperatorUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    player_notes: &State<Arc<PlayerNotes>>,
+    player_name: String,
+    body: Json<PlayerNoteCreateRequest>,
+) -> Result<Json<PlayerNoteObject>> {
+    let body = body.into_inner();
+    let kind = PlayerNoteKind::from_str(&body.kind)
+        .map_err(|_| Error::BadRequest(format!("Unknown note kind '{}'", body.kind)))?;
+    let note = player_notes.create(player_name.clone(), kind, body.text, a.0.sub)?;
+
+    if kind == PlayerNoteKind::Warning && body.whisper.unwrap_or(false) {
+        agent_client
+            .rcon_whisper(
+                vec![player_name],
+                GameMessage::ModerationWarning { text: &note.text }.render(),
+            )
+            .await?;
+    }
+
+    Ok(Json(to_player_note_object(note)))
+}
+
+#[put("/players/<player_name>/notes/<id>", data = "<body>")]
+pub async fn update_player_note(
+    _a: OperatorUser,
+    player_notes: &State<Arc<PlayerNotes>>,
+    player_name: String,
+    id: String,
+    body: Json<PlayerNoteUpdateRequest>,
+) -> Result<Json<PlayerNoteObject>> {
+    let note = player_notes.update(&player_name, &id, body.into_inner().text)?;
+    Ok(Json(to_player_note_object(note)))
+}
+
+#[delete("/players/<player_name>/notes/<id>")]
+pub async fn delete_player_note(
+    _a: OperatorUser,
+    player_notes: &State<Arc<PlayerNotes>>,
+    player_name: String,
+    id: String,
+) -> Result<()> {
+    player_notes.delete(&player_name, &id)
+}
+
+#[post("/players/<player_name>/message", data = "<body>")]
+pub async fn message_player(
+    _a: OperatorUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    player_name: String,
+    body: Json<PlayerMessageRequest>,
+) -> Result<()> {
+    let message = body.into_inner().message;
+    agent_client.rcon_whisper(vec![player_name], message).await
+}
+
+fn to_player_session_object(s: PlayerSession) -> PlayerSessionObject {
+    PlayerSessionObject {
+        name: s.name,
+        joined_at: s.joined_at.to_rfc3339(),
+        connection_quality: s.connection_quality.as_ref().to_owned(),
+        connection_issues: s.connection_issues as i32,
+    }
+}
+
+fn to_player_note_object(n: PlayerNote) -> PlayerNoteObject {
+    PlayerNoteObject {
+        id: n.id,
+        kind: n.kind.as_ref().to_owned(),
+        text: n.text,
+        author: n.author,
+        created_at: n.created_at.to_rfc3339(),
+        updated_at: n.updated_at.map(|dt| dt.to_rfc3339()),
+    }
+}
@@ -0,0 +1,41 @@
+use std::{collections::HashMap, sync::Arc};
+
+use rocket::{delete, get, put, serde::json::Json, State};
+
+use crate::{auth::ViewerUser, error::Result, preferences::Preferences};
+
+#[get("/preferences")]
+pub async fn get_preferences(
+    a: ViewerUser,
+    preferences: &State<Arc<Preferences>>,
+) -> Result<Json<HashMap<String, serde_json::Value>>> {
+    Ok(Json(preferences.list(&a.0.sub)?))
+}
+
+#[get("/preferences/<key>")]
+pub async fn get_preference(
+    a: ViewerUser,
+    preferences: &State<Arc<Preferences>>,
+    key: String,
+) -> Result<Json<serde_json::Value>> {
+    Ok(Json(preferences.get(&a.0.sub, &key)?))
+}
+
+#[put("/preferences/<key>", data = "<body>")]
+pub async fn put_preference(
+    a: ViewerUser,
+    preferences: &State<Arc<Preferences>>,
+    key: String,
+    body: Json<serde_json::Value>,
+) -> Result<()> {
+    preferences.set(&a.0.sub, &key, &body.into_inner())
+}
+
+#[delete("/preferences/<key>")]
+pub async fn delete_preference(
+    a: ViewerUser,
+    preferences: &State<Arc<Preferences>>,
+    key: String,
+) -> Result<()> {
+    preferences.delete(&a.0.sub, &key)
+}
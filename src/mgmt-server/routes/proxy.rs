@@ -1,9 +1,12 @@
 //! Routes to proxy calls to Factorio Mod Portal API
 //! Necessary as mods.factorio.com/api does not implement CORS
 
-use crate::error::Result;
+use std::sync::Arc;
 
-use rocket::{get, response::status};
+use crate::{clients::AgentApiClient, error::Result};
+
+use fctrl::schema::{factorio_mod_portal_api::ModInfoShort, ModVersion};
+use rocket::{get, response::status, serde::json::Json, State};
 
 #[get("/api/mods?<namelist>&<page_size>&<page>")]
 pub async fn mod_portal_batch_get(
@@ -69,3 +72,37 @@ pub async fn mod_portal_full_get(
         }
     }
 }
+
+/// Looks up the named mods, keeping only the releases that the installed version of Factorio can
+/// load, newest first, so that a stale release can't be picked. Mods not on the portal are left
+/// out. If Factorio isn't installed, all releases are kept.
+#[get("/api/mods/search?<namelist>")]
+pub async fn mod_portal_search(
+    agent_client: &State<Arc<AgentApiClient>>,
+    namelist: Vec<String>,
+) -> Result<Json<Vec<ModInfoShort>>> {
+    let installed = agent_client.version_get().await?;
+    let lookups = namelist.iter().map(|name| async move {
+        let url = format!("https://mods.factorio.com/api/mods/{}", name);
+        match reqwest::get(url).await?.error_for_status() {
+            Ok(r) => Ok(Some(r.json::<ModInfoShort>().await?)),
+            Err(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => Ok(None),
+            Err(e) => Err(e),
+        }
+    });
+
+    let mut mods = vec![];
+    for info in futures::future::try_join_all(lookups).await? {
+        if let Some(mut info) = info {
+            if let Some(installed) = &installed {
+                info.releases
+                    .retain(|r| installed.loads_mods_for(&r.info_json.factorio_version));
+            }
+            info.releases.sort_by(|a, b| {
+                ModVersion::from(b.version.as_str()).cmp(&ModVersion::from(a.version.as_str()))
+            });
+            mods.push(info);
+        }
+    }
+    Ok(Json(mods))
+}
@@ -0,0 +1,158 @@
+use std::{convert::TryFrom, sync::Arc};
+
+use fctrl::schema::{
+    mgmt_server_rest::{JobObject, ScheduleCreateRequest, ScheduleObject},
+    JobStatus, MissedRunPolicy,
+};
+use log::warn;
+use rocket::{delete, get, post, serde::json::Json, State};
+
+use crate::{
+    auth::AuthorizedUser,
+    clients::AgentApiClient,
+    error::{Error, Result},
+    scheduler::{Schedule, ScheduledAction, Scheduler},
+};
+
+#[get("/schedules")]
+pub async fn get_schedules(
+    _a: AuthorizedUser,
+    scheduler: &State<Arc<Scheduler>>,
+) -> Result<Json<Vec<ScheduleObject>>> {
+    let schedules = scheduler
+        .list()?
+        .into_iter()
+        .map(to_schedule_object)
+        .collect();
+    Ok(Json(schedules))
+}
+
+#[post("/schedules", data = "<body>")]
+pub async fn create_schedule(
+    _a: AuthorizedUser,
+    scheduler: &State<Arc<Scheduler>>,
+    body: Json<ScheduleCreateRequest>,
+) -> Result<Json<ScheduleObject>> {
+    let body = body.into_inner();
+    let action = match body.action.as_str() {
+        "restart" => ScheduledAction::Restart {
+            savefile: body
+                .savefile
+                .ok_or_else(|| Error::BadRequest("savefile is required for restart".to_owned()))?,
+        },
+        "mod_update" => ScheduledAction::ModUpdate,
+        "map_preview" => ScheduledAction::MapPreview,
+        "broadcast" => ScheduledAction::Broadcast {
+            message: body
+                .message
+                .ok_or_else(|| Error::BadRequest("message is required for broadcast".to_owned()))?,
+        },
+        other => {
+            return Err(Error::BadRequest(format!(
+                "Unknown schedule action '{}'",
+                other
+            )))
+        }
+    };
+    let jitter_secs = u64::try_from(body.jitter_secs.unwrap_or(0))
+        .map_err(|_| Error::BadRequest("jitter_secs must not be negative".to_owned()))?;
+    let missed_run = match body.missed_run.as_deref() {
+        None | Some("skip") => MissedRunPolicy::Skip,
+        Some("run_once") => MissedRunPolicy::RunOnce,
+        Some(other) => {
+            return Err(Error::BadRequest(format!(
+                "Unknown missed run policy '{}'",
+                other
+            )))
+        }
+    };
+    let schedule = scheduler.create(body.cron, action, jitter_secs, missed_run)?;
+    Ok(Json(to_schedule_object(schedule)))
+}
+
+#[delete("/schedules/<id>")]
+pub async fn delete_schedule(
+    _a: AuthorizedUser,
+    scheduler: &State<Arc<Scheduler>>,
+    id: String,
+) -> Result<()> {
+    scheduler.delete(&id)
+}
+
+#[get("/jobs")]
+pub async fn get_jobs(
+    _a: AuthorizedUser,
+    scheduler: &State<Arc<Scheduler>>,
+    agent_client: &State<Arc<AgentApiClient>>,
+) -> Result<Json<Vec<JobObject>>> {
+    let mut jobs: Vec<_> = scheduler
+        .list()?
+        .into_iter()
+        .map(|schedule| JobObject {
+            source: "mgmt-server".to_owned(),
+            next_run: schedule.next_run().map(|dt| dt.to_rfc3339()),
+            name: schedule.id,
+            schedule: schedule.cron,
+            jitter_secs: schedule.jitter_secs as i64,
+            missed_run: missed_run_str(schedule.missed_run).to_owned(),
+            last_run: schedule.last_run.map(|dt| dt.to_rfc3339()),
+            last_error: schedule.last_error,
+        })
+        .collect();
+    jobs.extend(
+        scheduler
+            .jobs()
+            .into_iter()
+            .map(|job| to_job_object("mgmt-server", job)),
+    );
+    match agent_client.job_list().await {
+        Ok(agent_jobs) => jobs.extend(
+            agent_jobs
+                .into_iter()
+                .map(|job| to_job_object("agent", job)),
+        ),
+        Err(e) => warn!("Couldn't list agent jobs: {:?}", e),
+    }
+    Ok(Json(jobs))
+}
+
+fn to_job_object(source: &str, job: JobStatus) -> JobObject {
+    JobObject {
+        source: source.to_owned(),
+        name: job.name,
+        schedule: format!("every {}s", job.interval_secs),
+        jitter_secs: job.jitter_secs as i64,
+        missed_run: missed_run_str(job.missed_run).to_owned(),
+        last_run: job.last_run.map(|dt| dt.to_rfc3339()),
+        next_run: Some(job.next_run.to_rfc3339()),
+        last_error: job.last_error,
+    }
+}
+
+fn missed_run_str(missed_run: MissedRunPolicy) -> &'static str {
+    match missed_run {
+        MissedRunPolicy::Skip => "skip",
+        MissedRunPolicy::RunOnce => "run_once",
+    }
+}
+
+fn to_schedule_object(schedule: Schedule) -> ScheduleObject {
+    let (action, savefile, message) = match schedule.action {
+        ScheduledAction::Restart { savefile } => ("restart", Some(savefile), None),
+        ScheduledAction::ModUpdate => ("mod_update", None, None),
+        ScheduledAction::MapPreview => ("map_preview", None, None),
+        ScheduledAction::Broadcast { message } => ("broadcast", None, Some(message)),
+    };
+    ScheduleObject {
+        next_run: schedule.next_run().map(|dt| dt.to_rfc3339()),
+        id: schedule.id,
+        cron: schedule.cron,
+        action: action.to_owned(),
+        savefile,
+        message,
+        last_run: schedule.last_run.map(|dt| dt.to_rfc3339()),
+        jitter_secs: Some(schedule.jitter_secs as i64),
+        missed_run: Some(missed_run_str(schedule.missed_run).to_owned()),
+        last_error: schedule.last_error,
+    }
+}
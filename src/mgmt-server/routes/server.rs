@@ -1,34 +1,64 @@
 use std::{
-    collections::HashSet, convert::{TryFrom, TryInto}, sync::Arc, time::Duration
+    collections::HashSet, convert::TryFrom, sync::Arc, time::Duration
 };
 
-use factorio_file_parser::ModSettings;
+use chrono::{DateTime, Utc};
+use fctrl::mod_settings::ModSettings;
 use fctrl::schema::{
-    mgmt_server_rest::*, Dlc, FactorioVersion, MapGenSettingsJson, MapSettingsJson, ModSettingsBytes, RconConfig, SaveBytes, SecretsObject, ServerSettingsConfig, ServerStartSaveFile, ServerStatus
+    mgmt_server_rest::*, AgentStreamingMessage, AgentStreamingMessageInner, Dlc, FactorioVersion, MapGenSettingsJson, MapSettingsJson, ModSettingsBytes, OperationId, PerformanceConfig, RconConfig, RestartPolicy, SaveBytes, SecretsObject, InternalServerState, ServerSettingsConfig, ServerStartSaveFile, ServerStatus
 };
 use rocket::{data::ToByteUnit, delete, serde::json::Json, Data};
 use rocket::{get, post, put};
-use rocket::{http::Status, State};
+use futures::{future, pin_mut, StreamExt};
+use rocket::{http::{ContentType, Status}, State};
+use serde::Serialize;
+use uuid::Uuid;
 
 use crate::{
-    auth::AuthorizedUser, clients::AgentApiClient, guards::{ContentLengthHeader, ContentRangeHeader, HostHeader}, link_download::{LinkDownloadManager, LinkDownloadTarget}, ws::WebSocketServer
+    auth::{AuthorizedUser, OperatorUser, ViewerUser}, clients::AgentApiClient, events::{broker::EventBroker, TopicName, SERVERSTATE_TOPIC_NAME, STDOUT_TOPIC_NAME}, guards::{ContentLengthHeader, ContentRangeHeader, ContentSha256Header, HostHeader}, link_download::{LinkDownloadManager, LinkDownloadTarget}, mod_import, save_diff, save_hashes::SaveHashes, save_upload, ws::WebSocketServer
 };
-use crate::{error::Result, routes::WsStreamingResponder};
+use crate::{error::{Error, Result}, routes::WsStreamingResponder};
 
 use super::LinkDownloadResponder;
 
+/// Largest savefile accepted by the streaming upload endpoint
+const MAX_SAVEFILE_UPLOAD_SIZE_GIB: u64 = 16;
+/// Largest single chunk accepted by the chunked savefile upload endpoint
+const MAX_SAVEFILE_CHUNK_SIZE_MIB: usize = 64;
+/// Largest mod settings accepted, either as a mod-settings.dat file or converted to JSON
+const MAX_MOD_SETTINGS_SIZE_MIB: u64 = 8;
+/// Longest delay accepted for a scheduled server stop
+const MAX_SCHEDULED_STOP_DELAY_SECS: u32 = 60 * 60;
+/// Longest wait for a save triggered through the API to finish
+const SERVER_SAVE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Largest mod-list.json accepted for import
+const MAX_MOD_LIST_SIZE_MIB: u64 = 8;
+/// Largest mod pack accepted for import, which is only read for the names of the mods in it
+const MAX_MOD_PACK_SIZE_GIB: u64 = 8;
+
 #[get("/server/control")]
 pub async fn status(
-    _a: AuthorizedUser,
+    _a: ViewerUser,
     agent_client: &State<Arc<AgentApiClient>>,
 ) -> Result<Json<ServerControlStatus>> {
     let ss = agent_client.server_status().await?;
     let mut num_players = 0;
+    let mut current_ups = None;
+    let mut current_map_clock = None;
+    let mut current_next_restart = None;
     let game_status = match ss {
         ServerStatus::NotRunning => GameStatus::NotRunning,
         ServerStatus::PreGame => GameStatus::PreGame,
-        ServerStatus::InGame { player_count } => {
+        ServerStatus::InGame {
+            player_count,
+            ups,
+            map_clock,
+            next_restart,
+        } => {
             num_players = player_count as i32;
+            current_ups = ups;
+            current_map_clock = map_clock;
+            current_next_restart = next_restart;
             GameStatus::InGame
         }
         ServerStatus::PostGame => GameStatus::PostGame,
@@ -36,13 +66,22 @@ pub async fn status(
     Ok(Json(ServerControlStatus {
         game_status,
         player_count: num_players,
+        ups: current_ups,
+        approximate_map_clock: current_map_clock.map(|c| {
+            Box::new(MapClock {
+                tick: c.tick as i64,
+                day: c.day as i64,
+                time_of_day: c.time_of_day(),
+            })
+        }),
+        next_restart: current_next_restart.map(|t| t.to_rfc3339()),
     }))
 }
 
 #[post("/server/control/create", data = "<create_request>")]
 pub async fn create_savefile<'a>(
     host: HostHeader<'a>,
-    _a: AuthorizedUser,
+    _a: OperatorUser,
     agent_client: &State<Arc<AgentApiClient>>,
     ws: &State<Arc<WebSocketServer>>,
     create_request: Json<ServerControlCreatePostRequest>,
@@ -75,31 +114,156 @@ pub async fn create_savefile<'a>(
 
 #[post("/server/control/start", data = "<savefile>")]
 pub async fn start_server(
-    _a: AuthorizedUser,
+    _a: OperatorUser,
     agent_client: &State<Arc<AgentApiClient>>,
     savefile: Json<ServerControlStartPostRequest>,
 ) -> Result<Status> {
-    let start_savefile_args = ServerStartSaveFile::Specific(savefile.into_inner().savefile);
-    agent_client.server_start(start_savefile_args).await?;
+    let savefile = savefile.into_inner();
+    let start_savefile_args = ServerStartSaveFile::Specific(savefile.savefile);
+    agent_client
+        .server_start(
+            start_savefile_args,
+            savefile.version.map(FactorioVersion),
+            savefile.profile,
+        )
+        .await?;
     Ok(Status::Accepted)
 }
 
+#[post("/server/control/start/plan", data = "<savefile>")]
+pub async fn plan_start_server(
+    _a: OperatorUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    savefile: Json<ServerControlStartPostRequest>,
+) -> Result<Json<ServerStartPlanResponse>> {
+    let savefile = savefile.into_inner();
+    let start_savefile_args = ServerStartSaveFile::Specific(savefile.savefile);
+    let plan = agent_client
+        .server_start_plan(
+            start_savefile_args,
+            savefile.version.map(FactorioVersion),
+            savefile.profile,
+        )
+        .await?;
+    Ok(Json(ServerStartPlanResponse {
+        command_line: plan.command_line,
+        settings_files: plan.settings_files,
+        issues: plan.issues,
+    }))
+}
+
 #[post("/server/control/stop")]
 pub async fn stop_server(
-    _a: AuthorizedUser,
+    _a: OperatorUser,
     agent_client: &State<Arc<AgentApiClient>>,
 ) -> Result<Status> {
     agent_client.server_stop().await?;
     Ok(Status::Accepted)
 }
 
+#[post("/server/control/stop-scheduled", data = "<body>")]
+pub async fn stop_server_scheduled<'a>(
+    host: HostHeader<'a>,
+    _a: OperatorUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    ws: &State<Arc<WebSocketServer>>,
+    body: Json<ServerControlStopScheduledPostRequest>,
+) -> Result<WsStreamingResponder> {
+    let body = body.into_inner();
+    let delay_secs = match u32::try_from(body.delay_secs) {
+        Ok(delay_secs) if delay_secs <= MAX_SCHEDULED_STOP_DELAY_SECS => delay_secs,
+        _ => {
+            return Err(Error::BadRequest(format!(
+                "Delay must be between 0 and {} seconds",
+                MAX_SCHEDULED_STOP_DELAY_SECS
+            )))
+        }
+    };
+    let (id, sub) = agent_client
+        .server_stop_scheduled(delay_secs, body.message)
+        .await?;
+
+    let resp = WsStreamingResponder::new(Arc::clone(&ws), host, id);
+
+    let ws = Arc::clone(&ws);
+    let path = resp.path.clone();
+    let timeout = Duration::from_secs(delay_secs as u64 + 300);
+    tokio::spawn(async move {
+        ws.stream_at(path, sub, timeout).await;
+    });
+
+    Ok(resp)
+}
+
+#[post("/server/save")]
+pub async fn save_server(
+    _a: OperatorUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    event_broker: &State<Arc<EventBroker>>,
+) -> Result<()> {
+    if !matches!(
+        agent_client.server_status().await?,
+        ServerStatus::InGame { .. }
+    ) {
+        return Err(Error::BadRequest("Server is not in game".to_owned()));
+    }
+
+    // subscribe before saving so that a quick save isn't missed
+    let save_finished = event_broker
+        .subscribe(TopicName::new(SERVERSTATE_TOPIC_NAME), |states_str| {
+            states_str.split_once(' ').map_or(false, |(from, _)| {
+                from == InternalServerState::InGameSavingMap.as_ref()
+            })
+        })
+        .await;
+    pin_mut!(save_finished);
+    agent_client.rcon_command("/server-save".to_owned()).await?;
+
+    match tokio::time::timeout(SERVER_SAVE_TIMEOUT, save_finished.next()).await {
+        Ok(Some(event)) => {
+            let states = event
+                .tags
+                .get(&TopicName::new(SERVERSTATE_TOPIC_NAME))
+                .unwrap();
+            match states.split_once(' ') {
+                Some((_, to)) if to == InternalServerState::InGame.as_ref() => Ok(()),
+                _ => Err(Error::AgentInternalError(format!(
+                    "Server did not return to in game after saving: {}",
+                    states
+                ))),
+            }
+        }
+        Ok(None) => Err(Error::InternalMessaging(
+            "Server state subscription ended while saving".to_owned(),
+        )),
+        Err(_) => Err(Error::AgentTimeout),
+    }
+}
+
 #[get("/server/install")]
 pub async fn get_install(
-    _a: AuthorizedUser,
+    _a: ViewerUser,
     agent_client: &State<Arc<AgentApiClient>>,
 ) -> Result<Json<ServerInstallGetResponse>> {
     let version = agent_client.version_get().await?.map(|v| v.0);
-    Ok(Json(ServerInstallGetResponse { version }))
+    let installed_versions = agent_client
+        .version_list()
+        .await?
+        .into_iter()
+        .map(|v| v.0)
+        .collect();
+    let mut available_dlcs: Vec<_> = agent_client
+        .mod_dlcs_available_get()
+        .await?
+        .into_iter()
+        .map(|d| d.to_string())
+        .collect();
+    available_dlcs.sort();
+    Ok(Json(ServerInstallGetResponse {
+        version,
+        installed_versions: Some(installed_versions),
+        available_dlcs: Some(available_dlcs),
+    }))
 }
 
 #[post("/server/install", data = "<body>")]
@@ -115,6 +279,7 @@ pub async fn upgrade_install<'a>(
         .version_install(
             FactorioVersion(body.version),
             body.force_install.unwrap_or(false),
+            body.keep_existing.unwrap_or(false),
         )
         .await?;
 
@@ -129,9 +294,32 @@ pub async fn upgrade_install<'a>(
     Ok(resp)
 }
 
+#[post("/server/install/archive", data = "<body>")]
+pub async fn install_from_archive<'a>(
+    host: HostHeader<'a>,
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    ws: &State<Arc<WebSocketServer>>,
+    body: Json<ServerInstallArchivePostRequest>,
+) -> Result<WsStreamingResponder> {
+    let (id, sub) = agent_client
+        .version_install_from_archive(body.into_inner().path)
+        .await?;
+
+    let resp = WsStreamingResponder::new(Arc::clone(&ws), host, id);
+
+    let ws = Arc::clone(&ws);
+    let path = resp.path.clone();
+    tokio::spawn(async move {
+        ws.stream_at(path, sub, Duration::from_secs(300)).await;
+    });
+
+    Ok(resp)
+}
+
 #[get("/server/savefiles")]
 pub async fn get_savefiles(
-    _a: AuthorizedUser,
+    _a: ViewerUser,
     agent_client: &State<Arc<AgentApiClient>>,
 ) -> Result<Json<Vec<SavefileObject>>> {
     let s = agent_client.save_list().await?;
@@ -140,6 +328,8 @@ pub async fn get_savefiles(
         .map(|s| SavefileObject {
             name: s.name,
             last_modified: Some(s.last_modified.to_string()),
+            staged: Some(s.staged),
+            remote: Some(s.remote),
         })
         .collect();
     Ok(Json(ret))
@@ -154,6 +344,76 @@ pub async fn delete_savefile(
     agent_client.save_delete(id).await
 }
 
+#[post("/server/savefiles/<id>/rename", data = "<body>")]
+pub async fn rename_savefile(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    id: String,
+    body: Json<SavefileNameRequest>,
+) -> Result<()> {
+    agent_client.save_rename(id, body.into_inner().name).await
+}
+
+#[post("/server/savefiles/<id>/copy", data = "<body>")]
+pub async fn copy_savefile(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    id: String,
+    body: Json<SavefileNameRequest>,
+) -> Result<()> {
+    agent_client.save_copy(id, body.into_inner().name).await
+}
+
+#[post("/server/savefiles/<id>/backup")]
+pub async fn backup_savefile<'a>(
+    host: HostHeader<'a>,
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    ws: &State<Arc<WebSocketServer>>,
+    id: String,
+) -> Result<WsStreamingResponder> {
+    let (id, sub) = agent_client.backup_create(id).await?;
+
+    let resp = WsStreamingResponder::new(Arc::clone(&ws), host, id);
+
+    let ws = Arc::clone(&ws);
+    let path = resp.path.clone();
+    tokio::spawn(async move {
+        ws.stream_at(path, sub, Duration::from_secs(300)).await;
+    });
+
+    Ok(resp)
+}
+
+#[get("/server/backups")]
+pub async fn get_backups(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+) -> Result<Json<BackupStatusResponse>> {
+    let status = agent_client.backup_status_get().await?;
+    Ok(Json(BackupStatusResponse {
+        enabled: status.enabled,
+        last_success: status.last_success.map(|b| Box::new(to_backup_object(b))),
+        last_failure: status.last_failure.map(|f| {
+            Box::new(BackupFailureObject {
+                savefile: f.save_name,
+                timestamp: f.timestamp.to_string(),
+                error: f.error,
+            })
+        }),
+        backups: status.backups.into_iter().map(to_backup_object).collect(),
+    }))
+}
+
+fn to_backup_object(backup: fctrl::schema::BackupObject) -> BackupObject {
+    BackupObject {
+        savefile: backup.save_name,
+        key: backup.key,
+        timestamp: backup.timestamp.to_string(),
+        size_bytes: backup.size_bytes as i64,
+    }
+}
+
 #[get("/server/savefiles/<id>")]
 pub async fn get_savefile(
     _a: AuthorizedUser,
@@ -176,29 +436,153 @@ pub async fn extract_mod_list_from_savefile(
         .map(|mo| ModObject {
             name: mo.name,
             version: mo.version,
+            enabled: None,
         })
         .collect();
     Ok(Json(resp))
 }
 
+#[get("/server/savefiles/<id>/diff/<other_id>")]
+pub async fn diff_savefiles(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    id: String,
+    other_id: String,
+) -> Result<Json<SavefileDiff>> {
+    let from = agent_client.save_metadata_get(id).await?;
+    let to = agent_client.save_metadata_get(other_id).await?;
+    let d = save_diff::diff(&from, &to);
+    let to_mod_objects = |mods: Vec<fctrl::schema::ModObject>| -> Vec<ModObject> {
+        mods.into_iter()
+            .map(|mo| ModObject {
+                name: mo.name,
+                version: mo.version,
+                enabled: None,
+            })
+            .collect()
+    };
+    Ok(Json(SavefileDiff {
+        summary: d.summary,
+        mods_added: to_mod_objects(d.mods_added),
+        mods_removed: to_mod_objects(d.mods_removed),
+        mods_changed: d
+            .mods_changed
+            .into_iter()
+            .map(|c| SavefileDiffModChange {
+                name: c.name,
+                from_version: c.from_version,
+                to_version: c.to_version,
+            })
+            .collect(),
+    }))
+}
+
 #[put("/server/savefiles/<id>", data = "<body>")]
 pub async fn put_savefile(
     _a: AuthorizedUser,
     agent_client: &State<Arc<AgentApiClient>>,
+    save_hashes: &State<Arc<SaveHashes>>,
     id: String,
     body: Data<'_>,
     content_length: ContentLengthHeader,
     content_range: ContentRangeHeader,
+    content_sha256: Option<ContentSha256Header>,
 ) -> Result<()> {
+    if content_length.length > MAX_SAVEFILE_CHUNK_SIZE_MIB * 1024 * 1024 {
+        return Err(Error::PayloadTooLarge(format!(
+            "Savefile chunk exceeds the {} MiB limit",
+            MAX_SAVEFILE_CHUNK_SIZE_MIB
+        )));
+    }
+    let sha256 = content_sha256.map(|h| h.sha256);
+    if let (0, Some(sha256)) = (content_range.start, &sha256) {
+        reject_duplicate_upload(agent_client, save_hashes, sha256).await?;
+    }
     let chunk_stream = body.open(content_length.length.bytes());
-    let savebytes = SaveBytes {
-        multipart_start: Some(content_range.start),
-        bytes: chunk_stream.into_bytes().await?.into_inner(),
+    save_upload::upload_range(agent_client, &id, content_range.start, chunk_stream).await?;
+
+    // finalise once the last chunk has been received
+    if content_range.end + 1 == content_range.length {
+        let sentinel = SaveBytes::sentinel(content_range.length).with_sha256(sha256.clone());
+        agent_client.save_put(id.clone(), sentinel).await?;
+        if let Some(sha256) = sha256 {
+            save_hashes.record(agent_client, &id, &sha256).await;
+        }
+    }
+    Ok(())
+}
+
+#[post("/server/savefiles/<id>", data = "<body>")]
+pub async fn upload_savefile(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    save_hashes: &State<Arc<SaveHashes>>,
+    id: String,
+    content_type: Option<&ContentType>,
+    content_sha256: Option<ContentSha256Header>,
+    body: Data<'_>,
+) -> Result<()> {
+    if let Some(content_sha256) = content_sha256 {
+        reject_duplicate_upload(agent_client, save_hashes, &content_sha256.sha256).await?;
+    }
+    let boundary = match content_type {
+        Some(ct) if ct.is_form_data() => match ct.param("boundary") {
+            Some(boundary) => Some(boundary),
+            None => return Err(Error::BadRequest("Multipart body has no boundary".to_owned())),
+        },
+        _ => None,
     };
-    agent_client.save_put(id, savebytes).await?;
+    // read past the limit, as Rocket silently truncates the body at it
+    let max_size = MAX_SAVEFILE_UPLOAD_SIZE_GIB.gibibytes();
+    let stream = body.open(max_size + 1);
+    let sha256 =
+        save_upload::upload(agent_client, id.clone(), stream, boundary, Some(max_size)).await?;
+    save_hashes.record(agent_client, &id, &sha256).await;
     Ok(())
 }
 
+/// Fails an upload of a savefile that is already on the server before any of it is transferred,
+/// so that the client can copy the existing savefile instead
+async fn reject_duplicate_upload(
+    agent_client: &AgentApiClient,
+    save_hashes: &SaveHashes,
+    sha256: &str,
+) -> Result<()> {
+    match save_hashes.find(agent_client, sha256).await? {
+        Some(existing) => Err(Error::SaveDuplicate(existing)),
+        None => Ok(()),
+    }
+}
+
+#[get("/server/map-preview")]
+pub async fn get_map_preview(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+) -> Result<(ContentType, Vec<u8>)> {
+    let preview = agent_client.map_preview_get().await?;
+    Ok((ContentType::PNG, preview.bytes))
+}
+
+#[post("/server/map-preview")]
+pub async fn generate_map_preview<'a>(
+    host: HostHeader<'a>,
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    ws: &State<Arc<WebSocketServer>>,
+) -> Result<WsStreamingResponder> {
+    let (id, sub) = agent_client.map_preview_generate().await?;
+
+    let resp = WsStreamingResponder::new(Arc::clone(&ws), host, id);
+
+    let ws = Arc::clone(&ws);
+    let path = resp.path.clone();
+    tokio::spawn(async move {
+        ws.stream_at(path, sub, Duration::from_secs(300)).await;
+    });
+
+    Ok(resp)
+}
+
 #[get("/server/config/adminlist")]
 pub async fn get_adminlist(
     _a: AuthorizedUser,
@@ -235,6 +619,62 @@ pub async fn put_banlist(
     agent_client.config_banlist_set(body.into_inner()).await
 }
 
+#[get("/server/config/restartpolicy")]
+pub async fn get_restart_policy(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+) -> Result<Json<ServerConfigRestartPolicy>> {
+    let restart_policy = agent_client.config_restart_policy_get().await?;
+    let resp = ServerConfigRestartPolicy {
+        max_retries: restart_policy.max_retries as i32,
+        backoff_secs: restart_policy.backoff_secs as i64,
+        uptime_restart_hours: Some(restart_policy.uptime_restart_hours as i32),
+        uptime_restart_max_delay_mins: Some(restart_policy.uptime_restart_max_delay_mins as i32),
+    };
+    Ok(Json(resp))
+}
+
+#[put("/server/config/restartpolicy", data = "<body>")]
+pub async fn put_restart_policy(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    body: Json<ServerConfigRestartPolicy>,
+) -> Result<()> {
+    let body = body.into_inner();
+    let restart_policy = RestartPolicy {
+        max_retries: u32::try_from(body.max_retries)
+            .map_err(|_| Error::BadRequest("max_retries must not be negative".to_owned()))?,
+        backoff_secs: u64::try_from(body.backoff_secs)
+            .map_err(|_| Error::BadRequest("backoff_secs must not be negative".to_owned()))?,
+        uptime_restart_hours: u32::try_from(body.uptime_restart_hours.unwrap_or(0)).map_err(
+            |_| Error::BadRequest("uptime_restart_hours must not be negative".to_owned()),
+        )?,
+        uptime_restart_max_delay_mins: u32::try_from(
+            body.uptime_restart_max_delay_mins.unwrap_or(0),
+        )
+        .map_err(|_| {
+            Error::BadRequest("uptime_restart_max_delay_mins must not be negative".to_owned())
+        })?,
+    };
+    agent_client.config_restart_policy_set(restart_policy).await
+}
+
+#[get("/server/config/diagnostics")]
+pub async fn get_config_diagnostics(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+) -> Result<Json<Vec<ServerConfigDiagnostic>>> {
+    let warnings = agent_client.config_diagnostics_get().await?;
+    let resp = warnings
+        .into_iter()
+        .map(|w| ServerConfigDiagnostic {
+            kind: w.kind.as_ref().to_owned(),
+            message: w.message,
+        })
+        .collect();
+    Ok(Json(resp))
+}
+
 #[get("/server/config/whitelist")]
 pub async fn get_whitelist(
     _a: AuthorizedUser,
@@ -260,6 +700,78 @@ pub async fn put_whitelist(
         .await
 }
 
+#[get("/server/config/launch-profiles")]
+pub async fn get_launch_profiles(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+) -> Result<Json<Vec<LaunchProfileObject>>> {
+    let profiles = agent_client.config_profile_list().await?;
+    let resp = profiles
+        .into_iter()
+        .map(|p| LaunchProfileObject {
+            name: p.name,
+            use_whitelist: p.use_whitelist,
+            public: p.public,
+            mod_count: p.mod_count as i32,
+        })
+        .collect();
+    Ok(Json(resp))
+}
+
+#[put("/server/config/launch-profiles/<name>")]
+pub async fn put_launch_profile(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    name: String,
+) -> Result<()> {
+    agent_client.config_profile_save(name).await
+}
+
+#[delete("/server/config/launch-profiles/<name>")]
+pub async fn delete_launch_profile(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    name: String,
+) -> Result<()> {
+    agent_client.config_profile_delete(name).await
+}
+
+#[get("/server/config/performance")]
+pub async fn get_performance_config(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+) -> Result<Json<ServerConfigPerformance>> {
+    let config = agent_client.config_performance_get().await?;
+    let resp = ServerConfigPerformance {
+        cpu_affinity: config
+            .cpu_affinity
+            .map(|cpus| cpus.into_iter().map(|cpu| cpu as i32).collect()),
+    };
+    Ok(Json(resp))
+}
+
+#[put("/server/config/performance", data = "<body>")]
+pub async fn put_performance_config(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    body: Json<ServerConfigPerformance>,
+) -> Result<()> {
+    let cpu_affinity = match body.into_inner().cpu_affinity {
+        Some(cpus) => Some(
+            cpus.into_iter()
+                .map(|cpu| {
+                    usize::try_from(cpu)
+                        .map_err(|_| Error::BadRequest(format!("Invalid CPU index {}", cpu)))
+                })
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        None => None,
+    };
+    agent_client
+        .config_performance_set(PerformanceConfig { cpu_affinity })
+        .await
+}
+
 #[get("/server/config/rcon")]
 pub async fn get_rcon_config(
     _a: AuthorizedUser,
@@ -342,7 +854,7 @@ pub async fn set_dlcs(
 
 #[get("/server/mods/list")]
 pub async fn get_mods_list(
-    _a: AuthorizedUser,
+    _a: ViewerUser,
     agent_client: &State<Arc<AgentApiClient>>,
 ) -> Result<Json<Vec<ModObject>>> {
     let mod_list = agent_client.mod_list_get().await?;
@@ -352,6 +864,7 @@ pub async fn get_mods_list(
         .map(|mo| ModObject {
             name: mo.name,
             version: mo.version,
+            enabled: Some(mo.enabled),
         })
         .collect();
     Ok(Json(resp))
@@ -372,10 +885,74 @@ pub async fn apply_mods_list<'a>(
         .map(|mo| fctrl::schema::ModObject {
             name: mo.name,
             version: mo.version,
+            enabled: mo.enabled.unwrap_or(true),
         })
         .collect();
 
-    let (id, sub) = agent_client.mod_list_set(mod_list).await?;
+    set_mods_list(host, agent_client, ws, mod_list).await
+}
+
+/// Sets the mod list from a mod-list.json, or from a zip of mods such as the mod directory of an
+/// existing server. The mods are downloaded from the mod portal as with a mod list set.
+#[post("/server/mods/list/import", data = "<body>")]
+pub async fn import_mods_list<'a>(
+    host: HostHeader<'a>,
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    ws: &State<Arc<WebSocketServer>>,
+    content_type: &ContentType,
+    body: Data<'_>,
+) -> Result<WsStreamingResponder> {
+    let mod_list = if content_type.is_json() {
+        let body = body
+            .open(MAX_MOD_LIST_SIZE_MIB.mebibytes())
+            .into_string()
+            .await?;
+        if !body.is_complete() {
+            return Err(Error::PayloadTooLarge(format!(
+                "Mod list exceeds the {} MiB limit",
+                MAX_MOD_LIST_SIZE_MIB
+            )));
+        }
+        mod_import::from_mod_list_json(&body)?
+    } else if content_type.is_zip() {
+        // the index of a zip is at the end, so spool the mod pack to disk to read it
+        let path = std::env::temp_dir().join(format!("fctrl-mod-pack-{}.zip", Uuid::new_v4()));
+        let file = body
+            .open(MAX_MOD_PACK_SIZE_GIB.gibibytes())
+            .into_file(&path)
+            .await;
+        let mod_list = match file {
+            Ok(file) if file.is_complete() => mod_import::from_mod_pack_zip(&path).await,
+            Ok(_) => Err(Error::PayloadTooLarge(format!(
+                "Mod pack exceeds the {} GiB limit",
+                MAX_MOD_PACK_SIZE_GIB
+            ))),
+            Err(e) => Err(e.into()),
+        };
+        let _ = tokio::fs::remove_file(&path).await;
+        mod_list?
+    } else {
+        return Err(Error::BadRequest(
+            "Expected a mod-list.json or a zip of mods".to_owned(),
+        ));
+    };
+
+    if mod_list.is_empty() {
+        return Err(Error::BadRequest("No mods found to import".to_owned()));
+    }
+
+    set_mods_list(host, agent_client, ws, mod_list).await
+}
+
+#[post("/server/mods/list/update-all")]
+pub async fn update_all_mods<'a>(
+    host: HostHeader<'a>,
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    ws: &State<Arc<WebSocketServer>>,
+) -> Result<WsStreamingResponder> {
+    let (id, sub) = agent_client.mod_list_update_all().await?;
 
     let resp = WsStreamingResponder::new(Arc::clone(&ws), host, id);
 
@@ -388,13 +965,51 @@ pub async fn apply_mods_list<'a>(
     Ok(resp)
 }
 
+#[post("/server/mods/list/<name>/enable")]
+pub async fn enable_mod(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    name: String,
+) -> Result<()> {
+    agent_client.mod_set_enabled(name, true).await
+}
+
+#[post("/server/mods/list/<name>/disable")]
+pub async fn disable_mod(
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    name: String,
+) -> Result<()> {
+    agent_client.mod_set_enabled(name, false).await
+}
+
+async fn set_mods_list<'a>(
+    host: HostHeader<'a>,
+    agent_client: &AgentApiClient,
+    ws: &Arc<WebSocketServer>,
+    mod_list: Vec<fctrl::schema::ModObject>,
+) -> Result<WsStreamingResponder> {
+    let (id, sub) = agent_client.mod_list_set(mod_list).await?;
+
+    let resp = WsStreamingResponder::new(Arc::clone(ws), host, id);
+
+    let ws = Arc::clone(ws);
+    let path = resp.path.clone();
+    tokio::spawn(async move {
+        ws.stream_at(path, sub, Duration::from_secs(300)).await;
+    });
+
+    Ok(resp)
+}
+
 #[get("/server/mods/settings")]
 pub async fn get_mod_settings(
     _a: AuthorizedUser,
     agent_client: &State<Arc<AgentApiClient>>,
 ) -> Result<Json<ModSettings>> {
     let ms_bytes = agent_client.mod_settings_get().await?;
-    let ms = ModSettings::try_from(ms_bytes.bytes.as_ref())?;
+    let ms = ModSettings::parse(&ms_bytes.bytes)
+        .map_err(|unreadable| Error::ModSettingsUnreadable(unreadable.to_string()))?;
 
     Ok(Json(ms))
 }
@@ -403,11 +1018,24 @@ pub async fn get_mod_settings(
 pub async fn put_mod_settings(
     _a: AuthorizedUser,
     agent_client: &State<Arc<AgentApiClient>>,
-    body: String,
+    body: Data<'_>,
 ) -> Result<()> {
+    let body = body
+        .open(MAX_MOD_SETTINGS_SIZE_MIB.mebibytes())
+        .into_string()
+        .await?;
+    if !body.is_complete() {
+        return Err(Error::PayloadTooLarge(format!(
+            "Mod settings exceed the {} MiB limit",
+            MAX_MOD_SETTINGS_SIZE_MIB
+        )));
+    }
     let ms: ModSettings = serde_json::from_str(&body)?;
-    let bytes = ms.try_into()?;
-    agent_client.mod_settings_set(ModSettingsBytes { bytes }).await
+    agent_client
+        .mod_settings_set(ModSettingsBytes {
+            bytes: ms.to_bytes(),
+        })
+        .await
 }
 
 #[get("/server/mods/settings-dat")]
@@ -423,9 +1051,23 @@ pub async fn get_mod_settings_dat(
 pub async fn put_mod_settings_dat(
     _a: AuthorizedUser,
     agent_client: &State<Arc<AgentApiClient>>,
-    body: Vec<u8>,
+    body: Data<'_>,
 ) -> Result<()> {
-    agent_client.mod_settings_set(ModSettingsBytes { bytes: body } ).await
+    let body = body
+        .open(MAX_MOD_SETTINGS_SIZE_MIB.mebibytes())
+        .into_bytes()
+        .await?;
+    if !body.is_complete() {
+        return Err(Error::PayloadTooLarge(format!(
+            "mod-settings.dat exceeds the {} MiB limit",
+            MAX_MOD_SETTINGS_SIZE_MIB
+        )));
+    }
+    agent_client
+        .mod_settings_set(ModSettingsBytes {
+            bytes: body.into_inner(),
+        })
+        .await
 }
 
 #[post("/server/rcon", data = "<body>")]
@@ -438,3 +1080,109 @@ pub async fn send_rcon_command(
     let response = agent_client.rcon_command(command).await?;
     Ok(Json(RconCommandResponse { response }))
 }
+
+#[post("/server/rcon/proxy")]
+pub async fn proxy_rcon<'a>(
+    host: HostHeader<'a>,
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    ws: &State<Arc<WebSocketServer>>,
+) -> Result<WsStreamingResponder> {
+    let id = OperationId(Uuid::new_v4().to_string());
+    let resp = WsStreamingResponder::new(Arc::clone(&ws), host, id);
+
+    let agent_client = Arc::clone(&agent_client);
+    let ws = Arc::clone(&ws);
+    let path = resp.path.clone();
+    tokio::spawn(async move {
+        ws.respond_at(
+            path,
+            |command| {
+                let agent_client = Arc::clone(&agent_client);
+                async move { agent_client.rcon_command(command).await }
+            },
+            Duration::from_secs(300),
+        )
+        .await;
+    });
+
+    Ok(resp)
+}
+
+/// Message sent over the interactive console websocket
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ConsoleMessage {
+    Stdout {
+        timestamp: DateTime<Utc>,
+        line: String,
+    },
+    RconResponse {
+        command: String,
+        response: String,
+    },
+    RconError {
+        command: String,
+        error: String,
+    },
+}
+
+impl ConsoleMessage {
+    fn to_json(&self) -> String {
+        // only strings and timestamps, so this can't fail
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+#[post("/server/console")]
+pub async fn console<'a>(
+    host: HostHeader<'a>,
+    _a: AuthorizedUser,
+    agent_client: &State<Arc<AgentApiClient>>,
+    event_broker: &State<Arc<EventBroker>>,
+    ws: &State<Arc<WebSocketServer>>,
+) -> Result<WsStreamingResponder> {
+    let id = OperationId(Uuid::new_v4().to_string());
+    let resp = WsStreamingResponder::new(Arc::clone(&ws), host, id);
+
+    let stdout = event_broker
+        .subscribe(TopicName::new(STDOUT_TOPIC_NAME), |_| true)
+        .await
+        .filter_map(|event| {
+            let line = match serde_json::from_str::<AgentStreamingMessage>(&event.content) {
+                Ok(AgentStreamingMessage {
+                    timestamp,
+                    content: AgentStreamingMessageInner::ServerStdout(line),
+                }) => Some(ConsoleMessage::Stdout { timestamp, line }.to_json()),
+                _ => None,
+            };
+            future::ready(line)
+        });
+
+    let agent_client = Arc::clone(&agent_client);
+    let ws = Arc::clone(&ws);
+    let path = resp.path.clone();
+    tokio::spawn(async move {
+        ws.interact_at(
+            path,
+            stdout,
+            |command| {
+                let agent_client = Arc::clone(&agent_client);
+                async move {
+                    let message = match agent_client.rcon_command(command.clone()).await {
+                        Ok(response) => ConsoleMessage::RconResponse { command, response },
+                        Err(e) => ConsoleMessage::RconError {
+                            command,
+                            error: format!("{:?}", e),
+                        },
+                    };
+                    message.to_json()
+                }
+            },
+            Duration::from_secs(300),
+        )
+        .await;
+    });
+
+    Ok(resp)
+}
@@ -0,0 +1,71 @@
+use std::{str::FromStr, sync::Arc};
+
+use fctrl::schema::{
+    mgmt_server_rest::{SettingsProfileObject, SettingsProfilePutRequest},
+    ServerVisibilityConfig,
+};
+use rocket::{get, post, put, serde::json::Json, State};
+use strum::IntoEnumIterator;
+
+use crate::{
+    auth::AuthorizedUser,
+    error::{Error, Result},
+    settings_profiles::{SettingsProfile, SettingsProfileMode, SettingsProfiles},
+};
+
+#[get("/server/config/settings-profiles")]
+pub async fn get_settings_profiles(
+    _a: AuthorizedUser,
+    settings_profiles: &State<Arc<SettingsProfiles>>,
+) -> Result<Json<Vec<SettingsProfileObject>>> {
+    let active = settings_profiles.active()?;
+    let mut profiles = vec![];
+    for mode in SettingsProfileMode::iter() {
+        if let Some(profile) = settings_profiles.get(mode)? {
+            profiles.push(SettingsProfileObject {
+                mode: mode.as_ref().to_owned(),
+                active: active == Some(mode),
+                name_suffix: profile.name_suffix,
+                game_password: profile.game_password,
+                public: profile.visibility.public,
+                lan: profile.visibility.lan,
+                require_user_verification: profile.require_user_verification,
+            });
+        }
+    }
+    Ok(Json(profiles))
+}
+
+#[put("/server/config/settings-profiles/<mode>", data = "<body>")]
+pub async fn put_settings_profile(
+    _a: AuthorizedUser,
+    settings_profiles: &State<Arc<SettingsProfiles>>,
+    mode: String,
+    body: Json<SettingsProfilePutRequest>,
+) -> Result<()> {
+    let mode = parse_mode(&mode)?;
+    let body = body.into_inner();
+    let profile = SettingsProfile {
+        name_suffix: body.name_suffix.unwrap_or_default(),
+        game_password: body.game_password.unwrap_or_default(),
+        visibility: ServerVisibilityConfig {
+            public: body.public,
+            lan: body.lan,
+        },
+        require_user_verification: body.require_user_verification,
+    };
+    settings_profiles.set(mode, &profile)
+}
+
+#[post("/server/config/settings-profiles/<mode>/activate")]
+pub async fn activate_settings_profile(
+    _a: AuthorizedUser,
+    settings_profiles: &State<Arc<SettingsProfiles>>,
+    mode: String,
+) -> Result<()> {
+    settings_profiles.switch(parse_mode(&mode)?).await
+}
+
+fn parse_mode(mode: &str) -> Result<SettingsProfileMode> {
+    SettingsProfileMode::from_str(mode).map_err(|_| Error::SettingsProfileNotFound)
+}
@@ -1,14 +1,20 @@
 use std::sync::Arc;
 
+use fctrl::schema::mgmt_server_rest::{
+    AgentStatusObject, DiscordStatus, DiskUsageObject, HealthObject, ProcessResourcesObject,
+};
 use log::error;
 use rocket::{get, serde::json::Json, State};
 
 use crate::clients::AgentApiClient;
+use crate::discord::DiscordClient;
 use crate::error::Result;
+use crate::events::broker::EventBroker;
 
 #[get("/system/monitor")]
 pub async fn monitor(
     agent_client: &State<Arc<AgentApiClient>>,
+    event_broker: &State<Arc<EventBroker>>,
 ) -> Result<Json<fctrl::schema::mgmt_server_rest::SystemResources>> {
     match agent_client.system_resources().await {
         Ok(s) => Ok(Json(fctrl::schema::mgmt_server_rest::SystemResources {
@@ -16,6 +22,25 @@ pub async fn monitor(
             cpus: s.cpus,
             mem_total_bytes: s.mem_total_bytes as i64,
             mem_used_bytes: s.mem_used_bytes as i64,
+            agent_dropped_messages: Some(s.dropped_messages as i64),
+            mgmt_server_dropped_messages: Some(event_broker.dropped_count() as i64),
+            disks: Some(
+                s.disks
+                    .into_iter()
+                    .map(|d| DiskUsageObject {
+                        path: d.path,
+                        total_bytes: d.total_bytes as i64,
+                        available_bytes: d.available_bytes as i64,
+                    })
+                    .collect(),
+            ),
+            server_process: s.server_process.map(|p| {
+                Box::new(ProcessResourcesObject {
+                    cpu_usage: p.cpu_usage,
+                    mem_rss_bytes: p.mem_rss_bytes as i64,
+                    uptime_secs: p.uptime_secs as i64,
+                })
+            }),
         })),
         Err(e) => {
             error!("Error retrieving agent build version: {:?}", e);
@@ -23,3 +48,34 @@ pub async fn monitor(
         },
     }
 }
+
+#[get("/health")]
+pub async fn health(discord: &State<Arc<Option<DiscordClient>>>) -> Json<HealthObject> {
+    let (discord_status, discord_pending_messages) = match discord.as_ref().as_ref() {
+        Some(discord) => {
+            let connectivity = discord.connectivity();
+            let status = if connectivity.is_connected() {
+                DiscordStatus::Connected
+            } else {
+                DiscordStatus::Disconnected
+            };
+            (status, Some(connectivity.pending_messages() as i64))
+        }
+        None => (DiscordStatus::Disabled, None),
+    };
+    Json(HealthObject {
+        discord_status,
+        discord_pending_messages,
+    })
+}
+
+#[get("/agent/status")]
+pub async fn agent_status(agent_client: &State<Arc<AgentApiClient>>) -> Json<AgentStatusObject> {
+    let health = agent_client.connection_health();
+    Json(AgentStatusObject {
+        connected: agent_client.is_connected(),
+        last_heartbeat: health.last_heartbeat.map(|dt| dt.to_rfc3339()),
+        reconnect_attempts: health.reconnect_attempts as i32,
+        latency_ms: health.latency.map(|d| d.as_millis() as i64),
+    })
+}
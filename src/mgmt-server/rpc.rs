@@ -8,12 +8,14 @@ use crate::clients::AgentApiClient;
 use crate::db::{Db, Record};
 use crate::discord::DiscordClient;
 use crate::error::{Error, Result};
+use crate::game_message::{AchievementsPolicy, GameMessage, MessageSource};
 use crate::metrics::{get_cf, DataPoint, MetricPeriod, Tick};
 
 pub struct RpcHandler {
     agent_client: Arc<AgentApiClient>,
     db: Arc<Db>,
     discord: Arc<Option<DiscordClient>>,
+    achievements_policy: AchievementsPolicy,
 }
 
 impl RpcHandler {
@@ -21,11 +23,13 @@ impl RpcHandler {
         agent_client: Arc<AgentApiClient>,
         db: Arc<Db>,
         discord: Arc<Option<DiscordClient>>,
+        achievements_policy: AchievementsPolicy,
     ) -> RpcHandler {
         RpcHandler {
             agent_client,
             db,
             discord,
+            achievements_policy,
         }
     }
 
@@ -69,14 +73,38 @@ impl RpcHandler {
                 // args is a json string
                 // Parse from json
                 let oneshot = serde_json::from_str::<OneshotData>(args)?;
-                if let Some(discord) = &*self.discord {
-                    discord.oneshot_alert(
-                        oneshot.notif_target_id,
-                        format!(
-                            "({},{}) {}",
-                            oneshot.position.x, oneshot.position.y, oneshot.message
-                        ),
-                    )
+                let alert_msg = format!(
+                    "({},{}) {}",
+                    oneshot.position.x, oneshot.position.y, oneshot.message
+                );
+                if !oneshot.notif_target_players.is_empty() {
+                    // notify in-game admins directly, in addition to any Discord alert
+                    let in_game_msg = GameMessage::Alert {
+                        message: &alert_msg,
+                    }
+                    .render();
+                    let notified = match self.achievements_policy.print_to_players_command(
+                        MessageSource::Rpc,
+                        &oneshot.notif_target_players,
+                        &in_game_msg,
+                    ) {
+                        Some(command) => self.agent_client.rcon_command(command).await.map(|_| ()),
+                        None => {
+                            self.agent_client
+                                .rcon_whisper(oneshot.notif_target_players, in_game_msg)
+                                .await
+                        }
+                    };
+                    // still raise the Discord alert if the in-game notification didn't go through
+                    if let Err(e) = notified {
+                        error!("Failed to notify in-game players of oneshot alert: {:?}", e);
+                    }
+                    if let Some(discord) = &*self.discord {
+                        discord.oneshot_alert(oneshot.notif_target_id, alert_msg)?;
+                    }
+                    Ok(())
+                } else if let Some(discord) = &*self.discord {
+                    discord.oneshot_alert(oneshot.notif_target_id, alert_msg)
                 } else {
                     Err(Error::Rpc(format!("discord integration not enabled")))
                 }
@@ -133,6 +161,9 @@ struct DataPointBatch {
 struct OneshotData {
     /// Identifier representing who to notify (discord snowflake id)
     notif_target_id: Option<String>,
+    /// Names of in-game players to whisper the alert to
+    #[serde(default)]
+    notif_target_players: Vec<String>,
     /// Map position
     position: Position,
     /// Alert message
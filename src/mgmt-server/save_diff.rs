@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+
+use fctrl::schema::{ModObject, SaveMetadata};
+
+/// Name of the built-in mod whose version is the version of Factorio that made a save
+const BASE_MOD_NAME: &str = "base";
+
+/// A mod present in both saves at different versions
+#[derive(Debug)]
+pub struct ModVersionChange {
+    pub name: String,
+    pub from_version: String,
+    pub to_version: String,
+}
+
+/// Differences going from one save to another, e.g. what restoring a backup over the current
+/// save would change.
+///
+/// Only what [`SaveMetadata`] records is compared: the Factorio version, mods, last saved time
+/// and file size. Map and game settings are stored in the level data, which isn't read.
+#[derive(Debug)]
+pub struct SaveDiff {
+    pub mods_added: Vec<ModObject>,
+    pub mods_removed: Vec<ModObject>,
+    pub mods_changed: Vec<ModVersionChange>,
+    /// Human-readable description of every difference
+    pub summary: Vec<String>,
+}
+
+pub fn diff(from: &SaveMetadata, to: &SaveMetadata) -> SaveDiff {
+    let mut summary = vec![];
+
+    let age = from.last_modified - to.last_modified;
+    if age > chrono::Duration::zero() {
+        summary.push(format!(
+            "{} was last saved {} before {}, progress made since then would be lost",
+            to.name,
+            format_duration(age),
+            from.name
+        ));
+    } else if age < chrono::Duration::zero() {
+        summary.push(format!(
+            "{} was last saved {} after {}",
+            to.name,
+            format_duration(-age),
+            from.name
+        ));
+    }
+
+    let from_mods: BTreeMap<_, _> = from.mods.iter().map(|m| (&m.name, &m.version)).collect();
+    let to_mods: BTreeMap<_, _> = to.mods.iter().map(|m| (&m.name, &m.version)).collect();
+    let mut mods_added = vec![];
+    let mut mods_removed = vec![];
+    let mut mods_changed = vec![];
+
+    for (name, version) in &to_mods {
+        match from_mods.get(name) {
+            None => mods_added.push(ModObject {
+                name: name.to_string(),
+                version: version.to_string(),
+                enabled: true,
+            }),
+            Some(from_version) if from_version != version => mods_changed.push(ModVersionChange {
+                name: name.to_string(),
+                from_version: from_version.to_string(),
+                to_version: version.to_string(),
+            }),
+            Some(_) => (),
+        }
+    }
+    for (name, version) in &from_mods {
+        if !to_mods.contains_key(name) {
+            mods_removed.push(ModObject {
+                name: name.to_string(),
+                version: version.to_string(),
+                enabled: true,
+            });
+        }
+    }
+
+    for change in &mods_changed {
+        if change.name == BASE_MOD_NAME {
+            summary.push(format!(
+                "Factorio version changes from {} to {}",
+                change.from_version, change.to_version
+            ));
+        } else {
+            summary.push(format!(
+                "Mod {} changes from {} to {}",
+                change.name, change.from_version, change.to_version
+            ));
+        }
+    }
+    for m in &mods_added {
+        summary.push(format!("Mod {} {} is added", m.name, m.version));
+    }
+    for m in &mods_removed {
+        summary.push(format!("Mod {} {} is removed", m.name, m.version));
+    }
+
+    if from.size_bytes != to.size_bytes {
+        summary.push(format!(
+            "Savefile size changes from {} to {}",
+            format_size(from.size_bytes),
+            format_size(to.size_bytes)
+        ));
+    }
+
+    SaveDiff {
+        mods_added,
+        mods_removed,
+        mods_changed,
+        summary,
+    }
+}
+
+fn format_duration(d: chrono::Duration) -> String {
+    if d.num_days() > 0 {
+        format!("{}d {}h", d.num_days(), d.num_hours() % 24)
+    } else if d.num_hours() > 0 {
+        format!("{}h {}m", d.num_hours(), d.num_minutes() % 60)
+    } else {
+        format!("{}m", d.num_minutes())
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn metadata(name: &str, hour: u32, mods: &[(&str, &str)]) -> SaveMetadata {
+        SaveMetadata {
+            name: name.to_owned(),
+            last_modified: Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap(),
+            size_bytes: 1024 * 1024,
+            mods: mods
+                .iter()
+                .map(|(name, version)| ModObject {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                    enabled: true,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn identical_saves_have_no_differences() {
+        let save = metadata("current", 12, &[("base", "1.1.104"), ("foo", "1.0.0")]);
+        let d = diff(&save, &save);
+        assert!(d.summary.is_empty());
+        assert!(d.mods_added.is_empty() && d.mods_removed.is_empty() && d.mods_changed.is_empty());
+    }
+
+    #[test]
+    fn restoring_older_backup_reports_lost_progress_and_mod_changes() {
+        let current = metadata(
+            "current",
+            14,
+            &[("base", "2.0.8"), ("foo", "1.1.0"), ("bar", "0.1.0")],
+        );
+        let backup = metadata(
+            "backup",
+            12,
+            &[("base", "1.1.104"), ("foo", "1.0.0"), ("baz", "2.0.0")],
+        );
+        let d = diff(&current, &backup);
+
+        assert_eq!(d.mods_added.len(), 1);
+        assert_eq!(d.mods_added[0].name, "baz");
+        assert_eq!(d.mods_removed.len(), 1);
+        assert_eq!(d.mods_removed[0].name, "bar");
+        assert_eq!(d.mods_changed.len(), 2);
+        assert!(d.summary[0].contains("2h 0m before current"));
+        assert!(d
+            .summary
+            .contains(&"Factorio version changes from 2.0.8 to 1.1.104".to_owned()));
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use fctrl::schema::Save;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clients::AgentApiClient,
+    db::{Cf, Db, Record},
+    error::Result,
+};
+
+lazy_static! {
+    static ref SAVEFILE_HASHES_CF: Cf = Cf("savefile_hashes".to_owned());
+}
+
+/// A savefile on the server as it was when its content was hashed
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct HashedSave {
+    name: String,
+    last_modified: DateTime<Utc>,
+}
+
+impl HashedSave {
+    /// Whether the savefile is still on the server unchanged, as it won't be once the server has
+    /// saved over it
+    fn is_current(&self, saves: &[Save]) -> bool {
+        saves.iter().any(|s| {
+            s.name == self.name && s.last_modified == self.last_modified && !s.staged && !s.remote
+        })
+    }
+}
+
+/// SHA-256 of uploaded savefiles, kept in the db by content hash so that uploading a savefile
+/// that is already on the server can be skipped
+pub struct SaveHashes {
+    db: Arc<Db>,
+}
+
+impl SaveHashes {
+    pub fn new(db: Arc<Db>) -> SaveHashes {
+        SaveHashes { db }
+    }
+
+    /// The name of the savefile on the server with the given content, if any
+    pub async fn find(
+        &self,
+        agent_client: &AgentApiClient,
+        sha256: &str,
+    ) -> Result<Option<String>> {
+        let key = sha256.to_lowercase();
+        let hashed: HashedSave = match self.db.read(&SAVEFILE_HASHES_CF, key.clone())? {
+            Some(record) => serde_json::from_str(&record.value)?,
+            None => return Ok(None),
+        };
+        if hashed.is_current(&agent_client.save_list().await?) {
+            return Ok(Some(hashed.name));
+        }
+
+        debug!(
+            "Forgetting hash of savefile {}, which has since changed",
+            hashed.name
+        );
+        self.db.delete(&SAVEFILE_HASHES_CF, &key)?;
+        Ok(None)
+    }
+
+    /// Records the content of a savefile that has just been uploaded. The upload has succeeded
+    /// either way, so failures are only logged.
+    pub async fn record(&self, agent_client: &AgentApiClient, name: &str, sha256: &str) {
+        if let Err(e) = self.try_record(agent_client, name, sha256).await {
+            warn!("Couldn't record hash of savefile {}: {:?}", name, e);
+        }
+    }
+
+    async fn try_record(
+        &self,
+        agent_client: &AgentApiClient,
+        name: &str,
+        sha256: &str,
+    ) -> Result<()> {
+        let save = agent_client
+            .save_list()
+            .await?
+            .into_iter()
+            .find(|s| s.name == name && !s.staged);
+        let hashed = match save {
+            Some(save) => HashedSave {
+                name: save.name,
+                last_modified: save.last_modified,
+            },
+            // a savefile replaced while in use isn't swapped in until the server stops
+            None => return Ok(()),
+        };
+        info!("Recording hash {} of savefile {}", sha256, name);
+        self.db.write(
+            &SAVEFILE_HASHES_CF,
+            &Record {
+                key: sha256.to_lowercase(),
+                value: serde_json::to_string(&hashed)?,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save(name: &str, last_modified: DateTime<Utc>, staged: bool) -> Save {
+        Save {
+            name: name.to_owned(),
+            last_modified,
+            staged,
+            remote: false,
+        }
+    }
+
+    #[test]
+    fn hash_is_stale_once_savefile_changes() {
+        let then = Utc::now();
+        let later = then + chrono::Duration::minutes(5);
+        let hashed = HashedSave {
+            name: "a".to_owned(),
+            last_modified: then,
+        };
+        assert!(hashed.is_current(&[save("b", later, false), save("a", then, false)]));
+        assert!(!hashed.is_current(&[save("a", later, false)]));
+        assert!(!hashed.is_current(&[save("a", then, true)]));
+        assert!(!hashed.is_current(&[save("b", then, false)]));
+    }
+}
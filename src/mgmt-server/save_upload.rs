@@ -0,0 +1,243 @@
+use fctrl::schema::SaveBytes;
+use log::info;
+use rocket::data::ByteUnit;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{
+    clients::AgentApiClient,
+    error::{Error, Result},
+};
+
+/// Size of each part forwarded to the agent
+const SAVEFILE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Streams a savefile upload through to the agent, without buffering the whole file in memory.
+///
+/// The body is either the raw savefile, or a multipart/form-data body with the given boundary,
+/// in which case the first file in it is taken. The agent verifies the checksum of the complete
+/// file before replacing any existing savefile of the same name. Returns the hex-encoded SHA-256
+/// of the savefile.
+///
+/// If a maximum size is given, the upload fails as soon as the body exceeds it, rather than the
+/// savefile being finalised from a truncated body. The body must be read with a limit above the
+/// maximum for this to be detected.
+pub async fn upload(
+    agent_client: &AgentApiClient,
+    name: String,
+    mut body: impl AsyncRead + Unpin,
+    multipart_boundary: Option<&str>,
+    max_size: Option<ByteUnit>,
+) -> Result<String> {
+    let mut multipart = multipart_boundary.map(MultipartFileExtractor::new);
+    let mut received = 0;
+    let mut hasher = Sha256::new();
+    let mut pending = Vec::with_capacity(SAVEFILE_CHUNK_SIZE);
+    let mut offset = 0;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = body.read(&mut buf).await?;
+        let eof = n == 0;
+        received += n as u64;
+        if let Some(max_size) = max_size.filter(|max| received > max.as_u64()) {
+            return Err(Error::PayloadTooLarge(format!(
+                "Savefile exceeds the {} limit",
+                max_size
+            )));
+        }
+        match multipart.as_mut() {
+            Some(multipart) => pending.extend(multipart.push(&buf[..n])),
+            None => pending.extend_from_slice(&buf[..n]),
+        }
+
+        while pending.len() >= SAVEFILE_CHUNK_SIZE || (eof && !pending.is_empty()) {
+            let rest = pending.split_off(pending.len().min(SAVEFILE_CHUNK_SIZE));
+            let chunk = std::mem::replace(&mut pending, rest);
+            hasher.update(&chunk);
+            let chunk_len = chunk.len();
+            let savebytes = SaveBytes {
+                multipart_start: Some(offset),
+                bytes: chunk,
+                sha256: None,
+            };
+            agent_client.save_put(name.clone(), savebytes).await?;
+            offset += chunk_len;
+        }
+
+        if eof {
+            break;
+        }
+    }
+
+    if matches!(&multipart, Some(m) if !m.is_done()) {
+        return Err(Error::BadRequest(
+            "Multipart body ended before the end of the file".to_owned(),
+        ));
+    }
+    if offset == 0 {
+        return Err(Error::BadRequest("Empty savefile".to_owned()));
+    }
+    let sha256 = format!("{:x}", hasher.finalize());
+    let sentinel = SaveBytes::sentinel(offset).with_sha256(Some(sha256.clone()));
+    agent_client.save_put(name.clone(), sentinel).await?;
+    info!("Uploaded savefile {} ({} bytes)", name, offset);
+    Ok(sha256)
+}
+
+/// Streams one range of a savefile uploaded in chunks through to the agent, in parts starting at
+/// `start` within the savefile, so that large chunks aren't buffered whole. Returns the number
+/// of bytes forwarded.
+pub async fn upload_range(
+    agent_client: &AgentApiClient,
+    name: &str,
+    start: usize,
+    mut body: impl AsyncRead + Unpin,
+) -> Result<usize> {
+    let mut part = vec![0; SAVEFILE_CHUNK_SIZE];
+    let mut offset = 0;
+    loop {
+        let mut filled = 0;
+        while filled < part.len() {
+            let n = body.read(&mut part[filled..]).await?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        if filled == 0 {
+            break;
+        }
+
+        let savebytes = SaveBytes {
+            multipart_start: Some(start + offset),
+            bytes: part[..filled].to_vec(),
+            sha256: None,
+        };
+        agent_client.save_put(name.to_owned(), savebytes).await?;
+        offset += filled;
+        if filled < part.len() {
+            break;
+        }
+    }
+    Ok(offset)
+}
+
+#[derive(Debug, PartialEq)]
+enum MultipartState {
+    /// Looking for the start of the next part
+    Boundary,
+    /// Reading the headers of a part
+    Headers,
+    /// Reading the content of the file part
+    Content,
+    /// The file part has ended, anything further is ignored
+    Done,
+}
+
+/// Extracts the content of the first file part of a multipart/form-data body, as the body
+/// arrives in arbitrarily split chunks
+struct MultipartFileExtractor {
+    /// Line break and dashes preceding each boundary
+    delimiter: Vec<u8>,
+    state: MultipartState,
+    buf: Vec<u8>,
+}
+
+impl MultipartFileExtractor {
+    fn new(boundary: &str) -> MultipartFileExtractor {
+        MultipartFileExtractor {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            state: MultipartState::Boundary,
+            // the first boundary has no preceding line break
+            buf: b"\r\n".to_vec(),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == MultipartState::Done
+    }
+
+    /// Takes the next chunk of the body, returning any file content it completes
+    fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.buf.extend_from_slice(chunk);
+        let mut content = vec![];
+        loop {
+            match self.state {
+                MultipartState::Boundary => match find(&self.buf, &self.delimiter) {
+                    Some(i) => {
+                        self.buf.drain(..i + self.delimiter.len());
+                        self.state = MultipartState::Headers;
+                    }
+                    None => {
+                        self.retain_tail();
+                        return content;
+                    }
+                },
+                MultipartState::Headers => match find(&self.buf, b"\r\n\r\n") {
+                    Some(i) => {
+                        let headers = String::from_utf8_lossy(&self.buf[..i]).to_lowercase();
+                        self.buf.drain(..i + 4);
+                        // skip over any other form fields
+                        self.state = match headers.contains("filename=") {
+                            true => MultipartState::Content,
+                            false => MultipartState::Boundary,
+                        };
+                    }
+                    None => return content,
+                },
+                MultipartState::Content => match find(&self.buf, &self.delimiter) {
+                    Some(i) => {
+                        content.extend(self.buf.drain(..i));
+                        self.buf.clear();
+                        self.state = MultipartState::Done;
+                    }
+                    None => {
+                        // hold back anything that could be the start of the delimiter
+                        let keep = (self.delimiter.len() - 1).min(self.buf.len());
+                        content.extend(self.buf.drain(..self.buf.len() - keep));
+                        return content;
+                    }
+                },
+                MultipartState::Done => {
+                    self.buf.clear();
+                    return content;
+                }
+            }
+        }
+    }
+
+    fn retain_tail(&mut self) {
+        let keep = (self.delimiter.len() - 1).min(self.buf.len());
+        self.buf.drain(..self.buf.len() - keep);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_file_from_split_multipart_body() {
+        let body = b"--xyz\r\n\
+            Content-Disposition: form-data; name=\"comment\"\r\n\r\n\
+            not the file\r\n\
+            --xyz\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"save.zip\"\r\n\
+            Content-Type: application/zip\r\n\r\n\
+            PK\x03\x04 \r\n--xy not a boundary\r\n\
+            --xyz--\r\n";
+        for chunk_size in [1, 3, 7, body.len()] {
+            let mut extractor = MultipartFileExtractor::new("xyz");
+            let mut content = vec![];
+            for chunk in body.chunks(chunk_size) {
+                content.extend(extractor.push(chunk));
+            }
+            assert!(extractor.is_done());
+            assert_eq!(content, b"PK\x03\x04 \r\n--xy not a boundary");
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Datelike, DurationRound, Timelike, Utc};
+use fctrl::schema::{JobStatus, MissedRunPolicy, ServerStartSaveFile, ServerStatus};
+use futures::future::BoxFuture;
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clients::AgentApiClient,
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+    feature_flags::{FeatureFlag, FeatureFlags},
+    game_message::{AchievementsPolicy, MessageSource},
+    ha::Leadership,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(20);
+/// How often the scheduler checks for due jobs
+const JOB_TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// Furthest back a missed run is looked for, and furthest ahead the next run is looked for
+const MAX_CATCH_UP_DAYS: i64 = 7;
+const MAX_LOOKAHEAD_DAYS: i64 = 366;
+
+lazy_static! {
+    static ref SCHEDULES_CF: Cf = Cf("schedules".to_owned());
+}
+
+/// A task to run whenever its cron expression matches, persisted in the db
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Schedule {
+    pub id: String,
+    pub cron: String,
+    pub action: ScheduledAction,
+    /// Start of the minute in which this schedule last ran, so it runs at most once per match
+    pub last_run: Option<DateTime<Utc>>,
+    /// Each run is delayed by up to this many seconds, chosen at random
+    #[serde(default)]
+    pub jitter_secs: u64,
+    /// Whether to catch up on a run missed while no instance was running schedules
+    #[serde(default)]
+    pub missed_run: MissedRunPolicy,
+    /// Error from the last run, if it failed
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl Schedule {
+    /// Next time this schedule will run, if within the next year
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        let expr = CronExpression::from_str(&self.cron).ok()?;
+        let now = Utc::now()
+            .duration_trunc(chrono::Duration::minutes(1))
+            .ok()?;
+        (1..=MAX_LOOKAHEAD_DAYS * 24 * 60)
+            .map(|m| now + chrono::Duration::minutes(m))
+            .find(|dt| expr.matches(dt))
+    }
+
+    /// Whether a run between the last run and `now` was missed
+    fn missed_run_since_last(&self, expr: &CronExpression, now: DateTime<Utc>) -> bool {
+        let last_run = match self.last_run {
+            Some(last_run) => last_run.max(now - chrono::Duration::days(MAX_CATCH_UP_DAYS)),
+            None => return false,
+        };
+        let minutes = (now - last_run).num_minutes();
+        (1..minutes)
+            .map(|m| last_run + chrono::Duration::minutes(m))
+            .any(|dt| expr.matches(&dt))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduledAction {
+    /// Stop the server if it is running, then start it again with the given save
+    Restart { savefile: String },
+    /// Update every installed mod to its latest release compatible with the installed version
+    ModUpdate,
+    /// Send a message to all players
+    Broadcast { message: String },
+    /// Regenerate the map preview image of the hosted save
+    MapPreview,
+}
+
+/// When a recurring job of the mgmt-server runs
+pub struct JobDefinition {
+    pub name: &'static str,
+    pub interval: Duration,
+    /// Each run is delayed by up to this long, chosen at random, to spread out load
+    pub jitter: Duration,
+}
+
+type JobFn = Arc<dyn Fn() -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+struct Job {
+    definition: JobDefinition,
+    run: JobFn,
+    running: bool,
+    last_run: Option<DateTime<Utc>>,
+    next_run: DateTime<Utc>,
+    last_error: Option<String>,
+}
+
+/// Runs admin-defined tasks on cron schedules, e.g. a nightly restart or map preview, along with
+/// the recurring jobs of other features, which register a job instead of spawning their own loop.
+///
+/// Only the leader runs schedules and jobs, and schedules only while the scheduler feature flag
+/// is enabled. Any instance can manage schedules as they are kept in the db. Runs missed while no
+/// leader was running schedules are caught up once if the schedule's missed run policy asks for
+/// it. A job never overlaps with itself, and its next run is an interval after the last finished.
+pub struct Scheduler {
+    agent_client: Arc<AgentApiClient>,
+    db: Arc<Db>,
+    achievements_policy: AchievementsPolicy,
+    jobs: Mutex<Vec<Job>>,
+}
+
+impl Scheduler {
+    pub fn start(
+        agent_client: Arc<AgentApiClient>,
+        db: Arc<Db>,
+        achievements_policy: AchievementsPolicy,
+        feature_flags: Arc<FeatureFlags>,
+        leadership: Leadership,
+    ) -> Arc<Scheduler> {
+        let scheduler = Arc::new(Scheduler {
+            agent_client,
+            db,
+            achievements_policy,
+            jobs: Mutex::new(vec![]),
+        });
+
+        let scheduler_clone = Arc::clone(&scheduler);
+        let leadership_clone = leadership.clone();
+        tokio::spawn(async move {
+            loop {
+                if leadership_clone.is_leader() && feature_flags.is_enabled(FeatureFlag::Scheduler)
+                {
+                    if let Err(e) = scheduler_clone.run_due().await {
+                        error!("Error running scheduled tasks: {:?}", e);
+                    }
+                }
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+        });
+
+        let scheduler_clone = Arc::clone(&scheduler);
+        tokio::spawn(async move {
+            loop {
+                if leadership.is_leader() {
+                    scheduler_clone.run_due_jobs();
+                }
+                tokio::time::sleep(JOB_TICK_INTERVAL).await;
+            }
+        });
+
+        scheduler
+    }
+
+    /// Adds a recurring job, first run an interval from now
+    pub fn register(
+        &self,
+        definition: JobDefinition,
+        run: impl Fn() -> BoxFuture<'static, Result<()>> + Send + Sync + 'static,
+    ) {
+        let next_run = Utc::now() + with_jitter(definition.interval, definition.jitter);
+        info!(
+            "Registered job {}, next run at {}",
+            definition.name, next_run
+        );
+        self.jobs.lock().unwrap().push(Job {
+            definition,
+            run: Arc::new(run),
+            running: false,
+            last_run: None,
+            next_run,
+            last_error: None,
+        });
+    }
+
+    pub fn jobs(&self) -> Vec<JobStatus> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|job| JobStatus {
+                name: job.definition.name.to_owned(),
+                interval_secs: job.definition.interval.as_secs(),
+                jitter_secs: job.definition.jitter.as_secs(),
+                missed_run: MissedRunPolicy::Skip,
+                last_run: job.last_run,
+                next_run: job.next_run,
+                last_error: job.last_error.clone(),
+            })
+            .collect()
+    }
+
+    pub fn list(&self) -> Result<Vec<Schedule>> {
+        self.db
+            .read_prefix(&SCHEDULES_CF, "")?
+            .into_iter()
+            .map(|r| Ok(serde_json::from_str(&r.value)?))
+            .collect()
+    }
+
+    pub fn create(
+        &self,
+        cron: String,
+        action: ScheduledAction,
+        jitter_secs: u64,
+        missed_run: MissedRunPolicy,
+    ) -> Result<Schedule> {
+        CronExpression::from_str(&cron)?;
+        let schedule = Schedule {
+            id: uuid::Uuid::new_v4().to_string(),
+            cron,
+            action,
+            last_run: None,
+            jitter_secs,
+            missed_run,
+            last_error: None,
+        };
+        self.write(&schedule)?;
+        info!("Created schedule {} ({})", schedule.id, schedule.cron);
+        Ok(schedule)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<()> {
+        if self.db.read(&SCHEDULES_CF, id.to_owned())?.is_none() {
+            return Err(Error::ScheduleNotFound);
+        }
+        self.db.delete(&SCHEDULES_CF, id)
+    }
+
+    fn write(&self, schedule: &Schedule) -> Result<()> {
+        self.db.write(
+            &SCHEDULES_CF,
+            &Record {
+                key: schedule.id.clone(),
+                value: serde_json::to_string(schedule)?,
+            },
+        )
+    }
+
+    async fn run_due(self: &Arc<Self>) -> Result<()> {
+        let now = Utc::now()
+            .duration_trunc(chrono::Duration::minutes(1))
+            .unwrap_or_else(|_| Utc::now());
+        for mut schedule in self.list()? {
+            if schedule.last_run == Some(now) {
+                continue;
+            }
+            let expr = match CronExpression::from_str(&schedule.cron) {
+                Ok(expr) => expr,
+                Err(e) => {
+                    warn!(
+                        "Skipping schedule {} with invalid cron: {:?}",
+                        schedule.id, e
+                    );
+                    continue;
+                }
+            };
+            let missed = schedule.missed_run == MissedRunPolicy::RunOnce
+                && schedule.missed_run_since_last(&expr, now);
+            if !expr.matches(&now) && !missed {
+                continue;
+            }
+            if missed {
+                info!("Catching up missed run of schedule {}", schedule.id);
+            }
+
+            // record the run first, so a failing task isn't retried every check
+            schedule.last_run = Some(now);
+            self.write(&schedule)?;
+            let delay = match schedule.jitter_secs {
+                0 => Duration::ZERO,
+                max => Duration::from_secs(rand::thread_rng().gen_range(0..=max)),
+            };
+            let scheduler = Arc::clone(self);
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                info!("Running schedule {}: {:?}", schedule.id, schedule.action);
+                let result = scheduler.run(&schedule.action).await;
+                if let Err(e) = &result {
+                    error!("Scheduled task {} failed: {:?}", schedule.id, e);
+                }
+                if let Err(e) = scheduler.record_result(&schedule.id, result) {
+                    error!(
+                        "Couldn't record result of schedule {}: {:?}",
+                        schedule.id, e
+                    );
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn run_due_jobs(self: &Arc<Self>) {
+        let now = Utc::now();
+        let mut jobs = self.jobs.lock().unwrap();
+        for (i, job) in jobs.iter_mut().enumerate() {
+            if job.running || job.next_run > now {
+                continue;
+            }
+            job.running = true;
+            let run = Arc::clone(&job.run);
+            let scheduler = Arc::clone(self);
+            tokio::spawn(async move {
+                let result = run().await;
+                scheduler.finish_job(i, result);
+            });
+        }
+    }
+
+    fn finish_job(&self, i: usize, result: Result<()>) {
+        let now = Utc::now();
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = &mut jobs[i];
+        if let Err(e) = &result {
+            error!("Job {} failed: {:?}", job.definition.name, e);
+        }
+        job.running = false;
+        job.last_run = Some(now);
+        job.last_error = result.err().map(|e| format!("{:?}", e));
+        job.next_run = now + with_jitter(job.definition.interval, job.definition.jitter);
+    }
+
+    fn record_result(&self, id: &str, result: Result<()>) -> Result<()> {
+        // re-read, as the schedule may have been deleted while running
+        let record = match self.db.read(&SCHEDULES_CF, id.to_owned())? {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+        let mut schedule: Schedule = serde_json::from_str(&record.value)?;
+        schedule.last_error = result.err().map(|e| format!("{:?}", e));
+        self.write(&schedule)
+    }
+
+    async fn run(&self, action: &ScheduledAction) -> Result<()> {
+        match action {
+            ScheduledAction::Restart { savefile } => {
+                if !matches!(
+                    self.agent_client.server_status().await?,
+                    ServerStatus::NotRunning
+                ) {
+                    self.agent_client.server_stop().await?;
+                }
+                self.agent_client
+                    .server_start(ServerStartSaveFile::Specific(savefile.clone()), None, None)
+                    .await
+            }
+            ScheduledAction::ModUpdate => {
+                // the outcome of the mod update is recorded in the operation history
+                let (id, _sub) = self.agent_client.mod_list_update_all().await?;
+                info!("Scheduled mod update started as operation {}", id.0);
+                Ok(())
+            }
+            ScheduledAction::MapPreview => {
+                // the outcome is recorded in the operation history
+                let (id, _sub) = self.agent_client.map_preview_generate().await?;
+                info!("Scheduled map preview started as operation {}", id.0);
+                Ok(())
+            }
+            ScheduledAction::Broadcast { message } => {
+                let command = self
+                    .achievements_policy
+                    .broadcast_command(MessageSource::Announcement, message);
+                self.agent_client.rcon_command(command).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn with_jitter(interval: Duration, jitter: Duration) -> chrono::Duration {
+    let jitter = match jitter.as_millis() {
+        0 => Duration::ZERO,
+        max => Duration::from_millis(rand::thread_rng().gen_range(0..=max as u64)),
+    };
+    chrono::Duration::from_std(interval + jitter).unwrap_or_default()
+}
+
+/// Standard 5-field cron expression: minute, hour, day of month, month, day of week.
+///
+/// Each field supports `*`, single values, ranges `a-b`, steps `*/n` or `a-b/n`, and
+/// comma-separated lists of these. Times are evaluated in UTC.
+#[derive(Debug, PartialEq)]
+pub struct CronExpression {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// If both day fields are restricted, a time matches if either of them matches
+    day_fields_restricted: bool,
+}
+
+impl FromStr for CronExpression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<_> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(Error::BadRequest(format!(
+                "Cron expression '{}' must have 5 fields",
+                s
+            )));
+        }
+        let days_of_week = parse_cron_field(fields[4], 0, 7)?;
+        Ok(CronExpression {
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days_of_month: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            // 7 is an alias for Sunday
+            days_of_week: (days_of_week | (days_of_week >> 7)) & 0x7f,
+            day_fields_restricted: fields[2] != "*" && fields[4] != "*",
+        })
+    }
+}
+
+impl CronExpression {
+    pub fn matches(&self, dt: &DateTime<Utc>) -> bool {
+        let bit = |mask: u64, n: u32| mask & (1 << n) != 0;
+        let dom = bit(self.days_of_month, dt.day());
+        let dow = bit(self.days_of_week, dt.weekday().num_days_from_sunday());
+        let day = if self.day_fields_restricted {
+            dom || dow
+        } else {
+            dom && dow
+        };
+        bit(self.minutes, dt.minute())
+            && bit(self.hours, dt.hour())
+            && bit(self.months, dt.month())
+            && day
+    }
+}
+
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let invalid = || Error::BadRequest(format!("Invalid cron field '{}'", field));
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                start.parse().map_err(|_| invalid())?,
+                end.parse().map_err(|_| invalid())?,
+            )
+        } else {
+            let value = range.parse().map_err(|_| invalid())?;
+            // a single value with a step means "from this value onwards"
+            (value, if part.contains('/') { max } else { value })
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for n in (start..=end).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn cron_matches_nightly_and_weekly() {
+        let nightly = CronExpression::from_str("30 4 * * *").unwrap();
+        assert!(nightly.matches(&Utc.with_ymd_and_hms(2024, 3, 5, 4, 30, 0).unwrap()));
+        assert!(!nightly.matches(&Utc.with_ymd_and_hms(2024, 3, 5, 4, 31, 0).unwrap()));
+
+        // 2024-03-03 is a Sunday
+        let weekly = CronExpression::from_str("0 12 * * 7").unwrap();
+        assert!(weekly.matches(&Utc.with_ymd_and_hms(2024, 3, 3, 12, 0, 0).unwrap()));
+        assert!(!weekly.matches(&Utc.with_ymd_and_hms(2024, 3, 4, 12, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn cron_supports_lists_ranges_and_steps() {
+        let expr = CronExpression::from_str("*/15 9-17/4 1,15 * *").unwrap();
+        assert_eq!(expr.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(expr.hours, 1 << 9 | 1 << 13 | 1 << 17);
+        assert_eq!(expr.days_of_month, 1 << 1 | 1 << 15);
+    }
+
+    #[test]
+    fn finds_runs_missed_since_last_run() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 5, 6, 0, 0).unwrap();
+        let mut schedule = Schedule {
+            id: "test".to_owned(),
+            cron: "30 4 * * *".to_owned(),
+            action: ScheduledAction::MapPreview,
+            last_run: None,
+            jitter_secs: 0,
+            missed_run: MissedRunPolicy::RunOnce,
+            last_error: None,
+        };
+        let expr = CronExpression::from_str(&schedule.cron).unwrap();
+        assert!(!schedule.missed_run_since_last(&expr, now));
+
+        schedule.last_run = Some(Utc.with_ymd_and_hms(2024, 3, 4, 4, 30, 0).unwrap());
+        assert!(schedule.missed_run_since_last(&expr, now));
+
+        schedule.last_run = Some(Utc.with_ymd_and_hms(2024, 3, 5, 4, 30, 0).unwrap());
+        assert!(!schedule.missed_run_since_last(&expr, now));
+    }
+
+    #[test]
+    fn cron_rejects_invalid_expressions() {
+        assert!(CronExpression::from_str("* * * *").is_err());
+        assert!(CronExpression::from_str("60 * * * *").is_err());
+        assert!(CronExpression::from_str("*/0 * * * *").is_err());
+        assert!(CronExpression::from_str("5-1 * * * *").is_err());
+        assert!(CronExpression::from_str("a * * * *").is_err());
+    }
+}
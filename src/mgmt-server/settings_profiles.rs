@@ -0,0 +1,202 @@
+use std::{str::FromStr, sync::Arc};
+
+use fctrl::schema::{
+    ServerSettingsConfig, ServerStartSaveFile, ServerStatus, ServerVisibilityConfig,
+};
+use lazy_static::lazy_static;
+use log::info;
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, EnumIter, EnumString};
+
+use crate::{
+    clients::AgentApiClient,
+    db::{Cf, Db, Record},
+    error::{Error, Result},
+};
+
+lazy_static! {
+    static ref SETTINGS_PROFILES_CF: Cf = Cf("settings_profiles".to_owned());
+}
+
+const ACTIVE_KEY: &str = "active";
+
+/// Which audience the server is set up for
+#[derive(AsRefStr, Clone, Copy, Debug, EnumIter, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum SettingsProfileMode {
+    /// Listed on the public server browser, e.g. for events
+    Public,
+    /// Unlisted or LAN only, for private play
+    Private,
+}
+
+/// The server settings that differ between public and private play. Everything else in the
+/// server settings is shared between profiles.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SettingsProfile {
+    /// Appended to the server name while this profile is active
+    pub name_suffix: String,
+    pub game_password: String,
+    pub visibility: ServerVisibilityConfig,
+    pub require_user_verification: bool,
+}
+
+/// Stored public and private variants of the server settings, and switching between them
+pub struct SettingsProfiles {
+    agent_client: Arc<AgentApiClient>,
+    db: Arc<Db>,
+}
+
+impl SettingsProfiles {
+    pub fn new(agent_client: Arc<AgentApiClient>, db: Arc<Db>) -> SettingsProfiles {
+        SettingsProfiles { agent_client, db }
+    }
+
+    pub fn get(&self, mode: SettingsProfileMode) -> Result<Option<SettingsProfile>> {
+        match self
+            .db
+            .read(&SETTINGS_PROFILES_CF, mode.as_ref().to_owned())?
+        {
+            Some(record) => Ok(Some(serde_json::from_str(&record.value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set(&self, mode: SettingsProfileMode, profile: &SettingsProfile) -> Result<()> {
+        self.db.write(
+            &SETTINGS_PROFILES_CF,
+            &Record {
+                key: mode.as_ref().to_owned(),
+                value: serde_json::to_string(profile)?,
+            },
+        )
+    }
+
+    /// The profile last switched to, if any
+    pub fn active(&self) -> Result<Option<SettingsProfileMode>> {
+        Ok(self
+            .db
+            .read(&SETTINGS_PROFILES_CF, ACTIVE_KEY.to_owned())?
+            .and_then(|r| SettingsProfileMode::from_str(&r.value).ok()))
+    }
+
+    /// Applies the profile to the server settings, then restarts the server with its latest save
+    /// if it is running so the change takes effect.
+    ///
+    /// Public listing requires a factorio.com token in the stored secrets, which the agent adds to
+    /// the server settings when the server starts.
+    pub async fn switch(&self, mode: SettingsProfileMode) -> Result<()> {
+        let profile = self.get(mode)?.ok_or(Error::SettingsProfileNotFound)?;
+        let previous_suffix = match self.active()? {
+            Some(previous) => self.get(previous)?.map(|p| p.name_suffix),
+            None => None,
+        };
+
+        if profile.visibility.public {
+            let secrets = self.agent_client.config_secrets_get().await?;
+            if secrets.token.is_none() {
+                return Err(Error::BadRequest(
+                    "Public listing requires a factorio.com token to be set in secrets".to_owned(),
+                ));
+            }
+        }
+
+        let mut config = self.agent_client.config_server_settings_get().await?;
+        apply_profile(&mut config, &profile, previous_suffix.as_deref());
+        self.agent_client.config_server_settings_set(config).await?;
+        self.db.write(
+            &SETTINGS_PROFILES_CF,
+            &Record {
+                key: ACTIVE_KEY.to_owned(),
+                value: mode.as_ref().to_owned(),
+            },
+        )?;
+        info!("Switched to {} settings profile", mode.as_ref());
+
+        if !matches!(
+            self.agent_client.server_status().await?,
+            ServerStatus::NotRunning
+        ) {
+            info!("Restarting server to apply settings profile");
+            self.agent_client.server_stop().await?;
+            // the hosted save is written on stop, so it is the most recently modified
+            let hosted_save = self
+                .agent_client
+                .save_list()
+                .await?
+                .into_iter()
+                .filter(|s| !s.remote)
+                .max_by_key(|s| s.last_modified)
+                .ok_or(Error::SaveNotFound)?;
+            self.agent_client
+                .server_start(ServerStartSaveFile::Specific(hosted_save.name), None, None)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn apply_profile(
+    config: &mut ServerSettingsConfig,
+    profile: &SettingsProfile,
+    previous_suffix: Option<&str>,
+) {
+    let base_name = match previous_suffix {
+        Some(suffix) if !suffix.is_empty() => config
+            .name
+            .strip_suffix(suffix)
+            .unwrap_or(&config.name)
+            .to_owned(),
+        _ => config.name.clone(),
+    };
+    config.name = format!("{}{}", base_name, profile.name_suffix);
+    config.game_password = profile.game_password.clone();
+    config.visibility = profile.visibility.clone();
+    config.require_user_verification = profile.require_user_verification;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn config(name: &str) -> ServerSettingsConfig {
+        let mut config = test_util::server_settings();
+        config.name = name.to_owned();
+        config
+    }
+
+    #[test]
+    fn switching_profiles_replaces_name_suffix() {
+        let public = SettingsProfile {
+            name_suffix: " [Event]".to_owned(),
+            game_password: "".to_owned(),
+            visibility: ServerVisibilityConfig {
+                public: true,
+                lan: true,
+            },
+            require_user_verification: true,
+        };
+        let private = SettingsProfile {
+            name_suffix: "".to_owned(),
+            game_password: "friends".to_owned(),
+            visibility: ServerVisibilityConfig {
+                public: false,
+                lan: true,
+            },
+            require_user_verification: false,
+        };
+
+        let mut c = config("My Factory");
+        apply_profile(&mut c, &public, None);
+        assert_eq!(c.name, "My Factory [Event]");
+        assert!(c.visibility.public);
+        assert_eq!(c.game_password, "");
+
+        apply_profile(&mut c, &private, Some(&public.name_suffix));
+        assert_eq!(c.name, "My Factory");
+        assert!(!c.visibility.public);
+        assert_eq!(c.game_password, "friends");
+    }
+}
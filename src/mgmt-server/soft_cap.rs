@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use futures::{pin_mut, StreamExt};
+use log::{error, info, warn};
+
+use crate::{
+    clients::AgentApiClient,
+    connection_quality::PlayerSessionTracker,
+    discord::DiscordClient,
+    events::{broker::EventBroker, TopicName, JOIN_TOPIC_NAME},
+    game_message::GameMessage,
+    ha::Leadership,
+};
+
+/// Soft player cap, e.g. for community events where a hard rejection by Factorio's `max_players`
+/// is too blunt.
+///
+/// Players joining beyond `cap` are let in, but admins are notified in-game and on Discord so that
+/// they can decide what to do.
+pub struct SoftPlayerCap {
+    cap: usize,
+}
+
+impl SoftPlayerCap {
+    pub fn new(cap: usize) -> SoftPlayerCap {
+        SoftPlayerCap { cap }
+    }
+
+    /// Players online once the given player has joined, if this puts the server over the cap. The
+    /// session tracker may not have recorded the join yet, so the player is added if missing.
+    fn over_cap(&self, mut online: Vec<String>, player: &str) -> Option<Vec<String>> {
+        if !online.iter().any(|p| p == player) {
+            online.push(player.to_owned());
+        }
+        if online.len() > self.cap {
+            Some(online)
+        } else {
+            None
+        }
+    }
+
+    pub async fn start(
+        self,
+        agent_client: Arc<AgentApiClient>,
+        event_broker: Arc<EventBroker>,
+        player_sessions: PlayerSessionTracker,
+        discord: Arc<Option<DiscordClient>>,
+        leadership: Leadership,
+    ) {
+        info!("Notifying admins of joins beyond {} players", self.cap);
+        let join_sub = event_broker
+            .subscribe(TopicName::new(JOIN_TOPIC_NAME), |_| true)
+            .await;
+        tokio::spawn(async move {
+            pin_mut!(join_sub);
+            while let Some(event) = join_sub.next().await {
+                if !leadership.is_leader() {
+                    continue;
+                }
+                let player = event.tags.get(&TopicName::new(JOIN_TOPIC_NAME)).unwrap();
+                let online = player_sessions
+                    .sessions()
+                    .await
+                    .into_iter()
+                    .map(|s| s.name)
+                    .collect();
+                if let Some(online) = self.over_cap(online, player) {
+                    self.notify_admins(&agent_client, &discord, player, &online)
+                        .await;
+                }
+            }
+
+            error!("soft player cap subscriber task is finishing - this should never happen!");
+        });
+    }
+
+    async fn notify_admins(
+        &self,
+        agent_client: &AgentApiClient,
+        discord: &Option<DiscordClient>,
+        player: &str,
+        online: &[String],
+    ) {
+        let alert_msg = format!(
+            "Player {} joined beyond the soft cap of {} players, {} are now online",
+            player,
+            self.cap,
+            online.len()
+        );
+        warn!("{}", alert_msg);
+
+        let message = GameMessage::SoftCapExceeded {
+            player,
+            online: online.len(),
+            cap: self.cap,
+        }
+        .render();
+        if let Err(e) = agent_client.rcon_whisper_admins(online, message).await {
+            error!("Couldn't notify admins in-game of soft cap: {:?}", e);
+        }
+
+        if let Some(discord) = discord.as_ref() {
+            if let Err(e) = discord.oneshot_alert(None, alert_msg) {
+                error!("Couldn't send soft cap alert: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn joins_beyond_cap_are_reported() {
+        let cap = SoftPlayerCap::new(2);
+        assert_eq!(cap.over_cap(names(&["a"]), "b"), None);
+        assert_eq!(cap.over_cap(names(&["a", "b"]), "b"), None);
+        assert_eq!(
+            cap.over_cap(names(&["a", "b"]), "c"),
+            Some(names(&["a", "b", "c"]))
+        );
+        assert_eq!(
+            cap.over_cap(names(&["a", "b", "c"]), "c"),
+            Some(names(&["a", "b", "c"]))
+        );
+    }
+}
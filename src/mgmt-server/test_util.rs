@@ -0,0 +1,29 @@
+use fctrl::schema::ServerSettingsConfig;
+
+/// Server settings as generated by the game, for tests to adjust as needed
+pub fn server_settings() -> ServerSettingsConfig {
+    serde_json::from_value(serde_json::json!({
+        "name": "",
+        "description": "",
+        "tags": [],
+        "visibility": { "public": false, "lan": true },
+        "autosave_interval": 10,
+        "autosave_only_on_server": true,
+        "non_blocking_saving": false,
+        "game_password": "",
+        "require_user_verification": false,
+        "max_players": 0,
+        "ignore_player_limit_for_returning_players": false,
+        "allow_commands": "admins-only",
+        "only_admins_can_pause_the_game": true,
+        "max_upload_in_kilobytes_per_second": 0,
+        "max_upload_slots": 5,
+        "minimum_latency_in_ticks": 0,
+        "max_heartbeats_per_second": 60,
+        "minimum_segment_size": 25,
+        "minimum_segment_size_peer_count": 20,
+        "maximum_segment_size": 100,
+        "maximum_segment_size_peer_count": 10,
+    }))
+    .unwrap()
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::{pin_mut, StreamExt};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clients::AgentApiClient,
+    db::{Cf, Db, Record},
+    error::Result,
+    events::{broker::EventBroker, TopicName, JOIN_TOPIC_NAME},
+    game_message::GameMessage,
+    ha::Leadership,
+};
+
+lazy_static! {
+    static ref PLAYERS_CF: Cf = Cf("players".to_owned());
+}
+
+/// Stored for every player who has joined the server
+#[derive(Debug, Deserialize, Serialize)]
+struct PlayerRecord {
+    first_join: DateTime<Utc>,
+}
+
+/// Whispers a welcome to each player the first time they ever join, followed by the configured
+/// banner lines, e.g. server rules, and an invite to the Discord server.
+///
+/// Players are remembered from when this is first enabled, so everyone is welcomed once at that
+/// point, including existing players.
+pub struct WelcomeMessage {
+    banner: Vec<String>,
+    discord_invite: Option<String>,
+}
+
+impl WelcomeMessage {
+    pub fn new(banner: Vec<String>, discord_invite: Option<String>) -> WelcomeMessage {
+        WelcomeMessage {
+            banner,
+            discord_invite,
+        }
+    }
+
+    /// Reads the banner from the text file in WELCOME_BANNER_FILE, one whisper per non-empty line,
+    /// and the invite link from WELCOME_DISCORD_INVITE. Returns None if neither is set.
+    pub fn from_env() -> Result<Option<WelcomeMessage>> {
+        let banner = match std::env::var("WELCOME_BANNER_FILE") {
+            Ok(path) => parse_banner(&std::fs::read_to_string(path)?),
+            Err(_) => vec![],
+        };
+        let discord_invite = std::env::var("WELCOME_DISCORD_INVITE").ok();
+        if banner.is_empty() && discord_invite.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(WelcomeMessage::new(banner, discord_invite)))
+    }
+
+    pub async fn start(
+        self,
+        agent_client: Arc<AgentApiClient>,
+        db: Arc<Db>,
+        event_broker: Arc<EventBroker>,
+        leadership: Leadership,
+    ) {
+        let join_sub = event_broker
+            .subscribe(TopicName::new(JOIN_TOPIC_NAME), |_| true)
+            .await;
+        tokio::spawn(async move {
+            pin_mut!(join_sub);
+            while let Some(event) = join_sub.next().await {
+                let player = event.tags.get(&TopicName::new(JOIN_TOPIC_NAME)).unwrap();
+                // every replica records joins, so that a new leader doesn't welcome anyone twice
+                let first_join = match record_join(&db, player, event.timestamp) {
+                    Ok(first_join) => first_join,
+                    Err(e) => {
+                        error!("Couldn't record join of player {}: {:?}", player, e);
+                        continue;
+                    }
+                };
+                if !first_join || !leadership.is_leader() {
+                    continue;
+                }
+
+                info!("Welcoming new player {}", player);
+                for line in self.lines(player) {
+                    if let Err(e) = agent_client.rcon_whisper(vec![player.clone()], line).await {
+                        error!("Couldn't welcome player {} via RCON: {:?}", player, e);
+                        break;
+                    }
+                }
+            }
+
+            error!("welcome subscriber task is finishing - this should never happen!");
+        });
+    }
+
+    fn lines(&self, player: &str) -> Vec<String> {
+        let mut lines = vec![GameMessage::Welcome { player }.render()];
+        lines.extend(self.banner.iter().cloned());
+        if let Some(url) = &self.discord_invite {
+            lines.push(GameMessage::DiscordInvite { url }.render());
+        }
+        lines
+    }
+}
+
+/// Records that the player joined, returning whether it is their first ever join
+fn record_join(db: &Db, player: &str, timestamp: DateTime<Utc>) -> Result<bool> {
+    if db.read(&PLAYERS_CF, player.to_owned())?.is_some() {
+        return Ok(false);
+    }
+    db.write(
+        &PLAYERS_CF,
+        &Record {
+            key: player.to_owned(),
+            value: serde_json::to_string(&PlayerRecord {
+                first_join: timestamp,
+            })?,
+        },
+    )?;
+    Ok(true)
+}
+
+fn parse_banner(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|l| l.trim_end())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banner_skips_blank_lines() {
+        assert_eq!(
+            parse_banner("Rules:\n\n1. Be nice  \n2. No griefing\n"),
+            vec!["Rules:", "1. Be nice", "2. No griefing"]
+        );
+    }
+}
@@ -7,7 +7,13 @@ use tokio::{
     net::{TcpListener, TcpStream},
     sync::{mpsc, oneshot, Mutex, MutexGuard},
 };
-use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tokio_tungstenite::{
+    tungstenite::{
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message,
+    },
+    WebSocketStream,
+};
 
 use crate::{error::Result, events::Event};
 
@@ -16,16 +22,23 @@ type DynamicStreamsHashMap = HashMap<String, oneshot::Sender<(String, WebSocketS
 pub struct WebSocketServer {
     pub port: u16,
     pub use_wss: bool,
+    /// Hostname to advertise in WebSocket URLs instead of the one the client used, if set
+    pub advertised_host: Option<String>,
     dynamic_streams_waiting: Arc<Mutex<DynamicStreamsHashMap>>,
 }
 
 impl WebSocketServer {
-    pub async fn new(bind_addr: SocketAddr, use_wss: bool) -> Result<Arc<WebSocketServer>> {
+    pub async fn new(
+        bind_addr: SocketAddr,
+        use_wss: bool,
+        advertised_host: Option<String>,
+    ) -> Result<Arc<WebSocketServer>> {
         let tcp_listener = TcpListener::bind(bind_addr).await?;
 
         let server = Arc::new(WebSocketServer {
             port: bind_addr.port(),
             use_wss,
+            advertised_host,
             dynamic_streams_waiting: Arc::new(Mutex::new(HashMap::new())),
         });
 
@@ -56,116 +69,250 @@ impl WebSocketServer {
         stream: impl Stream<Item = Event> + Unpin + Send,
         unconnected_timeout: Duration,
     ) {
-        let (tx, rx) = oneshot::channel();
+        if let Some((remote_addr, ws)) = self.wait_for_peer(&path, unconnected_timeout).await {
+            debug!("WebSocket peer {} connected to path {}", remote_addr, path);
+            let (mut ws_tx, mut ws_rx) = ws.split();
 
-        {
-            let mut mg = self.dynamic_streams_waiting.lock().await;
-            mg.insert(path.clone(), tx);
-        }
+            // 1 hour for inactivity timeout, even if client is connected
+            let (activity_tx, mut activity_rx) = mpsc::unbounded_channel();
+            let path_clone = path.clone();
+            let inactivity_task = tokio::spawn(async move {
+                let inactivity_timeout = Duration::from_secs(60 * 60);
+                let mut break_from_inactivity = true;
+                while let Ok(activity_opt) =
+                    tokio::time::timeout(inactivity_timeout, activity_rx.recv()).await
+                {
+                    if activity_opt.is_none() {
+                        // All senders dropped. Break here to avoid infinite loop eating CPU
+                        break_from_inactivity = false;
+                        break;
+                    }
+                }
+                if break_from_inactivity {
+                    info!(
+                        "WebSocket stream at {} timing out from inactivity after {} seconds",
+                        path_clone,
+                        inactivity_timeout.as_secs()
+                    );
+                }
+            });
 
-        match tokio::time::timeout(unconnected_timeout, rx).await {
-            Ok(res) => {
-                if let Ok((remote_addr, ws)) = res {
-                    debug!("WebSocket peer {} connected to path {}", remote_addr, path);
-                    let (mut ws_tx, mut ws_rx) = ws.split();
-
-                    // 1 hour for inactivity timeout, even if client is connected
-                    let (activity_tx, mut activity_rx) = mpsc::unbounded_channel();
-                    let path_clone = path.clone();
-                    let inactivity_task = tokio::spawn(async move {
-                        let inactivity_timeout = Duration::from_secs(60 * 60);
-                        let mut break_from_inactivity = true;
-                        while let Ok(activity_opt) =
-                            tokio::time::timeout(inactivity_timeout, activity_rx.recv()).await
-                        {
-                            if activity_opt.is_none() {
-                                // All senders dropped. Break here to avoid infinite loop eating CPU
-                                break_from_inactivity = false;
-                                break;
-                            }
+            // Abstract ws_tx with a channel to avoid locking
+            let path_clone = path.clone();
+            let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                while let Some(msg) = outgoing_rx.recv().await {
+                    if let Err(e) = activity_tx.send(ActivitySignal::Activity) {
+                        warn!("Error indicating websocket activity: {:?}", e);
+                    }
+                    debug!(
+                        "Sending message to WebSocket peer {} at path {}: {}",
+                        remote_addr, path_clone, msg
+                    );
+                    if let Err(e) = ws_tx.send(msg).await {
+                        error!(
+                            "Error sending message to WebSocket peer {} at path {}: {:?}",
+                            remote_addr, path_clone, e
+                        );
+                    }
+                }
+
+                debug!(
+                    "Closing WebSocket connection to peer {} at path {}",
+                    remote_addr, path_clone
+                );
+                let _ = ws_tx.send(Message::Close(None)).await;
+                let _ = ws_tx.close().await;
+            });
+
+            // Forward messages from stream to outgoing channel
+            let outgoing_tx_clone = outgoing_tx.clone();
+            pin_mut!(stream);
+            let forward_fut = stream.for_each(|e| {
+                let msg = Message::Text(e.content.into());
+                let _ = outgoing_tx_clone.send(msg);
+                future::ready(())
+            });
+
+            // Handle incoming messages
+            let handle_incoming_task = tokio::spawn(async move {
+                while let Some(Ok(msg)) = ws_rx.next().await {
+                    match msg {
+                        Message::Text(_) | Message::Binary(_) | Message::Pong(_) | Message::Frame(_) => {
+                            // ignore
                         }
-                        if break_from_inactivity {
-                            info!(
-                                "WebSocket stream at {} timing out from inactivity after {} seconds",
-                                path_clone,
-                                inactivity_timeout.as_secs()
-                            );
+                        Message::Ping(_) => {
+                            // tungstenite library handles pings already
                         }
-                    });
-
-                    // Abstract ws_tx with a channel to avoid locking
-                    let path_clone = path.clone();
-                    let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel();
-                    tokio::spawn(async move {
-                        while let Some(msg) = outgoing_rx.recv().await {
-                            if let Err(e) = activity_tx.send(ActivitySignal::Activity) {
-                                warn!("Error indicating websocket activity: {:?}", e);
-                            }
-                            debug!(
-                                "Sending message to WebSocket peer {} at path {}: {}",
-                                remote_addr, path_clone, msg
-                            );
-                            if let Err(e) = ws_tx.send(msg).await {
-                                error!(
-                                    "Error sending message to WebSocket peer {} at path {}: {:?}",
-                                    remote_addr, path_clone, e
-                                );
-                            }
+                        Message::Close(_) => {
+                            break;
                         }
+                    }
+                }
+            });
 
-                        debug!(
-                            "Closing WebSocket connection to peer {} at path {}",
-                            remote_addr, path_clone
-                        );
-                        let _ = ws_tx.send(Message::Close(None)).await;
-                        let _ = ws_tx.close().await;
-                    });
-
-                    // Forward messages from stream to outgoing channel
-                    let outgoing_tx_clone = outgoing_tx.clone();
-                    pin_mut!(stream);
-                    let forward_fut = stream.for_each(|e| {
-                        let msg = Message::Text(e.content.into());
-                        let _ = outgoing_tx_clone.send(msg);
-                        future::ready(())
-                    });
-
-                    // Handle incoming messages
-                    let handle_incoming_task = tokio::spawn(async move {
-                        while let Some(Ok(msg)) = ws_rx.next().await {
-                            match msg {
-                                Message::Text(_) | Message::Binary(_) | Message::Pong(_) | Message::Frame(_) => {
-                                    // ignore
-                                }
-                                Message::Ping(_) => {
-                                    // tungstenite library handles pings already
-                                }
-                                Message::Close(_) => {
-                                    break;
+            // Wait until the forwarded stream is done, client closes connection, or timeout from inactivity.
+            // Eiher way, we are done, close the outgoing channel to close the connection.
+            let futures: Vec<Pin<Box<dyn Future<Output = ()> + Send>>> = vec![
+                Box::pin(forward_fut.then(|_| future::ready(()))),
+                Box::pin(handle_incoming_task.then(|_| future::ready(()))),
+                Box::pin(inactivity_task.then(|_| future::ready(()))),
+            ];
+            future::select_all(futures).await;
+        }
+    }
+
+    /// Serves a request-response WebSocket at the given path. Each text message received from the
+    /// peer is passed to the handler, and the handler's reply is sent back to the peer. If the
+    /// handler fails, the connection is closed with an internal error status and the error as the
+    /// close reason.
+    pub async fn respond_at<F, Fut>(&self, path: String, handler: F, unconnected_timeout: Duration)
+    where
+        F: Fn(String) -> Fut + Send,
+        Fut: Future<Output = Result<String>> + Send,
+    {
+        if let Some((remote_addr, mut ws)) = self.wait_for_peer(&path, unconnected_timeout).await {
+            debug!("WebSocket peer {} connected to path {}", remote_addr, path);
+            // 1 hour for inactivity timeout, even if client is connected
+            let inactivity_timeout = Duration::from_secs(60 * 60);
+            let mut close_frame = None;
+            loop {
+                match tokio::time::timeout(inactivity_timeout, ws.next()).await {
+                    Ok(Some(Ok(Message::Text(request)))) => {
+                        let reply = match handler(request.to_string()).await {
+                            Ok(reply) => reply,
+                            Err(e) => {
+                                // close reasons are limited to 123 bytes
+                                let mut reason = format!("{:?}", e);
+                                while reason.len() > 123 {
+                                    reason.pop();
                                 }
+                                close_frame = Some(CloseFrame {
+                                    code: CloseCode::Error,
+                                    reason: reason.into(),
+                                });
+                                break;
                             }
+                        };
+                        if let Err(e) = ws.send(Message::Text(reply.into())).await {
+                            error!(
+                                "Error sending message to WebSocket peer {} at path {}: {:?}",
+                                remote_addr, path, e
+                            );
+                            break;
                         }
-                    });
-
-                    // Wait until the forwarded stream is done, client closes connection, or timeout from inactivity.
-                    // Eiher way, we are done, close the outgoing channel to close the connection.
-                    let futures: Vec<Pin<Box<dyn Future<Output = ()> + Send>>> = vec![
-                        Box::pin(forward_fut.then(|_| future::ready(()))),
-                        Box::pin(handle_incoming_task.then(|_| future::ready(()))),
-                        Box::pin(inactivity_task.then(|_| future::ready(()))),
-                    ];
-                    future::select_all(futures).await;
+                    }
+                    Ok(Some(Ok(Message::Close(_)))) | Ok(Some(Err(_))) | Ok(None) => {
+                        break;
+                    }
+                    Ok(Some(Ok(_))) => {
+                        // ignore
+                    }
+                    Err(_) => {
+                        info!(
+                            "WebSocket stream at {} timing out from inactivity after {} seconds",
+                            path,
+                            inactivity_timeout.as_secs()
+                        );
+                        break;
+                    }
+                }
+            }
+
+            debug!(
+                "Closing WebSocket connection to peer {} at path {}",
+                remote_addr, path
+            );
+            let _ = ws.close(close_frame).await;
+        }
+    }
+
+    /// Serves an interactive WebSocket at the given path, combining [`Self::stream_at`] and
+    /// [`Self::respond_at`]. Messages from the stream are forwarded to the peer as they arrive,
+    /// and each text message received from the peer is passed to the handler, with the handler's
+    /// reply sent back to the peer in between.
+    pub async fn interact_at<F, Fut>(
+        &self,
+        path: String,
+        stream: impl Stream<Item = String> + Unpin + Send,
+        handler: F,
+        unconnected_timeout: Duration,
+    ) where
+        F: Fn(String) -> Fut + Send,
+        Fut: Future<Output = String> + Send,
+    {
+        if let Some((remote_addr, mut ws)) = self.wait_for_peer(&path, unconnected_timeout).await {
+            debug!("WebSocket peer {} connected to path {}", remote_addr, path);
+            pin_mut!(stream);
+            // 1 hour for inactivity timeout, even if client is connected
+            let inactivity_timeout = Duration::from_secs(60 * 60);
+            let inactivity = tokio::time::sleep(inactivity_timeout);
+            pin_mut!(inactivity);
+            loop {
+                let reply = tokio::select! {
+                    msg = stream.next() => match msg {
+                        Some(msg) => msg,
+                        None => break,
+                    },
+                    incoming = ws.next() => match incoming {
+                        Some(Ok(Message::Text(request))) => handler(request.to_string()).await,
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => continue,
+                    },
+                    _ = &mut inactivity => {
+                        info!(
+                            "WebSocket stream at {} timing out from inactivity after {} seconds",
+                            path,
+                            inactivity_timeout.as_secs()
+                        );
+                        break;
+                    }
+                };
+                if let Err(e) = ws.send(Message::Text(reply.into())).await {
+                    error!(
+                        "Error sending message to WebSocket peer {} at path {}: {:?}",
+                        remote_addr, path, e
+                    );
+                    break;
                 }
+                inactivity
+                    .as_mut()
+                    .reset(tokio::time::Instant::now() + inactivity_timeout);
             }
+
+            debug!(
+                "Closing WebSocket connection to peer {} at path {}",
+                remote_addr, path
+            );
+            let _ = ws.close(None).await;
+        }
+    }
+
+    /// Registers the path and waits for a peer to connect to it
+    async fn wait_for_peer(
+        &self,
+        path: &str,
+        unconnected_timeout: Duration,
+    ) -> Option<(String, WebSocketStream<TcpStream>)> {
+        let (tx, rx) = oneshot::channel();
+
+        {
+            let mut mg = self.dynamic_streams_waiting.lock().await;
+            mg.insert(path.to_owned(), tx);
+        }
+
+        match tokio::time::timeout(unconnected_timeout, rx).await {
+            Ok(res) => res.ok(),
             Err(_) => {
                 // no-one connected, timed out
                 // remove the entry
                 let mut mg = self.dynamic_streams_waiting.lock().await;
-                mg.remove(&path);
+                mg.remove(path);
                 info!(
                     "WebSocket stream at {} timed out waiting for connection",
                     path
                 );
+                None
             }
         }
     }
@@ -243,7 +390,7 @@ mod tests {
     #[tokio::test]
     async fn can_timeout_on_stream_at() {
         let bind_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8378);
-        let s = WebSocketServer::new(bind_addr, false).await.unwrap();
+        let s = WebSocketServer::new(bind_addr, false, None).await.unwrap();
 
         // stream_at() should time out with the internal timeout of 200ms, completing the future
         // before the external timeout of 500ms
@@ -0,0 +1,356 @@
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::schema::{ModSettingsUnreadable, ModSettingsVersion};
+
+/// Contents of a mod-settings.dat file: the version of Factorio that wrote it, and a property tree
+/// of settings keyed by "startup", "runtime-global" and "runtime-per-user".
+///
+/// The format is the same from Factorio 0.17 through 2.0, except that 2.0 stores integers, such as
+/// the values of int-settings, as 64-bit integers rather than as numbers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ModSettings {
+    pub version: ModSettingsVersion,
+    pub settings: PropertyTree,
+}
+
+/// A value in Factorio's property tree format
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum PropertyTree {
+    None,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<PropertyTree>),
+    Dictionary(Dictionary),
+    SignedInteger(i64),
+    UnsignedInteger(u64),
+}
+
+/// Entries of a property tree dictionary, kept in the order they were read so that settings are
+/// written back as they were
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Dictionary(pub Vec<(String, PropertyTree)>);
+
+impl ModSettings {
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<ModSettings> {
+        let mut reader = bytes;
+        let version = ModSettingsVersion {
+            major: u16::from_le_bytes(read_array(&mut reader)?),
+            minor: u16::from_le_bytes(read_array(&mut reader)?),
+            patch: u16::from_le_bytes(read_array(&mut reader)?),
+            build: u16::from_le_bytes(read_array(&mut reader)?),
+        };
+        if has_flag_byte(version) {
+            read_array::<1>(&mut reader)?;
+        }
+        let settings = PropertyTree::read(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(invalid_data(format!(
+                "{} bytes left over after the settings",
+                reader.len()
+            )));
+        }
+        Ok(ModSettings { version, settings })
+    }
+
+    /// Parses the contents of mod-settings.dat, naming the version of Factorio that wrote it if it
+    /// can't be parsed, as the format can change between versions
+    pub fn parse(bytes: &[u8]) -> Result<ModSettings, ModSettingsUnreadable> {
+        ModSettings::from_bytes(bytes).map_err(|e| ModSettingsUnreadable {
+            version: ModSettingsVersion::from_header(bytes),
+            error: e.to_string(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for part in [
+            self.version.major,
+            self.version.minor,
+            self.version.patch,
+            self.version.build,
+        ] {
+            bytes.extend_from_slice(&part.to_le_bytes());
+        }
+        if has_flag_byte(self.version) {
+            bytes.push(0);
+        }
+        self.settings.write(&mut bytes);
+        bytes
+    }
+}
+
+impl PropertyTree {
+    fn read(reader: &mut &[u8]) -> io::Result<PropertyTree> {
+        let [kind, _any_type] = read_array::<2>(reader)?;
+        Ok(match kind {
+            0 => PropertyTree::None,
+            1 => PropertyTree::Bool(read_array::<1>(reader)?[0] != 0),
+            2 => PropertyTree::Number(f64::from_le_bytes(read_array(reader)?)),
+            3 => PropertyTree::String(read_string(reader)?),
+            // list entries have keys like dictionary entries, but they are always empty
+            4 => PropertyTree::List(read_entries(reader)?.into_iter().map(|(_, v)| v).collect()),
+            5 => PropertyTree::Dictionary(Dictionary(read_entries(reader)?)),
+            6 => PropertyTree::SignedInteger(i64::from_le_bytes(read_array(reader)?)),
+            7 => PropertyTree::UnsignedInteger(u64::from_le_bytes(read_array(reader)?)),
+            kind => return Err(invalid_data(format!("unknown property tree type {}", kind))),
+        })
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        let kind = match self {
+            PropertyTree::None => 0,
+            PropertyTree::Bool(_) => 1,
+            PropertyTree::Number(_) => 2,
+            PropertyTree::String(_) => 3,
+            PropertyTree::List(_) => 4,
+            PropertyTree::Dictionary(_) => 5,
+            PropertyTree::SignedInteger(_) => 6,
+            PropertyTree::UnsignedInteger(_) => 7,
+        };
+        bytes.extend_from_slice(&[kind, 0]);
+        match self {
+            PropertyTree::None => (),
+            PropertyTree::Bool(b) => bytes.push(*b as u8),
+            PropertyTree::Number(n) => bytes.extend_from_slice(&n.to_le_bytes()),
+            PropertyTree::String(s) => write_string(bytes, s),
+            PropertyTree::List(items) => {
+                bytes.extend_from_slice(&(items.len() as u32).to_le_bytes());
+                for item in items {
+                    write_string(bytes, "");
+                    item.write(bytes);
+                }
+            }
+            PropertyTree::Dictionary(Dictionary(entries)) => {
+                bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+                for (key, value) in entries {
+                    write_string(bytes, key);
+                    value.write(bytes);
+                }
+            }
+            PropertyTree::SignedInteger(i) => bytes.extend_from_slice(&i.to_le_bytes()),
+            PropertyTree::UnsignedInteger(u) => bytes.extend_from_slice(&u.to_le_bytes()),
+        }
+    }
+}
+
+impl Serialize for Dictionary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Dictionary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Dictionary, D::Error> {
+        struct DictionaryVisitor;
+
+        impl<'de> Visitor<'de> for DictionaryVisitor {
+            type Value = Dictionary;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of property trees")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Dictionary, A::Error> {
+                let mut entries = vec![];
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Dictionary(entries))
+            }
+        }
+
+        deserializer.deserialize_map(DictionaryVisitor)
+    }
+}
+
+/// Factorio 0.17 added a byte after the version, which is always zero
+fn has_flag_byte(version: ModSettingsVersion) -> bool {
+    (version.major, version.minor) >= (0, 17)
+}
+
+fn read_array<const N: usize>(reader: &mut &[u8]) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_entries(reader: &mut &[u8]) -> io::Result<Vec<(String, PropertyTree)>> {
+    let len = u32::from_le_bytes(read_array(reader)?);
+    (0..len)
+        .map(|_| Ok((read_string(reader)?, PropertyTree::read(reader)?)))
+        .collect()
+}
+
+/// Strings start with a flag for the empty string, then a length that takes one byte if it is
+/// less than 255, or 255 followed by four bytes otherwise
+fn read_string(reader: &mut &[u8]) -> io::Result<String> {
+    let [empty] = read_array::<1>(reader)?;
+    if empty != 0 {
+        return Ok(String::new());
+    }
+    let len = match read_array::<1>(reader)? {
+        [255] => u32::from_le_bytes(read_array(reader)?) as usize,
+        [len] => len as usize,
+    };
+    if reader.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (s, rest) = reader.split_at(len);
+    *reader = rest;
+    String::from_utf8(s.to_vec()).map_err(invalid_data)
+}
+
+fn write_string(bytes: &mut Vec<u8>, s: &str) {
+    if s.is_empty() {
+        bytes.push(1);
+        return;
+    }
+    bytes.push(0);
+    if s.len() < 255 {
+        bytes.push(s.len() as u8);
+    } else {
+        bytes.push(255);
+        bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    }
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary(entries: Vec<(&str, PropertyTree)>) -> PropertyTree {
+        PropertyTree::Dictionary(Dictionary(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), v))
+                .collect(),
+        ))
+    }
+
+    fn setting(value: PropertyTree) -> PropertyTree {
+        dictionary(vec![("value", value)])
+    }
+
+    #[test]
+    fn round_trips_mod_settings_from_factorio_2_0() {
+        #[rustfmt::skip]
+        let bytes: &[u8] = &[
+            // version 2.0.28 build 0, then the flag byte
+            2, 0, 0, 0, 28, 0, 0, 0, 0,
+            // dictionary of 3
+            5, 0, 3, 0, 0, 0,
+            0, 7, b's', b't', b'a', b'r', b't', b'u', b'p',
+            5, 0, 2, 0, 0, 0,
+            // int-setting, stored as a signed integer since 2.0
+            0, 9, b'm', b'y', b'-', b'm', b'o', b'd', b'-', b'i', b'n',
+            5, 0, 1, 0, 0, 0,
+            0, 5, b'v', b'a', b'l', b'u', b'e',
+            6, 0, 0xc0, 0xbd, 0xf0, 0xff, 0xff, 0xff, 0xff, 0xff,
+            // string-setting
+            0, 9, b'm', b'y', b'-', b'm', b'o', b'd', b'-', b's', b't',
+            5, 0, 1, 0, 0, 0,
+            0, 5, b'v', b'a', b'l', b'u', b'e',
+            3, 0, 0, 3, b'f', b'o', b'o',
+            0, 14, b'r', b'u', b'n', b't', b'i', b'm', b'e', b'-',
+            b'g', b'l', b'o', b'b', b'a', b'l',
+            5, 0, 1, 0, 0, 0,
+            // double-setting
+            0, 9, b'm', b'y', b'-', b'm', b'o', b'd', b'-', b'd', b'b',
+            5, 0, 1, 0, 0, 0,
+            0, 5, b'v', b'a', b'l', b'u', b'e',
+            2, 0, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f,
+            0, 16, b'r', b'u', b'n', b't', b'i', b'm', b'e', b'-',
+            b'p', b'e', b'r', b'-', b'u', b's', b'e', b'r',
+            5, 0, 1, 0, 0, 0,
+            // bool-setting
+            0, 9, b'm', b'y', b'-', b'm', b'o', b'd', b'-', b'b', b'o',
+            5, 0, 1, 0, 0, 0,
+            0, 5, b'v', b'a', b'l', b'u', b'e',
+            1, 0, 1,
+        ];
+
+        let ms = ModSettings::parse(bytes).unwrap();
+        assert_eq!(
+            ms,
+            ModSettings {
+                version: ModSettingsVersion::from_header(bytes).unwrap(),
+                settings: dictionary(vec![
+                    (
+                        "startup",
+                        dictionary(vec![
+                            (
+                                "my-mod-in",
+                                setting(PropertyTree::SignedInteger(-1_000_000))
+                            ),
+                            ("my-mod-st", setting(PropertyTree::String("foo".to_owned()))),
+                        ])
+                    ),
+                    (
+                        "runtime-global",
+                        dictionary(vec![("my-mod-db", setting(PropertyTree::Number(1.5)))])
+                    ),
+                    (
+                        "runtime-per-user",
+                        dictionary(vec![("my-mod-bo", setting(PropertyTree::Bool(true)))])
+                    ),
+                ]),
+            }
+        );
+        assert_eq!(ms.to_bytes(), bytes);
+
+        let json = serde_json::to_string(&ms).unwrap();
+        assert_eq!(serde_json::from_str::<ModSettings>(&json).unwrap(), ms);
+    }
+
+    #[test]
+    fn round_trips_long_strings_and_lists() {
+        let ms = ModSettings {
+            version: ModSettingsVersion {
+                major: 1,
+                minor: 1,
+                patch: 110,
+                build: 0,
+            },
+            settings: dictionary(vec![(
+                "startup",
+                PropertyTree::List(vec![
+                    PropertyTree::String("x".repeat(300)),
+                    PropertyTree::String(String::new()),
+                    PropertyTree::UnsignedInteger(u64::MAX),
+                    PropertyTree::None,
+                ]),
+            )]),
+        };
+        assert_eq!(ModSettings::from_bytes(&ms.to_bytes()).unwrap(), ms);
+    }
+
+    #[test]
+    fn reports_version_of_unreadable_mod_settings() {
+        let unreadable = ModSettings::parse(&[2, 0, 0, 0, 28, 0, 0, 0, 0, 9, 0]).unwrap_err();
+        assert_eq!(unreadable.version.unwrap().to_string(), "2.0.28");
+        assert!(ModSettings::parse(&[2, 0]).unwrap_err().version.is_none());
+    }
+}
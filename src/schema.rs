@@ -50,32 +50,61 @@ pub enum AgentRequest {
     //
     /// Get system resource statistics
     SystemResources,
+    /// Get the recurring jobs run by the agent's scheduler
+    JobList,
 
     // *********************************
     // * Installation management       *
     // *********************************
     //
     //
-    /// Install the requested version, overwriting the existing installation if different version.
+    /// Install the requested version, replacing all other installed versions.
     /// Can specify the force_install flag to force a re-install of the current version.
+    /// Can specify the keep_existing flag to install alongside existing versions instead, without
+    /// interrupting the running server.
     ///
     /// **This is a long-running operation.**
     VersionInstall {
         version: FactorioVersion,
         force_install: bool,
+        #[serde(default)]
+        keep_existing: bool,
     },
-    /// Get the currently installed version, if any.
+    /// Install from a headless server archive (.tar.xz) at the given path on the agent host, for
+    /// hosts without access to factorio.com. The version is read from the server binary in the
+    /// archive, and is installed alongside existing versions. Responds with the installed version.
+    ///
+    /// **This is a long-running operation.**
+    VersionInstallFromArchive(String),
+    /// Get the default installed version, if any. This is the latest installed version.
     VersionGet,
+    /// Get all installed versions.
+    VersionList,
 
     // *********************************
     // * Server control                *
     // *********************************
     //
     //
-    /// Start the server using the specific save file.
-    ServerStart(ServerStartSaveFile),
+    /// Start the server using the specific save file, and optionally a specific installed version.
+    /// If no version is given, the default installed version is used.
+    /// If a launch profile is given, its server settings, mods and whitelist flag are used in place
+    /// of the current ones.
+    ServerStart(ServerStartSaveFile, Option<FactorioVersion>, Option<String>),
+    /// Resolve everything a `ServerStart` with the same arguments would use, without starting the
+    /// server. Responds with the command line and settings files, and any issues found that would
+    /// prevent or affect the start.
+    ServerStartPlan(ServerStartSaveFile, Option<FactorioVersion>, Option<String>),
     /// Stop the server.
     ServerStop,
+    /// Stop the server after the given delay, warning players in-game with a countdown and
+    /// saving the game just before stopping. The message, if any, is added to each warning.
+    ///
+    /// **This is a long-running operation.**
+    ServerStopScheduled {
+        delay_secs: u32,
+        message: Option<String>,
+    },
     /// Get the current status of the server.
     ServerStatus,
 
@@ -84,19 +113,55 @@ pub enum AgentRequest {
     // *********************************
     //
     //
+    /// Back up the save file with the requested name to the configured backup storage, pruning
+    /// the oldest backups of that save file beyond the number to retain.
+    ///
+    /// **This is a long-running operation.**
+    BackupCreate(String),
+    /// Get the backups held in the configured backup storage, and the outcome of the most recent
+    /// backups.
+    BackupStatusGet,
     /// Create a new save file with the requested name.
     /// This will overwrite any existing save file of that name.
     ///
     /// **This is a long-running operation.**
     SaveCreate(String, Option<MapGenSettingsJson>, Option<MapSettingsJson>),
-    /// Delete the save file from the server with the requested name
+    /// Delete the save file from the server with the requested name.
+    /// Fails if the save file is being hosted by the running server.
     SaveDelete(String),
-    /// Gets the save file zip from the server
-    SaveGet(String),
+    /// Rename the save file with the first name to the second name.
+    /// Fails if a save file with the new name exists, or if the save file is being hosted by the
+    /// running server.
+    SaveRename(String, String),
+    /// Copy the save file with the first name to a new save file with the second name.
+    /// Fails if a save file with the new name exists.
+    SaveCopy(String, String),
+    /// Gets the save file zip from the server, optionally with flow control.
+    ///
+    /// If an ack interval is given, the agent will pause the transfer until a `SaveGetAck` is
+    /// received for every that many chunks.
+    ///
+    /// **This is a long-running operation.**
+    SaveGet(String, Option<usize>),
+    /// Acknowledges receipt of a batch of chunks for the `SaveGet` operation with the given id,
+    /// allowing the transfer to continue. No response is sent for this request.
+    SaveGetAck(OperationId),
     /// Get a list of the save files present on the server.
     SaveList,
+    /// Get the SHA-256 of the save file with the requested name, as served for download.
+    SaveChecksumGet(String),
+    /// Get metadata of the save file with the requested name, read from its header without
+    /// loading it in a server.
+    SaveMetadataGet(String),
     /// Upserts a save file with the requested name
     SaveSet(String, SaveBytes),
+    /// Render a preview image of the terrain of the hosted save, using the map-gen-settings
+    /// read from the running server. The image is kept until the next preview is generated.
+    ///
+    /// **This is a long-running operation.**
+    MapPreviewGenerate,
+    /// Get the most recently generated map preview image, if any.
+    MapPreviewGet,
 
     // *********************************
     // * Mod management                *
@@ -105,6 +170,8 @@ pub enum AgentRequest {
     //
     /// Get a list of built-in mods that are enabled on the server
     ModDlcsGet,
+    /// Get a list of built-in mods shipped with the installed version of Factorio
+    ModDlcsAvailableGet,
     /// Applies the desired list of built-in mods on the server
     ModDlcsSet(Vec<Dlc>),
     /// Get a list of mods installed on the server.
@@ -115,6 +182,16 @@ pub enum AgentRequest {
     ///
     /// **This is a long-running operation.**
     ModListSet(Vec<ModObject>),
+    /// Updates each installed mod to its newest release that the installed version of Factorio
+    /// can load.
+    ///
+    /// **This is a long-running operation.**
+    ModListUpdateAll,
+    /// Enables an installed mod. Only the mod list is changed, nothing is downloaded.
+    ModEnable(String),
+    /// Disables an installed mod, keeping it installed. Only the mod list is changed, nothing is
+    /// deleted.
+    ModDisable(String),
     /// Gets the mod-settings file on the server.
     ModSettingsGet,
     /// Sets the mod-settings file on the servere.
@@ -135,10 +212,30 @@ pub enum AgentRequest {
     ConfigBanListSet {
         users: Vec<String>,
     },
+    /// Cross-checks the configuration for combinations of settings that won't work as intended,
+    /// e.g. an empty whitelist.
+    ConfigDiagnosticsGet,
+    /// Gets the performance tuning applied when launching the server.
+    ConfigPerformanceGet,
+    /// Sets the performance tuning applied when launching the server. Takes effect on the next
+    /// server start.
+    ConfigPerformanceSet(PerformanceConfig),
+    /// Gets the saved launch profiles.
+    ConfigProfileList,
+    /// Saves the current server settings, mods and whitelist flag as the launch profile with the
+    /// given name, replacing any existing profile with that name.
+    ConfigProfileSave(String),
+    /// Deletes the launch profile with the given name.
+    ConfigProfileDelete(String),
     ConfigRconGet,
     ConfigRconSet {
         password: String,
     },
+    /// Gets the policy for restarting the server after it exits unexpectedly.
+    ConfigRestartPolicyGet,
+    /// Sets the policy for restarting the server after it exits unexpectedly. Takes effect on the
+    /// next server start.
+    ConfigRestartPolicySet(RestartPolicy),
     ConfigSecretsGet,
     ConfigSecretsSet {
         username: String,
@@ -158,6 +255,20 @@ pub enum AgentRequest {
     // * In-game                       *
     // *********************************
     RconCommand(String),
+    /// Sends a private message to each of the named players.
+    RconWhisper {
+        players: Vec<String>,
+        message: String,
+    },
+
+    // *********************************
+    // * Streaming                     *
+    // *********************************
+    //
+    //
+    /// Restricts the server stdout lines streamed to this connection to the given categories.
+    /// Pass `None` to receive all lines again, which is the default for a new connection.
+    StreamStdoutSubscribe(Option<Vec<StdoutCategory>>),
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -195,23 +306,41 @@ pub enum AgentOutMessage {
 
     // Structured operation responses
     AgentBuildVersion(BuildVersion),
+    BackupStatus(BackupStatus),
     ConflictingOperation,
     ConfigAdminList(Vec<String>),
     ConfigBanList(Vec<String>),
+    ConfigDiagnostics(Vec<ConfigWarning>),
     ConfigWhiteList(WhitelistObject),
+    ConfigPerformance(PerformanceConfig),
+    ConfigProfileList(Vec<LaunchProfile>),
     ConfigRcon(RconConfig),
+    ConfigRestartPolicy(RestartPolicy),
     ConfigSecrets(Option<SecretsObject>),
     ConfigServerSettings(ServerSettingsConfig),
     DlcList(Vec<Dlc>),
     FactorioVersion(FactorioVersion),
+    FactorioVersionList(Vec<FactorioVersion>),
+    InsufficientDiskSpace(InsufficientDiskSpace),
+    JobList(Vec<JobStatus>),
+    MapPreview(Option<MapPreviewBytes>),
     ModsList(Vec<ModObject>),
+    ModNotFound,
     ModSettings(Option<ModSettingsBytes>),
+    ModSettingsUnreadable(ModSettingsUnreadable),
+    ModVersionsNotFound(Vec<ModVersionNotFound>),
     MissingSecrets,
     NotInstalled,
+    ProfileNotFound,
     RconResponse(String),
+    SaveAlreadyExists,
     SaveFile(SaveBytes),
+    SaveChecksum(String),
+    SaveInUse,
     SaveList(Vec<Save>),
+    SaveMetadata(SaveMetadata),
     SaveNotFound,
+    ServerStartPlan(ServerStartPlan),
     ServerStatus(ServerStatus),
     SystemResources(SystemResources),
 }
@@ -222,23 +351,236 @@ pub struct BuildVersion {
     pub commit_hash: String,
 }
 
+/// Free space found by a preflight check to be short of what an operation needs
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InsufficientDiskSpace {
+    pub path: String,
+    pub required_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Backups of savefiles held off-box
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackupStatus {
+    /// Whether backup storage is configured on the agent
+    pub enabled: bool,
+    pub last_success: Option<BackupObject>,
+    pub last_failure: Option<BackupFailure>,
+    /// Backups held in storage, newest first
+    pub backups: Vec<BackupObject>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackupObject {
+    pub save_name: String,
+    /// Key of the backup in the bucket
+    pub key: String,
+    pub timestamp: DateTime<Utc>,
+    pub size_bytes: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackupFailure {
+    pub save_name: String,
+    pub timestamp: DateTime<Utc>,
+    pub error: String,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum ServerStartSaveFile {
     Latest,
     Specific(String),
 }
 
+/// What a server start would do, from a dry run
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ServerStartPlan {
+    /// Executable followed by each argument, with the RCON password redacted
+    pub command_line: Vec<String>,
+    /// Paths of the settings files and mod directory passed to the server
+    pub settings_files: Vec<String>,
+    /// Problems that would prevent the server from starting, or make it start differently than
+    /// expected. Empty if the start would go ahead as planned.
+    pub issues: Vec<String>,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum ServerStatus {
     NotRunning,
     PreGame,
-    InGame { player_count: u32 },
+    InGame {
+        player_count: u32,
+        /// Server updates per second over the last minute, absent while the game is paused or
+        /// before it has been measured
+        #[serde(default)]
+        ups: Option<f32>,
+        /// Estimated from the measured game time, absent until it has first been measured
+        #[serde(default)]
+        map_clock: Option<MapClock>,
+        /// Latest time the server will be restarted for uptime, absent unless the restart policy
+        /// restarts for uptime
+        #[serde(default)]
+        next_restart: Option<DateTime<Utc>>,
+    },
     PostGame,
 }
 
-#[derive(Clone, Debug, Deserialize, derive_more::From, derive_more::Into, Serialize)]
+/// Approximate in-game clock of the map, estimated from how long it has been running rather than
+/// read from the game. Assumes the default day length, and that nothing has changed the time of
+/// day since the map was created.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MapClock {
+    pub tick: u64,
+    /// Day of the day/night cycle, starting from 1
+    pub day: u64,
+    /// Minutes past midnight in-game
+    pub minute_of_day: u32,
+}
+
+impl MapClock {
+    pub const TICKS_PER_DAY: u64 = 25_000;
+
+    pub fn from_tick(tick: u64) -> MapClock {
+        // a new map starts at noon
+        let since_midnight = tick + MapClock::TICKS_PER_DAY / 2;
+        MapClock {
+            tick,
+            day: since_midnight / MapClock::TICKS_PER_DAY + 1,
+            minute_of_day: (since_midnight % MapClock::TICKS_PER_DAY * 24 * 60
+                / MapClock::TICKS_PER_DAY) as u32,
+        }
+    }
+
+    /// Time of day on a 24 hour clock, e.g. "18:40"
+    pub fn time_of_day(&self) -> String {
+        format!("{:02}:{:02}", self.minute_of_day / 60, self.minute_of_day % 60)
+    }
+}
+
+impl std::fmt::Display for MapClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Day {}, {}", self.day, self.time_of_day())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, derive_more::From, derive_more::Into, PartialEq, Eq, Serialize)]
 pub struct FactorioVersion(pub String);
 
+impl FactorioVersion {
+    /// Parses the version into its numeric components, e.g. "1.1.110" into [1, 1, 110].
+    /// Returns None if any component is not numeric.
+    pub fn components(&self) -> Option<Vec<u32>> {
+        self.0.split('.').map(|c| c.parse().ok()).collect()
+    }
+
+    /// Whether this version can load a mod release made for the major.minor
+    /// `mod_factorio_version`. Factorio 1.0 also loads mods made for 0.18.
+    pub fn loads_mods_for(&self, mod_factorio_version: &str) -> bool {
+        let installed = match self.components() {
+            Some(c) if c.len() >= 2 => (c[0], c[1]),
+            _ => return false,
+        };
+        let release = match FactorioVersion(mod_factorio_version.to_owned()).components() {
+            Some(c) if c.len() >= 2 => (c[0], c[1]),
+            _ => return false,
+        };
+        release == installed || (installed == (1, 0) && release == (0, 18))
+    }
+}
+
+impl PartialOrd for FactorioVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FactorioVersion {
+    /// Orders numerically by component. Unparseable versions sort before all valid versions,
+    /// and are ordered between themselves as strings.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.components(), &self.0).cmp(&(other.components(), &other.0))
+    }
+}
+
+/// Version of a mod, or a sentinel for the latest release on the mod portal.
+///
+/// Specific versions compare numerically by component, so "0.4.05" is equal to "0.4.5" and less
+/// than "0.4.10". The original string is kept as-is, since it determines the mod zip filename.
+#[derive(Clone, Debug)]
+pub enum ModVersion {
+    Latest,
+    Specific(String),
+}
+
+impl ModVersion {
+    const LATEST: &'static str = "latest";
+
+    /// Key used for equality, ordering and hashing. Unparseable versions sort before all valid
+    /// versions, and Latest sorts after all of them.
+    fn key(&self) -> (u8, Vec<u32>, &str) {
+        match self {
+            ModVersion::Specific(s) => {
+                match s.split('.').map(|c| c.parse().ok()).collect::<Option<Vec<u32>>>() {
+                    Some(components) => (1, components, ""),
+                    None => (0, vec![], s),
+                }
+            }
+            ModVersion::Latest => (2, vec![], ""),
+        }
+    }
+}
+
+impl From<&str> for ModVersion {
+    fn from(s: &str) -> Self {
+        if s.eq_ignore_ascii_case(ModVersion::LATEST) {
+            ModVersion::Latest
+        } else {
+            ModVersion::Specific(s.to_owned())
+        }
+    }
+}
+
+impl From<String> for ModVersion {
+    fn from(s: String) -> Self {
+        ModVersion::from(s.as_str())
+    }
+}
+
+impl std::fmt::Display for ModVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModVersion::Latest => f.write_str(ModVersion::LATEST),
+            ModVersion::Specific(s) => f.write_str(s),
+        }
+    }
+}
+
+impl PartialEq for ModVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for ModVersion {}
+
+impl std::hash::Hash for ModVersion {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for ModVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ModVersion {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MapGenSettingsJson(pub String);
 
@@ -249,6 +591,23 @@ pub struct MapSettingsJson(pub String);
 pub struct Save {
     pub name: String,
     pub last_modified: DateTime<Utc>,
+    /// Whether a replacement uploaded while the save was in use is waiting to be swapped in
+    #[serde(default)]
+    pub staged: bool,
+    /// Whether the save is only held in remote storage, to be pulled when it is next hosted
+    #[serde(default)]
+    pub remote: bool,
+}
+
+/// Metadata of a save file that can be read without loading it in a server
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SaveMetadata {
+    pub name: String,
+    pub last_modified: DateTime<Utc>,
+    pub size_bytes: u64,
+    /// Mods enabled when the save was made, including the base mod and any DLCs.
+    /// The version of the base mod is the version of Factorio that made the save.
+    pub mods: Vec<ModObject>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -256,6 +615,9 @@ pub struct SaveBytes {
     pub multipart_start: Option<usize>,
     #[serde(with = "base64")]
     pub bytes: Vec<u8>,
+    /// Hex-encoded SHA-256 of the complete file, to be verified when the file is finalised
+    #[serde(default)]
+    pub sha256: Option<String>,
 }
 
 impl SaveBytes {
@@ -263,6 +625,7 @@ impl SaveBytes {
         SaveBytes {
             multipart_start: None,
             bytes,
+            sha256: None,
         }
     }
 
@@ -270,9 +633,15 @@ impl SaveBytes {
         SaveBytes {
             multipart_start: Some(total_length),
             bytes: vec![],
+            sha256: None,
         }
     }
 
+    pub fn with_sha256(mut self, sha256: Option<String>) -> SaveBytes {
+        self.sha256 = sha256;
+        self
+    }
+
     pub fn is_sentinel(&self) -> bool {
         self.bytes.len() == 0
     }
@@ -285,11 +654,13 @@ impl std::fmt::Debug for SaveBytes {
             f.debug_struct("SaveBytes")
                 .field("multipart_start", &self.multipart_start)
                 .field("bytes", &debug_bytes)
+                .field("sha256", &self.sha256)
                 .finish()
         } else {
             f.debug_struct("SaveBytes")
                 .field("multipart_start", &self.multipart_start)
                 .field("bytes", &self.bytes)
+                .field("sha256", &self.sha256)
                 .finish()
         }
     }
@@ -301,6 +672,68 @@ pub struct ModSettingsBytes {
     pub bytes: Vec<u8>,
 }
 
+/// Version of Factorio that wrote a mod-settings.dat file, which determines its format
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ModSettingsVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+    pub build: u16,
+}
+
+impl ModSettingsVersion {
+    /// Reads the version from the header at the start of a mod-settings.dat file, which every
+    /// version of the format begins with
+    pub fn from_header(bytes: &[u8]) -> Option<ModSettingsVersion> {
+        let mut parts = bytes
+            .get(..8)?
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]));
+        Some(ModSettingsVersion {
+            major: parts.next()?,
+            minor: parts.next()?,
+            patch: parts.next()?,
+            build: parts.next()?,
+        })
+    }
+}
+
+impl std::fmt::Display for ModSettingsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// A mod-settings.dat file that couldn't be parsed, most likely as it was written by a version
+/// of Factorio with a format that isn't supported
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModSettingsUnreadable {
+    /// None if the file is too short to have a version header
+    pub version: Option<ModSettingsVersion>,
+    pub error: String,
+}
+
+impl std::fmt::Display for ModSettingsUnreadable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.version {
+            Some(version) => write!(
+                f,
+                "mod-settings.dat written by Factorio {} is not in a supported format: {}",
+                version, self.error
+            ),
+            None => write!(f, "mod-settings.dat has no version header: {}", self.error),
+        }
+    }
+}
+
+/// PNG image of the map terrain
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MapPreviewBytes {
+    #[serde(with = "base64")]
+    pub bytes: Vec<u8>,
+    pub generated_at: DateTime<Utc>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize, EnumString, Display)]
 pub enum Dlc {
     #[serde(rename = "base")]
@@ -321,6 +754,58 @@ pub enum Dlc {
 pub struct ModObject {
     pub name: String,
     pub version: String,
+    /// Disabled mods stay installed but aren't loaded by the game
+    #[serde(default = "ModObject::default_enabled")]
+    pub enabled: bool,
+}
+
+impl ModObject {
+    fn default_enabled() -> bool {
+        true
+    }
+}
+
+/// A mod version requested in a mod list that isn't published on the mod portal
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ModVersionNotFound {
+    pub name: String,
+    pub requested_version: String,
+    /// Versions published on the mod portal, newest first. Empty if the mod doesn't exist.
+    pub available_versions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ConfigWarning {
+    pub kind: ConfigWarningKind,
+    /// Human-readable description of the problem and how to fix it
+    pub message: String,
+}
+
+#[derive(AsRefStr, Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ConfigWarningKind {
+    WhitelistEmpty,
+    PublicWithoutCredentials,
+    RconPortCollision,
+    ModsWithoutCredentials,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PerformanceConfig {
+    /// Indices of the CPUs the server process may run on, like a taskset CPU list.
+    /// If unset, the server may run on any CPU.
+    pub cpu_affinity: Option<Vec<usize>>,
+}
+
+/// A named set of server settings, mods and whitelist flag which the server can be started with
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LaunchProfile {
+    pub name: String,
+    pub use_whitelist: bool,
+    /// Whether the server settings make the game visible to the public
+    pub public: bool,
+    pub mod_count: usize,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -329,6 +814,48 @@ pub struct RconConfig {
     pub password: String,
 }
 
+/// When to restart the server automatically, after the process exits unexpectedly or after it
+/// has been running for a long time
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RestartPolicy {
+    /// Maximum number of consecutive restart attempts, or 0 to never restart automatically
+    pub max_retries: u32,
+    /// Delay before the first restart attempt, doubled for each consecutive attempt after that
+    pub backoff_secs: u64,
+    /// Restart the server once it has been running for this many hours, or 0 to never restart
+    /// for uptime
+    #[serde(default)]
+    pub uptime_restart_hours: u32,
+    /// How long past the uptime limit to wait for all players to leave before restarting anyway
+    #[serde(default)]
+    pub uptime_restart_max_delay_mins: u32,
+}
+
+impl RestartPolicy {
+    /// Delay before the given restart attempt (starting at 1), or None if out of retries
+    pub fn backoff(&self, attempt: u32) -> Option<std::time::Duration> {
+        if attempt == 0 || attempt > self.max_retries {
+            None
+        } else {
+            let multiplier = 2u64.saturating_pow(attempt - 1);
+            Some(std::time::Duration::from_secs(
+                self.backoff_secs.saturating_mul(multiplier),
+            ))
+        }
+    }
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_retries: 0,
+            backoff_secs: 10,
+            uptime_restart_hours: 0,
+            uptime_restart_max_delay_mins: 0,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SecretsObject {
     pub username: String,
@@ -350,6 +877,53 @@ pub struct AgentStreamingMessage {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum AgentStreamingMessageInner {
     ServerStdout(String),
+    /// The server process exited without being asked to stop
+    ServerCrashed {
+        exit_status: String,
+        /// Consecutive restart attempt number, if the server is being restarted
+        restart_attempt: Option<u32>,
+    },
+    /// An operation interrupted by the agent restarting was found on startup, and its partial
+    /// changes were dealt with as described
+    OperationRecovered(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize, EnumString, Display)]
+pub enum StdoutCategory {
+    #[serde(rename = "chat")]
+    #[strum(serialize = "chat")]
+    Chat,
+    #[serde(rename = "joinleave")]
+    #[strum(serialize = "joinleave")]
+    JoinLeave,
+    #[serde(rename = "rpc")]
+    #[strum(serialize = "rpc")]
+    Rpc,
+    #[serde(rename = "serverstate")]
+    #[strum(serialize = "serverstate")]
+    ServerState,
+    #[serde(rename = "systemlog")]
+    #[strum(serialize = "systemlog")]
+    SystemLog,
+}
+
+impl StdoutCategory {
+    pub fn classify(line: &str) -> StdoutCategory {
+        use self::regex::*;
+
+        if CHAT_RE.is_match(line) {
+            // includes echoes from the discord chat link
+            StdoutCategory::Chat
+        } else if JOIN_RE.is_match(line) || LEAVE_RE.is_match(line) {
+            StdoutCategory::JoinLeave
+        } else if RPC_RE.is_match(line) {
+            StdoutCategory::Rpc
+        } else if STATE_CHANGE_RE.is_match(line) {
+            StdoutCategory::ServerState
+        } else {
+            StdoutCategory::SystemLog
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -423,25 +997,99 @@ pub struct SystemResources {
     pub cpus: Vec<f32>,
     pub mem_total_bytes: u64,
     pub mem_used_bytes: u64,
+    /// Number of streaming messages dropped because a connected peer fell behind
+    #[serde(default)]
+    pub dropped_messages: u64,
+    /// Usage of each filesystem holding the agent's installations and data
+    #[serde(default)]
+    pub disks: Vec<DiskUsage>,
+    /// Resource usage of the Factorio server process, if it is running
+    #[serde(default)]
+    pub server_process: Option<ProcessResources>,
+}
+
+/// What to do about runs of a recurring job that were due while its scheduler wasn't running
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedRunPolicy {
+    /// Wait for the next run
+    #[default]
+    Skip,
+    /// Run once as soon as possible, however many runs were missed
+    RunOnce,
+}
+
+/// A recurring job run by the agent's scheduler
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub interval_secs: u64,
+    /// Each run is delayed by up to this long, chosen at random
+    pub jitter_secs: u64,
+    pub missed_run: MissedRunPolicy,
+    pub last_run: Option<DateTime<Utc>>,
+    pub next_run: DateTime<Utc>,
+    /// Error from the last run, if it failed
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DiskUsage {
+    /// Mount point of the filesystem
+    pub path: String,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProcessResources {
+    /// CPU usage, where 100% is one full core
+    pub cpu_usage: f32,
+    pub mem_rss_bytes: u64,
+    pub uptime_secs: u64,
 }
 
-/// module for serde to handle binary fields
+/// module for serde to handle binary fields, as base64 in JSON and as raw bytes in binary formats
 mod base64 {
     use base64::Engine;
-    use serde::{Deserialize, Serialize};
+    use serde::{de::Visitor, Deserialize, Serialize};
     use serde::{Deserializer, Serializer};
 
     pub fn serialize<S: Serializer>(v: &Vec<u8>, s: S) -> Result<S::Ok, S::Error> {
+        if !s.is_human_readable() {
+            return s.serialize_bytes(v);
+        }
         let base64 = base64::engine::general_purpose::STANDARD_NO_PAD.encode(v);
         String::serialize(&base64, s)
     }
 
     pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        if !d.is_human_readable() {
+            return d.deserialize_byte_buf(BytesVisitor);
+        }
         let base64 = String::deserialize(d)?;
         base64::engine::general_purpose::STANDARD_NO_PAD
             .decode(base64.as_bytes())
             .map_err(|e| serde::de::Error::custom(e))
     }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("bytes")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+    }
 }
 
 pub mod regex {
@@ -466,6 +1114,17 @@ pub mod regex {
         pub static ref LEAVE_RE: Regex = Regex::new(
             r"^(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}) \[LEAVE\] ([^:]+) left the game$"
         ).unwrap();
+        // network peer identified as a player from process stdout
+        pub static ref PEER_INFO_RE: Regex = Regex::new(
+            r"Received peer info for peer\((\d+)\) username\(([^)]+)\)"
+        ).unwrap();
+        // connection problem with a network peer from process stdout, see PEER_ID_RE for the peer
+        pub static ref PEER_CONNECTION_ISSUE_RE: Regex = Regex::new(
+            r"(?i)dropping packets|lagging behind|not heard from|timed out"
+        ).unwrap();
+        pub static ref PEER_ID_RE: Regex = Regex::new(
+            r"peer\s?\((\d+)\)"
+        ).unwrap();
         pub static ref MOD_FILENAME_RE: Regex = Regex::new(
             r"^(.+)_(\d+\.\d+\.\d+)\.zip$"
         ).unwrap();
@@ -477,6 +1136,14 @@ pub mod regex {
         pub static ref RPC_RE: Regex = Regex::new(
             r"^FCTRL_RPC (.+)$"
         ).unwrap();
+        // start of an autosave from process stdout
+        pub static ref AUTOSAVE_STARTED_RE: Regex = Regex::new(
+            r"Saving to (_autosave\d+) \((?:non-)?blocking\)"
+        ).unwrap();
+        // completion of any save, including autosaves, from process stdout
+        pub static ref SAVE_FINISHED_RE: Regex = Regex::new(
+            r"Saving finished"
+        ).unwrap();
         // server internal state change from process stdout
         pub static ref STATE_CHANGE_RE: Regex = Regex::new(
             r"changing state from\(([a-zA-Z]+)\) to\(([a-zA-Z]+)\)"
@@ -491,3 +1158,132 @@ pub mod regex {
         ).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_stdout_lines() {
+        assert_eq!(
+            StdoutCategory::classify("2024-01-02 03:04:05 [CHAT] someone: hello"),
+            StdoutCategory::Chat
+        );
+        assert_eq!(
+            StdoutCategory::classify("2024-01-02 03:04:05 [CHAT] <server>: [Discord] someone: hi"),
+            StdoutCategory::Chat
+        );
+        assert_eq!(
+            StdoutCategory::classify("2024-01-02 03:04:05 [JOIN] someone joined the game"),
+            StdoutCategory::JoinLeave
+        );
+        assert_eq!(
+            StdoutCategory::classify("2024-01-02 03:04:05 [LEAVE] someone left the game"),
+            StdoutCategory::JoinLeave
+        );
+        assert_eq!(
+            StdoutCategory::classify("FCTRL_RPC {}"),
+            StdoutCategory::Rpc
+        );
+        assert_eq!(
+            StdoutCategory::classify("  10.001 Info ServerMultiplayerManager.cpp:1 changing state from(Ready) to(PreparedToHostGame)"),
+            StdoutCategory::ServerState
+        );
+        assert_eq!(
+            StdoutCategory::classify("   0.000 2024-01-02 03:04:05; Factorio 2.0.28"),
+            StdoutCategory::SystemLog
+        );
+    }
+
+    #[test]
+    fn reads_mod_settings_version_header() {
+        // 2.0.28 build 0, followed by the start of the property tree
+        let bytes = [2, 0, 0, 0, 28, 0, 0, 0, 0, 5, 0];
+        assert_eq!(
+            ModSettingsVersion::from_header(&bytes),
+            Some(ModSettingsVersion {
+                major: 2,
+                minor: 0,
+                patch: 28,
+                build: 0,
+            })
+        );
+        assert_eq!(
+            ModSettingsVersion::from_header(&bytes).unwrap().to_string(),
+            "2.0.28"
+        );
+        assert_eq!(ModSettingsVersion::from_header(&bytes[..7]), None);
+    }
+
+    fn v(s: &str) -> FactorioVersion {
+        FactorioVersion(s.to_owned())
+    }
+
+    #[test]
+    fn factorio_version_orders_numerically() {
+        assert!(v("1.1.9") < v("1.1.10"));
+        assert!(v("1.1.110") < v("2.0.7"));
+        assert!(v("0.18.47") < v("1.0.0"));
+        assert_eq!(v("2.0.7").cmp(&v("2.0.7")), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn factorio_version_loads_mods_by_major_and_minor_version() {
+        assert!(v("2.0.28").loads_mods_for("2.0"));
+        assert!(!v("2.0.28").loads_mods_for("1.1"));
+        assert!(!v("2.0.28").loads_mods_for(""));
+        assert!(v("1.0.0").loads_mods_for("0.18"));
+        assert!(!v("latest").loads_mods_for("2.0"));
+    }
+
+    #[test]
+    fn factorio_version_can_sort() {
+        let mut versions = vec![v("2.0.7"), v("1.1.10"), v("1.1.9"), v("1.1.110")];
+        versions.sort();
+        assert_eq!(versions, vec![v("1.1.9"), v("1.1.10"), v("1.1.110"), v("2.0.7")]);
+    }
+
+    #[test]
+    fn mod_version_orders_numerically() {
+        assert!(ModVersion::from("0.4.05") < ModVersion::from("0.4.10"));
+        assert!(ModVersion::from("0.4.9") < ModVersion::from("0.4.10"));
+        assert!(ModVersion::from("19.1.0") > ModVersion::from("2.0.0"));
+        assert_eq!(ModVersion::from("0.4.05"), ModVersion::from("0.4.5"));
+    }
+
+    #[test]
+    fn mod_version_latest_sorts_last() {
+        assert_eq!(ModVersion::from("latest"), ModVersion::Latest);
+        assert_eq!(ModVersion::Latest.to_string(), "latest");
+        assert!(ModVersion::from("999.999.999") < ModVersion::Latest);
+    }
+
+    #[test]
+    fn factorio_version_unparseable_sorts_first() {
+        assert_eq!(v("latest").components(), None);
+        assert!(v("latest") < v("0.0.1"));
+    }
+
+    #[test]
+    fn map_clock_starts_at_noon_on_day_one() {
+        assert_eq!(MapClock::from_tick(0).to_string(), "Day 1, 12:00");
+        assert_eq!(MapClock::from_tick(12_500).to_string(), "Day 2, 00:00");
+        assert_eq!(
+            MapClock::from_tick(MapClock::TICKS_PER_DAY * 212 + 16_250).to_string(),
+            "Day 213, 18:00"
+        );
+    }
+
+    #[test]
+    fn restart_policy_backs_off_exponentially() {
+        let policy = RestartPolicy {
+            max_retries: 3,
+            backoff_secs: 5,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Some(std::time::Duration::from_secs(5)));
+        assert_eq!(policy.backoff(3), Some(std::time::Duration::from_secs(20)));
+        assert_eq!(policy.backoff(4), None);
+        assert_eq!(RestartPolicy::default().backoff(1), None);
+    }
+}
@@ -3,6 +3,401 @@ pub mod version {
     pub const GIT_SHA: Option<&'static str> = option_env!("GIT_COMMIT_HASH");
 }
 
+/// Signed, expiring links to resources, shared between the mgmt-server and the agent
+pub mod signing {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    fn mac(secret: &str, resource: &str, expires: i64) -> HmacSha256 {
+        // HMAC accepts keys of any length, this cannot fail
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}:{}", resource, expires).as_bytes());
+        mac
+    }
+
+    /// Signs access to the resource until the `expires` unix timestamp
+    pub fn sign(secret: &str, resource: &str, expires: i64) -> String {
+        URL_SAFE_NO_PAD.encode(mac(secret, resource, expires).finalize().into_bytes())
+    }
+
+    /// Checks the signature against the resource and expiry. The expiry itself is not checked.
+    pub fn verify(secret: &str, resource: &str, expires: i64, signature: &str) -> bool {
+        match URL_SAFE_NO_PAD.decode(signature) {
+            Ok(signature) => mac(secret, resource, expires)
+                .verify_slice(&signature)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn can_verify_signature() {
+            let signature = sign("secret", "save1", 1700000000);
+            assert!(verify("secret", "save1", 1700000000, &signature));
+        }
+
+        #[test]
+        fn rejects_tampered_signature() {
+            let signature = sign("secret", "save1", 1700000000);
+            assert!(!verify("secret", "save2", 1700000000, &signature));
+            assert!(!verify("secret", "save1", 1800000000, &signature));
+            assert!(!verify("other", "save1", 1700000000, &signature));
+            assert!(!verify("secret", "save1", 1700000000, "not a signature"));
+        }
+    }
+}
+
+/// HTTP byte range requests, used to resume savefile downloads
+pub mod range {
+    /// Inclusive range of bytes within a resource
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct ByteRange {
+        pub start: u64,
+        pub end: u64,
+    }
+
+    impl ByteRange {
+        pub fn size(&self) -> u64 {
+            self.end - self.start + 1
+        }
+
+        /// Value of the Content-Range header for this range of a resource of the given length
+        pub fn content_range(&self, total: u64) -> String {
+            format!("bytes {}-{}/{}", self.start, self.end, total)
+        }
+    }
+
+    /// The requested range starts beyond the end of the resource
+    #[derive(Debug, PartialEq)]
+    pub struct RangeNotSatisfiable;
+
+    /// Parses a Range header value against a resource of the given length.
+    ///
+    /// Returns None if the whole resource should be served instead, which is the case for
+    /// malformed headers and requests for multiple ranges, and Err if the range lies outside the
+    /// resource.
+    pub fn parse(header: &str, total: u64) -> Result<Option<ByteRange>, RangeNotSatisfiable> {
+        let spec = match header.trim().strip_prefix("bytes=") {
+            Some(spec) if !spec.contains(',') => spec.trim(),
+            _ => return Ok(None),
+        };
+        let (start, end) = match spec.split_once('-') {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        let range = if start.is_empty() {
+            // suffix range, i.e. the last n bytes
+            match end.parse::<u64>() {
+                Ok(0) => return Err(RangeNotSatisfiable),
+                Ok(n) => ByteRange {
+                    start: total.saturating_sub(n),
+                    end: total.saturating_sub(1),
+                },
+                Err(_) => return Ok(None),
+            }
+        } else {
+            let start = match start.parse::<u64>() {
+                Ok(start) => start,
+                Err(_) => return Ok(None),
+            };
+            let end = if end.is_empty() {
+                total.saturating_sub(1)
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => end.min(total.saturating_sub(1)),
+                    _ => return Ok(None),
+                }
+            };
+            ByteRange { start, end }
+        };
+        if total == 0 || range.start >= total {
+            return Err(RangeNotSatisfiable);
+        }
+        Ok(Some(range))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parses_single_ranges() {
+            assert_eq!(
+                parse("bytes=0-99", 1000),
+                Ok(Some(ByteRange { start: 0, end: 99 }))
+            );
+            assert_eq!(
+                parse("bytes=900-", 1000),
+                Ok(Some(ByteRange {
+                    start: 900,
+                    end: 999
+                }))
+            );
+            assert_eq!(
+                parse("bytes=-100", 1000),
+                Ok(Some(ByteRange {
+                    start: 900,
+                    end: 999
+                }))
+            );
+            assert_eq!(
+                parse("bytes=500-5000", 1000),
+                Ok(Some(ByteRange {
+                    start: 500,
+                    end: 999
+                }))
+            );
+        }
+
+        #[test]
+        fn serves_whole_resource_for_unsupported_ranges() {
+            assert_eq!(parse("bytes=0-9,20-29", 1000), Ok(None));
+            assert_eq!(parse("items=0-9", 1000), Ok(None));
+            assert_eq!(parse("bytes=9-0", 1000), Ok(None));
+            assert_eq!(parse("bytes=1000-", 1000), Err(RangeNotSatisfiable));
+        }
+    }
+}
+
+/// zlib framing, a custom fctrl protocol compressing messages on the WebSocket link between the
+/// mgmt-server and the agent, which mostly carries JSON and base64 and compresses well.
+///
+/// This is not the standard permessage-deflate extension (RFC 7692), which tungstenite doesn't
+/// implement, and other WebSocket peers won't understand it. Compression happens above the
+/// WebSocket layer, one message at a time:
+///
+/// 1. The mgmt-server offers the protocol by sending the [`HEADER`] header with the [`ZLIB`]
+///    value on the handshake request.
+/// 2. The agent accepts by echoing the header in its response. Without it, neither side compresses.
+/// 3. Once accepted, either side may send a text message as a binary message holding the tag byte
+///    `0x02` followed by its zlib-compressed UTF-8 text. Only messages of at least 1 KiB are
+///    worth compressing.
+///
+/// Uncompressed text messages are still accepted at any time, and binary messages are told apart
+/// from those of [`ws_binary`] by the tag byte.
+pub mod ws_zlib_framing {
+    use std::io::{Read, Write};
+
+    use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+    use tokio_tungstenite::tungstenite::{http::HeaderMap, Message};
+
+    /// Header offering zlib framing on the handshake request, and accepting it on the response
+    pub const HEADER: &str = "x-fctrl-zlib-framing";
+    pub const ZLIB: &str = "zlib";
+    /// Set to false on either side to disable compression
+    pub const ENV_AGENT_WS_COMPRESSION: &str = "AGENT_WS_COMPRESSION";
+
+    /// Smaller messages are sent as is, as compressing them saves next to nothing
+    const MIN_COMPRESSED_LEN: usize = 1024;
+    /// First byte of every compressed message
+    const TAG: u8 = 0x02;
+
+    pub fn enabled_from_env() -> bool {
+        !matches!(
+            std::env::var(ENV_AGENT_WS_COMPRESSION).as_deref(),
+            Ok("false") | Ok("0")
+        )
+    }
+
+    /// Whether the handshake headers offer or accept compression
+    pub fn negotiated(headers: &HeaderMap) -> bool {
+        headers
+            .get(HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.split(',').any(|codec| codec.trim() == ZLIB))
+    }
+
+    /// Compresses a text message if it is large enough to be worth it, falling back to the
+    /// message as is if it can't be compressed. Other messages are returned unchanged.
+    /// Compression runs on the blocking pool, as messages carrying file contents are large enough
+    /// to stall the runtime otherwise.
+    pub async fn compress(message: Message) -> std::io::Result<Message> {
+        match message {
+            Message::Text(text) if text.len() >= MIN_COMPRESSED_LEN => {
+                let compressed = tokio::task::spawn_blocking(move || {
+                    let mut bytes = Vec::with_capacity(text.len() / 4);
+                    bytes.push(TAG);
+                    // messages are compressed as they are sent, so favour speed over ratio
+                    let mut encoder = ZlibEncoder::new(bytes, Compression::fast());
+                    match encoder
+                        .write_all(text.as_bytes())
+                        .and_then(|_| encoder.finish())
+                    {
+                        Ok(bytes) => Message::Binary(bytes.into()),
+                        Err(_) => Message::Text(text),
+                    }
+                })
+                .await?;
+                Ok(compressed)
+            }
+            message => Ok(message),
+        }
+    }
+
+    /// Restores a compressed message to the text message it was compressed from. Other messages
+    /// are returned unchanged.
+    pub async fn decompress(message: Message) -> std::io::Result<Message> {
+        match message {
+            Message::Binary(bytes) if bytes.first() == Some(&TAG) => {
+                tokio::task::spawn_blocking(move || {
+                    let mut text = String::new();
+                    ZlibDecoder::new(&bytes[1..]).read_to_string(&mut text)?;
+                    Ok(Message::Text(text.into()))
+                })
+                .await?
+            }
+            message => Ok(message),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn compresses_only_large_text_messages() {
+            let large = format!("{{\"bytes\":\"{}\"}}", "QUFB".repeat(1000));
+            let compressed = compress(Message::Text(large.clone().into())).await.unwrap();
+            assert!(compressed.is_binary());
+            assert!(compressed.len() < large.len() / 10);
+            assert_eq!(
+                decompress(compressed).await.unwrap(),
+                Message::Text(large.into())
+            );
+
+            let small = Message::Text("{}".into());
+            assert_eq!(compress(small.clone()).await.unwrap(), small);
+            let binary = Message::Binary(vec![1, 2, 3].into());
+            assert_eq!(decompress(binary.clone()).await.unwrap(), binary);
+        }
+    }
+}
+
+/// Binary encoding of messages on the WebSocket link between the mgmt-server and the agent, for
+/// the messages carrying file contents, which would otherwise be base64-encoded in JSON.
+///
+/// Negotiated during the handshake in the same way as [`ws_zlib_framing`], with the mgmt-server
+/// only offering it if configured to. Once accepted, either side may send a file transfer message
+/// as a binary message holding a tag byte followed by the bincode-encoded message. Everything else
+/// is still sent as JSON, which also stays the only encoding for peers like the ws-client.
+pub mod ws_binary {
+    use serde::{de::DeserializeOwned, Serialize};
+    use tokio_tungstenite::tungstenite::{http::HeaderMap, Message};
+
+    use crate::schema::AgentRequest;
+
+    /// Header offering binary encoding on the handshake request, and accepting it on the response
+    pub const HEADER: &str = "x-fctrl-encoding";
+    /// MessagePack with struct fields by name, so peers on different builds can tolerate added
+    /// or reordered fields
+    pub const MSGPACK: &str = "msgpack";
+    /// Set to true on the mgmt-server to offer binary encoding
+    pub const ENV_AGENT_WS_BINARY_ENCODING: &str = "AGENT_WS_BINARY_ENCODING";
+
+    /// First byte of every binary-encoded message
+    const TAG: u8 = 0x01;
+
+    pub fn enabled_from_env() -> bool {
+        matches!(
+            std::env::var(ENV_AGENT_WS_BINARY_ENCODING).as_deref(),
+            Ok("true") | Ok("1")
+        )
+    }
+
+    /// Whether the handshake headers offer or accept binary encoding
+    pub fn negotiated(headers: &HeaderMap) -> bool {
+        headers
+            .get(HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.split(',').any(|e| e.trim() == MSGPACK))
+    }
+
+    /// Whether the request carries file contents, and so is worth binary-encoding
+    pub fn is_file_transfer(request: &AgentRequest) -> bool {
+        matches!(
+            request,
+            AgentRequest::SaveSet(..) | AgentRequest::ModSettingsSet(_)
+        )
+    }
+
+    pub fn encode<T: Serialize>(value: &T) -> Result<Message, rmp_serde::encode::Error> {
+        let mut bytes = vec![TAG];
+        rmp_serde::encode::write_named(&mut bytes, value)?;
+        Ok(Message::Binary(bytes.into()))
+    }
+
+    /// Decodes a binary-encoded message, or returns None if the message isn't binary-encoded
+    pub fn decode<T: DeserializeOwned>(
+        message: &Message,
+    ) -> Option<Result<T, rmp_serde::decode::Error>> {
+        match message {
+            Message::Binary(bytes) if bytes.first() == Some(&TAG) => {
+                Some(rmp_serde::from_slice(&bytes[1..]))
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::schema::{AgentRequestWithId, OperationId, SaveBytes};
+
+        use super::*;
+
+        #[test]
+        fn round_trips_file_transfers_without_base64() {
+            let request = AgentRequestWithId {
+                operation_id: OperationId("op".to_owned()),
+                message: AgentRequest::SaveSet(
+                    "save".to_owned(),
+                    SaveBytes::new(vec![7; 3000]).with_sha256(Some("abc".to_owned())),
+                ),
+            };
+            assert!(is_file_transfer(&request.message));
+            let message = encode(&request).unwrap();
+            assert!(message.len() < 3100);
+
+            let decoded: AgentRequestWithId = decode(&message).unwrap().unwrap();
+            match decoded.message {
+                AgentRequest::SaveSet(name, bytes) => {
+                    assert_eq!(name, "save");
+                    assert_eq!(bytes.bytes, vec![7; 3000]);
+                    assert_eq!(bytes.sha256.as_deref(), Some("abc"));
+                }
+                _ => panic!("decoded to a different request"),
+            }
+            assert!(decode::<AgentRequestWithId>(&Message::Text("{}".into())).is_none());
+        }
+
+        #[test]
+        fn tolerates_fields_added_by_newer_peers() {
+            #[derive(Serialize)]
+            struct NewerRequestWithId {
+                added: u32,
+                message: AgentRequest,
+                operation_id: OperationId,
+            }
+            let message = encode(&NewerRequestWithId {
+                added: 1,
+                message: AgentRequest::SaveSet("save".to_owned(), SaveBytes::new(vec![7; 10])),
+                operation_id: OperationId("op".to_owned()),
+            })
+            .unwrap();
+
+            let decoded: AgentRequestWithId = decode(&message).unwrap().unwrap();
+            assert_eq!(decoded.operation_id.0, "op");
+            assert!(matches!(decoded.message, AgentRequest::SaveSet(name, _) if name == "save"));
+        }
+    }
+}
+
 // #[cfg(test)] // https://github.com/rust-lang/rust/issues/45599
 pub mod testing {
     pub fn logger_init() {
@@ -122,28 +122,69 @@ fn get_message_from_input(input: String) -> Option<AgentRequestWithId> {
             if let Some(&"true") = args.get(2) {
                 force_install = true;
             }
+            let mut keep_existing = false;
+            if let Some(&"true") = args.get(3) {
+                keep_existing = true;
+            }
             AgentRequestWithId {
                 operation_id,
                 message: AgentRequest::VersionInstall {
                     version: FactorioVersion(v.to_string()),
                     force_install,
+                    keep_existing,
                 },
             }
         }),
+        "VersionList" => Some(AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::VersionList,
+        }),
         "ServerStart" => args
             .get(1)
             .map(|savefile| {
                 if *savefile == "Latest" {
                     Some(AgentRequestWithId {
                         operation_id,
-                        message: AgentRequest::ServerStart(ServerStartSaveFile::Latest),
+                        message: AgentRequest::ServerStart(
+                            ServerStartSaveFile::Latest,
+                            args.get(2).map(|v| FactorioVersion(v.to_string())),
+                            args.get(3).map(|p| p.to_string()),
+                        ),
                     })
                 } else if *savefile == "Specific" {
                     args.get(2).map(|name| AgentRequestWithId {
                         operation_id,
-                        message: AgentRequest::ServerStart(ServerStartSaveFile::Specific(
-                            name.to_string(),
-                        )),
+                        message: AgentRequest::ServerStart(
+                            ServerStartSaveFile::Specific(name.to_string()),
+                            args.get(3).map(|v| FactorioVersion(v.to_string())),
+                            args.get(4).map(|p| p.to_string()),
+                        ),
+                    })
+                } else {
+                    None
+                }
+            })
+            .flatten(),
+        "ServerStartPlan" => args
+            .get(1)
+            .map(|savefile| {
+                if *savefile == "Latest" {
+                    Some(AgentRequestWithId {
+                        operation_id,
+                        message: AgentRequest::ServerStartPlan(
+                            ServerStartSaveFile::Latest,
+                            args.get(2).map(|v| FactorioVersion(v.to_string())),
+                            args.get(3).map(|p| p.to_string()),
+                        ),
+                    })
+                } else if *savefile == "Specific" {
+                    args.get(2).map(|name| AgentRequestWithId {
+                        operation_id,
+                        message: AgentRequest::ServerStartPlan(
+                            ServerStartSaveFile::Specific(name.to_string()),
+                            args.get(3).map(|v| FactorioVersion(v.to_string())),
+                            args.get(4).map(|p| p.to_string()),
+                        ),
                     })
                 } else {
                     None
@@ -154,6 +195,17 @@ fn get_message_from_input(input: String) -> Option<AgentRequestWithId> {
             operation_id,
             message: AgentRequest::ServerStop,
         }),
+        "ServerStopScheduled" => {
+            args.get(1)
+                .and_then(|s| s.parse().ok())
+                .map(|delay_secs| AgentRequestWithId {
+                    operation_id,
+                    message: AgentRequest::ServerStopScheduled {
+                        delay_secs,
+                        message: args.get(2).map(|_| args[2..].join(" ")),
+                    },
+                })
+        }
         "ServerStatus" => Some(AgentRequestWithId {
             operation_id,
             message: AgentRequest::ServerStatus,
@@ -162,6 +214,44 @@ fn get_message_from_input(input: String) -> Option<AgentRequestWithId> {
             operation_id,
             message: AgentRequest::SaveCreate(name.to_string(), None, None),
         }),
+        "SaveDelete" => args.get(1).map(|name| AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::SaveDelete(name.to_string()),
+        }),
+        "SaveRename" => match (args.get(1), args.get(2)) {
+            (Some(name), Some(new_name)) => Some(AgentRequestWithId {
+                operation_id,
+                message: AgentRequest::SaveRename(name.to_string(), new_name.to_string()),
+            }),
+            _ => None,
+        },
+        "SaveCopy" => match (args.get(1), args.get(2)) {
+            (Some(name), Some(new_name)) => Some(AgentRequestWithId {
+                operation_id,
+                message: AgentRequest::SaveCopy(name.to_string(), new_name.to_string()),
+            }),
+            _ => None,
+        },
+        "BackupCreate" => args.get(1).map(|name| AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::BackupCreate(name.to_string()),
+        }),
+        "BackupStatusGet" => Some(AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::BackupStatusGet,
+        }),
+        "SaveChecksumGet" => args.get(1).map(|name| AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::SaveChecksumGet(name.to_string()),
+        }),
+        "SaveMetadataGet" => args.get(1).map(|name| AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::SaveMetadataGet(name.to_string()),
+        }),
+        "MapPreviewGenerate" => Some(AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::MapPreviewGenerate,
+        }),
         "ModListGet" => Some(AgentRequestWithId {
             operation_id,
             message: AgentRequest::ModListGet,
@@ -175,6 +265,18 @@ fn get_message_from_input(input: String) -> Option<AgentRequestWithId> {
                     message: AgentRequest::ModListSet(list),
                 })
         }
+        "ModListUpdateAll" => Some(AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ModListUpdateAll,
+        }),
+        "ModEnable" => args.get(1).map(|name| AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ModEnable(name.to_string()),
+        }),
+        "ModDisable" => args.get(1).map(|name| AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ModDisable(name.to_string()),
+        }),
         "ModSettingsGet" => Some(AgentRequestWithId {
             operation_id,
             message: AgentRequest::ModSettingsGet,
@@ -201,6 +303,39 @@ fn get_message_from_input(input: String) -> Option<AgentRequestWithId> {
                 message: AgentRequest::ConfigAdminListSet { admins: al },
             })
         }
+        "ConfigDiagnosticsGet" => Some(AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ConfigDiagnosticsGet,
+        }),
+        "ConfigPerformanceGet" => Some(AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ConfigPerformanceGet,
+        }),
+        "ConfigPerformanceSet" => {
+            let cpu_affinity = match args.get(1) {
+                Some(cpus) => match cpus.split(',').map(|s| s.parse()).collect() {
+                    Ok(cpus) => Some(cpus),
+                    Err(_) => return None,
+                },
+                None => None,
+            };
+            Some(AgentRequestWithId {
+                operation_id,
+                message: AgentRequest::ConfigPerformanceSet(PerformanceConfig { cpu_affinity }),
+            })
+        }
+        "ConfigProfileList" => Some(AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ConfigProfileList,
+        }),
+        "ConfigProfileSave" => args.get(1).map(|name| AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ConfigProfileSave(name.to_string()),
+        }),
+        "ConfigProfileDelete" => args.get(1).map(|name| AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ConfigProfileDelete(name.to_string()),
+        }),
         "ConfigRconGet" => Some(AgentRequestWithId {
             operation_id,
             message: AgentRequest::ConfigRconGet,
@@ -211,6 +346,25 @@ fn get_message_from_input(input: String) -> Option<AgentRequestWithId> {
                 password: pw.to_string(),
             },
         }),
+        "ConfigRestartPolicyGet" => Some(AgentRequestWithId {
+            operation_id,
+            message: AgentRequest::ConfigRestartPolicyGet,
+        }),
+        "ConfigRestartPolicySet" => {
+            let max_retries = args.get(1)?.parse().ok()?;
+            let backoff_secs = args.get(2)?.parse().ok()?;
+            let uptime_restart_hours = args.get(3).map_or(Some(0), |s| s.parse().ok())?;
+            let uptime_restart_max_delay_mins = args.get(4).map_or(Some(0), |s| s.parse().ok())?;
+            Some(AgentRequestWithId {
+                operation_id,
+                message: AgentRequest::ConfigRestartPolicySet(RestartPolicy {
+                    max_retries,
+                    backoff_secs,
+                    uptime_restart_hours,
+                    uptime_restart_max_delay_mins,
+                }),
+            })
+        }
         "ConfigSecretsGet" => Some(AgentRequestWithId {
             operation_id,
             message: AgentRequest::ConfigSecretsGet,
@@ -248,6 +402,33 @@ fn get_message_from_input(input: String) -> Option<AgentRequestWithId> {
                 message: AgentRequest::RconCommand(cmd),
             })
         }
+        "RconWhisper" => args.get(1).map(|players| {
+            let message = args.iter().skip(2).cloned().collect::<Vec<_>>().join(" ");
+            AgentRequestWithId {
+                operation_id,
+                message: AgentRequest::RconWhisper {
+                    players: players.split(',').map(|p| p.to_string()).collect(),
+                    message,
+                },
+            }
+        }),
+        "StreamStdoutSubscribe" => {
+            // no categories given means subscribe to everything
+            let categories = args
+                .iter()
+                .skip(1)
+                .map(|c| c.parse::<StdoutCategory>())
+                .collect::<Result<Vec<_>, _>>()
+                .ok()?;
+            Some(AgentRequestWithId {
+                operation_id,
+                message: AgentRequest::StreamStdoutSubscribe(if categories.is_empty() {
+                    None
+                } else {
+                    Some(categories)
+                }),
+            })
+        }
         _ => None,
     }
 }
@@ -0,0 +1,424 @@
+//! End-to-end tests driving the mgmt-server REST API, which relays to a live agent and Factorio
+//! server process.
+//!
+//! Both binaries must already be built (`cargo build`). The mgmt-server keeps its db in memory.
+//! Tests that install a real headless Factorio release need network access, so are ignored unless
+//! run with `cargo test --test fctrl-e2e -- --ignored`.
+
+use std::time::Duration;
+use std::{path::PathBuf, process::Stdio};
+
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use serial_test::serial;
+use sha2::{Digest, Sha256};
+use tokio::process::{Child, Command};
+
+use fctrl::util;
+
+const VERSION_TO_INSTALL: &'static str = "1.1.104";
+const AGENT_WS_PORT: &'static str = "5465";
+const MGMT_SERVER_PORT: &'static str = "8765";
+const MGMT_SERVER_WS_PORT: &'static str = "8766";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+struct E2eTestFixture {
+    agent: Child,
+    mgmt_server: Child,
+    http: reqwest::Client,
+    work_dir: PathBuf,
+}
+
+impl E2eTestFixture {
+    pub async fn new(name: &str) -> Self {
+        let executables_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("target")
+            .join("debug");
+
+        // fresh working directory per test, so installs, saves and the db don't leak across tests
+        let work_dir = std::env::temp_dir().join(format!("fctrl-e2e-{}", name));
+        let _ = tokio::fs::remove_dir_all(&work_dir).await;
+        let agent_dir = work_dir.join("agent");
+        let mgmt_server_dir = work_dir.join("mgmt-server");
+        tokio::fs::create_dir_all(&agent_dir).await.unwrap();
+        tokio::fs::create_dir_all(mgmt_server_dir.join("web").join("dist").join("web"))
+            .await
+            .unwrap();
+
+        let agent = Command::new(executables_dir.join("agent"))
+            .current_dir(&agent_dir)
+            .env("AGENT_WS_PORT", AGENT_WS_PORT)
+            .env("FACTORIO_PORT", "34198")
+            .env("FACTORIO_RCON_PORT", "27016")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null()) // Comment out this line to show agent logs for debugging
+            .spawn()
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let mgmt_server = Command::new(executables_dir.join("mgmt-server"))
+            .current_dir(&mgmt_server_dir)
+            .env("AGENT_ADDR", format!("ws://localhost:{}", AGENT_WS_PORT))
+            .env("AUTH_PROVIDER", "none")
+            .env("DB_IN_MEMORY", "true")
+            .env("MGMT_SERVER_WS_ADDRESS", "127.0.0.1")
+            .env("MGMT_SERVER_WS_PORT", MGMT_SERVER_WS_PORT)
+            .env("RPROXY_ENABLED", "false")
+            .env("ROCKET_ADDRESS", "127.0.0.1")
+            .env("ROCKET_PORT", MGMT_SERVER_PORT)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null()) // Comment out this line to show mgmt-server logs for debugging
+            .spawn()
+            .unwrap();
+
+        let f = E2eTestFixture {
+            agent,
+            mgmt_server,
+            http: reqwest::Client::new(),
+            work_dir,
+        };
+        f.wait_until_ready(Duration::from_secs(30)).await;
+        f
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", MGMT_SERVER_PORT, path)
+    }
+
+    fn api_url(&self, path: &str) -> String {
+        self.url(&format!("/api/v0{}", path))
+    }
+
+    async fn wait_until_ready(&self, timeout: Duration) {
+        with_timeout(timeout, async {
+            loop {
+                if let Ok(r) = self.http.get(self.api_url("/server/control")).send().await {
+                    if r.status().is_success() {
+                        return;
+                    }
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        })
+        .await
+    }
+
+    pub async fn get_json(&self, path: &str) -> Value {
+        let resp = self.http.get(self.api_url(path)).send().await.unwrap();
+        assert!(
+            resp.status().is_success(),
+            "GET {} returned {}",
+            path,
+            resp.status()
+        );
+        resp.json().await.unwrap()
+    }
+
+    /// POSTs to a long-running operation endpoint, and waits for the operation to finish
+    pub async fn post_operation(&self, path: &str, body: Value, timeout: Duration) -> String {
+        let resp = self
+            .http
+            .post(self.api_url(path))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let location = resp.headers()["Location"].to_str().unwrap().to_owned();
+        let operation_id = location.rsplit('/').next().unwrap().to_owned();
+        self.wait_for_operation(&operation_id, timeout).await
+    }
+
+    /// Polls the operation history until a final status is recorded, returning that status
+    async fn wait_for_operation(&self, operation_id: &str, timeout: Duration) -> String {
+        let path = format!("/operations/{}/events", operation_id);
+        with_timeout(timeout, async {
+            loop {
+                let events = self.get_json(&path).await;
+                let status = events
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|e| e["status"].as_str().unwrap().to_owned())
+                    .find(|s| s == "Completed" || s == "Failed");
+                if let Some(status) = status {
+                    return status;
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        })
+        .await
+    }
+
+    /// Sets the portal credentials to placeholders, which are fine as long as nothing is downloaded
+    async fn set_placeholder_secrets(&self) {
+        let resp = self
+            .http
+            .put(self.api_url("/server/config/secrets"))
+            .json(&json!({ "username": "e2e", "token": "e2e" }))
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+    }
+
+    /// Path of a mod zip in the agent's mod directory
+    fn mod_zip_path(&self, name: &str, version: &str) -> PathBuf {
+        self.work_dir
+            .join("agent")
+            .join("data")
+            .join("mods")
+            .join(format!("{}_{}.zip", name, version))
+    }
+
+    async fn mod_list(&self) -> Vec<(String, String, bool)> {
+        let mut mods: Vec<_> = self
+            .get_json("/server/mods/list")
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| {
+                (
+                    m["name"].as_str().unwrap().to_owned(),
+                    m["version"].as_str().unwrap().to_owned(),
+                    m["enabled"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        mods.sort();
+        mods
+    }
+
+    async fn savefile_names(&self) -> Vec<String> {
+        self.get_json("/server/savefiles")
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["name"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    async fn game_status(&self) -> String {
+        self.get_json("/server/control").await["game_status"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    async fn wait_for_game_status(&self, expected: &str, timeout: Duration) {
+        with_timeout(timeout, async {
+            while self.game_status().await != expected {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        })
+        .await
+    }
+
+    /// Follows the download link for a savefile and returns its bytes
+    async fn download_savefile(&self, id: &str) -> Vec<u8> {
+        let resp = self
+            .http
+            .get(self.api_url(&format!("/server/savefiles/{}", id)))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let location = resp.headers()["Location"].to_str().unwrap().to_owned();
+
+        let resp = self.http.get(self.url(&location)).send().await.unwrap();
+        assert!(resp.status().is_success());
+        resp.bytes().await.unwrap().to_vec()
+    }
+
+    /// Uploads a savefile as a single chunk
+    async fn upload_savefile(&self, id: &str, bytes: Vec<u8>) {
+        let len = bytes.len();
+        let sha256 = format!("{:x}", Sha256::digest(&bytes));
+        let resp = self
+            .http
+            .put(self.api_url(&format!("/server/savefiles/{}", id)))
+            .header("Content-Range", format!("bytes 0-{}/{}", len - 1, len))
+            .header("X-Content-Sha256", sha256)
+            .body(bytes)
+            .send()
+            .await
+            .unwrap();
+        assert!(
+            resp.status().is_success(),
+            "upload returned {}",
+            resp.status()
+        );
+    }
+}
+
+impl Drop for E2eTestFixture {
+    fn drop(&mut self) {
+        // send SIGINT to both, so the agent stops any running server
+        for child in [&self.mgmt_server, &self.agent] {
+            if let Some(id) = child.id() {
+                let _ = signal::kill(Pid::from_raw(id as i32), Signal::SIGINT);
+            }
+        }
+        let _ = std::fs::remove_dir_all(&self.work_dir);
+    }
+}
+
+async fn with_timeout<T>(timeout: Duration, f: impl std::future::Future<Output = T>) -> T {
+    tokio::time::timeout(timeout, f)
+        .await
+        .expect("timed out waiting for condition")
+}
+
+#[tokio::test]
+#[serial]
+async fn can_get_server_status_through_mgmt_server() {
+    util::testing::logger_init();
+
+    let f = E2eTestFixture::new("status").await;
+
+    assert_eq!(f.game_status().await, "NotRunning");
+    let install = f.get_json("/server/install").await;
+    assert!(install["version"].is_null());
+
+    drop(f);
+}
+
+#[tokio::test]
+#[serial]
+async fn mod_list_with_installed_mods_roundtrip() {
+    util::testing::logger_init();
+
+    let f = E2eTestFixture::new("mods").await;
+    f.set_placeholder_secrets().await;
+
+    // an empty list creates the mod directory
+    let status = f
+        .post_operation("/server/mods/list", json!([]), Duration::from_secs(60))
+        .await;
+    assert_eq!(status, "Completed");
+
+    // mods already in the mod directory are taken as is, so nothing is fetched from the portal
+    for (name, version) in [("e2e-mod", "1.0.0"), ("e2e-other", "2.0.0")] {
+        tokio::fs::write(f.mod_zip_path(name, version), b"")
+            .await
+            .unwrap();
+    }
+    let status = f
+        .post_operation(
+            "/server/mods/list",
+            json!([
+                { "name": "e2e-mod", "version": "1.0.0" },
+                { "name": "e2e-other", "version": "2.0.0", "enabled": false },
+            ]),
+            Duration::from_secs(60),
+        )
+        .await;
+    assert_eq!(status, "Completed");
+    assert_eq!(
+        f.mod_list().await,
+        vec![
+            ("e2e-mod".to_owned(), "1.0.0".to_owned(), true),
+            ("e2e-other".to_owned(), "2.0.0".to_owned(), false),
+        ]
+    );
+
+    // mods left out of the list are deleted
+    let status = f
+        .post_operation(
+            "/server/mods/list",
+            json!([{ "name": "e2e-mod", "version": "1.0.0" }]),
+            Duration::from_secs(60),
+        )
+        .await;
+    assert_eq!(status, "Completed");
+    assert_eq!(
+        f.mod_list().await,
+        vec![("e2e-mod".to_owned(), "1.0.0".to_owned(), true)]
+    );
+    assert!(f.mod_zip_path("e2e-mod", "1.0.0").is_file());
+    assert!(!f.mod_zip_path("e2e-other", "2.0.0").exists());
+
+    drop(f);
+}
+
+#[tokio::test]
+#[serial]
+#[ignore = "downloads the Factorio headless server"]
+async fn install_create_mods_start_and_save_roundtrip() {
+    util::testing::logger_init();
+
+    let f = E2eTestFixture::new("journey").await;
+
+    // install
+    let status = f
+        .post_operation(
+            "/server/install",
+            json!({ "version": VERSION_TO_INSTALL }),
+            Duration::from_secs(300),
+        )
+        .await;
+    assert_eq!(status, "Completed");
+    assert_eq!(
+        f.get_json("/server/install").await["version"].as_str(),
+        Some(VERSION_TO_INSTALL)
+    );
+
+    // create a save
+    let status = f
+        .post_operation(
+            "/server/control/create",
+            json!({ "savefile": "e2e" }),
+            Duration::from_secs(120),
+        )
+        .await;
+    assert_eq!(status, "Completed");
+    assert!(f.savefile_names().await.contains(&"e2e".to_owned()));
+
+    // mod list, vanilla only so the placeholder portal credentials are never used
+    f.set_placeholder_secrets().await;
+    let status = f
+        .post_operation("/server/mods/list", json!([]), Duration::from_secs(60))
+        .await;
+    assert_eq!(status, "Completed");
+    assert_eq!(f.get_json("/server/mods/list").await, json!([]));
+
+    // start then stop
+    let resp = f
+        .http
+        .post(f.api_url("/server/control/start"))
+        .json(&json!({ "savefile": "e2e" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    f.wait_for_game_status("InGame", Duration::from_secs(60))
+        .await;
+
+    let resp = f
+        .http
+        .post(f.api_url("/server/control/stop"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    f.wait_for_game_status("NotRunning", Duration::from_secs(60))
+        .await;
+
+    // save roundtrip: download the save and upload it under a new name
+    let original = f.download_savefile("e2e").await;
+    assert!(!original.is_empty());
+    f.upload_savefile("e2e-roundtrip", original.clone()).await;
+    assert!(f
+        .savefile_names()
+        .await
+        .contains(&"e2e-roundtrip".to_owned()));
+    assert_eq!(f.download_savefile("e2e-roundtrip").await, original);
+
+    drop(f);
+}
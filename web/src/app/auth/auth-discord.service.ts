@@ -37,7 +37,7 @@ export class AuthDiscordService {
   }
 
   getAuthorisationUrl(client_id: string): string {
-    return `${DISCORD_AUTHORISATION_ENDPOINT}?response_type=code&client_id=${client_id}&scope=identify&prompt=none&redirect_uri=${window.location.origin}/oauth-redirect`;
+    return `${DISCORD_AUTHORISATION_ENDPOINT}?response_type=code&client_id=${client_id}&scope=identify%20guilds.members.read&prompt=none&redirect_uri=${window.location.origin}/oauth-redirect`;
   }
 
   tryGetAccessToken(): Option<string> {
@@ -15,6 +15,7 @@ export class Dashboard2Component implements OnInit {
   installedVersion: string | null;
   status: string;
   playerCount: number;
+  ups: number | null = null;
   selectedSave: string;
   installVersionString: string;
 
@@ -65,6 +66,7 @@ export class Dashboard2Component implements OnInit {
     this.apiClient.serverControlGet().subscribe(s => {
       this.status = s.game_status;
       this.playerCount = s.player_count;
+      this.ups = s.ups ?? null;
     });
   }
 
@@ -111,6 +113,28 @@ export class Dashboard2Component implements OnInit {
     });
   }
 
+  stopServerScheduled(delaySecs: number): void {
+    this.apiClient.serverControlStopScheduledPost$Response({
+      body: {
+        delay_secs: delaySecs,
+      },
+    }).subscribe(resp => {
+      const location = resp.headers.get('Location');
+      if (location !== null) {
+        this.operationService.subscribe(
+          location,
+          'Scheduled stop',
+          async () => {
+            console.debug('Scheduled stop success');
+          },
+          async err => {
+            console.warn(`Scheduled stop error: ${err}`);
+          }
+        );
+      }
+    });
+  }
+
   createSave(savename: string): void {
     const payload = {
       body: {
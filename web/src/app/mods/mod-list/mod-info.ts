@@ -3,5 +3,6 @@ export interface ModInfo {
     title: string;
     summary: string;
     selectedVersion: string;
+    enabled: boolean;
     versions: string[];
 }
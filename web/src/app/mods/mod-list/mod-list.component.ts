@@ -1,14 +1,12 @@
 import { Component, OnInit } from '@angular/core';
 import { faCheck, faPlus, faSave, faExternalLink, faRefresh } from '@fortawesome/free-solid-svg-icons';
-import { Option } from 'prelude-ts';
-import { EMPTY, Observable, of, Subject, timer } from 'rxjs';
-import { catchError, debounceTime, distinctUntilChanged, expand, map, reduce, switchMap } from 'rxjs/operators';
-import { ModInfoBatch, ModInfoShort } from 'src/app/factorio-mod-portal-api/models';
+import { Observable, of, Subject, timer } from 'rxjs';
+import { catchError, debounceTime, distinctUntilChanged, map, switchMap } from 'rxjs/operators';
+import { ModInfoShort } from 'src/app/factorio-mod-portal-api/models';
 import { FactorioModPortalApiService } from 'src/app/factorio-mod-portal-api/services';
 import { MgmtServerRestApiService } from 'src/app/mgmt-server-rest-api/services';
 import { OperationService } from 'src/app/operation.service';
 import { ModInfo } from './mod-info';
-import { compareVersions } from 'compare-versions';
 import { ServerModList } from 'src/app/mgmt-server-rest-api/models';
 
 @Component({
@@ -61,27 +59,6 @@ export class ModListComponent implements OnInit {
     });
   }
 
-  private fetchModListInfoSinglePage(namelist: string[], page: number): Observable<{ nextPage: Option<number>, results: ModInfoBatch[] }> {
-    return this.modPortalClient.apiModsGet({
-      namelist: namelist,
-      page: page,
-      page_size: 'max',
-    }).pipe(
-      map(listResponse => {
-        let nextPage: Option<number>;
-        if (listResponse.pagination.page === listResponse.pagination.page_count) {
-          nextPage = Option.none();
-        } else {
-          nextPage = Option.some(listResponse.pagination.page + 1);
-        }
-        return {
-          nextPage: nextPage,
-          results: listResponse.results,
-        };
-      })
-    );
-  }
-
   fetchModList(): void {
     this.apiClient.serverModsListGet().subscribe(modList => {
       this.updateModList(modList);
@@ -94,27 +71,19 @@ export class ModListComponent implements OnInit {
       this.ready = true;
     } else {
       let namelist = modList.map(mo => mo.name);
-      let all = this.fetchModListInfoSinglePage(namelist, 1)
-        .pipe(
-          expand((data, _) => {
-            return data.nextPage.match({
-              Some: nextPage => this.fetchModListInfoSinglePage(namelist, nextPage),
-              None: () => EMPTY,
-            });
-          }),
-          reduce((acc: ModInfoBatch[], data) => {
-            return acc.concat(data.results);
-          }, []),
-        )
-        .subscribe(modInfoBatch => {
+      // releases come filtered to those compatible with the installed version, newest first
+      this.modPortalClient.apiModsSearchGet({ namelist })
+        .subscribe(modInfos => {
           const infoList: ModInfo[] = [];
-          for (const remoteInfo of modInfoBatch) {
+          for (const remoteInfo of modInfos) {
+            const mo = modList.find(mo => mo.name === remoteInfo.name);
             infoList.push({
-              name: remoteInfo.name ?? '<undefined>',
-              title: remoteInfo.title ?? '<undefined>',
-              summary: remoteInfo.summary ?? '<undefined>',
-              selectedVersion: modList.find(mo => mo.name === remoteInfo.name)?.version ?? '',
-              versions: remoteInfo.releases?.map((r: { version: any; }) => r.version).sort(compareVersions).reverse() ?? [],
+              name: remoteInfo.name,
+              title: remoteInfo.title,
+              summary: remoteInfo.summary,
+              selectedVersion: mo?.version ?? '',
+              enabled: mo?.enabled ?? true,
+              versions: remoteInfo.releases.map(r => r.version),
             });
           }
           // sort by friendly name, since this is what the in-game mod manager does
@@ -132,6 +101,7 @@ export class ModListComponent implements OnInit {
         return {
           name: info.name,
           version: info.selectedVersion,
+          enabled: info.enabled,
         };
       }),
     }).subscribe(response => {
@@ -197,9 +167,15 @@ export class ModListComponent implements OnInit {
   }
 
   prefetchModToAdd(name: string): Observable<ModInfo> {
-    return this.modPortalClient.apiModsModNameGet({
-      mod_name: name,
+    return this.modPortalClient.apiModsSearchGet({
+      namelist: [name],
     }).pipe(
+      map(modInfos => {
+        if (modInfos.length === 0) {
+          throw new Error(`mod ${name} not found`);
+        }
+        return modInfos[0];
+      }),
       catchError(err => {
         console.error(`error with prefetch: ${JSON.stringify(err, null, 2)}`);
         const ret: ModInfoShort = {
@@ -213,7 +189,7 @@ export class ModListComponent implements OnInit {
         return of(ret);
       }),
       map(infoShort => {
-        const versions = infoShort.releases?.map(r => r.version).sort(compareVersions).reverse();
+        const versions = infoShort.releases.map(r => r.version);
         const selectedVersion = versions.length === 0 ? '' : versions[0];
         const ret: ModInfo = {
           name: infoShort.name,
@@ -221,6 +197,7 @@ export class ModListComponent implements OnInit {
           summary: infoShort.summary,
           versions,
           selectedVersion,
+          enabled: true,
         };
         return ret;
       }));